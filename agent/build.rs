@@ -38,7 +38,11 @@ fn generate_protobuf() -> Result<(), Box<dyn Error>> {
         // FIXME: wait for newer api to pin codegen file
         // .include_file("mod.rs")
         .compile(
-            &["../message/opentelemetry/opentelemetry/proto/trace/v1/trace.proto"],
+            &[
+                "../message/opentelemetry/opentelemetry/proto/trace/v1/trace.proto",
+                "../message/opentelemetry/opentelemetry/proto/metrics/v1/metrics.proto",
+                "../message/opentelemetry/opentelemetry/proto/logs/v1/logs.proto",
+            ],
             &["../message/opentelemetry"],
         )?;
 
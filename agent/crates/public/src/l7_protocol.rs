@@ -40,13 +40,19 @@ pub enum L7Protocol {
 
     // NoSQL
     Redis = 80,
+    MongoDB = 81,
 
     // MQ
     Kafka = 100,
     MQTT = 101,
+    AMQP = 102,
 
     // INFRA
     DNS = 120,
 
+    // 用户通过 WASM 插件自定义的协议，协议号受限于 u128 的 bitmap
+    // user defined protocol from wasm plugin, limited by the u128 bitmap
+    Custom = 127,
+
     Max = 255,
 }
@@ -15,10 +15,13 @@
  */
 
 use bitflags::bitflags;
-use hpack::decoder::Decoder;
+use hpack::decoder::{Decoder, DecoderError};
 
 const STATIC_INDEX_MIN: usize = 1;
 const STATIC_INDEX_MAX: usize = 61;
+// RFC7540规定SETTINGS_HEADER_TABLE_SIZE的初始值为4096
+// initial value of SETTINGS_HEADER_TABLE_SIZE defined in RFC7540
+pub const DEFAULT_DYNAMIC_TABLE_SIZE: usize = 4096;
 
 #[derive(PartialEq, Copy, Clone, Debug)]
 pub enum ParseError {
@@ -44,6 +47,10 @@ bitflags! {
 
 pub struct Parser<'a> {
     decoder: Decoder<'a>,
+    // 为0时不查询动态表，否则为动态表大小上限
+    // dynamic table lookup is disabled when 0, otherwise it is the upper limit of the table size
+    dynamic_table_limit: usize,
+    dynamic_table_size: usize,
 }
 
 fn parse_int(buf: &[u8], prefix: u8) -> Result<(usize, usize), ParseError> {
@@ -94,9 +101,54 @@ impl Parser<'_> {
     pub fn new() -> Parser<'static> {
         Parser {
             decoder: Decoder::new(),
+            dynamic_table_limit: 0,
+            dynamic_table_size: DEFAULT_DYNAMIC_TABLE_SIZE,
         }
     }
 
+    // 跨头部块维护动态表，用于同一连接同一方向的连续解析，动态表大小不超过limit
+    // 从连接中途开始跟踪时本地动态表只包含编码端动态表中最新的若干项，索引超出本地动态表的字段会被跳过
+    // ===============================================================================================
+    // keeps the dynamic table across header blocks of one direction of a connection, and the
+    // table size never exceeds limit. When tracking starts in the middle of a connection, the
+    // local table only holds the newest entries of the encoder's, fields indexing beyond it are
+    // skipped
+    pub fn with_dynamic_table(limit: usize) -> Parser<'static> {
+        let mut parser = Parser {
+            decoder: Decoder::new(),
+            dynamic_table_limit: limit,
+            dynamic_table_size: DEFAULT_DYNAMIC_TABLE_SIZE,
+        };
+        parser.resize_dynamic_table(DEFAULT_DYNAMIC_TABLE_SIZE);
+        parser
+    }
+
+    pub fn dynamic_table_enabled(&self) -> bool {
+        self.dynamic_table_limit > 0
+    }
+
+    pub fn dynamic_table_size(&self) -> usize {
+        self.dynamic_table_size
+    }
+
+    fn resize_dynamic_table(&mut self, size: usize) {
+        self.dynamic_table_size = size;
+        if self.dynamic_table_enabled() {
+            self.decoder
+                .set_max_table_size(size.min(self.dynamic_table_limit));
+        } else {
+            self.decoder.set_max_table_size(size);
+        }
+    }
+
+    // 清空动态表并设置新的大小，用于SETTINGS_HEADER_TABLE_SIZE变化或解析失败后重新同步
+    // clears the dynamic table and sets the new size, used when SETTINGS_HEADER_TABLE_SIZE
+    // changes or to resync after a parse failure
+    pub fn reset_dynamic_table(&mut self, size: usize) {
+        self.decoder.set_max_table_size(0);
+        self.resize_dynamic_table(size);
+    }
+
     fn parse_kv_pair(
         &mut self,
         buf: &[u8],
@@ -117,7 +169,25 @@ impl Parser<'_> {
             // 静态表index从1到61，共60项。如果index大于61, 意味着这是一个dynamic table的
             // index，我们无法解出index对应的value，应该跳过对应的字节继续解析。
             if index > STATIC_INDEX_MAX {
-                return Ok((None, val_len));
+                if !self.dynamic_table_enabled() {
+                    return Ok((None, val_len));
+                }
+                if val_len > buf.len() {
+                    return Err(ParseError::InvalidInput);
+                }
+                return match self.decoder.decode(&buf[..val_len]) {
+                    Ok(rst) => Ok((Some(rst), val_len)),
+                    Err(DecoderError::HeaderIndexOutOfBounds) => {
+                        // 名字不在本地动态表中，编码端插入的新表项无法得知，清空动态表保持同步
+                        // the name is not in the local table, so the entry inserted by the
+                        // encoder is unknown, clear the table to keep in sync
+                        if prefix == 6 {
+                            self.reset_dynamic_table(self.dynamic_table_size);
+                        }
+                        Ok((None, val_len))
+                    }
+                    Err(_) => Err(ParseError::InvalidHuffmanCode),
+                };
             }
         } else {
             // New Name
@@ -157,6 +227,12 @@ impl Parser<'_> {
                 Ok(rst) => Ok((Some(rst), index_len)),
                 Err(_) => Err(ParseError::InvalidHuffmanCode),
             }
+        } else if index > STATIC_INDEX_MAX && self.dynamic_table_enabled() {
+            match self.decoder.decode(&buf[..index_len]) {
+                Ok(rst) => Ok((Some(rst), index_len)),
+                Err(DecoderError::HeaderIndexOutOfBounds) => Ok((None, index_len)),
+                Err(_) => Err(ParseError::InvalidHuffmanCode),
+            }
         } else {
             Ok((None, index_len))
         }
@@ -170,7 +246,10 @@ impl Parser<'_> {
     }
 
     fn parse_sizeup(&mut self, buf: &[u8]) -> Result<usize, ParseError> {
-        let (_, consumed) = parse_int(buf, 5)?;
+        let (size, consumed) = parse_int(buf, 5)?;
+        if self.dynamic_table_enabled() {
+            self.resize_dynamic_table(size);
+        }
         Ok(consumed)
     }
 
@@ -233,7 +312,17 @@ impl Parser<'_> {
         let mut offset = 0;
 
         while offset < input.len() {
-            offset += self.parse_one_field(input, offset, &mut header_list)?;
+            match self.parse_one_field(input, offset, &mut header_list) {
+                Ok(consumed) => offset += consumed,
+                Err(e) => {
+                    // 头部块未解析完时无法确定编码端插入了哪些表项
+                    // entries inserted by the rest of the header block are unknown
+                    if self.dynamic_table_enabled() {
+                        self.reset_dynamic_table(self.dynamic_table_size);
+                    }
+                    return Err(e);
+                }
+            }
         }
 
         Ok(header_list)
@@ -426,4 +515,73 @@ mod tests {
         assert_eq!(b"content-length", r2[3].0.as_slice());
         assert_eq!(b"46", r2[3].1.as_slice());
     }
+
+    // RFC7541附录C.3
+    // RFC7541 appendix C.3
+    const REQUEST_1: [u8; 20] = [
+        0x82, 0x86, 0x84, 0x41, 0x0f, 0x77, 0x77, 0x77, 0x2e, 0x65, 0x78, 0x61, 0x6d, 0x70, 0x6c,
+        0x65, 0x2e, 0x63, 0x6f, 0x6d,
+    ];
+    const REQUEST_2: [u8; 14] = [
+        0x82, 0x86, 0x84, 0xbe, 0x58, 0x08, 0x6e, 0x6f, 0x2d, 0x63, 0x61, 0x63, 0x68, 0x65,
+    ];
+
+    #[test]
+    fn parse_with_dynamic_table() {
+        let mut parser = Parser::new();
+        parser.parse(&REQUEST_1).unwrap();
+        let r = parser.parse(&REQUEST_2).unwrap();
+        assert_eq!(4, r.len());
+        assert!(r.iter().all(|(k, _)| k.as_slice() != b":authority"));
+
+        let mut parser = Parser::with_dynamic_table(DEFAULT_DYNAMIC_TABLE_SIZE);
+        parser.parse(&REQUEST_1).unwrap();
+        let r = parser.parse(&REQUEST_2).unwrap();
+        assert_eq!(5, r.len());
+        assert_eq!(b":authority", r[3].0.as_slice());
+        assert_eq!(b"www.example.com", r[3].1.as_slice());
+        assert_eq!(b"cache-control", r[4].0.as_slice());
+
+        // 最新插入的表项索引为62
+        // the newest entry has index 62
+        let r = parser.parse(&[0xbe, 0xbf]).unwrap();
+        assert_eq!(b"cache-control", r[0].0.as_slice());
+        assert_eq!(b"no-cache", r[0].1.as_slice());
+        assert_eq!(b"www.example.com", r[1].1.as_slice());
+
+        parser.reset_dynamic_table(DEFAULT_DYNAMIC_TABLE_SIZE);
+        assert!(parser.parse(&[0xbe]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_with_partial_dynamic_table() {
+        // 从第二个请求开始跟踪，本地动态表只有之后插入的表项
+        // tracking starts from the second request, only entries inserted since then are known
+        let mut parser = Parser::with_dynamic_table(DEFAULT_DYNAMIC_TABLE_SIZE);
+        let r = parser.parse(&REQUEST_2).unwrap();
+        assert_eq!(4, r.len());
+        let r = parser.parse(&[0xbe, 0xbf]).unwrap();
+        assert_eq!(1, r.len());
+        assert_eq!(b"no-cache", r[0].1.as_slice());
+    }
+
+    #[test]
+    fn parse_dynamic_table_size_update() {
+        let mut parser = Parser::with_dynamic_table(DEFAULT_DYNAMIC_TABLE_SIZE);
+        parser.parse(&REQUEST_1).unwrap();
+        // 大小更新为0会清空动态表
+        // size update to 0 evicts all entries
+        assert!(parser.parse(&[0x20, 0xbe]).unwrap().is_empty());
+        assert_eq!(0, parser.dynamic_table_size());
+
+        // 超过上限的动态表只保留最新的表项
+        // table beyond the limit keeps only the newest entries
+        let mut parser = Parser::with_dynamic_table(64);
+        parser.parse(&REQUEST_1).unwrap();
+        let r = parser.parse(&REQUEST_2).unwrap();
+        assert_eq!(b"www.example.com", r[3].1.as_slice());
+        let r = parser.parse(&[0xbe, 0xbf]).unwrap();
+        assert_eq!(1, r.len());
+        assert_eq!(b"cache-control", r[0].0.as_slice());
+    }
 }
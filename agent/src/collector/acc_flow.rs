@@ -79,7 +79,7 @@ impl AccumulatedFlow {
                     && other_stats.l7_protocol != L7Protocol::Unknown)
             {
                 self.l7_protocol = other_stats.l7_protocol;
-                self.app_meter = *app_meter;
+                self.app_meter = app_meter.clone();
             } else if other_stats.l7_protocol == self.l7_protocol {
                 self.app_meter.sequential_merge(app_meter);
             }
@@ -631,7 +631,7 @@ impl Stash {
             || tagger.direction == Direction::ClientToServer
         {
             let key = StashKey::new(&tagger, ip, None);
-            self.add(key, tagger.clone(), Meter::Flow(flow_meter));
+            self.add(key, tagger.clone(), Meter::Flow(Box::new(flow_meter)));
             if tagger.l7_protocol != L7Protocol::Unknown
                 && self.context.config.load().l7_metrics_enabled
             {
@@ -769,7 +769,7 @@ impl Stash {
         self.add(
             key,
             tagger.clone(),
-            Meter::Flow(acc_flow.flow_meter.clone()),
+            Meter::Flow(Box::new(acc_flow.flow_meter.clone())),
         );
         if tagger.l7_protocol != L7Protocol::Unknown
             && self.context.config.load().l7_metrics_enabled
@@ -950,6 +950,16 @@ impl Collector {
         }
         info!("{} id=({}) stopped", self.context.name, self.context.id);
     }
+
+    // 等待输入队列关闭后线程自行退出，队列中剩余的数据不会被丢弃
+    // wait for the thread to exit after the input queue is closed, without dropping queued data
+    pub fn join(&self) {
+        if let Some(t) = self.thread.lock().unwrap().take() {
+            let _ = t.join();
+        }
+        self.running.store(false, Ordering::Relaxed);
+        info!("{} id=({}) joined", self.context.name, self.context.id);
+    }
 }
 
 #[cfg(test)]
@@ -522,12 +522,12 @@ impl SubQuadGen {
                 is_active_host0: true,
                 is_active_host1: true,
                 policy_ids: policy_ids.clone(),
-                flow_meter: *flow_meter,
+                flow_meter: flow_meter.clone(),
                 time_in_second,
                 nat_src_ip: nat_real_ip0,
                 nat_dst_ip: nat_real_ip1,
                 key: key.clone(),
-                app_meter: *app_meter,
+                app_meter: app_meter.clone(),
             };
             match key {
                 QgKey::V6(k) => stash.v6_flows.insert(*k, acc_flow),
@@ -673,6 +673,16 @@ impl QuadrupleGeneratorThread {
         let _ = self.thread_handle.take().unwrap().join();
         info!("stopped quadruple generator: {}", self.id);
     }
+
+    // 等待输入队列关闭后线程自行退出，退出前会输出所有未完成的统计
+    // wait for the thread to exit after the input queue is closed, all pending stats are flushed
+    pub fn join(&mut self) {
+        if let Some(t) = self.thread_handle.take() {
+            let _ = t.join();
+        }
+        self.running.store(false, Ordering::Relaxed);
+        info!("joined quadruple generator: {}", self.id);
+    }
 }
 
 pub struct QuadrupleGenerator {
@@ -979,7 +989,15 @@ impl QuadrupleGenerator {
                 art_count: stats.tcp.art_count,
                 rrt_count: 0,
                 cit_count: stats.tcp.cit_count,
+
+                srt_histogram: stats.tcp.srt_histogram.clone(),
+                art_histogram: stats.tcp.art_histogram.clone(),
+                cit_histogram: stats.tcp.cit_histogram.clone(),
+                ..Default::default()
             };
+            if stats.tcp.rtt > 0 {
+                flow_meter.latency.rtt_histogram.record(stats.tcp.rtt);
+            }
 
             let src_perf = &stats.tcp.counts_peers[0];
             let dst_perf = &stats.tcp.counts_peers[1];
@@ -994,7 +1012,8 @@ impl QuadrupleGenerator {
         } else {
             flow_meter.latency.art_max = stats.tcp.art_max;
             flow_meter.latency.art_sum = stats.tcp.art_sum as u64;
-            flow_meter.latency.art_count = stats.tcp.art_max;
+            flow_meter.latency.art_count = stats.tcp.art_count;
+            flow_meter.latency.art_histogram = stats.tcp.art_histogram.clone();
         }
 
         if !l7_metrics_enabled.load(Ordering::Relaxed) {
@@ -1021,6 +1040,7 @@ impl QuadrupleGenerator {
                         rrt_max: stats.l7.rrt_max,
                         rrt_sum: stats.l7.rrt_sum as u64,
                         rrt_count: stats.l7.rrt_count,
+                        rrt_histogram: stats.l7.rrt_histogram.clone(),
                     },
                     anomaly: AppAnomaly {
                         client_error: stats.l7.err_client_count,
@@ -1033,6 +1053,7 @@ impl QuadrupleGenerator {
                 flow_meter.latency.rrt_max = stats.l7.rrt_max;
                 flow_meter.latency.rrt_sum = stats.l7.rrt_sum;
                 flow_meter.latency.rrt_count = stats.l7.rrt_count;
+                flow_meter.latency.rrt_histogram = stats.l7.rrt_histogram.clone();
                 flow_meter.anomaly.l7_client_error = stats.l7.err_client_count;
                 flow_meter.anomaly.l7_server_error = stats.l7.err_server_count;
                 flow_meter.anomaly.l7_timeout = stats.l7.err_timeout;
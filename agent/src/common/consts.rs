@@ -240,6 +240,7 @@ pub mod icmpv6 {
 pub mod ipv4 {
     pub const VERSION_IHL_OFFSET: usize = 0;
     pub const TOTAL_LENGTH_OFFSET: usize = 2;
+    pub const ID_OFFSET: usize = 4;
     pub const FLAGS_OFFSET: usize = 6;
     pub const TTL_OFFSET: usize = 8;
     pub const PROTO_OFFSET: usize = 9;
@@ -385,6 +386,15 @@ pub const VXLAN_FLAGS_OFFSET_DECAP: usize = 0;
 pub const VXLAN_VNI_OFFSET_DECAP: usize = 4;
 pub const ERSPAN_ID_OFFSET: usize = 0; // erspan2和3共用，4字节取0x3ff
 pub const ERSPAN_III_FLAGS_OFFSET: usize = 11;
+pub const GENEVE_HEADER_SIZE: usize = 8; // 不含变长options
+pub const GENEVE_PROTOCOL_OFFSET: usize = 2;
+pub const GENEVE_VNI_OFFSET: usize = 4;
+pub const GTPU_HEADER_SIZE: usize = 8; // 不含可选字段和扩展头
+pub const GTPU_OPTION_SIZE: usize = 4; // Sequence Number + N-PDU Number + Next Extension Header Type
+pub const GTPU_MSG_TYPE_OFFSET: usize = 1;
+pub const GTPU_TEID_OFFSET: usize = 4;
+pub const MPLS_HEADER_SIZE: usize = 4;
+pub const MPLS_PW_CONTROL_WORD_SIZE: usize = 4;
 
 // IpAddr mask
 pub const IPV6_MAX_MASK_LEN: u8 = 128;
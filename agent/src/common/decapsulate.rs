@@ -34,6 +34,9 @@ pub enum TunnelType {
     Ipip = DecapType::Ipip as u8,
     TencentGre = DecapType::Tencent as u8,
     ErspanOrTeb = TunnelType::TencentGre as u8 + 1,
+    Geneve = DecapType::Geneve as u8,
+    Gtpu = DecapType::Gtpu as u8,
+    Mpls = DecapType::Mpls as u8,
 }
 
 impl From<DecapType> for TunnelType {
@@ -43,6 +46,9 @@ impl From<DecapType> for TunnelType {
             DecapType::Vxlan => TunnelType::Vxlan,
             DecapType::Ipip => TunnelType::Ipip,
             DecapType::Tencent => TunnelType::TencentGre,
+            DecapType::Geneve => TunnelType::Geneve,
+            DecapType::Gtpu => TunnelType::Gtpu,
+            DecapType::Mpls => TunnelType::Mpls,
         }
     }
 }
@@ -55,6 +61,9 @@ impl fmt::Display for TunnelType {
             TunnelType::Ipip => write!(f, "IPIP"),
             TunnelType::TencentGre => write!(f, "GRE"),
             TunnelType::ErspanOrTeb => write!(f, "ERSPAN_TEB"),
+            TunnelType::Geneve => write!(f, "GENEVE"),
+            TunnelType::Gtpu => write!(f, "GTPU"),
+            TunnelType::Mpls => write!(f, "MPLS"),
         }
     }
 }
@@ -110,6 +119,18 @@ impl fmt::Display for TunnelTypeBitmap {
         }
         if self.has(TunnelType::ErspanOrTeb) {
             write!(f, "{}{}", separation, TunnelType::ErspanOrTeb)?;
+            separation = " ";
+        }
+        if self.has(TunnelType::Geneve) {
+            write!(f, "{}{}", separation, TunnelType::Geneve)?;
+            separation = " ";
+        }
+        if self.has(TunnelType::Gtpu) {
+            write!(f, "{}{}", separation, TunnelType::Gtpu)?;
+            separation = " ";
+        }
+        if self.has(TunnelType::Mpls) {
+            write!(f, "{}{}", separation, TunnelType::Mpls)?;
         }
         write!(f, "")
     }
@@ -123,9 +144,62 @@ const LE_VXLAN_PROTO_UDP_DPORT: u16 = 0xB512; // 0x12B5(4789)'s LittleEndian
 const LE_VXLAN_PROTO_UDP_DPORT2: u16 = 0x1821; // 0x2118(8472)'s LittleEndian
 const LE_VXLAN_PROTO_UDP_DPORT3: u16 = 0x801A; // 0x1A80(6784)'s LittleEndian
 const LE_TEB_PROTO: u16 = 0x5865; // 0x6558(25944)'s LittleEndian
+const LE_GENEVE_PROTO_UDP_DPORT: u16 = 0xC117; // 0x17C1(6081)'s LittleEndian
+const LE_GTPU_PROTO_UDP_DPORT: u16 = 0x6808; // 0x0868(2152)'s LittleEndian
 
 const VXLAN_FLAGS: u8 = 8;
-const TUNNEL_TIER_LIMIT: u8 = 2;
+const GENEVE_VERSION_MASK: u8 = 0xc0;
+const GENEVE_OPTION_LEN_MASK: u8 = 0x3f;
+const GTPU_VERSION_PT: u8 = 0x30; // Version 1, Protocol Type GTP
+const GTPU_VERSION_PT_MASK: u8 = 0xf0;
+const GTPU_FLAGS_EXTENSION: u8 = 0x04;
+const GTPU_FLAGS_OPTIONS_MASK: u8 = 0x07; // E, S, PN任意一个置位时带有4字节可选字段
+const GTPU_MSG_TYPE_GPDU: u8 = 0xff;
+const MPLS_BOTTOM_OF_STACK: u32 = 0x100;
+const MPLS_LABEL_STACK_LIMIT: usize = 8;
+// 最多解析的隧道层数
+// max number of nested tunnel layers to decapsulate
+pub const TUNNEL_TIER_LIMIT: u8 = 4;
+
+// 单层隧道的信息，underlay为IPv6时仅保存地址的后四个字节
+// A single tunnel layer, only the lowest 4 bytes are kept for IPv6 underlay addresses
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct TunnelLayer {
+    pub tunnel_type: TunnelType,
+    pub id: u32,
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+}
+
+impl Default for TunnelLayer {
+    fn default() -> Self {
+        TunnelLayer {
+            tunnel_type: TunnelType::default(),
+            id: 0,
+            src: Ipv4Addr::UNSPECIFIED,
+            dst: Ipv4Addr::UNSPECIFIED,
+        }
+    }
+}
+
+impl TunnelLayer {
+    fn new(tunnel_type: TunnelType, id: u32, l3_packet: &[u8], underlay_ipv6: bool) -> Self {
+        let (sip_offset, dip_offset) = if underlay_ipv6 {
+            (IP6_SIP_OFFSET, IP6_DIP_OFFSET)
+        } else {
+            (
+                FIELD_OFFSET_SIP - ETH_HEADER_SIZE,
+                FIELD_OFFSET_DIP - ETH_HEADER_SIZE,
+            )
+        };
+        TunnelLayer {
+            tunnel_type,
+            id,
+            src: Ipv4Addr::from(bytes::read_u32_be(&l3_packet[sip_offset..])),
+            dst: Ipv4Addr::from(bytes::read_u32_be(&l3_packet[dip_offset..])),
+        }
+    }
+}
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct TunnelInfo {
@@ -137,6 +211,10 @@ pub struct TunnelInfo {
     pub tunnel_type: TunnelType,
     pub tier: u8,
     pub is_ipv6: bool,
+    // 由外向内的各层隧道，前tier个有效，其中第一个与上面最外层的隧道信息相同
+    // tunnel layers from the outermost to the innermost, the first `tier` ones are valid
+    // and the first one is the same as the outermost tunnel above
+    pub layers: [TunnelLayer; TUNNEL_TIER_LIMIT as usize],
 }
 
 impl Default for TunnelInfo {
@@ -150,28 +228,54 @@ impl Default for TunnelInfo {
             tunnel_type: TunnelType::default(),
             tier: 0,
             is_ipv6: false,
+            layers: Default::default(),
         }
     }
 }
 
 impl TunnelInfo {
-    fn decapsulate_addr(&mut self, l3_packet: &[u8]) {
-        self.src = Ipv4Addr::from(bytes::read_u32_be(
-            &l3_packet[FIELD_OFFSET_SIP - ETH_HEADER_SIZE..],
-        ));
-        self.dst = Ipv4Addr::from(bytes::read_u32_be(
-            &l3_packet[FIELD_OFFSET_DIP - ETH_HEADER_SIZE..],
-        ));
-    }
-
     fn decapsulate_mac(&mut self, packet: &[u8]) {
         self.mac_src = bytes::read_u32_be(&packet[FIELD_OFFSET_SA + 2..]); // MAC低4个字节
         self.mac_dst = bytes::read_u32_be(&packet[FIELD_OFFSET_DA + 2..]);
     }
 
-    fn decapsulate_v6_addr(&mut self, l3_packet: &[u8]) {
-        self.src = Ipv4Addr::from(bytes::read_u32_be(&l3_packet[IP6_SIP_OFFSET..]));
-        self.dst = Ipv4Addr::from(bytes::read_u32_be(&l3_packet[IP6_DIP_OFFSET..]));
+    // 记录一层隧道，最外层隧道的信息同时保存在TunnelInfo中
+    // records a tunnel layer, the outermost one is also saved in TunnelInfo itself
+    fn add_layer(&mut self, packet: &[u8], layer: TunnelLayer, underlay_ipv6: bool) {
+        if self.tier == 0 {
+            self.decapsulate_mac(packet);
+            self.src = layer.src;
+            self.dst = layer.dst;
+            self.id = layer.id;
+            self.tunnel_type = layer.tunnel_type;
+            self.is_ipv6 = underlay_ipv6;
+        }
+        self.layers[self.tier as usize] = layer;
+        self.tier += 1;
+    }
+
+    pub fn layers(&self) -> &[TunnelLayer] {
+        &self.layers[..self.tier as usize]
+    }
+
+    // 去除隧道头，将l2层头放在overlay ip头前，返回移动后的l2层头位置
+    // overlay_offset为overlay ip头在packet中的位置，需要不小于l2_len
+    // Strips the tunnel headers by moving the l2 header right before the overlay ip header,
+    // returns where the moved l2 header starts. overlay_offset must be no less than l2_len
+    fn move_l2_header(
+        packet: &mut [u8],
+        l2_len: usize,
+        overlay_offset: usize,
+        overlay_ipv6: bool,
+    ) -> usize {
+        let start = overlay_offset - l2_len;
+        packet.copy_within(0..l2_len, start);
+        if !overlay_ipv6 {
+            bytes::write_u16_be(&mut packet[start + l2_len - 2..], EthernetType::Ipv4 as u16);
+        } else {
+            bytes::write_u16_be(&mut packet[start + l2_len - 2..], EthernetType::Ipv6 as u16);
+        }
+        start
     }
 
     pub fn decapsulate_vxlan(&mut self, packet: &[u8], l2_len: usize) -> usize {
@@ -191,15 +295,12 @@ impl TunnelInfo {
             return 0;
         }
 
-        // 仅保存最外层的隧道信息
-        if self.tier == 0 {
-            self.decapsulate_addr(l3_packet);
-            self.decapsulate_mac(packet);
-            self.tunnel_type = TunnelType::Vxlan;
-            self.id =
-                bytes::read_u32_be(&l3_packet[FIELD_OFFSET_VXLAN_VNI - ETH_HEADER_SIZE..]) >> 8;
-        }
-        self.tier += 1;
+        let vni = bytes::read_u32_be(&l3_packet[FIELD_OFFSET_VXLAN_VNI - ETH_HEADER_SIZE..]) >> 8;
+        self.add_layer(
+            packet,
+            TunnelLayer::new(TunnelType::Vxlan, vni, l3_packet, false),
+            false,
+        );
 
         // return offset start from L3
         FIELD_OFFSET_VXLAN_FLAGS - ETH_HEADER_SIZE + VXLAN_HEADER_SIZE
@@ -231,43 +332,38 @@ impl TunnelInfo {
         match gre_protocol_type {
             // ERSPAN I
             LE_ERSPAN_PROTO_TYPE_II if flags == 0 => {
-                // 仅保存最外层的隧道信息
-                if self.tier == 0 {
-                    self.decapsulate_addr(l3_packet);
-                    self.decapsulate_mac(packet);
-                    self.tunnel_type = TunnelType::ErspanOrTeb;
-                }
-                self.tier += 1;
+                self.add_layer(
+                    packet,
+                    TunnelLayer::new(TunnelType::ErspanOrTeb, 0, l3_packet, false),
+                    false,
+                );
                 ip_header_size + GRE_HEADER_SIZE_DECAP + ERSPAN_I_HEADER_SIZE
             }
             // ERSPAN II
             LE_ERSPAN_PROTO_TYPE_II => {
                 let gre_header_size =
                     GRE_HEADER_SIZE_DECAP + TunnelInfo::calc_gre_option_size(flags);
-                if self.tier == 0 {
-                    self.decapsulate_addr(l3_packet);
-                    self.decapsulate_mac(packet);
-                    self.tunnel_type = TunnelType::ErspanOrTeb;
-                    self.id = bytes::read_u32_be(
-                        &l3_packet[ip_header_size + gre_header_size + ERSPAN_ID_OFFSET..],
-                    ) & 0x3ff;
-                }
-                self.tier += 1;
+                let id = bytes::read_u32_be(
+                    &l3_packet[ip_header_size + gre_header_size + ERSPAN_ID_OFFSET..],
+                ) & 0x3ff;
+                self.add_layer(
+                    packet,
+                    TunnelLayer::new(TunnelType::ErspanOrTeb, id, l3_packet, false),
+                    false,
+                );
                 ip_header_size + gre_header_size + ERSPAN_II_HEADER_SIZE
             }
             LE_ERSPAN_PROTO_TYPE_III => {
                 let gre_header_size =
                     GRE_HEADER_SIZE_DECAP + TunnelInfo::calc_gre_option_size(flags);
-                // 仅保存最外层的隧道信息
-                if self.tier == 0 {
-                    self.decapsulate_addr(l3_packet);
-                    self.decapsulate_mac(packet);
-                    self.tunnel_type = TunnelType::ErspanOrTeb;
-                    self.id = bytes::read_u32_be(
-                        &l3_packet[ip_header_size + gre_header_size + ERSPAN_ID_OFFSET..],
-                    ) & 0x3ff;
-                }
-                self.tier += 1;
+                let id = bytes::read_u32_be(
+                    &l3_packet[ip_header_size + gre_header_size + ERSPAN_ID_OFFSET..],
+                ) & 0x3ff;
+                self.add_layer(
+                    packet,
+                    TunnelLayer::new(TunnelType::ErspanOrTeb, id, l3_packet, false),
+                    false,
+                );
 
                 let flag =
                     l3_packet[ip_header_size + gre_header_size + ERSPAN_III_FLAGS_OFFSET] & 0x1;
@@ -306,17 +402,14 @@ impl TunnelInfo {
             gre_key_offset += GRE_CSUM_LEN;
         }
 
-        if self.tier == 0 {
-            self.decapsulate_mac(packet);
-        }
+        let l3_packet = &packet[l2_len..];
+        let key = bytes::read_u32_be(&l3_packet[ip_header_size + gre_key_offset..]);
+        self.add_layer(
+            packet,
+            TunnelLayer::new(TunnelType::TencentGre, key, l3_packet, false),
+            false,
+        );
         let l3_packet = &mut packet[l2_len..];
-        // 仅保存最外层的隧道信息
-        if self.tier == 0 {
-            self.decapsulate_addr(l3_packet);
-            self.tunnel_type = TunnelType::TencentGre;
-            self.id = bytes::read_u32_be(&l3_packet[ip_header_size + gre_key_offset..]);
-        }
-        self.tier += 1;
         let overlay_offset = gre_header_size + ip_header_size - ETH_HEADER_SIZE; // 伪造L2层信息
 
         // NOTICE:
@@ -358,14 +451,12 @@ impl TunnelInfo {
         }
 
         let l3_packet = &packet[l2_len..];
-        // 仅保存最外层的隧道信息
-        if self.tier == 0 {
-            self.decapsulate_addr(l3_packet);
-            self.decapsulate_mac(packet);
-            self.tunnel_type = TunnelType::ErspanOrTeb;
-            self.id = bytes::read_u32_be(&l3_packet[ip_header_size + gre_key_offset..]);
-        }
-        self.tier += 1;
+        let key = bytes::read_u32_be(&l3_packet[ip_header_size + gre_key_offset..]);
+        self.add_layer(
+            packet,
+            TunnelLayer::new(TunnelType::ErspanOrTeb, key, l3_packet, false),
+            false,
+        );
         gre_header_size + ip_header_size
     }
 
@@ -432,9 +523,7 @@ impl TunnelInfo {
             .try_into()
             .unwrap_or_default();
         match protocol {
-            IpProtocol::Udp if tunnel_types.has(TunnelType::Vxlan) => {
-                self.decapsulate_vxlan(packet, l2_len)
-            }
+            IpProtocol::Udp => self.decapsulate_udp(packet, l2_len, false, tunnel_types),
             IpProtocol::Gre => self.decapsulate_gre(packet, l2_len, tunnel_types),
             IpProtocol::Ipv4 if tunnel_types.has(TunnelType::Ipip) => {
                 self.decapsulate_ipip(packet, l2_len, false, false)
@@ -464,17 +553,14 @@ impl TunnelInfo {
             return 0;
         }
 
-        // 仅保存最外层的隧道信息
-        if self.tier == 0 {
-            self.decapsulate_v6_addr(l3_packet);
-            self.decapsulate_mac(packet);
-            self.tunnel_type = TunnelType::Vxlan;
-            self.id = bytes::read_u32_be(
-                &l3_packet[IPV6_HEADER_SIZE + UDP_HEADER_SIZE + VXLAN_VNI_OFFSET_DECAP..],
-            ) >> 8;
-            self.is_ipv6 = true;
-        }
-        self.tier += 1;
+        let vni = bytes::read_u32_be(
+            &l3_packet[IPV6_HEADER_SIZE + UDP_HEADER_SIZE + VXLAN_VNI_OFFSET_DECAP..],
+        ) >> 8;
+        self.add_layer(
+            packet,
+            TunnelLayer::new(TunnelType::Vxlan, vni, l3_packet, true),
+            true,
+        );
 
         // return offset start from L3
         IPV6_HEADER_SIZE + UDP_HEADER_SIZE + VXLAN_HEADER_SIZE
@@ -503,9 +589,7 @@ impl TunnelInfo {
 
         let protocol: IpProtocol = l3_packet[IP6_PROTO_OFFSET].try_into().unwrap_or_default();
         match protocol {
-            IpProtocol::Udp if tunnel_types.has(TunnelType::Vxlan) => {
-                self.decapsulate_v6_vxlan(packet, l2_len)
-            }
+            IpProtocol::Udp => self.decapsulate_udp(packet, l2_len, true, tunnel_types),
             IpProtocol::Ipv4 if tunnel_types.has(TunnelType::Ipip) => {
                 self.decapsulate_ipip(packet, l2_len, true, false)
             }
@@ -527,11 +611,7 @@ impl TunnelInfo {
         underlay_ipv6: bool,
         overlay_ipv6: bool,
     ) -> usize {
-        if self.tier == 0 {
-            self.decapsulate_mac(packet);
-        }
-
-        let l3_packet = &mut packet[l2_len..];
+        let l3_packet = &packet[l2_len..];
         let underlay_ip_header_size = if underlay_ipv6 {
             // underlay网络为IPv6时不支持Options字段
             IPV6_HEADER_SIZE
@@ -539,31 +619,229 @@ impl TunnelInfo {
             ((l3_packet[IP_IHL_OFFSET] & 0xf) << 2) as usize
         };
 
-        if self.tier == 0 {
-            if underlay_ipv6 {
-                self.decapsulate_v6_addr(l3_packet);
-                self.is_ipv6 = true;
-            } else {
-                self.decapsulate_addr(l3_packet);
+        self.add_layer(
+            packet,
+            TunnelLayer::new(TunnelType::Ipip, 0, l3_packet, underlay_ipv6),
+            underlay_ipv6,
+        );
+
+        // 去除underlay ip头，将l2层头放在overlay ip头前
+        let start = Self::move_l2_header(
+            packet,
+            l2_len,
+            l2_len + underlay_ip_header_size,
+            overlay_ipv6,
+        );
+        // l2已经做过解析，这个去除掉已经解析的l2长度
+        start - l2_len
+    }
+
+    fn decapsulate_udp(
+        &mut self,
+        packet: &mut [u8],
+        l2_len: usize,
+        underlay_ipv6: bool,
+        tunnel_types: &TunnelTypeBitmap,
+    ) -> usize {
+        let l3_packet = &packet[l2_len..];
+        let ip_header_size = if underlay_ipv6 {
+            // underlay网络为IPv6时不支持Options字段
+            IPV6_HEADER_SIZE
+        } else {
+            ((l3_packet[IP_IHL_OFFSET] & 0xf) << 2) as usize
+        };
+        if l3_packet.len() < ip_header_size + UDP_HEADER_SIZE {
+            return 0;
+        }
+
+        let dst_port = bytes::read_u16_le(&l3_packet[ip_header_size + UDP_DPORT_OFFSET..]);
+        match dst_port {
+            LE_GENEVE_PROTO_UDP_DPORT if tunnel_types.has(TunnelType::Geneve) => {
+                self.decapsulate_geneve(packet, l2_len, ip_header_size, underlay_ipv6)
+            }
+            LE_GTPU_PROTO_UDP_DPORT if tunnel_types.has(TunnelType::Gtpu) => {
+                self.decapsulate_gtpu(packet, l2_len, ip_header_size, underlay_ipv6)
             }
-            self.tunnel_type = TunnelType::Ipip;
-            self.id = 0;
+            _ if tunnel_types.has(TunnelType::Vxlan) => {
+                if underlay_ipv6 {
+                    self.decapsulate_v6_vxlan(packet, l2_len)
+                } else {
+                    self.decapsulate_vxlan(packet, l2_len)
+                }
+            }
+            _ => 0,
         }
-        self.tier += 1;
+    }
 
-        // 去除underlay ip头，将l2层头放在overlay ip头前
+    pub fn decapsulate_geneve(
+        &mut self,
+        packet: &mut [u8],
+        l2_len: usize,
+        ip_header_size: usize,
+        underlay_ipv6: bool,
+    ) -> usize {
+        let l3_packet = &packet[l2_len..];
+        let geneve_offset = ip_header_size + UDP_HEADER_SIZE;
+        if l3_packet.len() < geneve_offset + GENEVE_HEADER_SIZE {
+            return 0;
+        }
+        let version_opt_len = l3_packet[geneve_offset];
+        if version_opt_len & GENEVE_VERSION_MASK != 0 {
+            return 0;
+        }
+        // Options长度以4字节为单位
+        let overlay_offset = geneve_offset
+            + GENEVE_HEADER_SIZE
+            + ((version_opt_len & GENEVE_OPTION_LEN_MASK) as usize) * 4;
+        if l3_packet.len() <= overlay_offset {
+            return 0;
+        }
+        let protocol = bytes::read_u16_le(&l3_packet[geneve_offset + GENEVE_PROTOCOL_OFFSET..]);
+        if protocol != LE_TEB_PROTO
+            && protocol != LE_IPV4_PROTO_TYPE_I
+            && protocol != LE_IPV6_PROTO_TYPE_I
+        {
+            return 0;
+        }
 
-        // 偏移计算：overlay ip头开始位置(l2Len + underlayIpHeaderSize) - l2层长度(l2Len)
-        let start = l2_len + underlay_ip_header_size - l2_len;
+        let vni = bytes::read_u32_be(&l3_packet[geneve_offset + GENEVE_VNI_OFFSET..]) >> 8;
+        self.add_layer(
+            packet,
+            TunnelLayer::new(TunnelType::Geneve, vni, l3_packet, underlay_ipv6),
+            underlay_ipv6,
+        );
 
-        packet.copy_within(0..l2_len, start);
-        if !overlay_ipv6 {
-            bytes::write_u16_be(&mut packet[start + l2_len - 2..], EthernetType::Ipv4 as u16);
-        } else {
-            bytes::write_u16_be(&mut packet[start + l2_len - 2..], EthernetType::Ipv6 as u16);
+        match protocol {
+            // return offset start from L3
+            LE_TEB_PROTO => overlay_offset,
+            // 例如AWS GWLB，Geneve内直接承载IP报文，需要伪造L2层头
+            _ => {
+                Self::move_l2_header(
+                    packet,
+                    l2_len,
+                    l2_len + overlay_offset,
+                    protocol == LE_IPV6_PROTO_TYPE_I,
+                ) - l2_len
+            }
+        }
+    }
+
+    pub fn decapsulate_gtpu(
+        &mut self,
+        packet: &mut [u8],
+        l2_len: usize,
+        ip_header_size: usize,
+        underlay_ipv6: bool,
+    ) -> usize {
+        let l3_packet = &packet[l2_len..];
+        let gtpu_offset = ip_header_size + UDP_HEADER_SIZE;
+        if l3_packet.len() < gtpu_offset + GTPU_HEADER_SIZE {
+            return 0;
+        }
+        // 仅解析承载用户数据的G-PDU
+        let flags = l3_packet[gtpu_offset];
+        if flags & GTPU_VERSION_PT_MASK != GTPU_VERSION_PT
+            || l3_packet[gtpu_offset + GTPU_MSG_TYPE_OFFSET] != GTPU_MSG_TYPE_GPDU
+        {
+            return 0;
+        }
+
+        let mut overlay_offset = gtpu_offset + GTPU_HEADER_SIZE;
+        if flags & GTPU_FLAGS_OPTIONS_MASK != 0 {
+            overlay_offset += GTPU_OPTION_SIZE;
+            if l3_packet.len() < overlay_offset {
+                return 0;
+            }
+            if flags & GTPU_FLAGS_EXTENSION != 0 {
+                // 扩展头链，每个扩展头的长度以4字节为单位，最后一个字节为下一个扩展头类型
+                let mut next_type = l3_packet[overlay_offset - 1];
+                while next_type != 0 {
+                    if l3_packet.len() <= overlay_offset {
+                        return 0;
+                    }
+                    let extension_len = l3_packet[overlay_offset] as usize * 4;
+                    if extension_len == 0 || l3_packet.len() < overlay_offset + extension_len {
+                        return 0;
+                    }
+                    overlay_offset += extension_len;
+                    next_type = l3_packet[overlay_offset - 1];
+                }
+            }
+        }
+        if l3_packet.len() <= overlay_offset {
+            return 0;
+        }
+        let overlay_ipv6 = match l3_packet[overlay_offset] >> 4 {
+            4 => false,
+            6 => true,
+            _ => return 0,
+        };
+
+        let teid = bytes::read_u32_be(&l3_packet[gtpu_offset + GTPU_TEID_OFFSET..]);
+        self.add_layer(
+            packet,
+            TunnelLayer::new(TunnelType::Gtpu, teid, l3_packet, underlay_ipv6),
+            underlay_ipv6,
+        );
+
+        // GTP-U内直接承载IP报文，需要伪造L2层头
+        Self::move_l2_header(packet, l2_len, l2_len + overlay_offset, overlay_ipv6) - l2_len
+    }
+
+    // MPLS标签栈可能比l2层头短，因此返回值为去除标签栈后l2层头在packet中的位置，
+    // 而不是相对于L3的偏移，返回0表示未解封装
+    // The label stack may be shorter than the l2 header, so unlike other decapsulate
+    // functions, this returns where the l2 header starts after the label stack is
+    // stripped instead of an offset from L3. Returns 0 if nothing is decapsulated
+    pub fn decapsulate_mpls(
+        &mut self,
+        packet: &mut [u8],
+        l2_len: usize,
+        tunnel_types: &TunnelTypeBitmap,
+    ) -> usize {
+        if !tunnel_types.has(TunnelType::Mpls) || self.tier == TUNNEL_TIER_LIMIT {
+            return 0;
+        }
+
+        let mut offset = l2_len;
+        let mut label;
+        loop {
+            if packet.len() < offset + MPLS_HEADER_SIZE
+                || offset - l2_len == MPLS_LABEL_STACK_LIMIT * MPLS_HEADER_SIZE
+            {
+                return 0;
+            }
+            let entry = bytes::read_u32_be(&packet[offset..]);
+            offset += MPLS_HEADER_SIZE;
+            label = entry >> 12;
+            if entry & MPLS_BOTTOM_OF_STACK != 0 {
+                break;
+            }
+        }
+        if packet.len() <= offset {
+            return 0;
+        }
+
+        // 栈底之后没有协议标识，通过首个半字节区分IP和带控制字的以太网伪线
+        let overlay = packet[offset] >> 4;
+        match overlay {
+            0 if packet.len() >= offset + MPLS_PW_CONTROL_WORD_SIZE + ETH_HEADER_SIZE => (),
+            4 | 6 => (),
+            _ => return 0,
+        }
+
+        // 使用栈底标签（通常为VPN标签）作为隧道ID
+        let layer = TunnelLayer {
+            tunnel_type: TunnelType::Mpls,
+            id: label,
+            ..Default::default()
+        };
+        self.add_layer(packet, layer, false);
+
+        match overlay {
+            0 => offset + MPLS_PW_CONTROL_WORD_SIZE,
+            _ => Self::move_l2_header(packet, l2_len, offset, overlay == 6),
         }
-        // l2已经做过解析，这个去除掉已经解析的l2长度
-        start - l2_len
     }
 }
 
@@ -611,7 +889,7 @@ mod tests {
     #[test]
     fn test_decapsulate_erspan() {
         let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::ErspanOrTeb]);
-        let expected = TunnelInfo {
+        let expected = single_layer(TunnelInfo {
             src: Ipv4Addr::new(172, 28, 25, 108),
             dst: Ipv4Addr::new(172, 28, 28, 70),
             mac_src: 0xbdf819ff,
@@ -620,7 +898,8 @@ mod tests {
             tunnel_type: TunnelType::ErspanOrTeb,
             tier: 1,
             is_ipv6: false,
-        };
+            ..Default::default()
+        });
         let mut packets: Vec<Vec<u8>> = Capture::load_pcap(
             Path::new(PCAP_PATH_PREFIX).join("decapsulate_erspan1.pcap"),
             None,
@@ -647,7 +926,7 @@ mod tests {
     #[test]
     fn test_decapsulate_erspan_ii() {
         let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::ErspanOrTeb]);
-        let expected = TunnelInfo {
+        let expected = single_layer(TunnelInfo {
             src: Ipv4Addr::new(2, 2, 2, 2),
             dst: Ipv4Addr::new(1, 1, 1, 1),
             mac_src: 0xf1e20101,
@@ -656,7 +935,8 @@ mod tests {
             tunnel_type: TunnelType::ErspanOrTeb,
             tier: 1,
             is_ipv6: false,
-        };
+            ..Default::default()
+        });
         let mut packets: Vec<Vec<u8>> = Capture::load_pcap(
             Path::new(PCAP_PATH_PREFIX).join("decapsulate_test.pcap"),
             None,
@@ -683,7 +963,7 @@ mod tests {
     #[test]
     fn test_decapsulate_erspan_iii() {
         let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::ErspanOrTeb]);
-        let expected = TunnelInfo {
+        let expected = single_layer(TunnelInfo {
             src: Ipv4Addr::new(172, 16, 1, 103),
             dst: Ipv4Addr::new(10, 30, 101, 132),
             mac_src: 0x60d19449,
@@ -692,7 +972,8 @@ mod tests {
             tunnel_type: TunnelType::ErspanOrTeb,
             tier: 1,
             is_ipv6: false,
-        };
+            ..Default::default()
+        });
         let mut packets: Vec<Vec<u8>> = Capture::load_pcap(
             Path::new(PCAP_PATH_PREFIX).join("decapsulate_test.pcap"),
             None,
@@ -712,7 +993,7 @@ mod tests {
     #[test]
     fn test_decapsulate_vxlan() {
         let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::Vxlan]);
-        let expected = TunnelInfo {
+        let expected = single_layer(TunnelInfo {
             src: Ipv4Addr::new(172, 16, 1, 103),
             dst: Ipv4Addr::new(172, 20, 1, 171),
             mac_src: 0xafda7679,
@@ -721,7 +1002,8 @@ mod tests {
             tunnel_type: TunnelType::Vxlan,
             tier: 1,
             is_ipv6: false,
-        };
+            ..Default::default()
+        });
         let mut packets: Vec<Vec<u8>> = Capture::load_pcap(
             Path::new(PCAP_PATH_PREFIX).join("decapsulate_test.pcap"),
             None,
@@ -741,7 +1023,7 @@ mod tests {
     #[test]
     fn test_decapsulate_tencent_gre() {
         let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::TencentGre]);
-        let expected = TunnelInfo {
+        let expected = single_layer(TunnelInfo {
             src: Ipv4Addr::new(10, 19, 0, 21),
             dst: Ipv4Addr::new(10, 21, 64, 5),
             mac_src: 0xbffac801,
@@ -750,7 +1032,8 @@ mod tests {
             tunnel_type: TunnelType::TencentGre,
             tier: 1,
             is_ipv6: false,
-        };
+            ..Default::default()
+        });
         let expected_overlay = [
             0x00, 0x00, 0x00, 0x00, 0x02, 0x85, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x08, 0x00,
             0x45, 0x00, 0x00, 0x28, 0x87, 0x93, 0x40, 0x00, 0x40, 0x06, 0xa8, 0xe7, 0x0a, 0x01,
@@ -778,7 +1061,7 @@ mod tests {
     #[test]
     fn test_decapsulate_teb() {
         let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::ErspanOrTeb]);
-        let expected = TunnelInfo {
+        let expected = single_layer(TunnelInfo {
             src: Ipv4Addr::new(10, 25, 6, 6),
             dst: Ipv4Addr::new(10, 25, 59, 67),
             mac_src: 0x3503bca8,
@@ -787,7 +1070,8 @@ mod tests {
             tunnel_type: TunnelType::ErspanOrTeb,
             tier: 1,
             is_ipv6: false,
-        };
+            ..Default::default()
+        });
         let mut packets: Vec<Vec<u8>> = Capture::load_pcap(
             Path::new(PCAP_PATH_PREFIX).join("vmware-gre-teb.pcap"),
             None,
@@ -807,7 +1091,7 @@ mod tests {
     #[test]
     fn test_decapsulate_ipv6_vxlan() {
         let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::Vxlan]);
-        let expected = TunnelInfo {
+        let expected = single_layer(TunnelInfo {
             src: Ipv4Addr::new(0, 0, 2, 63),
             dst: Ipv4Addr::new(0, 0, 2, 61),
             mac_src: 0x3e7eda7d,
@@ -816,7 +1100,8 @@ mod tests {
             tunnel_type: TunnelType::Vxlan,
             tier: 1,
             is_ipv6: true,
-        };
+            ..Default::default()
+        });
         let mut packets: Vec<Vec<u8>> =
             Capture::load_pcap(Path::new(PCAP_PATH_PREFIX).join("ip6-vxlan.pcap"), None).into();
         let packet = packets[0].as_mut_slice();
@@ -833,7 +1118,7 @@ mod tests {
     #[test]
     fn test_decapsulate_ipip() {
         let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::Ipip]);
-        let expected = TunnelInfo {
+        let expected = single_layer(TunnelInfo {
             src: Ipv4Addr::new(10, 162, 42, 93),
             dst: Ipv4Addr::new(10, 162, 33, 164),
             mac_src: 0x027dc643,
@@ -842,7 +1127,8 @@ mod tests {
             tunnel_type: TunnelType::Ipip,
             tier: 1,
             is_ipv6: false,
-        };
+            ..Default::default()
+        });
         let mut packets: Vec<Vec<u8>> =
             Capture::load_pcap(Path::new(PCAP_PATH_PREFIX).join("ipip.pcap"), None).into();
         let packet = packets[0].as_mut_slice();
@@ -877,4 +1163,343 @@ mod tests {
         assert!(actual_bitmap.has(TunnelType::Vxlan));
         assert!(actual_bitmap.has(TunnelType::ErspanOrTeb));
     }
+
+    // 单层隧道时layers中只有最外层隧道
+    fn single_layer(mut info: TunnelInfo) -> TunnelInfo {
+        info.layers[0] = TunnelLayer {
+            tunnel_type: info.tunnel_type,
+            id: info.id,
+            src: info.src,
+            dst: info.dst,
+        };
+        info
+    }
+
+    const OUTER_SRC_MAC: [u8; 6] = [0x00, 0x11, 0x22, 0x33, 0x44, 0x55];
+    const OUTER_DST_MAC: [u8; 6] = [0x00, 0x66, 0x77, 0x88, 0x99, 0xaa];
+
+    fn eth_header(eth_type: EthernetType) -> Vec<u8> {
+        let mut header = OUTER_DST_MAC.to_vec();
+        header.extend_from_slice(&OUTER_SRC_MAC);
+        header.extend_from_slice(&(eth_type as u16).to_be_bytes());
+        header
+    }
+
+    fn ipv4_header(protocol: IpProtocol, src: [u8; 4], dst: [u8; 4]) -> Vec<u8> {
+        let mut header = vec![0x45, 0, 0, 0, 0, 0, 0x40, 0, 64, protocol as u8, 0, 0];
+        header.extend_from_slice(&src);
+        header.extend_from_slice(&dst);
+        header
+    }
+
+    fn ipv6_header(protocol: IpProtocol) -> Vec<u8> {
+        let mut header = vec![0x60, 0, 0, 0, 0, 0, protocol as u8, 64];
+        header.extend_from_slice(&[0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        header.extend_from_slice(&[0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]);
+        header
+    }
+
+    fn udp_header(dst_port: u16) -> Vec<u8> {
+        let mut header = vec![0xc0, 0x00];
+        header.extend_from_slice(&dst_port.to_be_bytes());
+        header.extend_from_slice(&[0, 0, 0, 0]);
+        header
+    }
+
+    fn overlay_ipv4() -> Vec<u8> {
+        let mut packet = ipv4_header(IpProtocol::Tcp, [192, 168, 0, 1], [192, 168, 0, 2]);
+        packet.extend_from_slice(&[0; TCP_HEADER_SIZE]);
+        packet
+    }
+
+    fn overlay_ethernet() -> Vec<u8> {
+        let mut packet = vec![0x02, 0, 0, 0, 0, 2, 0x02, 0, 0, 0, 0, 1, 0x08, 0x00];
+        packet.extend(overlay_ipv4());
+        packet
+    }
+
+    #[test]
+    fn bitmap_display() {
+        let bitmap = TunnelTypeBitmap::new(&vec![
+            TunnelType::Vxlan,
+            TunnelType::Geneve,
+            TunnelType::Gtpu,
+            TunnelType::Mpls,
+        ]);
+        assert_eq!(bitmap.to_string(), "VXLAN GENEVE GTPU MPLS");
+    }
+
+    #[test]
+    fn test_decapsulate_geneve() {
+        let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::Geneve]);
+        let expected = single_layer(TunnelInfo {
+            src: Ipv4Addr::new(10, 0, 0, 1),
+            dst: Ipv4Addr::new(10, 0, 0, 2),
+            mac_src: 0x22334455,
+            mac_dst: 0x778899aa,
+            id: 0x1234,
+            tunnel_type: TunnelType::Geneve,
+            tier: 1,
+            is_ipv6: false,
+            ..Default::default()
+        });
+        // Geneve携带一个4字节的option，内层为以太网帧
+        let mut packet = eth_header(EthernetType::Ipv4);
+        packet.extend(ipv4_header(IpProtocol::Udp, [10, 0, 0, 1], [10, 0, 0, 2]));
+        packet.extend(udp_header(6081));
+        packet.extend_from_slice(&[0x01, 0x00, 0x65, 0x58, 0x00, 0x12, 0x34, 0x00]);
+        packet.extend_from_slice(&[0x01, 0x02, 0x03, 0x04]);
+        packet.extend(overlay_ethernet());
+
+        let l2_len = 14;
+        let mut actual = TunnelInfo::default();
+        let offset = actual.decapsulate(&mut packet, l2_len, &bitmap);
+        let expected_offset = IPV4_HEADER_SIZE + UDP_HEADER_SIZE + GENEVE_HEADER_SIZE + 4;
+
+        assert_eq!(offset, expected_offset);
+        assert_eq!(actual, expected);
+        assert_eq!(&packet[l2_len + offset..], &overlay_ethernet()[..]);
+
+        // 未开启Geneve解封装时不处理
+        let mut actual = TunnelInfo::default();
+        let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::Vxlan]);
+        assert_eq!(actual.decapsulate(&mut packet, l2_len, &bitmap), 0);
+        assert_eq!(actual.tunnel_type, TunnelType::None);
+    }
+
+    #[test]
+    fn test_decapsulate_geneve_ip() {
+        let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::Geneve]);
+        // 例如AWS GWLB，Geneve内层直接为IP报文
+        let mut packet = eth_header(EthernetType::Ipv4);
+        packet.extend(ipv4_header(IpProtocol::Udp, [10, 0, 0, 1], [10, 0, 0, 2]));
+        packet.extend(udp_header(6081));
+        packet.extend_from_slice(&[0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x64, 0x00]);
+        packet.extend(overlay_ipv4());
+
+        let l2_len = 14;
+        let mut actual = TunnelInfo::default();
+        let offset = actual.decapsulate(&mut packet, l2_len, &bitmap);
+        let expected_offset = IPV4_HEADER_SIZE + UDP_HEADER_SIZE + GENEVE_HEADER_SIZE - l2_len;
+
+        assert_eq!(offset, expected_offset);
+        assert_eq!(actual.tunnel_type, TunnelType::Geneve);
+        assert_eq!(actual.id, 100);
+        let mut expected_overlay = eth_header(EthernetType::Ipv4);
+        expected_overlay.extend(overlay_ipv4());
+        assert_eq!(&packet[l2_len + offset..], &expected_overlay[..]);
+    }
+
+    #[test]
+    fn test_decapsulate_ipv6_geneve() {
+        let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::Geneve]);
+        let expected = single_layer(TunnelInfo {
+            src: Ipv4Addr::new(0, 0, 0, 1),
+            dst: Ipv4Addr::new(0, 0, 0, 2),
+            mac_src: 0x22334455,
+            mac_dst: 0x778899aa,
+            id: 0xabcdef,
+            tunnel_type: TunnelType::Geneve,
+            tier: 1,
+            is_ipv6: true,
+            ..Default::default()
+        });
+        let mut packet = eth_header(EthernetType::Ipv6);
+        packet.extend(ipv6_header(IpProtocol::Udp));
+        packet.extend(udp_header(6081));
+        packet.extend_from_slice(&[0x00, 0x00, 0x65, 0x58, 0xab, 0xcd, 0xef, 0x00]);
+        packet.extend(overlay_ethernet());
+
+        let l2_len = 14;
+        let mut actual = TunnelInfo::default();
+        let offset = actual.decapsulate_v6(&mut packet, l2_len, &bitmap);
+        let expected_offset = IPV6_HEADER_SIZE + UDP_HEADER_SIZE + GENEVE_HEADER_SIZE;
+
+        assert_eq!(offset, expected_offset);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_decapsulate_gtpu() {
+        let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::Gtpu]);
+        let expected = single_layer(TunnelInfo {
+            src: Ipv4Addr::new(10, 0, 0, 1),
+            dst: Ipv4Addr::new(10, 0, 0, 2),
+            mac_src: 0x22334455,
+            mac_dst: 0x778899aa,
+            id: 0x11223344,
+            tunnel_type: TunnelType::Gtpu,
+            tier: 1,
+            is_ipv6: false,
+            ..Default::default()
+        });
+        // 带有PDU Session Container扩展头的G-PDU，内层为IPv6
+        let mut packet = eth_header(EthernetType::Ipv4);
+        packet.extend(ipv4_header(IpProtocol::Udp, [10, 0, 0, 1], [10, 0, 0, 2]));
+        packet.extend(udp_header(2152));
+        packet.extend_from_slice(&[0x34, 0xff, 0x00, 0x40, 0x11, 0x22, 0x33, 0x44]);
+        packet.extend_from_slice(&[0x00, 0x00, 0x00, 0x85]);
+        packet.extend_from_slice(&[0x01, 0x10, 0x09, 0x00]);
+        let mut overlay = ipv6_header(IpProtocol::Tcp);
+        overlay.extend_from_slice(&[0; TCP_HEADER_SIZE]);
+        packet.extend_from_slice(&overlay);
+
+        let l2_len = 14;
+        let mut actual = TunnelInfo::default();
+        let offset = actual.decapsulate(&mut packet, l2_len, &bitmap);
+        let expected_offset =
+            IPV4_HEADER_SIZE + UDP_HEADER_SIZE + GTPU_HEADER_SIZE + GTPU_OPTION_SIZE + 4 - l2_len;
+
+        assert_eq!(offset, expected_offset);
+        assert_eq!(actual, expected);
+        let mut expected_overlay = eth_header(EthernetType::Ipv6);
+        expected_overlay.extend(overlay);
+        assert_eq!(&packet[l2_len + offset..], &expected_overlay[..]);
+
+        // 非G-PDU（例如Echo Request）不处理
+        let mut packet = eth_header(EthernetType::Ipv4);
+        packet.extend(ipv4_header(IpProtocol::Udp, [10, 0, 0, 1], [10, 0, 0, 2]));
+        packet.extend(udp_header(2152));
+        packet.extend_from_slice(&[0x32, 0x01, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00]);
+        packet.extend_from_slice(&[0; 24]);
+        let mut actual = TunnelInfo::default();
+        assert_eq!(actual.decapsulate(&mut packet, l2_len, &bitmap), 0);
+        assert_eq!(actual.tunnel_type, TunnelType::None);
+    }
+
+    #[test]
+    fn test_decapsulate_mpls() {
+        let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::Mpls]);
+        let expected = single_layer(TunnelInfo {
+            mac_src: 0x22334455,
+            mac_dst: 0x778899aa,
+            id: 200,
+            tunnel_type: TunnelType::Mpls,
+            tier: 1,
+            ..Default::default()
+        });
+        // 两层标签，内层为IPv4
+        let mut packet = eth_header(EthernetType::MplsUnicast);
+        packet.extend_from_slice(&[0x00, 0x06, 0x40, 0x40, 0x00, 0x0c, 0x81, 0x40]);
+        packet.extend(overlay_ipv4());
+
+        let l2_len = 14;
+        let mut actual = TunnelInfo::default();
+        let start = actual.decapsulate_mpls(&mut packet, l2_len, &bitmap);
+
+        assert_eq!(start, 2 * MPLS_HEADER_SIZE);
+        assert_eq!(actual, expected);
+        let mut expected_overlay = eth_header(EthernetType::Ipv4);
+        expected_overlay.extend(overlay_ipv4());
+        assert_eq!(&packet[start..], &expected_overlay[..]);
+
+        // 带控制字的以太网伪线
+        let mut packet = eth_header(EthernetType::MplsUnicast);
+        packet.extend_from_slice(&[0x00, 0x0c, 0x81, 0x40, 0x00, 0x00, 0x00, 0x00]);
+        packet.extend(overlay_ethernet());
+
+        let mut actual = TunnelInfo::default();
+        let start = actual.decapsulate_mpls(&mut packet, l2_len, &bitmap);
+
+        assert_eq!(start, l2_len + MPLS_HEADER_SIZE + MPLS_PW_CONTROL_WORD_SIZE);
+        assert_eq!(actual, expected);
+        assert_eq!(&packet[start..], &overlay_ethernet()[..]);
+
+        // 没有栈底标签
+        let mut packet = eth_header(EthernetType::MplsUnicast);
+        packet.extend_from_slice(&[0x00, 0x06, 0x40, 0x40]);
+        let mut actual = TunnelInfo::default();
+        assert_eq!(actual.decapsulate_mpls(&mut packet, l2_len, &bitmap), 0);
+        assert_eq!(actual.tunnel_type, TunnelType::None);
+    }
+
+    #[test]
+    fn test_decapsulate_multi_layer() {
+        let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::Vxlan, TunnelType::TencentGre]);
+        // VXLAN in GRE
+        let mut packet = eth_header(EthernetType::Ipv4);
+        packet.extend(ipv4_header(IpProtocol::Gre, [10, 0, 0, 1], [10, 0, 0, 2]));
+        packet.extend_from_slice(&[0x20, 0x00, 0x08, 0x00, 0x00, 0x01, 0x02, 0x85]);
+        packet.extend(ipv4_header(
+            IpProtocol::Udp,
+            [172, 16, 0, 1],
+            [172, 16, 0, 2],
+        ));
+        packet.extend(udp_header(4789));
+        packet.extend_from_slice(&[0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x7b, 0x00]);
+        packet.extend(overlay_ethernet());
+
+        let l2_len = 14;
+        let mut actual = TunnelInfo::default();
+        let mut decap_len = 0;
+        for _ in 0..TUNNEL_TIER_LIMIT {
+            let offset = actual.decapsulate(&mut packet[decap_len..], l2_len, &bitmap);
+            if offset == 0 {
+                break;
+            }
+            decap_len += l2_len + offset;
+        }
+
+        assert_eq!(&packet[decap_len..], &overlay_ethernet()[..]);
+        assert_eq!(actual.tier, 2);
+        // 最外层隧道的信息保持不变
+        assert_eq!(actual.tunnel_type, TunnelType::TencentGre);
+        assert_eq!(actual.id, 0x10285);
+        assert_eq!(actual.mac_src, 0x22334455);
+        assert_eq!(
+            actual.layers(),
+            &[
+                TunnelLayer {
+                    tunnel_type: TunnelType::TencentGre,
+                    id: 0x10285,
+                    src: Ipv4Addr::new(10, 0, 0, 1),
+                    dst: Ipv4Addr::new(10, 0, 0, 2),
+                },
+                TunnelLayer {
+                    tunnel_type: TunnelType::Vxlan,
+                    id: 123,
+                    src: Ipv4Addr::new(172, 16, 0, 1),
+                    dst: Ipv4Addr::new(172, 16, 0, 2),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decapsulate_tier_limit() {
+        let bitmap = TunnelTypeBitmap::new(&vec![TunnelType::Ipip]);
+        let tiers = TUNNEL_TIER_LIMIT as usize + 1;
+        let mut packet = eth_header(EthernetType::Ipv4);
+        for i in 0..tiers {
+            packet.extend(ipv4_header(
+                IpProtocol::Ipv4,
+                [10, 0, 0, i as u8],
+                [10, 0, 1, i as u8],
+            ));
+        }
+        packet.extend(overlay_ipv4());
+
+        let l2_len = 14;
+        let mut actual = TunnelInfo::default();
+        let mut decap_len = 0;
+        for _ in 0..tiers {
+            let offset = actual.decapsulate(&mut packet[decap_len..], l2_len, &bitmap);
+            if offset == 0 {
+                break;
+            }
+            decap_len += l2_len + offset;
+        }
+
+        assert_eq!(actual.tier, TUNNEL_TIER_LIMIT);
+        assert_eq!(actual.layers().len(), TUNNEL_TIER_LIMIT as usize);
+        for (i, layer) in actual.layers().iter().enumerate() {
+            assert_eq!(layer.tunnel_type, TunnelType::Ipip);
+            assert_eq!(layer.src, Ipv4Addr::new(10, 0, 0, i as u8));
+        }
+        // 超过层数限制的隧道不再解封装
+        assert_eq!(
+            &packet[decap_len + l2_len..decap_len + l2_len + IPV4_HEADER_SIZE],
+            &ipv4_header(IpProtocol::Ipv4, [10, 0, 0, 4], [10, 0, 1, 4])[..]
+        );
+    }
 }
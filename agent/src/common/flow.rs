@@ -28,7 +28,7 @@ use serde::{Serialize, Serializer};
 #[cfg(target_os = "linux")]
 use super::super::ebpf::{MSG_REQUEST, MSG_REQUEST_END, MSG_RESPONSE, MSG_RESPONSE_END};
 use super::{
-    decapsulate::TunnelType,
+    decapsulate::{TunnelLayer, TunnelType, TUNNEL_TIER_LIMIT},
     enums::{EthernetType, IpProtocol, TapType, TcpFlags},
     tap_port::TapPort,
 };
@@ -40,7 +40,7 @@ use crate::{
 use crate::{
     flow_generator::protocol_logs::{duration_to_micros, to_string_format},
     flow_generator::FlowState,
-    metric::document::TapSide,
+    metric::{document::TapSide, meter::LatencyHistogram},
 };
 use public::utils::net::MacAddr;
 
@@ -230,6 +230,25 @@ pub struct TunnelField {
     pub tier: u8,
     #[serde(skip)]
     pub is_ipv6: bool,
+    // 由外向内的各层隧道，用于关联overlay流与underlay路径
+    #[serde(rename = "tunnel_tx_layers", serialize_with = "valid_tunnel_layers")]
+    pub tx_layers: [TunnelLayer; TUNNEL_TIER_LIMIT as usize],
+    #[serde(rename = "tunnel_rx_layers", serialize_with = "valid_tunnel_layers")]
+    pub rx_layers: [TunnelLayer; TUNNEL_TIER_LIMIT as usize],
+}
+
+fn valid_tunnel_layers<S>(
+    layers: &[TunnelLayer; TUNNEL_TIER_LIMIT as usize],
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(
+        layers
+            .iter()
+            .take_while(|l| l.tunnel_type != TunnelType::None),
+    )
 }
 
 pub fn mac_low32_to_string<S>(d: &u32, serializer: S) -> Result<S::Ok, S::Error>
@@ -255,6 +274,8 @@ impl Default for TunnelField {
             tunnel_type: TunnelType::default(),
             tier: 0,
             is_ipv6: false,
+            tx_layers: Default::default(),
+            rx_layers: Default::default(),
         }
     }
 }
@@ -266,9 +287,31 @@ impl TunnelField {
         swap(&mut self.tx_mac0, &mut self.rx_mac0);
         swap(&mut self.tx_mac1, &mut self.rx_mac1);
         swap(&mut self.tx_id, &mut self.rx_id);
+        swap(&mut self.tx_layers, &mut self.rx_layers);
+    }
+}
+
+impl From<&TunnelLayer> for flow_log::TunnelLayer {
+    fn from(l: &TunnelLayer) -> Self {
+        flow_log::TunnelLayer {
+            tunnel_type: l.tunnel_type as u32,
+            id: l.id,
+            ip0: u32::from_be_bytes(l.src.octets()),
+            ip1: u32::from_be_bytes(l.dst.octets()),
+        }
     }
 }
 
+fn tunnel_layers_to_pb(
+    layers: &[TunnelLayer; TUNNEL_TIER_LIMIT as usize],
+) -> Vec<flow_log::TunnelLayer> {
+    layers
+        .iter()
+        .take_while(|l| l.tunnel_type != TunnelType::None)
+        .map(|l| l.into())
+        .collect()
+}
+
 impl fmt::Display for TunnelField {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if self.tunnel_type == TunnelType::None {
@@ -303,6 +346,8 @@ impl From<TunnelField> for flow_log::TunnelField {
             tunnel_type: f.tunnel_type as u32,
             tier: f.tier as u32,
             is_ipv6: 0,
+            tx_layers: tunnel_layers_to_pb(&f.tx_layers),
+            rx_layers: tunnel_layers_to_pb(&f.rx_layers),
         }
     }
 }
@@ -364,6 +409,15 @@ pub struct TcpPerfStats {
     pub counts_peers: [TcpPerfCountsPeer; 2],
     #[serde(skip)]
     pub total_retrans_count: u32,
+
+    // 逐个样本记录的时延分布，仅用于指标统计
+    // per-sample latency distributions, only used by flow metrics
+    #[serde(skip)]
+    pub srt_histogram: LatencyHistogram,
+    #[serde(skip)]
+    pub art_histogram: LatencyHistogram, // UDP复用
+    #[serde(skip)]
+    pub cit_histogram: LatencyHistogram,
 }
 
 pub fn serialize_tcp_perf_counts<S>(
@@ -428,6 +482,10 @@ impl TcpPerfStats {
         self.counts_peers[0].sequential_merge(&other.counts_peers[0]);
         self.counts_peers[1].sequential_merge(&other.counts_peers[1]);
         self.total_retrans_count += other.total_retrans_count;
+
+        self.srt_histogram.merge(&other.srt_histogram);
+        self.art_histogram.merge(&other.art_histogram);
+        self.cit_histogram.merge(&other.cit_histogram);
     }
 
     pub fn reverse(&mut self) {
@@ -531,6 +589,14 @@ pub struct L7PerfStats {
     pub rrt_count: u32, // u32可记录40000M时延, 一条流在一分钟内的请求数远无法达到此数值
     pub rrt_sum: u64,   // us RRT(Request Response Time)
     pub rrt_max: u32,   // us agent保证在3600s以内
+    // 逐个样本记录的RRT分布，仅用于指标统计
+    // per-sample RRT distribution, only used by app metrics
+    #[serde(skip)]
+    pub rrt_histogram: LatencyHistogram,
+    // 按请求类型细分的统计，目前仅Redis按命令统计
+    // statistics broken down by request type, only redis commands for now
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub request_type_stats: Vec<L7RequestTypeStats>,
 }
 
 impl L7PerfStats {
@@ -545,6 +611,78 @@ impl L7PerfStats {
         if self.rrt_max < other.rrt_max {
             self.rrt_max = other.rrt_max
         }
+        self.rrt_histogram.merge(&other.rrt_histogram);
+        for stats in other.request_type_stats.iter() {
+            L7RequestTypeStats::get_or_insert(&mut self.request_type_stats, &stats.request_type)
+                .sequential_merge(stats);
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct L7RequestTypeStats {
+    pub request_type: String,
+    pub request_count: u32,
+    pub response_count: u32,
+    pub err_client_count: u32,
+    pub err_server_count: u32,
+    pub rrt_count: u32,
+    pub rrt_sum: u64, // us
+    pub rrt_max: u32, // us
+}
+
+impl L7RequestTypeStats {
+    // 每条流最多统计的请求类型数，超出的类型计入OTHERS
+    // the max number of request types per flow, the exceeded types are counted into OTHERS
+    pub const MAX_REQUEST_TYPES: usize = 32;
+    pub const OTHERS: &'static str = "OTHERS";
+
+    pub fn get_or_insert<'a>(stats: &'a mut Vec<Self>, request_type: &str) -> &'a mut Self {
+        let request_type = if stats.len() >= Self::MAX_REQUEST_TYPES
+            && !stats.iter().any(|s| s.request_type == request_type)
+        {
+            Self::OTHERS
+        } else {
+            request_type
+        };
+        let index = match stats.iter().position(|s| s.request_type == request_type) {
+            Some(i) => i,
+            None => {
+                stats.push(Self {
+                    request_type: request_type.to_owned(),
+                    ..Default::default()
+                });
+                stats.len() - 1
+            }
+        };
+        &mut stats[index]
+    }
+
+    pub fn sequential_merge(&mut self, other: &L7RequestTypeStats) {
+        self.request_count += other.request_count;
+        self.response_count += other.response_count;
+        self.err_client_count += other.err_client_count;
+        self.err_server_count += other.err_server_count;
+        self.rrt_count += other.rrt_count;
+        self.rrt_sum += other.rrt_sum;
+        if self.rrt_max < other.rrt_max {
+            self.rrt_max = other.rrt_max
+        }
+    }
+}
+
+impl From<L7RequestTypeStats> for flow_log::L7RequestTypeStats {
+    fn from(s: L7RequestTypeStats) -> Self {
+        flow_log::L7RequestTypeStats {
+            request_type: s.request_type,
+            request_count: s.request_count,
+            response_count: s.response_count,
+            err_client_count: s.err_client_count,
+            err_server_count: s.err_server_count,
+            rrt_count: s.rrt_count,
+            rrt_sum: s.rrt_sum,
+            rrt_max: s.rrt_max,
+        }
     }
 }
 
@@ -559,6 +697,7 @@ impl From<L7PerfStats> for flow_log::L7PerfStats {
             rrt_count: p.rrt_count,
             rrt_sum: p.rrt_sum,
             rrt_max: p.rrt_max,
+            request_type_stats: p.request_type_stats.into_iter().map(|s| s.into()).collect(),
         }
     }
 }
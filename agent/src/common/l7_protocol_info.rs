@@ -19,8 +19,8 @@ use serde::Serialize;
 
 use crate::flow_generator::{
     protocol_logs::{
-        pb_adapter::L7ProtocolSendLog, DnsInfo, DubboInfo, HttpInfo, KafkaInfo, MqttInfo,
-        MysqlInfo, PostgreInfo, RedisInfo,
+        pb_adapter::L7ProtocolSendLog, AmqpInfo, CustomInfo, DnsInfo, DubboInfo, GrpcInfo,
+        HttpInfo, KafkaInfo, MongoDBInfo, MqttInfo, MysqlInfo, PostgreInfo, RedisInfo,
     },
     AppProtoHead, Result,
 };
@@ -74,6 +74,10 @@ all_protocol_info!(
     //
     // add new protocol info below
     PostgreInfo(PostgreInfo),
+    GrpcInfo(GrpcInfo),
+    MongoDBInfo(MongoDBInfo),
+    AmqpInfo(AmqpInfo),
+    CustomInfo(CustomInfo),
 );
 
 #[enum_dispatch(L7ProtocolInfo)]
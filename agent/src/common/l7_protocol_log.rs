@@ -25,7 +25,8 @@ use super::MetaPacket;
 
 use crate::config::handler::LogParserAccess;
 use crate::flow_generator::protocol_logs::{
-    DnsLog, DubboLog, HttpLog, KafkaLog, MqttLog, MysqlLog, PostgresqlLog, RedisLog,
+    AmqpLog, DnsLog, DubboLog, GrpcLog, HttpLog, KafkaLog, MongoDBLog, MqttLog, MysqlLog,
+    PostgresqlLog, RedisLog, WasmLog,
 };
 use crate::flow_generator::Result;
 
@@ -140,6 +141,7 @@ about bitmap:
     Kafka = 100,
     Mqtt = 101,
     Dns = 120,
+    Custom = 127,
 
  TODO: cbpf 处理过程
  hint: check 和 parse 是同一个结构，check可以把解析结果保存下来,避免重复解析.
@@ -206,7 +208,6 @@ macro_rules! all_protocol {
             match p {
                 L7Protocol::Http1 | L7Protocol::Http1TLS => Some(L7ProtocolParser::HttpParser(HttpLog::new_v1())),
                 L7Protocol::Http2 | L7Protocol::Http2TLS => Some(L7ProtocolParser::HttpParser(HttpLog::new_v2(false))),
-                L7Protocol::Grpc => Some(L7ProtocolParser::HttpParser(HttpLog::new_v2(true))),
 
                 $(
                     L7Protocol::$l7_proto=>Some(L7ProtocolParser::$parser($log::$new_func())),
@@ -281,6 +282,12 @@ all_protocol!(
     Dubbo,DubboParser,DubboLog::default;
     MQTT,MqttParser,MqttLog::default;
     // add protocol below
+    // grpc is based on http2, must place after http2 parser
+    Grpc,GrpcParser,GrpcLog::new;
+    MongoDB,MongoDBParser,MongoDBLog::default;
+    AMQP,AmqpParser,AmqpLog::default;
+    // wasm plugins are checked after all builtin protocols
+    Custom,WasmParser,WasmLog::new;
 );
 
 impl L7ProtocolParser {
@@ -327,9 +334,30 @@ pub trait L7ProtocolParserInterface {
     fn parsable_on_udp(&self) -> bool {
         true
     }
+    // TCP流重组时用于分帧，返回payload开头第一个完整消息的长度，默认不分帧，按报文解析
+    // =================================================================================
+    // used to frame messages when tcp reassembly is enabled, returns the length of the
+    // first message at the head of payload. by default messages are not framed and each
+    // packet is parsed as is
+    fn message_length(&self, _payload: &[u8], _param: &ParseParam) -> MessageLength {
+        MessageLength::Unknown
+    }
     fn reset(&mut self);
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageLength {
+    // 第一个消息的完整长度，可能大于已收到的数据
+    // the full length of the first message, may exceed the received data
+    Complete(usize),
+    // 消息不完整且暂时无法确定长度，需等待更多数据
+    // the message is incomplete and its length is not known yet, wait for more data
+    Partial,
+    // 无法分帧，直接解析已收到的全部数据
+    // can not be framed, parse all received data as is
+    Unknown,
+}
+
 #[derive(Clone, Copy)]
 pub struct EbpfParam {
     pub is_tls: bool,
@@ -479,6 +507,11 @@ impl From<&Vec<String>> for L7ProtocolBitmap {
         for i in v.iter() {
             if let Ok(p) = L7ProtocolParser::try_from(i.as_str()) {
                 bitmap.set_enabled(p.protocol());
+                // gRPC 原先由 HTTP2 解析，已有配置中开启 HTTP2 时同时开启 gRPC
+                // gRPC used to be parsed as HTTP2, enable gRPC as well when HTTP2 is enabled in existing configs
+                if p.protocol() == L7Protocol::Http2 {
+                    bitmap.set_enabled(L7Protocol::Grpc);
+                }
             }
         }
         bitmap
@@ -62,6 +62,10 @@ pub struct MetaPacket<'a> {
 
     pub raw: Option<&'a [u8]>,
     pub packet_len: usize,
+    // IP分片重组得到的报文或未能重组而丢弃的分片所包含的原始分片数，其它报文为0
+    // number of original fragments of a reassembled packet or of fragments dropped
+    // without reassembly, 0 for other packets
+    pub ip_fragments: u32,
     pub vlan_tag_size: usize,
     pub ttl: u8,
     pub reset_ttl: bool,
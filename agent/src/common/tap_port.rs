@@ -73,9 +73,10 @@ impl TapPort {
         )
     }
 
-    pub fn from_tunnel_ip(ip: u32, is_ip_v6: bool) -> Self {
+    pub fn from_tunnel_ip(tunnel_type: TunnelType, ip: u32, is_ip_v6: bool) -> Self {
         Self(
             ip as u64
+                | ((tunnel_type as u64) << Self::TUNNEL_TYPE_OFFSET)
                 | ((if is_ip_v6 {
                     Self::FROM_TUNNEL_IPV6
                 } else {
@@ -135,10 +136,10 @@ impl fmt::Display for TapPort {
                 )
             }
             TapPort::FROM_TUNNEL_IPV4 => {
-                write!(f, "IPv4@{}", Ipv4Addr::from(p))
+                write!(f, "IPv4@{}@{}", tt, Ipv4Addr::from(p))
             }
             TapPort::FROM_TUNNEL_IPV6 => {
-                write!(f, "IPv6@{:#10x}", p)
+                write!(f, "IPv6@{}@{:#10x}", tt, p)
             }
             TapPort::FROM_ID => {
                 write!(f, "ID@{}@{}", tt, p)
@@ -162,3 +163,29 @@ impl fmt::Debug for TapPort {
         write!(f, "{}", self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tunnel_type_encoding() {
+        for tunnel_type in [TunnelType::Geneve, TunnelType::Gtpu, TunnelType::Mpls] {
+            let tap_port = TapPort::from_id(tunnel_type, 100);
+            assert_eq!(
+                tap_port.split_fields(),
+                (100, TapPort::FROM_ID, tunnel_type)
+            );
+
+            let tap_port = TapPort::from_tunnel_ip(tunnel_type, 0x0a000001, false);
+            assert_eq!(
+                tap_port.split_fields(),
+                (0x0a000001, TapPort::FROM_TUNNEL_IPV4, tunnel_type)
+            );
+            assert_eq!(
+                tap_port.to_string(),
+                format!("IPv4@{}@10.0.0.1", tunnel_type)
+            );
+        }
+    }
+}
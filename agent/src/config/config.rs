@@ -47,6 +47,9 @@ use crate::trident::RunningMode;
 const K8S_CA_CRT_PATH: &str = "/run/secrets/kubernetes.io/serviceaccount/ca.crt";
 const MINUTE: Duration = Duration::from_secs(60);
 const DEFAULT_STANDALONE_CONFIG: &str = "/etc/deepflow-agent-standalone.yaml";
+const DEFAULT_WASM_PLUGIN_FUEL_LIMIT: u64 = 1_000_000;
+const DEFAULT_WASM_PLUGIN_MEMORY_LIMIT: usize = 16 << 20;
+const WASM_PAGE_SIZE: usize = 64 << 10;
 
 #[derive(Debug, Error)]
 pub enum ConfigError {
@@ -255,6 +258,10 @@ pub struct YamlConfig {
     pub collector_sender_queue_count: usize,
     pub flow_sender_queue_size: usize,
     pub flow_sender_queue_count: usize,
+    pub collector_sender_spill_size: u32,
+    pub collector_sender_spill_dir: String,
+    pub sender_tls: SenderTlsConfig,
+    pub ip_fragment: IpFragmentConfig,
     #[serde(with = "humantime_serde")]
     pub second_flow_extra_delay: Duration,
     #[serde(with = "humantime_serde")]
@@ -272,6 +279,8 @@ pub struct YamlConfig {
     pub ebpf_log_file: String,
     pub kubernetes_namespace: String,
     pub external_metrics_sender_queue_size: usize,
+    pub external_otel_metrics_sender_queue_size: usize,
+    pub external_otel_logs_sender_queue_size: usize,
     pub l7_protocol_inference_max_fail_count: usize,
     pub l7_protocol_inference_ttl: usize,
     pub packet_sequence_block_size: usize, // Enterprise Edition Feature: packet-sequence
@@ -284,12 +293,27 @@ pub struct YamlConfig {
     #[serde(rename = "ebpf-uprobe-process-name-regexs")]
     pub ebpf_uprobe_proc_regexp: UprobeProcRegExp,
     pub external_agent_http_proxy_compressed: bool,
+    // 服务端暂不支持解析 OpenTelemetry 的 metrics 和 logs，默认关闭
+    // the server does not decode OpenTelemetry metrics and logs yet, disabled by default
+    pub external_agent_http_proxy_otel_metrics_logs_enabled: bool,
     pub standalone_data_file_size: u32,
     pub standalone_data_file_dir: String,
+    #[serde(with = "humantime_serde")]
+    pub standalone_data_file_rotate_interval: Duration,
+    pub standalone_data_file_retention_count: usize,
+    pub standalone_data_file_quota: u32,
     pub log_file: String,
     #[serde(rename = "l7-protocol-ports")]
     // hashmap<protocolName, portRange>
     pub l7_protocol_ports: HashMap<String, String>,
+    pub tcp_reassembly: TcpReassemblyConfig,
+    pub sql_normalization: SqlNormalizationConfig,
+    pub redis_log: RedisLogConfig,
+    pub l7_log_baggage_keys: Vec<String>,
+    pub http_log_capture: HttpLogCaptureConfig,
+    pub wasm_plugin_dir: String,
+    pub wasm_plugin_fuel_limit: u64,
+    pub wasm_plugin_memory_limit: usize,
 }
 
 impl YamlConfig {
@@ -350,6 +374,18 @@ impl YamlConfig {
         if c.first_path_level < 1 || c.first_path_level > 16 {
             c.first_path_level = 8;
         }
+        if c.ip_fragment.timeout < Duration::from_secs(1)
+            || c.ip_fragment.timeout > Duration::from_secs(60)
+        {
+            c.ip_fragment.timeout = Duration::from_secs(5);
+        }
+        if c.ip_fragment.max_memory == 0 {
+            c.ip_fragment.max_memory = 4;
+        }
+        if c.tcp_reassembly.max_buffer_size < 1024 || c.tcp_reassembly.max_buffer_size > 16 << 20 {
+            c.tcp_reassembly.max_buffer_size = 64 << 10;
+        }
+        c.http_log_capture.normalize();
 
         // L7Log Session timeout must more than or equal 10s to keep window
         if c.l7_log_session_aggr_timeout.as_secs() < 10 {
@@ -359,6 +395,12 @@ impl YamlConfig {
         if c.external_metrics_sender_queue_size == 0 {
             c.external_metrics_sender_queue_size = 1 << 12;
         }
+        if c.external_otel_metrics_sender_queue_size == 0 {
+            c.external_otel_metrics_sender_queue_size = 1 << 12;
+        }
+        if c.external_otel_logs_sender_queue_size == 0 {
+            c.external_otel_logs_sender_queue_size = 1 << 12;
+        }
 
         if c.l7_protocol_inference_max_fail_count == 0 {
             c.l7_protocol_inference_max_fail_count = L7_PROTOCOL_INFERENCE_MAX_FAIL_COUNT;
@@ -392,10 +434,24 @@ impl YamlConfig {
         }
         c.vxlan_flags |= 0x08;
 
+        if c.collector_sender_spill_dir.len() == 0 {
+            c.collector_sender_spill_dir = Path::new(DEFAULT_LOG_FILE)
+                .parent()
+                .unwrap()
+                .join("spill")
+                .to_str()
+                .unwrap()
+                .to_string();
+        }
+
         if c.standalone_data_file_size == 0 {
             c.standalone_data_file_size = 200;
         }
 
+        if c.standalone_data_file_retention_count == 0 {
+            c.standalone_data_file_retention_count = 168;
+        }
+
         if c.standalone_data_file_dir.len() == 0 {
             c.standalone_data_file_dir = Path::new(DEFAULT_LOG_FILE)
                 .parent()
@@ -405,6 +461,13 @@ impl YamlConfig {
                 .to_string();
         }
 
+        if c.wasm_plugin_fuel_limit == 0 {
+            c.wasm_plugin_fuel_limit = DEFAULT_WASM_PLUGIN_FUEL_LIMIT;
+        }
+        if c.wasm_plugin_memory_limit < WASM_PAGE_SIZE {
+            c.wasm_plugin_memory_limit = DEFAULT_WASM_PLUGIN_MEMORY_LIMIT;
+        }
+
         if let Err(e) = c.validate() {
             return Err(io::Error::new(io::ErrorKind::InvalidInput, e.to_string()));
         }
@@ -488,6 +551,16 @@ impl Default for YamlConfig {
             // default size changes according to tap_mode
             flow_sender_queue_size: 1 << 16,
             flow_sender_queue_count: 1,
+            collector_sender_spill_size: 0,
+            collector_sender_spill_dir: Path::new(DEFAULT_LOG_FILE)
+                .parent()
+                .unwrap()
+                .join("spill")
+                .to_str()
+                .unwrap()
+                .to_string(),
+            sender_tls: Default::default(),
+            ip_fragment: Default::default(),
             second_flow_extra_delay: Duration::from_secs(0),
             packet_delay: Duration::from_secs(1),
             triple: Default::default(),
@@ -502,6 +575,8 @@ impl Default for YamlConfig {
             ebpf_log_file: "".into(),
             kubernetes_namespace: "".into(),
             external_metrics_sender_queue_size: 1 << 12,
+            external_otel_metrics_sender_queue_size: 1 << 12,
+            external_otel_logs_sender_queue_size: 1 << 12,
             l7_protocol_inference_max_fail_count: L7_PROTOCOL_INFERENCE_MAX_FAIL_COUNT,
             l7_protocol_inference_ttl: L7_PROTOCOL_INFERENCE_TTL,
             packet_sequence_block_size: 64, // Enterprise Edition Feature: packet-sequence
@@ -519,6 +594,7 @@ impl Default for YamlConfig {
                 protos
             },
             external_agent_http_proxy_compressed: false,
+            external_agent_http_proxy_otel_metrics_logs_enabled: false,
             standalone_data_file_size: 200,
             standalone_data_file_dir: Path::new(DEFAULT_LOG_FILE)
                 .parent()
@@ -526,9 +602,20 @@ impl Default for YamlConfig {
                 .to_str()
                 .unwrap()
                 .to_string(),
+            standalone_data_file_rotate_interval: Duration::from_secs(3600),
+            standalone_data_file_retention_count: 168,
+            standalone_data_file_quota: 10240,
 
             log_file: DEFAULT_LOG_FILE.into(),
             l7_protocol_ports: HashMap::from([(String::from("DNS"), String::from("53"))]),
+            tcp_reassembly: Default::default(),
+            sql_normalization: Default::default(),
+            redis_log: Default::default(),
+            l7_log_baggage_keys: vec![],
+            http_log_capture: Default::default(),
+            wasm_plugin_dir: "".into(),
+            wasm_plugin_fuel_limit: DEFAULT_WASM_PLUGIN_FUEL_LIMIT,
+            wasm_plugin_memory_limit: DEFAULT_WASM_PLUGIN_MEMORY_LIMIT,
         }
     }
 }
@@ -590,6 +677,7 @@ pub struct PcapConfig {
     pub max_file_period: Duration,
     pub file_directory: PathBuf,
     pub server_port: u32,
+    pub file_format: PcapFileFormat,
 }
 
 impl Default for PcapConfig {
@@ -607,10 +695,22 @@ impl Default for PcapConfig {
             max_file_period: Duration::from_secs(300),
             file_directory: "/var/lib/pcap".into(),
             server_port: 20205,
+            file_format: PcapFileFormat::Pcap,
         }
     }
 }
 
+// pcapng 格式会为每个 tap port 写入 Interface Description Block，
+// 并在每个包的注释中记录 acl_gid、tap_type 和 flow_id
+// pcapng writes an Interface Description Block for each tap port,
+// and records acl_gid, tap_type and flow_id in the comment of each packet
+#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum PcapFileFormat {
+    Pcap,
+    Pcapng,
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct FlowGeneratorConfig {
@@ -672,6 +772,142 @@ impl Default for XflowGeneratorConfig {
     }
 }
 
+// 发送数据到数据节点时使用的TLS配置，cert-file和key-file同时配置时启用双向认证
+// TLS settings for sending data to analyzers, mutual TLS is enabled if both
+// cert-file and key-file are set
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SenderTlsConfig {
+    pub enabled: bool,
+    pub ca_file: String,
+    pub cert_file: String,
+    pub key_file: String,
+    pub server_name: String,
+}
+
+// IP分片重组配置，每个dispatcher独立重组，max-memory单位为M
+// IP fragment reassembly settings, each dispatcher reassembles on its own, max-memory is in MB
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct IpFragmentConfig {
+    pub enabled: bool,
+    #[serde(with = "humantime_serde")]
+    pub timeout: Duration,
+    pub max_memory: u32,
+}
+
+impl Default for IpFragmentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout: Duration::from_secs(5),
+            max_memory: 4,
+        }
+    }
+}
+
+// TCP流重组配置，protocols为需要重组的应用协议，max-buffer-size为每条流每个方向的缓存上限，单位为字节
+// TCP reassembly settings, protocols are the application protocols to reassemble,
+// max-buffer-size is the buffer limit of each direction of a flow in bytes
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct TcpReassemblyConfig {
+    pub protocols: Vec<String>,
+    pub max_buffer_size: usize,
+}
+
+impl Default for TcpReassemblyConfig {
+    fn default() -> Self {
+        Self {
+            protocols: vec![],
+            max_buffer_size: 64 << 10,
+        }
+    }
+}
+
+// SQL语句规范化配置，enabled开启后MySQL和PostgreSQL日志携带语句指纹及其哈希，
+// redact-raw-statement开启后以指纹代替原始语句上送
+// SQL normalization settings, MySQL and PostgreSQL logs carry the statement fingerprint
+// and its hash when enabled, and the fingerprint replaces the raw statement when
+// redact-raw-statement is on
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct SqlNormalizationConfig {
+    pub enabled: bool,
+    pub redact_raw_statement: bool,
+}
+
+// Redis日志的请求参数处理，超过max-argument-length的参数截断，0表示不截断；
+// redact-values开启后仅保留命令名和第一个参数（通常为key），其余参数以?代替，AUTH和HELLO的参数全部以?代替，
+// 批量回复和聚合回复的内容也以?代替
+// Argument handling of redis logs, arguments longer than max-argument-length are truncated,
+// 0 means no truncation. When redact-values is on, only the command name and the first
+// argument (usually the key) are kept and the other arguments are replaced by ?, all
+// arguments of AUTH and HELLO are replaced, so are the contents of bulk and aggregate replies
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct RedisLogConfig {
+    pub max_argument_length: usize,
+    pub redact_values: bool,
+}
+
+// HTTP日志额外采集的请求头、响应头和query参数，redact-names中的header和query参数的值整体脱敏，
+// redact-patterns中的正则表达式匹配的部分脱敏，作用于采集的值和URL路径
+// Extra request headers, response headers and query parameters captured into HTTP logs.
+// Values of headers and query parameters in redact-names are masked entirely, and parts
+// matching redact-patterns are masked in captured values and URL paths
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct HttpLogCaptureConfig {
+    pub request_headers: Vec<String>,
+    pub response_headers: Vec<String>,
+    pub query_parameters: Vec<String>,
+    pub redact_names: Vec<String>,
+    pub redact_patterns: Vec<String>,
+}
+
+impl Default for HttpLogCaptureConfig {
+    fn default() -> Self {
+        Self {
+            request_headers: vec![],
+            response_headers: vec![],
+            query_parameters: vec![],
+            redact_names: vec![
+                "authorization".to_string(),
+                "proxy-authorization".to_string(),
+                "cookie".to_string(),
+                "set-cookie".to_string(),
+            ],
+            redact_patterns: vec![],
+        }
+    }
+}
+
+impl HttpLogCaptureConfig {
+    // header名和query参数名统一为小写，丢弃非法的正则表达式
+    // Lowercases header and query parameter names, and drops malformed regexes
+    fn normalize(&mut self) {
+        for names in [
+            &mut self.request_headers,
+            &mut self.response_headers,
+            &mut self.query_parameters,
+            &mut self.redact_names,
+        ] {
+            for name in names.iter_mut() {
+                *name = name.trim().to_lowercase();
+            }
+            names.retain(|name| !name.is_empty());
+        }
+        self.redact_patterns.retain(|p| {
+            if let Err(e) = regex::Regex::new(p) {
+                warn!("http-log-capture: malformed redact-pattern({}): {}", p, e);
+                return false;
+            }
+            true
+        });
+    }
+}
+
 #[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
 #[serde(default, rename_all = "kebab-case")]
 pub struct TripleMapConfig {
@@ -845,7 +1081,7 @@ impl RuntimeConfig {
         Ok(c)
     }
 
-    fn standalone_default() -> Self {
+    pub(crate) fn standalone_default() -> Self {
         Self {
             vtap_group_id: Default::default(),
             enabled: true,
@@ -1174,7 +1410,7 @@ where
             TunnelType::try_from(t).map_err(|_| {
                 de::Error::invalid_value(
                     Unexpected::Unsigned(t as u64),
-                    &"None|Vxlan|Ipip|TencentGre|ErspanOrTeb",
+                    &"None|Vxlan|Ipip|TencentGre|ErspanOrTeb|Geneve|Gtpu|Mpls",
                 )
             })
         })
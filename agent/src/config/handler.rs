@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use std::borrow::Cow;
 use std::cmp::{max, min};
 use std::fmt;
 use std::net::IpAddr;
@@ -31,12 +32,16 @@ use cgroups_rs::{CpuResources, MemoryResources, Resources};
 use flexi_logger::writers::FileLogWriter;
 use flexi_logger::{Age, Cleanup, Criterion, FileSpec, LoggerHandle, Naming};
 use log::{info, warn, Level};
+use regex::Regex;
 use sysinfo::SystemExt;
 
 #[cfg(target_os = "linux")]
 use super::config::UprobeProcRegExp;
 use super::{
-    config::{Config, PcapConfig, PortConfig, YamlConfig},
+    config::{
+        Config, HttpLogCaptureConfig, PcapConfig, PortConfig, RedisLogConfig, SenderTlsConfig,
+        SqlNormalizationConfig, YamlConfig,
+    },
     ConfigError, IngressFlavour, KubernetesPollerType, RuntimeConfig,
 };
 
@@ -52,7 +57,7 @@ use crate::{
     common::{decapsulate::TunnelTypeBitmap, enums::TapType},
     dispatcher::recv_engine,
     exception::ExceptionHandler,
-    flow_generator::{FlowTimeout, TcpTimeout},
+    flow_generator::{protocol_logs::update_wasm_plugins, FlowTimeout, TcpTimeout},
     handler::PacketHandlerBuilder,
     proto::trident::{self, CaptureSocketType},
     proto::{
@@ -182,8 +187,14 @@ pub struct SenderConfig {
     pub npb_socket_type: trident::SocketType,
     pub compressor_socket_type: trident::SocketType,
     pub collector_socket_type: trident::SocketType,
+    pub collector_sender_spill_size: u32,
+    pub collector_sender_spill_dir: String,
+    pub tls: SenderTlsConfig,
     pub standalone_data_file_size: u32,
     pub standalone_data_file_dir: String,
+    pub standalone_data_file_rotate_interval: Duration,
+    pub standalone_data_file_retention_count: usize,
+    pub standalone_data_file_quota: u32,
     pub server_tx_bandwidth_threshold: u64,
     pub bandwidth_probe_interval: Duration,
     pub enabled: bool,
@@ -404,6 +415,18 @@ pub struct LogParserConfig {
     pub l7_log_collect_nps_threshold: u64,
     pub l7_log_session_aggr_timeout: Duration,
     pub l7_log_dynamic: L7LogDynamicConfig,
+    pub wasm_plugin: WasmPluginConfig,
+    pub tcp_reassembly_bitmap: L7ProtocolBitmap,
+    pub tcp_reassembly_max_buffer_size: usize,
+    pub sql_normalization: SqlNormalizationConfig,
+    pub redis_log: RedisLogConfig,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct WasmPluginConfig {
+    pub dir: String,
+    pub fuel_limit: u64,
+    pub memory_limit: usize,
 }
 
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -511,6 +534,7 @@ pub enum TraceType {
     Sw6,
     Sw8,
     TraceParent,
+    B3,
     Customize(String),
 }
 
@@ -520,6 +544,7 @@ const TRACE_TYPE_UBER: &str = "uber-trace-id";
 const TRACE_TYPE_SW6: &str = "sw6";
 const TRACE_TYPE_SW8: &str = "sw8";
 const TRACE_TYPE_TRACE_PARENT: &str = "traceparent";
+const TRACE_TYPE_B3: &str = "b3";
 
 impl From<&str> for TraceType {
     // 参数支持如下两种格式：
@@ -538,6 +563,7 @@ impl From<&str> for TraceType {
             TRACE_TYPE_SW6 => TraceType::Sw6,
             TRACE_TYPE_SW8 => TraceType::Sw8,
             TRACE_TYPE_TRACE_PARENT => TraceType::TraceParent,
+            TRACE_TYPE_B3 => TraceType::B3,
             _ if t.len() > 0 => TraceType::Customize(t.to_string()),
             _ => TraceType::Disabled,
         }
@@ -566,6 +592,7 @@ impl TraceType {
             TraceType::Sw6 => context == TRACE_TYPE_SW6,
             TraceType::Sw8 => context == TRACE_TYPE_SW8,
             TraceType::TraceParent => context == TRACE_TYPE_TRACE_PARENT,
+            TraceType::B3 => context.eq_ignore_ascii_case(TRACE_TYPE_B3),
             TraceType::Customize(tag) => context == tag.as_str(),
             _ => false,
         }
@@ -579,6 +606,7 @@ impl TraceType {
             TraceType::Sw6 => TRACE_TYPE_SW6.to_string(),
             TraceType::Sw8 => TRACE_TYPE_SW8.to_string(),
             TraceType::TraceParent => TRACE_TYPE_TRACE_PARENT.to_string(),
+            TraceType::B3 => TRACE_TYPE_B3.to_string(),
             TraceType::Customize(tag) => tag.to_string(),
             _ => "".to_string(),
         }
@@ -602,6 +630,10 @@ pub struct L7LogDynamicConfig {
 
     pub trace_types: Vec<TraceType>,
     pub span_types: Vec<TraceType>,
+    // 从W3C baggage中提取的key
+    // keys extracted from W3C baggage
+    pub baggage_keys: Vec<String>,
+    pub http_capture: HttpCaptureRules,
 }
 
 impl L7LogDynamicConfig {
@@ -622,6 +654,121 @@ impl L7LogDynamicConfig {
         }
         return false;
     }
+
+    pub fn has_trace_type(&self, trace_type: &TraceType) -> bool {
+        self.trace_types.contains(trace_type) || self.span_types.contains(trace_type)
+    }
+}
+
+// 按源字符串比较的正则表达式，便于配置变更检测
+// Regex compared by its source so that configs holding it can be compared
+#[derive(Clone, Debug)]
+pub struct RegexPattern(pub Regex);
+
+impl PartialEq for RegexPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl Eq for RegexPattern {}
+
+// HTTP日志额外采集的header、query参数及脱敏规则，名字均为小写
+// Extra headers and query parameters captured into HTTP logs and the redaction rules,
+// all names are in lowercase
+#[derive(Default, Clone, PartialEq, Eq, Debug)]
+pub struct HttpCaptureRules {
+    pub request_headers: Vec<String>,
+    pub response_headers: Vec<String>,
+    pub query_parameters: Vec<String>,
+    pub redact_names: Vec<String>,
+    pub redact_patterns: Vec<RegexPattern>,
+}
+
+impl From<&HttpLogCaptureConfig> for HttpCaptureRules {
+    fn from(c: &HttpLogCaptureConfig) -> Self {
+        Self {
+            request_headers: c.request_headers.clone(),
+            response_headers: c.response_headers.clone(),
+            query_parameters: c.query_parameters.clone(),
+            redact_names: c.redact_names.clone(),
+            redact_patterns: c
+                .redact_patterns
+                .iter()
+                .filter_map(|p| Regex::new(p).ok().map(RegexPattern))
+                .collect(),
+        }
+    }
+}
+
+impl HttpCaptureRules {
+    pub const REDACTED: &'static str = "***";
+
+    // 名字在redact-names中时整体脱敏，否则脱敏正则表达式匹配的部分
+    // Masks the whole value if the name is in redact-names, otherwise masks the parts
+    // matching redact-patterns
+    pub fn redact<'a>(&self, name: &str, value: &'a str) -> Cow<'a, str> {
+        if self.redact_names.iter().any(|n| n == name) {
+            return Cow::Borrowed(Self::REDACTED);
+        }
+        self.redact_by_patterns(value)
+    }
+
+    fn redact_by_patterns<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        let mut value = Cow::Borrowed(value);
+        for pattern in self.redact_patterns.iter() {
+            if pattern.0.is_match(&value) {
+                value = Cow::Owned(pattern.0.replace_all(&value, Self::REDACTED).into_owned());
+            }
+        }
+        value
+    }
+
+    // 脱敏URL中redact-names包含的query参数及正则表达式匹配的部分
+    // Masks query parameters in redact-names and the parts matching redact-patterns in a URL
+    pub fn redact_url<'a>(&self, url: &'a str) -> Cow<'a, str> {
+        let (path, query) = match url.split_once('?') {
+            Some((path, query)) if !self.redact_names.is_empty() => (path, query),
+            _ => return self.redact_by_patterns(url),
+        };
+        let mut redacted = false;
+        let params = query
+            .split('&')
+            .map(|param| match param.split_once('=') {
+                Some((name, _)) if self.redact_names.contains(&name.to_lowercase()) => {
+                    redacted = true;
+                    format!("{}={}", name, Self::REDACTED)
+                }
+                _ => param.to_string(),
+            })
+            .collect::<Vec<_>>();
+        if !redacted {
+            return self.redact_by_patterns(url);
+        }
+        let url = format!("{}?{}", path, params.join("&"));
+        Cow::Owned(self.redact_by_patterns(&url).into_owned())
+    }
+
+    // 从URL中提取需要采集的query参数，返回(参数名, 脱敏后的值)
+    // Extracts the query parameters to capture from a URL as (name, redacted value)
+    pub fn capture_query<'a>(
+        &'a self,
+        url: &'a str,
+    ) -> impl Iterator<Item = (String, Cow<'a, str>)> + 'a {
+        let query = match url.split_once('?') {
+            Some((_, query)) if !self.query_parameters.is_empty() => query,
+            _ => "",
+        };
+        query.split('&').filter_map(move |param| {
+            let (name, value) = param.split_once('=').unwrap_or((param, ""));
+            let name = name.to_lowercase();
+            if !self.query_parameters.contains(&name) {
+                return None;
+            }
+            let value = self.redact(&name, value);
+            Some((name, value))
+        })
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -629,6 +776,7 @@ pub struct MetricServerConfig {
     pub enabled: bool,
     pub port: u16,
     pub compressed: bool,
+    pub otel_metrics_logs_enabled: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -765,8 +913,18 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 server_tx_bandwidth_threshold: conf.server_tx_bandwidth_threshold,
                 bandwidth_probe_interval: conf.bandwidth_probe_interval,
                 collector_socket_type: conf.collector_socket_type,
+                collector_sender_spill_size: conf.yaml_config.collector_sender_spill_size,
+                collector_sender_spill_dir: conf.yaml_config.collector_sender_spill_dir.clone(),
+                tls: conf.yaml_config.sender_tls.clone(),
                 standalone_data_file_size: conf.yaml_config.standalone_data_file_size,
                 standalone_data_file_dir: conf.yaml_config.standalone_data_file_dir.clone(),
+                standalone_data_file_rotate_interval: conf
+                    .yaml_config
+                    .standalone_data_file_rotate_interval,
+                standalone_data_file_retention_count: conf
+                    .yaml_config
+                    .standalone_data_file_retention_count,
+                standalone_data_file_quota: conf.yaml_config.standalone_data_file_quota,
                 enabled: conf.collector_enabled,
             },
             npb: NpbConfig {
@@ -852,7 +1010,20 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                         .split(',')
                         .map(|item| TraceType::from(item))
                         .collect(),
+                    baggage_keys: conf.yaml_config.l7_log_baggage_keys.clone(),
+                    http_capture: HttpCaptureRules::from(&conf.yaml_config.http_log_capture),
+                },
+                wasm_plugin: WasmPluginConfig {
+                    dir: conf.yaml_config.wasm_plugin_dir.clone(),
+                    fuel_limit: conf.yaml_config.wasm_plugin_fuel_limit,
+                    memory_limit: conf.yaml_config.wasm_plugin_memory_limit,
                 },
+                tcp_reassembly_bitmap: L7ProtocolBitmap::from(
+                    &conf.yaml_config.tcp_reassembly.protocols,
+                ),
+                tcp_reassembly_max_buffer_size: conf.yaml_config.tcp_reassembly.max_buffer_size,
+                sql_normalization: conf.yaml_config.sql_normalization,
+                redis_log: conf.yaml_config.redis_log,
             },
             debug: DebugConfig {
                 vtap_id: conf.vtap_id as u16,
@@ -912,6 +1083,9 @@ impl TryFrom<(Config, RuntimeConfig)> for ModuleConfig {
                 enabled: conf.external_agent_http_proxy_enabled,
                 port: conf.external_agent_http_proxy_port as u16,
                 compressed: conf.yaml_config.external_agent_http_proxy_compressed,
+                otel_metrics_logs_enabled: conf
+                    .yaml_config
+                    .external_agent_http_proxy_otel_metrics_logs_enabled,
             },
             trident_type: conf.trident_type,
             port_config: PortConfig {
@@ -1736,6 +1910,11 @@ impl ConfigHandler {
                 }
                 callbacks.push(l7_log_collect_nps_threshold_callback);
             }
+            // 插件在配置线程中加载，报文处理线程只读取加载完成的插件
+            // plugins are loaded on the config thread, packet threads only read the loaded plugins
+            if candidate_config.log_parser.wasm_plugin != new_config.log_parser.wasm_plugin {
+                update_wasm_plugins(&new_config.log_parser.wasm_plugin);
+            }
 
             candidate_config.log_parser = new_config.log_parser;
         }
@@ -1806,6 +1985,22 @@ impl ConfigHandler {
                 }
                 callbacks.push(metric_server_callback);
             }
+            if candidate_config.metric_server.otel_metrics_logs_enabled
+                != new_config.metric_server.otel_metrics_logs_enabled
+            {
+                fn otel_metrics_logs_callback(
+                    handler: &ConfigHandler,
+                    components: &mut Components,
+                ) {
+                    components.external_metrics_server.enable_otel_metrics_logs(
+                        handler
+                            .candidate_config
+                            .metric_server
+                            .otel_metrics_logs_enabled,
+                    );
+                }
+                callbacks.push(otel_metrics_logs_callback);
+            }
             info!(
                 "integration collector config change from {:#?} to {:#?}",
                 candidate_config.metric_server, new_config.metric_server
@@ -18,7 +18,9 @@ mod config;
 pub mod handler;
 
 pub use config::{
-    Config, ConfigError, FlowGeneratorConfig, IngressFlavour, KubernetesPollerType, PcapConfig,
-    RuntimeConfig, TripleMapConfig, UprobeProcRegExp, XflowGeneratorConfig, YamlConfig,
+    Config, ConfigError, FlowGeneratorConfig, HttpLogCaptureConfig, IngressFlavour,
+    IpFragmentConfig, KubernetesPollerType, PcapConfig, PcapFileFormat, RedisLogConfig,
+    RuntimeConfig, SenderTlsConfig, SqlNormalizationConfig, TripleMapConfig, UprobeProcRegExp,
+    XflowGeneratorConfig, YamlConfig,
 };
 pub use handler::{DispatcherConfig, FlowAccess, FlowConfig, ModuleConfig, NpbConfig};
@@ -315,6 +315,7 @@ impl AnalyzerModeDispatcher {
                 meta_packet.tunnel = Some(&base.tunnel_info);
                 if base.tunnel_info.tunnel_type == TunnelType::TencentGre
                     || base.tunnel_info.tunnel_type == TunnelType::Vxlan
+                    || base.tunnel_info.tunnel_type == TunnelType::Geneve
                 {
                     // Tencent TCE and Qingyun Private Cloud need to query cloud platform information through TunnelID
                     // Only the case of single-layer tunnel encapsulation needs to be considered here
@@ -330,7 +331,17 @@ impl AnalyzerModeDispatcher {
                 base.npb_dedup_enabled.load(Ordering::Relaxed),
             );
             // flowProcesser
-            flow_map.inject_meta_packet(&mut meta_packet);
+            let defragmented = base
+                .fragment_reassembler
+                .inject(overlay_packet, meta_packet.lookup_key.timestamp);
+            BaseDispatcher::inject_meta_packet(
+                &mut flow_map,
+                &mut base.policy_getter,
+                base.id,
+                &mut base.fragment_reassembler,
+                &defragmented,
+                &mut meta_packet,
+            );
             let mini_packet = MiniPacket::new(overlay_packet, &meta_packet);
             for i in pipeline.handlers.iter_mut() {
                 i.handle(&mini_packet);
@@ -27,12 +27,13 @@ use std::sync::{
 use std::thread;
 use std::time::Duration;
 
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
 
 #[cfg(target_os = "windows")]
 use super::error::Error;
 use super::{
     error::Result,
+    fragment::{Defragmented, FragmentReassembler},
     recv_engine::{self, bpf, RecvEngine},
     BpfOptions, Options, PacketCounter, Pipeline,
 };
@@ -45,7 +46,7 @@ use crate::config::handler::LogParserAccess;
 use crate::platform::GenericPoller;
 use crate::{
     common::{
-        decapsulate::{TunnelInfo, TunnelType, TunnelTypeBitmap},
+        decapsulate::{TunnelInfo, TunnelType, TunnelTypeBitmap, TUNNEL_TIER_LIMIT},
         endpoint::FeatureFlags,
         enums::{EthernetType, TapType},
         MetaPacket, TaggedFlow, TapTyper, DEFAULT_CONTROLLER_PORT, DEFAULT_INGESTER_PORT,
@@ -53,7 +54,7 @@ use crate::{
     },
     config::{handler::FlowAccess, DispatcherConfig},
     exception::ExceptionHandler,
-    flow_generator::MetaAppProto,
+    flow_generator::{FlowMap, MetaAppProto},
     handler::PacketHandlerBuilder,
     policy::PolicyGetter,
     proto::trident::{Exception, IfMacSource, TapMode},
@@ -89,6 +90,7 @@ pub(super) struct BaseDispatcher {
 
     pub(super) tunnel_type_bitmap: Arc<Mutex<TunnelTypeBitmap>>,
     pub(super) tunnel_info: TunnelInfo,
+    pub(super) fragment_reassembler: FragmentReassembler,
 
     pub(super) tap_type_handler: TapTypeHandler,
 
@@ -138,6 +140,73 @@ impl BaseDispatcher {
         meta_packet.queue_hash = queue_hash;
     }
 
+    // 分片重组完成时以重组后的报文代替分片注入FlowMap，使L7能解析完整载荷，
+    // 缓存中的分片只查询策略，供PCAP和NPB等后续处理使用，未能重组的分片按原始报文注入
+    // once reassembled, the datagram instead of the fragment is injected into FlowMap so
+    // that L7 parses the whole payload, buffered fragments only look up policies for
+    // later processes such as PCAP and NPB, fragments failed to reassemble are injected as is
+    pub(super) fn inject_meta_packet(
+        flow_map: &mut FlowMap,
+        policy_getter: &mut PolicyGetter,
+        id: usize,
+        fragment_reassembler: &mut FragmentReassembler,
+        defragmented: &Defragmented,
+        meta_packet: &mut MetaPacket,
+    ) {
+        for dropped in fragment_reassembler.drain_dropped() {
+            if let Some(mut dropped_packet) = dropped.meta_packet() {
+                flow_map.inject_meta_packet(&mut dropped_packet);
+            }
+        }
+
+        let datagram = match defragmented {
+            Defragmented::Bypass => {
+                flow_map.inject_meta_packet(meta_packet);
+                return;
+            }
+            Defragmented::Pending => {
+                policy_getter.lookup(meta_packet, id);
+                fragment_reassembler.record(meta_packet);
+                return;
+            }
+            Defragmented::Complete(d) => d,
+        };
+
+        let mut reassembled = MetaPacket::empty();
+        if let Err(e) = reassembled.update(
+            &datagram.packet,
+            meta_packet.lookup_key.l2_end_0,
+            meta_packet.lookup_key.l2_end_1,
+            meta_packet.lookup_key.timestamp,
+            datagram.packet.len(),
+        ) {
+            debug!("reassembled packet update failed: {:?}", e);
+            flow_map.inject_meta_packet(meta_packet);
+            return;
+        }
+        // 除端口外沿用分片的查询键，保留dispatcher对MAC、隧道等的修改
+        // reuse lookup key of the fragment except ports, keeping changes on MAC,
+        // tunnel and so on made by dispatchers
+        let (src_port, dst_port) = (
+            reassembled.lookup_key.src_port,
+            reassembled.lookup_key.dst_port,
+        );
+        reassembled.lookup_key = meta_packet.lookup_key.clone();
+        reassembled.lookup_key.src_port = src_port;
+        reassembled.lookup_key.dst_port = dst_port;
+        reassembled.tunnel = meta_packet.tunnel;
+        reassembled.tap_port = meta_packet.tap_port;
+        reassembled.reset_ttl = meta_packet.reset_ttl;
+        reassembled.queue_hash = meta_packet.queue_hash;
+        // L4统计使用原始分片的包数和包长
+        // L4 metrics count packets and bytes of the original fragments
+        reassembled.packet_len = datagram.bytes;
+        reassembled.ip_fragments = datagram.fragments;
+        flow_map.inject_meta_packet(&mut reassembled);
+
+        policy_getter.lookup(meta_packet, id);
+    }
+
     pub(super) fn listener(&self) -> BaseDispatcherListener {
         BaseDispatcherListener {
             id: self.id,
@@ -304,6 +373,13 @@ impl BaseDispatcher {
             // 最外层隧道封装，可能是ERSPAN或VXLAN
             EthernetType::Ipv4 => tunnel_info.decapsulate(packet, l2_len, bitmap),
             EthernetType::Ipv6 => tunnel_info.decapsulate_v6(packet, l2_len, bitmap),
+            // MPLS返回的是去除标签栈后l2层头的位置
+            EthernetType::MplsUnicast | EthernetType::MplsMulticast => {
+                return Ok((
+                    tunnel_info.decapsulate_mpls(packet, l2_len, bitmap),
+                    tap_type,
+                ));
+            }
             _ => 0,
         };
         if offset == 0 {
@@ -321,8 +397,8 @@ impl BaseDispatcher {
     ) -> Result<(usize, TapType)> {
         let mut decap_len = 0;
         let mut tap_type = TapType::Any;
-        // 仅解析两层隧道
-        for i in 0..2 {
+        // 最多解析TUNNEL_TIER_LIMIT层隧道
+        for i in 0..TUNNEL_TIER_LIMIT {
             let (offset, t) = Self::decapsulate(
                 &mut packet[decap_len..],
                 tap_type_handler,
@@ -461,6 +537,13 @@ impl BaseDispatcher {
             // 最外层隧道封装，可能是ERSPAN或VXLAN
             EthernetType::Ipv4 => tunnel_info.decapsulate(packet, l2_len, bitmap),
             EthernetType::Ipv6 => tunnel_info.decapsulate_v6(packet, l2_len, bitmap),
+            // MPLS返回的是去除标签栈后l2层头的位置
+            EthernetType::MplsUnicast | EthernetType::MplsMulticast => {
+                return Ok((
+                    tunnel_info.decapsulate_mpls(packet, l2_len, bitmap),
+                    tap_type,
+                ));
+            }
             _ => 0,
         };
         if offset == 0 {
@@ -478,8 +561,8 @@ impl BaseDispatcher {
     ) -> Result<(usize, TapType)> {
         let mut decap_len = 0;
         let mut tap_type = TapType::Any;
-        // 仅解析两层隧道
-        for i in 0..2 {
+        // 最多解析TUNNEL_TIER_LIMIT层隧道
+        for i in 0..TUNNEL_TIER_LIMIT {
             let (offset, t) = Self::decapsulate(
                 &mut packet[decap_len..],
                 tap_type_handler,
@@ -0,0 +1,1081 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::{BTreeSet, HashMap};
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::Duration;
+use std::vec::Drain;
+
+use log::debug;
+
+use crate::{
+    common::{
+        decapsulate::TunnelInfo,
+        enums::{EthernetType, IpProtocol},
+        ipv4, ipv6,
+        lookup_key::LookupKey,
+        MetaPacket, TapPort, ETH_HEADER_SIZE, FIELD_OFFSET_ETH_TYPE, IPV4_ADDR_LEN,
+        IPV4_FRAG_MORE_FRAGMENT, IPV4_HEADER_SIZE, IPV6_ADDR_LEN, IPV6_FRAGMENT_LEN,
+        IPV6_FRAG_MORE_FRAGMENT, IPV6_HEADER_SIZE, VLAN_HEADER_SIZE,
+    },
+    config::IpFragmentConfig,
+    utils::{
+        bytes::{read_u16_be, read_u32_be},
+        stats,
+    },
+};
+
+const IPV4_FRAG_OFFSET_MASK: u16 = 0x1FFF;
+const IPV6_FRAG_OFFSET_MASK: u16 = 0xFFF8;
+const FRAG_OFFSET_UNIT: usize = 8;
+// IPv6分片头之前最多解析的扩展头个数
+// max number of IPv6 extension headers parsed before the fragment header
+const IPV6_EXTENSION_HEADER_LIMIT: usize = 8;
+
+#[derive(Default)]
+pub struct FragmentCounter {
+    // 收到的分片数
+    // fragments received
+    fragments: AtomicU64,
+    // 重组完成的报文数
+    // datagrams reassembled
+    reassembled: AtomicU64,
+    // 超时丢弃的报文数
+    // datagrams dropped on timeout
+    timeout: AtomicU64,
+    // 内存不足时淘汰的报文数
+    // datagrams evicted on memory limit
+    evicted: AtomicU64,
+    // 截断、重叠或超长而无法重组的分片数
+    // fragments that can not be reassembled due to truncation, overlapping or oversize
+    invalid: AtomicU64,
+    memory: AtomicU64,
+}
+
+impl stats::RefCountable for FragmentCounter {
+    fn get_counters(&self) -> Vec<stats::Counter> {
+        vec![
+            (
+                "fragments",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(self.fragments.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "reassembled",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(self.reassembled.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "timeout",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(self.timeout.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "evicted",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(self.evicted.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "invalid",
+                stats::CounterType::Counted,
+                stats::CounterValue::Unsigned(self.invalid.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "memory",
+                stats::CounterType::Gauged,
+                stats::CounterValue::Unsigned(self.memory.load(Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+// 重组后的报文
+// a reassembled datagram
+pub struct Datagram {
+    // 以首个分片的L2和L3头部重建的完整报文，不含分片标识
+    // full packet rebuilt with L2 and L3 headers of the first fragment, without fragmentation
+    pub packet: Vec<u8>,
+    // 原始分片数和原始分片的包长之和，用于L4统计
+    // number and total length of the original fragments, used by L4 metrics
+    pub fragments: u32,
+    pub bytes: usize,
+}
+
+// 缓存分片的分发上下文，报文无法重组时用于把分片注入FlowMap
+// dispatch context of buffered fragments, used to inject them into FlowMap when the
+// datagram can not be reassembled
+struct FragmentContext {
+    packet: Vec<u8>,
+    lookup_key: LookupKey,
+    tunnel: Option<TunnelInfo>,
+    tap_port: TapPort,
+    reset_ttl: bool,
+    queue_hash: u8,
+}
+
+// 超时、淘汰或冲突而未能重组的分片
+// fragments dropped on timeout, eviction or conflicts without being reassembled
+pub struct DroppedFragments {
+    context: FragmentContext,
+    pub fragments: u32,
+    pub bytes: usize,
+}
+
+impl DroppedFragments {
+    // 以首个缓存分片重建MetaPacket，包数和包长为所有被丢弃分片之和，用于L4统计
+    // rebuilds MetaPacket with the first buffered fragment, counting packets and bytes
+    // of all dropped fragments for L4 metrics
+    pub fn meta_packet(&self) -> Option<MetaPacket<'_>> {
+        let context = &self.context;
+        let mut meta_packet = MetaPacket::empty();
+        if let Err(e) = meta_packet.update(
+            &context.packet,
+            context.lookup_key.l2_end_0,
+            context.lookup_key.l2_end_1,
+            context.lookup_key.timestamp,
+            context.packet.len(),
+        ) {
+            debug!("dropped fragment update failed: {:?}", e);
+            return None;
+        }
+        meta_packet.lookup_key = context.lookup_key.clone();
+        meta_packet.tunnel = context.tunnel.as_ref();
+        meta_packet.tap_port = context.tap_port;
+        meta_packet.reset_ttl = context.reset_ttl;
+        meta_packet.queue_hash = context.queue_hash;
+        meta_packet.packet_len = self.bytes;
+        meta_packet.ip_fragments = self.fragments;
+        Some(meta_packet)
+    }
+}
+
+pub enum Defragmented {
+    // 不是分片或分片无法重组，按原始报文处理
+    // not a fragment, or a fragment that can not be reassembled, handled as is
+    Bypass,
+    // 分片已缓存，等待其余分片
+    // fragment is buffered, waiting for the rest
+    Pending,
+    // 最后一个分片到达，重组完成
+    // the last fragment arrived and the datagram is reassembled
+    Complete(Datagram),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct FragmentKey {
+    src: IpAddr,
+    dst: IpAddr,
+    id: u32,
+    proto: u8,
+}
+
+struct Fragment<'a> {
+    key: FragmentKey,
+    is_ipv6: bool,
+    l3_offset: usize,
+    // L2和L3头部，IPv6时为分片头之前的部分
+    // L2 and L3 headers, for IPv6 it's the part before the fragment header
+    header: &'a [u8],
+    // IPv6分片头之前的next header字段的位置
+    // position of the next header field preceding the IPv6 fragment header
+    next_header_offset: usize,
+    offset: usize,
+    more: bool,
+    payload: &'a [u8],
+    packet_len: usize,
+}
+
+impl<'a> Fragment<'a> {
+    // 非IP分片时返回Ok(None)，分片不完整时返回Err
+    // returns Ok(None) if packet is not an IP fragment, Err if the fragment is incomplete
+    fn parse(packet: &'a [u8]) -> Result<Option<Self>, &'static str> {
+        if packet.len() < ETH_HEADER_SIZE {
+            return Ok(None);
+        }
+        let mut l3_offset = ETH_HEADER_SIZE;
+        let mut eth_type = read_u16_be(&packet[FIELD_OFFSET_ETH_TYPE..]);
+        // 和MetaPacket一样最多解析两层VLAN
+        // parse at most two VLAN tags as MetaPacket does
+        for _ in 0..2 {
+            if eth_type != EthernetType::Dot1Q {
+                break;
+            }
+            l3_offset += VLAN_HEADER_SIZE;
+            if packet.len() < l3_offset {
+                return Ok(None);
+            }
+            eth_type = read_u16_be(&packet[l3_offset - 2..]);
+        }
+        if eth_type == EthernetType::Ipv4 {
+            Self::parse_ipv4(packet, l3_offset)
+        } else if eth_type == EthernetType::Ipv6 {
+            Self::parse_ipv6(packet, l3_offset)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_ipv4(packet: &'a [u8], l3_offset: usize) -> Result<Option<Self>, &'static str> {
+        if packet.len() < l3_offset + IPV4_HEADER_SIZE {
+            return Ok(None);
+        }
+        let l3 = &packet[l3_offset..];
+        let frag = read_u16_be(&l3[ipv4::FLAGS_OFFSET..]);
+        if frag & IPV4_FRAG_OFFSET_MASK == 0 && frag & IPV4_FRAG_MORE_FRAGMENT == 0 {
+            return Ok(None);
+        }
+        let ihl = (l3[ipv4::VERSION_IHL_OFFSET] & 0xF) as usize * 4;
+        let total_length = read_u16_be(&l3[ipv4::TOTAL_LENGTH_OFFSET..]) as usize;
+        if ihl < IPV4_HEADER_SIZE || total_length <= ihl {
+            return Err("invalid ipv4 header");
+        }
+        if l3.len() < total_length {
+            return Err("fragment truncated");
+        }
+        let src =
+            <[u8; 4]>::try_from(&l3[ipv4::SRC_OFFSET..ipv4::SRC_OFFSET + IPV4_ADDR_LEN]).unwrap();
+        let dst =
+            <[u8; 4]>::try_from(&l3[ipv4::DST_OFFSET..ipv4::DST_OFFSET + IPV4_ADDR_LEN]).unwrap();
+        Ok(Some(Self {
+            key: FragmentKey {
+                src: IpAddr::from(Ipv4Addr::from(src)),
+                dst: IpAddr::from(Ipv4Addr::from(dst)),
+                id: read_u16_be(&l3[ipv4::ID_OFFSET..]) as u32,
+                proto: l3[ipv4::PROTO_OFFSET],
+            },
+            is_ipv6: false,
+            l3_offset,
+            header: &packet[..l3_offset + ihl],
+            next_header_offset: 0,
+            offset: (frag & IPV4_FRAG_OFFSET_MASK) as usize * FRAG_OFFSET_UNIT,
+            more: frag & IPV4_FRAG_MORE_FRAGMENT != 0,
+            payload: &l3[ihl..total_length],
+            packet_len: l3_offset + total_length,
+        }))
+    }
+
+    fn parse_ipv6(packet: &'a [u8], l3_offset: usize) -> Result<Option<Self>, &'static str> {
+        if packet.len() < l3_offset + IPV6_HEADER_SIZE {
+            return Ok(None);
+        }
+        let payload_length = read_u16_be(&packet[l3_offset + ipv6::PAYLOAD_LENGTH_OFFSET..]);
+        // payload为0时可能是TSO或Jumbo报文，不做处理
+        // zero payload length implies TSO or jumbo packets, which are left as is
+        if payload_length == 0 {
+            return Ok(None);
+        }
+        let end = l3_offset + IPV6_HEADER_SIZE + payload_length as usize;
+        let mut next_header_offset = l3_offset + ipv6::PROTO_OFFSET;
+        let mut offset = l3_offset + IPV6_HEADER_SIZE;
+        for _ in 0..IPV6_EXTENSION_HEADER_LIMIT {
+            if offset + 2 > end.min(packet.len()) {
+                return Ok(None);
+            }
+            let next_header = packet[next_header_offset];
+            let length = if next_header == IpProtocol::Ipv6HopByHop
+                || next_header == IpProtocol::Ipv6Routing
+                || next_header == IpProtocol::Ipv6Destination
+            {
+                (packet[offset + 1] as usize + 1) * 8
+            } else if next_header == IpProtocol::Ah {
+                (packet[offset + 1] as usize + 2) * 4
+            } else if next_header == IpProtocol::Ipv6Fragment {
+                break;
+            } else {
+                return Ok(None);
+            };
+            next_header_offset = offset;
+            offset += length;
+        }
+        if packet[next_header_offset] != IpProtocol::Ipv6Fragment {
+            return Ok(None);
+        }
+        if offset + IPV6_FRAGMENT_LEN > end.min(packet.len()) {
+            return Err("invalid ipv6 fragment header");
+        }
+        let frag = read_u16_be(&packet[offset + ipv6::FRAG_OFFSET..]);
+        // 原子分片无需重组
+        // atomic fragments need no reassembly
+        if frag & IPV6_FRAG_OFFSET_MASK == 0 && frag & IPV6_FRAG_MORE_FRAGMENT == 0 {
+            return Ok(None);
+        }
+        if offset + IPV6_FRAGMENT_LEN == end {
+            return Err("invalid ipv6 fragment header");
+        }
+        if packet.len() < end {
+            return Err("fragment truncated");
+        }
+        let l3 = &packet[l3_offset..];
+        let src =
+            <[u8; 16]>::try_from(&l3[ipv6::SRC_OFFSET..ipv6::SRC_OFFSET + IPV6_ADDR_LEN]).unwrap();
+        let dst =
+            <[u8; 16]>::try_from(&l3[ipv6::DST_OFFSET..ipv6::DST_OFFSET + IPV6_ADDR_LEN]).unwrap();
+        Ok(Some(Self {
+            key: FragmentKey {
+                src: IpAddr::from(Ipv6Addr::from(src)),
+                dst: IpAddr::from(Ipv6Addr::from(dst)),
+                id: read_u32_be(&packet[offset + ipv6::FRAG_ID_OFFSET..]),
+                proto: packet[offset],
+            },
+            is_ipv6: true,
+            l3_offset,
+            header: &packet[..offset],
+            next_header_offset,
+            offset: (frag & IPV6_FRAG_OFFSET_MASK) as usize,
+            more: frag & IPV6_FRAG_MORE_FRAGMENT != 0,
+            payload: &packet[offset + IPV6_FRAGMENT_LEN..end],
+            packet_len: end,
+        }))
+    }
+}
+
+#[derive(Default)]
+struct FragmentQueue {
+    start_time: Duration,
+    is_ipv6: bool,
+    l3_offset: usize,
+    // 首个分片的L2和L3头部，IPv6的next header已替换为上层协议
+    // L2 and L3 headers of the first fragment, with IPv6 next header replaced by the upper protocol
+    header: Vec<u8>,
+    // 按offset排序的分片载荷
+    // fragment payloads sorted by offset
+    fragments: Vec<(usize, Vec<u8>)>,
+    // 最后一个分片到达后才能确定
+    // known after the last fragment arrived
+    total_length: Option<usize>,
+    received: usize,
+    packet_count: u32,
+    packet_bytes: usize,
+    memory: usize,
+    // mirror模式下同一报文可能在两个方向分别处理，因此可能有多个上下文
+    // a packet may be handled twice in mirror mode, so there may be more than one context
+    contexts: Vec<FragmentContext>,
+}
+
+impl FragmentQueue {
+    // 返回分片在队列中的插入位置，重复分片返回Ok(None)，与已有分片冲突时返回Err
+    // returns the insert position of the fragment, Ok(None) for duplicates and Err on conflicts
+    fn position(&self, fragment: &Fragment) -> Result<Option<usize>, &'static str> {
+        let start = fragment.offset;
+        let end = start + fragment.payload.len();
+        if end > u16::MAX as usize {
+            return Err("datagram oversize");
+        }
+        if fragment.more && fragment.payload.len() % FRAG_OFFSET_UNIT != 0 {
+            return Err("fragment length unaligned");
+        }
+        match self.total_length {
+            Some(total) if end > total || !fragment.more && end != total => {
+                return Err("fragment beyond the last one");
+            }
+            None if !fragment.more => {
+                if let Some((offset, data)) = self.fragments.last() {
+                    if offset + data.len() > end {
+                        return Err("fragment beyond the last one");
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        let index = self
+            .fragments
+            .partition_point(|(offset, _)| *offset < start);
+        if let Some((offset, data)) = self.fragments.get(index) {
+            if *offset == start && data.len() == fragment.payload.len() {
+                return Ok(None);
+            }
+            if *offset < end {
+                return Err("fragment overlapped");
+            }
+        }
+        if index > 0 {
+            let (offset, data) = &self.fragments[index - 1];
+            if offset + data.len() > start {
+                return Err("fragment overlapped");
+            }
+        }
+        Ok(Some(index))
+    }
+
+    fn insert(&mut self, index: usize, fragment: &Fragment) {
+        if fragment.offset == 0 {
+            self.is_ipv6 = fragment.is_ipv6;
+            self.l3_offset = fragment.l3_offset;
+            self.header = fragment.header.to_vec();
+            if fragment.is_ipv6 {
+                self.header[fragment.next_header_offset] = fragment.key.proto;
+            }
+            self.memory += self.header.len();
+        }
+        if !fragment.more {
+            self.total_length = Some(fragment.offset + fragment.payload.len());
+        }
+        self.fragments
+            .insert(index, (fragment.offset, fragment.payload.to_vec()));
+        self.received += fragment.payload.len();
+        self.packet_count += 1;
+        self.packet_bytes += fragment.packet_len;
+        self.memory += fragment.payload.len();
+    }
+
+    fn is_complete(&self) -> bool {
+        // 分片之间没有重叠，收到的长度等于总长度即表示没有空洞
+        // fragments never overlap, so there's no hole if received length equals to the total
+        !self.header.is_empty() && self.total_length == Some(self.received)
+    }
+
+    fn assemble(self) -> Result<Datagram, &'static str> {
+        let l3_header_len = self.header.len() - self.l3_offset;
+        let mut packet = self.header;
+        packet.reserve(self.received);
+        for (_, data) in self.fragments.iter() {
+            packet.extend_from_slice(data);
+        }
+        let l3 = &mut packet[self.l3_offset..];
+        if self.is_ipv6 {
+            let payload_length = l3_header_len - IPV6_HEADER_SIZE + self.received;
+            if payload_length > u16::MAX as usize {
+                return Err("datagram oversize");
+            }
+            l3[ipv6::PAYLOAD_LENGTH_OFFSET..ipv6::PAYLOAD_LENGTH_OFFSET + 2]
+                .copy_from_slice(&(payload_length as u16).to_be_bytes());
+        } else {
+            let total_length = l3_header_len + self.received;
+            if total_length > u16::MAX as usize {
+                return Err("datagram oversize");
+            }
+            l3[ipv4::TOTAL_LENGTH_OFFSET..ipv4::TOTAL_LENGTH_OFFSET + 2]
+                .copy_from_slice(&(total_length as u16).to_be_bytes());
+            l3[ipv4::FLAGS_OFFSET..ipv4::FLAGS_OFFSET + 2].copy_from_slice(&[0, 0]);
+        }
+        Ok(Datagram {
+            packet,
+            fragments: self.packet_count,
+            bytes: self.packet_bytes,
+        })
+    }
+}
+
+// 以(src, dst, id, proto)为键缓存IP分片，超时或超出内存限制时丢弃最早的报文
+// buffers IP fragments keyed by (src, dst, id, proto), the oldest datagrams are dropped
+// on timeout or when memory limit is exceeded
+pub struct FragmentReassembler {
+    enabled: bool,
+    timeout: Duration,
+    max_memory: usize,
+
+    memory: usize,
+    queues: HashMap<FragmentKey, FragmentQueue>,
+    // 按首个分片的时间排序，用于超时和淘汰
+    // sorted by time of the first fragment, used for timeout and eviction
+    time_set: BTreeSet<(Duration, FragmentKey)>,
+    // 最近一个分片创建了新的缓存队列时有值，用于记录该队列的分发上下文
+    // set if the last fragment created a new queue, whose dispatch context is to be recorded
+    recording: Option<FragmentKey>,
+    dropped: Vec<DroppedFragments>,
+
+    counter: Arc<FragmentCounter>,
+}
+
+impl FragmentReassembler {
+    pub fn new(config: &IpFragmentConfig) -> Self {
+        Self {
+            enabled: config.enabled,
+            timeout: config.timeout,
+            max_memory: config.max_memory as usize * 1024 * 1024,
+            memory: 0,
+            queues: HashMap::new(),
+            time_set: BTreeSet::new(),
+            recording: None,
+            dropped: vec![],
+            counter: Default::default(),
+        }
+    }
+
+    pub fn counter(&self) -> &Arc<FragmentCounter> {
+        &self.counter
+    }
+
+    pub fn inject(&mut self, packet: &[u8], timestamp: Duration) -> Defragmented {
+        self.recording = None;
+        if !self.enabled {
+            return Defragmented::Bypass;
+        }
+        let fragment = match Fragment::parse(packet) {
+            Ok(Some(f)) => f,
+            Ok(None) => return Defragmented::Bypass,
+            Err(e) => {
+                self.counter.fragments.fetch_add(1, Ordering::Relaxed);
+                self.counter.invalid.fetch_add(1, Ordering::Relaxed);
+                debug!("ip fragment ignored: {}", e);
+                return Defragmented::Bypass;
+            }
+        };
+        self.counter.fragments.fetch_add(1, Ordering::Relaxed);
+        self.expire(timestamp);
+
+        let index = match self.queues.get(&fragment.key) {
+            Some(queue) => queue.position(&fragment),
+            None => FragmentQueue::default().position(&fragment),
+        };
+        let index = match index {
+            Ok(Some(i)) => i,
+            Ok(None) => {
+                // 重复的分片不缓存，只计入报文的包数和包长
+                // duplicate fragments are not buffered, but counted in packets and bytes
+                if let Some(queue) = self.queues.get_mut(&fragment.key) {
+                    queue.packet_count += 1;
+                    queue.packet_bytes += fragment.packet_len;
+                }
+                return Defragmented::Pending;
+            }
+            Err(e) => {
+                // 无法重组时丢弃整个报文，其余分片按原始报文处理
+                // drop the whole datagram if it can not be reassembled, the rest
+                // fragments are handled as is
+                self.counter.invalid.fetch_add(1, Ordering::Relaxed);
+                debug!("ip fragment ignored: {}", e);
+                self.drop_queue(&fragment.key);
+                return Defragmented::Bypass;
+            }
+        };
+
+        let required = fragment.payload.len()
+            + if fragment.offset == 0 {
+                fragment.header.len()
+            } else {
+                0
+            };
+        if required > self.max_memory {
+            self.counter.invalid.fetch_add(1, Ordering::Relaxed);
+            return Defragmented::Bypass;
+        }
+        while self.memory + required > self.max_memory {
+            let oldest = match self.time_set.iter().next() {
+                Some((_, key)) => *key,
+                None => break,
+            };
+            self.drop_queue(&oldest);
+            self.counter.evicted.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let time_set = &mut self.time_set;
+        let queue = self.queues.entry(fragment.key).or_insert_with(|| {
+            time_set.insert((timestamp, fragment.key));
+            FragmentQueue {
+                start_time: timestamp,
+                ..Default::default()
+            }
+        });
+        // 淘汰可能移除了当前报文的队列，需要重新计算插入位置
+        // eviction may have removed the queue of this datagram, recalculate the position
+        let index = if queue.fragments.is_empty() { 0 } else { index };
+        let memory = queue.memory;
+        queue.insert(index, &fragment);
+        self.memory += queue.memory - memory;
+
+        let result = if queue.is_complete() {
+            let mut queue = self.remove(&fragment.key).unwrap();
+            let contexts = mem::take(&mut queue.contexts);
+            let (packet_count, packet_bytes) = (queue.packet_count, queue.packet_bytes);
+            match queue.assemble() {
+                Ok(d) => {
+                    self.counter.reassembled.fetch_add(1, Ordering::Relaxed);
+                    Defragmented::Complete(d)
+                }
+                Err(e) => {
+                    self.counter.invalid.fetch_add(1, Ordering::Relaxed);
+                    debug!("ip fragment ignored: {}", e);
+                    // 当前分片按原始报文处理，其余缓存的分片作为被丢弃的分片统计
+                    // this fragment is handled as is, the other buffered ones are counted
+                    // as dropped fragments
+                    self.push_dropped(
+                        contexts,
+                        packet_count - 1,
+                        packet_bytes - fragment.packet_len,
+                    );
+                    Defragmented::Bypass
+                }
+            }
+        } else {
+            if queue.contexts.is_empty() {
+                self.recording = Some(fragment.key);
+            }
+            Defragmented::Pending
+        };
+        self.counter
+            .memory
+            .store(self.memory as u64, Ordering::Relaxed);
+        result
+    }
+
+    fn expire(&mut self, timestamp: Duration) {
+        while let Some((start_time, key)) = self.time_set.iter().next().copied() {
+            if start_time + self.timeout >= timestamp {
+                break;
+            }
+            self.drop_queue(&key);
+            self.counter.timeout.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // 记录最近一个分片所在报文的分发上下文，在分片缓存后由dispatcher调用
+    // records the dispatch context of the datagram of the last fragment, called by
+    // dispatchers after the fragment is buffered
+    pub fn record(&mut self, meta_packet: &MetaPacket) {
+        let queue = match self.recording.and_then(|key| self.queues.get_mut(&key)) {
+            Some(queue) => queue,
+            None => return,
+        };
+        let context = FragmentContext {
+            packet: meta_packet.raw.unwrap_or_default().to_vec(),
+            lookup_key: meta_packet.lookup_key.clone(),
+            tunnel: meta_packet.tunnel.copied(),
+            tap_port: meta_packet.tap_port,
+            reset_ttl: meta_packet.reset_ttl,
+            queue_hash: meta_packet.queue_hash,
+        };
+        queue.memory += context.packet.len();
+        self.memory += context.packet.len();
+        queue.contexts.push(context);
+        self.counter
+            .memory
+            .store(self.memory as u64, Ordering::Relaxed);
+    }
+
+    // 取出未能重组的分片，由dispatcher注入FlowMap，避免L4统计丢失
+    // takes fragments dropped without reassembly, dispatchers inject them into FlowMap
+    // so that they are not missing from L4 metrics
+    pub fn drain_dropped(&mut self) -> Drain<'_, DroppedFragments> {
+        self.dropped.drain(..)
+    }
+
+    fn push_dropped(&mut self, contexts: Vec<FragmentContext>, fragments: u32, bytes: usize) {
+        if fragments == 0 {
+            return;
+        }
+        for context in contexts {
+            self.dropped.push(DroppedFragments {
+                context,
+                fragments,
+                bytes,
+            });
+        }
+    }
+
+    fn drop_queue(&mut self, key: &FragmentKey) {
+        if let Some(queue) = self.remove(key) {
+            self.push_dropped(queue.contexts, queue.packet_count, queue.packet_bytes);
+        }
+    }
+
+    fn remove(&mut self, key: &FragmentKey) -> Option<FragmentQueue> {
+        let queue = self.queues.remove(key)?;
+        self.time_set.remove(&(queue.start_time, *key));
+        self.memory -= queue.memory;
+        Some(queue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: u16 = 0x1234;
+    const UDP_PAYLOAD_LEN: usize = 3000;
+
+    fn config() -> IpFragmentConfig {
+        IpFragmentConfig {
+            enabled: true,
+            timeout: Duration::from_secs(5),
+            max_memory: 1,
+        }
+    }
+
+    fn udp(payload_len: usize) -> Vec<u8> {
+        let mut udp = vec![];
+        udp.extend_from_slice(&53u16.to_be_bytes());
+        udp.extend_from_slice(&33333u16.to_be_bytes());
+        udp.extend_from_slice(&((payload_len + 8) as u16).to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend((0..payload_len).map(|i| i as u8));
+        udp
+    }
+
+    fn eth(eth_type: EthernetType) -> Vec<u8> {
+        let mut eth = vec![
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x00, 0x66, 0x77, 0x88, 0x99, 0xaa,
+        ];
+        eth.extend_from_slice(&u16::from(eth_type).to_be_bytes());
+        eth
+    }
+
+    fn ipv4(frag: u16, data: &[u8]) -> Vec<u8> {
+        let mut packet = eth(EthernetType::Ipv4);
+        packet.extend_from_slice(&[0x45, 0]);
+        packet.extend_from_slice(&((IPV4_HEADER_SIZE + data.len()) as u16).to_be_bytes());
+        packet.extend_from_slice(&ID.to_be_bytes());
+        packet.extend_from_slice(&frag.to_be_bytes());
+        packet.extend_from_slice(&[64, u8::from(IpProtocol::Udp), 0, 0]);
+        packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    fn ipv6(next_header: IpProtocol, data: &[u8]) -> Vec<u8> {
+        let mut packet = eth(EthernetType::Ipv6);
+        packet.extend_from_slice(&[0x60, 0, 0, 0]);
+        packet.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        packet.extend_from_slice(&[u8::from(next_header), 64]);
+        packet.extend_from_slice(&"2001:db8::1".parse::<Ipv6Addr>().unwrap().octets());
+        packet.extend_from_slice(&"2001:db8::2".parse::<Ipv6Addr>().unwrap().octets());
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    // 按每片size字节切分L3载荷
+    // splits L3 payload into fragments of size bytes each
+    fn ipv4_fragments(data: &[u8], size: usize) -> Vec<Vec<u8>> {
+        data.chunks(size)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut frag = (i * size / FRAG_OFFSET_UNIT) as u16;
+                if (i + 1) * size < data.len() {
+                    frag |= IPV4_FRAG_MORE_FRAGMENT;
+                }
+                ipv4(frag, chunk)
+            })
+            .collect()
+    }
+
+    fn ipv6_fragments(data: &[u8], size: usize) -> Vec<Vec<u8>> {
+        data.chunks(size)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut frag = (i * size) as u16;
+                if (i + 1) * size < data.len() {
+                    frag |= IPV6_FRAG_MORE_FRAGMENT;
+                }
+                let mut payload = vec![u8::from(IpProtocol::Udp), 0];
+                payload.extend_from_slice(&frag.to_be_bytes());
+                payload.extend_from_slice(&(ID as u32).to_be_bytes());
+                payload.extend_from_slice(chunk);
+                ipv6(IpProtocol::Ipv6Fragment, &payload)
+            })
+            .collect()
+    }
+
+    fn reassemble(
+        reassembler: &mut FragmentReassembler,
+        fragments: &[Vec<u8>],
+        timestamp: Duration,
+    ) -> Option<Datagram> {
+        let mut datagram = None;
+        for (i, f) in fragments.iter().enumerate() {
+            match reassembler.inject(f, timestamp) {
+                Defragmented::Pending => assert!(i + 1 < fragments.len()),
+                Defragmented::Complete(d) => {
+                    assert_eq!(i + 1, fragments.len());
+                    datagram = Some(d);
+                }
+                Defragmented::Bypass => return None,
+            }
+        }
+        datagram
+    }
+
+    #[test]
+    fn not_fragment() {
+        let mut reassembler = FragmentReassembler::new(&config());
+        let packet = ipv4(0, &udp(100));
+        assert!(matches!(
+            reassembler.inject(&packet, Duration::ZERO),
+            Defragmented::Bypass
+        ));
+        let packet = ipv6(IpProtocol::Udp, &udp(100));
+        assert!(matches!(
+            reassembler.inject(&packet, Duration::ZERO),
+            Defragmented::Bypass
+        ));
+        assert_eq!(reassembler.counter.fragments.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn ipv4_reassembly() {
+        let mut reassembler = FragmentReassembler::new(&config());
+        let data = udp(UDP_PAYLOAD_LEN);
+        let packet = ipv4(0, &data);
+        let mut fragments = ipv4_fragments(&data, 1480);
+        assert_eq!(fragments.len(), 3);
+        // 乱序到达
+        // out of order
+        fragments.swap(0, 2);
+
+        let datagram = reassemble(&mut reassembler, &fragments, Duration::ZERO).unwrap();
+        assert_eq!(datagram.packet, packet);
+        assert_eq!(datagram.fragments, 3);
+        assert_eq!(
+            datagram.bytes,
+            fragments.iter().map(|f| f.len()).sum::<usize>()
+        );
+        assert_eq!(reassembler.memory, 0);
+        assert!(reassembler.queues.is_empty() && reassembler.time_set.is_empty());
+
+        let mut meta_packet = MetaPacket::empty();
+        meta_packet
+            .update(
+                &datagram.packet,
+                false,
+                false,
+                Duration::ZERO,
+                datagram.packet.len(),
+            )
+            .unwrap();
+        assert_eq!(meta_packet.lookup_key.src_port, 53);
+        assert_eq!(meta_packet.lookup_key.dst_port, 33333);
+        assert_eq!(meta_packet.l4_payload_len(), UDP_PAYLOAD_LEN);
+        assert_eq!(meta_packet.get_l4_payload().unwrap(), &data[8..]);
+    }
+
+    #[test]
+    fn ipv6_reassembly() {
+        let mut reassembler = FragmentReassembler::new(&config());
+        let data = udp(UDP_PAYLOAD_LEN);
+        let packet = ipv6(IpProtocol::Udp, &data);
+        let mut fragments = ipv6_fragments(&data, 1232);
+        fragments.swap(1, 2);
+
+        let datagram = reassemble(&mut reassembler, &fragments, Duration::ZERO).unwrap();
+        assert_eq!(datagram.packet, packet);
+        assert_eq!(datagram.fragments, 3);
+
+        let mut meta_packet = MetaPacket::empty();
+        meta_packet
+            .update(
+                &datagram.packet,
+                false,
+                false,
+                Duration::ZERO,
+                datagram.packet.len(),
+            )
+            .unwrap();
+        assert_eq!(meta_packet.lookup_key.proto, IpProtocol::Udp);
+        assert_eq!(meta_packet.lookup_key.src_port, 53);
+        assert_eq!(meta_packet.get_l4_payload().unwrap(), &data[8..]);
+    }
+
+    #[test]
+    fn duplicate_and_overlap() {
+        let mut reassembler = FragmentReassembler::new(&config());
+        let data = udp(UDP_PAYLOAD_LEN);
+        let fragments = ipv4_fragments(&data, 1480);
+
+        assert!(matches!(
+            reassembler.inject(&fragments[0], Duration::ZERO),
+            Defragmented::Pending
+        ));
+        // 重传的分片不缓存，只计入包数
+        // retransmitted fragments are not buffered, only counted in packets
+        assert!(matches!(
+            reassembler.inject(&fragments[0], Duration::ZERO),
+            Defragmented::Pending
+        ));
+        let queue = reassembler.queues.values().next().unwrap();
+        assert_eq!(queue.packet_count, 2);
+        assert_eq!(queue.fragments.len(), 1);
+
+        // 重叠的分片导致整个报文被丢弃
+        // overlapping fragments drop the whole datagram
+        let overlapped = ipv4(
+            (1480 / FRAG_OFFSET_UNIT - 1) as u16 | IPV4_FRAG_MORE_FRAGMENT,
+            &data[1472..2960],
+        );
+        assert!(matches!(
+            reassembler.inject(&overlapped, Duration::ZERO),
+            Defragmented::Bypass
+        ));
+        assert!(reassembler.queues.is_empty());
+        assert_eq!(reassembler.memory, 0);
+        assert_eq!(reassembler.counter.invalid.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut reassembler = FragmentReassembler::new(&config());
+        let data = udp(UDP_PAYLOAD_LEN);
+        let fragments = ipv4_fragments(&data, 1480);
+        assert!(matches!(
+            reassembler.inject(&fragments[0][..100], Duration::ZERO),
+            Defragmented::Bypass
+        ));
+        assert!(reassembler.queues.is_empty());
+        assert_eq!(reassembler.counter.invalid.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn timeout() {
+        let mut reassembler = FragmentReassembler::new(&config());
+        let data = udp(UDP_PAYLOAD_LEN);
+        let fragments = ipv4_fragments(&data, 1480);
+
+        assert!(matches!(
+            reassembler.inject(&fragments[0], Duration::from_secs(1)),
+            Defragmented::Pending
+        ));
+        // 超时后首个分片已被丢弃，剩余分片无法完成重组
+        // the first fragment is dropped after timeout, the rest can not complete the datagram
+        assert!(matches!(
+            reassembler.inject(&fragments[1], Duration::from_secs(7)),
+            Defragmented::Pending
+        ));
+        assert!(matches!(
+            reassembler.inject(&fragments[2], Duration::from_secs(7)),
+            Defragmented::Pending
+        ));
+        assert_eq!(reassembler.counter.timeout.load(Ordering::Relaxed), 1);
+
+        assert!(matches!(
+            reassembler.inject(&fragments[0], Duration::from_secs(8)),
+            Defragmented::Complete(_)
+        ));
+        assert_eq!(reassembler.memory, 0);
+    }
+
+    #[test]
+    fn memory_limit() {
+        let mut reassembler = FragmentReassembler::new(&config());
+        let data = udp(UDP_PAYLOAD_LEN);
+        let fragments = ipv4_fragments(&data, 1480);
+
+        // 每个报文缓存两个分片，总量超过1M时淘汰最早的报文
+        // two fragments are buffered for each datagram, the oldest ones are evicted
+        // when 1MB is exceeded
+        let count = 1024 * 1024 / (1480 * 2) + 10;
+        for i in 0..count {
+            let mut first = fragments[0].clone();
+            let mut second = fragments[1].clone();
+            for f in [&mut first, &mut second] {
+                f[ETH_HEADER_SIZE + ipv4::ID_OFFSET..ETH_HEADER_SIZE + ipv4::ID_OFFSET + 2]
+                    .copy_from_slice(&(i as u16).to_be_bytes());
+            }
+            let timestamp = Duration::from_millis(i as u64);
+            assert!(matches!(
+                reassembler.inject(&first, timestamp),
+                Defragmented::Pending
+            ));
+            assert!(matches!(
+                reassembler.inject(&second, timestamp),
+                Defragmented::Pending
+            ));
+            assert!(reassembler.memory <= reassembler.max_memory);
+        }
+        assert!(reassembler.counter.evicted.load(Ordering::Relaxed) > 0);
+        assert_eq!(
+            reassembler.memory,
+            reassembler.queues.values().map(|q| q.memory).sum::<usize>()
+        );
+        assert_eq!(reassembler.queues.len(), reassembler.time_set.len());
+    }
+
+    #[test]
+    fn dropped_fragments() {
+        let mut reassembler = FragmentReassembler::new(&config());
+        let data = udp(UDP_PAYLOAD_LEN);
+        let fragments = ipv4_fragments(&data, 1480);
+
+        let timestamp = Duration::from_secs(1);
+        for _ in 0..2 {
+            assert!(matches!(
+                reassembler.inject(&fragments[1], timestamp),
+                Defragmented::Pending
+            ));
+            let mut meta_packet = MetaPacket::empty();
+            meta_packet
+                .update(&fragments[1], false, false, timestamp, fragments[1].len())
+                .unwrap();
+            meta_packet.tap_port = TapPort(1);
+            reassembler.record(&meta_packet);
+        }
+        // 只记录创建队列的分片的上下文
+        // only the context of the fragment creating the queue is recorded
+        assert_eq!(
+            reassembler.queues.values().next().unwrap().contexts.len(),
+            1
+        );
+        assert_eq!(reassembler.drain_dropped().count(), 0);
+
+        // 超时后缓存的分片和重复的分片一起按原始报文统计
+        // buffered fragments and duplicates are counted as is after timeout
+        let mut other = fragments[0].clone();
+        other[ETH_HEADER_SIZE + ipv4::ID_OFFSET..ETH_HEADER_SIZE + ipv4::ID_OFFSET + 2]
+            .copy_from_slice(&(ID + 1).to_be_bytes());
+        assert!(matches!(
+            reassembler.inject(&other, Duration::from_secs(7)),
+            Defragmented::Pending
+        ));
+        let dropped = reassembler.drain_dropped().collect::<Vec<_>>();
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].fragments, 2);
+        assert_eq!(dropped[0].bytes, fragments[1].len() * 2);
+        let meta_packet = dropped[0].meta_packet().unwrap();
+        assert_eq!(meta_packet.ip_fragments, 2);
+        assert_eq!(meta_packet.packet_len, fragments[1].len() * 2);
+        assert_eq!(meta_packet.tap_port, TapPort(1));
+        assert_eq!(meta_packet.lookup_key.timestamp, timestamp);
+        assert_eq!(reassembler.queues.len(), 1);
+
+        // 冲突的分片丢弃已缓存的分片
+        // conflicting fragments drop the buffered ones
+        let timestamp = Duration::from_secs(8);
+        assert!(matches!(
+            reassembler.inject(&fragments[0], timestamp),
+            Defragmented::Pending
+        ));
+        let mut meta_packet = MetaPacket::empty();
+        meta_packet
+            .update(&fragments[0], false, false, timestamp, fragments[0].len())
+            .unwrap();
+        reassembler.record(&meta_packet);
+        let overlapped = ipv4(IPV4_FRAG_MORE_FRAGMENT, &data[..1488]);
+        assert!(matches!(
+            reassembler.inject(&overlapped, timestamp),
+            Defragmented::Bypass
+        ));
+        let dropped = reassembler.drain_dropped().collect::<Vec<_>>();
+        assert_eq!(dropped.len(), 1);
+        assert_eq!(dropped[0].fragments, 1);
+        assert_eq!(dropped[0].bytes, fragments[0].len());
+    }
+
+    #[test]
+    fn disabled() {
+        let mut reassembler = FragmentReassembler::new(&IpFragmentConfig {
+            enabled: false,
+            ..config()
+        });
+        let fragments = ipv4_fragments(&udp(UDP_PAYLOAD_LEN), 1480);
+        assert!(matches!(
+            reassembler.inject(&fragments[0], Duration::ZERO),
+            Defragmented::Bypass
+        ));
+    }
+}
@@ -226,6 +226,7 @@ impl LocalModeDispatcher {
                 meta_packet.tunnel = Some(&base.tunnel_info);
                 if base.tunnel_info.tunnel_type == TunnelType::TencentGre
                     || base.tunnel_info.tunnel_type == TunnelType::Vxlan
+                    || base.tunnel_info.tunnel_type == TunnelType::Geneve
                 {
                     // 腾讯TCE、青云私有云需要通过TunnelID查询云平台信息
                     // 这里只需要考虑单层隧道封装的情况
@@ -255,7 +256,17 @@ impl LocalModeDispatcher {
                 base.id as u8,
                 base.npb_dedup_enabled.load(Ordering::Relaxed),
             );
-            flow_map.inject_meta_packet(&mut meta_packet);
+            let defragmented = base
+                .fragment_reassembler
+                .inject(overlay_packet, meta_packet.lookup_key.timestamp);
+            BaseDispatcher::inject_meta_packet(
+                &mut flow_map,
+                &mut base.policy_getter,
+                base.id,
+                &mut base.fragment_reassembler,
+                &defragmented,
+                &mut meta_packet,
+            );
             let mini_packet = MiniPacket::new(overlay_packet, &meta_packet);
             for h in pipeline.handlers.iter_mut() {
                 h.handle(&mini_packet);
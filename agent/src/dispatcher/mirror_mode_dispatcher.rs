@@ -44,11 +44,13 @@ use crate::{
     dispatcher::{
         base_dispatcher::{BaseDispatcher, BaseDispatcherListener},
         error::{Error, Result},
+        fragment::{Defragmented, FragmentReassembler},
         PacketCounter,
     },
     flow_generator::FlowMap,
     handler::PacketHandlerBuilder,
     handler::{MiniPacket, PacketHandler},
+    policy::PolicyGetter,
     proto::{common::TridentType, trident::IfMacSource},
     rpc::get_timestamp,
     utils::{
@@ -304,6 +306,9 @@ impl MirrorModeDispatcher {
         handler_builder: &Arc<Mutex<Vec<PacketHandlerBuilder>>>,
         tunnel_info: &TunnelInfo,
         flow_map: &mut FlowMap,
+        policy_getter: &mut PolicyGetter,
+        fragment_reassembler: &mut FragmentReassembler,
+        defragmented: &Defragmented,
         counter: &Arc<PacketCounter>,
         trident_type: TridentType,
         mac: u32,
@@ -344,7 +349,14 @@ impl MirrorModeDispatcher {
             npb_dedup,
         );
         // flowProcesser
-        flow_map.inject_meta_packet(&mut meta_packet);
+        BaseDispatcher::inject_meta_packet(
+            flow_map,
+            policy_getter,
+            id,
+            fragment_reassembler,
+            defragmented,
+            &mut meta_packet,
+        );
         let mini_packet = MiniPacket::new(overlay_packet, &meta_packet);
         for i in pipeline.handlers.iter_mut() {
             i.handle(&mini_packet);
@@ -444,6 +456,13 @@ impl MirrorModeDispatcher {
                 self.base.tunnel_info,
             );
             let trident_type = self.trident_type.lock().unwrap().clone();
+            // 双向都是本地虚拟机时同一个报文会处理两次，分片只需重组一次
+            // packets between two local VMs are handled twice, but fragments
+            // are reassembled only once
+            let defragmented = self
+                .base
+                .fragment_reassembler
+                .inject(overlay_packet, timestamp);
             if !src_local && !dst_local {
                 let _ = Self::handler(
                     self.base.id,
@@ -458,6 +477,9 @@ impl MirrorModeDispatcher {
                     &self.base.handler_builder,
                     &self.base.tunnel_info,
                     &mut flow_map,
+                    &mut self.base.policy_getter,
+                    &mut self.base.fragment_reassembler,
+                    &defragmented,
                     &self.base.counter,
                     trident_type,
                     self.mac,
@@ -480,6 +502,9 @@ impl MirrorModeDispatcher {
                     &self.base.handler_builder,
                     &self.base.tunnel_info,
                     &mut flow_map,
+                    &mut self.base.policy_getter,
+                    &mut self.base.fragment_reassembler,
+                    &defragmented,
                     &self.base.counter,
                     trident_type,
                     self.mac,
@@ -500,6 +525,9 @@ impl MirrorModeDispatcher {
                     &self.base.handler_builder,
                     &self.base.tunnel_info,
                     &mut flow_map,
+                    &mut self.base.policy_getter,
+                    &mut self.base.fragment_reassembler,
+                    &defragmented,
                     &self.base.counter,
                     trident_type,
                     self.mac,
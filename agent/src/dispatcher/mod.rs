@@ -18,6 +18,7 @@ mod error;
 pub(crate) mod recv_engine;
 
 mod base_dispatcher;
+mod fragment;
 
 mod analyzer_mode_dispatcher;
 mod local_mode_dispatcher;
@@ -51,14 +52,18 @@ use windows_recv_engine::WinPacket;
 use analyzer_mode_dispatcher::{AnalyzerModeDispatcher, AnalyzerModeDispatcherListener}; // Enterprise Edition Feature: analyzer_mode
 use base_dispatcher::{BaseDispatcher, TapTypeHandler};
 use error::{Error, Result};
+use fragment::FragmentReassembler;
 use local_mode_dispatcher::{LocalModeDispatcher, LocalModeDispatcherListener};
 use mirror_mode_dispatcher::{MirrorModeDispatcher, MirrorModeDispatcherListener};
-pub use recv_engine::RecvEngine;
 #[cfg(target_os = "linux")]
 pub use recv_engine::{
     af_packet::{self, bpf::*, BpfSyntax, OptTpacketVersion, RawInstruction, Tpacket},
     DEFAULT_BLOCK_SIZE, FRAME_SIZE_MAX, FRAME_SIZE_MIN, POLL_TIMEOUT,
 };
+pub use recv_engine::{
+    pcap_file::{PcapFile, PcapFileStatus},
+    RecvEngine,
+};
 
 #[cfg(target_os = "linux")]
 use crate::platform::GenericPoller;
@@ -67,7 +72,7 @@ use crate::{
     common::{enums::TapType, TaggedFlow, TapTyper},
     config::{
         handler::{FlowAccess, LogParserAccess},
-        DispatcherConfig,
+        DispatcherConfig, IpFragmentConfig,
     },
     exception::ExceptionHandler,
     flow_generator::MetaAppProto,
@@ -429,6 +434,7 @@ pub struct Options {
     pub vxlan_port: u16,
     pub controller_port: u16,
     pub controller_tls_port: u16,
+    pub ip_fragment: IpFragmentConfig,
 }
 
 struct Pipeline {
@@ -556,6 +562,7 @@ pub struct DispatcherBuilder {
     pcap_interfaces: Option<Vec<Link>>,
     netns: Option<NsFile>,
     trident_type: Option<TridentType>,
+    pcap_file: Option<PcapFile>,
 }
 
 impl DispatcherBuilder {
@@ -682,6 +689,13 @@ impl DispatcherBuilder {
         self
     }
 
+    // 指定后从文件读取报文，用于离线回放
+    // packets are read from the file instead of interfaces if specified, used by offline replay
+    pub fn pcap_file(mut self, v: PcapFile) -> Self {
+        self.pcap_file = Some(v);
+        self
+    }
+
     pub fn build(mut self) -> Result<Dispatcher> {
         let netns = self.netns.unwrap_or_default();
         #[cfg(target_os = "linux")]
@@ -697,10 +711,13 @@ impl DispatcherBuilder {
             .ok_or(Error::ConfigIncomplete("no options".into()))?;
         let tap_mode = options.tap_mode;
         let snap_len = options.snap_len;
-        #[cfg(target_os = "windows")]
-        let engine = Self::get_engine(&self.pcap_interfaces, tap_mode, &options)?;
-        #[cfg(target_os = "linux")]
-        let engine = Self::get_engine(&mut self.src_interface, tap_mode, &options)?;
+        let engine = match self.pcap_file.take() {
+            Some(f) => RecvEngine::PcapFile(f),
+            #[cfg(target_os = "windows")]
+            None => Self::get_engine(&self.pcap_interfaces, tap_mode, &options)?,
+            #[cfg(target_os = "linux")]
+            None => Self::get_engine(&mut self.src_interface, tap_mode, &options)?,
+        };
 
         let kernel_counter = engine.get_counter_handle();
         let id = self.id.ok_or(Error::ConfigIncomplete("no id".into()))?;
@@ -716,6 +733,15 @@ impl DispatcherBuilder {
             .take()
             .ok_or(Error::ConfigIncomplete("no platform poller".into()))?;
 
+        let fragment_reassembler = FragmentReassembler::new(&options.ip_fragment);
+        collector.register_countable(
+            "ip-fragment",
+            stats::Countable::Ref(
+                Arc::downgrade(fragment_reassembler.counter()) as Weak<dyn stats::RefCountable>
+            ),
+            vec![stats::StatsOption::Tag("id", id.to_string())],
+        );
+
         let src_interface = self.src_interface.unwrap_or("".to_string());
         let base = BaseDispatcher {
             engine,
@@ -742,6 +768,7 @@ impl DispatcherBuilder {
             tap_interfaces: Default::default(),
             tunnel_type_bitmap: Default::default(),
             tunnel_info: Default::default(),
+            fragment_reassembler,
 
             tap_type_handler: TapTypeHandler {
                 tap_typer: self
@@ -16,6 +16,7 @@
 
 pub mod af_packet;
 pub(crate) mod bpf;
+pub mod pcap_file;
 
 #[cfg(target_os = "windows")]
 use std::ffi::CStr;
@@ -24,6 +25,7 @@ use std::time::Duration;
 
 #[cfg(target_os = "linux")]
 use af_packet::{options::Options, tpacket::Tpacket};
+use pcap_file::PcapFile;
 pub use public::error::{Error, Result};
 use public::packet;
 
@@ -43,6 +45,7 @@ pub enum RecvEngine {
     Dpdk(),
     #[cfg(target_os = "windows")]
     WinPcap(Option<WinPacket>),
+    PcapFile(PcapFile),
 }
 
 impl RecvEngine {
@@ -55,6 +58,7 @@ impl RecvEngine {
             Self::Dpdk() => todo!(),
             #[cfg(target_os = "windows")]
             Self::WinPcap(_) => Ok(()),
+            Self::PcapFile(_) => Ok(()),
         }
     }
 
@@ -82,6 +86,7 @@ impl RecvEngine {
                 .as_mut()
                 .ok_or(Error::WinpcapError(Self::WIN_PCAP_NONE.to_string()))
                 .and_then(|e| e.read()),
+            Self::PcapFile(f) => f.read(),
         }
     }
 
@@ -90,6 +95,7 @@ impl RecvEngine {
         match self {
             Self::AfPacket(e) => e.set_bpf(s).map_err(|e| e.into()),
             Self::Dpdk() => todo!(),
+            Self::PcapFile(_) => Ok(()),
         }
     }
 
@@ -100,6 +106,7 @@ impl RecvEngine {
                 .as_mut()
                 .ok_or(Error::WinpcapError(Self::WIN_PCAP_NONE.to_string()))
                 .and_then(|e| e.set_bpf(s.to_str().unwrap())),
+            Self::PcapFile(_) => Ok(()),
             _ => todo!(),
         }
     }
@@ -114,6 +121,7 @@ impl RecvEngine {
                 Some(w) => w.get_counter_handle(),
                 None => Arc::new(WinPcapCounter::default()),
             },
+            Self::PcapFile(f) => f.status(),
         }
     }
 }
@@ -0,0 +1,304 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// 离线回放使用的收包引擎，从 pcap/pcapng 文件读取报文交给 dispatcher 处理
+//
+// recv engine for offline replay, packets read from a pcap/pcapng file are handed to dispatcher
+
+use std::{
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::warn;
+use public::{
+    error::{Error, Result},
+    packet,
+};
+
+use super::POLL_TIMEOUT;
+use crate::{
+    pcap::{Reader, Record},
+    utils::stats,
+};
+
+// 报文之间及文件读完后，每秒推动一次 flow_map，与实时采集中 dispatcher 的定时 flush 一致
+// flow_map is ticked every second between packets and after EOF, like the dispatcher does live
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Default)]
+pub struct PcapFileStatus {
+    packets: AtomicU64,
+    skipped_packets: AtomicU64,
+    finished: AtomicBool,
+    error: Mutex<Option<String>>,
+}
+
+impl PcapFileStatus {
+    pub fn packets(&self) -> u64 {
+        self.packets.load(Ordering::Relaxed)
+    }
+
+    pub fn skipped_packets(&self) -> u64 {
+        self.skipped_packets.load(Ordering::Relaxed)
+    }
+
+    // 文件读完且时间已推动到所有流超时输出
+    // EOF is reached and time is moved on until all flows are timed out
+    pub fn is_finished(&self) -> bool {
+        self.finished.load(Ordering::Relaxed)
+    }
+
+    pub fn error(&self) -> Option<String> {
+        self.error.lock().unwrap().clone()
+    }
+}
+
+impl stats::RefCountable for PcapFileStatus {
+    fn get_counters(&self) -> Vec<stats::Counter> {
+        vec![
+            (
+                "file_packets",
+                stats::CounterType::Gauged,
+                stats::CounterValue::Unsigned(self.packets()),
+            ),
+            (
+                "file_skipped_packets",
+                stats::CounterType::Gauged,
+                stats::CounterValue::Unsigned(self.skipped_packets()),
+            ),
+        ]
+    }
+}
+
+// 根据 speed 控制回放节奏，虚拟时间经过 d 时实际时间经过 d / speed
+// paces the replay, virtual time d takes d / speed of wall clock time
+struct Pacer {
+    speed: u32,
+    first_packet_time: Option<Duration>,
+    start: Instant,
+}
+
+impl Pacer {
+    fn new(speed: u32) -> Self {
+        Self {
+            speed,
+            first_packet_time: None,
+            start: Instant::now(),
+        }
+    }
+
+    fn wait_until(&mut self, timestamp: Duration) {
+        if self.speed == 0 {
+            return;
+        }
+        let first = *self.first_packet_time.get_or_insert_with(|| {
+            self.start = Instant::now();
+            timestamp
+        });
+        let deadline = self.start + timestamp.saturating_sub(first) / self.speed;
+        let now = Instant::now();
+        if deadline > now {
+            thread::sleep(deadline - now);
+        }
+    }
+}
+
+pub struct PcapFile {
+    reader: Option<Reader<BufReader<File>>>,
+    pending: Option<Record>,
+    #[cfg(target_os = "linux")]
+    buffer: Vec<u8>,
+
+    pacer: Pacer,
+    next_tick: Option<Duration>,
+    last_timestamp: Duration,
+    drain_time: Duration,
+
+    ntp_diff: Arc<AtomicI64>,
+    status: Arc<PcapFileStatus>,
+}
+
+impl PcapFile {
+    // drain_time 为文件读完后继续推动的时间，应足够所有流超时输出
+    // drain_time is how long time is moved on after EOF, it should be enough for all flows to time out
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        speed: u32,
+        drain_time: Duration,
+        ntp_diff: Arc<AtomicI64>,
+    ) -> io::Result<Self> {
+        Ok(Self {
+            reader: Some(Reader::open(path)?),
+            pending: None,
+            #[cfg(target_os = "linux")]
+            buffer: vec![],
+            pacer: Pacer::new(speed),
+            next_tick: None,
+            last_timestamp: Duration::ZERO,
+            drain_time,
+            ntp_diff,
+            status: Default::default(),
+        })
+    }
+
+    pub fn status(&self) -> Arc<PcapFileStatus> {
+        self.status.clone()
+    }
+
+    // 通过 ntp_diff 将各组件看到的当前时间调整为报文时间，返回设置的 ntp_diff
+    // ntp_diff makes all components see the packet time as the current time, the diff set is returned
+    fn set_virtual_time(&self, timestamp: Duration) -> i64 {
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        let diff = timestamp.as_nanos() as i64 - now.as_nanos() as i64;
+        self.ntp_diff.store(diff, Ordering::Relaxed);
+        diff
+    }
+
+    fn next_record(&mut self) -> Option<Record> {
+        if self.pending.is_none() {
+            let reader = self.reader.as_mut()?;
+            match reader.next_packet() {
+                Ok(Some(record)) => self.pending = Some(record),
+                Ok(None) => (),
+                Err(e) => {
+                    warn!("read pcap file failed: {}", e);
+                    self.status.error.lock().unwrap().replace(e.to_string());
+                }
+            }
+            self.status
+                .skipped_packets
+                .store(reader.skipped(), Ordering::Relaxed);
+            if self.pending.is_none() {
+                self.reader = None;
+            }
+        }
+        self.pending.take()
+    }
+
+    // 每次调用返回一个报文或推动一个 tick，tick 以 Timeout 返回，由 dispatcher 调用 flow_map 的定时 flush
+    // every call returns a packet or moves on a tick, ticks are returned as Timeout so that
+    // dispatcher flushes flow_map as it does on timeouts of live capture
+    pub fn read(&mut self) -> Result<packet::Packet> {
+        if self.status.is_finished() {
+            thread::sleep(POLL_TIMEOUT);
+            return Err(Error::Timeout);
+        }
+        let record = match self.next_record() {
+            Some(record) => record,
+            None => {
+                match self.next_tick {
+                    Some(tick) if tick <= self.last_timestamp + self.drain_time => {
+                        self.set_virtual_time(tick);
+                        self.next_tick = Some(tick + TICK_INTERVAL);
+                    }
+                    _ => self.status.finished.store(true, Ordering::Relaxed),
+                }
+                return Err(Error::Timeout);
+            }
+        };
+        match self.next_tick {
+            Some(tick) if tick < record.timestamp => {
+                self.pacer.wait_until(tick);
+                self.set_virtual_time(tick);
+                self.next_tick = Some(tick + TICK_INTERVAL);
+                self.pending = Some(record);
+                return Err(Error::Timeout);
+            }
+            Some(_) => (),
+            None => self.next_tick = Some(record.timestamp + TICK_INTERVAL),
+        }
+
+        self.pacer.wait_until(record.timestamp);
+        // dispatcher 会在报文时间上加 ntp_diff，这里返回减去 ntp_diff 的时间
+        // dispatcher adds ntp_diff to packet time, so the time with ntp_diff subtracted is returned
+        let diff = self.set_virtual_time(record.timestamp);
+        let timestamp = Duration::from_nanos((record.timestamp.as_nanos() as i64 - diff) as u64);
+        self.last_timestamp = self.last_timestamp.max(record.timestamp);
+        self.status.packets.fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(target_os = "linux")]
+        let data = {
+            self.buffer = record.data;
+            &mut self.buffer[..]
+        };
+        #[cfg(target_os = "windows")]
+        let data = record.data;
+        Ok(packet::Packet {
+            timestamp,
+            if_index: 0,
+            capture_length: record.original_length as isize,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::rpc::get_timestamp;
+
+    #[test]
+    fn read_and_drain() {
+        let ntp_diff = Arc::new(AtomicI64::new(0));
+        let mut file = PcapFile::open(
+            "resources/test/flow_generator/http/httpv1.pcap",
+            0,
+            Duration::from_secs(3),
+            ntp_diff.clone(),
+        )
+        .unwrap();
+        let status = file.status();
+
+        let mut last_packet_time = Duration::ZERO;
+        let mut ticks = vec![];
+        while !status.is_finished() {
+            match file.read() {
+                Ok(packet) => {
+                    let diff = ntp_diff.load(Ordering::Relaxed);
+                    let timestamp =
+                        Duration::from_nanos((packet.timestamp.as_nanos() as i64 + diff) as u64);
+                    assert!(timestamp >= last_packet_time);
+                    last_packet_time = timestamp;
+                    // 当前时间即报文时间
+                    // current time is the packet time
+                    assert!(get_timestamp(diff) >= timestamp);
+                    assert!(get_timestamp(diff) < timestamp + Duration::from_secs(1));
+                }
+                Err(Error::Timeout) => {
+                    ticks.push(get_timestamp(ntp_diff.load(Ordering::Relaxed)));
+                }
+                Err(e) => panic!("{}", e),
+            }
+        }
+
+        assert!(status.packets() > 0);
+        assert_eq!(status.skipped_packets(), 0);
+        assert!(status.error().is_none());
+        assert!(ticks.len() >= 3);
+        assert!(*ticks.last().unwrap() > last_packet_time + Duration::from_secs(2));
+    }
+}
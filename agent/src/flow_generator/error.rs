@@ -35,18 +35,30 @@ pub enum Error {
     ZeroPayloadLen,
     #[error("invalid ip protocol")]
     InvalidIpProtocol,
+    #[error("amqp log parse failed")]
+    AmqpLogParseFailed,
+    #[error("amqp perf parse failed")]
+    AmqpPerfParseFailed,
     #[error("dubbo header parse failed")]
     DubboHeaderParseFailed,
+    #[error("grpc header parse failed")]
+    GrpcHeaderParseFailed,
     #[error("http header parse failed")]
     HttpHeaderParseFailed,
     #[error("kafka log parse failed")]
     KafkaLogParseFailed,
     #[error("kafka perf parse failed")]
     KafkaPerfParseFailed,
+    #[error("mongodb log parse failed")]
+    MongoDBLogParseFailed,
+    #[error("mongodb perf parse failed")]
+    MongoDBPerfParseFailed,
     #[error("mqtt log parse failed")]
     MqttLogParseFailed,
     #[error("mqtt perf parse failed")]
     MqttPerfParseFailed,
+    #[error("postgresql perf parse failed")]
+    PostgresqlPerfParseFailed,
     #[error("redis log parse failed")]
     RedisLogParseFailed,
     #[error("redis perf parse failed")]
@@ -55,6 +67,8 @@ pub enum Error {
     MysqlLogParseFailed,
     #[error("mysql perf parse failed")]
     MysqlPerfParseFailed,
+    #[error("wasm plugin parse failed: {0}")]
+    WasmPluginParseFailed(String),
     #[error("{0}")]
     DNSLogParseFailed(String),
     #[error("{0}")]
@@ -56,7 +56,7 @@ use crate::{
             TunnelField,
         },
         l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
-        l7_protocol_log::{get_parser, L7ProtocolParserInterface},
+        l7_protocol_log::{get_parser, L7ProtocolBitmap, L7ProtocolParserInterface},
         lookup_key::LookupKey,
         meta_packet::{MetaPacket, MetaPacketTcpHeader},
         tagged_flow::TaggedFlow,
@@ -64,7 +64,7 @@ use crate::{
         FeatureFlags,
     },
     config::{
-        handler::{L7LogDynamicConfig, LogParserAccess, LogParserConfig},
+        handler::{L7LogDynamicConfig, LogParserAccess, LogParserConfig, WasmPluginConfig},
         FlowAccess, FlowConfig, ModuleConfig, RuntimeConfig,
     },
     policy::{Policy, PolicyGetter},
@@ -432,9 +432,7 @@ impl FlowMap {
 
     fn generate_flow_id(&mut self, timestamp: Duration, thread_id: u32) -> u64 {
         self.total_flow += 1;
-        (timestamp.as_nanos() as u64 >> 30 & TIMER_FLOW_ID_MASK) << 32
-            | thread_id as u64 & THREAD_FLOW_ID_MASK << 24
-            | self.total_flow as u64 & COUNTER_FLOW_ID_MASK
+        generate_flow_id(timestamp, thread_id, self.total_flow as u64)
     }
 
     fn update_tcp_flow(&mut self, meta_packet: &mut MetaPacket, node: &mut FlowNode) -> bool {
@@ -591,6 +589,7 @@ impl FlowMap {
                     tier: tunnel.tier,
                     tunnel_type: tunnel.tunnel_type,
                     is_ipv6: tunnel.is_ipv6,
+                    tx_layers: tunnel.layers,
                     ..Default::default()
                 }
             } else {
@@ -609,8 +608,8 @@ impl FlowMap {
             // 统计量
             flow_metrics_peers: [
                 FlowMetricsPeer {
-                    total_packet_count: 1,
-                    packet_count: 1,
+                    total_packet_count: meta_packet.ip_fragments.max(1) as u64,
+                    packet_count: meta_packet.ip_fragments.max(1) as u64,
                     total_byte_count: meta_packet.packet_len as u64,
                     byte_count: meta_packet.packet_len as u64,
                     l3_byte_count: meta_packet.l3_payload_len() as u64,
@@ -747,8 +746,11 @@ impl FlowMap {
 
         let flow = &mut node.tagged_flow.flow;
         let flow_metrics_peer = &mut flow.flow_metrics_peers[meta_packet.direction as usize];
-        flow_metrics_peer.packet_count += 1;
-        flow_metrics_peer.total_packet_count += 1;
+        // 重组后的报文按原始分片数统计
+        // reassembled packets are counted by their original fragments
+        let packet_count = meta_packet.ip_fragments.max(1) as u64;
+        flow_metrics_peer.packet_count += packet_count;
+        flow_metrics_peer.total_packet_count += packet_count;
         flow_metrics_peer.byte_count += meta_packet.packet_len as u64;
         flow_metrics_peer.l3_byte_count += meta_packet.l3_payload_len() as u64;
         flow_metrics_peer.l4_byte_count += meta_packet.l4_payload_len() as u64;
@@ -769,6 +771,7 @@ impl FlowMap {
                     flow.tunnel.tx_mac0 = tunnel.mac_src;
                     flow.tunnel.tx_mac1 = tunnel.mac_dst;
                     flow.tunnel.tx_id = tunnel.id;
+                    flow.tunnel.tx_layers = tunnel.layers;
                 }
                 PacketDirection::ServerToClient => {
                     flow.tunnel.rx_ip0 = tunnel.src;
@@ -776,6 +779,7 @@ impl FlowMap {
                     flow.tunnel.rx_mac0 = tunnel.mac_src;
                     flow.tunnel.rx_mac1 = tunnel.mac_dst;
                     flow.tunnel.rx_id = tunnel.id;
+                    flow.tunnel.rx_layers = tunnel.layers;
                 }
             }
             flow.tunnel.tier = tunnel.tier;
@@ -1244,6 +1248,16 @@ impl FlowMap {
     }
 }
 
+// flowID由时间戳(秒)、线程号和流计数组成:
+// +--------------------------+------------+----------------------+
+// |       timestamp(32)      | thread(8)  |      counter(24)     |
+// +--------------------------+------------+----------------------+
+pub fn generate_flow_id(timestamp: Duration, thread_id: u32, total_flow: u64) -> u64 {
+    (timestamp.as_nanos() as u64 >> 30 & TIMER_FLOW_ID_MASK) << 32
+        | (thread_id as u64 & THREAD_FLOW_ID_MASK) << 24
+        | total_flow & COUNTER_FLOW_ID_MASK
+}
+
 pub fn _reverse_meta_packet(packet: &mut MetaPacket) {
     let lookup_key = &mut packet.lookup_key;
     mem::swap(&mut lookup_key.src_ip, &mut lookup_key.dst_ip);
@@ -1282,6 +1296,11 @@ pub fn _new_flow_map_and_receiver(
             l7_log_collect_nps_threshold: 0,
             l7_log_session_aggr_timeout: Duration::new(0, 0),
             l7_log_dynamic: L7LogDynamicConfig::default(),
+            wasm_plugin: WasmPluginConfig::default(),
+            tcp_reassembly_bitmap: L7ProtocolBitmap::from(&vec![]),
+            tcp_reassembly_max_buffer_size: 0,
+            sql_normalization: Default::default(),
+            redis_log: Default::default(),
         },
         ..Default::default()
     };
@@ -1825,6 +1844,14 @@ mod tests {
         assert_eq!(perf_stats.srt_max, 12);
     }
 
+    #[test]
+    fn flow_id_fields() {
+        let flow_id = generate_flow_id(Duration::from_nanos(5 << 30), 3, (1 << 24) + 7);
+        assert_eq!(flow_id >> 32, 5);
+        assert_eq!(flow_id >> 24 & THREAD_FLOW_ID_MASK, 3);
+        assert_eq!(flow_id & COUNTER_FLOW_ID_MASK, 7);
+    }
+
     #[test]
     fn tcp_syn_ack_zerowin() {
         let (mut flow_map, output_queue_receiver) =
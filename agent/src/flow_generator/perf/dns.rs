@@ -90,15 +90,19 @@ impl L7FlowPerf for DnsPerfData {
                     return Err(Error::DNSPerfParseFailed("dns payload length error"));
                 }
 
-                let size = read_u16_be(payload) as usize;
-                if size < payload[DNS_TCP_PAYLOAD_OFFSET..].len() {
-                    return Err(Error::DNSPerfParseFailed("dns payload length error"));
+                // 一个报文中可能包含多个带 2 字节长度前缀的 DNS 消息
+                let mut offset = 0;
+                while offset + DNS_TCP_PAYLOAD_OFFSET < payload.len() {
+                    let size = read_u16_be(&payload[offset..]) as usize;
+                    let start = offset + DNS_TCP_PAYLOAD_OFFSET;
+                    let end = payload.len().min(start + size);
+                    self.decode_payload(
+                        &payload[start..end],
+                        packet.lookup_key.timestamp,
+                        flow_id,
+                    )?;
+                    offset = start + size;
                 }
-                self.decode_payload(
-                    &payload[DNS_TCP_PAYLOAD_OFFSET..],
-                    packet.lookup_key.timestamp,
-                    flow_id,
-                )?;
             }
             _ => return Err(Error::DNSPerfParseFailed("dns translation type error")),
         }
@@ -123,9 +127,11 @@ impl L7FlowPerf for DnsPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_histogram: stats.rrt_histogram,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -250,6 +256,7 @@ impl DnsPerfData {
             perf_stats.rrt_last = rrt;
             perf_stats.rrt_sum += rrt;
             perf_stats.rrt_count += 1;
+            perf_stats.rrt_histogram.record(rrt.as_micros() as u32);
             return Ok(());
         }
 
@@ -264,6 +271,7 @@ mod tests {
     use super::*;
 
     use crate::common::flow::PacketDirection;
+    use crate::metric::meter::LatencyHistogram;
     use crate::utils::test::Capture;
 
     const FILE_DIR: &str = "resources/test/flow_generator/dns";
@@ -272,7 +280,7 @@ mod tests {
         let rrt_cache = Rc::new(RefCell::new(L7RrtCache::new(100)));
         let mut dns_perf_data = DnsPerfData::new(rrt_cache);
 
-        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(pcap), None);
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(pcap), Some(1500));
         let mut packets = capture.as_meta_packets();
         if packets.len() < 2 {
             return dns_perf_data;
@@ -291,30 +299,58 @@ mod tests {
 
     #[test]
     fn check() {
-        let expected = vec![(
-            "dns.pcap",
-            DnsPerfData {
-                perf_stats: Some(PerfStats {
-                    req_count: 2,
-                    resp_count: 2,
-                    req_err_count: 1,
-                    resp_err_count: 0,
-                    rrt_count: 2,
-                    rrt_max: Duration::from_nanos(176754000),
-                    rrt_last: Duration::from_nanos(4804000),
-                    rrt_sum: Duration::from_nanos(181558000),
-                }),
-                session_data: DnsSessionData {
-                    id: 0,
-                    status_code: 0,
-                    status: L7ResponseStatus::Ok,
-                    has_log_data: true,
-                    l7_proto: L7Protocol::DNS,
-                    msg_type: LogMessageType::Response,
-                    rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
+        let expected = vec![
+            (
+                "dns.pcap",
+                DnsPerfData {
+                    perf_stats: Some(PerfStats {
+                        req_count: 2,
+                        resp_count: 2,
+                        req_err_count: 1,
+                        resp_err_count: 0,
+                        rrt_count: 2,
+                        rrt_max: Duration::from_nanos(176754000),
+                        rrt_last: Duration::from_nanos(4804000),
+                        rrt_sum: Duration::from_nanos(181558000),
+                        rrt_histogram: LatencyHistogram::from_counts(&[(4096, 1), (163840, 1)]),
+                    }),
+                    session_data: DnsSessionData {
+                        id: 0,
+                        status_code: 0,
+                        status: L7ResponseStatus::Ok,
+                        has_log_data: true,
+                        l7_proto: L7Protocol::DNS,
+                        msg_type: LogMessageType::Response,
+                        rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
+                    },
                 },
-            },
-        )];
+            ),
+            (
+                "dns_tcp_multi.pcap",
+                DnsPerfData {
+                    perf_stats: Some(PerfStats {
+                        req_count: 4,
+                        resp_count: 4,
+                        req_err_count: 0,
+                        resp_err_count: 0,
+                        rrt_count: 4,
+                        rrt_max: Duration::from_micros(800),
+                        rrt_last: Duration::from_micros(800),
+                        rrt_sum: Duration::from_micros(3200),
+                        rrt_histogram: LatencyHistogram::from_counts(&[(768, 4)]),
+                    }),
+                    session_data: DnsSessionData {
+                        id: 0,
+                        status_code: 0,
+                        status: L7ResponseStatus::Ok,
+                        has_log_data: true,
+                        l7_proto: L7Protocol::DNS,
+                        msg_type: LogMessageType::Response,
+                        rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
+                    },
+                },
+            ),
+        ];
 
         for item in expected.iter() {
             assert_eq!(item.1, run(item.0), "parse pcap {} unexcepted", item.0);
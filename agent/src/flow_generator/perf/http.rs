@@ -120,9 +120,11 @@ impl L7FlowPerf for HttpPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_histogram: stats.rrt_histogram,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -260,6 +262,7 @@ impl HttpPerfData {
             perf_stats.rrt_last = rrt;
             perf_stats.rrt_sum += rrt;
             perf_stats.rrt_count += 1;
+            perf_stats.rrt_histogram.record(rrt.as_micros() as u32);
         } else {
             // HTTP请求行：GET /background.png HTTP/1.0
             let context: Vec<&str> = line_info.split(" ").collect();
@@ -446,6 +449,7 @@ impl HttpPerfData {
             perf_stats.rrt_last = rrt;
             perf_stats.rrt_sum += rrt;
             perf_stats.rrt_count += 1;
+            perf_stats.rrt_histogram.record(rrt.as_micros() as u32);
             perf_stats.resp_count += 1;
         } else {
             self.session_data.msg_type = LogMessageType::Request;
@@ -468,6 +472,7 @@ mod tests {
 
     use super::*;
 
+    use crate::metric::meter::LatencyHistogram;
     use crate::utils::test::Capture;
 
     const FILE_DIR: &str = "resources/test/flow_generator/http";
@@ -509,6 +514,7 @@ mod tests {
                         rrt_max: Duration::from_nanos(84051000),
                         rrt_last: Duration::from_nanos(84051000),
                         rrt_sum: Duration::from_nanos(84051000),
+                        rrt_histogram: LatencyHistogram::from_counts(&[(81920, 1)]),
                     }),
                     session_data: HttpSessionData {
                         l7_proto: L7Protocol::Http1,
@@ -533,6 +539,7 @@ mod tests {
                         rrt_max: Duration::from_nanos(2023000),
                         rrt_last: Duration::from_nanos(2023000),
                         rrt_sum: Duration::from_nanos(2023000),
+                        rrt_histogram: LatencyHistogram::from_counts(&[(1792, 1)]),
                     }),
                     session_data: HttpSessionData {
                         l7_proto: L7Protocol::Http2,
@@ -18,6 +18,7 @@ mod dns;
 mod http;
 pub mod l7_rrt;
 mod mq;
+mod reassembly;
 mod rpc;
 mod sql;
 mod stats;
@@ -29,17 +30,19 @@ use std::rc::Rc;
 use std::sync::Arc;
 use std::time::Duration;
 
+use arc_swap::access::Access;
 use enum_dispatch::enum_dispatch;
 use public::bitmap::Bitmap;
 
 use super::app_table::AppTable;
 use super::error::{Error, Result};
-use super::protocol_logs::{AppProtoHead, PostgresqlLog};
+use super::protocol_logs::AppProtoHead;
 
+use crate::common::ebpf::EbpfType;
 use crate::common::flow::PacketDirection;
 use crate::common::l7_protocol_info::L7ProtocolInfo;
 use crate::common::l7_protocol_log::{
-    get_all_protocol, get_parse_bitmap, L7ProtocolBitmap, L7ProtocolParser,
+    get_all_protocol, get_parse_bitmap, get_parser, L7ProtocolBitmap, L7ProtocolParser,
     L7ProtocolParserInterface, ParseParam,
 };
 use crate::common::{
@@ -52,9 +55,10 @@ use crate::config::handler::LogParserAccess;
 use {
     self::http::HttpPerfData,
     dns::DnsPerfData,
-    mq::{KafkaPerfData, MqttPerfData},
-    rpc::DubboPerfData,
-    sql::{MysqlPerfData, RedisPerfData},
+    mq::{AmqpPerfData, KafkaPerfData, MqttPerfData},
+    reassembly::TcpReassembler,
+    rpc::{DubboPerfData, GrpcPerfData},
+    sql::{MongoDBPerfData, MysqlPerfData, PostgresqlPerfData, RedisPerfData},
     tcp::TcpPerf,
     udp::UdpPerf,
 };
@@ -97,7 +101,10 @@ pub enum L7FlowPerfTable {
     DubboPerfData,
     MysqlPerfData,
     HttpPerfData,
-    PostgresqlLog,
+    PostgresqlPerfData,
+    GrpcPerfData,
+    MongoDBPerfData,
+    AmqpPerfData,
 }
 
 pub struct FlowPerf {
@@ -120,6 +127,11 @@ pub struct FlowPerf {
 
     // port bitmap max = 65535, indicate the l7 protocol in this port whether to parse
     l7_protocol_parse_port_bitmap: Arc<Vec<(String, Bitmap)>>,
+
+    // 协议开启TCP流重组时，将报文重组为完整消息后再交给log parser解析
+    // reassembles packets into complete messages before log parsing if tcp reassembly
+    // is enabled for the protocol
+    tcp_reassembler: Option<TcpReassembler>,
 }
 
 impl FlowPerf {
@@ -131,12 +143,19 @@ impl FlowPerf {
             L7Protocol::Dubbo => Some(L7FlowPerfTable::from(DubboPerfData::new(rrt_cache.clone()))),
             L7Protocol::Kafka => Some(L7FlowPerfTable::from(KafkaPerfData::new(rrt_cache.clone()))),
             L7Protocol::MQTT => Some(L7FlowPerfTable::from(MqttPerfData::new(rrt_cache.clone()))),
+            L7Protocol::AMQP => Some(L7FlowPerfTable::from(AmqpPerfData::new(rrt_cache.clone()))),
             L7Protocol::MySQL => Some(L7FlowPerfTable::from(MysqlPerfData::new(rrt_cache.clone()))),
-            L7Protocol::PostgreSQL => Some(L7FlowPerfTable::from(PostgresqlLog::new())),
+            L7Protocol::PostgreSQL => Some(L7FlowPerfTable::from(PostgresqlPerfData::new(
+                rrt_cache.clone(),
+            ))),
             L7Protocol::Redis => Some(L7FlowPerfTable::from(RedisPerfData::new(rrt_cache.clone()))),
-            L7Protocol::Http1 | L7Protocol::Http2 | L7Protocol::Grpc => {
+            L7Protocol::MongoDB => Some(L7FlowPerfTable::from(MongoDBPerfData::new(
+                rrt_cache.clone(),
+            ))),
+            L7Protocol::Http1 | L7Protocol::Http2 => {
                 Some(L7FlowPerfTable::from(HttpPerfData::new(rrt_cache.clone())))
             }
+            L7Protocol::Grpc => Some(L7FlowPerfTable::from(GrpcPerfData::new(rrt_cache.clone()))),
             _ => None,
         }
     }
@@ -190,12 +209,21 @@ impl FlowPerf {
         }
 
         if let Some(payload) = packet.get_l4_payload() {
-            let parser = self.l7_protocol_log_parser.as_mut().unwrap();
-            parser.set_parse_config(&self.parse_config);
-            let ret = parser.parse_payload(payload, parse_param);
-            parser.reset();
-
-            if !self.is_success {
+            let (ret, is_pending) = if self.is_tcp_reassembly_enabled(packet) {
+                let ret = self.l7_parse_reassembled_log(packet, payload, parse_param);
+                // 消息尚未完整时不更新协议识别结果
+                // do not update the protocol result while the message is incomplete
+                let is_pending = matches!(&ret, Ok(i) if i.is_empty());
+                (ret, is_pending)
+            } else {
+                let parser = self.l7_protocol_log_parser.as_mut().unwrap();
+                parser.set_parse_config(&self.parse_config);
+                let ret = parser.parse_payload(payload, parse_param);
+                parser.reset();
+                (ret, false)
+            };
+
+            if !self.is_success && !is_pending {
                 if ret.is_ok() {
                     app_table.set_protocol(packet, self.l7_protocol);
                     self.is_success = true;
@@ -209,6 +237,64 @@ impl FlowPerf {
         return Err(Error::L7ProtocolUnknown);
     }
 
+    fn is_tcp_reassembly_enabled(&mut self, packet: &MetaPacket) -> bool {
+        if packet.lookup_key.proto != IpProtocol::Tcp || packet.ebpf_type != EbpfType::None {
+            return false;
+        }
+        if self.tcp_reassembler.is_none() {
+            let config = self.parse_config.load();
+            if config.tcp_reassembly_bitmap.is_disabled(self.l7_protocol) {
+                return false;
+            }
+            self.tcp_reassembler = Some(TcpReassembler::new(config.tcp_reassembly_max_buffer_size));
+        }
+        true
+    }
+
+    // 报文载荷放入重组缓存后依次解析其中的完整消息，没有完整消息时返回空结果
+    // ========================================================================
+    // injects the payload into the reassembly buffer and parses complete messages
+    // in it, returns empty result if there is no complete message
+    fn l7_parse_reassembled_log(
+        &mut self,
+        packet: &MetaPacket,
+        payload: &[u8],
+        parse_param: &ParseParam,
+    ) -> Result<Vec<L7ProtocolInfo>> {
+        let reassembler = self.tcp_reassembler.as_mut().unwrap();
+        let parser = self.l7_protocol_log_parser.as_mut().unwrap();
+        let seq = packet.tcp_data.seq;
+        let payload_len = packet.payload_len as usize;
+        // 载荷被截断时无法重组，从下一个报文重新开始，当前报文直接解析
+        // truncated payload can not be reassembled, restart from the next packet
+        // and parse this one as is
+        if payload.len() < payload_len {
+            reassembler.resync(packet.direction, seq.wrapping_add(payload_len as u32));
+            parser.set_parse_config(&self.parse_config);
+            let ret = parser.parse_payload(payload, parse_param);
+            parser.reset();
+            return ret;
+        }
+
+        reassembler.inject(packet.direction, seq, &payload[..payload_len]);
+        let mut infos = vec![];
+        let mut last_error = None;
+        while let Some(message) =
+            reassembler.pop_message(packet.direction, |m| parser.message_length(m, parse_param))
+        {
+            parser.set_parse_config(&self.parse_config);
+            match parser.parse_payload(&message, parse_param) {
+                Ok(i) => infos.extend(i),
+                Err(e) => last_error = Some(e),
+            }
+            parser.reset();
+        }
+        match last_error {
+            Some(e) if infos.is_empty() => Err(e),
+            _ => Ok(infos),
+        }
+    }
+
     fn l7_check(
         &mut self,
         packet: &MetaPacket,
@@ -240,6 +326,17 @@ impl FlowPerf {
                         self.l7_parse_perf(packet, flow_id, app_table)?;
                     }
 
+                    // 重组时check的结果可能来自不完整的消息，换用新的解析器只按完整消息解析一次，
+                    // 避免有状态的解析器（如HPACK动态表、Redis请求序号）重复处理同一数据
+                    // ==========================================================================
+                    // with reassembly the check result may come from an incomplete message, use a
+                    // fresh parser to decode complete messages only once, so that stateful parsers
+                    // (e.g. HPACK dynamic table, Redis request seq) do not process the data twice
+                    if self.is_tcp_reassembly_enabled(packet) {
+                        if let Some(parser) = get_parser(i.protocol()) {
+                            i = parser;
+                        }
+                    }
                     self.l7_protocol_log_parser = Some(i);
                     return self.l7_parse_log(packet, app_table, &param);
                 }
@@ -320,6 +417,7 @@ impl FlowPerf {
             is_skip: false,
             parse_config,
             l7_protocol_parse_port_bitmap,
+            tcp_reassembler: None,
         })
     }
 
@@ -329,6 +427,7 @@ impl FlowPerf {
         self.is_skip = false;
         self.is_success = false;
         self.l7 = Self::l7_new(l7_protocol, self.rrt_cache.clone());
+        self.tcp_reassembler = None;
     }
 
     pub fn parse(
@@ -0,0 +1,380 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::{
+    common::{
+        enums::IpProtocol,
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        meta_packet::MetaPacket,
+    },
+    flow_generator::{
+        error::{Error, Result},
+        perf::l7_rrt::L7RrtCache,
+        perf::stats::PerfStats,
+        perf::L7FlowPerf,
+        protocol_logs::{AmqpLog, AppProtoHead, L7ResponseStatus, LogMessageType},
+    },
+};
+
+struct AmqpSessionData {
+    pub status: L7ResponseStatus,
+    pub has_log_data: bool,
+
+    pub l7_proto: L7Protocol,
+    pub msg_type: LogMessageType,
+    rrt_cache: Rc<RefCell<L7RrtCache>>,
+}
+
+pub struct AmqpPerfData {
+    perf_stats: Option<PerfStats>,
+    session_data: AmqpSessionData,
+    // 复用日志解析获取 channel、方法类型以及 reply-code
+    parser: AmqpLog,
+}
+
+impl PartialEq for AmqpPerfData {
+    fn eq(&self, other: &AmqpPerfData) -> bool {
+        self.perf_stats == other.perf_stats
+            && self.session_data.l7_proto == other.session_data.l7_proto
+            && self.session_data.msg_type == other.session_data.msg_type
+            && self.session_data.status == other.session_data.status
+            && self.session_data.has_log_data == other.session_data.has_log_data
+    }
+}
+
+impl Eq for AmqpPerfData {}
+
+impl fmt::Debug for AmqpPerfData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(perf_stats) = self.perf_stats.as_ref() {
+            write!(f, "perf_stats: {:?}", perf_stats)?;
+        } else {
+            write!(f, "perf_stats: None")?;
+        };
+        write!(f, "l7_proto: {:?}", self.session_data.l7_proto)?;
+        write!(f, "msg_type: {:?}", self.session_data.msg_type)?;
+        write!(f, "status {:?}", self.session_data.status)?;
+        write!(f, "has_log_data: {:?}", self.session_data.has_log_data)
+    }
+}
+
+impl L7FlowPerf for AmqpPerfData {
+    fn parse(&mut self, packet: &MetaPacket, flow_id: u64) -> Result<()> {
+        if packet.lookup_key.proto != IpProtocol::Tcp {
+            return Err(Error::InvalidIpProtocol);
+        }
+
+        let payload = packet.get_l4_payload().ok_or(Error::ZeroPayloadLen)?;
+
+        let infos = self
+            .parser
+            .parse_payload(payload, &ParseParam::from(packet))
+            .map_err(|_| Error::AmqpPerfParseFailed);
+        self.parser.reset();
+
+        let info = match infos?.into_iter().next() {
+            Some(L7ProtocolInfo::AmqpInfo(info)) => info,
+            _ => return Ok(()),
+        };
+
+        self.session_data.l7_proto = L7Protocol::AMQP;
+        self.session_data.has_log_data = true;
+
+        // Connection.Start/Tune、Channel.Close 等请求可能由服务端发起，按方法类型而不是报文方向区分请求和响应
+        let timestamp = packet.lookup_key.timestamp;
+        match info.msg_type {
+            LogMessageType::Request => {
+                self.calc_request(timestamp, flow_id, info.session_id(), info.status)
+            }
+            LogMessageType::Response => {
+                if self.calc_response(timestamp, flow_id, info.session_id(), info.status) {
+                    return Err(Error::L7ReqNotFound(1));
+                }
+            }
+            _ => self.calc_session(info.status),
+        }
+
+        Ok(())
+    }
+
+    fn data_updated(&self) -> bool {
+        self.perf_stats.is_some()
+    }
+
+    fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
+        if let Some(stats) = self.perf_stats.take() {
+            FlowPerfStats {
+                l7_protocol: L7Protocol::AMQP,
+                l7: L7PerfStats {
+                    request_count: stats.req_count,
+                    response_count: stats.resp_count,
+                    rrt_count: stats.rrt_count,
+                    rrt_sum: stats.rrt_sum.as_micros() as u64,
+                    rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_histogram: stats.rrt_histogram,
+                    err_client_count: stats.req_err_count,
+                    err_server_count: stats.resp_err_count,
+                    err_timeout: timeout_count,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        } else {
+            FlowPerfStats {
+                l7_protocol: L7Protocol::AMQP,
+                l7: L7PerfStats {
+                    err_timeout: timeout_count,
+                    ..Default::default()
+                },
+                ..Default::default()
+            }
+        }
+    }
+
+    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
+        if self.session_data.l7_proto != L7Protocol::AMQP || !self.session_data.has_log_data {
+            return None;
+        }
+        self.session_data.has_log_data = false;
+
+        let rrt = self
+            .perf_stats
+            .as_ref()
+            .map(|s| s.rrt_last.as_micros() as u64)
+            .unwrap_or_default();
+
+        Some((
+            AppProtoHead {
+                proto: self.session_data.l7_proto,
+                msg_type: self.session_data.msg_type,
+                rrt,
+            },
+            0,
+        ))
+    }
+}
+
+impl AmqpPerfData {
+    pub fn new(rrt_cache: Rc<RefCell<L7RrtCache>>) -> Self {
+        let session_data = AmqpSessionData {
+            status: L7ResponseStatus::default(),
+            has_log_data: false,
+            l7_proto: L7Protocol::default(),
+            msg_type: LogMessageType::default(),
+            rrt_cache,
+        };
+        Self {
+            perf_stats: None,
+            session_data,
+            parser: AmqpLog::default(),
+        }
+    }
+
+    // 同一个 channel 上同步方法是串行的，使用 channel 区分连接上的多个请求
+    fn calc_request(
+        &mut self,
+        timestamp: Duration,
+        flow_id: u64,
+        channel: Option<u32>,
+        status: L7ResponseStatus,
+    ) {
+        self.session_data.msg_type = LogMessageType::Request;
+
+        let perf_stats = self.perf_stats.get_or_insert(PerfStats::default());
+        perf_stats.req_count += 1;
+        perf_stats.rrt_last = Duration::ZERO;
+
+        // Connection.Close/Channel.Close 的 reply-code 携带在请求中
+        self.session_data.status = status;
+        match status {
+            L7ResponseStatus::ClientError => perf_stats.req_err_count += 1,
+            L7ResponseStatus::ServerError => perf_stats.resp_err_count += 1,
+            _ => {}
+        }
+
+        self.session_data
+            .rrt_cache
+            .borrow_mut()
+            .add_req_time(flow_id, channel, timestamp);
+    }
+
+    // Publish/Deliver/Ack 等异步方法不计算 rrt，只统计 Basic.Return 等携带的错误
+    fn calc_session(&mut self, status: L7ResponseStatus) {
+        self.session_data.msg_type = LogMessageType::Session;
+        self.session_data.status = status;
+
+        match status {
+            L7ResponseStatus::ClientError => {
+                self.perf_stats
+                    .get_or_insert(PerfStats::default())
+                    .req_err_count += 1
+            }
+            L7ResponseStatus::ServerError => {
+                self.perf_stats
+                    .get_or_insert(PerfStats::default())
+                    .resp_err_count += 1
+            }
+            _ => {}
+        }
+    }
+
+    // 返回是否无法匹配到request
+    fn calc_response(
+        &mut self,
+        timestamp: Duration,
+        flow_id: u64,
+        channel: Option<u32>,
+        status: L7ResponseStatus,
+    ) -> bool {
+        self.session_data.msg_type = LogMessageType::Response;
+
+        let perf_stats = self.perf_stats.get_or_insert(PerfStats::default());
+        perf_stats.resp_count += 1;
+
+        self.session_data.status = status;
+        match status {
+            L7ResponseStatus::ClientError => perf_stats.req_err_count += 1,
+            L7ResponseStatus::ServerError => perf_stats.resp_err_count += 1,
+            _ => {}
+        }
+
+        perf_stats.rrt_last = Duration::ZERO;
+
+        let req_timestamp = match self
+            .session_data
+            .rrt_cache
+            .borrow_mut()
+            .get_and_remove_l7_req_time(flow_id, channel)
+        {
+            Some(t) => t,
+            None => return true,
+        };
+
+        if timestamp < req_timestamp {
+            return false;
+        }
+
+        let rrt = timestamp - req_timestamp;
+        if rrt > perf_stats.rrt_max {
+            perf_stats.rrt_max = rrt;
+        }
+        perf_stats.rrt_last = rrt;
+        perf_stats.rrt_sum += rrt;
+        perf_stats.rrt_count += 1;
+        perf_stats.rrt_histogram.record(rrt.as_micros() as u32);
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::common::flow::PacketDirection;
+    use crate::metric::meter::LatencyHistogram;
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/amqp";
+
+    fn run(pcap: &str) -> AmqpPerfData {
+        let rrt_cache = Rc::new(RefCell::new(L7RrtCache::new(100)));
+        let mut amqp_perf_data = AmqpPerfData::new(rrt_cache);
+
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(pcap), Some(1500));
+        let mut packets = capture.as_meta_packets();
+        if packets.len() < 2 {
+            return amqp_perf_data;
+        }
+
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        for packet in packets.iter_mut() {
+            if packet.lookup_key.dst_port == first_dst_port {
+                packet.direction = PacketDirection::ClientToServer;
+            } else {
+                packet.direction = PacketDirection::ServerToClient;
+            }
+            let _ = amqp_perf_data.parse(packet, 0x1f3c01010);
+        }
+        amqp_perf_data
+    }
+
+    #[test]
+    fn check() {
+        let expected = vec![
+            (
+                "amqp_queue_declare.pcap",
+                AmqpPerfData {
+                    perf_stats: Some(PerfStats {
+                        req_count: 1,
+                        resp_count: 1,
+                        req_err_count: 0,
+                        resp_err_count: 0,
+                        rrt_count: 1,
+                        rrt_max: Duration::from_nanos(420000),
+                        rrt_last: Duration::from_nanos(420000),
+                        rrt_sum: Duration::from_nanos(420000),
+                        rrt_histogram: LatencyHistogram::from_counts(&[(384, 1)]),
+                    }),
+                    session_data: AmqpSessionData {
+                        l7_proto: L7Protocol::AMQP,
+                        status: L7ResponseStatus::Ok,
+                        has_log_data: true,
+                        msg_type: LogMessageType::Response,
+                        rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
+                    },
+                    parser: AmqpLog::default(),
+                },
+            ),
+            (
+                "amqp_publish_error.pcap",
+                AmqpPerfData {
+                    perf_stats: Some(PerfStats {
+                        req_count: 1,
+                        resp_count: 1,
+                        req_err_count: 1,
+                        resp_err_count: 0,
+                        rrt_count: 1,
+                        rrt_max: Duration::from_nanos(150000),
+                        rrt_last: Duration::from_nanos(150000),
+                        rrt_sum: Duration::from_nanos(150000),
+                        rrt_histogram: LatencyHistogram::from_counts(&[(128, 1)]),
+                    }),
+                    session_data: AmqpSessionData {
+                        l7_proto: L7Protocol::AMQP,
+                        status: L7ResponseStatus::Ok,
+                        has_log_data: true,
+                        msg_type: LogMessageType::Response,
+                        rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
+                    },
+                    parser: AmqpLog::default(),
+                },
+            ),
+        ];
+
+        for item in expected.iter() {
+            assert_eq!(item.1, run(item.0), "parse pcap {} unexcepted", item.0);
+        }
+    }
+}
@@ -23,6 +23,8 @@ use crate::{
     common::{
         enums::IpProtocol,
         flow::{FlowPerfStats, L7PerfStats, L7Protocol, PacketDirection},
+        l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
+        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
         meta_packet::MetaPacket,
     },
     flow_generator::{
@@ -30,7 +32,7 @@ use crate::{
         perf::l7_rrt::L7RrtCache,
         perf::stats::PerfStats,
         perf::L7FlowPerf,
-        protocol_logs::{AppProtoHead, L7ResponseStatus, LogMessageType},
+        protocol_logs::{AppProtoHead, KafkaLog, L7ResponseStatus, LogMessageType},
     },
     utils::bytes,
 };
@@ -38,41 +40,30 @@ use crate::{
 pub const PORT: u16 = 9092;
 
 const KAFKA_REMAIN: u32 = 4;
-const KAFKA_FETCH: i16 = 1;
-const KAFKA_FETCH_STATUS_CODE_OFFSET: usize = 14;
-const KAFKA_API_KEY_MASK_VALUE: u64 = 0x7fff000000000000;
-const KAFKA_API_KEY_OFFSET: usize = 48;
-const KAFKA_REQ_TIMESTAMP_MASK_VALUE: u64 = 0xffffffffffff;
 
-const KAFKA_REQ_HEADER_LEN: usize = 14;
-const KAFKA_RESP_HEADER_LEN: usize = 8;
-
-pub struct KafkaPerfData {
-    stats: Option<PerfStats>,
-
-    correlation_id: u32,
-    api_key: u16,
-    status_code: u16,
-    status: L7ResponseStatus,
-
-    has_log_data: bool,
-
-    l7_proto: L7Protocol,
-    msg_type: LogMessageType,
+struct KafkaSessionData {
+    pub status: L7ResponseStatus,
+    pub has_log_data: bool,
 
+    pub l7_proto: L7Protocol,
+    pub msg_type: LogMessageType,
     rrt_cache: Rc<RefCell<L7RrtCache>>,
 }
 
+pub struct KafkaPerfData {
+    perf_stats: Option<PerfStats>,
+    session_data: KafkaSessionData,
+    // 复用日志解析获取 correlation_id 以及各个 topic/partition 的错误码
+    parser: KafkaLog,
+}
+
 impl PartialEq for KafkaPerfData {
     fn eq(&self, other: &KafkaPerfData) -> bool {
-        self.stats == other.stats
-            && self.l7_proto == other.l7_proto
-            && self.msg_type == other.msg_type
-            && self.correlation_id == other.correlation_id
-            && self.api_key == other.api_key
-            && self.status_code == other.status_code
-            && self.status == other.status
-            && self.has_log_data == other.has_log_data
+        self.perf_stats == other.perf_stats
+            && self.session_data.l7_proto == other.session_data.l7_proto
+            && self.session_data.msg_type == other.session_data.msg_type
+            && self.session_data.status == other.session_data.status
+            && self.session_data.has_log_data == other.session_data.has_log_data
     }
 }
 
@@ -80,19 +71,15 @@ impl Eq for KafkaPerfData {}
 
 impl fmt::Debug for KafkaPerfData {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if let Some(stats) = self.stats.as_ref() {
-            write!(f, "status: {:?}", stats)?;
+        if let Some(perf_stats) = self.perf_stats.as_ref() {
+            write!(f, "perf_stats: {:?}", perf_stats)?;
         } else {
-            write!(f, "status: None")?;
+            write!(f, "perf_stats: None")?;
         };
-        write!(f, "l7_proto: {:?}", self.l7_proto)?;
-        write!(f, "msg_type: {:?}", self.msg_type)?;
-
-        write!(f, "correlation_id: {:?}", self.correlation_id)?;
-        write!(f, "api_key: {:?}", self.api_key)?;
-        write!(f, "status_code: {:?}", self.status_code)?;
-        write!(f, "status {:?}", self.status)?;
-        write!(f, "has_log_data: {:?}", self.has_log_data)
+        write!(f, "l7_proto: {:?}", self.session_data.l7_proto)?;
+        write!(f, "msg_type: {:?}", self.session_data.msg_type)?;
+        write!(f, "status {:?}", self.session_data.status)?;
+        write!(f, "has_log_data: {:?}", self.session_data.has_log_data)
     }
 }
 
@@ -103,30 +90,42 @@ impl L7FlowPerf for KafkaPerfData {
         }
 
         let payload = packet.get_l4_payload().ok_or(Error::ZeroPayloadLen)?;
+        // 只统计完整的消息，避免把消息分段后的后续报文当作新的请求或响应
+        if payload.len() < KAFKA_REMAIN as usize
+            || bytes::read_u32_be(payload) + KAFKA_REMAIN != packet.payload_len as u32
+        {
+            return Err(Error::KafkaPerfParseFailed);
+        }
 
-        match packet.direction {
-            PacketDirection::ClientToServer => {
-                self.parse_request_header(payload, packet.payload_len)?;
-                self.calc_request(packet.lookup_key.timestamp, flow_id);
-                self.l7_proto = L7Protocol::Kafka;
-                self.has_log_data = true;
-                Ok(())
-            }
-            _ => {
-                self.parse_response_header(payload, packet.payload_len)?;
-                self.l7_proto = L7Protocol::Kafka;
-                self.has_log_data = true;
-                if self.calc_response(payload, packet.lookup_key.timestamp, flow_id) {
-                    Err(Error::L7ReqNotFound(1))
-                } else {
-                    Ok(())
-                }
-            }
+        let infos = self
+            .parser
+            .parse_payload(payload, &ParseParam::from(packet))
+            .map_err(|_| Error::KafkaPerfParseFailed);
+        self.parser.reset();
+
+        let (status, session_id) = match infos?.into_iter().next() {
+            Some(L7ProtocolInfo::KafkaInfo(info)) => (info.status, info.session_id()),
+            _ => return Ok(()),
+        };
+
+        self.session_data.l7_proto = L7Protocol::Kafka;
+        self.session_data.has_log_data = true;
+
+        if packet.direction == PacketDirection::ClientToServer {
+            self.calc_request(packet.lookup_key.timestamp, flow_id, session_id);
+        } else if self.calc_response(packet.lookup_key.timestamp, flow_id, session_id, status) {
+            return Err(Error::L7ReqNotFound(1));
         }
+
+        Ok(())
+    }
+
+    fn data_updated(&self) -> bool {
+        self.perf_stats.is_some()
     }
 
     fn copy_and_reset_data(&mut self, timeout_count: u32) -> FlowPerfStats {
-        if let Some(stats) = self.stats.take() {
+        if let Some(stats) = self.perf_stats.take() {
             FlowPerfStats {
                 l7_protocol: L7Protocol::Kafka,
                 l7: L7PerfStats {
@@ -135,9 +134,11 @@ impl L7FlowPerf for KafkaPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_histogram: stats.rrt_histogram,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -154,182 +155,101 @@ impl L7FlowPerf for KafkaPerfData {
     }
 
     fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
-        if self.l7_proto != L7Protocol::Kafka || !self.has_log_data {
+        if self.session_data.l7_proto != L7Protocol::Kafka || !self.session_data.has_log_data {
             return None;
         }
-        self.has_log_data = false;
+        self.session_data.has_log_data = false;
 
         let rrt = self
-            .stats
+            .perf_stats
             .as_ref()
             .map(|s| s.rrt_last.as_micros() as u64)
             .unwrap_or_default();
 
         Some((
             AppProtoHead {
-                proto: self.l7_proto,
-                msg_type: self.msg_type,
-                rrt: rrt,
+                proto: self.session_data.l7_proto,
+                msg_type: self.session_data.msg_type,
+                rrt,
             },
             0,
         ))
     }
-
-    fn data_updated(&self) -> bool {
-        self.stats.is_some()
-    }
 }
 
 impl KafkaPerfData {
     pub fn new(rrt_cache: Rc<RefCell<L7RrtCache>>) -> Self {
-        Self {
-            stats: None,
-            correlation_id: 0,
-            api_key: 0,
-            status_code: 0,
-            l7_proto: L7Protocol::default(),
-            msg_type: LogMessageType::default(),
+        let session_data = KafkaSessionData {
             status: L7ResponseStatus::default(),
             has_log_data: false,
-            rrt_cache: rrt_cache,
+            l7_proto: L7Protocol::default(),
+            msg_type: LogMessageType::default(),
+            rrt_cache,
+        };
+        Self {
+            perf_stats: None,
+            session_data,
+            parser: KafkaLog::new(),
         }
     }
 
-    // RequestOrResponse => Size (RequestMessage | ResponseMessage)
-    //  message_size => int32
-    //  RequestMessage ｜ ResponseMessage
-    //
-    //  Request Header：
-    // RequestMessage => api_key api_version correlation_id client_id
-    //  api_key => int16
-    //  api_version => int16
-    //  correlation_id => int32
-    //  client_id => string
-    //  MetadataRequest | ProduceRequest | FetchRequest | ListOffsetRequest |......
-    fn parse_request_header(&mut self, payload: &[u8], payload_len: u16) -> Result<()> {
-        if payload.len() < KAFKA_REQ_HEADER_LEN {
-            return Err(Error::KafkaPerfParseFailed);
-        }
-
-        let message_size = bytes::read_u32_be(payload);
-
-        if message_size + KAFKA_REMAIN != payload_len as u32 {
-            return Err(Error::KafkaPerfParseFailed);
-        }
-        let client_id_len = bytes::read_u16_be(&payload[12..]) as usize;
+    // 同一个连接上可能有多个未完成的请求，使用 correlation_id 区分
+    fn calc_request(&mut self, timestamp: Duration, flow_id: u64, correlation_id: Option<u32>) {
+        self.session_data.msg_type = LogMessageType::Request;
 
-        if payload.len() < KAFKA_REQ_HEADER_LEN + client_id_len {
-            return Err(Error::KafkaPerfParseFailed);
-        }
-
-        self.api_key = bytes::read_u16_be(&payload[4..]);
-        self.correlation_id = bytes::read_u32_be(&payload[8..]);
-        Ok(())
+        let perf_stats = self.perf_stats.get_or_insert(PerfStats::default());
+        perf_stats.req_count += 1;
+        perf_stats.rrt_last = Duration::ZERO;
+        self.session_data
+            .rrt_cache
+            .borrow_mut()
+            .add_req_time(flow_id, correlation_id, timestamp);
     }
 
-    // RequestOrResponse => Size (RequestMessage | ResponseMessage)
-    //  message_size => int32
-    //  RequestMessage ｜ ResponseMessage
-    //
-    // Reponse Header：
-    //  ResponseMessage => correlation_id
-    //  correlation_id => int32
-    //  MetadataResponse | ProduceResponse | FetchResponse | ListOffsetResponse |......
-    fn parse_response_header(&mut self, payload: &[u8], payload_len: u16) -> Result<()> {
-        if payload.len() < KAFKA_RESP_HEADER_LEN {
-            return Err(Error::KafkaPerfParseFailed);
-        }
-
-        let message_size = bytes::read_u32_be(payload);
-        if message_size + KAFKA_REMAIN != payload_len as u32 {
-            return Err(Error::KafkaPerfParseFailed);
+    // 返回是否无法匹配到request
+    fn calc_response(
+        &mut self,
+        timestamp: Duration,
+        flow_id: u64,
+        correlation_id: Option<u32>,
+        status: L7ResponseStatus,
+    ) -> bool {
+        self.session_data.msg_type = LogMessageType::Response;
+
+        let perf_stats = self.perf_stats.get_or_insert(PerfStats::default());
+        perf_stats.resp_count += 1;
+
+        // broker 返回的非 0 错误码都作为服务端错误
+        self.session_data.status = status;
+        if status == L7ResponseStatus::ServerError {
+            perf_stats.resp_err_count += 1;
         }
 
-        self.correlation_id = bytes::read_u32_be(&payload[4..]);
-        Ok(())
-    }
-
-    fn calc_request(&mut self, timestamp: Duration, flow_id: u64) {
-        let stats = self.stats.get_or_insert(PerfStats::default());
-        stats.rrt_last = Duration::ZERO;
-        stats.req_count += 1;
-
-        self.msg_type = LogMessageType::Request;
-
-        self.has_log_data = true;
-
-        let api_key = (self.api_key as u64) << KAFKA_API_KEY_OFFSET;
-
-        let timestamp_nanos = (timestamp.as_nanos() as u64 & KAFKA_REQ_TIMESTAMP_MASK_VALUE)
-            | (api_key & KAFKA_API_KEY_MASK_VALUE);
-
-        self.rrt_cache.borrow_mut().add_req_time(
-            flow_id,
-            Some(self.correlation_id),
-            Duration::from_nanos(timestamp_nanos),
-        );
-    }
-
-    fn calc_response(&mut self, payload: &[u8], timestamp: Duration, flow_id: u64) -> bool {
-        self.msg_type = LogMessageType::Response;
-
-        let stats = self.stats.get_or_insert(PerfStats::default());
-        stats.resp_count += 1;
-        self.has_log_data = true;
+        perf_stats.rrt_last = Duration::ZERO;
 
-        let req_timestmp_nanos = match self
+        let req_timestamp = match self
+            .session_data
             .rrt_cache
             .borrow_mut()
-            .get_and_remove_l7_req_time(flow_id, Some(self.correlation_id))
+            .get_and_remove_l7_req_time(flow_id, correlation_id)
         {
-            Some(t) => t.as_nanos() as u64,
+            Some(t) => t,
             None => return true,
         };
 
-        let api_key = (req_timestmp_nanos & KAFKA_API_KEY_MASK_VALUE) >> KAFKA_API_KEY_OFFSET;
-        // 只支持对fetch命令解析返回码
-        if api_key as i16 == KAFKA_FETCH && payload.len() > KAFKA_FETCH_STATUS_CODE_OFFSET {
-            self.status_code = bytes::read_u16_be(&payload[12..]);
-            if self.status_code == 0 {
-                self.status = L7ResponseStatus::Ok;
-            } else {
-                self.status = L7ResponseStatus::ServerError;
-                stats.resp_err_count += 1;
-            }
-        } else {
-            self.status_code = 0;
-            self.status = L7ResponseStatus::NotExist;
-        }
-
-        if (timestamp.as_nanos() as u64 & KAFKA_REQ_TIMESTAMP_MASK_VALUE)
-            < (req_timestmp_nanos & KAFKA_REQ_TIMESTAMP_MASK_VALUE)
-        {
-            stats.rrt_last = Duration::ZERO;
-            return true;
+        if timestamp < req_timestamp {
+            return false;
         }
 
-        let rrt = (timestamp.as_nanos() as u64 & KAFKA_REQ_TIMESTAMP_MASK_VALUE)
-            - (req_timestmp_nanos & KAFKA_REQ_TIMESTAMP_MASK_VALUE);
-
-        let rrt = Duration::from_nanos(rrt);
-        if rrt > stats.rrt_max {
-            stats.rrt_max = rrt;
+        let rrt = timestamp - req_timestamp;
+        if rrt > perf_stats.rrt_max {
+            perf_stats.rrt_max = rrt;
         }
-        stats.rrt_last = rrt;
-        stats.rrt_sum += rrt;
-        stats.rrt_count += 1;
-        return false;
-    }
-
-    fn reset(&mut self) {
-        self.stats = None;
-        self.correlation_id = 0;
-        self.api_key = 0;
-        self.status_code = 0;
-        self.l7_proto = L7Protocol::default();
-        self.msg_type = LogMessageType::default();
-        self.status = L7ResponseStatus::default();
-        self.has_log_data = false;
+        perf_stats.rrt_last = rrt;
+        perf_stats.rrt_sum += rrt;
+        perf_stats.rrt_count += 1;
+        perf_stats.rrt_histogram.record(rrt.as_micros() as u32);
+        false
     }
 }
 
@@ -339,6 +259,7 @@ mod tests {
 
     use super::*;
 
+    use crate::metric::meter::LatencyHistogram;
     use crate::utils::test::Capture;
 
     const FILE_DIR: &str = "resources/test/flow_generator/kafka";
@@ -347,10 +268,10 @@ mod tests {
         let rrt_cache = Rc::new(RefCell::new(L7RrtCache::new(100)));
         let mut kafka_perf_data = KafkaPerfData::new(rrt_cache);
 
-        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(pcap), None);
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(pcap), Some(1500));
         let mut packets = capture.as_meta_packets();
         if packets.len() < 2 {
-            return kafka_perf_data.stats.unwrap_or_default();
+            return kafka_perf_data.perf_stats.unwrap_or_default();
         }
 
         let first_dst_port = packets[0].lookup_key.dst_port;
@@ -362,7 +283,7 @@ mod tests {
             }
             let _ = kafka_perf_data.parse(packet, 1608373855724393643);
         }
-        kafka_perf_data.stats.unwrap_or_default()
+        kafka_perf_data.perf_stats.unwrap_or_default()
     }
 
     #[test]
@@ -379,6 +300,7 @@ mod tests {
                     rrt_max: Duration::from_nanos(4941000),
                     rrt_last: Duration::from_nanos(4941000),
                     rrt_sum: Duration::from_nanos(4941000),
+                    rrt_histogram: LatencyHistogram::from_counts(&[(4096, 1)]),
                 },
             ),
             (
@@ -392,6 +314,21 @@ mod tests {
                     rrt_max: Duration::from_nanos(504829000),
                     rrt_last: Duration::from_nanos(504829000),
                     rrt_sum: Duration::from_nanos(504829000),
+                    rrt_histogram: LatencyHistogram::from_counts(&[(458752, 1)]),
+                },
+            ),
+            (
+                "kafka_produce.pcap",
+                PerfStats {
+                    req_count: 1,
+                    resp_count: 1,
+                    req_err_count: 0,
+                    resp_err_count: 1,
+                    rrt_count: 1,
+                    rrt_max: Duration::from_nanos(2500000),
+                    rrt_last: Duration::from_nanos(2500000),
+                    rrt_sum: Duration::from_nanos(2500000),
+                    rrt_histogram: LatencyHistogram::from_counts(&[(2048, 1)]),
                 },
             ),
         ];
@@ -14,9 +14,12 @@
  * limitations under the License.
  */
 
+mod amqp;
 mod kafka;
 mod mqtt;
 
+pub use amqp::AmqpPerfData;
+
 pub use kafka::KafkaPerfData;
 pub use kafka::PORT as KAFKA_PORT;
 
@@ -110,9 +110,11 @@ impl L7FlowPerf for MqttPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_histogram: stats.rrt_histogram,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -278,6 +280,7 @@ impl MqttPerfData {
         stats.rrt_last = rrt;
         stats.rrt_sum += rrt;
         stats.rrt_count += 1;
+        stats.rrt_histogram.record(rrt.as_micros() as u32);
     }
 
     fn reset(&mut self) {
@@ -297,6 +300,7 @@ mod tests {
     use super::*;
 
     use crate::common::flow::PacketDirection;
+    use crate::metric::meter::LatencyHistogram;
     use crate::utils::test::Capture;
 
     const FILE_DIR: &str = "resources/test/flow_generator/mqtt";
@@ -337,6 +341,7 @@ mod tests {
                     rrt_max: Duration::from_nanos(256746000),
                     rrt_last: Duration::from_nanos(256746000),
                     rrt_sum: Duration::from_nanos(256746000),
+                    rrt_histogram: LatencyHistogram::from_counts(&[(229376, 1)]),
                 },
             ),
             (
@@ -350,6 +355,7 @@ mod tests {
                     rrt_max: Duration::from_nanos(272795000),
                     rrt_last: Duration::from_nanos(272795000),
                     rrt_sum: Duration::from_nanos(272795000),
+                    rrt_histogram: LatencyHistogram::from_counts(&[(262144, 1)]),
                 },
             ),
         ];
@@ -0,0 +1,393 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::mem;
+
+use super::tcp::{SeqSegment, SEQ_LIST_MAX_LEN};
+
+use crate::common::{flow::PacketDirection, l7_protocol_log::MessageLength};
+
+// 单方向的TCP流缓存，按seq排列载荷，最多缓存SEQ_LIST_MAX_LEN个乱序报文
+// single direction TCP stream buffer, orders payload by seq and holds at most
+// SEQ_LIST_MAX_LEN out-of-order segments
+#[derive(Default)]
+struct StreamBuffer {
+    // 下一个期望的seq，未收到数据时为None
+    // the next expected seq, None before any data arrives
+    next_seq: Option<u32>,
+    // 已按序到达但尚未组成完整消息的数据
+    // in-order data not yet framed into complete messages
+    data: Vec<u8>,
+    // 乱序到达的报文，按seq升序排列
+    // out-of-order segments sorted by seq
+    out_of_order: Vec<(SeqSegment, Vec<u8>)>,
+    out_of_order_bytes: usize,
+    // 超长消息中尚未到达、需要丢弃的字节数
+    // bytes of an oversize message still to be dropped when they arrive
+    skip: usize,
+}
+
+impl StreamBuffer {
+    fn reset(&mut self, next_seq: u32) {
+        self.next_seq = Some(next_seq);
+        self.data.clear();
+        self.out_of_order.clear();
+        self.out_of_order_bytes = 0;
+        self.skip = 0;
+    }
+
+    fn append(&mut self, mut payload: &[u8]) {
+        if self.skip > 0 {
+            let n = self.skip.min(payload.len());
+            self.skip -= n;
+            payload = &payload[n..];
+        }
+        self.data.extend_from_slice(payload);
+    }
+
+    fn inject(&mut self, seq: u32, payload: &[u8], max_size: usize) {
+        let next_seq = *self.next_seq.get_or_insert(seq);
+        let offset = seq.wrapping_sub(next_seq) as i32;
+        if offset < 0 {
+            // 重传或部分重叠，只保留未收到的部分
+            // retransmission or partial overlap, keep the part not received yet
+            let overlap = offset.unsigned_abs() as usize;
+            if overlap < payload.len() {
+                self.in_order(&payload[overlap..]);
+            }
+        } else if offset == 0 {
+            self.in_order(payload);
+        } else if offset as usize > max_size {
+            // 跳跃过大无法等待，从当前报文重新开始
+            // the gap is too large to wait for, restart from this segment
+            self.reset(seq);
+            self.in_order(payload);
+        } else {
+            self.insert_out_of_order(seq, payload, max_size);
+        }
+    }
+
+    fn in_order(&mut self, payload: &[u8]) {
+        self.append(payload);
+        self.next_seq = self.next_seq.map(|s| s.wrapping_add(payload.len() as u32));
+        self.drain_out_of_order();
+    }
+
+    fn drain_out_of_order(&mut self) {
+        while let Some((seg, _)) = self.out_of_order.first() {
+            let next_seq = self.next_seq.unwrap();
+            let offset = seg.seq.wrapping_sub(next_seq) as i32;
+            if offset > 0 {
+                break;
+            }
+            let (_, payload) = self.out_of_order.remove(0);
+            self.out_of_order_bytes -= payload.len();
+            let overlap = offset.unsigned_abs() as usize;
+            if overlap < payload.len() {
+                self.append(&payload[overlap..]);
+                self.next_seq = Some(next_seq.wrapping_add((payload.len() - overlap) as u32));
+            }
+        }
+    }
+
+    fn insert_out_of_order(&mut self, seq: u32, payload: &[u8], max_size: usize) {
+        if payload.len() > max_size {
+            // 单个报文超出缓存上限（如GRO/TSO合并的报文），无法等待缺失的数据，
+            // 从该报文重新开始，只保留缓存上限内的部分
+            // ===================================================================
+            // a single segment exceeds the buffer limit (e.g. merged by GRO/TSO),
+            // can not wait for the missing data, restart from this segment and
+            // keep only the part within the buffer limit
+            self.reset(seq.wrapping_add(payload.len() as u32));
+            self.data.extend_from_slice(&payload[..max_size]);
+            return;
+        }
+        let next_seq = self.next_seq.unwrap();
+        let offset = seq.wrapping_sub(next_seq);
+        let index = match self
+            .out_of_order
+            .binary_search_by_key(&offset, |(s, _)| s.seq.wrapping_sub(next_seq))
+        {
+            Ok(_) => return,
+            Err(i) => i,
+        };
+        if self.out_of_order.len() >= SEQ_LIST_MAX_LEN
+            || self.out_of_order_bytes + payload.len() > max_size
+        {
+            // 缺失的报文迟迟未到，丢弃不完整的消息，从第一个乱序报文继续
+            // the missing segment does not arrive in time, drop the incomplete
+            // message and continue from the first out-of-order segment
+            self.skip_gap();
+            self.inject(seq, payload, max_size);
+            return;
+        }
+        self.out_of_order.insert(
+            index,
+            (
+                SeqSegment {
+                    seq,
+                    len: payload.len() as u32,
+                },
+                payload.to_vec(),
+            ),
+        );
+        self.out_of_order_bytes += payload.len();
+    }
+
+    fn skip_gap(&mut self) {
+        self.data.clear();
+        self.skip = 0;
+        if let Some((seg, _)) = self.out_of_order.first() {
+            self.next_seq = Some(seg.seq);
+        }
+        self.drain_out_of_order();
+    }
+
+    fn pop_message<F>(&mut self, max_size: usize, frame: F) -> Option<Vec<u8>>
+    where
+        F: FnOnce(&[u8]) -> MessageLength,
+    {
+        if self.data.is_empty() {
+            return None;
+        }
+        match frame(&self.data) {
+            MessageLength::Complete(0) => {
+                // 长度为0无法消费任何数据，视为分帧失败，丢弃缓存避免死循环
+                // a zero length consumes nothing, treat it as a framing failure
+                // and drop the buffer to avoid looping forever
+                self.data.clear();
+                None
+            }
+            MessageLength::Complete(n) if n <= self.data.len() => {
+                let remain = self.data.split_off(n);
+                Some(mem::replace(&mut self.data, remain))
+            }
+            MessageLength::Complete(n) if n > max_size => {
+                // 消息超出缓存上限，先解析已收到的部分，剩余部分到达后丢弃
+                // the message exceeds the buffer limit, parse the received part
+                // now and drop the rest when it arrives
+                self.skip = n - self.data.len();
+                Some(mem::take(&mut self.data))
+            }
+            MessageLength::Complete(_) | MessageLength::Partial if self.data.len() < max_size => {
+                None
+            }
+            _ => Some(mem::take(&mut self.data)),
+        }
+    }
+}
+
+// TCP流重组，将两个方向的报文载荷分别按seq排序拼接，再按协议分帧成完整的应用消息
+// TCP stream reassembly, orders and joins payloads by seq in each direction,
+// then frames them into complete application messages by protocol
+pub struct TcpReassembler {
+    max_buffer_size: usize,
+    peers: [StreamBuffer; 2],
+}
+
+impl TcpReassembler {
+    pub fn new(max_buffer_size: usize) -> Self {
+        Self {
+            max_buffer_size,
+            peers: Default::default(),
+        }
+    }
+
+    pub fn inject(&mut self, direction: PacketDirection, seq: u32, payload: &[u8]) {
+        if payload.is_empty() {
+            return;
+        }
+        self.peers[direction as usize].inject(seq, payload, self.max_buffer_size);
+    }
+
+    // 丢弃该方向缓存的数据，从next_seq开始重新组装，用于载荷被截断等无法重组的场景
+    // drops data buffered in the direction and restarts from next_seq, used when
+    // the payload can not be reassembled, e.g. truncated by snap length
+    pub fn resync(&mut self, direction: PacketDirection, next_seq: u32) {
+        self.peers[direction as usize].reset(next_seq);
+    }
+
+    // 取出缓存头部的一个完整消息，frame返回消息长度
+    // 无法分帧或缓存已满时返回缓存中的全部数据
+    // ===========================================================================
+    // pops a complete message from the head of the buffer, frame returns the
+    // message length, returns all buffered data if it can not be framed or the
+    // buffer is full
+    pub fn pop_message<F>(&mut self, direction: PacketDirection, frame: F) -> Option<Vec<u8>>
+    where
+        F: FnOnce(&[u8]) -> MessageLength,
+    {
+        self.peers[direction as usize].pop_message(self.max_buffer_size, frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_BUFFER_SIZE: usize = 64;
+
+    // 测试用的分帧：1字节长度 + 数据
+    // framing for tests: 1 byte length + data
+    fn frame(payload: &[u8]) -> MessageLength {
+        match payload.first() {
+            Some(&0) => MessageLength::Unknown,
+            Some(&len) => MessageLength::Complete(len as usize + 1),
+            None => MessageLength::Partial,
+        }
+    }
+
+    fn pop_all(r: &mut TcpReassembler, direction: PacketDirection) -> Vec<Vec<u8>> {
+        let mut messages = vec![];
+        while let Some(m) = r.pop_message(direction, frame) {
+            messages.push(m);
+        }
+        messages
+    }
+
+    #[test]
+    fn split_messages() {
+        let mut r = TcpReassembler::new(MAX_BUFFER_SIZE);
+        let c2s = PacketDirection::ClientToServer;
+        r.inject(c2s, 1000, &[4, b'a', b'b']);
+        assert!(pop_all(&mut r, c2s).is_empty());
+        r.inject(c2s, 1003, &[b'c', b'd', 2, b'e']);
+        assert_eq!(pop_all(&mut r, c2s), vec![vec![4, b'a', b'b', b'c', b'd']]);
+        r.inject(c2s, 1007, &[b'f', 1, b'g']);
+        assert_eq!(
+            pop_all(&mut r, c2s),
+            vec![vec![2, b'e', b'f'], vec![1, b'g']]
+        );
+
+        // 两个方向互不影响
+        // directions are independent
+        let s2c = PacketDirection::ServerToClient;
+        r.inject(s2c, 7, &[1, b'x']);
+        r.inject(c2s, 1010, &[3]);
+        assert_eq!(pop_all(&mut r, s2c), vec![vec![1, b'x']]);
+        assert!(pop_all(&mut r, c2s).is_empty());
+    }
+
+    #[test]
+    fn out_of_order_and_retrans() {
+        let mut r = TcpReassembler::new(MAX_BUFFER_SIZE);
+        let c2s = PacketDirection::ClientToServer;
+        r.inject(c2s, 100, &[5, b'a']);
+        r.inject(c2s, 104, b"de");
+        r.inject(c2s, 104, b"de");
+        assert!(pop_all(&mut r, c2s).is_empty());
+        // 与已收到数据部分重叠的重传
+        // retransmission partially overlapping received data
+        r.inject(c2s, 101, b"abc");
+        assert_eq!(
+            pop_all(&mut r, c2s),
+            vec![vec![5, b'a', b'b', b'c', b'd', b'e']]
+        );
+        r.inject(c2s, 100, &[5, b'a']);
+        assert!(pop_all(&mut r, c2s).is_empty());
+
+        // seq回绕
+        // seq wraps around
+        let mut r = TcpReassembler::new(MAX_BUFFER_SIZE);
+        r.inject(c2s, u32::MAX - 1, &[3, b'a']);
+        r.inject(c2s, 2, &[1, b'x']);
+        r.inject(c2s, 0, b"bc");
+        assert_eq!(
+            pop_all(&mut r, c2s),
+            vec![vec![3, b'a', b'b', b'c'], vec![1, b'x']]
+        );
+    }
+
+    #[test]
+    fn lost_segment() {
+        let mut r = TcpReassembler::new(MAX_BUFFER_SIZE);
+        let c2s = PacketDirection::ClientToServer;
+        r.inject(c2s, 0, &[9, b'a']);
+        // seq 2丢失，乱序报文超过上限后放弃等待
+        // seq 2 is lost, stop waiting after too many out-of-order segments
+        let mut seq = 3;
+        for _ in 0..SEQ_LIST_MAX_LEN {
+            r.inject(c2s, seq, &[1, b'x']);
+            seq += 2;
+        }
+        assert!(pop_all(&mut r, c2s).is_empty());
+        r.inject(c2s, seq, &[1, b'y']);
+        let messages = pop_all(&mut r, c2s);
+        assert_eq!(messages.len(), SEQ_LIST_MAX_LEN + 1);
+        assert_eq!(messages.last().unwrap(), &vec![1, b'y']);
+
+        // 跳跃过大时直接从新报文开始
+        // restart from the new segment if the gap is too large
+        r.inject(c2s, seq + 2 + MAX_BUFFER_SIZE as u32 * 2, &[1, b'z']);
+        assert_eq!(pop_all(&mut r, c2s), vec![vec![1, b'z']]);
+    }
+
+    #[test]
+    fn unframed_and_oversize() {
+        let mut r = TcpReassembler::new(MAX_BUFFER_SIZE);
+        let c2s = PacketDirection::ClientToServer;
+        // 无法分帧时直接返回全部数据
+        // return all data if it can not be framed
+        r.inject(c2s, 0, &[0, b'a', b'b']);
+        assert_eq!(pop_all(&mut r, c2s), vec![vec![0, b'a', b'b']]);
+
+        // 超长消息只返回已收到的部分，剩余部分被丢弃
+        // only the received part of an oversize message is returned, the rest is dropped
+        r.inject(c2s, 3, &[200, b'a']);
+        assert_eq!(pop_all(&mut r, c2s), vec![vec![200, b'a']]);
+        let mut seq = 5;
+        for _ in 0..3 {
+            r.inject(c2s, seq, &[b'-'; 50]);
+            seq += 50;
+        }
+        assert!(pop_all(&mut r, c2s).is_empty());
+        r.inject(c2s, seq, &[b'-'; 49]);
+        r.inject(c2s, seq + 49, &[1, b'b']);
+        seq += 51;
+        assert_eq!(pop_all(&mut r, c2s), vec![vec![1, b'b']]);
+
+        // 超出缓存上限的乱序报文
+        // out-of-order segment larger than the buffer limit
+        r.inject(c2s, seq, &[3, b'a']);
+        let mut oversize = vec![1, b'x'];
+        oversize.resize(MAX_BUFFER_SIZE * 2, 0);
+        r.inject(c2s, seq + 10, &oversize);
+        let messages = pop_all(&mut r, c2s);
+        assert_eq!(messages[0], vec![1, b'x']);
+        assert_eq!(
+            messages.iter().map(|m| m.len()).sum::<usize>(),
+            MAX_BUFFER_SIZE
+        );
+        seq += 10 + MAX_BUFFER_SIZE as u32 * 2;
+        r.inject(c2s, seq, &[1, b'y']);
+        assert_eq!(pop_all(&mut r, c2s), vec![vec![1, b'y']]);
+
+        // 分帧返回长度0时丢弃缓存
+        // drop the buffer if framing returns a zero length
+        r.inject(c2s, seq, &[2, b'a']);
+        assert_eq!(r.pop_message(c2s, |_| MessageLength::Complete(0)), None);
+        r.inject(c2s, seq + 2, &[1, b'b']);
+        seq += 4;
+        assert_eq!(pop_all(&mut r, c2s), vec![vec![1, b'b']]);
+
+        // 截断的报文重新同步
+        // resync after a truncated segment
+        r.inject(c2s, seq + 3, &[5, b'a']);
+        r.resync(c2s, seq + 100);
+        r.inject(c2s, seq + 100, &[1, b'c']);
+        assert_eq!(pop_all(&mut r, c2s), vec![vec![1, b'c']]);
+    }
+}
@@ -113,9 +113,11 @@ impl L7FlowPerf for DubboPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_histogram: stats.rrt_histogram,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -224,6 +226,7 @@ impl DubboPerfData {
         perf_stats.rrt_last = rrt;
         perf_stats.rrt_sum += rrt;
         perf_stats.rrt_count += 1;
+        perf_stats.rrt_histogram.record(rrt.as_micros() as u32);
         false
     }
 
@@ -243,6 +246,7 @@ mod tests {
 
     use super::*;
 
+    use crate::metric::meter::LatencyHistogram;
     use crate::utils::test::Capture;
 
     const FILE_DIR: &str = "resources/test/flow_generator/dubbo";
@@ -283,6 +287,7 @@ mod tests {
                     rrt_max: Duration::from_nanos(4332000),
                     rrt_last: Duration::from_nanos(4332000),
                     rrt_sum: Duration::from_nanos(4332000),
+                    rrt_histogram: LatencyHistogram::from_counts(&[(4096, 1)]),
                 }),
                 session_data: DubboSessionData {
                     l7_proto: L7Protocol::Dubbo,
@@ -15,6 +15,8 @@
  */
 
 mod dubbo;
+mod grpc;
 
 pub use dubbo::DubboPerfData;
 pub use dubbo::PORT as DUBBO_PORT;
+pub use grpc::GrpcPerfData;
@@ -14,10 +14,14 @@
  * limitations under the License.
  */
 
+mod mongodb;
 mod mysql;
+mod postgresql;
 mod redis;
 
+pub use mongodb::MongoDBPerfData;
 pub use mysql::MysqlPerfData;
 pub use mysql::PORT as MYSQL_PORT;
+pub use postgresql::PostgresqlPerfData;
 pub use redis::RedisPerfData;
 pub use redis::PORT as REDIS_PORT;
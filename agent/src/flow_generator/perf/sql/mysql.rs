@@ -158,9 +158,11 @@ impl L7FlowPerf for MysqlPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_histogram: stats.rrt_histogram,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    ..Default::default()
                 },
                 ..Default::default()
             }
@@ -267,6 +269,7 @@ impl MysqlPerfData {
         stats.rrt_last = rrt;
         stats.rrt_sum += rrt;
         stats.rrt_count += 1;
+        stats.rrt_histogram.record(rrt.as_micros() as u32);
         false
     }
 
@@ -297,7 +300,9 @@ mod test {
 
     use super::*;
 
-    use crate::{common::flow::PacketDirection, utils::test::Capture};
+    use crate::{
+        common::flow::PacketDirection, metric::meter::LatencyHistogram, utils::test::Capture,
+    };
 
     const FILE_DIR: &str = "resources/test/flow_generator/mysql";
 
@@ -335,6 +340,12 @@ mod test {
                         rrt_max: Duration::from_nanos(123000),
                         rrt_sum: Duration::from_nanos(373000),
                         rrt_last: Duration::ZERO,
+                        rrt_histogram: LatencyHistogram::from_counts(&[
+                            (32, 1),
+                            (40, 1),
+                            (80, 2),
+                            (112, 1),
+                        ]),
                     }),
                     l7_proto: L7Protocol::MySQL,
                     msg_type: LogMessageType::Request,
@@ -358,6 +369,7 @@ mod test {
                         rrt_max: Duration::from_nanos(146000),
                         rrt_sum: Duration::from_nanos(226000),
                         rrt_last: Duration::ZERO,
+                        rrt_histogram: LatencyHistogram::from_counts(&[(28, 1), (48, 1), (128, 1)]),
                     }),
                     l7_proto: L7Protocol::MySQL,
                     msg_type: LogMessageType::Request,
@@ -381,6 +393,40 @@ mod test {
                         rrt_max: Duration::from_nanos(5355000),
                         rrt_sum: Duration::from_nanos(127090000),
                         rrt_last: Duration::from_nanos(692000),
+                        rrt_histogram: LatencyHistogram::from_counts(&[
+                            (8, 1),
+                            (10, 46),
+                            (12, 37),
+                            (14, 17),
+                            (16, 3),
+                            (20, 11),
+                            (24, 17),
+                            (28, 5),
+                            (32, 30),
+                            (40, 19),
+                            (48, 22),
+                            (56, 23),
+                            (64, 21),
+                            (80, 4),
+                            (96, 3),
+                            (128, 6),
+                            (160, 6),
+                            (192, 13),
+                            (224, 11),
+                            (256, 7),
+                            (320, 11),
+                            (384, 7),
+                            (448, 4),
+                            (512, 1),
+                            (640, 7),
+                            (768, 11),
+                            (896, 3),
+                            (1024, 14),
+                            (1280, 7),
+                            (1792, 1),
+                            (2048, 20),
+                            (5120, 2),
+                        ]),
                     }),
                     l7_proto: L7Protocol::MySQL,
                     msg_type: LogMessageType::Response,
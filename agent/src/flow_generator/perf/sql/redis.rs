@@ -15,14 +15,16 @@
  */
 
 use std::cell::RefCell;
+use std::collections::VecDeque;
 use std::fmt;
+use std::mem;
 use std::rc::Rc;
 use std::time::Duration;
 
 use crate::{
     common::{
         enums::IpProtocol,
-        flow::{FlowPerfStats, L7PerfStats, L7Protocol, PacketDirection},
+        flow::{FlowPerfStats, L7PerfStats, L7Protocol, L7RequestTypeStats, PacketDirection},
         meta_packet::MetaPacket,
     },
     flow_generator::{
@@ -30,12 +32,17 @@ use crate::{
         perf::l7_rrt::L7RrtCache,
         perf::stats::PerfStats,
         perf::L7FlowPerf,
-        protocol_logs::{decode, AppProtoHead, L7ResponseStatus, LogMessageType},
+        protocol_logs::{decode, split_messages, AppProtoHead, L7ResponseStatus, LogMessageType},
     },
 };
 
 pub const PORT: u16 = 6379;
 
+// 流内最多等待回应的请求数，超过时丢弃最早的请求
+// max number of requests waiting for responses in a flow, the oldest is dropped if exceeded
+const MAX_PENDING_REQUESTS: usize = 1024;
+const MAX_COMMAND_LENGTH: usize = 32;
+
 pub struct RedisPerfData {
     pub stats: Option<PerfStats>,
     l7_proto: L7Protocol,
@@ -44,6 +51,13 @@ pub struct RedisPerfData {
     status: L7ResponseStatus,
     has_log_data: bool,
     rrt_cache: Rc<RefCell<L7RrtCache>>,
+
+    // Redis按请求顺序回应，等待回应的请求的序号和命令
+    // redis responds in request order, sequence numbers and commands of the requests
+    // waiting for responses
+    pending: VecDeque<(u32, String)>,
+    req_seq: u32,
+    command_stats: Vec<L7RequestTypeStats>,
 }
 
 impl PartialEq for RedisPerfData {
@@ -54,6 +68,9 @@ impl PartialEq for RedisPerfData {
             && self.active == other.active
             && self.status == other.status
             && self.has_log_data == other.has_log_data
+            && self.pending == other.pending
+            && self.req_seq == other.req_seq
+            && self.command_stats == other.command_stats
     }
 }
 
@@ -70,7 +87,10 @@ impl fmt::Debug for RedisPerfData {
         write!(f, "msg_type: {:?}", self.msg_type)?;
         write!(f, "active: {:?}", self.active)?;
         write!(f, "status {:?}", self.status)?;
-        write!(f, "has_log_data: {:?}", self.has_log_data)
+        write!(f, "has_log_data: {:?}", self.has_log_data)?;
+        write!(f, "pending: {:?}", self.pending)?;
+        write!(f, "req_seq: {:?}", self.req_seq)?;
+        write!(f, "command_stats: {:?}", self.command_stats)
     }
 }
 
@@ -87,21 +107,37 @@ impl L7FlowPerf for RedisPerfData {
         {
             return Err(Error::RedisPerfParseFailed);
         }
-        // Redis协议通过Redis请求来识别，对于请求报文格式严格检查，回应有分段的情况不会严格检查
-        let (context, _, is_error_resp) =
-            decode(payload, packet.direction == PacketDirection::ClientToServer)
-                .ok_or(Error::RedisPerfParseFailed)?;
-        self.l7_proto = L7Protocol::Redis;
-        self.has_log_data = true;
-        if packet.direction == PacketDirection::ClientToServer {
-            self.calc_request(packet.lookup_key.timestamp, flow_id);
-        } else if self.calc_response(
-            packet.lookup_key.timestamp,
-            &context,
-            flow_id,
-            is_error_resp,
-        ) {
-            return Err(Error::L7ReqNotFound(1));
+        let is_request = packet.direction == PacketDirection::ClientToServer;
+        let mut parsed = false;
+        let mut req_not_found = 0;
+        // 流水线中多个命令或回应可能在同一个报文中
+        // pipelined commands or responses may share a packet
+        for message in split_messages(payload) {
+            // RESP3推送消息不是对请求的回应
+            // RESP3 pushes are not responses to requests
+            if !is_request && message[0] == b'>' {
+                parsed = true;
+                continue;
+            }
+            // Redis协议通过Redis请求来识别，对于请求报文格式严格检查，回应有分段的情况不会严格检查
+            let (context, _, is_error_resp) = match decode(message, is_request) {
+                Some(r) => r,
+                // 后续的数据可能被截断
+                // the following data may be truncated
+                None if parsed => break,
+                None => return Err(Error::RedisPerfParseFailed),
+            };
+            parsed = true;
+            self.l7_proto = L7Protocol::Redis;
+            self.has_log_data = true;
+            if is_request {
+                self.calc_request(packet.lookup_key.timestamp, &context, flow_id);
+            } else if self.calc_response(packet.lookup_key.timestamp, flow_id, is_error_resp) {
+                req_not_found += 1;
+            }
+        }
+        if req_not_found > 0 {
+            return Err(Error::L7ReqNotFound(req_not_found));
         }
         Ok(())
     }
@@ -120,9 +156,11 @@ impl L7FlowPerf for RedisPerfData {
                     rrt_count: stats.rrt_count,
                     rrt_sum: stats.rrt_sum.as_micros() as u64,
                     rrt_max: stats.rrt_max.as_micros() as u32,
+                    rrt_histogram: stats.rrt_histogram,
                     err_client_count: stats.req_err_count,
                     err_server_count: stats.resp_err_count,
                     err_timeout: timeout_count,
+                    request_type_stats: mem::take(&mut self.command_stats),
                 },
                 ..Default::default()
             }
@@ -170,32 +208,56 @@ impl RedisPerfData {
             status: L7ResponseStatus::default(),
             has_log_data: false,
             rrt_cache: rrt_cache,
+            pending: VecDeque::new(),
+            req_seq: 0,
+            command_stats: vec![],
         }
     }
 
-    fn calc_request(&mut self, timestamp: Duration, flow_id: u64) {
+    // 命令统一为大写，例如"get"和"GET"计入同一命令
+    // commands are uppercased, e.g. "get" and "GET" are counted as the same command
+    fn command(context: &[u8]) -> String {
+        let command = match context.iter().position(|&x| x == b' ') {
+            Some(i) if i > 0 => &context[..i],
+            _ => context,
+        };
+        let command = &command[..command.len().min(MAX_COMMAND_LENGTH)];
+        String::from_utf8_lossy(command).to_ascii_uppercase()
+    }
+
+    fn calc_request(&mut self, timestamp: Duration, context: &[u8], flow_id: u64) {
         let stats = self.stats.get_or_insert(PerfStats::default());
         stats.rrt_last = Duration::ZERO;
         stats.req_count += 1;
-        self.active += 1;
         self.msg_type = LogMessageType::Request;
+
+        let command = Self::command(context);
+        L7RequestTypeStats::get_or_insert(&mut self.command_stats, &command).request_count += 1;
+        if self.pending.len() >= MAX_PENDING_REQUESTS {
+            // 丢弃最早的请求时一并删除其请求时间，避免残留在rrt_cache中被计为超时
+            // remove the request time of the dropped request as well, so that it does not stay
+            // in rrt_cache and get counted as timeout
+            if let Some((seq, _)) = self.pending.pop_front() {
+                self.rrt_cache
+                    .borrow_mut()
+                    .get_and_remove_l7_req_time(flow_id, Some(seq));
+            }
+            self.active -= 1;
+        }
         self.rrt_cache
             .borrow_mut()
-            .add_req_time(flow_id, None, timestamp);
+            .add_req_time(flow_id, Some(self.req_seq), timestamp);
+        self.pending.push_back((self.req_seq, command));
+        self.req_seq = self.req_seq.wrapping_add(1);
+        self.active += 1;
     }
 
     // 返回是否无法匹配到request
-    fn calc_response(
-        &mut self,
-        timestamp: Duration,
-        context: &Vec<u8>,
-        flow_id: u64,
-        is_error_resp: bool,
-    ) -> bool {
+    fn calc_response(&mut self, timestamp: Duration, flow_id: u64, is_error_resp: bool) -> bool {
         let stats = self.stats.get_or_insert(PerfStats::default());
         stats.resp_count += 1;
         self.msg_type = LogMessageType::Response;
-        if context.len() > 1 && context[0] == b'-' && is_error_resp {
+        if is_error_resp {
             stats.resp_err_count += 1;
             self.status = L7ResponseStatus::ServerError;
         } else {
@@ -203,19 +265,26 @@ impl RedisPerfData {
         }
         stats.rrt_last = Duration::ZERO;
 
-        if self.active <= 0 {
-            return true;
+        let (seq, command) = match self.pending.pop_front() {
+            Some(p) => p,
+            None => return true,
+        };
+        self.active -= 1;
+        let command_stats = L7RequestTypeStats::get_or_insert(&mut self.command_stats, &command);
+        command_stats.response_count += 1;
+        if is_error_resp {
+            command_stats.err_server_count += 1;
         }
+
         let req_timestamp = match self
             .rrt_cache
             .borrow_mut()
-            .get_and_remove_l7_req_time(flow_id, None)
+            .get_and_remove_l7_req_time(flow_id, Some(seq))
         {
             Some(t) => t,
             None => return true,
         };
 
-        self.active -= 1;
         if timestamp < req_timestamp {
             return false;
         }
@@ -226,6 +295,14 @@ impl RedisPerfData {
         stats.rrt_last = rrt;
         stats.rrt_sum += rrt;
         stats.rrt_count += 1;
+        stats.rrt_histogram.record(rrt.as_micros() as u32);
+
+        let rrt = rrt.as_micros();
+        if rrt as u32 > command_stats.rrt_max {
+            command_stats.rrt_max = rrt as u32;
+        }
+        command_stats.rrt_sum += rrt as u64;
+        command_stats.rrt_count += 1;
         false
     }
 
@@ -236,6 +313,8 @@ impl RedisPerfData {
         self.active = 0;
         self.status = L7ResponseStatus::default();
         self.has_log_data = false;
+        self.pending.clear();
+        self.command_stats.clear();
     }
 }
 
@@ -245,6 +324,7 @@ mod tests {
 
     use super::*;
 
+    use crate::metric::meter::LatencyHistogram;
     use crate::utils::test::Capture;
 
     const FILE_DIR: &str = "resources/test/flow_generator/redis";
@@ -286,6 +366,14 @@ mod tests {
                         rrt_max: Duration::from_nanos(96000),
                         rrt_last: Duration::ZERO,
                         rrt_sum: Duration::from_nanos(592000),
+                        rrt_histogram: LatencyHistogram::from_counts(&[
+                            (20, 2),
+                            (28, 1),
+                            (56, 3),
+                            (64, 1),
+                            (80, 2),
+                            (96, 1),
+                        ]),
                     }),
                     l7_proto: L7Protocol::Redis,
                     status: L7ResponseStatus::ServerError,
@@ -293,6 +381,30 @@ mod tests {
                     has_log_data: true,
                     msg_type: LogMessageType::Response,
                     rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
+                    pending: VecDeque::new(),
+                    req_seq: 10,
+                    command_stats: vec![
+                        L7RequestTypeStats {
+                            request_type: "GET".to_string(),
+                            request_count: 7,
+                            response_count: 7,
+                            err_server_count: 0,
+                            rrt_count: 7,
+                            rrt_sum: 524,
+                            rrt_max: 96,
+                            ..Default::default()
+                        },
+                        L7RequestTypeStats {
+                            request_type: "EXISTS".to_string(),
+                            request_count: 3,
+                            response_count: 3,
+                            err_server_count: 0,
+                            rrt_count: 3,
+                            rrt_sum: 68,
+                            rrt_max: 28,
+                            ..Default::default()
+                        },
+                    ],
                 },
             ),
             (
@@ -307,6 +419,7 @@ mod tests {
                         rrt_max: Duration::from_nanos(73000),
                         rrt_last: Duration::from_nanos(73000),
                         rrt_sum: Duration::from_nanos(73000),
+                        rrt_histogram: LatencyHistogram::from_counts(&[(64, 1)]),
                     }),
                     l7_proto: L7Protocol::Redis,
                     active: 0,
@@ -314,6 +427,18 @@ mod tests {
                     has_log_data: true,
                     msg_type: LogMessageType::Response,
                     rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
+                    pending: VecDeque::new(),
+                    req_seq: 1,
+                    command_stats: vec![L7RequestTypeStats {
+                        request_type: "GET".to_string(),
+                        request_count: 1,
+                        response_count: 1,
+                        err_server_count: 1,
+                        rrt_count: 1,
+                        rrt_sum: 73,
+                        rrt_max: 73,
+                        ..Default::default()
+                    }],
                 },
             ),
             (
@@ -328,6 +453,7 @@ mod tests {
                         rrt_max: Duration::from_nanos(1209000),
                         rrt_last: Duration::from_nanos(1209000),
                         rrt_sum: Duration::from_nanos(1209000),
+                        rrt_histogram: LatencyHistogram::from_counts(&[(1024, 1)]),
                     }),
                     l7_proto: L7Protocol::Redis,
                     active: 0,
@@ -335,6 +461,18 @@ mod tests {
                     has_log_data: true,
                     msg_type: LogMessageType::Response,
                     rrt_cache: Rc::new(RefCell::new(L7RrtCache::new(100))),
+                    pending: VecDeque::new(),
+                    req_seq: 1,
+                    command_stats: vec![L7RequestTypeStats {
+                        request_type: "LRANGE".to_string(),
+                        request_count: 1,
+                        response_count: 1,
+                        err_server_count: 0,
+                        rrt_count: 1,
+                        rrt_sum: 1209,
+                        rrt_max: 1209,
+                        ..Default::default()
+                    }],
                 },
             ),
         ];
@@ -19,6 +19,7 @@ use std::time::Duration;
 
 use serde::Serialize;
 
+use crate::metric::meter::LatencyHistogram;
 use crate::utils::stats::{Counter, CounterType, CounterValue, RefCountable};
 
 // 每次获取统计数据后此结构体都会被清零，不能在其中保存Flow级别的信息避免被清空
@@ -32,6 +33,7 @@ pub struct PerfStats {
     pub rrt_max: Duration,
     pub rrt_last: Duration,
     pub rrt_sum: Duration,
+    pub rrt_histogram: LatencyHistogram,
 }
 
 #[derive(Default)]
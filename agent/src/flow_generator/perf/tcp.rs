@@ -31,6 +31,7 @@ use crate::{
         meta_packet::{MetaPacket, MetaPacketTcpHeader},
     },
     flow_generator::error::{Error, Result},
+    metric::meter::LatencyHistogram,
 };
 
 const SRT_MAX: Duration = Duration::from_secs(10);
@@ -71,13 +72,13 @@ enum PacketSeqType {
 }
 
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
-struct SeqSegment {
+pub(super) struct SeqSegment {
     // 避免乱序，识别重传
     pub seq: u32,
     pub len: u32,
 }
 
-const SEQ_LIST_MAX_LEN: usize = 16;
+pub(super) const SEQ_LIST_MAX_LEN: usize = 16;
 
 #[derive(Default)]
 struct SessionPeer {
@@ -366,12 +367,13 @@ struct PacketVariance {
 #[derive(Default)]
 struct PerfControl(SessionPeer, SessionPeer);
 
-#[derive(Default, Debug, PartialEq, Eq)]
+#[derive(Default, PartialEq, Eq)]
 struct TimeStats {
     pub count: u32,
     pub sum: Duration,
     pub max: Duration,
     pub updated: bool,
+    pub histogram: LatencyHistogram,
 }
 
 impl TimeStats {
@@ -381,10 +383,24 @@ impl TimeStats {
         if self.max < d {
             self.max = d;
         }
+        self.histogram.record(d.as_micros() as u32);
         self.updated = true;
     }
 }
 
+// 直方图与count/sum/max来自相同的样本，调试输出中省略
+// the histogram is built from the same samples as count/sum/max and is left out of debug output
+impl fmt::Debug for TimeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TimeStats")
+            .field("count", &self.count)
+            .field("sum", &self.sum)
+            .field("max", &self.max)
+            .field("updated", &self.updated)
+            .finish()
+    }
+}
+
 // art---Application Response Time
 // 现有3个连续包PSH/ACK--ACK--PSH/ACK,其中第一个包是client端的请求包，
 // 后2个包是server端的应答包，art表示后2个包之间的时间间隔
@@ -552,22 +568,26 @@ impl PerfData {
                 stats.art_max = self.art_1.max.as_micros() as u32;
                 stats.art_sum = self.art_1.sum.as_micros() as u32;
                 stats.art_count = self.art_1.count;
+                stats.art_histogram = self.art_1.histogram.clone();
             }
             if self.srt_1.updated {
                 stats.srt_max = self.srt_1.max.as_micros() as u32;
                 stats.srt_sum = self.srt_1.sum.as_micros() as u32;
                 stats.srt_count = self.srt_1.count;
+                stats.srt_histogram = self.srt_1.histogram.clone();
             }
         } else {
             if self.art_0.updated {
                 stats.art_max = self.art_0.max.as_micros() as u32;
                 stats.art_sum = self.art_0.sum.as_micros() as u32;
                 stats.art_count = self.art_0.count;
+                stats.art_histogram = self.art_0.histogram.clone();
             }
             if self.srt_0.updated {
                 stats.srt_max = self.srt_0.max.as_micros() as u32;
                 stats.srt_sum = self.srt_0.sum.as_micros() as u32;
                 stats.srt_count = self.srt_0.count;
+                stats.srt_histogram = self.srt_0.histogram.clone();
             }
             stats.reverse();
         }
@@ -588,6 +608,7 @@ impl PerfData {
             stats.cit_max = self.cit.max.as_micros() as u32;
             stats.cit_sum = self.cit.sum.as_micros() as u32;
             stats.cit_count = self.cit.count;
+            stats.cit_histogram = self.cit.histogram.clone();
         }
     }
 }
@@ -22,6 +22,7 @@ use crate::common::{
     meta_packet::MetaPacket,
 };
 use crate::flow_generator::error::{Error, Result};
+use crate::metric::meter::LatencyHistogram;
 
 use super::{L4FlowPerf, ART_MAX};
 
@@ -31,6 +32,7 @@ pub struct UdpPerf {
     art_max: Duration,
     art_sum: Duration,
     art_count: u32,
+    art_histogram: LatencyHistogram,
     last_pkt_direction: PacketDirection,
     data_update_flag: bool,
 }
@@ -58,6 +60,7 @@ impl L4FlowPerf for UdpPerf {
                 self.art_max = max(self.art_max, art);
                 self.art_sum += art;
                 self.art_count += 1;
+                self.art_histogram.record(art.as_micros() as u32);
                 self.data_update_flag = true;
             }
         }
@@ -77,6 +80,7 @@ impl L4FlowPerf for UdpPerf {
         stats.tcp.art_max = (self.art_max.as_nanos() / Duration::from_micros(1).as_nanos()) as u32;
         stats.tcp.art_sum = (self.art_sum.as_nanos() / Duration::from_micros(1).as_nanos()) as u32;
         stats.tcp.art_count = self.art_count;
+        stats.tcp.art_histogram = self.art_histogram.clone();
 
         stats
     }
@@ -36,6 +36,11 @@ pub const HTTP_RESP_MIN_LEN: usize = 13; // 响应行："HTTP/1.1 200 "
 
 pub const HTTP_HOST_OFFSET: usize = 6;
 pub const HTTP_CONTENT_LENGTH_OFFSET: usize = 16;
+// 额外采集的header和query参数在attributes中的名字前缀
+// name prefixes of the extra headers and query parameters captured into attributes
+pub const HTTP_REQUEST_HEADER_ATTR_PREFIX: &str = "http.request.header.";
+pub const HTTP_RESPONSE_HEADER_ATTR_PREFIX: &str = "http.response.header.";
+pub const HTTP_REQUEST_QUERY_ATTR_PREFIX: &str = "http.request.query.";
 
 pub const HTTPV2_CUSTOM_DATA_MIN_LENGTH: usize = 16;
 
@@ -45,15 +50,56 @@ pub const HTTPV2_MAGIC_PREFIX: &str = "PRI * HTTP";
 
 pub const HTTPV2_FRAME_DATA_TYPE: u8 = 0x00;
 pub const HTTPV2_FRAME_HEADERS_TYPE: u8 = 0x01;
+pub const HTTPV2_FRAME_SETTINGS_TYPE: u8 = 0x04;
+pub const HTTPV2_FRAME_PUSH_PROMISE_TYPE: u8 = 0x05;
+pub const HTTPV2_FRAME_CONTINUATION_TYPE: u8 = 0x09;
 
 pub const HTTPV2_FRAME_TYPE_MIN: u8 = 0x00;
 pub const HTTPV2_FRAME_TYPE_MAX: u8 = 0x09;
 
+pub const FLAG_HEADERS_END_STREAM: u8 = 0x1;
+pub const FLAG_HEADERS_END_HEADERS: u8 = 0x4;
+pub const FLAG_SETTINGS_ACK: u8 = 0x1;
+
+pub const HTTPV2_SETTINGS_PARAMETER_LENGTH: usize = 6;
+pub const HTTPV2_SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+// 每条流每个方向的HPACK动态表上限
+// upper limit of the HPACK dynamic table of each direction of a flow
+pub const HTTPV2_HPACK_DYNAMIC_TABLE_LIMIT: usize = 16 << 10;
+
 pub const TRACE_ID_TYPE: usize = 0;
 pub const SPAN_ID_TYPE: usize = 1;
 
 // 参考：https://www.w3.org/Protocols/rfc2616/rfc2616-sec9.html
 
+// gRPC constants
+// 参考：https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md
+pub const GRPC_CONTENT_TYPE_PREFIX: &str = "application/grpc";
+pub const GRPC_MESSAGE_HEADER_LEN: usize = 5; // Compressed-Flag 1B + Message-Length 4B
+
+// Dubbo 3 Triple 协议基于 gRPC，使用 tri- 开头的头部
+// 参考：https://github.com/apache/dubbo/blob/3.2/dubbo-rpc/dubbo-rpc-triple/src/main/java/org/apache/dubbo/rpc/protocol/tri/TripleHeaderEnum.java
+pub const TRIPLE_HEADER_PREFIX: &str = "tri-";
+
+// grpc-status, 参考：https://github.com/grpc/grpc/blob/master/doc/statuscodes.md
+pub const GRPC_STATUS_OK: i32 = 0;
+pub const GRPC_STATUS_CANCELLED: i32 = 1;
+pub const GRPC_STATUS_UNKNOWN: i32 = 2;
+pub const GRPC_STATUS_INVALID_ARGUMENT: i32 = 3;
+pub const GRPC_STATUS_DEADLINE_EXCEEDED: i32 = 4;
+pub const GRPC_STATUS_NOT_FOUND: i32 = 5;
+pub const GRPC_STATUS_ALREADY_EXISTS: i32 = 6;
+pub const GRPC_STATUS_PERMISSION_DENIED: i32 = 7;
+pub const GRPC_STATUS_RESOURCE_EXHAUSTED: i32 = 8;
+pub const GRPC_STATUS_FAILED_PRECONDITION: i32 = 9;
+pub const GRPC_STATUS_ABORTED: i32 = 10;
+pub const GRPC_STATUS_OUT_OF_RANGE: i32 = 11;
+pub const GRPC_STATUS_UNIMPLEMENTED: i32 = 12;
+pub const GRPC_STATUS_INTERNAL: i32 = 13;
+pub const GRPC_STATUS_UNAVAILABLE: i32 = 14;
+pub const GRPC_STATUS_DATA_LOSS: i32 = 15;
+pub const GRPC_STATUS_UNAUTHENTICATED: i32 = 16;
+
 // Kafka constants
 pub const KAFKA_REQ_HEADER_LEN: usize = 14;
 pub const KAFKA_RESP_HEADER_LEN: usize = 8;
@@ -78,6 +124,33 @@ pub const SERVICE_ERROR: u8 = 70;
 pub const SERVER_ERROR: u8 = 80;
 pub const SERVER_THREADPOOL_EXHAUSTED_ERROR: u8 = 100;
 
+// serialization id
+// 参考：https://github.com/apache/dubbo/blob/3.2/dubbo-common/src/main/java/org/apache/dubbo/common/serialize/Constants.java
+pub const DUBBO_SERIAL_HESSIAN2: u8 = 2;
+pub const DUBBO_SERIAL_JAVA: u8 = 3;
+pub const DUBBO_SERIAL_COMPACTED_JAVA: u8 = 4;
+pub const DUBBO_SERIAL_FASTJSON: u8 = 6;
+pub const DUBBO_SERIAL_NATIVE_JAVA: u8 = 7;
+pub const DUBBO_SERIAL_KRYO: u8 = 8;
+pub const DUBBO_SERIAL_FST: u8 = 9;
+pub const DUBBO_SERIAL_NATIVE_HESSIAN: u8 = 10;
+pub const DUBBO_SERIAL_AVRO: u8 = 11;
+pub const DUBBO_SERIAL_PROTOSTUFF: u8 = 12;
+pub const DUBBO_SERIAL_GSON: u8 = 16;
+pub const DUBBO_SERIAL_PROTOBUF_JSON: u8 = 21;
+pub const DUBBO_SERIAL_PROTOBUF: u8 = 22;
+pub const DUBBO_SERIAL_FASTJSON2: u8 = 23;
+pub const DUBBO_SERIAL_KRYO2: u8 = 25;
+
+// response body flag
+// 参考：https://github.com/apache/dubbo/blob/3.2/dubbo-rpc/dubbo-rpc-dubbo/src/main/java/org/apache/dubbo/rpc/protocol/dubbo/DubboCodec.java
+pub const RESPONSE_WITH_EXCEPTION: i32 = 0;
+pub const RESPONSE_VALUE: i32 = 1;
+pub const RESPONSE_NULL_VALUE: i32 = 2;
+pub const RESPONSE_WITH_EXCEPTION_WITH_ATTACHMENTS: i32 = 3;
+pub const RESPONSE_VALUE_WITH_ATTACHMENTS: i32 = 4;
+pub const RESPONSE_NULL_VALUE_WITH_ATTACHMENTS: i32 = 5;
+
 pub const BC_STRING: u8 = 0x73;
 pub const BC_STRING_CHUNK: u8 = 0x72;
 pub const BC_STRING_DIRECT: u8 = 0x00;
@@ -85,6 +158,14 @@ pub const STRING_DIRECT_MAX: u8 = 0x1f;
 pub const BC_STRING_SHORT: u8 = 0x30;
 pub const BC_STRING_SHORT_MAX: u8 = 0x33; // STRING_SHORT_MAX >> 8 | BC_STRING_SHORT
 pub const STRING_SHORT_MAX: u16 = 0x3ff;
+// hessian2 的字符串、类定义和对象实例标记
+// hessian2 tags of string, class definition and object instance
+pub const BC_STRING_FINAL: u8 = b'S';
+pub const BC_STRING_NON_FINAL: u8 = b'R';
+pub const BC_OBJECT_DEF: u8 = b'C';
+pub const BC_OBJECT: u8 = b'O';
+pub const BC_OBJECT_DIRECT: u8 = 0x60;
+pub const OBJECT_DIRECT_MAX: u8 = 0x6f;
 
 // 参考：https://dubbo.apache.org/zh/docs/concepts/rpc-protocol/#protocol-spec
 // Dubbo Request Body
@@ -140,6 +221,12 @@ pub const INT_BASE_LEN: usize = 1;
 pub const MYSQL_RESPONSE_CODE_OK: u8 = 0;
 pub const MYSQL_RESPONSE_CODE_ERR: u8 = 0xff;
 pub const MYSQL_RESPONSE_CODE_EOF: u8 = 0xfe;
+pub const MYSQL_RESPONSE_CODE_LOCAL_INFILE: u8 = 0xfb;
+// 以0xfe开头且小于9字节的包为EOF包或代替EOF的OK包，其中EOF包固定为5字节
+// packets starting with 0xfe and shorter than 9 bytes are EOF packets or OK packets
+// replacing EOF, EOF packets are always 5 bytes
+pub const EOF_PACKET_LEN: usize = 5;
+pub const EOF_PACKET_MAX_LEN: usize = 9;
 
 pub const COM_QUIT: u8 = 1;
 pub const COM_INIT_DB: u8 = 2;
@@ -151,6 +238,30 @@ pub const COM_STMT_CLOSE: u8 = 25;
 pub const COM_STMT_FETCH: u8 = 28;
 pub const COM_MAX: u8 = 26;
 
+// MongoDB constants
+// 参考：https://www.mongodb.com/docs/manual/reference/mongodb-wire-protocol/
+pub const MONGO_HEADER_LEN: usize = 16;
+// 服务端默认的 maxMessageSizeBytes
+pub const MONGO_MESSAGE_MAX_LEN: usize = 48 * 1024 * 1024;
+
+pub const MONGO_OP_REPLY: i32 = 1;
+pub const MONGO_OP_UPDATE: i32 = 2001;
+pub const MONGO_OP_INSERT: i32 = 2002;
+pub const MONGO_OP_QUERY: i32 = 2004;
+pub const MONGO_OP_GET_MORE: i32 = 2005;
+pub const MONGO_OP_DELETE: i32 = 2006;
+pub const MONGO_OP_KILL_CURSORS: i32 = 2007;
+pub const MONGO_OP_COMPRESSED: i32 = 2012;
+pub const MONGO_OP_MSG: i32 = 2013;
+
+// OP_MSG flagBits
+pub const MONGO_MSG_FLAG_CHECKSUM_PRESENT: u32 = 1;
+// OP_MSG section kind
+pub const MONGO_MSG_SECTION_BODY: u8 = 0;
+pub const MONGO_MSG_SECTION_DOCUMENT_SEQUENCE: u8 = 1;
+// OP_REPLY responseFlags
+pub const MONGO_REPLY_FLAG_QUERY_FAILURE: i32 = 2;
+
 // dns constants
 use std::time::Duration;
 
@@ -185,18 +296,35 @@ pub const DNS_NAME_MAX_SIZE: usize = 255;
 pub const DNS_HEADER_QDCOUNT_OFFSET: usize = 4;
 pub const DNS_HEADER_ANCOUNT_OFFSET: usize = 6;
 pub const DNS_HEADER_NSCOUNT_OFFSET: usize = 8;
+pub const DNS_HEADER_ARCOUNT_OFFSET: usize = 10;
 pub const QUESTION_CLASS_OFFSET: usize = 2;
 pub const QUESTION_CLASS_TYPE_SIZE: usize = 4;
 pub const RR_CLASS_OFFSET: usize = 2;
+pub const RR_TTL_OFFSET: usize = 4;
 pub const RR_DATALENGTH_OFFSET: usize = 8;
 pub const RR_RDATA_OFFSET: usize = 10;
 pub const DNS_TYPE_A: u16 = 1;
 pub const DNS_TYPE_NS: u16 = 2;
+pub const DNS_TYPE_CNAME: u16 = 5;
 pub const DNS_TYPE_SOA: u16 = 6;
 pub const DNS_TYPE_WKS: u16 = 11;
 pub const DNS_TYPE_PTR: u16 = 12;
+pub const DNS_TYPE_MX: u16 = 15;
+pub const DNS_TYPE_TXT: u16 = 16;
 pub const DNS_TYPE_AAAA: u16 = 28;
+pub const DNS_TYPE_SRV: u16 = 33;
 pub const DNS_TYPE_DNAME: u16 = 39;
+pub const DNS_TYPE_OPT: u16 = 41;
 pub const DNS_TYPE_WKS_LENGTH: usize = 5;
-pub const DNS_TYPE_PTR_LENGTH: usize = 2;
+pub const DNS_TYPE_MX_PREFERENCE_LENGTH: usize = 2;
+pub const DNS_TYPE_SRV_FIXED_LENGTH: usize = 6;
+// EDNS0, 参考：https://www.rfc-editor.org/rfc/rfc6891
+pub const EDNS_OPTION_HEADER_SIZE: usize = 4;
+pub const EDNS_OPTION_CLIENT_SUBNET: u16 = 8;
+pub const EDNS_CLIENT_SUBNET_HEADER_SIZE: usize = 4;
+pub const EDNS_FAMILY_IPV4: u16 = 1;
+pub const EDNS_FAMILY_IPV6: u16 = 2;
+// OPT 记录的 TTL 字段：extended-rcode(8) version(8) DO(1) Z(15)
+pub const EDNS_DO_OFFSET: usize = 2;
+pub const EDNS_DO_MASK: u8 = 0x80;
 pub const DOMAIN_NAME_SPLIT: char = ';';
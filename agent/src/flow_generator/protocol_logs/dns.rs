@@ -13,9 +13,11 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
+use std::net::{Ipv4Addr, Ipv6Addr};
+
 use serde::Serialize;
 
-use super::pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response};
+use super::pb_adapter::{ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response};
 use super::{consts::*, value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
 use crate::{
     common::{
@@ -27,7 +29,7 @@ use crate::{
     },
     flow_generator::error::{Error, Result},
     log_info_merge, parse_common,
-    utils::bytes::read_u16_be,
+    utils::bytes::{read_u16_be, read_u32_be},
 };
 use public::{l7_protocol::L7Protocol, utils::net::parse_ip_slice};
 
@@ -46,8 +48,21 @@ pub struct DnsInfo {
     // A: ipv4/ipv6地址
     // NS: name server
     // SOA: primary name server
+    // MX: preference exchange
+    // SRV: priority weight port target
     #[serde(rename = "response_result", skip_serializing_if = "value_is_default")]
     pub answers: String,
+    // 与 answers 一一对应的 TTL
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub answer_ttls: Vec<u32>,
+
+    // EDNS0 OPT 记录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub edns_udp_size: Option<u16>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub edns_client_subnet: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub dnssec_ok: bool,
 
     #[serde(rename = "response_status")]
     pub status: L7ResponseStatus,
@@ -93,6 +108,15 @@ impl L7ProtocolInfoInterface for DnsInfo {
 impl DnsInfo {
     pub fn merge(&mut self, other: Self) {
         self.answers = other.answers;
+        self.answer_ttls = other.answer_ttls;
+        // 优先使用请求中客户端携带的 EDNS0 信息
+        if self.edns_udp_size.is_none() {
+            self.edns_udp_size = other.edns_udp_size;
+            self.dnssec_ok = other.dnssec_ok;
+        }
+        if self.edns_client_subnet.is_empty() {
+            self.edns_client_subnet = other.edns_client_subnet;
+        }
         if other.status != L7ResponseStatus::default() {
             self.status = other.status;
         }
@@ -113,6 +137,16 @@ impl DnsInfo {
         match self.domain_type {
             1..=16 => typ[self.domain_type as usize],
             28 => "AAAA",
+            33 => "SRV",
+            39 => "DNAME",
+            41 => "OPT",
+            43 => "DS",
+            46 => "RRSIG",
+            47 => "NSEC",
+            48 => "DNSKEY",
+            50 => "NSEC3",
+            64 => "SVCB",
+            65 => "HTTPS",
             252 => "AXFR",
             253 => "MAILB",
             254 => "MAILA",
@@ -125,6 +159,36 @@ impl DnsInfo {
 impl From<DnsInfo> for L7ProtocolSendLog {
     fn from(f: DnsInfo) -> Self {
         let req_type = String::from(f.get_domain_str());
+
+        let mut attributes = vec![];
+        if !f.answer_ttls.is_empty() {
+            attributes.push(KeyVal {
+                key: String::from("answer_ttls"),
+                val: f
+                    .answer_ttls
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect::<Vec<_>>()
+                    .join(&DOMAIN_NAME_SPLIT.to_string()),
+            });
+        }
+        if let Some(size) = f.edns_udp_size {
+            attributes.push(KeyVal {
+                key: String::from("edns_udp_size"),
+                val: size.to_string(),
+            });
+            attributes.push(KeyVal {
+                key: String::from("dnssec_ok"),
+                val: f.dnssec_ok.to_string(),
+            });
+        }
+        if !f.edns_client_subnet.is_empty() {
+            attributes.push(KeyVal {
+                key: String::from("edns_client_subnet"),
+                val: f.edns_client_subnet,
+            });
+        }
+
         let log = L7ProtocolSendLog {
             req: L7Request {
                 req_type,
@@ -139,6 +203,11 @@ impl From<DnsInfo> for L7ProtocolSendLog {
             },
             ext_info: Some(ExtendedInfo {
                 request_id: Some(f.trans_id as u32),
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -151,6 +220,8 @@ impl From<DnsInfo> for L7ProtocolSendLog {
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct DnsLog {
     info: DnsInfo,
+    // DNS over TCP 的一个报文中可能包含多个消息
+    infos: Vec<DnsInfo>,
     // 是否已经解析过,避免check后重复解析
     parsed: bool,
 }
@@ -166,12 +237,15 @@ impl L7ProtocolParserInterface for DnsLog {
     }
 
     fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
-        if self.parsed {
-            return Ok(vec![L7ProtocolInfo::DnsInfo(self.info.clone())]);
+        if !self.parsed {
+            parse_common!(self, param);
+            self.parse(payload, param.l4_protocol, param.direction, None, None)?;
         }
-        parse_common!(self, param);
-        self.parse(payload, param.l4_protocol, param.direction, None, None)?;
-        Ok(vec![L7ProtocolInfo::DnsInfo(self.info.clone())])
+        Ok(self
+            .infos
+            .iter()
+            .map(|i| L7ProtocolInfo::DnsInfo(i.clone()))
+            .collect())
     }
 
     fn protocol(&self) -> L7Protocol {
@@ -189,6 +263,10 @@ impl DnsLog {
         self.info.query_type = 0;
         self.info.query_name = String::new();
         self.info.answers = String::new();
+        self.info.answer_ttls.clear();
+        self.info.edns_udp_size = None;
+        self.info.edns_client_subnet = String::new();
+        self.info.dnssec_ok = false;
         self.info.status_code = None;
     }
 
@@ -309,80 +387,168 @@ impl DnsLog {
         }
 
         self.info.domain_type = read_u16_be(&payload[offset..]);
+        let ttl = read_u32_be(&payload[offset + RR_TTL_OFFSET..]);
         let data_length = read_u16_be(&payload[offset + RR_DATALENGTH_OFFSET..]) as usize;
         if data_length != 0 {
-            self.decode_rdata(payload, offset + RR_RDATA_OFFSET, data_length)?;
+            if let Some(answer) =
+                self.decode_rdata(payload, offset + RR_RDATA_OFFSET, data_length)?
+            {
+                if self.info.answers.len() > 0 {
+                    self.info.answers.push(DOMAIN_NAME_SPLIT);
+                }
+                self.info.answers.push_str(&answer);
+                self.info.answer_ttls.push(ttl);
+            }
         }
 
         Ok(offset + RR_RDATA_OFFSET + data_length)
     }
 
-    fn decode_rdata(&mut self, payload: &[u8], g_offset: usize, data_length: usize) -> Result<()> {
-        let answer_name_len = self.info.answers.len();
-        if answer_name_len > 0
-            && self.info.answers[answer_name_len - 1..] != DOMAIN_NAME_SPLIT.to_string()
-        {
-            self.info.answers.push(DOMAIN_NAME_SPLIT);
+    // additional section 中只解析 EDNS0 的 OPT 记录，其他记录直接跳过
+    fn decode_additional_record(&mut self, payload: &[u8], g_offset: usize) -> Result<usize> {
+        let (_, offset) = self.decode_name(payload, g_offset)?;
+
+        if payload.len() <= offset || payload[offset..].len() < RR_RDATA_OFFSET {
+            let err_msg = format!("additional record length error: {}", payload.len());
+            return Err(Error::DNSLogParseFailed(err_msg));
         }
 
-        match self.info.domain_type {
-            DNS_TYPE_A | DNS_TYPE_AAAA => match data_length {
-                IPV4_ADDR_LEN | IPV6_ADDR_LEN => {
-                    if let Some(ipaddr) = parse_ip_slice(&payload[g_offset..g_offset + data_length])
-                    {
-                        self.info.answers.push_str(&ipaddr.to_string());
-                    }
+        let data_length = read_u16_be(&payload[offset + RR_DATALENGTH_OFFSET..]) as usize;
+        let rdata_offset = offset + RR_RDATA_OFFSET;
+        if read_u16_be(&payload[offset..]) == DNS_TYPE_OPT {
+            // OPT 记录的 CLASS 字段为请求方能接收的 UDP 报文大小
+            self.info.edns_udp_size = Some(read_u16_be(&payload[offset + RR_CLASS_OFFSET..]));
+            self.info.dnssec_ok =
+                payload[offset + RR_TTL_OFFSET + EDNS_DO_OFFSET] & EDNS_DO_MASK != 0;
+            let end = payload.len().min(rdata_offset + data_length);
+            self.decode_edns_options(&payload[rdata_offset..end]);
+        }
+
+        Ok(rdata_offset + data_length)
+    }
+
+    /*
+     EDNS0 option
+     +-------------------+---------------------+-------------------+
+     | option-code(u16)  | option-length(u16)  | option-data       |
+     +-------------------+---------------------+-------------------+
+     client subnet option-data, 参考：https://www.rfc-editor.org/rfc/rfc7871
+     +--------------+-------------------------+------------------------+---------+
+     | family(u16)  | source-prefix-length(u8) | scope-prefix-length(u8) | address |
+     +--------------+-------------------------+------------------------+---------+
+    */
+    fn decode_edns_options(&mut self, options: &[u8]) {
+        let mut offset = 0;
+        while offset + EDNS_OPTION_HEADER_SIZE <= options.len() {
+            let code = read_u16_be(&options[offset..]);
+            let length = read_u16_be(&options[offset + 2..]) as usize;
+            let start = offset + EDNS_OPTION_HEADER_SIZE;
+            let data = &options[start..options.len().min(start + length)];
+            offset = start + length;
+
+            if code != EDNS_OPTION_CLIENT_SUBNET || data.len() < EDNS_CLIENT_SUBNET_HEADER_SIZE {
+                continue;
+            }
+            let source_prefix = data[2];
+            let address = &data[EDNS_CLIENT_SUBNET_HEADER_SIZE..];
+            let ip = match read_u16_be(data) {
+                EDNS_FAMILY_IPV4 => {
+                    let mut octets = [0u8; IPV4_ADDR_LEN];
+                    let n = address.len().min(IPV4_ADDR_LEN);
+                    octets[..n].copy_from_slice(&address[..n]);
+                    Ipv4Addr::from(octets).to_string()
                 }
-                _ => {
-                    let err_msg = format!(
-                        "domain type {} data length {} invalid",
-                        self.info.domain_type, data_length
-                    );
-                    return Err(Error::DNSLogParseFailed(err_msg));
+                EDNS_FAMILY_IPV6 => {
+                    let mut octets = [0u8; IPV6_ADDR_LEN];
+                    let n = address.len().min(IPV6_ADDR_LEN);
+                    octets[..n].copy_from_slice(&address[..n]);
+                    Ipv6Addr::from(octets).to_string()
+                }
+                _ => continue,
+            };
+            self.info.edns_client_subnet = format!("{}/{}", ip, source_prefix);
+        }
+    }
+
+    // 返回用于 answers 的字符串，不关心的记录类型返回 None
+    fn decode_rdata(
+        &self,
+        payload: &[u8],
+        g_offset: usize,
+        data_length: usize,
+    ) -> Result<Option<String>> {
+        let invalid_length = || {
+            let err_msg = format!(
+                "domain type {} data length {} invalid",
+                self.info.domain_type, data_length
+            );
+            Err(Error::DNSLogParseFailed(err_msg))
+        };
+
+        let answer = match self.info.domain_type {
+            DNS_TYPE_A | DNS_TYPE_AAAA => match data_length {
+                IPV4_ADDR_LEN | IPV6_ADDR_LEN if g_offset + data_length <= payload.len() => {
+                    parse_ip_slice(&payload[g_offset..g_offset + data_length])
+                        .map(|ipaddr| ipaddr.to_string())
                 }
+                _ => return invalid_length(),
             },
-            DNS_TYPE_NS | DNS_TYPE_DNAME | DNS_TYPE_SOA => {
+            DNS_TYPE_NS | DNS_TYPE_CNAME | DNS_TYPE_PTR | DNS_TYPE_DNAME | DNS_TYPE_SOA => {
                 if data_length > DNS_NAME_MAX_SIZE {
-                    let err_msg = format!(
-                        "domain type {} data length {} invalid",
-                        self.info.domain_type, data_length
-                    );
-                    return Err(Error::DNSLogParseFailed(err_msg));
+                    return invalid_length();
                 }
 
+                // SOA 只记录 primary name server
                 let (name, _) = self.decode_name(payload, g_offset)?;
-                self.info.answers.push_str(&name);
+                Some(name)
             }
-            DNS_TYPE_WKS => {
-                if data_length < DNS_TYPE_WKS_LENGTH {
-                    let err_msg = format!(
-                        "domain type {} data length {} invalid",
-                        self.info.domain_type, data_length
-                    );
-                    return Err(Error::DNSLogParseFailed(err_msg));
+            DNS_TYPE_MX => {
+                if data_length <= DNS_TYPE_MX_PREFERENCE_LENGTH
+                    || g_offset + DNS_TYPE_MX_PREFERENCE_LENGTH > payload.len()
+                {
+                    return invalid_length();
                 }
-                if let Some(ipaddr) = parse_ip_slice(&payload[g_offset..g_offset + data_length]) {
-                    self.info.answers.push_str(&ipaddr.to_string());
+                let preference = read_u16_be(&payload[g_offset..]);
+                let (name, _) =
+                    self.decode_name(payload, g_offset + DNS_TYPE_MX_PREFERENCE_LENGTH)?;
+                Some(format!("{} {}", preference, name))
+            }
+            DNS_TYPE_SRV => {
+                if data_length <= DNS_TYPE_SRV_FIXED_LENGTH
+                    || g_offset + DNS_TYPE_SRV_FIXED_LENGTH > payload.len()
+                {
+                    return invalid_length();
                 }
+                let priority = read_u16_be(&payload[g_offset..]);
+                let weight = read_u16_be(&payload[g_offset + 2..]);
+                let port = read_u16_be(&payload[g_offset + 4..]);
+                let (target, _) =
+                    self.decode_name(payload, g_offset + DNS_TYPE_SRV_FIXED_LENGTH)?;
+                Some(format!("{} {} {} {}", priority, weight, port, target))
             }
-            DNS_TYPE_PTR => {
-                if data_length != DNS_TYPE_PTR_LENGTH {
-                    let err_msg = format!(
-                        "domain type {} data length {} invalid",
-                        self.info.domain_type, data_length
-                    );
-                    return Err(Error::DNSLogParseFailed(err_msg));
+            DNS_TYPE_TXT => {
+                // 一个或多个 <length><character-string>，报文可能被截断
+                let data = &payload
+                    [g_offset.min(payload.len())..payload.len().min(g_offset + data_length)];
+                let mut texts = vec![];
+                let mut offset = 0;
+                while offset < data.len() {
+                    let end = data.len().min(offset + 1 + data[offset] as usize);
+                    texts.push(String::from_utf8_lossy(&data[offset + 1..end]));
+                    offset = end;
                 }
+                Some(texts.join(" "))
             }
-            _ => {
-                let err_msg = format!(
-                    "other domain type {} data length {} invalid",
-                    self.info.domain_type, data_length
-                );
-                return Err(Error::DNSLogParseFailed(err_msg));
+            DNS_TYPE_WKS => {
+                if data_length < DNS_TYPE_WKS_LENGTH || g_offset + data_length > payload.len() {
+                    return invalid_length();
+                }
+                parse_ip_slice(&payload[g_offset..g_offset + data_length])
+                    .map(|ipaddr| ipaddr.to_string())
             }
-        }
-        Ok(())
+            _ => None,
+        };
+        Ok(answer)
     }
 
     fn set_status(&mut self, status_code: u8) {
@@ -408,6 +574,7 @@ impl DnsLog {
         let qd_count = read_u16_be(&payload[DNS_HEADER_QDCOUNT_OFFSET..]);
         let an_count = read_u16_be(&payload[DNS_HEADER_ANCOUNT_OFFSET..]);
         let ns_count = read_u16_be(&payload[DNS_HEADER_NSCOUNT_OFFSET..]);
+        let ar_count = read_u16_be(&payload[DNS_HEADER_ARCOUNT_OFFSET..]);
 
         let mut g_offset = DNS_HEADER_SIZE;
 
@@ -427,11 +594,47 @@ impl DnsLog {
             }
 
             self.info.msg_type = LogMessageType::Response;
+        } else if an_count != 0 || ns_count != 0 {
+            return Ok(());
+        }
+
+        // additional section 可能因为截断而不完整，不影响前面已经解析的结果
+        for _i in 0..ar_count {
+            match self.decode_additional_record(payload, g_offset) {
+                Ok(offset) => g_offset = offset,
+                Err(_) => break,
+            }
         }
 
         Ok(())
     }
 
+    // DNS over TCP 的每个消息前有 2 字节的长度，一个报文中可能包含多个消息，最后一个消息可能被截断
+    fn parse_tcp(&mut self, payload: &[u8]) -> Result<()> {
+        let mut offset = 0;
+        while offset + DNS_TCP_PAYLOAD_OFFSET < payload.len() {
+            let size = read_u16_be(&payload[offset..]) as usize;
+            let start = offset + DNS_TCP_PAYLOAD_OFFSET;
+            let end = payload.len().min(start + size);
+
+            self.reset_logs();
+            if let Err(e) = self.decode_payload(&payload[start..end]) {
+                if self.infos.is_empty() {
+                    return Err(e);
+                }
+                break;
+            }
+            self.infos.push(self.info.clone());
+            offset = start + size;
+        }
+
+        if self.infos.is_empty() {
+            let err_msg = format!("dns payload length error:{}", payload.len());
+            return Err(Error::DNSLogParseFailed(err_msg));
+        }
+        Ok(())
+    }
+
     fn parse(
         &mut self,
         payload: &[u8],
@@ -440,21 +643,15 @@ impl DnsLog {
         _is_req_end: Option<bool>,
         _is_resp_end: Option<bool>,
     ) -> Result<()> {
-        self.reset_logs();
+        self.infos.clear();
         match proto {
-            IpProtocol::Udp => self.decode_payload(payload),
-            IpProtocol::Tcp => {
-                if payload.len() <= DNS_TCP_PAYLOAD_OFFSET {
-                    let err_msg = format!("dns payload length error:{}", payload.len());
-                    return Err(Error::DNSLogParseFailed(err_msg));
-                }
-                let size = read_u16_be(payload);
-                if (size as usize) < payload[DNS_TCP_PAYLOAD_OFFSET..].len() {
-                    let err_msg = format!("dns payload length error:{}", size);
-                    return Err(Error::DNSLogParseFailed(err_msg));
-                }
-                self.decode_payload(&payload[DNS_TCP_PAYLOAD_OFFSET..])
+            IpProtocol::Udp => {
+                self.reset_logs();
+                self.decode_payload(payload)?;
+                self.infos.push(self.info.clone());
+                Ok(())
             }
+            IpProtocol::Tcp => self.parse_tcp(payload),
             _ => {
                 let err_msg = format!("dns payload length error:{}", payload.len());
                 Err(Error::DNSLogParseFailed(err_msg))
@@ -534,4 +731,58 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn check_tcp_multi_messages() {
+        let capture =
+            Capture::load_pcap(Path::new(FILE_DIR).join("dns_tcp_multi.pcap"), Some(1500));
+        let packets = capture.as_meta_packets();
+        assert_eq!(packets.len(), 2);
+
+        let mut dns = DnsLog::default();
+        let payload = packets[0].get_l4_payload().unwrap();
+        dns.parse(
+            payload,
+            IpProtocol::Tcp,
+            PacketDirection::ClientToServer,
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(dns.infos.len(), 4);
+        let srv = &dns.infos[0];
+        assert_eq!(srv.query_name, "_http._tcp.example.com");
+        assert_eq!(srv.get_domain_str(), "SRV");
+        assert_eq!(srv.edns_udp_size, Some(1232));
+        assert_eq!(srv.edns_client_subnet, "192.168.1.0/24");
+        assert!(srv.dnssec_ok);
+        assert_eq!(dns.infos[3].get_domain_str(), "PTR");
+
+        let payload = packets[1].get_l4_payload().unwrap();
+        dns.parse(
+            payload,
+            IpProtocol::Tcp,
+            PacketDirection::ServerToClient,
+            None,
+            None,
+        )
+        .unwrap();
+        let answers = dns
+            .infos
+            .iter()
+            .map(|i| (i.trans_id, i.answers.as_str(), i.answer_ttls.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            answers,
+            vec![
+                (0x1001, "10 60 8080 web.example.com", vec![300]),
+                (0x1002, "10 mail.example.com", vec![3600]),
+                (0x1003, "v=spf1 -all hello", vec![60]),
+                (0x1004, "server.example.com", vec![120]),
+            ]
+        );
+        assert_eq!(dns.infos[0].edns_udp_size, Some(1232));
+        assert!(dns.infos[0].dnssec_ok);
+        assert_eq!(dns.infos[1].edns_udp_size, None);
+    }
 }
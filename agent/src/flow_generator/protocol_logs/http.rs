@@ -14,13 +14,15 @@
  * limitations under the License.
  */
 
-use std::str;
+use std::{borrow::Cow, fmt, mem, str};
 
 use arc_swap::access::Access;
 use log::debug;
 use serde::Serialize;
 
-use super::pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response, TraceInfo};
+use super::pb_adapter::{
+    ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response, TraceInfo,
+};
 use super::value_is_default;
 use super::LogMessageType;
 use super::{consts::*, AppProtoHead, L7ResponseStatus};
@@ -32,15 +34,64 @@ use crate::{
         flow::L7Protocol,
         flow::PacketDirection,
         l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
-        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
+        l7_protocol_log::{L7ProtocolParserInterface, MessageLength, ParseParam},
     },
     config::handler::{L7LogDynamicConfig, LogParserAccess, TraceType},
     flow_generator::error::{Error, Result},
     flow_generator::protocol_logs::L7ProtoRawDataType,
     parse_common,
-    utils::bytes::{read_u32_be, read_u32_le},
+    utils::bytes::{read_u16_be, read_u32_be, read_u32_le},
 };
 use public::utils::net::h2pack;
+
+// 传播头中trace id和span id之外的上下文
+// Context carried by propagation headers besides the trace id and span id
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+pub struct TraceContext {
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub parent_span_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sampled: Option<bool>,
+    #[serde(rename = "tracestate", skip_serializing_if = "value_is_default")]
+    pub trace_state: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub baggage: Vec<(String, String)>,
+}
+
+impl TraceContext {
+    pub fn merge(&mut self, other: Self) {
+        if self.parent_span_id.is_empty() {
+            self.parent_span_id = other.parent_span_id;
+        }
+        if self.sampled.is_none() {
+            self.sampled = other.sampled;
+        }
+        if self.trace_state.is_empty() {
+            self.trace_state = other.trace_state;
+        }
+        if self.baggage.is_empty() {
+            self.baggage = other.baggage;
+        }
+    }
+
+    pub fn into_trace_info(self, trace_id: String, span_id: String) -> TraceInfo {
+        TraceInfo {
+            trace_id: Some(trace_id),
+            span_id: Some(span_id),
+            parent_span_id: Some(self.parent_span_id),
+            sampled: self.sampled,
+            trace_state: Some(self.trace_state).filter(|s| !s.is_empty()),
+            baggage: Some(
+                self.baggage
+                    .into_iter()
+                    .map(|(key, val)| KeyVal { key, val })
+                    .collect::<Vec<_>>(),
+            )
+            .filter(|b| !b.is_empty()),
+        }
+    }
+}
+
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct HttpInfo {
     // 流是否结束，用于 http2 ebpf uprobe 处理.
@@ -73,6 +124,8 @@ pub struct HttpInfo {
     pub trace_id: String,
     #[serde(skip_serializing_if = "value_is_default")]
     pub span_id: String,
+    #[serde(flatten)]
+    pub trace_context: TraceContext,
 
     #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
     pub method: String,
@@ -98,6 +151,11 @@ pub struct HttpInfo {
     status_code: Option<i32>,
     #[serde(rename = "response_status")]
     status: L7ResponseStatus,
+
+    // 按配置额外采集并脱敏后的header和query参数
+    // extra headers and query parameters captured by the config, already redacted
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<(String, String)>,
 }
 
 impl L7ProtocolInfoInterface for HttpInfo {
@@ -218,6 +276,8 @@ impl HttpInfo {
         if self.span_id.is_empty() {
             self.span_id = other.span_id;
         }
+        self.trace_context.merge(other.trace_context);
+        self.attributes.extend(other.attributes);
         if self.x_request_id.is_empty() {
             self.x_request_id = other.x_request_id.clone();
         }
@@ -325,6 +385,17 @@ impl From<HttpInfo> for L7ProtocolSendLog {
             (f.method, f.path, f.host, String::new())
         };
 
+        let attributes = if f.attributes.is_empty() {
+            None
+        } else {
+            Some(
+                f.attributes
+                    .into_iter()
+                    .map(|(key, val)| KeyVal { key, val })
+                    .collect(),
+            )
+        };
+
         L7ProtocolSendLog {
             req_len: f.req_content_length,
             resp_len: f.resp_content_length,
@@ -340,11 +411,7 @@ impl From<HttpInfo> for L7ProtocolSendLog {
                 code: f.status_code,
                 ..Default::default()
             },
-            trace_info: Some(TraceInfo {
-                trace_id: Some(f.trace_id),
-                span_id: Some(f.span_id),
-                ..Default::default()
-            }),
+            trace_info: Some(f.trace_context.into_trace_info(f.trace_id, f.span_id)),
             ext_info: Some(ExtendedInfo {
                 request_id: f.stream_id,
                 x_request_id: Some(f.x_request_id),
@@ -352,6 +419,7 @@ impl From<HttpInfo> for L7ProtocolSendLog {
                 user_agent: f.user_agent,
                 referer: f.referer,
                 rpc_service: service_name,
+                attributes,
                 ..Default::default()
             }),
             ..Default::default()
@@ -359,6 +427,182 @@ impl From<HttpInfo> for L7ProtocolSendLog {
     }
 }
 
+// HTTP/2头部块的解码结果
+// decoding result of an HTTP/2 header block
+pub type Http2HeaderBlock = std::result::Result<Vec<(Vec<u8>, Vec<u8>)>, h2pack::ParseError>;
+
+// HTTP/2连接每个方向的HPACK解码上下文，跨报文保留动态表
+// HPACK decoding context of each direction of an HTTP/2 connection, keeps the dynamic
+// tables across packets
+#[derive(Default)]
+pub struct Http2HpackContext {
+    parsers: [Option<h2pack::parser::Parser<'static>>; 2],
+    // 对端SETTINGS帧通告的SETTINGS_HEADER_TABLE_SIZE
+    // SETTINGS_HEADER_TABLE_SIZE advertised by the SETTINGS frame of the peer
+    header_table_sizes: [Option<u32>; 2],
+}
+
+impl Http2HpackContext {
+    fn parser(&mut self, direction: PacketDirection) -> &mut h2pack::parser::Parser<'static> {
+        self.parsers[direction as usize].get_or_insert_with(|| {
+            h2pack::parser::Parser::with_dynamic_table(HTTPV2_HPACK_DYNAMIC_TABLE_LIMIT)
+        })
+    }
+
+    // 动态表无法与编码端保持一致时清空，之后只使用新插入的表项
+    // clears the dynamic table when it can not be kept in sync with the encoder, only entries
+    // inserted afterwards are used
+    fn reset(&mut self, direction: PacketDirection) {
+        if let Some(parser) = self.parsers[direction as usize].as_mut() {
+            parser.reset_dynamic_table(parser.dynamic_table_size());
+        }
+    }
+
+    // SETTINGS帧由解码端发送，限制的是对端方向编码使用的动态表
+    // SETTINGS frame is sent by the decoder and limits the dynamic table used by the encoder
+    // of the opposite direction
+    fn set_header_table_size(&mut self, direction: PacketDirection, size: u32) {
+        let index = direction as usize;
+        if self.header_table_sizes[index] == Some(size) {
+            return;
+        }
+        self.header_table_sizes[index] = Some(size);
+        self.parser(direction.reversed())
+            .reset_dynamic_table(size as usize);
+    }
+
+    // 按顺序解码报文中所有头部块并处理SETTINGS帧，使HPACK动态表与编码端保持同步，按出现顺序返回各HEADERS帧的stream id和头部
+    // 头部块跨报文或被截断时无法解码，清空动态表
+    // ======================================================================================================
+    // decodes all header blocks in the packet in order and handles SETTINGS frames to keep the
+    // HPACK dynamic table in sync with the encoder, returns the stream id and headers of every
+    // HEADERS frame in order. Header blocks across packets or truncated can not be decoded, and
+    // the dynamic table is cleared
+    pub fn decode_header_blocks(
+        &mut self,
+        payload: &[u8],
+        direction: PacketDirection,
+    ) -> Vec<(u32, Http2HeaderBlock)> {
+        let mut headers = vec![];
+        // 等待CONTINUATION帧的头部块及其是否来自HEADERS帧
+        // header block waiting for CONTINUATION frames and whether it comes from a HEADERS frame
+        let mut pending_block: Option<(Vec<u8>, bool)> = None;
+        let mut frame_payload = payload;
+        let mut httpv2_header = Httpv2Headers::default();
+
+        while frame_payload.len() > HTTPV2_FRAME_HEADER_LENGTH {
+            if HttpLog::has_magic(frame_payload) {
+                frame_payload = &frame_payload[HTTPV2_MAGIC_LENGTH..];
+                continue;
+            }
+            if httpv2_header.parse_headers_frame(frame_payload).is_err() {
+                break;
+            }
+            frame_payload = &frame_payload[HTTPV2_FRAME_HEADER_LENGTH..];
+            let frame_length = httpv2_header.frame_length as usize;
+            let is_header_block_frame = matches!(
+                httpv2_header.frame_type,
+                HTTPV2_FRAME_HEADERS_TYPE
+                    | HTTPV2_FRAME_PUSH_PROMISE_TYPE
+                    | HTTPV2_FRAME_CONTINUATION_TYPE
+            );
+            if frame_length > frame_payload.len() {
+                if is_header_block_frame || pending_block.is_some() {
+                    self.reset(direction);
+                }
+                return headers;
+            }
+            let frame_body = &frame_payload[..frame_length];
+            frame_payload = &frame_payload[frame_length..];
+
+            if httpv2_header.frame_type == HTTPV2_FRAME_SETTINGS_TYPE {
+                if httpv2_header.flags & FLAG_SETTINGS_ACK == 0 {
+                    for param in frame_body.chunks_exact(HTTPV2_SETTINGS_PARAMETER_LENGTH) {
+                        if read_u16_be(param) == HTTPV2_SETTINGS_HEADER_TABLE_SIZE {
+                            self.set_header_table_size(direction, read_u32_be(&param[2..]));
+                        }
+                    }
+                }
+                continue;
+            }
+            if !is_header_block_frame {
+                continue;
+            }
+
+            let (block, is_headers) = match (pending_block.take(), httpv2_header.frame_type) {
+                (Some((mut block, is_headers)), HTTPV2_FRAME_CONTINUATION_TYPE) => {
+                    block.extend_from_slice(frame_body);
+                    (block, is_headers)
+                }
+                (None, HTTPV2_FRAME_HEADERS_TYPE | HTTPV2_FRAME_PUSH_PROMISE_TYPE) => {
+                    match HttpLog::http2_header_block_fragment(&httpv2_header, frame_body) {
+                        Some(fragment) => (
+                            fragment.to_vec(),
+                            httpv2_header.frame_type == HTTPV2_FRAME_HEADERS_TYPE,
+                        ),
+                        None => {
+                            self.reset(direction);
+                            continue;
+                        }
+                    }
+                }
+                _ => {
+                    // 头部块的帧序列不完整
+                    // frame sequence of the header block is incomplete
+                    self.reset(direction);
+                    continue;
+                }
+            };
+            if httpv2_header.flags & FLAG_HEADERS_END_HEADERS == 0 {
+                pending_block = Some((block, is_headers));
+                continue;
+            }
+            let result = self.parser(direction).parse(&block);
+            if is_headers {
+                headers.push((httpv2_header.stream_id, result));
+            }
+        }
+        if pending_block.is_some() {
+            self.reset(direction);
+        }
+        headers
+    }
+}
+
+// 动态表只能随流逐个报文建立，复制出的解析器从空的动态表开始
+// dynamic tables are built packet by packet along the flow, a cloned parser starts with empty tables
+// HPACK解码器无法复制，只允许在尚未解码任何头部块的上下文上调用clone
+// the HPACK decoder can not be copied, clone is only allowed on a context which has not
+// decoded any header block yet
+impl Clone for Http2HpackContext {
+    fn clone(&self) -> Self {
+        debug_assert!(
+            self.parsers.iter().all(Option::is_none),
+            "clone of Http2HpackContext with dynamic tables"
+        );
+        Self {
+            parsers: Default::default(),
+            header_table_sizes: self.header_table_sizes,
+        }
+    }
+}
+
+impl fmt::Debug for Http2HpackContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Http2HpackContext")
+            .field(
+                "dynamic_table_sizes",
+                &self
+                    .parsers
+                    .iter()
+                    .map(|p| p.as_ref().map(|p| p.dynamic_table_size()))
+                    .collect::<Vec<_>>(),
+            )
+            .field("header_table_sizes", &self.header_table_sizes)
+            .finish()
+    }
+}
+
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct HttpLog {
     info: HttpInfo,
@@ -368,6 +612,10 @@ pub struct HttpLog {
     #[serde(skip)]
     l7_log_dynamic_config: L7LogDynamicConfig,
     proto: L7Protocol,
+    // reset时保留
+    // kept across reset
+    #[serde(skip)]
+    hpack: Http2HpackContext,
 }
 
 impl L7ProtocolParserInterface for HttpLog {
@@ -465,9 +713,17 @@ impl L7ProtocolParserInterface for HttpLog {
         false
     }
 
+    fn message_length(&self, payload: &[u8], _: &ParseParam) -> MessageLength {
+        match self.proto {
+            L7Protocol::Http1 => http1_message_length(payload),
+            _ => MessageLength::Unknown,
+        }
+    }
+
     fn reset(&mut self) {
         self.info = HttpInfo::default();
         let conf = self.l7_log_dynamic_config.clone();
+        let hpack = mem::take(&mut self.hpack);
         match self.proto {
             L7Protocol::Http1 => *self = Self::new_v1(),
             L7Protocol::Http2 => *self = Self::new_v2(false),
@@ -475,6 +731,7 @@ impl L7ProtocolParserInterface for HttpLog {
             _ => unreachable!(),
         }
         self.l7_log_dynamic_config = conf;
+        self.hpack = hpack;
     }
 }
 
@@ -500,8 +757,8 @@ fn parse_lines(payload: &[u8], limit: usize) -> Vec<&[u8]> {
 }
 
 impl HttpLog {
-    const TRACE_ID: u8 = 0;
-    const SPAN_ID: u8 = 1;
+    pub(crate) const TRACE_ID: u8 = 0;
+    pub(crate) const SPAN_ID: u8 = 1;
 
     pub fn new(config: &LogParserAccess) -> Self {
         Self {
@@ -563,9 +820,13 @@ impl HttpLog {
         if param.l4_protocol != IpProtocol::Tcp {
             return false;
         }
-        self.parsed = self
-            .parse_http_v2(payload, PacketDirection::ClientToServer)
-            .is_ok();
+        // HPACK动态表按方向维护，必须按报文实际方向解析
+        // HPACK dynamic tables are per direction, parse with the actual direction of the packet
+        self.parsed = self.parse_http_v2(payload, param.direction).is_ok();
+        // gRPC 由 GrpcLog 解析
+        if self.parsed && self.info.is_grpc() {
+            self.parsed = false;
+        }
         self.parsed
     }
 
@@ -642,6 +903,9 @@ impl HttpLog {
         let key = Vec::from(&payload[HTTPV2_CUSTOM_DATA_MIN_LENGTH..val_offset]);
         let val = Vec::from(&payload[val_offset..val_offset + val_len]);
         self.on_header(&key, &val, direction);
+        if key.as_slice() == b":path" {
+            self.capture_url();
+        }
         if key.as_slice() == b"content-length" {
             self.info.req_content_length = Some(
                 str::from_utf8(val.as_slice())
@@ -719,6 +983,7 @@ impl HttpLog {
         } else {
             self.info.req_content_length = content_length;
         }
+        self.capture_url();
         Ok(())
     }
 
@@ -733,12 +998,42 @@ impl HttpLog {
         }
     }
 
+    // 返回头部块片段，HEADERS帧去掉填充和优先级，PUSH_PROMISE帧去掉填充和Promised Stream ID
+    // returns the header block fragment, without padding and priority of HEADERS frame, or
+    // padding and promised stream id of PUSH_PROMISE frame
+    fn http2_header_block_fragment<'a>(
+        httpv2_header: &Httpv2Headers,
+        frame_payload: &'a [u8],
+    ) -> Option<&'a [u8]> {
+        let mut start = 0;
+        let mut end = frame_payload.len();
+        if httpv2_header.flags & FLAG_HEADERS_PADDED != 0 {
+            end = end.checked_sub(*frame_payload.first()? as usize)?;
+            start += 1;
+        }
+        if httpv2_header.frame_type == HTTPV2_FRAME_PUSH_PROMISE_TYPE {
+            start += 4;
+        } else if httpv2_header.flags & FLAG_HEADERS_PRIORITY != 0 {
+            start += 5;
+        }
+        if start >= end {
+            return None;
+        }
+        Some(&frame_payload[start..end])
+    }
+
     fn parse_http_v2(&mut self, payload: &[u8], direction: PacketDirection) -> Result<()> {
         let mut content_length: Option<u32> = None;
         let mut header_frame_parsed = false;
         let mut is_httpv2 = false;
         let mut frame_payload = payload;
         let mut httpv2_header = Httpv2Headers::default();
+        let mut first_headers = self
+            .hpack
+            .decode_header_blocks(payload, direction)
+            .into_iter()
+            .next()
+            .map(|(_, headers)| headers);
 
         while frame_payload.len() > HTTPV2_FRAME_HEADER_LENGTH {
             if Self::has_magic(frame_payload) {
@@ -783,8 +1078,11 @@ impl HttpLog {
                 let header_frame_payload =
                     &frame_payload[l_offset as usize..httpv2_header.frame_length as usize];
 
-                let mut parser = h2pack::parser::Parser::new();
-                let parse_rst = parser.parse(header_frame_payload);
+                // 头部块未能完整解码时退回到只解析当前帧
+                // fallback to parse the current frame only if the header block is not decoded
+                let parse_rst = first_headers
+                    .take()
+                    .unwrap_or_else(|| h2pack::parser::Parser::new().parse(header_frame_payload));
 
                 if let Err(_) = parse_rst {
                     return Err(Error::HttpHeaderParseFailed);
@@ -846,6 +1144,7 @@ impl HttpLog {
                     return Err(Error::HttpHeaderParseFailed);
                 }
                 self.info.req_content_length = content_length;
+                self.capture_url();
             } else {
                 if let Some(code) = self.info.status_code {
                     let code = code as u16;
@@ -925,6 +1224,12 @@ impl HttpLog {
                 self.info.span_id = id;
             }
         }
+        Self::decode_trace_context(
+            &self.l7_log_dynamic_config,
+            key_str,
+            &String::from_utf8_lossy(val.as_ref()),
+            &mut self.info.trace_context,
+        );
         if !self.l7_log_dynamic_config.x_request_id_origin.is_empty()
             && key_bytes == self.l7_log_dynamic_config.x_request_id_lower.as_bytes()
         {
@@ -936,6 +1241,43 @@ impl HttpLog {
         {
             self.info.client_ip = String::from_utf8_lossy(val.as_ref()).into_owned();
         }
+
+        let capture = &self.l7_log_dynamic_config.http_capture;
+        let (names, prefix) = if direction == PacketDirection::ClientToServer {
+            (&capture.request_headers, HTTP_REQUEST_HEADER_ATTR_PREFIX)
+        } else {
+            (&capture.response_headers, HTTP_RESPONSE_HEADER_ATTR_PREFIX)
+        };
+        if names.iter().any(|name| name == key_str) {
+            let val = String::from_utf8_lossy(val.as_ref());
+            self.info.attributes.push((
+                format!("{}{}", prefix, key_str),
+                capture.redact(key_str, &val).into_owned(),
+            ));
+        }
+    }
+
+    // 采集配置的query参数并对URL脱敏，在请求头解析完成后调用，脱敏后原始URL不再保留
+    // Captures the configured query parameters and redacts the URL, called after the
+    // request headers are parsed, the original URL is not kept after redaction
+    fn capture_url(&mut self) {
+        if self.info.msg_type != LogMessageType::Request || self.info.path.is_empty() {
+            return;
+        }
+        let capture = &self.l7_log_dynamic_config.http_capture;
+        for (name, value) in capture.capture_query(&self.info.path) {
+            self.info.attributes.push((
+                format!("{}{}", HTTP_REQUEST_QUERY_ATTR_PREFIX, name),
+                value.into_owned(),
+            ));
+        }
+        let path = match capture.redact_url(&self.info.path) {
+            Cow::Owned(path) => Some(path),
+            Cow::Borrowed(_) => None,
+        };
+        if let Some(path) = path {
+            self.info.path = path;
+        }
     }
 
     // uber-trace-id: TRACEID:SPANID:PARENTSPANID:FLAGS
@@ -1007,7 +1349,141 @@ impl HttpLog {
         None
     }
 
-    fn decode_id(payload: &str, trace_type: &str, id_type: u8) -> Option<String> {
+    // b3: TRACEID-SPANID-SAMPLED-PARENTSPANID，仅有采样标记时为b3: SAMPLED
+    // b3: TRACEID-SPANID-SAMPLED-PARENTSPANID, or b3: SAMPLED when only sampling is propagated
+    fn decode_b3(value: &str, id_type: u8) -> Option<String> {
+        let mut segs = value.trim().split('-');
+        let trace_id = segs.next()?;
+        let span_id = segs.next()?;
+        match id_type {
+            Self::TRACE_ID => Some(trace_id.to_string()),
+            Self::SPAN_ID => Some(span_id.to_string()),
+            _ => None,
+        }
+    }
+
+    // 1/true为采样，0/false为不采样，d表示debug，也是采样
+    // 1 or true means sampled, 0 or false means not sampled, and d means debug, which is sampled
+    fn decode_b3_sampled(value: &str) -> Option<bool> {
+        match value.trim() {
+            "1" | "d" | "true" => Some(true),
+            "0" | "false" => Some(false),
+            _ => None,
+        }
+    }
+
+    fn decode_b3_context(value: &str, context: &mut TraceContext) {
+        let segs: Vec<&str> = value.trim().split('-').collect();
+        if segs.len() == 1 {
+            context.sampled = Self::decode_b3_sampled(segs[0]).or(context.sampled);
+            return;
+        }
+        if let Some(sampled) = segs.get(2) {
+            context.sampled = Self::decode_b3_sampled(sampled).or(context.sampled);
+        }
+        if let Some(parent_span_id) = segs.get(3) {
+            context.parent_span_id = parent_span_id.to_string();
+        }
+    }
+
+    // baggage: KEY1=VALUE1;PROPERTY,KEY2=VALUE2，仅保留配置的key
+    // baggage: KEY1=VALUE1;PROPERTY,KEY2=VALUE2, only the configured keys are kept
+    fn decode_baggage(value: &str, keys: &[String], baggage: &mut Vec<(String, String)>) {
+        for member in value.split(',') {
+            let member = member.split(';').next().unwrap_or_default();
+            let (key, val) = match member.split_once('=') {
+                Some((key, val)) => (key.trim(), val.trim()),
+                None => continue,
+            };
+            if !keys.iter().any(|k| k == key) || baggage.iter().any(|(k, _)| k == key) {
+                continue;
+            }
+            baggage.push((key.to_string(), val.to_string()));
+        }
+    }
+
+    // 传播上下文的key(需为小写)所属的格式是否开启
+    // whether the propagation format of the context key is enabled, the key must be in lowercase
+    pub(crate) fn is_trace_context_enabled(config: &L7LogDynamicConfig, key: &str) -> bool {
+        match key {
+            "traceparent" | "tracestate" => config.has_trace_type(&TraceType::TraceParent),
+            "b3" => config.has_trace_type(&TraceType::B3),
+            "x-b3-parentspanid" | "x-b3-sampled" | "x-b3-flags" => {
+                config.has_trace_type(&TraceType::XB3) || config.has_trace_type(&TraceType::XB3Span)
+            }
+            "uber-trace-id" => config.has_trace_type(&TraceType::Uber),
+            "sw6" | "sw8" => config.has_trace_type(&TraceType::from(key)),
+            "baggage" => !config.baggage_keys.is_empty(),
+            _ => false,
+        }
+    }
+
+    // 提取已开启的传播格式中的parent span id、采样标记、tracestate和选定的baggage，key需为小写
+    // Extracts the parent span id, sampling flag, tracestate and selected baggage of the
+    // enabled propagation formats, the key must be in lowercase
+    pub(crate) fn decode_trace_context(
+        config: &L7LogDynamicConfig,
+        key: &str,
+        value: &str,
+        context: &mut TraceContext,
+    ) {
+        if !Self::is_trace_context_enabled(config, key) {
+            return;
+        }
+        match key {
+            // traceparent: 00-TRACEID-SPANID-FLAGS，FLAGS的最低位为采样标记
+            // traceparent: 00-TRACEID-SPANID-FLAGS, the lowest bit of FLAGS is the sampling flag
+            "traceparent" => {
+                if let Some(Ok(flags)) = value
+                    .trim()
+                    .split('-')
+                    .nth(3)
+                    .map(|f| u8::from_str_radix(f, 16))
+                {
+                    context.sampled = Some(flags & 0x1 != 0);
+                }
+            }
+            "tracestate" => {
+                context.trace_state = value.trim().to_string();
+            }
+            "b3" => Self::decode_b3_context(value, context),
+            "x-b3-parentspanid" => {
+                context.parent_span_id = value.trim().to_string();
+            }
+            "x-b3-sampled" => {
+                context.sampled = Self::decode_b3_sampled(value).or(context.sampled);
+            }
+            "x-b3-flags" => {
+                if value.trim() == "1" {
+                    context.sampled = Some(true);
+                }
+            }
+            // uber-trace-id: TRACEID:SPANID:PARENTSPANID:FLAGS，FLAGS的最低位为采样标记
+            // uber-trace-id: TRACEID:SPANID:PARENTSPANID:FLAGS, the lowest bit of FLAGS is the
+            // sampling flag
+            "uber-trace-id" => {
+                if let Some(Ok(flags)) = value
+                    .trim()
+                    .split(':')
+                    .nth(3)
+                    .map(|f| u8::from_str_radix(f, 16))
+                {
+                    context.sampled = Some(flags & 0x1 != 0);
+                }
+            }
+            // sw6/sw8的第一个字段为SAMPLE，取值为0或1
+            // the first field of sw6/sw8 is SAMPLE, which is 0 or 1
+            "sw6" | "sw8" => match value.trim().split('-').next() {
+                Some("1") => context.sampled = Some(true),
+                Some("0") => context.sampled = Some(false),
+                _ => (),
+            },
+            "baggage" => Self::decode_baggage(value, &config.baggage_keys, &mut context.baggage),
+            _ => (),
+        }
+    }
+
+    pub(crate) fn decode_id(payload: &str, trace_type: &str, id_type: u8) -> Option<String> {
         let trace_type = TraceType::from(trace_type);
         match trace_type {
             TraceType::Disabled | TraceType::XB3 | TraceType::XB3Span | TraceType::Customize(_) => {
@@ -1016,6 +1492,7 @@ impl HttpLog {
             TraceType::Uber => Self::decode_uber_id(payload, id_type),
             TraceType::Sw6 | TraceType::Sw8 => Self::decode_skywalking_id(payload, id_type),
             TraceType::TraceParent => Self::decode_traceparent(payload, id_type),
+            TraceType::B3 => Self::decode_b3(payload, id_type),
         }
     }
 
@@ -1119,6 +1596,101 @@ pub fn is_http_v1_payload(buf: &[u8]) -> bool {
     false
 }
 
+fn find_bytes(s: &[u8], pattern: &[u8]) -> Option<usize> {
+    s.windows(pattern.len()).position(|w| w == pattern)
+}
+
+// HTTP/1消息由头部和Content-Length或chunked编码确定长度的消息体组成
+// =====================================================================
+// a HTTP/1 message consists of headers and a body whose length is decided by
+// Content-Length or chunked encoding
+fn http1_message_length(payload: &[u8]) -> MessageLength {
+    if !is_http_v1_payload(payload) {
+        let is_prefix = HTTP_METHODS
+            .iter()
+            .chain([RESPONSE_PREFIX].iter())
+            .any(|m| has_prefix(m.as_bytes(), payload));
+        return if is_prefix {
+            MessageLength::Partial
+        } else {
+            MessageLength::Unknown
+        };
+    }
+    let header_len = match find_bytes(payload, b"\r\n\r\n") {
+        Some(i) => i + 4,
+        None => return MessageLength::Partial,
+    };
+
+    let mut lines = payload[..header_len - 4].split(|c| *c == b'\n');
+    let start_line = lines.next().unwrap_or_default();
+    // 1xx、204、304响应没有消息体
+    // 1xx, 204 and 304 responses have no body
+    if has_prefix(start_line, RESPONSE_PREFIX.as_bytes()) {
+        match start_line.get(HTTP_V1_VERSION_LEN + 1..HTTP_V1_VERSION_LEN + 4) {
+            Some([b'1', _, _]) | Some(b"204") | Some(b"304") => {
+                return MessageLength::Complete(header_len)
+            }
+            _ => (),
+        }
+    }
+    let mut content_length = 0;
+    for line in lines {
+        let col_index = match line.iter().position(|x| *x == b':') {
+            Some(i) => i,
+            None => continue,
+        };
+        let (key, value) = (&line[..col_index], &line[col_index + 1..]);
+        let value = str::from_utf8(value).unwrap_or_default().trim();
+        if key.eq_ignore_ascii_case(b"content-length") {
+            content_length = value.parse::<usize>().unwrap_or_default();
+        } else if key.eq_ignore_ascii_case(b"transfer-encoding")
+            && value.to_ascii_lowercase().contains("chunked")
+        {
+            return match chunked_body_length(&payload[header_len..]) {
+                MessageLength::Complete(n) => MessageLength::Complete(header_len + n),
+                MessageLength::Partial => MessageLength::Partial,
+                // 无法解析消息体时只取头部
+                // only take the headers if the body can not be parsed
+                MessageLength::Unknown => MessageLength::Complete(header_len),
+            };
+        }
+    }
+    MessageLength::Complete(header_len + content_length)
+}
+
+fn chunked_body_length(body: &[u8]) -> MessageLength {
+    let mut offset = 0;
+    loop {
+        let line_end = match find_bytes(&body[offset..], b"\r\n") {
+            Some(i) => offset + i,
+            None => return MessageLength::Partial,
+        };
+        let size = str::from_utf8(&body[offset..line_end])
+            .ok()
+            .and_then(|s| usize::from_str_radix(s.split(';').next()?.trim(), 16).ok());
+        offset = line_end + 2;
+        match size {
+            // 最后一个chunk之后是可选的trailer，以空行结束
+            // the last chunk is followed by optional trailers ending with an empty line
+            Some(0) if has_prefix(&body[offset..], b"\r\n") => {
+                return MessageLength::Complete(offset + 2)
+            }
+            Some(0) => {
+                return match find_bytes(&body[offset..], b"\r\n\r\n") {
+                    Some(i) => MessageLength::Complete(offset + i + 4),
+                    None => MessageLength::Partial,
+                }
+            }
+            Some(size) => match size.checked_add(2).and_then(|n| offset.checked_add(n)) {
+                Some(end) if end <= body.len() => offset = end,
+                Some(_) => return MessageLength::Partial,
+                None => return MessageLength::Unknown,
+            },
+            None => return MessageLength::Unknown,
+        }
+    }
+}
+
 // check first line is http request line
 pub fn is_http_req_line(line: String) -> bool {
     if line.len() < 14 {
@@ -1204,9 +1776,12 @@ pub fn get_http_resp_info(line_info: &str) -> Result<(String, u16)> {
 #[cfg(test)]
 mod tests {
     use crate::common::MetaPacket;
+    use crate::config::{handler::HttpCaptureRules, HttpLogCaptureConfig};
+    use crate::proto::flow_log;
     use crate::utils::test::Capture;
     use std::fs;
     use std::mem::size_of;
+    use std::net::{IpAddr, Ipv4Addr};
     use std::path::Path;
     use std::slice::from_raw_parts;
 
@@ -1244,6 +1819,7 @@ mod tests {
                 x_request_id_with_colon: "".to_string(),
                 trace_types: vec![TraceType::Sw8],
                 span_types: vec![TraceType::Sw8],
+                baggage_keys: vec![],
             };
             let _ = http.parse(
                 payload,
@@ -1356,4 +1932,307 @@ mod tests {
             println!("{:#?}", h);
         }
     }
+
+    #[test]
+    fn check_trace_context() {
+        let mut http = HttpLog::new_v1();
+        http.l7_log_dynamic_config = L7LogDynamicConfig {
+            trace_types: vec![TraceType::TraceParent, TraceType::B3, TraceType::XB3],
+            span_types: vec![TraceType::TraceParent, TraceType::B3, TraceType::XB3Span],
+            baggage_keys: vec!["tenant".to_string(), "region".to_string()],
+            ..Default::default()
+        };
+        let payload = b"GET /api HTTP/1.1\r\n\
+            traceparent: 00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01\r\n\
+            tracestate: congo=t61rcWkgMzE\r\n\
+            baggage: tenant=acme;ttl=60, user=alice,region=eu-west\r\n\r\n";
+        http.parse_http_v1(payload, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(http.info.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(http.info.span_id, "00f067aa0ba902b7");
+        assert_eq!(
+            http.info.trace_context,
+            TraceContext {
+                parent_span_id: "".to_string(),
+                sampled: Some(true),
+                trace_state: "congo=t61rcWkgMzE".to_string(),
+                baggage: vec![
+                    ("tenant".to_string(), "acme".to_string()),
+                    ("region".to_string(), "eu-west".to_string()),
+                ],
+            }
+        );
+
+        http.reset_logs();
+        let payload = b"GET /api HTTP/1.1\r\n\
+            b3: 80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-d-05e3ac9a4f6e3b90\r\n\r\n";
+        http.parse_http_v1(payload, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(http.info.trace_id, "80f198ee56343ba864fe8b2a57d3eff7");
+        assert_eq!(http.info.span_id, "e457b5a2e4d86bd1");
+        assert_eq!(http.info.trace_context.parent_span_id, "05e3ac9a4f6e3b90");
+        assert_eq!(http.info.trace_context.sampled, Some(true));
+
+        http.reset_logs();
+        let payload = b"GET /api HTTP/1.1\r\n\
+            X-B3-TraceId: 463ac35c9f6413ad\r\n\
+            X-B3-SpanId: a2fb4a1d1a96d312\r\n\
+            X-B3-ParentSpanId: 0020000000000001\r\n\
+            X-B3-Sampled: 0\r\n\r\n";
+        http.parse_http_v1(payload, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(http.info.trace_id, "463ac35c9f6413ad");
+        assert_eq!(http.info.span_id, "a2fb4a1d1a96d312");
+        assert_eq!(http.info.trace_context.parent_span_id, "0020000000000001");
+        assert_eq!(http.info.trace_context.sampled, Some(false));
+
+        let info = http.info.clone();
+        let mut log = flow_log::AppProtoLogsData::default();
+        L7ProtocolSendLog::from(info).fill_app_proto_log(&mut log);
+        assert_eq!(
+            log.trace_info.unwrap().parent_span_id,
+            "0020000000000001".to_string()
+        );
+        let ext_info = log.ext_info.unwrap();
+        assert_eq!(ext_info.attribute_names, vec!["trace_sampled".to_string()]);
+        assert_eq!(ext_info.attribute_values, vec!["false".to_string()]);
+    }
+
+    #[test]
+    fn check_http_capture() {
+        let mut http = HttpLog::new_v1();
+        http.l7_log_dynamic_config.http_capture = HttpCaptureRules::from(&HttpLogCaptureConfig {
+            request_headers: vec!["authorization".to_string(), "x-tenant".to_string()],
+            response_headers: vec!["x-cache".to_string()],
+            query_parameters: vec!["q".to_string(), "token".to_string()],
+            redact_names: vec!["authorization".to_string(), "token".to_string()],
+            redact_patterns: vec![r"\b\d{13,19}\b".to_string()],
+        });
+
+        let payload = b"GET /cards/4111111111111111/charge?q=shoes&token=secret HTTP/1.1\r\n\
+            Authorization: Bearer abc\r\n\
+            X-Tenant: acme\r\n\r\n";
+        http.parse_http_v1(payload, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(http.info.path, "/cards/***/charge?q=shoes&token=***");
+        assert_eq!(
+            http.info.attributes,
+            vec![
+                (
+                    "http.request.header.authorization".to_string(),
+                    "***".to_string()
+                ),
+                (
+                    "http.request.header.x-tenant".to_string(),
+                    "acme".to_string()
+                ),
+                ("http.request.query.q".to_string(), "shoes".to_string()),
+                ("http.request.query.token".to_string(), "***".to_string()),
+            ]
+        );
+
+        let request = http.info.clone();
+        http.reset_logs();
+        let payload = b"HTTP/1.1 200 OK\r\nX-Cache: HIT\r\nSet-Cookie: a=b\r\n\r\n";
+        http.parse_http_v1(payload, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(
+            http.info.attributes,
+            vec![(
+                "http.response.header.x-cache".to_string(),
+                "HIT".to_string()
+            )]
+        );
+
+        let mut response = http.info.clone();
+        response.merge(request).unwrap();
+        let mut log = flow_log::AppProtoLogsData::default();
+        L7ProtocolSendLog::from(response).fill_app_proto_log(&mut log);
+        let ext_info = log.ext_info.unwrap();
+        assert_eq!(ext_info.attribute_names.len(), 5);
+        assert!(!ext_info
+            .attribute_values
+            .iter()
+            .any(|v| v.contains("secret")));
+    }
+
+    #[test]
+    fn check_message_length() {
+        let req = b"POST /a HTTP/1.1\r\nHost: a.com\r\ncontent-length: 4\r\n\r\nbody";
+        assert_eq!(http1_message_length(b"PO"), MessageLength::Partial);
+        assert_eq!(http1_message_length(&req[..30]), MessageLength::Partial);
+        assert_eq!(
+            http1_message_length(&req[..req.len() - 1]),
+            MessageLength::Complete(req.len())
+        );
+        assert_eq!(
+            http1_message_length(b"\x16\x03\x01"),
+            MessageLength::Unknown
+        );
+
+        let resp = b"HTTP/1.1 304 Not Modified\r\nContent-Length: 10\r\n\r\n";
+        assert_eq!(
+            http1_message_length(resp),
+            MessageLength::Complete(resp.len())
+        );
+
+        let mut resp =
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n".to_vec();
+        assert_eq!(http1_message_length(&resp), MessageLength::Partial);
+        resp.extend_from_slice(b"0\r\n\r\n");
+        let len = resp.len();
+        resp.extend_from_slice(b"HTTP/1.1 200 OK\r\n");
+        assert_eq!(http1_message_length(&resp), MessageLength::Complete(len));
+
+        // chunk长度溢出时只取头部
+        // only take the headers if the chunk size overflows
+        let header = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+        let mut resp = header.to_vec();
+        resp.extend_from_slice(b"ffffffffffffffff\r\n");
+        assert_eq!(
+            http1_message_length(&resp),
+            MessageLength::Complete(header.len())
+        );
+    }
+
+    fn http2_frame(frame_type: u8, flags: u8, stream_id: u32, body: &[u8]) -> Vec<u8> {
+        let mut frame = (body.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend_from_slice(&[frame_type, flags]);
+        frame.extend_from_slice(&stream_id.to_be_bytes());
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    #[test]
+    fn check_hpack_dynamic_table() {
+        let end_stream_headers = FLAG_HEADERS_END_STREAM | FLAG_HEADERS_END_HEADERS;
+        // :path和:authority以增量索引方式插入动态表
+        // :path and :authority inserted into the dynamic table with incremental indexing
+        let mut block = vec![0x82, 0x86, 0x44, 0x0a];
+        block.extend_from_slice(b"/api/users");
+        block.extend_from_slice(&[0x41, 0x0f]);
+        block.extend_from_slice(b"www.example.com");
+        let mut first_request = http2_frame(
+            HTTPV2_FRAME_SETTINGS_TYPE,
+            0,
+            0,
+            &[0x00, 0x01, 0x00, 0x00, 0x10, 0x00],
+        );
+        first_request.extend(http2_frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            end_stream_headers,
+            1,
+            &block,
+        ));
+        // 第二个请求通过动态表索引引用:path(63)和:authority(62)
+        // second request refers to :path(63) and :authority(62) in the dynamic table
+        let second_request = http2_frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            end_stream_headers,
+            3,
+            &[0x82, 0x86, 0xbf, 0xbe],
+        );
+
+        let mut http = HttpLog::new_v2(false);
+        http.parse_http_v2(&first_request, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(http.info.path, "/api/users");
+        http.reset();
+        http.parse_http_v2(&second_request, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(http.info.path, "/api/users");
+        assert_eq!(http.info.host, "www.example.com");
+        assert_eq!(http.info.stream_id, Some(3));
+
+        // 头部块拆分到CONTINUATION帧
+        // header block split into CONTINUATION frame
+        http.reset();
+        let mut third_request = http2_frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_STREAM,
+            5,
+            &[0x82],
+        );
+        third_request.extend(http2_frame(
+            HTTPV2_FRAME_CONTINUATION_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            5,
+            &[0x86, 0xbf, 0xbe],
+        ));
+        http.parse_http_v2(&third_request, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(http.info.path, "/api/users");
+        assert_eq!(http.info.host, "www.example.com");
+
+        // 服务端修改SETTINGS_HEADER_TABLE_SIZE后请求方向的动态表被清空
+        // dynamic table of requests is cleared after server changes SETTINGS_HEADER_TABLE_SIZE
+        http.reset();
+        let settings = http2_frame(
+            HTTPV2_FRAME_SETTINGS_TYPE,
+            0,
+            0,
+            &[0x00, 0x01, 0x00, 0x00, 0x20, 0x00],
+        );
+        assert!(http
+            .parse_http_v2(&settings, PacketDirection::ServerToClient)
+            .is_err());
+        http.reset();
+        http.parse_http_v2(&second_request, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(http.info.path, "");
+        assert_eq!(http.info.host, "");
+    }
+
+    #[test]
+    fn check_hpack_dynamic_table_on_response() {
+        let end_stream_headers = FLAG_HEADERS_END_STREAM | FLAG_HEADERS_END_HEADERS;
+        // 第一个报文为响应，:status 404以增量索引方式插入响应方向的动态表
+        // first packet is a response, :status 404 inserted into the dynamic table of responses
+        let mut block = vec![0x48, 0x03];
+        block.extend_from_slice(b"404");
+        let first_response = http2_frame(HTTPV2_FRAME_HEADERS_TYPE, end_stream_headers, 1, &block);
+        let second_response =
+            http2_frame(HTTPV2_FRAME_HEADERS_TYPE, end_stream_headers, 3, &[0xbe]);
+        let mut param = ParseParam {
+            l4_protocol: IpProtocol::Tcp,
+            ip_src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            ip_dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            port_src: 8080,
+            port_dst: 45678,
+            direction: PacketDirection::ServerToClient,
+            ebpf_type: EbpfType::None,
+            ebpf_param: None,
+            time: 100,
+        };
+
+        let mut http = HttpLog::new_v2(false);
+        assert!(http.http2_check_protocol(&first_response, &param));
+        assert_eq!(http.info.status_code, Some(404));
+        http.reset();
+        http.parse_http_v2(&second_response, PacketDirection::ServerToClient)
+            .unwrap();
+        assert_eq!(http.info.status_code, Some(404));
+        assert_eq!(http.info.stream_id, Some(3));
+
+        // 请求方向的动态表不受响应影响
+        // dynamic table of requests is not affected by responses
+        http.reset();
+        param.direction = PacketDirection::ClientToServer;
+        let mut block = vec![0x82, 0x86, 0x44, 0x0a];
+        block.extend_from_slice(b"/api/users");
+        let request = http2_frame(HTTPV2_FRAME_HEADERS_TYPE, end_stream_headers, 5, &block);
+        assert!(http.http2_check_protocol(&request, &param));
+        http.reset();
+        let request = http2_frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            end_stream_headers,
+            7,
+            &[0x82, 0x86, 0xbe, 0xbf],
+        );
+        assert!(http
+            .parse_http_v2(&request, PacketDirection::ClientToServer)
+            .is_ok());
+        assert_eq!(http.info.path, "/api/users");
+        assert_eq!(http.info.status_code, None);
+    }
 }
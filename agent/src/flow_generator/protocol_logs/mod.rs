@@ -20,19 +20,24 @@ mod http;
 mod mq;
 mod parser;
 pub mod pb_adapter;
+mod plugin;
 mod rpc;
 mod sql;
 pub use self::http::{
-    check_http_method, get_http_request_version, get_http_resp_info, is_http_v1_payload, HttpInfo,
-    HttpLog, Httpv2Headers,
+    check_http_method, get_http_request_version, get_http_resp_info, is_http_v1_payload,
+    Http2HeaderBlock, Http2HpackContext, HttpInfo, HttpLog, Httpv2Headers, TraceContext,
 };
 use self::pb_adapter::L7ProtocolSendLog;
 pub use dns::{DnsInfo, DnsLog};
-pub use mq::{mqtt, KafkaInfo, KafkaLog, MqttInfo, MqttLog};
+pub use mq::{mqtt, AmqpInfo, AmqpLog, KafkaInfo, KafkaLog, MqttInfo, MqttLog};
 pub use parser::{AppProtoLogsParser, MetaAppProto};
-pub use rpc::{DubboHeader, DubboInfo, DubboLog};
+pub use plugin::{update_wasm_plugins, CustomAttribute, CustomInfo, WasmLog};
+pub use rpc::{
+    grpc_status_to_response_status, DubboHeader, DubboInfo, DubboLog, GrpcHeader, GrpcInfo, GrpcLog,
+};
 pub use sql::{
-    decode, MysqlHeader, MysqlInfo, MysqlLog, PostgreInfo, PostgresqlLog, RedisInfo, RedisLog,
+    decode, split_messages, MongoDBInfo, MongoDBLog, MysqlHeader, MysqlInfo, MysqlLog, PostgreInfo,
+    PostgresqlLog, RedisInfo, RedisLog,
 };
 
 use std::{
@@ -496,15 +501,17 @@ impl AppProtoLogsData {
         // |flow_id 高8位| flow_id 低24位|proto 8 位|session 低24位|
 
         // due to grpc is init by http2 and modify during parse, it must reset to http2 when the protocol is grpc.
+        // GrpcLog 解析的日志协议始终为 grpc，不需要重置
         let proto = if self.base_info.head.proto == L7Protocol::Grpc {
-            if let L7ProtocolInfo::HttpInfo(http) = &self.special_info {
-                if http.is_tls() {
-                    L7Protocol::Http2TLS
-                } else {
-                    L7Protocol::Http2
+            match &self.special_info {
+                L7ProtocolInfo::HttpInfo(http) => {
+                    if http.is_tls() {
+                        L7Protocol::Http2TLS
+                    } else {
+                        L7Protocol::Http2
+                    }
                 }
-            } else {
-                unreachable!()
+                _ => L7Protocol::Grpc,
             }
         } else {
             self.base_info.head.proto
@@ -0,0 +1,705 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::str;
+
+use serde::Serialize;
+
+use super::super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+
+use crate::common::enums::IpProtocol;
+use crate::common::flow::L7Protocol;
+use crate::common::flow::PacketDirection;
+use crate::common::l7_protocol_info::L7ProtocolInfo;
+use crate::common::l7_protocol_info::L7ProtocolInfoInterface;
+use crate::common::l7_protocol_log::L7ProtocolParserInterface;
+use crate::common::l7_protocol_log::ParseParam;
+use crate::flow_generator::error::{Error, Result};
+use crate::flow_generator::protocol_logs::pb_adapter::{
+    ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response,
+};
+use crate::log_info_merge;
+use crate::parse_common;
+use crate::utils::bytes::{read_u16_be, read_u32_be, read_u64_be};
+
+// AMQP 0-9-1, 参考：https://www.rabbitmq.com/resources/specs/amqp0-9-1.pdf
+const PROTOCOL_HEADER: &[u8] = b"AMQP\x00\x00\x09\x01";
+
+/*
+ frame
+ +-----------+--------------+-----------+----------------+-----------------+
+ | type(u8)  | channel(u16) | size(u32) | payload(size)  | frame-end(0xce) |
+ +-----------+--------------+-----------+----------------+-----------------+
+*/
+const FRAME_HEADER_LEN: usize = 7;
+const FRAME_END: u8 = 0xce;
+
+const FRAME_METHOD: u8 = 1;
+const FRAME_HEADER: u8 = 2;
+const FRAME_BODY: u8 = 3;
+const FRAME_HEARTBEAT: u8 = 8;
+
+const CLASS_CONNECTION: u16 = 10;
+const CLASS_CHANNEL: u16 = 20;
+const CLASS_EXCHANGE: u16 = 40;
+const CLASS_QUEUE: u16 = 50;
+const CLASS_BASIC: u16 = 60;
+const CLASS_CONFIRM: u16 = 85;
+const CLASS_TX: u16 = 90;
+
+const REPLY_SUCCESS: u16 = 200;
+const CONNECTION_FORCED: u16 = 320;
+const RESOURCE_ERROR: u16 = 506;
+const NOT_IMPLEMENTED: u16 = 540;
+const INTERNAL_ERROR: u16 = 541;
+
+// reply-code 取值参考协议规范中的 constants 一节，服务端原因导致的错误归为 ServerError
+fn reply_code_status(code: u16) -> L7ResponseStatus {
+    match code {
+        REPLY_SUCCESS => L7ResponseStatus::Ok,
+        CONNECTION_FORCED | RESOURCE_ERROR | NOT_IMPLEMENTED | INTERNAL_ERROR => {
+            L7ResponseStatus::ServerError
+        }
+        _ => L7ResponseStatus::ClientError,
+    }
+}
+
+fn method_name(class_id: u16, method_id: u16) -> Option<&'static str> {
+    let name = match (class_id, method_id) {
+        (CLASS_CONNECTION, 10) => "Connection.Start",
+        (CLASS_CONNECTION, 11) => "Connection.StartOk",
+        (CLASS_CONNECTION, 20) => "Connection.Secure",
+        (CLASS_CONNECTION, 21) => "Connection.SecureOk",
+        (CLASS_CONNECTION, 30) => "Connection.Tune",
+        (CLASS_CONNECTION, 31) => "Connection.TuneOk",
+        (CLASS_CONNECTION, 40) => "Connection.Open",
+        (CLASS_CONNECTION, 41) => "Connection.OpenOk",
+        (CLASS_CONNECTION, 50) => "Connection.Close",
+        (CLASS_CONNECTION, 51) => "Connection.CloseOk",
+        (CLASS_CONNECTION, 60) => "Connection.Blocked",
+        (CLASS_CONNECTION, 61) => "Connection.Unblocked",
+        (CLASS_CHANNEL, 10) => "Channel.Open",
+        (CLASS_CHANNEL, 11) => "Channel.OpenOk",
+        (CLASS_CHANNEL, 20) => "Channel.Flow",
+        (CLASS_CHANNEL, 21) => "Channel.FlowOk",
+        (CLASS_CHANNEL, 40) => "Channel.Close",
+        (CLASS_CHANNEL, 41) => "Channel.CloseOk",
+        (CLASS_EXCHANGE, 10) => "Exchange.Declare",
+        (CLASS_EXCHANGE, 11) => "Exchange.DeclareOk",
+        (CLASS_EXCHANGE, 20) => "Exchange.Delete",
+        (CLASS_EXCHANGE, 21) => "Exchange.DeleteOk",
+        (CLASS_EXCHANGE, 30) => "Exchange.Bind",
+        (CLASS_EXCHANGE, 31) => "Exchange.BindOk",
+        (CLASS_EXCHANGE, 40) => "Exchange.Unbind",
+        (CLASS_EXCHANGE, 51) => "Exchange.UnbindOk",
+        (CLASS_QUEUE, 10) => "Queue.Declare",
+        (CLASS_QUEUE, 11) => "Queue.DeclareOk",
+        (CLASS_QUEUE, 20) => "Queue.Bind",
+        (CLASS_QUEUE, 21) => "Queue.BindOk",
+        (CLASS_QUEUE, 30) => "Queue.Purge",
+        (CLASS_QUEUE, 31) => "Queue.PurgeOk",
+        (CLASS_QUEUE, 40) => "Queue.Delete",
+        (CLASS_QUEUE, 41) => "Queue.DeleteOk",
+        (CLASS_QUEUE, 50) => "Queue.Unbind",
+        (CLASS_QUEUE, 51) => "Queue.UnbindOk",
+        (CLASS_BASIC, 10) => "Basic.Qos",
+        (CLASS_BASIC, 11) => "Basic.QosOk",
+        (CLASS_BASIC, 20) => "Basic.Consume",
+        (CLASS_BASIC, 21) => "Basic.ConsumeOk",
+        (CLASS_BASIC, 30) => "Basic.Cancel",
+        (CLASS_BASIC, 31) => "Basic.CancelOk",
+        (CLASS_BASIC, 40) => "Basic.Publish",
+        (CLASS_BASIC, 50) => "Basic.Return",
+        (CLASS_BASIC, 60) => "Basic.Deliver",
+        (CLASS_BASIC, 70) => "Basic.Get",
+        (CLASS_BASIC, 71) => "Basic.GetOk",
+        (CLASS_BASIC, 72) => "Basic.GetEmpty",
+        (CLASS_BASIC, 80) => "Basic.Ack",
+        (CLASS_BASIC, 90) => "Basic.Reject",
+        (CLASS_BASIC, 100) => "Basic.RecoverAsync",
+        (CLASS_BASIC, 110) => "Basic.Recover",
+        (CLASS_BASIC, 111) => "Basic.RecoverOk",
+        (CLASS_BASIC, 120) => "Basic.Nack",
+        (CLASS_CONFIRM, 10) => "Confirm.Select",
+        (CLASS_CONFIRM, 11) => "Confirm.SelectOk",
+        (CLASS_TX, 10) => "Tx.Select",
+        (CLASS_TX, 11) => "Tx.SelectOk",
+        (CLASS_TX, 20) => "Tx.Commit",
+        (CLASS_TX, 21) => "Tx.CommitOk",
+        (CLASS_TX, 30) => "Tx.Rollback",
+        (CLASS_TX, 31) => "Tx.RollbackOk",
+        _ => return None,
+    };
+    Some(name)
+}
+
+// 同步方法（需要对端回复 xxx-Ok）作为请求，xxx-Ok 作为响应；
+// Publish/Deliver/Ack 等异步方法没有对应的响应，作为单独的会话发送
+fn method_message_type(class_id: u16, method_id: u16) -> LogMessageType {
+    match (class_id, method_id) {
+        (CLASS_CONNECTION, 60 | 61) => LogMessageType::Session,
+        (CLASS_BASIC, 40 | 50 | 60 | 80 | 90 | 100 | 120) => LogMessageType::Session,
+        (CLASS_BASIC, 72) => LogMessageType::Response,
+        (_, m) if m % 2 == 1 => LogMessageType::Response,
+        _ => LogMessageType::Request,
+    }
+}
+
+// 方法参数按顺序紧密排列，报文可能被截断，读取失败时返回 None
+struct ArgReader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> ArgReader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, offset: 0 }
+    }
+
+    fn skip(&mut self, n: usize) -> Option<()> {
+        if self.offset + n > self.buf.len() {
+            return None;
+        }
+        self.offset += n;
+        Some(())
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let start = self.offset;
+        self.skip(2)?;
+        Some(read_u16_be(&self.buf[start..]))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        let start = self.offset;
+        self.skip(8)?;
+        Some(read_u64_be(&self.buf[start..]))
+    }
+
+    fn short_str(&mut self) -> Option<&'a str> {
+        let len = *self.buf.get(self.offset)? as usize;
+        let start = self.offset + 1;
+        self.skip(1 + len)?;
+        str::from_utf8(&self.buf[start..start + len]).ok()
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct AmqpInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+    #[serde(skip)]
+    is_tls: bool,
+
+    pub channel: u16,
+    #[serde(skip)]
+    pub class_id: u16,
+    #[serde(skip)]
+    pub method_id: u16,
+
+    // 方法名，例如：Basic.Publish、Queue.Declare
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub method: String,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub exchange: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub routing_key: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub queue: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub consumer_tag: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delivery_tag: Option<u64>,
+    // 消息内容长度，取自 content header frame 的 body-size
+    #[serde(rename = "request_length", skip_serializing_if = "Option::is_none")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
+    pub resp_len: Option<u32>,
+
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub reply_code: Option<u16>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub reply_text: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl AmqpInfo {
+    pub fn merge(&mut self, other: Self) {
+        if other.msg_type != LogMessageType::Response {
+            return;
+        }
+        if self.queue.is_empty() {
+            self.queue = other.queue;
+        }
+        if self.consumer_tag.is_empty() {
+            self.consumer_tag = other.consumer_tag;
+        }
+        if self.exchange.is_empty() {
+            self.exchange = other.exchange;
+            self.routing_key = other.routing_key;
+        }
+        if other.delivery_tag.is_some() {
+            self.delivery_tag = other.delivery_tag;
+        }
+        self.resp_len = other.resp_len;
+        // xxx-Ok 响应不携带 reply-code，Close 的 reply-code 在请求中
+        if other.reply_code.is_some() {
+            self.reply_code = other.reply_code;
+            self.reply_text = other.reply_text;
+            self.status = other.status;
+        }
+    }
+}
+
+impl L7ProtocolInfoInterface for AmqpInfo {
+    fn session_id(&self) -> Option<u32> {
+        match self.msg_type {
+            LogMessageType::Request | LogMessageType::Response => Some(self.channel as u32),
+            _ => None,
+        }
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, AmqpInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::AMQP,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+
+    fn skip_send(&self) -> bool {
+        false
+    }
+}
+
+impl From<AmqpInfo> for L7ProtocolSendLog {
+    fn from(f: AmqpInfo) -> Self {
+        let mut attributes = vec![KeyVal {
+            key: String::from("channel"),
+            val: f.channel.to_string(),
+        }];
+        if !f.consumer_tag.is_empty() {
+            attributes.push(KeyVal {
+                key: String::from("consumer_tag"),
+                val: f.consumer_tag,
+            });
+        }
+        if let Some(tag) = f.delivery_tag {
+            attributes.push(KeyVal {
+                key: String::from("delivery_tag"),
+                val: tag.to_string(),
+            });
+        }
+
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: f.method,
+                domain: f.exchange,
+                resource: if f.routing_key.is_empty() {
+                    f.queue.clone()
+                } else {
+                    f.routing_key
+                },
+                endpoint: f.queue,
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.reply_code.map(|c| c as i32),
+                exception: f.reply_text,
+                ..Default::default()
+            },
+            ext_info: Some(ExtendedInfo {
+                request_id: Some(f.channel as u32),
+                attributes: Some(attributes),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AmqpLog {
+    info: AmqpInfo,
+    #[serde(skip)]
+    has_method: bool,
+}
+
+impl L7ProtocolParserInterface for AmqpLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if param.l4_protocol != IpProtocol::Tcp || !param.ebpf_type.is_raw_protocol() {
+            return false;
+        }
+        if payload.starts_with(PROTOCOL_HEADER) {
+            return true;
+        }
+        // 协议识别时要求第一个帧是完整的 method frame，避免误识别
+        if payload.len() < FRAME_HEADER_LEN || payload[0] != FRAME_METHOD {
+            return false;
+        }
+        let size = read_u32_be(&payload[3..]) as usize;
+        if payload.len() < FRAME_HEADER_LEN + size + 1
+            || payload[FRAME_HEADER_LEN + size] != FRAME_END
+        {
+            return false;
+        }
+        let ok = self.parse(payload, param.direction).is_ok() && self.has_method;
+        self.reset();
+        ok
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        parse_common!(self, param);
+        self.parse(payload, param.direction)?;
+        if !self.has_method {
+            // 协议头、心跳或者单独的 content body，不输出日志
+            return Ok(vec![]);
+        }
+        Ok(vec![L7ProtocolInfo::AmqpInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::AMQP
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl AmqpLog {
+    // 一个报文中可能包含多个帧（例如 Basic.Publish + content header + content body），只解析第一个 method frame
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<()> {
+        if payload.starts_with(PROTOCOL_HEADER) {
+            return Ok(());
+        }
+
+        let mut offset = 0;
+        while offset + FRAME_HEADER_LEN <= payload.len() {
+            let frame_type = payload[offset];
+            let channel = read_u16_be(&payload[offset + 1..]);
+            let size = read_u32_be(&payload[offset + 3..]) as usize;
+            let frame_end = offset + FRAME_HEADER_LEN + size;
+            if frame_end < payload.len() && payload[frame_end] != FRAME_END {
+                return Err(Error::AmqpLogParseFailed);
+            }
+            let frame = &payload[offset + FRAME_HEADER_LEN..frame_end.min(payload.len())];
+
+            match frame_type {
+                FRAME_METHOD if !self.has_method => self.on_method(frame, channel)?,
+                FRAME_HEADER if self.has_method && channel == self.info.channel => {
+                    self.on_content_header(frame, direction)
+                }
+                FRAME_METHOD | FRAME_HEADER | FRAME_BODY | FRAME_HEARTBEAT => {}
+                _ => return Err(Error::AmqpLogParseFailed),
+            }
+            offset = frame_end + 1;
+        }
+
+        if offset == 0 {
+            return Err(Error::AmqpLogParseFailed);
+        }
+        Ok(())
+    }
+
+    fn on_method(&mut self, frame: &[u8], channel: u16) -> Result<()> {
+        if frame.len() < 4 {
+            return Err(Error::AmqpLogParseFailed);
+        }
+        let class_id = read_u16_be(frame);
+        let method_id = read_u16_be(&frame[2..]);
+        let name = method_name(class_id, method_id).ok_or(Error::AmqpLogParseFailed)?;
+
+        self.has_method = true;
+        self.info.channel = channel;
+        self.info.class_id = class_id;
+        self.info.method_id = method_id;
+        self.info.method = name.to_owned();
+        self.info.msg_type = method_message_type(class_id, method_id);
+        self.info.status = L7ResponseStatus::Ok;
+
+        let _ = self.on_method_args(ArgReader::new(&frame[4..]));
+        if let Some(code) = self.info.reply_code {
+            self.info.status = reply_code_status(code);
+        }
+        Ok(())
+    }
+
+    fn on_method_args(&mut self, mut args: ArgReader) -> Option<()> {
+        let info = &mut self.info;
+        match (info.class_id, info.method_id) {
+            // Connection.Close / Channel.Close: reply-code, reply-text, class-id, method-id
+            (CLASS_CONNECTION, 50) | (CLASS_CHANNEL, 40) => {
+                info.reply_code = Some(args.u16()?);
+                info.reply_text = args.short_str()?.to_owned();
+            }
+            // Exchange.Declare / Exchange.Delete: reserved, exchange
+            (CLASS_EXCHANGE, 10 | 20) => {
+                args.skip(2)?;
+                info.exchange = args.short_str()?.to_owned();
+            }
+            // Exchange.Bind / Exchange.Unbind: reserved, destination, source, routing-key
+            (CLASS_EXCHANGE, 30 | 40) => {
+                args.skip(2)?;
+                info.exchange = args.short_str()?.to_owned();
+                args.short_str()?;
+                info.routing_key = args.short_str()?.to_owned();
+            }
+            // Queue.Declare / Queue.Purge / Queue.Delete / Basic.Get: reserved, queue
+            (CLASS_QUEUE, 10 | 30 | 40) | (CLASS_BASIC, 70) => {
+                args.skip(2)?;
+                info.queue = args.short_str()?.to_owned();
+            }
+            // Queue.DeclareOk: queue, message-count, consumer-count
+            (CLASS_QUEUE, 11) => {
+                info.queue = args.short_str()?.to_owned();
+            }
+            // Queue.Bind / Queue.Unbind: reserved, queue, exchange, routing-key
+            (CLASS_QUEUE, 20 | 50) => {
+                args.skip(2)?;
+                info.queue = args.short_str()?.to_owned();
+                info.exchange = args.short_str()?.to_owned();
+                info.routing_key = args.short_str()?.to_owned();
+            }
+            // Basic.Consume: reserved, queue, consumer-tag
+            (CLASS_BASIC, 20) => {
+                args.skip(2)?;
+                info.queue = args.short_str()?.to_owned();
+                info.consumer_tag = args.short_str()?.to_owned();
+            }
+            // Basic.ConsumeOk / Basic.Cancel / Basic.CancelOk: consumer-tag
+            (CLASS_BASIC, 21 | 30 | 31) => {
+                info.consumer_tag = args.short_str()?.to_owned();
+            }
+            // Basic.Publish: reserved, exchange, routing-key
+            (CLASS_BASIC, 40) => {
+                args.skip(2)?;
+                info.exchange = args.short_str()?.to_owned();
+                info.routing_key = args.short_str()?.to_owned();
+            }
+            // Basic.Return: reply-code, reply-text, exchange, routing-key
+            (CLASS_BASIC, 50) => {
+                info.reply_code = Some(args.u16()?);
+                info.reply_text = args.short_str()?.to_owned();
+                info.exchange = args.short_str()?.to_owned();
+                info.routing_key = args.short_str()?.to_owned();
+            }
+            // Basic.Deliver: consumer-tag, delivery-tag, redelivered, exchange, routing-key
+            (CLASS_BASIC, 60) => {
+                info.consumer_tag = args.short_str()?.to_owned();
+                info.delivery_tag = Some(args.u64()?);
+                args.skip(1)?;
+                info.exchange = args.short_str()?.to_owned();
+                info.routing_key = args.short_str()?.to_owned();
+            }
+            // Basic.GetOk: delivery-tag, redelivered, exchange, routing-key, message-count
+            (CLASS_BASIC, 71) => {
+                info.delivery_tag = Some(args.u64()?);
+                args.skip(1)?;
+                info.exchange = args.short_str()?.to_owned();
+                info.routing_key = args.short_str()?.to_owned();
+            }
+            // Basic.Ack / Basic.Reject / Basic.Nack: delivery-tag, flags
+            (CLASS_BASIC, 80 | 90 | 120) => {
+                info.delivery_tag = Some(args.u64()?);
+            }
+            _ => {}
+        }
+        Some(())
+    }
+
+    /*
+     content header frame
+     +---------------+-------------+-----------------+----------------------------+
+     | class-id(u16) | weight(u16) | body-size(u64)  | property-flags, properties  |
+     +---------------+-------------+-----------------+----------------------------+
+    */
+    fn on_content_header(&mut self, frame: &[u8], direction: PacketDirection) {
+        if frame.len() < 12 {
+            return;
+        }
+        let body_size = read_u64_be(&frame[4..]).min(u32::MAX as u64) as u32;
+        match direction {
+            PacketDirection::ClientToServer => self.info.req_len = Some(body_size),
+            PacketDirection::ServerToClient => self.info.resp_len = Some(body_size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/amqp";
+
+    fn parse_all(file_name: &str) -> Vec<AmqpInfo> {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(file_name), Some(1500));
+        let mut packets = capture.as_meta_packets();
+        let first_dst_port = packets[0].lookup_key.dst_port;
+
+        let mut infos = vec![];
+        let mut parser = AmqpLog::default();
+        for packet in packets.iter_mut() {
+            if packet.lookup_key.dst_port == first_dst_port {
+                packet.direction = PacketDirection::ClientToServer;
+            } else {
+                packet.direction = PacketDirection::ServerToClient;
+            }
+            let param = &ParseParam::from(&*packet);
+            let payload = packet.get_l4_payload().unwrap();
+            assert!(parser.check_payload(payload, param));
+            parser.reset();
+            for info in parser.parse_payload(payload, param).unwrap() {
+                if let L7ProtocolInfo::AmqpInfo(info) = info {
+                    infos.push(info);
+                }
+            }
+            parser.reset();
+        }
+        infos
+    }
+
+    #[test]
+    fn check_queue_declare() {
+        let infos = parse_all("amqp_queue_declare.pcap");
+        assert_eq!(infos.len(), 2);
+
+        let (req, resp) = (&infos[0], &infos[1]);
+        assert_eq!(req.msg_type, LogMessageType::Request);
+        assert_eq!(resp.msg_type, LogMessageType::Response);
+        assert_eq!(req.session_id(), resp.session_id());
+        assert_eq!(req.method.as_str(), "Queue.Declare");
+        assert_eq!(resp.method.as_str(), "Queue.DeclareOk");
+
+        let mut merged = L7ProtocolInfo::AmqpInfo(req.clone());
+        merged
+            .merge_log(L7ProtocolInfo::AmqpInfo(resp.clone()))
+            .unwrap();
+        if let L7ProtocolInfo::AmqpInfo(info) = merged {
+            assert_eq!(info.channel, 1);
+            assert_eq!(info.queue.as_str(), "orders");
+            assert_eq!(info.status, L7ResponseStatus::Ok);
+        } else {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn check_publish_and_close() {
+        let infos = parse_all("amqp_publish_error.pcap");
+        assert_eq!(infos.len(), 3);
+
+        let publish = &infos[0];
+        assert_eq!(publish.msg_type, LogMessageType::Session);
+        assert_eq!(publish.method.as_str(), "Basic.Publish");
+        assert_eq!(publish.channel, 1);
+        assert_eq!(publish.exchange.as_str(), "orders.topic");
+        assert_eq!(publish.routing_key.as_str(), "order.created");
+        assert_eq!(publish.req_len, Some(17));
+        assert_eq!(publish.session_id(), None);
+
+        let close = &infos[1];
+        assert_eq!(close.msg_type, LogMessageType::Request);
+        assert_eq!(close.method.as_str(), "Channel.Close");
+        assert_eq!(close.reply_code, Some(404));
+        assert_eq!(
+            close.reply_text.as_str(),
+            "NOT_FOUND - no exchange 'orders.topic' in vhost '/'"
+        );
+        assert_eq!(close.status, L7ResponseStatus::ClientError);
+
+        let close_ok = &infos[2];
+        assert_eq!(close_ok.msg_type, LogMessageType::Response);
+        assert_eq!(close_ok.method.as_str(), "Channel.CloseOk");
+        assert_eq!(close.session_id(), close_ok.session_id());
+    }
+
+    #[test]
+    fn check_deliver() {
+        let mut parser = AmqpLog::default();
+        // Basic.Deliver + content header(body-size 5) + content body
+        let payload = [
+            &[1u8, 0, 2, 0, 0, 0, 30][..],
+            &[0, 60, 0, 60, 8][..],
+            b"ctag-1.0",
+            &[0, 0, 0, 0, 0, 0, 0, 7, 0, 3][..],
+            b"ex1",
+            &[3][..],
+            b"rk1",
+            &[FRAME_END][..],
+            &[
+                2, 0, 2, 0, 0, 0, 14, 0, 60, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0, FRAME_END,
+            ][..],
+            &[3, 0, 2, 0, 0, 0, 5][..],
+            b"hello",
+            &[FRAME_END][..],
+        ]
+        .concat();
+        parser
+            .parse(&payload, PacketDirection::ServerToClient)
+            .unwrap();
+        let info = &parser.info;
+        assert_eq!(info.msg_type, LogMessageType::Session);
+        assert_eq!(info.method.as_str(), "Basic.Deliver");
+        assert_eq!(info.channel, 2);
+        assert_eq!(info.consumer_tag.as_str(), "ctag-1.0");
+        assert_eq!(info.delivery_tag, Some(7));
+        assert_eq!(info.exchange.as_str(), "ex1");
+        assert_eq!(info.routing_key.as_str(), "rk1");
+        assert_eq!(info.resp_len, Some(5));
+    }
+
+    #[test]
+    fn check_reply_code() {
+        assert_eq!(reply_code_status(200), L7ResponseStatus::Ok);
+        assert_eq!(reply_code_status(312), L7ResponseStatus::ClientError);
+        assert_eq!(reply_code_status(403), L7ResponseStatus::ClientError);
+        assert_eq!(reply_code_status(541), L7ResponseStatus::ServerError);
+    }
+
+    #[test]
+    fn check_non_amqp() {
+        let mut parser = AmqpLog::default();
+        let http = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        assert!(parser.parse(http, PacketDirection::ClientToServer).is_err());
+    }
+}
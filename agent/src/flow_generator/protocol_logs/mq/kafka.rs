@@ -13,18 +13,22 @@
  * See the License for the specific language governing permissions and
  * limitations under the License.
  */
+use std::collections::HashMap;
+use std::mem;
+use std::str;
+
 use serde::Serialize;
 
 use super::super::{
-    consts::KAFKA_REQ_HEADER_LEN, value_is_default, value_is_negative, AppProtoHead,
-    L7ResponseStatus, LogMessageType,
+    consts::{KAFKA_REQ_HEADER_LEN, KAFKA_RESP_HEADER_LEN},
+    value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
 };
 
 use crate::common::flow::L7Protocol;
 use crate::common::l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface};
-use crate::common::l7_protocol_log::{L7ProtocolParserInterface, ParseParam};
+use crate::common::l7_protocol_log::{L7ProtocolParserInterface, MessageLength, ParseParam};
 use crate::flow_generator::protocol_logs::pb_adapter::{
-    ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response,
+    ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response,
 };
 use crate::{
     common::enums::IpProtocol,
@@ -34,7 +38,115 @@ use crate::{
 };
 use crate::{log_info_merge, parse_common};
 
+const KAFKA_PRODUCE: u16 = 0;
 const KAFKA_FETCH: u16 = 1;
+const KAFKA_METADATA: u16 = 3;
+const KAFKA_OFFSET_COMMIT: u16 = 8;
+const KAFKA_JOIN_GROUP: u16 = 11;
+
+const KAFKA_TOPIC_ID_LEN: usize = 16;
+// 一个消息中最多记录的 topic 名称个数
+const KAFKA_TOPIC_NAME_MAX: usize = 8;
+// 等待响应的请求个数上限，超过后清空
+const KAFKA_PENDING_REQUEST_MAX: usize = 64;
+
+// 只解析常用 API 的消息体，返回开始使用 flexible 编码（compact 类型和 tagged fields）的版本号
+// 参考：https://kafka.apache.org/protocol.html#protocol_messages
+fn flexible_version(api_key: u16) -> Option<u16> {
+    match api_key {
+        KAFKA_PRODUCE => Some(9),
+        KAFKA_FETCH => Some(12),
+        KAFKA_METADATA => Some(9),
+        KAFKA_OFFSET_COMMIT => Some(8),
+        KAFKA_JOIN_GROUP => Some(6),
+        _ => None,
+    }
+}
+
+// 消息体的读取器，报文可能被截断，读取失败时返回 None
+struct KafkaReader<'a> {
+    buf: &'a [u8],
+    offset: usize,
+    flexible: bool,
+}
+
+impl<'a> KafkaReader<'a> {
+    fn new(buf: &'a [u8], flexible: bool) -> Self {
+        Self {
+            buf,
+            offset: 0,
+            flexible,
+        }
+    }
+
+    fn skip(&mut self, n: usize) -> Option<&'a [u8]> {
+        if self.offset + n > self.buf.len() {
+            return None;
+        }
+        let data = &self.buf[self.offset..self.offset + n];
+        self.offset += n;
+        Some(data)
+    }
+
+    fn i16(&mut self) -> Option<i16> {
+        self.skip(2).map(|b| read_u16_be(b) as i16)
+    }
+
+    fn i32(&mut self) -> Option<i32> {
+        self.skip(4).map(|b| read_u32_be(b) as i32)
+    }
+
+    fn uvarint(&mut self) -> Option<u32> {
+        let mut value = 0u32;
+        for i in 0..5 {
+            let b = *self.skip(1)?.first()?;
+            value |= ((b & 0x7f) as u32) << (7 * i);
+            if b & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    // flexible 版本使用 unsigned varint 编码长度 N+1，0 表示 null；否则使用定长整数，-1 表示 null
+    fn length(&mut self, legacy_i16: bool) -> Option<usize> {
+        if self.flexible {
+            return Some(self.uvarint()?.saturating_sub(1) as usize);
+        }
+        let len = if legacy_i16 {
+            self.i16()? as i32
+        } else {
+            self.i32()?
+        };
+        Some(len.max(0) as usize)
+    }
+
+    fn string(&mut self) -> Option<&'a str> {
+        let len = self.length(true)?;
+        str::from_utf8(self.skip(len)?).ok()
+    }
+
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.length(false)?;
+        self.skip(len)
+    }
+
+    fn array_len(&mut self) -> Option<usize> {
+        self.length(false)
+    }
+
+    fn tagged_fields(&mut self) -> Option<()> {
+        if !self.flexible {
+            return Some(());
+        }
+        for _ in 0..self.uvarint()? {
+            self.uvarint()?;
+            let size = self.uvarint()? as usize;
+            self.skip(size)?;
+        }
+        Some(())
+    }
+}
 
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct KafkaInfo {
@@ -58,6 +170,11 @@ pub struct KafkaInfo {
     pub api_key: u16,
     #[serde(skip)]
     pub client_id: String,
+    // 逗号分隔的 topic 名称
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub topic_name: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub partition_count: u32,
 
     // reponse
     #[serde(rename = "response_length", skip_serializing_if = "value_is_negative")]
@@ -66,11 +183,6 @@ pub struct KafkaInfo {
     pub status: L7ResponseStatus,
     #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
     pub status_code: Option<i32>,
-
-    // the first 14 byte of resp_data, use to parse error code
-    // only fetch and api version > 7 can get the correct err code
-    #[serde(skip)]
-    pub resp_data: Option<[u8; 14]>,
 }
 
 impl L7ProtocolInfoInterface for KafkaInfo {
@@ -104,34 +216,62 @@ impl KafkaInfo {
     // https://kafka.apache.org/protocol.html
     const API_KEY_MAX: u16 = 67;
     pub fn merge(&mut self, other: Self) {
-        if self.resp_msg_size.is_none() {
-            self.resp_msg_size = other.resp_msg_size;
-        }
-        /*
-            reference:  https://kafka.apache.org/protocol.html#protocol_messages
-
-            only fetch api and api version > 7 parse the error code
-
-            Fetch Response (Version: 7) => throttle_time_ms error_code session_id [responses]
-                throttle_time_ms => INT32
-                error_code => INT16
-                ...
-        */
         match other.msg_type {
-            LogMessageType::Response if self.api_key == KAFKA_FETCH && self.api_version >= 7 => {
-                if let Some(d) = other.resp_data {
-                    self.set_status_code(read_u16_be(&d[12..]) as i32)
+            LogMessageType::Response => {
+                self.resp_msg_size = other.resp_msg_size;
+                if other.status_code.is_some() {
+                    self.status = other.status;
+                    self.status_code = other.status_code;
+                }
+                if self.topic_name.is_empty() {
+                    self.topic_name = other.topic_name;
+                }
+                if self.partition_count == 0 {
+                    self.partition_count = other.partition_count;
                 }
             }
-            LogMessageType::Request if other.api_key == KAFKA_FETCH && other.api_version >= 7 => {
-                if let Some(d) = self.resp_data {
-                    self.set_status_code(read_u16_be(&d[12..]) as i32)
+            LogMessageType::Request => {
+                self.req_msg_size = other.req_msg_size;
+                self.api_key = other.api_key;
+                self.api_version = other.api_version;
+                self.client_id = other.client_id;
+                // 请求中的 topic 和 partition 更准确，例如 Metadata 响应可能包含全部 topic
+                if !other.topic_name.is_empty() {
+                    self.topic_name = other.topic_name;
+                }
+                if other.partition_count > 0 {
+                    self.partition_count = other.partition_count;
                 }
             }
             _ => {}
         }
     }
 
+    fn add_topic(&mut self, name: &str, partition_count: usize) {
+        self.partition_count = self
+            .partition_count
+            .saturating_add(partition_count.min(u32::MAX as usize) as u32);
+        if name.is_empty() {
+            return;
+        }
+        if self.topic_name.split(',').any(|t| t == name) {
+            return;
+        }
+        if self.topic_name.is_empty() {
+            self.topic_name = name.to_owned();
+        } else if self.topic_name.split(',').count() < KAFKA_TOPIC_NAME_MAX {
+            self.topic_name.push(',');
+            self.topic_name.push_str(name);
+        }
+    }
+
+    // 记录第一个非 0 的错误码，包括顶层以及每个 topic/partition 的错误码
+    fn on_error_code(&mut self, code: i16) {
+        if self.status_code.unwrap_or_default() == 0 {
+            self.set_status_code(code as i32);
+        }
+    }
+
     pub fn set_status_code(&mut self, code: i32) {
         self.status_code = Some(code);
         if code == 0 {
@@ -230,6 +370,7 @@ impl From<KafkaInfo> for L7ProtocolSendLog {
             resp_len: f.resp_msg_size,
             req: L7Request {
                 req_type: String::from(command_str),
+                resource: f.topic_name,
                 ..Default::default()
             },
             resp: L7Response {
@@ -239,6 +380,14 @@ impl From<KafkaInfo> for L7ProtocolSendLog {
             },
             ext_info: Some(ExtendedInfo {
                 request_id: Some(f.correlation_id),
+                attributes: if f.partition_count > 0 {
+                    Some(vec![KeyVal {
+                        key: String::from("partition_count"),
+                        val: f.partition_count.to_string(),
+                    }])
+                } else {
+                    None
+                },
                 ..Default::default()
             }),
             ..Default::default()
@@ -250,6 +399,9 @@ impl From<KafkaInfo> for L7ProtocolSendLog {
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct KafkaLog {
     info: KafkaInfo,
+    // 响应中不携带 api_key 和 api_version，需要根据请求记录的 correlation_id 查找后才能解析消息体
+    #[serde(skip)]
+    pending_requests: HashMap<u32, (u16, u16)>,
 }
 
 impl L7ProtocolParserInterface for KafkaLog {
@@ -281,8 +433,23 @@ impl L7ProtocolParserInterface for KafkaLog {
         false
     }
 
+    // 请求和响应都以不包括自身的4字节长度开头
+    // both requests and responses start with a 4 bytes length excluding itself
+    fn message_length(&self, payload: &[u8], _: &ParseParam) -> MessageLength {
+        if payload.len() < Self::MSG_LEN_SIZE {
+            return MessageLength::Partial;
+        }
+        let len = read_u32_be(payload) as i32;
+        if len < 0 || Self::MSG_LEN_SIZE + (len as usize) < KAFKA_RESP_HEADER_LEN {
+            return MessageLength::Unknown;
+        }
+        MessageLength::Complete(Self::MSG_LEN_SIZE + len as usize)
+    }
+
     fn reset(&mut self) {
+        let pending_requests = mem::take(&mut self.pending_requests);
         *self = Self::default();
+        self.pending_requests = pending_requests;
         self.info.status = L7ResponseStatus::NotExist;
     }
 }
@@ -301,6 +468,8 @@ impl KafkaLog {
         self.info.api_version = 0;
         self.info.api_key = 0;
         self.info.client_id = String::new();
+        self.info.topic_name = String::new();
+        self.info.partition_count = 0;
         self.info.resp_msg_size = None;
         self.info.status = L7ResponseStatus::Ok;
         self.info.status_code = None;
@@ -333,6 +502,20 @@ impl KafkaLog {
             return Err(Error::KafkaLogParseFailed);
         }
 
+        let (api_key, api_version) = (self.info.api_key, self.info.api_version);
+        if let Some(version) = flexible_version(api_key) {
+            let mut reader = KafkaReader::new(
+                &payload[KAFKA_REQ_HEADER_LEN + client_id_len..],
+                api_version >= version,
+            );
+            let _ = self.decode_request_body(&mut reader, api_key, api_version);
+        }
+        if self.pending_requests.len() >= KAFKA_PENDING_REQUEST_MAX {
+            self.pending_requests.clear();
+        }
+        self.pending_requests
+            .insert(self.info.correlation_id, (api_key, api_version));
+
         Ok(AppProtoHead {
             proto: L7Protocol::Kafka,
             msg_type: self.info.msg_type,
@@ -345,8 +528,17 @@ impl KafkaLog {
         self.info.resp_msg_size = Some(read_u32_be(payload));
         self.info.correlation_id = read_u32_be(&payload[4..]);
         self.info.msg_type = LogMessageType::Response;
-        if payload.len() >= 14 {
-            self.info.resp_data = Some(payload[..14].try_into().unwrap())
+
+        if let Some((api_key, api_version)) =
+            self.pending_requests.remove(&self.info.correlation_id)
+        {
+            self.info.api_key = api_key;
+            self.info.api_version = api_version;
+            if let Some(version) = flexible_version(api_key) {
+                let mut reader =
+                    KafkaReader::new(&payload[KAFKA_RESP_HEADER_LEN..], api_version >= version);
+                let _ = self.decode_response_body(&mut reader, api_key, api_version);
+            }
         }
         Ok(AppProtoHead {
             proto: L7Protocol::Kafka,
@@ -355,6 +547,379 @@ impl KafkaLog {
         })
     }
 
+    // 请求头 v2 以及响应头 v1 在 flexible 版本中带有 tagged fields
+    fn decode_request_body(
+        &mut self,
+        r: &mut KafkaReader,
+        api_key: u16,
+        version: u16,
+    ) -> Option<()> {
+        r.tagged_fields()?;
+        match api_key {
+            KAFKA_PRODUCE => self.decode_produce_request(r, version),
+            KAFKA_FETCH => self.decode_fetch_request(r, version),
+            KAFKA_METADATA => self.decode_metadata_request(r, version),
+            KAFKA_OFFSET_COMMIT => self.decode_offset_commit_request(r, version),
+            KAFKA_JOIN_GROUP => self.decode_join_group_request(r, version),
+            _ => None,
+        }
+    }
+
+    fn decode_response_body(
+        &mut self,
+        r: &mut KafkaReader,
+        api_key: u16,
+        version: u16,
+    ) -> Option<()> {
+        r.tagged_fields()?;
+        match api_key {
+            KAFKA_PRODUCE => self.decode_produce_response(r, version),
+            KAFKA_FETCH => self.decode_fetch_response(r, version),
+            KAFKA_METADATA => self.decode_metadata_response(r, version),
+            KAFKA_OFFSET_COMMIT => self.decode_offset_commit_response(r, version),
+            KAFKA_JOIN_GROUP => self.decode_join_group_response(r, version),
+            _ => None,
+        }
+    }
+
+    /*
+        Produce Request => transactional_id(v3+) acks timeout_ms [topic_data]
+          topic_data => name [partition_data]
+            partition_data => index records
+    */
+    fn decode_produce_request(&mut self, r: &mut KafkaReader, version: u16) -> Option<()> {
+        if version >= 3 {
+            r.string()?;
+        }
+        r.skip(6)?;
+        for _ in 0..r.array_len()? {
+            let name = r.string()?;
+            let partitions = r.array_len()?;
+            self.info.add_topic(name, partitions);
+            for _ in 0..partitions {
+                r.skip(4)?;
+                r.bytes()?;
+                r.tagged_fields()?;
+            }
+            r.tagged_fields()?;
+        }
+        Some(())
+    }
+
+    /*
+        Produce Response => [responses] throttle_time_ms(v1+)
+          responses => name [partition_responses]
+            partition_responses => index error_code base_offset log_append_time_ms(v2+)
+                                   log_start_offset(v5+) [record_errors](v8+) error_message(v8+)
+    */
+    fn decode_produce_response(&mut self, r: &mut KafkaReader, version: u16) -> Option<()> {
+        for _ in 0..r.array_len()? {
+            let name = r.string()?;
+            let partitions = r.array_len()?;
+            self.info.add_topic(name, partitions);
+            for _ in 0..partitions {
+                r.skip(4)?;
+                self.info.on_error_code(r.i16()?);
+                r.skip(8)?;
+                if version >= 2 {
+                    r.skip(8)?;
+                }
+                if version >= 5 {
+                    r.skip(8)?;
+                }
+                if version >= 8 {
+                    for _ in 0..r.array_len()? {
+                        r.skip(4)?;
+                        r.string()?;
+                        r.tagged_fields()?;
+                    }
+                    r.string()?;
+                }
+                r.tagged_fields()?;
+            }
+            r.tagged_fields()?;
+        }
+        Some(())
+    }
+
+    /*
+        Fetch Request => replica_id(v0-14) max_wait_ms min_bytes max_bytes(v3+) isolation_level(v4+)
+                         session_id(v7+) session_epoch(v7+) [topics] ...
+          topics => topic(v0-12) | topic_id(v13+) [partitions]
+            partitions => partition current_leader_epoch(v9+) fetch_offset last_fetched_epoch(v12+)
+                          log_start_offset(v5+) partition_max_bytes
+    */
+    fn decode_fetch_request(&mut self, r: &mut KafkaReader, version: u16) -> Option<()> {
+        if version < 15 {
+            r.skip(4)?;
+        }
+        r.skip(8)?;
+        if version >= 3 {
+            r.skip(4)?;
+        }
+        if version >= 4 {
+            r.skip(1)?;
+        }
+        if version >= 7 {
+            r.skip(8)?;
+        }
+        for _ in 0..r.array_len()? {
+            // v13 开始使用 topic_id 代替 topic 名称
+            let name = if version >= 13 {
+                r.skip(KAFKA_TOPIC_ID_LEN)?;
+                ""
+            } else {
+                r.string()?
+            };
+            let partitions = r.array_len()?;
+            self.info.add_topic(name, partitions);
+            for _ in 0..partitions {
+                r.skip(4)?;
+                if version >= 9 {
+                    r.skip(4)?;
+                }
+                r.skip(8)?;
+                if version >= 12 {
+                    r.skip(4)?;
+                }
+                if version >= 5 {
+                    r.skip(8)?;
+                }
+                r.skip(4)?;
+                r.tagged_fields()?;
+            }
+            r.tagged_fields()?;
+        }
+        Some(())
+    }
+
+    /*
+        Fetch Response => throttle_time_ms(v1+) error_code(v7+) session_id(v7+) [responses]
+          responses => topic(v0-12) | topic_id(v13+) [partitions]
+            partitions => partition_index error_code high_watermark last_stable_offset(v4+)
+                          log_start_offset(v5+) [aborted_transactions](v4+)
+                          preferred_read_replica(v11+) records
+    */
+    fn decode_fetch_response(&mut self, r: &mut KafkaReader, version: u16) -> Option<()> {
+        if version >= 1 {
+            r.skip(4)?;
+        }
+        if version >= 7 {
+            self.info.on_error_code(r.i16()?);
+            r.skip(4)?;
+        }
+        for _ in 0..r.array_len()? {
+            let name = if version >= 13 {
+                r.skip(KAFKA_TOPIC_ID_LEN)?;
+                ""
+            } else {
+                r.string()?
+            };
+            let partitions = r.array_len()?;
+            self.info.add_topic(name, partitions);
+            for _ in 0..partitions {
+                r.skip(4)?;
+                self.info.on_error_code(r.i16()?);
+                r.skip(8)?;
+                if version >= 4 {
+                    r.skip(8)?;
+                }
+                if version >= 5 {
+                    r.skip(8)?;
+                }
+                if version >= 4 {
+                    for _ in 0..r.array_len()? {
+                        r.skip(16)?;
+                        r.tagged_fields()?;
+                    }
+                }
+                if version >= 11 {
+                    r.skip(4)?;
+                }
+                r.bytes()?;
+                r.tagged_fields()?;
+            }
+            r.tagged_fields()?;
+        }
+        Some(())
+    }
+
+    /*
+        Metadata Request => [topics] ...
+          topics => topic_id(v10+) name
+    */
+    fn decode_metadata_request(&mut self, r: &mut KafkaReader, version: u16) -> Option<()> {
+        for _ in 0..r.array_len()? {
+            if version >= 10 {
+                r.skip(KAFKA_TOPIC_ID_LEN)?;
+            }
+            self.info.add_topic(r.string()?, 0);
+            r.tagged_fields()?;
+        }
+        Some(())
+    }
+
+    /*
+        Metadata Response => throttle_time_ms(v3+) [brokers] cluster_id(v2+) controller_id(v1+) [topics]
+          brokers => node_id host port rack(v1+)
+          topics => error_code name topic_id(v10+) is_internal(v1+) [partitions]
+            partitions => error_code partition_index leader_id leader_epoch(v7+) [replica_nodes]
+                          [isr_nodes] [offline_replicas](v5+)
+    */
+    fn decode_metadata_response(&mut self, r: &mut KafkaReader, version: u16) -> Option<()> {
+        if version >= 3 {
+            r.skip(4)?;
+        }
+        for _ in 0..r.array_len()? {
+            r.skip(4)?;
+            r.string()?;
+            r.skip(4)?;
+            if version >= 1 {
+                r.string()?;
+            }
+            r.tagged_fields()?;
+        }
+        if version >= 2 {
+            r.string()?;
+        }
+        if version >= 1 {
+            r.skip(4)?;
+        }
+        for _ in 0..r.array_len()? {
+            self.info.on_error_code(r.i16()?);
+            let name = r.string()?;
+            if version >= 10 {
+                r.skip(KAFKA_TOPIC_ID_LEN)?;
+            }
+            if version >= 1 {
+                r.skip(1)?;
+            }
+            let partitions = r.array_len()?;
+            self.info.add_topic(name, partitions);
+            for _ in 0..partitions {
+                self.info.on_error_code(r.i16()?);
+                r.skip(8)?;
+                if version >= 7 {
+                    r.skip(4)?;
+                }
+                let replica_arrays = if version >= 5 { 3 } else { 2 };
+                for _ in 0..replica_arrays {
+                    let nodes = r.array_len()?;
+                    r.skip(nodes * 4)?;
+                }
+                r.tagged_fields()?;
+            }
+            if version >= 8 {
+                r.skip(4)?;
+            }
+            r.tagged_fields()?;
+        }
+        Some(())
+    }
+
+    /*
+        OffsetCommit Request => group_id generation_id(v1+) member_id(v1+) group_instance_id(v7+)
+                                retention_time_ms(v2-4) [topics]
+          topics => name [partitions]
+            partitions => partition_index committed_offset committed_leader_epoch(v6+)
+                          commit_timestamp(v1) committed_metadata
+    */
+    fn decode_offset_commit_request(&mut self, r: &mut KafkaReader, version: u16) -> Option<()> {
+        r.string()?;
+        if version >= 1 {
+            r.skip(4)?;
+            r.string()?;
+        }
+        if version >= 7 {
+            r.string()?;
+        }
+        if (2..=4).contains(&version) {
+            r.skip(8)?;
+        }
+        for _ in 0..r.array_len()? {
+            let name = r.string()?;
+            let partitions = r.array_len()?;
+            self.info.add_topic(name, partitions);
+            for _ in 0..partitions {
+                r.skip(12)?;
+                if version >= 6 {
+                    r.skip(4)?;
+                }
+                if version == 1 {
+                    r.skip(8)?;
+                }
+                r.string()?;
+                r.tagged_fields()?;
+            }
+            r.tagged_fields()?;
+        }
+        Some(())
+    }
+
+    /*
+        OffsetCommit Response => throttle_time_ms(v3+) [topics]
+          topics => name [partitions]
+            partitions => partition_index error_code
+    */
+    fn decode_offset_commit_response(&mut self, r: &mut KafkaReader, version: u16) -> Option<()> {
+        if version >= 3 {
+            r.skip(4)?;
+        }
+        for _ in 0..r.array_len()? {
+            let name = r.string()?;
+            let partitions = r.array_len()?;
+            self.info.add_topic(name, partitions);
+            for _ in 0..partitions {
+                r.skip(4)?;
+                self.info.on_error_code(r.i16()?);
+                r.tagged_fields()?;
+            }
+            r.tagged_fields()?;
+        }
+        Some(())
+    }
+
+    /*
+        JoinGroup Request => group_id session_timeout_ms rebalance_timeout_ms(v1+) member_id
+                             group_instance_id(v5+) protocol_type [protocols]
+          protocols => name metadata
+
+        protocol_type 为 consumer 时 metadata 为订阅信息：version [topics] user_data ...
+    */
+    fn decode_join_group_request(&mut self, r: &mut KafkaReader, version: u16) -> Option<()> {
+        r.string()?;
+        r.skip(4)?;
+        if version >= 1 {
+            r.skip(4)?;
+        }
+        r.string()?;
+        if version >= 5 {
+            r.string()?;
+        }
+        let protocol_type = r.string()?;
+        if protocol_type != "consumer" || r.array_len()? == 0 {
+            return Some(());
+        }
+        r.string()?;
+        let mut subscription = KafkaReader::new(r.bytes()?, false);
+        subscription.skip(2)?;
+        for _ in 0..subscription.array_len()? {
+            self.info.add_topic(subscription.string()?, 0);
+        }
+        Some(())
+    }
+
+    /*
+        JoinGroup Response => throttle_time_ms(v2+) error_code ...
+    */
+    fn decode_join_group_response(&mut self, r: &mut KafkaReader, version: u16) -> Option<()> {
+        if version >= 2 {
+            r.skip(4)?;
+        }
+        self.info.on_error_code(r.i16()?);
+        Some(())
+    }
+
     pub fn kafka_check_protocol(payload: &[u8], param: &ParseParam) -> bool {
         if param.l4_protocol != IpProtocol::Tcp {
             return false;
@@ -443,6 +1008,89 @@ mod tests {
         output
     }
 
+    fn parse_session(name: &str) -> KafkaInfo {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), Some(1500));
+        let mut packets = capture.as_meta_packets();
+        packets[0].direction = PacketDirection::ClientToServer;
+        packets[1].direction = PacketDirection::ServerToClient;
+
+        let mut kafka = KafkaLog::new();
+        let mut infos = vec![];
+        for packet in packets.iter().take(2) {
+            let param = &ParseParam::from(packet);
+            let payload = packet.get_l4_payload().unwrap();
+            infos.push(kafka.parse_payload(payload, param).unwrap().swap_remove(0));
+            kafka.reset();
+        }
+
+        let resp = infos.pop().unwrap();
+        let mut req = infos.pop().unwrap();
+        assert_eq!(req.session_id(), resp.session_id());
+        req.merge_log(resp).unwrap();
+        if let L7ProtocolInfo::KafkaInfo(info) = req {
+            return info;
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn check_produce() {
+        let info = parse_session("kafka_produce.pcap");
+        assert_eq!(info.get_command(), "Produce");
+        assert_eq!(info.topic_name.as_str(), "orders");
+        assert_eq!(info.partition_count, 2);
+        assert_eq!(info.status_code, Some(6));
+        assert_eq!(info.status, L7ResponseStatus::ServerError);
+    }
+
+    #[test]
+    fn check_fetch() {
+        // Fetch v12 使用 flexible 编码，增量 fetch session 请求中不包含 topic
+        let info = parse_session("kafka_fetch.pcap");
+        assert_eq!(info.get_command(), "Fetch");
+        assert_eq!(info.api_version, 12);
+        assert_eq!(info.topic_name.as_str(), "");
+        assert_eq!(info.status_code, Some(0));
+        assert_eq!(info.status, L7ResponseStatus::Ok);
+    }
+
+    #[test]
+    fn check_topic_names() {
+        let mut info = KafkaInfo::default();
+        info.add_topic("orders", 2);
+        info.add_topic("payments", 1);
+        info.add_topic("orders", 1);
+        assert_eq!(info.topic_name.as_str(), "orders,payments");
+        assert_eq!(info.partition_count, 4);
+
+        info.on_error_code(0);
+        assert_eq!(info.status_code, Some(0));
+        info.on_error_code(3);
+        info.on_error_code(6);
+        assert_eq!(info.status_code, Some(3));
+        assert_eq!(info.status, L7ResponseStatus::ServerError);
+    }
+
+    #[test]
+    fn check_message_length() {
+        let kafka = KafkaLog::default();
+        let param = ParseParam::from(&MetaPacket::default());
+        let mut payload = vec![0, 0, 0, 8, 0, 3, 0, 1, 0, 0, 0];
+        assert_eq!(
+            kafka.message_length(&payload[..3], &param),
+            MessageLength::Partial
+        );
+        assert_eq!(
+            kafka.message_length(&payload, &param),
+            MessageLength::Complete(12)
+        );
+        payload[0] = 0x80;
+        assert_eq!(
+            kafka.message_length(&payload, &param),
+            MessageLength::Unknown
+        );
+    }
+
     #[test]
     fn check() {
         let files = vec![("kafka.pcap", "kafka.result")];
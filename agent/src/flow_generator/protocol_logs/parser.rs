@@ -551,4 +551,14 @@ impl AppProtoLogsParser {
         }
         info!("app protocol logs parser (id={}) stopped", self.id);
     }
+
+    // 等待输入队列关闭后线程自行退出，退出前会发送所有缓存的会话
+    // wait for the thread to exit after the input queue is closed, all cached sessions are sent
+    pub fn join(&self) {
+        if let Some(thread) = self.thread.lock().unwrap().take() {
+            let _ = thread.join();
+        }
+        self.running.store(false, Ordering::SeqCst);
+        info!("app protocol logs parser (id={}) joined", self.id);
+    }
 }
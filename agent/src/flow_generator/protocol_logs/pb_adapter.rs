@@ -39,6 +39,11 @@ pub struct TraceInfo {
     pub trace_id: Option<String>,
     pub span_id: Option<String>,
     pub parent_span_id: Option<String>,
+    // 以下字段以attributes上送
+    // the following fields are sent as attributes
+    pub sampled: Option<bool>,
+    pub trace_state: Option<String>,
+    pub baggage: Option<Vec<KeyVal>>,
 }
 
 #[derive(Debug)]
@@ -57,6 +62,8 @@ pub struct ExtendedInfo {
     pub user_agent: Option<String>,
     pub referer: Option<String>,
     pub attributes: Option<Vec<KeyVal>>,
+    // 仅 WASM 插件使用，区分 L7Protocol::Custom 下的具体协议
+    pub protocol_str: Option<String>,
 }
 
 /*
@@ -113,6 +120,7 @@ impl L7ProtocolSendLog {
             log.version = version.into();
         }
 
+        let mut trace_attributes = vec![];
         if let Some(trace_info) = self.trace_info {
             let mut t = flow_log::TraceInfo::default();
             if let Some(s) = trace_info.span_id {
@@ -124,6 +132,26 @@ impl L7ProtocolSendLog {
             if let Some(s) = trace_info.trace_id {
                 t.trace_id = s.into();
             }
+            if let Some(sampled) = trace_info.sampled {
+                trace_attributes.push(KeyVal {
+                    key: "trace_sampled".into(),
+                    val: sampled.to_string(),
+                });
+            }
+            if let Some(s) = trace_info.trace_state {
+                trace_attributes.push(KeyVal {
+                    key: "tracestate".into(),
+                    val: s,
+                });
+            }
+            if let Some(baggage) = trace_info.baggage {
+                for kv in baggage.into_iter() {
+                    trace_attributes.push(KeyVal {
+                        key: format!("baggage.{}", kv.key),
+                        val: kv.val,
+                    });
+                }
+            }
 
             log.trace_info = Some(t);
         }
@@ -152,6 +180,9 @@ impl L7ProtocolSendLog {
             if let Some(referer) = ext.referer {
                 ext_info.http_referer = referer;
             }
+            if let Some(s) = ext.protocol_str {
+                ext_info.protocol_str = s;
+            }
             if let Some(attr) = ext.attributes {
                 for kv in attr.into_iter() {
                     ext_info.attribute_names.push(kv.key);
@@ -160,5 +191,13 @@ impl L7ProtocolSendLog {
             }
             log.ext_info = Some(ext_info);
         }
+
+        if !trace_attributes.is_empty() {
+            let ext_info = log.ext_info.get_or_insert_with(Default::default);
+            for kv in trace_attributes.into_iter() {
+                ext_info.attribute_names.push(kv.key);
+                ext_info.attribute_values.push(kv.val);
+            }
+        }
     }
 }
@@ -0,0 +1,257 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use serde::{Deserialize, Serialize};
+
+use super::super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+
+use crate::common::flow::L7Protocol;
+use crate::common::l7_protocol_info::L7ProtocolInfo;
+use crate::common::l7_protocol_info::L7ProtocolInfoInterface;
+use crate::flow_generator::error::Result;
+use crate::flow_generator::protocol_logs::pb_adapter::{
+    ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response,
+};
+use crate::log_info_merge;
+
+/*
+ 插件返回的一条协议日志，由 parse_payload 以 JSON 数组的形式输出，所有字段均可省略:
+ a protocol log returned by plugin, parse_payload outputs a JSON array of it, every field is optional:
+
+ [{
+     "msg_type": "request",          // request | response | session
+     "session_id": 1,                // 用于请求和响应的聚合 / used to merge request and response
+     "req_type": "GET",
+     "domain": "",
+     "resource": "/api/v1/orders",
+     "endpoint": "",
+     "req_len": 100,
+     "resp_len": 200,
+     "status": "ok",                 // ok | not_exist | server_error | client_error
+     "code": 0,
+     "exception": "",
+     "result": "",
+     "request_id": 1,
+     "attributes": [{"key": "k", "val": "v"}]
+ }]
+*/
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct CustomRecord {
+    pub msg_type: String,
+    pub session_id: Option<u32>,
+    pub req_type: String,
+    pub domain: String,
+    pub resource: String,
+    pub endpoint: String,
+    pub req_len: Option<u32>,
+    pub resp_len: Option<u32>,
+    pub status: String,
+    pub code: Option<i32>,
+    pub exception: String,
+    pub result: String,
+    pub request_id: Option<u32>,
+    pub attributes: Vec<CustomAttribute>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct CustomAttribute {
+    pub key: String,
+    pub val: String,
+}
+
+// 插件解析出的通用协议日志，所有插件共用 L7Protocol::Custom，通过 proto_str 区分
+#[derive(Serialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct CustomInfo {
+    #[serde(rename = "protocol_str")]
+    pub proto_str: String,
+    #[serde(skip)]
+    pub msg_type: LogMessageType,
+    #[serde(skip)]
+    pub session_id: Option<u32>,
+
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub req_type: String,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub domain: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub resource: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub endpoint: String,
+    #[serde(rename = "request_id", skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u32>,
+    #[serde(rename = "request_length", skip_serializing_if = "Option::is_none")]
+    pub req_len: Option<u32>,
+
+    #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
+    pub resp_len: Option<u32>,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub code: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub exception: String,
+    #[serde(rename = "response_result", skip_serializing_if = "value_is_default")]
+    pub result: String,
+
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<CustomAttribute>,
+
+    pub start_time: u64,
+    pub end_time: u64,
+    pub is_tls: bool,
+}
+
+impl CustomInfo {
+    pub fn new(proto_str: &str, record: CustomRecord) -> Self {
+        Self {
+            proto_str: proto_str.to_string(),
+            msg_type: match record.msg_type.as_str() {
+                "request" => LogMessageType::Request,
+                "response" => LogMessageType::Response,
+                "session" => LogMessageType::Session,
+                _ => LogMessageType::Other,
+            },
+            session_id: record.session_id,
+            req_type: record.req_type,
+            domain: record.domain,
+            resource: record.resource,
+            endpoint: record.endpoint,
+            request_id: record.request_id,
+            req_len: record.req_len,
+            resp_len: record.resp_len,
+            status: match record.status.as_str() {
+                "not_exist" => L7ResponseStatus::NotExist,
+                "server_error" => L7ResponseStatus::ServerError,
+                "client_error" => L7ResponseStatus::ClientError,
+                _ => L7ResponseStatus::Ok,
+            },
+            code: record.code,
+            exception: record.exception,
+            result: record.result,
+            attributes: record.attributes,
+            ..Default::default()
+        }
+    }
+
+    pub fn merge(&mut self, other: Self) {
+        if self.req_type.is_empty() {
+            self.req_type = other.req_type;
+        }
+        if self.domain.is_empty() {
+            self.domain = other.domain;
+        }
+        if self.resource.is_empty() {
+            self.resource = other.resource;
+        }
+        if self.endpoint.is_empty() {
+            self.endpoint = other.endpoint;
+        }
+        if self.request_id.is_none() {
+            self.request_id = other.request_id;
+        }
+        if self.req_len.is_none() {
+            self.req_len = other.req_len;
+        }
+        if other.resp_len.is_some() {
+            self.resp_len = other.resp_len;
+        }
+        if other.status != L7ResponseStatus::default() {
+            self.status = other.status;
+        }
+        if other.code.is_some() {
+            self.code = other.code;
+        }
+        if !other.exception.is_empty() {
+            self.exception = other.exception;
+        }
+        if !other.result.is_empty() {
+            self.result = other.result;
+        }
+        self.attributes.extend(other.attributes);
+    }
+}
+
+impl L7ProtocolInfoInterface for CustomInfo {
+    fn session_id(&self) -> Option<u32> {
+        self.session_id
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, CustomInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Custom,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+
+    fn skip_send(&self) -> bool {
+        false
+    }
+}
+
+impl From<CustomInfo> for L7ProtocolSendLog {
+    fn from(f: CustomInfo) -> Self {
+        let attributes = f
+            .attributes
+            .into_iter()
+            .map(|a| KeyVal {
+                key: a.key,
+                val: a.val,
+            })
+            .collect::<Vec<_>>();
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: f.req_type,
+                domain: f.domain,
+                resource: f.resource,
+                endpoint: f.endpoint,
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.code,
+                exception: f.exception,
+                result: f.result,
+            },
+            ext_info: Some(ExtendedInfo {
+                request_id: f.request_id,
+                protocol_str: Some(f.proto_str),
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
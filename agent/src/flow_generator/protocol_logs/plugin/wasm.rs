@@ -0,0 +1,591 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::cell::RefCell;
+use std::fs;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use arc_swap::ArcSwapOption;
+use log::{debug, info, warn};
+use wasmtime::{
+    Config, Engine, InstancePre, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+    TypedFunc,
+};
+
+use super::custom_info::{CustomInfo, CustomRecord};
+
+use crate::common::flow::L7Protocol;
+use crate::common::l7_protocol_info::L7ProtocolInfo;
+use crate::common::l7_protocol_log::L7ProtocolParserInterface;
+use crate::common::l7_protocol_log::ParseParam;
+use crate::config::handler::{LogParserAccess, WasmPluginConfig};
+use crate::flow_generator::error::{Error, Result};
+
+/*
+ WASM 插件接口，插件目录下的每个 *.wasm 文件为一个插件，文件名（不含扩展名）作为协议名称。
+ 插件需要导出以下符号:
+
+ wasm plugin ABI, every *.wasm file in plugin directory is a plugin, the file stem is used as protocol name.
+ the plugin must export:
+
+     memory
+     alloc(size: i32) -> i32
+     check_payload(ptr: i32, len: i32, l4_protocol: i32, direction: i32, port_src: i32, port_dst: i32) -> i32
+     parse_payload(ptr: i32, len: i32, l4_protocol: i32, direction: i32, port_src: i32, port_dst: i32) -> i64
+
+ agent 先调用 alloc 申请内存并写入 payload，然后调用 check_payload 或 parse_payload.
+ check_payload 返回非 0 表示匹配。parse_payload 返回值小于 0 表示解析失败，否则高 32 位为结果的地址，
+ 低 32 位为结果的长度，结果为 CustomRecord 的 JSON 数组，参考 custom_info.rs。
+ 每个线程复用插件的实例，插件的内存在调用之间保留。每次调用受 fuel 和内存大小的限制，超出限制视为匹配/解析失败，
+ 调用失败后实例会被丢弃并在下次调用时重建。
+
+ agent calls alloc to get a buffer and writes payload into it, then calls check_payload or parse_payload.
+ check_payload returns non-zero if matched. parse_payload returns negative number on failure,
+ otherwise the high 32 bits is the address of result and the low 32 bits is the length,
+ the result is a JSON array of CustomRecord, see custom_info.rs.
+ plugin instances are reused by each thread, so the plugin memory persists across calls. every call is limited by fuel
+ and memory size, exceeding the limits is regarded as mismatch or failure. an instance is dropped after a failed call
+ and recreated on the next call.
+*/
+const EXPORT_MEMORY: &str = "memory";
+const EXPORT_ALLOC: &str = "alloc";
+const EXPORT_CHECK_PAYLOAD: &str = "check_payload";
+const EXPORT_PARSE_PAYLOAD: &str = "parse_payload";
+
+const WASM_FILE_EXTENSION: &str = "wasm";
+
+// (ptr, len, l4_protocol, direction, port_src, port_dst)
+type PayloadArgs = (i32, i32, i32, i32, i32, i32);
+
+struct StoreData {
+    limits: StoreLimits,
+}
+
+struct WasmPlugin {
+    name: String,
+    instance_pre: InstancePre<StoreData>,
+}
+
+struct PluginInstance {
+    store: Store<StoreData>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    check_payload: TypedFunc<PayloadArgs, i32>,
+    parse_payload: TypedFunc<PayloadArgs, i64>,
+}
+
+static WASM_VM_ID: AtomicU64 = AtomicU64::new(1);
+
+pub struct WasmVm {
+    // 每次加载插件分配新的id，用于判断线程缓存的实例是否过期
+    // a new id is assigned every time plugins are loaded, used to expire instances cached by threads
+    id: u64,
+    engine: Engine,
+    plugins: Vec<WasmPlugin>,
+    fuel_limit: u64,
+    memory_limit: usize,
+}
+
+thread_local! {
+    // 当前线程的插件实例，按插件序号索引
+    // plugin instances of the current thread, indexed by plugin index
+    static PLUGIN_INSTANCES: RefCell<(u64, Vec<Option<PluginInstance>>)> =
+        const { RefCell::new((0, vec![])) };
+}
+
+impl WasmVm {
+    fn new(conf: &WasmPluginConfig) -> anyhow::Result<Self> {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+        Ok(Self {
+            id: WASM_VM_ID.fetch_add(1, Ordering::Relaxed),
+            engine: Engine::new(&config)?,
+            plugins: vec![],
+            fuel_limit: conf.fuel_limit,
+            memory_limit: conf.memory_limit,
+        })
+    }
+
+    fn load_dir(conf: &WasmPluginConfig) -> anyhow::Result<Self> {
+        let mut vm = Self::new(conf)?;
+        let mut paths = fs::read_dir(&conf.dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().map(|e| e == WASM_FILE_EXTENSION) == Some(true))
+            .collect::<Vec<_>>();
+        // 按文件名排序，保证插件的匹配顺序固定
+        paths.sort();
+        for path in paths.iter() {
+            let name = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(name) => name.to_string(),
+                None => continue,
+            };
+            match fs::read(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| vm.add_plugin(&name, &bytes))
+            {
+                Ok(_) => info!("wasm plugin {} loaded from {}", name, path.display()),
+                Err(e) => warn!("wasm plugin {} load failed: {}", path.display(), e),
+            }
+        }
+        Ok(vm)
+    }
+
+    fn add_plugin(&mut self, name: &str, bytes: &[u8]) -> anyhow::Result<()> {
+        let module = Module::new(&self.engine, bytes)?;
+        for export in [
+            EXPORT_MEMORY,
+            EXPORT_ALLOC,
+            EXPORT_CHECK_PAYLOAD,
+            EXPORT_PARSE_PAYLOAD,
+        ] {
+            if module.get_export(export).is_none() {
+                return Err(anyhow::anyhow!("export {} not found", export));
+            }
+        }
+        // 不向插件提供任何 import，插件无法访问宿主的资源
+        let linker = Linker::new(&self.engine);
+        self.plugins.push(WasmPlugin {
+            name: name.to_string(),
+            instance_pre: linker.instantiate_pre(&module)?,
+        });
+        Ok(())
+    }
+
+    fn plugin_count(&self) -> usize {
+        self.plugins.len()
+    }
+
+    fn plugin_name(&self, index: usize) -> &str {
+        &self.plugins[index].name
+    }
+
+    fn instantiate(&self, index: usize) -> anyhow::Result<PluginInstance> {
+        let mut store = Store::new(
+            &self.engine,
+            StoreData {
+                limits: StoreLimitsBuilder::new()
+                    .memory_size(self.memory_limit)
+                    .instances(1)
+                    .build(),
+            },
+        );
+        store.limiter(|data| &mut data.limits);
+
+        let instance = self.plugins[index].instance_pre.instantiate(&mut store)?;
+        let memory = instance
+            .get_memory(&mut store, EXPORT_MEMORY)
+            .ok_or(anyhow::anyhow!("memory not found"))?;
+        Ok(PluginInstance {
+            alloc: instance.get_typed_func(&mut store, EXPORT_ALLOC)?,
+            check_payload: instance.get_typed_func(&mut store, EXPORT_CHECK_PAYLOAD)?,
+            parse_payload: instance.get_typed_func(&mut store, EXPORT_PARSE_PAYLOAD)?,
+            store,
+            memory,
+        })
+    }
+
+    // 使用当前线程的实例调用插件，调用前补满 fuel 并写入 payload，调用失败时丢弃实例
+    // calls the plugin with the instance of the current thread, fuel is refilled and payload is
+    // written before the call, the instance is dropped if the call fails
+    fn call<T, F>(
+        &self,
+        index: usize,
+        payload: &[u8],
+        param: &ParseParam,
+        f: F,
+    ) -> anyhow::Result<T>
+    where
+        F: FnOnce(&mut PluginInstance, PayloadArgs) -> anyhow::Result<T>,
+    {
+        PLUGIN_INSTANCES.with(|instances| {
+            let mut instances = instances.borrow_mut();
+            if instances.0 != self.id {
+                *instances = (self.id, self.plugins.iter().map(|_| None).collect());
+            }
+            let slot = &mut instances.1[index];
+            if slot.is_none() {
+                *slot = Some(self.instantiate(index)?);
+            }
+            let instance = slot.as_mut().unwrap();
+
+            let result = (|| {
+                let remaining = instance.store.consume_fuel(0)?;
+                instance
+                    .store
+                    .add_fuel(self.fuel_limit.saturating_sub(remaining))?;
+                let ptr = instance
+                    .alloc
+                    .call(&mut instance.store, payload.len() as i32)?;
+                instance
+                    .memory
+                    .write(&mut instance.store, ptr as u32 as usize, payload)?;
+                let args = (
+                    ptr,
+                    payload.len() as i32,
+                    u8::from(param.l4_protocol) as i32,
+                    param.direction as i32,
+                    param.port_src as i32,
+                    param.port_dst as i32,
+                );
+                f(instance, args)
+            })();
+            if result.is_err() {
+                *slot = None;
+            }
+            result
+        })
+    }
+
+    fn check_payload(&self, index: usize, payload: &[u8], param: &ParseParam) -> bool {
+        let result = self.call(index, payload, param, |instance, args| {
+            Ok(instance.check_payload.call(&mut instance.store, args)? != 0)
+        });
+        match result {
+            Ok(matched) => matched,
+            Err(e) => {
+                debug!(
+                    "wasm plugin {} check payload failed: {}",
+                    self.plugin_name(index),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    fn parse_payload(
+        &self,
+        index: usize,
+        payload: &[u8],
+        param: &ParseParam,
+    ) -> anyhow::Result<Vec<CustomRecord>> {
+        self.call(index, payload, param, |instance, args| {
+            let ret = instance.parse_payload.call(&mut instance.store, args)?;
+            if ret < 0 {
+                return Err(anyhow::anyhow!("parse payload returns {}", ret));
+            }
+
+            let (ptr, len) = ((ret >> 32) as u32 as usize, ret as u32 as usize);
+            let data = instance.memory.data(&instance.store);
+            if ptr + len > data.len() {
+                return Err(anyhow::anyhow!(
+                    "result out of memory bounds, ptr: {} len: {}",
+                    ptr,
+                    len
+                ));
+            }
+            Ok(serde_json::from_slice(&data[ptr..ptr + len])?)
+        })
+    }
+}
+
+// 插件在配置线程中加载，加载完成后原子替换，所有解析器共享
+// plugins are loaded on the config thread and swapped in atomically, shared by all parsers
+static WASM_VM: ArcSwapOption<WasmVm> = ArcSwapOption::const_empty();
+
+// 配置变化时调用，加载目录中的插件，目录为空时卸载所有插件
+// called when the config changes, loads plugins in the directory, or unloads all plugins
+// if the directory is empty
+pub fn update_wasm_plugins(conf: &WasmPluginConfig) {
+    if conf.dir.is_empty() {
+        if WASM_VM.swap(None).is_some() {
+            info!("wasm plugins unloaded");
+        }
+        return;
+    }
+    let vm = match WasmVm::load_dir(conf) {
+        Ok(vm) if vm.plugin_count() > 0 => Some(Arc::new(vm)),
+        Ok(_) => {
+            warn!("no wasm plugin found in {}", conf.dir);
+            None
+        }
+        Err(e) => {
+            warn!("load wasm plugins from {} failed: {}", conf.dir, e);
+            None
+        }
+    };
+    WASM_VM.store(vm);
+}
+
+#[derive(Default)]
+pub struct WasmLog {
+    vm: Option<Arc<WasmVm>>,
+    // check_payload 匹配到的插件，解析成功时 reset 保留，解析失败后重新匹配
+    // the plugin matched by check_payload, kept on reset if parsing succeeded,
+    // matched again after a parse failure
+    plugin: Option<usize>,
+    parse_failed: bool,
+}
+
+impl L7ProtocolParserInterface for WasmLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        let vm = match self.vm.as_ref() {
+            Some(vm) => vm,
+            None => return false,
+        };
+        self.plugin = (0..vm.plugin_count()).find(|i| vm.check_payload(*i, payload, param));
+        self.plugin.is_some()
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if self.plugin.is_none() && !self.check_payload(payload, param) {
+            return Err(Error::WasmPluginParseFailed(String::from(
+                "no plugin matched",
+            )));
+        }
+        let vm = self.vm.as_ref().unwrap();
+        let index = self.plugin.unwrap();
+        let name = vm.plugin_name(index);
+        let records = match vm.parse_payload(index, payload, param) {
+            Ok(records) => records,
+            Err(e) => {
+                self.parse_failed = true;
+                return Err(Error::WasmPluginParseFailed(format!("{}: {}", name, e)));
+            }
+        };
+
+        Ok(records
+            .into_iter()
+            .map(|record| {
+                let mut info = CustomInfo::new(name, record);
+                info.start_time = param.time;
+                info.end_time = param.time;
+                info.is_tls = param.is_tls();
+                L7ProtocolInfo::CustomInfo(info)
+            })
+            .collect())
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Custom
+    }
+
+    // 只读取已加载的插件，不在报文处理路径上加载
+    // only takes the loaded plugins, they are never loaded on the packet path
+    fn set_parse_config(&mut self, _: &LogParserAccess) {
+        let changed = match (self.vm.as_ref(), WASM_VM.load().as_ref()) {
+            (Some(a), Some(b)) => !Arc::ptr_eq(a, b),
+            (None, None) => false,
+            _ => true,
+        };
+        if changed {
+            self.vm = WASM_VM.load_full();
+            self.plugin = None;
+        }
+    }
+
+    fn reset(&mut self) {
+        if self.parse_failed {
+            self.plugin = None;
+            self.parse_failed = false;
+        }
+    }
+}
+
+impl WasmLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use arc_swap::{access::Map, ArcSwap};
+
+    use super::*;
+
+    use crate::common::ebpf::EbpfType;
+    use crate::common::enums::IpProtocol;
+    use crate::common::flow::PacketDirection;
+    use crate::common::l7_protocol_info::L7ProtocolInfoInterface;
+    use crate::config::handler::ModuleConfig;
+    use crate::flow_generator::protocol_logs::LogMessageType;
+
+    // 第一个字节为 0x7f 的报文被识别为该协议，parse_payload 返回 data 段中的固定结果
+    const ECHO_PLUGIN: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $heap (mut i32) (i32.const 1024))
+            (data (i32.const 0) "[{\"msg_type\":\"request\",\"session_id\":7,\"req_type\":\"GET\",\"resource\":\"/orders\",\"attributes\":[{\"key\":\"tenant\",\"val\":\"a\"}]}]")
+            (func (export "alloc") (param $size i32) (result i32)
+                (local $ptr i32)
+                (local.set $ptr (global.get $heap))
+                (global.set $heap (i32.add (global.get $heap) (local.get $size)))
+                (local.get $ptr))
+            (func (export "check_payload") (param $ptr i32) (param $len i32) (param i32 i32 i32 i32) (result i32)
+                (if (result i32) (i32.gt_s (local.get $len) (i32.const 0))
+                    (then (i32.eq (i32.load8_u (local.get $ptr)) (i32.const 0x7f)))
+                    (else (i32.const 0))))
+            (func (export "parse_payload") (param i32 i32 i32 i32 i32 i32) (result i64)
+                (i64.const 119)))
+    "#;
+
+    const LOOP_PLUGIN: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (func (export "alloc") (param i32) (result i32) (i32.const 0))
+            (func (export "check_payload") (param i32 i32 i32 i32 i32 i32) (result i32)
+                (loop $forever (br $forever))
+                (i32.const 1))
+            (func (export "parse_payload") (param i32 i32 i32 i32 i32 i32) (result i64)
+                (i64.const -1)))
+    "#;
+
+    // 第二次及之后的调用匹配，parse_payload 总是 trap
+    const COUNTER_PLUGIN: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (global $calls (mut i32) (i32.const 0))
+            (func (export "alloc") (param i32) (result i32) (i32.const 0))
+            (func (export "check_payload") (param i32 i32 i32 i32 i32 i32) (result i32)
+                (global.set $calls (i32.add (global.get $calls) (i32.const 1)))
+                (i32.ge_s (global.get $calls) (i32.const 2)))
+            (func (export "parse_payload") (param i32 i32 i32 i32 i32 i32) (result i64)
+                (unreachable)))
+    "#;
+
+    fn new_vm(plugins: &[(&str, &str)]) -> WasmVm {
+        let conf = WasmPluginConfig {
+            dir: String::new(),
+            fuel_limit: 1_000_000,
+            memory_limit: 1 << 20,
+        };
+        let mut vm = WasmVm::new(&conf).unwrap();
+        for (name, wat) in plugins {
+            vm.add_plugin(name, wat.as_bytes()).unwrap();
+        }
+        vm
+    }
+
+    fn new_param() -> ParseParam {
+        ParseParam {
+            l4_protocol: IpProtocol::Tcp,
+            ip_src: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            ip_dst: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+            port_src: 45678,
+            port_dst: 9000,
+            direction: PacketDirection::ClientToServer,
+            ebpf_type: EbpfType::None,
+            ebpf_param: None,
+            time: 100,
+        }
+    }
+
+    #[test]
+    fn check_and_parse() {
+        let vm = new_vm(&[("echo", ECHO_PLUGIN)]);
+        let mut parser = WasmLog {
+            vm: Some(Arc::new(vm)),
+            ..Default::default()
+        };
+        let param = new_param();
+
+        assert!(!parser.check_payload(b"\x00hello", &param));
+        assert!(parser.check_payload(b"\x7fhello", &param));
+
+        let infos = parser.parse_payload(b"\x7fhello", &param).unwrap();
+        assert_eq!(infos.len(), 1);
+        let info = match &infos[0] {
+            L7ProtocolInfo::CustomInfo(info) => info,
+            _ => unreachable!(),
+        };
+        assert_eq!(info.proto_str, "echo");
+        assert_eq!(info.msg_type, LogMessageType::Request);
+        assert_eq!(info.session_id(), Some(7));
+        assert_eq!(info.req_type, "GET");
+        assert_eq!(info.resource, "/orders");
+        assert_eq!(info.attributes.len(), 1);
+        assert_eq!(info.start_time, 100);
+    }
+
+    #[test]
+    fn check_fuel_limit() {
+        let mut vm = new_vm(&[("loop", LOOP_PLUGIN), ("echo", ECHO_PLUGIN)]);
+        vm.fuel_limit = 10000;
+        let mut parser = WasmLog {
+            vm: Some(Arc::new(vm)),
+            ..Default::default()
+        };
+
+        // 死循环的插件耗尽 fuel 后被跳过
+        assert!(parser.check_payload(b"\x7fhello", &new_param()));
+        assert_eq!(parser.plugin, Some(1));
+    }
+
+    #[test]
+    fn check_missing_export() {
+        let mut vm = new_vm(&[]);
+        assert!(vm
+            .add_plugin(
+                "empty",
+                r#"(module (memory (export "memory") 1))"#.as_bytes()
+            )
+            .is_err());
+        assert_eq!(vm.plugin_count(), 0);
+    }
+
+    #[test]
+    fn check_instance_reuse() {
+        let vm = Arc::new(new_vm(&[("counter", COUNTER_PLUGIN)]));
+        let param = new_param();
+
+        // 同一线程的调用复用实例，插件的状态被保留
+        assert!(!vm.check_payload(0, b"hello", &param));
+        assert!(vm.check_payload(0, b"hello", &param));
+
+        // 调用失败后实例被丢弃，匹配到的插件在 reset 后重新匹配
+        let mut parser = WasmLog {
+            vm: Some(vm.clone()),
+            plugin: Some(0),
+            ..Default::default()
+        };
+        assert!(parser.parse_payload(b"hello", &param).is_err());
+        parser.reset();
+        assert_eq!(parser.plugin, None);
+        assert!(!vm.check_payload(0, b"hello", &param));
+    }
+
+    #[test]
+    fn update_plugins() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("echo.wasm"), ECHO_PLUGIN).unwrap();
+        let mut conf = WasmPluginConfig {
+            dir: dir.path().to_str().unwrap().to_owned(),
+            fuel_limit: 1_000_000,
+            memory_limit: 1 << 20,
+        };
+        let current_config = Arc::new(ArcSwap::from_pointee(ModuleConfig::default()));
+        let log_parser_config: LogParserAccess =
+            Map::new(current_config, |config| &config.log_parser);
+        let mut parser = WasmLog::new();
+
+        update_wasm_plugins(&conf);
+        parser.set_parse_config(&log_parser_config);
+        assert!(parser.check_payload(b"\x7fhello", &new_param()));
+        assert_eq!(parser.vm.as_ref().unwrap().plugin_name(0), "echo");
+
+        conf.dir = String::new();
+        update_wasm_plugins(&conf);
+        parser.set_parse_config(&log_parser_config);
+        assert!(parser.vm.is_none());
+        assert!(!parser.check_payload(b"\x7fhello", &new_param()));
+    }
+}
@@ -21,7 +21,8 @@ use log::debug;
 use serde::Serialize;
 
 use super::super::{
-    consts::*, value_is_default, value_is_negative, AppProtoHead, L7ResponseStatus, LogMessageType,
+    consts::*, value_is_default, value_is_negative, AppProtoHead, HttpLog, L7ResponseStatus,
+    LogMessageType, TraceContext,
 };
 
 use crate::common::enums::IpProtocol;
@@ -34,13 +35,27 @@ use crate::common::l7_protocol_log::ParseParam;
 use crate::config::handler::{L7LogDynamicConfig, LogParserAccess, TraceType};
 use crate::flow_generator::error::{Error, Result};
 use crate::flow_generator::protocol_logs::pb_adapter::{
-    ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response, TraceInfo,
+    ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response,
 };
 use crate::log_info_merge;
 use crate::parse_common;
-use crate::utils::bytes::{read_u32_be, read_u64_be};
+use crate::utils::bytes::{read_u16_be, read_u32_be, read_u64_be};
 
 const TRACE_ID_MAX_LEN: usize = 1024;
+// attachment中可能携带传播上下文的key及其小写形式
+// attachment keys that may carry propagation context and their lowercase forms
+const TRACE_CONTEXT_KEYS: [(&str, &str); 10] = [
+    ("traceparent", "traceparent"),
+    ("tracestate", "tracestate"),
+    ("b3", "b3"),
+    ("X-B3-ParentSpanId", "x-b3-parentspanid"),
+    ("X-B3-Sampled", "x-b3-sampled"),
+    ("X-B3-Flags", "x-b3-flags"),
+    ("uber-trace-id", "uber-trace-id"),
+    ("sw6", "sw6"),
+    ("sw8", "sw8"),
+    ("baggage", "baggage"),
+];
 
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct DubboInfo {
@@ -75,6 +90,8 @@ pub struct DubboInfo {
     pub trace_id: String,
     #[serde(skip_serializing_if = "value_is_default")]
     pub span_id: String,
+    #[serde(flatten)]
+    pub trace_context: TraceContext,
 
     // resp
     #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
@@ -83,6 +100,11 @@ pub struct DubboInfo {
     pub resp_status: L7ResponseStatus,
     #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
     pub status_code: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub exception: String,
 }
 
 impl DubboInfo {
@@ -96,6 +118,9 @@ impl DubboInfo {
         if self.status_code.is_none() {
             self.status_code = other.status_code;
         }
+        if self.exception.is_empty() {
+            self.exception = other.exception;
+        }
     }
 }
 
@@ -142,13 +167,10 @@ impl From<DubboInfo> for L7ProtocolSendLog {
             resp: L7Response {
                 status: f.resp_status,
                 code: f.status_code,
+                exception: f.exception,
                 ..Default::default()
             },
-            trace_info: Some(TraceInfo {
-                trace_id: Some(f.trace_id),
-                span_id: Some(f.span_id),
-                ..Default::default()
-            }),
+            trace_info: Some(f.trace_context.into_trace_info(f.trace_id, f.span_id)),
             ext_info: Some(ExtendedInfo {
                 rpc_service: Some(f.service_name),
                 request_id: Some(f.request_id as u32),
@@ -226,6 +248,8 @@ impl DubboLog {
         self.info.resp_msg_size = None;
         self.info.resp_status = L7ResponseStatus::Ok;
         self.info.status_code = None;
+        self.info.exception = String::new();
+        self.info.trace_context = TraceContext::default();
     }
 
     fn check_char_boundary(payload: &Cow<'_, str>, start: usize, end: usize) -> bool {
@@ -279,17 +303,13 @@ impl DubboLog {
         return None;
     }
 
-    // 注意 dubbo trace id 解析是区分大小写的
-    fn decode_trace_id(payload: &Cow<'_, str>, trace_type: &TraceType, info: &mut DubboInfo) {
-        let tag = match trace_type {
-            TraceType::Sw8 => TraceType::Sw8.to_string(),
-            TraceType::Customize(tag) => tag.to_string(),
-            _ => return,
-        };
-
+    // 查找attachment中tag对应的值，tag按hessian2短字符串编码，长度不会超过256
+    // Looks up the value of `tag` in the attachments, the tag is encoded as a hessian2 short
+    // string whose length does not exceed 256
+    fn decode_attachment(payload: &Cow<'_, str>, tag: &str) -> Option<String> {
         let mut start = 0;
         while start < payload.len() {
-            let index = payload[start..].find(tag.as_str());
+            let index = payload[start..].find(tag);
             if index.is_none() {
                 break;
             }
@@ -307,14 +327,29 @@ impl DubboLog {
                 continue;
             }
 
-            if let Some(trace_id) =
-                Self::decode_field(payload, start + index + tag.len(), last_index)
+            if let Some(value) = Self::decode_field(payload, start + index + tag.len(), last_index)
             {
-                info.trace_id = trace_id;
-                break;
+                return Some(value);
             }
             start += index + tag.len();
         }
+        None
+    }
+
+    // 注意 dubbo trace id 解析是区分大小写的
+    fn decode_trace_id(payload: &Cow<'_, str>, trace_type: &TraceType, info: &mut DubboInfo) {
+        let tag = match trace_type {
+            TraceType::Sw8 => TraceType::Sw8.to_string(),
+            TraceType::Customize(tag) => tag.to_string(),
+            TraceType::XB3 | TraceType::Uber | TraceType::TraceParent | TraceType::B3 => {
+                trace_type.to_string()
+            }
+            _ => return,
+        };
+
+        if let Some(trace_id) = Self::decode_attachment(payload, tag.as_str()) {
+            info.trace_id = trace_id;
+        }
 
         match trace_type {
             TraceType::Sw8 => {
@@ -324,6 +359,10 @@ impl DubboLog {
                     }
                 }
             }
+            TraceType::Uber | TraceType::TraceParent | TraceType::B3 => {
+                info.trace_id =
+                    HttpLog::decode_id(&info.trace_id, &tag, HttpLog::TRACE_ID).unwrap_or_default();
+            }
             _ => return,
         };
     }
@@ -332,36 +371,14 @@ impl DubboLog {
         let tag = match trace_type {
             TraceType::Customize(tag) => tag.to_string(),
             TraceType::Sw8 => TraceType::Sw8.to_string(),
+            TraceType::XB3Span | TraceType::Uber | TraceType::TraceParent | TraceType::B3 => {
+                trace_type.to_string()
+            }
             _ => return,
         };
 
-        let mut start = 0;
-        while start < payload.len() {
-            let index = payload[start..].find(tag.as_str());
-            if index.is_none() {
-                break;
-            }
-            let index = index.unwrap();
-            // 注意这里tag长度不会超过256
-            if index == 0 || tag.len() != payload.as_bytes()[start + index - 1] as usize {
-                start += index + tag.len();
-                continue;
-            }
-            let last_index = payload
-                .len()
-                .min(TRACE_ID_MAX_LEN + start + index + tag.len());
-            if Self::check_char_boundary(&payload, start + index, last_index) {
-                start += index + tag.len();
-                continue;
-            }
-
-            if let Some(span_id) =
-                Self::decode_field(payload, start + index + tag.len(), last_index)
-            {
-                info.span_id = span_id;
-                break;
-            }
-            start += index + tag.len();
+        if let Some(span_id) = Self::decode_attachment(payload, tag.as_str()) {
+            info.span_id = span_id;
         }
 
         match trace_type {
@@ -388,55 +405,75 @@ impl DubboLog {
                     }
                 }
             }
+            TraceType::Uber | TraceType::TraceParent | TraceType::B3 => {
+                info.span_id =
+                    HttpLog::decode_id(&info.span_id, &tag, HttpLog::SPAN_ID).unwrap_or_default();
+            }
             _ => return,
         };
     }
 
+    // 同时尝试原始大小写和小写的key，不同的SDK对X-B3-*的大小写处理不一致
+    // Both the original and the lowercase key are tried since SDKs disagree on the case of X-B3-*
+    // 只查找已开启的格式的key
+    // only keys of the enabled formats are looked up
+    fn decode_trace_context(&mut self, payload: &Cow<'_, str>) {
+        for (key, lower_key) in TRACE_CONTEXT_KEYS {
+            if !HttpLog::is_trace_context_enabled(&self.l7_log_dynamic_config, lower_key) {
+                continue;
+            }
+            let value = Self::decode_attachment(payload, key).or_else(|| {
+                if lower_key != key {
+                    Self::decode_attachment(payload, lower_key)
+                } else {
+                    None
+                }
+            });
+            if let Some(value) = value {
+                HttpLog::decode_trace_context(
+                    &self.l7_log_dynamic_config,
+                    lower_key,
+                    &value,
+                    &mut self.info.trace_context,
+                );
+            }
+        }
+    }
+
     // 尽力而为的去解析Dubbo请求中Body各参数
     fn get_req_body_info(&mut self, payload: &[u8]) {
+        let serial_id = self.info.serial_id;
         let mut n = BODY_PARAM_MIN;
         let mut para_index = 0;
         let payload_len = payload.len();
 
         while n < BODY_PARAM_MAX {
-            let (offset, para_len) = get_req_param_len(&payload[para_index..]);
-            para_index += offset;
-            if para_len == 0 || para_len + para_index > payload_len {
-                return;
-            }
+            let (value, offset) = match decode_string(serial_id, &payload[para_index..]) {
+                Some(v) => v,
+                None => return,
+            };
 
             match n {
-                BODY_PARAM_DUBBO_VERSION => {
-                    self.info.dubbo_version =
-                        String::from_utf8_lossy(&payload[para_index..para_index + para_len])
-                            .into_owned()
-                }
-                BODY_PARAM_SERVICE_NAME => {
-                    self.info.service_name =
-                        String::from_utf8_lossy(&payload[para_index..para_index + para_len])
-                            .into_owned();
-                }
-                BODY_PARAM_SERVICE_VERSION => {
-                    self.info.service_version =
-                        String::from_utf8_lossy(&payload[para_index..para_index + para_len])
-                            .into_owned();
-                }
-                BODY_PARAM_METHOD_NAME => {
-                    self.info.method_name =
-                        String::from_utf8_lossy(&payload[para_index..para_index + para_len])
-                            .into_owned();
-                }
+                BODY_PARAM_DUBBO_VERSION => self.info.dubbo_version = value,
+                BODY_PARAM_SERVICE_NAME => self.info.service_name = value,
+                BODY_PARAM_SERVICE_VERSION => self.info.service_version = value,
+                BODY_PARAM_METHOD_NAME => self.info.method_name = value,
                 _ => return,
             }
 
-            para_index += para_len;
+            para_index += offset;
             if payload_len <= para_index {
                 return;
             }
             n += 1;
         }
 
-        if self.l7_log_dynamic_config.trace_types.is_empty() || para_index >= payload.len() {
+        // attachment 仅支持按 hessian2 编码查找
+        // attachments are only looked up in hessian2 encoding
+        if !is_hessian2(serial_id)
+            || self.l7_log_dynamic_config.trace_types.is_empty()
+            || para_index >= payload.len()
+        {
             return;
         }
 
@@ -461,6 +498,7 @@ impl DubboLog {
                 break;
             }
         }
+        self.decode_trace_context(&payload_str);
     }
 
     fn request(&mut self, payload: &[u8], dubbo_header: &DubboHeader) {
@@ -490,7 +528,35 @@ impl DubboLog {
         }
     }
 
-    fn response(&mut self, dubbo_header: &DubboHeader) {
+    // 尽力而为的去解析Dubbo响应中的异常信息
+    // 状态码不是OK时Body为错误信息字符串，否则Body以响应标记开头，标记为异常时其后是序列化后的Throwable
+    // ==================================================================================
+    // best effort to decode the exception of a Dubbo response
+    // the body is an error message string if the status is not OK, otherwise it starts with a response
+    // flag, followed by the serialized Throwable if the flag indicates an exception
+    fn get_resp_body_info(&mut self, payload: &[u8], status_code: u8) {
+        let serial_id = self.info.serial_id;
+        if status_code != OK {
+            if let Some((message, _)) = decode_string(serial_id, payload) {
+                self.info.exception = message;
+            }
+            return;
+        }
+
+        let (flag, offset) = match decode_response_flag(serial_id, payload) {
+            Some(f) => f,
+            None => return,
+        };
+        if flag != RESPONSE_WITH_EXCEPTION && flag != RESPONSE_WITH_EXCEPTION_WITH_ATTACHMENTS {
+            return;
+        }
+        self.info.resp_status = L7ResponseStatus::ServerError;
+        if let Some(exception) = decode_exception(serial_id, &payload[offset..]) {
+            self.info.exception = exception;
+        }
+    }
+
+    fn response(&mut self, payload: &[u8], dubbo_header: &DubboHeader) {
         self.info.msg_type = LogMessageType::Response;
 
         self.info.data_type = dubbo_header.data_type;
@@ -499,6 +565,12 @@ impl DubboLog {
         self.info.request_id = dubbo_header.request_id;
         self.info.status_code = Some(dubbo_header.status_code as i32);
         self.set_status(dubbo_header.status_code);
+
+        // 心跳等事件的响应没有响应标记
+        // event responses such as heartbeat have no response flag
+        if !dubbo_header.is_event {
+            self.get_resp_body_info(&payload[DUBBO_HEADER_LEN..], dubbo_header.status_code);
+        }
     }
 
     pub fn dubbo_check_protocol(payload: &[u8], param: &ParseParam) -> bool {
@@ -537,7 +609,7 @@ impl DubboLog {
                 self.request(payload, &dubbo_header);
             }
             PacketDirection::ServerToClient => {
-                self.response(&dubbo_header);
+                self.response(payload, &dubbo_header);
             }
         }
         Ok(())
@@ -549,6 +621,7 @@ pub struct DubboHeader {
     // Dubbo Header
     pub serial_id: u8,
     pub data_type: u8,
+    pub is_event: bool,
     pub status_code: u8,
     pub data_length: i32,
     pub request_id: i64,
@@ -574,6 +647,7 @@ impl DubboHeader {
 
         self.serial_id = payload[2] & 0x1f;
         self.data_type = payload[2] & 0x80;
+        self.is_event = payload[2] & 0x20 != 0;
         self.status_code = payload[3];
         self.request_id = read_u64_be(&payload[4..]) as i64;
         self.data_length = read_u32_be(&payload[12..]) as i32;
@@ -590,23 +664,362 @@ impl DubboHeader {
             return false;
         }
 
-        // TODO：增加检查serial_id字段
-        return true;
+        is_known_serial_id(self.serial_id)
+    }
+}
+
+fn is_known_serial_id(serial_id: u8) -> bool {
+    matches!(
+        serial_id,
+        DUBBO_SERIAL_HESSIAN2
+            | DUBBO_SERIAL_JAVA
+            | DUBBO_SERIAL_COMPACTED_JAVA
+            | DUBBO_SERIAL_FASTJSON
+            | DUBBO_SERIAL_NATIVE_JAVA
+            | DUBBO_SERIAL_KRYO
+            | DUBBO_SERIAL_FST
+            | DUBBO_SERIAL_NATIVE_HESSIAN
+            | DUBBO_SERIAL_AVRO
+            | DUBBO_SERIAL_PROTOSTUFF
+            | DUBBO_SERIAL_GSON
+            | DUBBO_SERIAL_PROTOBUF_JSON
+            | DUBBO_SERIAL_PROTOBUF
+            | DUBBO_SERIAL_FASTJSON2
+            | DUBBO_SERIAL_KRYO2
+    )
+}
+
+fn is_hessian2(serial_id: u8) -> bool {
+    serial_id == DUBBO_SERIAL_HESSIAN2 || serial_id == DUBBO_SERIAL_NATIVE_HESSIAN
+}
+
+fn is_kryo(serial_id: u8) -> bool {
+    serial_id == DUBBO_SERIAL_KRYO || serial_id == DUBBO_SERIAL_KRYO2
+}
+
+// 按序列化方式解析一个字符串，返回字符串和占用的字节数，不支持的序列化方式返回None
+// decodes a string by the serialization, returns the string and the number of bytes it takes,
+// None is returned for unsupported serializations
+fn decode_string(serial_id: u8, payload: &[u8]) -> Option<(String, usize)> {
+    if is_hessian2(serial_id) {
+        decode_hessian2_string(payload)
+    } else if is_kryo(serial_id) {
+        decode_kryo_string(payload)
+    } else if serial_id == DUBBO_SERIAL_PROTOBUF {
+        decode_protobuf_string(payload)
+    } else {
+        None
     }
 }
 
-// 参考开源代码解析：https://github.com/apache/dubbo-go-hessian2/blob/master/decode.go#L289
-// 返回offset和数据length
-pub fn get_req_param_len(payload: &[u8]) -> (usize, usize) {
-    let tag = payload[0];
+// 响应标记由 ObjectOutput.writeByte 写入
+// the response flag is written by ObjectOutput.writeByte
+fn decode_response_flag(serial_id: u8, payload: &[u8]) -> Option<(i32, usize)> {
+    if is_hessian2(serial_id) {
+        decode_hessian2_int(payload)
+    } else if is_kryo(serial_id) {
+        Some((*payload.first()? as i32, 1))
+    } else if serial_id == DUBBO_SERIAL_PROTOBUF {
+        // Int32Value 的值为0时消息为空
+        // the message is empty if the value of Int32Value is 0
+        let (message, offset) = decode_protobuf_delimited(payload)?;
+        let flag = match find_protobuf_field(message, 1) {
+            Some(value) => decode_varint(value)?.0 as i32,
+            None => 0,
+        };
+        Some((flag, offset))
+    } else {
+        None
+    }
+}
+
+// 返回 "异常类名: 异常信息"，无法解析异常信息时仅返回类名
+// returns "exception class: message", or the class only if the message can not be decoded
+fn decode_exception(serial_id: u8, payload: &[u8]) -> Option<String> {
+    let (class_name, message) = if is_hessian2(serial_id) {
+        decode_hessian2_exception(payload)?
+    } else if is_kryo(serial_id) {
+        (decode_kryo_class_name(payload)?, None)
+    } else if serial_id == DUBBO_SERIAL_PROTOBUF {
+        decode_protobuf_exception(payload)?
+    } else {
+        return None;
+    };
+    match message {
+        Some(message) if !message.is_empty() => Some(format!("{}: {}", class_name, message)),
+        _ => Some(class_name),
+    }
+}
+
+// hessian2 字符串长度的单位是 UTF-16 字符，返回 chars 个字符占用的字节数
+// the length of hessian2 strings is in UTF-16 chars, returns the number of bytes taken by `chars` chars
+fn utf8_chars_len(payload: &[u8], chars: usize) -> Option<usize> {
+    let mut offset = 0;
+    let mut count = 0;
+    while count < chars {
+        let (len, units) = match *payload.get(offset)? {
+            0xc0..=0xdf => (2, 1),
+            0xe0..=0xef => (3, 1),
+            0xf0..=0xf7 => (4, 2),
+            _ => (1, 1),
+        };
+        offset += len;
+        count += units;
+    }
+    if offset > payload.len() {
+        return None;
+    }
+    Some(offset)
+}
+
+// 参考开源代码解析：https://github.com/apache/dubbo-go-hessian2/blob/master/string.go
+fn decode_hessian2_string(payload: &[u8]) -> Option<(String, usize)> {
+    let mut value = vec![];
+    let mut offset = 0;
+    loop {
+        let tag = *payload.get(offset)?;
+        let (is_final, header_len, chars) = match tag {
+            BC_STRING_DIRECT..=STRING_DIRECT_MAX => (true, 1, tag as usize),
+            BC_STRING_SHORT..=BC_STRING_SHORT_MAX => (
+                true,
+                2,
+                (((tag - BC_STRING_SHORT) as usize) << 8) + *payload.get(offset + 1)? as usize,
+            ),
+            BC_STRING_FINAL | BC_STRING | BC_STRING_NON_FINAL | BC_STRING_CHUNK => {
+                if payload.len() < offset + 3 {
+                    return None;
+                }
+                (
+                    tag == BC_STRING_FINAL || tag == BC_STRING,
+                    3,
+                    read_u16_be(&payload[offset + 1..]) as usize,
+                )
+            }
+            _ => return None,
+        };
+        offset += header_len;
+        let len = utf8_chars_len(&payload[offset..], chars)?;
+        value.extend_from_slice(&payload[offset..offset + len]);
+        offset += len;
+        if is_final {
+            return Some((String::from_utf8_lossy(&value).into_owned(), offset));
+        }
+    }
+}
+
+// 参考开源代码解析：https://github.com/apache/dubbo-go-hessian2/blob/master/int.go
+fn decode_hessian2_int(payload: &[u8]) -> Option<(i32, usize)> {
+    let tag = *payload.first()?;
     match tag {
-        BC_STRING_DIRECT..=STRING_DIRECT_MAX => (1, tag as usize),
-        0x30..=0x33 if payload.len() > 2 => (2, ((tag as usize - 0x30) << 8) + payload[1] as usize),
-        BC_STRING_CHUNK | BC_STRING if payload.len() > 3 => {
-            (3, ((payload[1] as usize) << 8) + payload[2] as usize)
+        0x80..=0xbf => Some((tag as i32 - 0x90, 1)),
+        0xc0..=0xcf => Some((((tag as i32 - 0xc8) << 8) + *payload.get(1)? as i32, 2)),
+        0xd0..=0xd7 => {
+            if payload.len() < 3 {
+                return None;
+            }
+            Some((
+                ((tag as i32 - 0xd4) << 16) + ((payload[1] as i32) << 8) + payload[2] as i32,
+                3,
+            ))
+        }
+        b'I' if payload.len() >= 5 => Some((read_u32_be(&payload[1..]) as i32, 5)),
+        _ => None,
+    }
+}
+
+// 跳过一个非对象的 hessian2 值，返回占用的字节数，不支持的类型返回None
+// skips a hessian2 value that is not an object, returns the number of bytes it takes,
+// None is returned for unsupported types
+fn skip_hessian2_value(payload: &[u8]) -> Option<usize> {
+    let len = match *payload.first()? {
+        // null, true, false
+        b'N' | b'T' | b'F' => 1,
+        // int
+        0x80..=0xbf | 0xc0..=0xcf | 0xd0..=0xd7 | b'I' => {
+            return decode_hessian2_int(payload).map(|(_, n)| n)
+        }
+        // long
+        0xd8..=0xef => 1,
+        0xf0..=0xff => 2,
+        0x38..=0x3f => 3,
+        0x59 => 5,
+        b'L' => 9,
+        // double
+        0x5b | 0x5c => 1,
+        0x5d => 2,
+        0x5e => 3,
+        0x5f => 5,
+        b'D' => 9,
+        // date
+        0x4a => 9,
+        0x4b => 5,
+        // reference
+        b'Q' => return decode_hessian2_int(&payload[1..]).map(|(_, n)| n + 1),
+        // string
+        BC_STRING_DIRECT..=STRING_DIRECT_MAX
+        | BC_STRING_SHORT..=BC_STRING_SHORT_MAX
+        | BC_STRING_FINAL
+        | BC_STRING_NON_FINAL => return decode_hessian2_string(payload).map(|(_, n)| n),
+        _ => return None,
+    };
+    if len > payload.len() {
+        return None;
+    }
+    Some(len)
+}
+
+// Throwable 由 JavaSerializer 编码为对象：类定义 'C' 类名 字段数 字段名...，随后是对象实例
+// 'O'/0x60 及各字段的值，detailMessage 之前的字段都是基本类型或者字符串时才能解析出异常信息
+// ==================================================================================
+// Throwable is encoded as an object by JavaSerializer: class definition 'C' class fields field
+// names..., followed by the instance 'O'/0x60 and the field values, the message can only be
+// decoded if all the fields before detailMessage are primitives or strings
+fn decode_hessian2_exception(payload: &[u8]) -> Option<(String, Option<String>)> {
+    if payload.first() != Some(&BC_OBJECT_DEF) {
+        return None;
+    }
+    let (class_name, offset) = decode_hessian2_string(&payload[1..])?;
+    Some((
+        class_name,
+        decode_hessian2_detail_message(&payload[1 + offset..]),
+    ))
+}
+
+fn decode_hessian2_detail_message(payload: &[u8]) -> Option<String> {
+    let (field_count, mut offset) = decode_hessian2_int(payload)?;
+    let mut message_index = None;
+    for i in 0..field_count {
+        let (name, len) = decode_hessian2_string(payload.get(offset..)?)?;
+        if name == "detailMessage" {
+            message_index = Some(i);
         }
-        _ => (0, 0),
+        offset += len;
+    }
+    let message_index = message_index?;
+
+    match *payload.get(offset)? {
+        BC_OBJECT_DIRECT..=OBJECT_DIRECT_MAX => offset += 1,
+        BC_OBJECT => offset += 1 + decode_hessian2_int(payload.get(offset + 1..)?)?.1,
+        _ => return None,
+    }
+    for _ in 0..message_index {
+        offset += skip_hessian2_value(payload.get(offset..)?)?;
     }
+    decode_hessian2_string(payload.get(offset..)?).map(|(message, _)| message)
+}
+
+fn decode_varint(payload: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0;
+    for (i, b) in payload.iter().take(10).enumerate() {
+        value |= ((b & 0x7f) as u64) << (7 * i);
+        if b & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+// 参考：https://github.com/EsotericSoftware/kryo/blob/master/src/com/esotericsoftware/kryo/io/Output.java
+// ASCII 字符串的最后一个字节最高位置1，否则先写入长度（字符数+1，0表示null），
+// 长度首字节 bit7 为标记、bit6 表示后续字节、低6位为长度低位，后续字节按 varint 编码
+// ==================================================================================
+// the highest bit of the last byte of an ASCII string is set, otherwise the length (chars + 1,
+// 0 for null) is written first, the first length byte has a flag in bit7, a continuation bit in
+// bit6 and the low 6 bits of the length, the following bytes are varint encoded
+fn decode_kryo_string(payload: &[u8]) -> Option<(String, usize)> {
+    let first = *payload.first()?;
+    if first & 0x80 == 0 {
+        let end = payload.iter().position(|b| b & 0x80 != 0)?;
+        let mut value = payload[..=end].to_vec();
+        value[end] &= 0x7f;
+        return Some((String::from_utf8_lossy(&value).into_owned(), end + 1));
+    }
+
+    let mut chars = (first & 0x3f) as usize;
+    let mut offset = 1;
+    if first & 0x40 != 0 {
+        let (high, len) = decode_varint(&payload[1..])?;
+        chars |= (high as usize) << 6;
+        offset += len;
+    }
+    if chars == 0 {
+        return None;
+    }
+    let len = utf8_chars_len(&payload[offset..], chars - 1)?;
+    Some((
+        String::from_utf8_lossy(&payload[offset..offset + len]).into_owned(),
+        offset + len,
+    ))
+}
+
+// 未注册的类按类名写入：NAME + 2 (即1)、类名 id、类名
+// unregistered classes are written by name: NAME + 2 (that is 1), name id, class name
+fn decode_kryo_class_name(payload: &[u8]) -> Option<String> {
+    if payload.first() != Some(&1) {
+        return None;
+    }
+    let (_, offset) = decode_varint(&payload[1..])?;
+    decode_kryo_string(&payload[1 + offset..]).map(|(name, _)| name)
+}
+
+// 参考：https://github.com/apache/dubbo-spi-extensions/tree/master/dubbo-serialization-extensions/dubbo-serialization-protobuf
+// 各值使用 writeDelimitedTo 编码：varint 长度 + 消息
+// values are encoded by writeDelimitedTo: varint length + message
+fn decode_protobuf_delimited(payload: &[u8]) -> Option<(&[u8], usize)> {
+    let (len, offset) = decode_varint(payload)?;
+    let end = offset.checked_add(len as usize)?;
+    if end > payload.len() {
+        return None;
+    }
+    Some((&payload[offset..end], end))
+}
+
+// 返回消息中第一个 field 字段的值，varint 返回其编码，length-delimited 返回其内容
+// returns the value of the first `field` in the message, the encoding for varint and the content
+// for length-delimited
+fn find_protobuf_field(message: &[u8], field: u64) -> Option<&[u8]> {
+    let mut offset = 0;
+    while offset < message.len() {
+        let (key, len) = decode_varint(&message[offset..])?;
+        offset += len;
+        let (start, end) = match key & 0x7 {
+            0 => (offset, offset + decode_varint(&message[offset..])?.1),
+            1 => (offset, offset + 8),
+            2 => {
+                let (len, n) = decode_varint(&message[offset..])?;
+                (offset + n, (offset + n).checked_add(len as usize)?)
+            }
+            5 => (offset, offset + 4),
+            _ => return None,
+        };
+        if end > message.len() {
+            return None;
+        }
+        if key >> 3 == field {
+            return Some(&message[start..end]);
+        }
+        offset = end;
+    }
+    None
+}
+
+// StringValue 的 value 字段为1
+// the value field of StringValue is 1
+fn decode_protobuf_string(payload: &[u8]) -> Option<(String, usize)> {
+    let (message, offset) = decode_protobuf_delimited(payload)?;
+    let value = find_protobuf_field(message, 1).unwrap_or_default();
+    Some((String::from_utf8_lossy(value).into_owned(), offset))
+}
+
+// ThrowableProto 的 original_class_name 字段为1，original_message 字段为2
+// original_class_name is field 1 and original_message is field 2 of ThrowableProto
+fn decode_protobuf_exception(payload: &[u8]) -> Option<(String, Option<String>)> {
+    let (message, _) = decode_protobuf_delimited(payload)?;
+    let class_name = find_protobuf_field(message, 1)?;
+    Some((
+        String::from_utf8_lossy(class_name).into_owned(),
+        find_protobuf_field(message, 2).map(|m| String::from_utf8_lossy(m).into_owned()),
+    ))
 }
 
 #[cfg(test)]
@@ -691,4 +1104,173 @@ mod tests {
             }
         }
     }
+
+    fn response_packet(serial_id: u8, status_code: u8, body: &[u8]) -> Vec<u8> {
+        let mut payload = vec![DUBBO_MAGIC_HIGH, DUBBO_MAGIC_LOW, serial_id, status_code];
+        payload.extend_from_slice(&1u64.to_be_bytes());
+        payload.extend_from_slice(&(body.len() as u32).to_be_bytes());
+        payload.extend_from_slice(body);
+        payload
+    }
+
+    fn parse_response(payload: &[u8]) -> DubboInfo {
+        let mut dubbo = DubboLog::default();
+        dubbo
+            .parse(
+                payload,
+                IpProtocol::Tcp,
+                PacketDirection::ServerToClient,
+                None,
+                None,
+            )
+            .unwrap();
+        dubbo.info
+    }
+
+    #[test]
+    fn decode_strings() {
+        assert_eq!(
+            decode_string(DUBBO_SERIAL_HESSIAN2, b"\x052.0.2"),
+            Some(("2.0.2".to_string(), 6))
+        );
+        // hessian2 字符串长度是字符数
+        let mut hessian = vec![0x03];
+        hessian.extend_from_slice("异常a".as_bytes());
+        assert_eq!(
+            decode_string(DUBBO_SERIAL_HESSIAN2, &hessian),
+            Some(("异常a".to_string(), 8))
+        );
+        assert_eq!(decode_string(DUBBO_SERIAL_HESSIAN2, b"\x052.0"), None);
+
+        assert_eq!(
+            decode_string(DUBBO_SERIAL_KRYO, b"2.0.\xb2getUser"),
+            Some(("2.0.2".to_string(), 5))
+        );
+        assert_eq!(
+            decode_string(DUBBO_SERIAL_KRYO, b"\x82a"),
+            Some(("a".to_string(), 2))
+        );
+        assert_eq!(
+            decode_string(DUBBO_SERIAL_KRYO, b"\x81"),
+            Some(("".to_string(), 1))
+        );
+
+        assert_eq!(
+            decode_string(DUBBO_SERIAL_PROTOBUF, b"\x07\x0a\x052.0.2"),
+            Some(("2.0.2".to_string(), 8))
+        );
+        assert_eq!(
+            decode_string(DUBBO_SERIAL_PROTOBUF, b"\x00"),
+            Some(("".to_string(), 1))
+        );
+
+        assert_eq!(decode_string(DUBBO_SERIAL_FASTJSON, b"\"2.0.2\""), None);
+    }
+
+    #[test]
+    fn response_exception() {
+        // hessian2: RESPONSE_WITH_EXCEPTION, class definition with fields, instance
+        let mut body = vec![0x90, BC_OBJECT_DEF, 0x1a];
+        body.extend_from_slice(b"java.lang.RuntimeException");
+        body.push(0x93);
+        for name in ["code", "detailMessage", "cause"] {
+            body.push(name.len() as u8);
+            body.extend_from_slice(name.as_bytes());
+        }
+        body.extend_from_slice(&[BC_OBJECT_DIRECT, 0xc8, 0x64, 0x09]);
+        body.extend_from_slice(b"not found");
+        body.push(b'N');
+        let info = parse_response(&response_packet(DUBBO_SERIAL_HESSIAN2, OK, &body));
+        assert_eq!(info.status_code, Some(OK as i32));
+        assert_eq!(info.resp_status, L7ResponseStatus::ServerError);
+        assert_eq!(
+            info.exception.as_str(),
+            "java.lang.RuntimeException: not found"
+        );
+
+        // hessian2: RESPONSE_VALUE_WITH_ATTACHMENTS
+        let info = parse_response(&response_packet(
+            DUBBO_SERIAL_HESSIAN2,
+            OK,
+            &[0x94, 0x05, b'h', b'e', b'l', b'l', b'o'],
+        ));
+        assert_eq!(info.resp_status, L7ResponseStatus::Ok);
+        assert!(info.exception.is_empty());
+
+        // kryo: 仅解析类名
+        let mut body = vec![0x03, 0x01, 0x00];
+        body.extend_from_slice(b"java.lang.IllegalStateExceptio");
+        body.push(b'n' | 0x80);
+        let info = parse_response(&response_packet(DUBBO_SERIAL_KRYO, OK, &body));
+        assert_eq!(info.resp_status, L7ResponseStatus::ServerError);
+        assert_eq!(info.exception.as_str(), "java.lang.IllegalStateException");
+
+        // protobuf: Int32Value(0) + ThrowableProto
+        let mut message = vec![0x0a, 0x13];
+        message.extend_from_slice(b"java.io.IOException");
+        message.extend_from_slice(&[0x12, 0x06]);
+        message.extend_from_slice(b"closed");
+        let mut body = vec![0x00, message.len() as u8];
+        body.extend_from_slice(&message);
+        let info = parse_response(&response_packet(DUBBO_SERIAL_PROTOBUF, OK, &body));
+        assert_eq!(info.resp_status, L7ResponseStatus::ServerError);
+        assert_eq!(info.exception.as_str(), "java.io.IOException: closed");
+
+        // 状态码不是OK时Body是错误信息
+        let mut body = vec![0x0f];
+        body.extend_from_slice(b"service timeout");
+        let info = parse_response(&response_packet(
+            DUBBO_SERIAL_HESSIAN2,
+            SERVER_TIMEOUT,
+            &body,
+        ));
+        assert_eq!(info.resp_status, L7ResponseStatus::ServerError);
+        assert_eq!(info.exception.as_str(), "service timeout");
+    }
+
+    #[test]
+    fn trace_context() {
+        let mut attachments = String::new();
+        for (key, value) in [
+            ("x-b3-parentspanid", "a2fb4a1d1a96d312"),
+            ("X-B3-Sampled", "1"),
+            ("sw8", "0-abc"),
+            ("baggage", "tenant=a"),
+        ] {
+            attachments.push(key.len() as u8 as char);
+            attachments.push_str(key);
+            attachments.push(value.len() as u8 as char);
+            attachments.push_str(value);
+        }
+        attachments.push('Z');
+        let payload = Cow::from(attachments);
+
+        let mut dubbo = DubboLog {
+            l7_log_dynamic_config: L7LogDynamicConfig {
+                trace_types: vec![TraceType::XB3],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        dubbo.decode_trace_context(&payload);
+        let context = &dubbo.info.trace_context;
+        assert_eq!(context.parent_span_id.as_str(), "a2fb4a1d1a96d312");
+        // sw8和baggage未开启
+        // sw8 and baggage are not enabled
+        assert_eq!(context.sampled, Some(true));
+        assert!(context.baggage.is_empty());
+    }
+
+    #[test]
+    fn check_serial_id() {
+        let mut header = DubboHeader::default();
+        let mut payload = response_packet(DUBBO_SERIAL_KRYO, 0, &[]);
+        payload[2] |= 0x80;
+        header.parse_headers(&payload).unwrap();
+        assert!(header.check());
+
+        payload[2] = 0x80 | 0x1f;
+        header.parse_headers(&payload).unwrap();
+        assert!(!header.check());
+    }
 }
@@ -0,0 +1,992 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::mem;
+use std::str;
+
+use arc_swap::access::Access;
+use log::debug;
+use serde::Serialize;
+
+use super::super::{
+    consts::*, value_is_default, AppProtoHead, Http2HeaderBlock, Http2HpackContext, HttpLog,
+    Httpv2Headers, L7ResponseStatus, LogMessageType,
+};
+
+use crate::common::enums::IpProtocol;
+use crate::common::flow::L7Protocol;
+use crate::common::flow::PacketDirection;
+use crate::common::l7_protocol_info::L7ProtocolInfo;
+use crate::common::l7_protocol_info::L7ProtocolInfoInterface;
+use crate::common::l7_protocol_log::L7ProtocolParserInterface;
+use crate::common::l7_protocol_log::ParseParam;
+use crate::config::handler::{L7LogDynamicConfig, LogParserAccess};
+use crate::flow_generator::error::{Error, Result};
+use crate::flow_generator::protocol_logs::pb_adapter::{
+    ExtendedInfo, KeyVal, L7ProtocolSendLog, L7Request, L7Response, TraceInfo,
+};
+use crate::log_info_merge;
+use crate::parse_common;
+use crate::utils::bytes::read_u32_be;
+use public::utils::net::h2pack;
+
+// 等待 trailers 的响应数量上限，超过后清空，防止异常流量导致内存增长
+// ===========================================================
+// max number of responses waiting for trailers, cleared when exceeded
+const PENDING_RESPONSE_MAX: usize = 64;
+
+// 参考：https://github.com/grpc/grpc/blob/master/doc/statuscodes.md
+pub fn grpc_status_to_response_status(code: i32) -> L7ResponseStatus {
+    match code {
+        GRPC_STATUS_OK => L7ResponseStatus::Ok,
+        GRPC_STATUS_CANCELLED
+        | GRPC_STATUS_INVALID_ARGUMENT
+        | GRPC_STATUS_NOT_FOUND
+        | GRPC_STATUS_ALREADY_EXISTS
+        | GRPC_STATUS_PERMISSION_DENIED
+        | GRPC_STATUS_FAILED_PRECONDITION
+        | GRPC_STATUS_OUT_OF_RANGE
+        | GRPC_STATUS_UNAUTHENTICATED => L7ResponseStatus::ClientError,
+        _ => L7ResponseStatus::ServerError,
+    }
+}
+
+fn http_status_to_response_status(code: u16) -> L7ResponseStatus {
+    match code {
+        HTTP_STATUS_CLIENT_ERROR_MIN..=HTTP_STATUS_CLIENT_ERROR_MAX => {
+            L7ResponseStatus::ClientError
+        }
+        HTTP_STATUS_SERVER_ERROR_MIN..=HTTP_STATUS_SERVER_ERROR_MAX => {
+            L7ResponseStatus::ServerError
+        }
+        _ => L7ResponseStatus::Ok,
+    }
+}
+
+// grpc-message 使用 percent-encoding 编码
+// 参考：https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md#responses
+fn decode_grpc_message(value: &[u8]) -> String {
+    let mut out = Vec::with_capacity(value.len());
+    let mut i = 0;
+    while i < value.len() {
+        if value[i] == b'%' && i + 2 < value.len() {
+            if let Ok(b) =
+                u8::from_str_radix(str::from_utf8(&value[i + 1..i + 3]).unwrap_or_default(), 16)
+            {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(value[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn has_magic(payload: &[u8]) -> bool {
+    if payload.len() < HTTPV2_MAGIC_LENGTH {
+        return false;
+    }
+    if let Ok(payload_str) = str::from_utf8(&payload[..HTTPV2_MAGIC_PREFIX.len()]) {
+        payload_str.starts_with(HTTPV2_MAGIC_PREFIX)
+    } else {
+        false
+    }
+}
+
+// 一个报文中某个 HTTP/2 stream 上与 gRPC 相关的帧信息，由 log 和 perf 共用.
+// 一个报文可能包含多个 stream 的帧，目前仅解析第一个出现的 stream.
+// =================================================================================
+// gRPC related frames of one HTTP/2 stream in a payload, shared by log and perf.
+// a payload may contain frames of several streams, only the first stream is parsed now.
+#[derive(Debug, Default, PartialEq)]
+pub struct GrpcHeader {
+    pub stream_id: u32,
+    pub headers: Vec<(Vec<u8>, Vec<u8>)>,
+    // 是否解析到 HEADERS 帧
+    pub has_headers: bool,
+    // HEADERS 帧携带 END_STREAM 标志，对于响应即为 trailers
+    pub end_stream: bool,
+    // DATA 帧中 Length-Prefixed-Message 的数量和总长度
+    pub msg_count: u32,
+    pub data_len: u32,
+}
+
+impl GrpcHeader {
+    // HTTP/2 帧格式参考 Httpv2Headers::parse_headers_frame
+    // 头部块由连接上该方向的 HPACK 上下文解码，以便引用动态表
+    // =====================================================================
+    // header blocks are decoded by the HPACK context of this direction of the
+    // connection, so that dynamic table references can be resolved
+    pub fn parse_frames(
+        &mut self,
+        payload: &[u8],
+        hpack: &mut Http2HpackContext,
+        direction: PacketDirection,
+    ) -> Result<()> {
+        let mut frame_payload = payload;
+        let mut h2_header = Httpv2Headers::default();
+        let mut stream_found = false;
+        let mut header_blocks = hpack.decode_header_blocks(payload, direction).into_iter();
+
+        while frame_payload.len() > HTTPV2_FRAME_HEADER_LENGTH {
+            if has_magic(frame_payload) {
+                frame_payload = &frame_payload[HTTPV2_MAGIC_LENGTH..];
+                continue;
+            }
+            if h2_header.parse_headers_frame(frame_payload).is_err() {
+                break;
+            }
+            frame_payload = &frame_payload[HTTPV2_FRAME_HEADER_LENGTH..];
+            let frame_length = h2_header.frame_length as usize;
+            let frame = &frame_payload[..frame_length.min(frame_payload.len())];
+
+            match h2_header.frame_type {
+                HTTPV2_FRAME_HEADERS_TYPE | HTTPV2_FRAME_DATA_TYPE
+                    if h2_header.stream_id != 0
+                        && (!stream_found || h2_header.stream_id == self.stream_id) =>
+                {
+                    stream_found = true;
+                    self.stream_id = h2_header.stream_id;
+                    if h2_header.frame_type == HTTPV2_FRAME_HEADERS_TYPE {
+                        let stream_id = self.stream_id;
+                        let header_block = header_blocks
+                            .find(|(id, _)| *id == stream_id)
+                            .map(|(_, headers)| headers);
+                        self.on_headers_frame(frame, &h2_header, header_block)?;
+                    } else {
+                        self.on_data_frame(frame, &h2_header);
+                    }
+                }
+                _ => {}
+            }
+
+            if frame_length >= frame_payload.len() {
+                break;
+            }
+            frame_payload = &frame_payload[frame_length..];
+        }
+
+        if stream_found {
+            Ok(())
+        } else {
+            Err(Error::GrpcHeaderParseFailed)
+        }
+    }
+
+    // HTTPv2-HEADERS-FramePayload类型格式:https://tools.ietf.org/html/rfc7540#section-6.2
+    // 头部块未能完整解码时退回到只解析当前帧
+    // fallback to parse the current frame only if the header block is not decoded
+    fn on_headers_frame(
+        &mut self,
+        frame: &[u8],
+        h2_header: &Httpv2Headers,
+        header_block: Option<Http2HeaderBlock>,
+    ) -> Result<()> {
+        let mut header_list = match header_block {
+            Some(result) => result.map_err(|_| Error::GrpcHeaderParseFailed)?,
+            None => Self::parse_header_fragment(frame, h2_header)?,
+        };
+        self.headers.append(&mut header_list);
+        self.has_headers = true;
+        if h2_header.flags & FLAG_HEADERS_END_STREAM != 0 {
+            self.end_stream = true;
+        }
+        Ok(())
+    }
+
+    fn parse_header_fragment(
+        frame: &[u8],
+        h2_header: &Httpv2Headers,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut start = 0;
+        let mut end = h2_header.frame_length as usize;
+        if h2_header.flags & FLAG_HEADERS_PADDED != 0 {
+            if frame.is_empty() || frame[0] as usize >= end {
+                return Err(Error::GrpcHeaderParseFailed);
+            }
+            end -= frame[0] as usize;
+            start += 1;
+        }
+        if h2_header.flags & FLAG_HEADERS_PRIORITY != 0 {
+            start += 5;
+        }
+        // 报文被截断时尽量解析已有部分
+        end = end.min(frame.len());
+        if start >= end {
+            return Err(Error::GrpcHeaderParseFailed);
+        }
+
+        h2pack::parser::Parser::new()
+            .parse(&frame[start..end])
+            .map_err(|_| Error::GrpcHeaderParseFailed)
+    }
+
+    // DATA 帧中包含一个或多个 Length-Prefixed-Message:
+    // +-------------------+---------------------------+------------------+
+    // | Compressed-Flag(8)|     Message-Length(32)    |  Message(...)    |
+    // +-------------------+---------------------------+------------------+
+    // 跨越多个 DATA 帧的消息仅在首个帧中计数
+    fn on_data_frame(&mut self, frame: &[u8], h2_header: &Httpv2Headers) {
+        let mut data = frame;
+        let mut data_len = h2_header.frame_length;
+        if h2_header.flags & FLAG_HEADERS_PADDED != 0 {
+            if data.is_empty() || data[0] as u32 >= data_len {
+                return;
+            }
+            data_len -= data[0] as u32 + 1;
+            data = &data[1..];
+        }
+        self.data_len += data_len;
+
+        while data.len() >= GRPC_MESSAGE_HEADER_LEN {
+            if data[0] > 1 {
+                // Compressed-Flag 只能为 0 或 1
+                break;
+            }
+            self.msg_count += 1;
+            let msg_len = read_u32_be(&data[1..]) as usize + GRPC_MESSAGE_HEADER_LEN;
+            if msg_len >= data.len() {
+                break;
+            }
+            data = &data[msg_len..];
+        }
+    }
+
+    fn get_header(&self, key: &[u8]) -> Option<&[u8]> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.as_slice() == key)
+            .map(|(_, v)| v.as_slice())
+    }
+
+    pub fn is_request(&self) -> bool {
+        self.get_header(b":method").is_some()
+    }
+
+    pub fn http_status(&self) -> Option<u16> {
+        self.get_header(b":status")
+            .and_then(|v| str::from_utf8(v).ok())
+            .and_then(|v| v.parse::<u16>().ok())
+    }
+
+    pub fn grpc_status(&self) -> Option<i32> {
+        self.get_header(b"grpc-status")
+            .and_then(|v| str::from_utf8(v).ok())
+            .and_then(|v| v.parse::<i32>().ok())
+    }
+
+    // 返回 gRPC 调用的结束状态，在收到 trailers 或 END_STREAM 之前返回 None
+    pub fn response_status(&self) -> Option<L7ResponseStatus> {
+        if let Some(code) = self.grpc_status() {
+            return Some(grpc_status_to_response_status(code));
+        }
+        if self.end_stream {
+            return Some(http_status_to_response_status(
+                self.http_status().unwrap_or_default(),
+            ));
+        }
+        None
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct GrpcInfo {
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+    #[serde(skip)]
+    is_tls: bool,
+    msg_type: LogMessageType,
+    // 是否出现过 content-type: application/grpc 或者 grpc-status
+    #[serde(skip)]
+    is_grpc: bool,
+
+    #[serde(rename = "request_id", skip_serializing_if = "value_is_default")]
+    pub stream_id: Option<u32>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub version: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub trace_id: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub span_id: String,
+
+    // full method, such as: /packageName.ServiceName/MethodName
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub path: String,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub authority: String,
+    #[serde(rename = "user_agent", skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub x_request_id: String,
+
+    // 是否出现过 tri- 开头的头部，即 Dubbo 3 Triple 协议的调用
+    #[serde(skip)]
+    is_triple: bool,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub service_version: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub service_group: String,
+
+    #[serde(rename = "request_length", skip_serializing_if = "Option::is_none")]
+    pub req_content_length: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
+    pub resp_content_length: Option<u32>,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub req_msg_count: u32,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub resp_msg_count: u32,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub http_status: Option<u16>,
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub grpc_status: Option<i32>,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub grpc_message: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl GrpcInfo {
+    pub fn merge(&mut self, other: Self) {
+        match other.msg_type {
+            LogMessageType::Request => {
+                if self.path.is_empty() {
+                    self.path = other.path;
+                }
+                if self.authority.is_empty() {
+                    self.authority = other.authority;
+                }
+                if self.user_agent.is_none() {
+                    self.user_agent = other.user_agent;
+                }
+                if self.req_content_length.is_none() {
+                    self.req_content_length = other.req_content_length;
+                }
+                if self.service_version.is_empty() {
+                    self.service_version = other.service_version;
+                }
+                if self.service_group.is_empty() {
+                    self.service_group = other.service_group;
+                }
+                self.req_msg_count += other.req_msg_count;
+            }
+            LogMessageType::Response => {
+                if other.status != L7ResponseStatus::default() {
+                    self.status = other.status;
+                }
+                if self.http_status.is_none() {
+                    self.http_status = other.http_status;
+                }
+                if self.grpc_status.is_none() {
+                    self.grpc_status = other.grpc_status;
+                }
+                if self.grpc_message.is_empty() {
+                    self.grpc_message = other.grpc_message;
+                }
+                if self.resp_content_length.is_none() {
+                    self.resp_content_length = other.resp_content_length;
+                }
+                self.resp_msg_count += other.resp_msg_count;
+            }
+            _ => {}
+        }
+        if self.trace_id.is_empty() {
+            self.trace_id = other.trace_id;
+        }
+        if self.span_id.is_empty() {
+            self.span_id = other.span_id;
+        }
+        if self.x_request_id.is_empty() {
+            self.x_request_id = other.x_request_id;
+        }
+        self.is_triple |= other.is_triple;
+    }
+
+    fn set_status(&mut self) {
+        self.status = if let Some(code) = self.grpc_status {
+            grpc_status_to_response_status(code)
+        } else {
+            http_status_to_response_status(self.http_status.unwrap_or_default())
+        };
+    }
+
+    // grpc path: /packageName.ServiceName/MethodName
+    // return packageName.ServiceName, MethodName
+    fn service_and_method(&self) -> Option<(&str, &str)> {
+        let path = self.path.strip_prefix('/')?;
+        let (service, method) = path.split_once('/')?;
+        if service.is_empty() || method.is_empty() || method.contains('/') {
+            return None;
+        }
+        Some((service, method))
+    }
+}
+
+impl L7ProtocolInfoInterface for GrpcInfo {
+    fn session_id(&self) -> Option<u32> {
+        self.stream_id
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, GrpcInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::Grpc,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+
+    fn skip_send(&self) -> bool {
+        false
+    }
+}
+
+impl From<GrpcInfo> for L7ProtocolSendLog {
+    fn from(f: GrpcInfo) -> Self {
+        let service_name = f.service_and_method().map(|(s, _)| s.to_owned());
+
+        let mut attributes = vec![];
+        if f.req_msg_count > 0 {
+            attributes.push(KeyVal {
+                key: String::from("request_message_count"),
+                val: f.req_msg_count.to_string(),
+            });
+        }
+        if f.resp_msg_count > 0 {
+            attributes.push(KeyVal {
+                key: String::from("response_message_count"),
+                val: f.resp_msg_count.to_string(),
+            });
+        }
+        if let Some(code) = f.http_status {
+            attributes.push(KeyVal {
+                key: String::from("http_status_code"),
+                val: code.to_string(),
+            });
+        }
+        if f.is_triple {
+            attributes.push(KeyVal {
+                key: String::from("rpc_protocol"),
+                val: String::from("dubbo-triple"),
+            });
+        }
+        if !f.service_version.is_empty() {
+            attributes.push(KeyVal {
+                key: String::from("service_version"),
+                val: f.service_version,
+            });
+        }
+        if !f.service_group.is_empty() {
+            attributes.push(KeyVal {
+                key: String::from("service_group"),
+                val: f.service_group,
+            });
+        }
+
+        L7ProtocolSendLog {
+            req_len: f.req_content_length,
+            resp_len: f.resp_content_length,
+            version: Some(f.version),
+            req: L7Request {
+                // grpc method always post, reference https://github.com/grpc/grpc/blob/master/doc/PROTOCOL-HTTP2.md
+                req_type: String::from("POST"),
+                resource: service_name.clone().unwrap_or_default(),
+                domain: f.authority,
+                endpoint: f.path,
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.grpc_status,
+                exception: f.grpc_message,
+                ..Default::default()
+            },
+            trace_info: Some(TraceInfo {
+                trace_id: Some(f.trace_id),
+                span_id: Some(f.span_id),
+                ..Default::default()
+            }),
+            ext_info: Some(ExtendedInfo {
+                request_id: f.stream_id,
+                x_request_id: Some(f.x_request_id),
+                user_agent: f.user_agent,
+                rpc_service: service_name,
+                attributes: if attributes.is_empty() {
+                    None
+                } else {
+                    Some(attributes)
+                },
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct GrpcLog {
+    info: GrpcInfo,
+
+    // check 是否已经解析过，已经解析过parse会跳过
+    parsed: bool,
+    // 响应还未结束（等待 trailers），本次不输出日志
+    #[serde(skip)]
+    ignore: bool,
+    #[serde(skip)]
+    l7_log_dynamic_config: L7LogDynamicConfig,
+
+    // 响应的 HEADERS 帧和 trailers 可能不在同一个报文中，先缓存已解析的部分，收到 trailers 后再输出日志
+    // ==========================================================================================
+    // response HEADERS and trailers may be in different packets, cache the parsed part until trailers arrive
+    #[serde(skip)]
+    pending_responses: HashMap<u32, GrpcInfo>,
+
+    // eBPF go uprobe 上报的是自定义格式的头部数据而不是 HTTP/2 帧，仍由 HttpLog 解析
+    // ===========================================================================
+    // go uprobe reports custom formatted header rather than HTTP/2 frames, still parse by HttpLog
+    #[serde(skip)]
+    uprobe_parser: HttpLog,
+
+    // 连接每个方向的 HPACK 动态表，跨报文保留
+    // HPACK dynamic tables of each direction of the connection, kept across packets
+    #[serde(skip)]
+    hpack: Http2HpackContext,
+}
+
+impl L7ProtocolParserInterface for GrpcLog {
+    fn set_parse_config(&mut self, log_parser_config: &LogParserAccess) {
+        self.update_config(log_parser_config);
+        self.uprobe_parser.set_parse_config(log_parser_config);
+    }
+
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if param.l4_protocol != IpProtocol::Tcp || !param.ebpf_type.is_raw_protocol() {
+            return false;
+        }
+        parse_common!(self, param);
+        self.parsed = self.parse(payload, param.direction).is_ok() && self.info.is_grpc;
+        self.parsed
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if !param.ebpf_type.is_raw_protocol() {
+            return self.uprobe_parser.parse_payload(payload, param);
+        }
+        if !self.parsed {
+            parse_common!(self, param);
+            self.parse(payload, param.direction)?;
+        }
+        if self.ignore {
+            return Ok(vec![]);
+        }
+        Ok(vec![L7ProtocolInfo::GrpcInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::Grpc
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        let mut log = Self::new();
+        log.l7_log_dynamic_config = mem::take(&mut self.l7_log_dynamic_config);
+        log.pending_responses = mem::take(&mut self.pending_responses);
+        log.hpack = mem::take(&mut self.hpack);
+        self.uprobe_parser.reset();
+        mem::swap(&mut log.uprobe_parser, &mut self.uprobe_parser);
+        *self = log;
+    }
+}
+
+impl GrpcLog {
+    pub fn new() -> Self {
+        Self {
+            info: GrpcInfo::default(),
+            parsed: false,
+            ignore: false,
+            l7_log_dynamic_config: L7LogDynamicConfig::default(),
+            pending_responses: HashMap::new(),
+            uprobe_parser: HttpLog::new_v2(true),
+            hpack: Http2HpackContext::default(),
+        }
+    }
+
+    pub fn update_config(&mut self, config: &LogParserAccess) {
+        self.l7_log_dynamic_config = config.load().l7_log_dynamic.clone();
+        debug!(
+            "grpc log update l7 log dynamic config to {:#?}",
+            self.l7_log_dynamic_config
+        );
+    }
+
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<()> {
+        let mut header = GrpcHeader::default();
+        header.parse_frames(payload, &mut self.hpack, direction)?;
+
+        self.ignore = false;
+        self.info.stream_id = Some(header.stream_id);
+        self.info.version = String::from("2");
+
+        match direction {
+            PacketDirection::ClientToServer => {
+                if !header.has_headers {
+                    // client streaming 后续的 DATA 帧，请求日志已经输出
+                    self.ignore = true;
+                    return Ok(());
+                }
+                for (key, val) in header.headers.iter() {
+                    Self::on_header(&self.l7_log_dynamic_config, &mut self.info, key, val);
+                }
+                if !header.is_request() || self.info.path.is_empty() {
+                    return Err(Error::GrpcHeaderParseFailed);
+                }
+                self.info.msg_type = LogMessageType::Request;
+                self.info.req_msg_count = header.msg_count;
+                self.info.req_content_length = Some(header.data_len);
+            }
+            PacketDirection::ServerToClient => {
+                let pending = self.pending_responses.remove(&header.stream_id);
+                if !header.has_headers && pending.is_none() {
+                    return Err(Error::GrpcHeaderParseFailed);
+                }
+                let mut info = pending.unwrap_or_default();
+                info.start_time = self.info.start_time;
+                info.end_time = self.info.end_time;
+                info.is_tls = self.info.is_tls;
+                info.stream_id = self.info.stream_id;
+                info.version = String::from("2");
+                info.msg_type = LogMessageType::Response;
+                for (key, val) in header.headers.iter() {
+                    Self::on_header(&self.l7_log_dynamic_config, &mut info, key, val);
+                }
+                if header.is_request() {
+                    return Err(Error::GrpcHeaderParseFailed);
+                }
+                info.resp_msg_count += header.msg_count;
+                info.resp_content_length =
+                    Some(info.resp_content_length.unwrap_or_default() + header.data_len);
+
+                if info.grpc_status.is_some() || header.end_stream {
+                    info.set_status();
+                } else {
+                    if self.pending_responses.len() >= PENDING_RESPONSE_MAX {
+                        self.pending_responses.clear();
+                    }
+                    self.pending_responses
+                        .insert(header.stream_id, info.clone());
+                    self.ignore = true;
+                }
+                self.info = info;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_header(config: &L7LogDynamicConfig, info: &mut GrpcInfo, key: &[u8], val: &[u8]) {
+        match key {
+            b":status" => {
+                info.http_status = str::from_utf8(val).ok().and_then(|v| v.parse::<u16>().ok());
+                return;
+            }
+            b":path" => {
+                info.path = String::from_utf8_lossy(val).into_owned();
+                return;
+            }
+            b":authority" | b"host" => {
+                info.authority = String::from_utf8_lossy(val).into_owned();
+                return;
+            }
+            b"content-type" => {
+                if val.starts_with(GRPC_CONTENT_TYPE_PREFIX.as_bytes()) {
+                    info.is_grpc = true;
+                }
+                return;
+            }
+            b"user-agent" => {
+                info.user_agent = Some(String::from_utf8_lossy(val).into_owned());
+                return;
+            }
+            b"grpc-status" => {
+                info.grpc_status = str::from_utf8(val).ok().and_then(|v| v.parse::<i32>().ok());
+                info.is_grpc = true;
+                return;
+            }
+            b"grpc-message" => {
+                info.grpc_message = decode_grpc_message(val);
+                return;
+            }
+            b"tri-service-version" => {
+                info.service_version = String::from_utf8_lossy(val).into_owned();
+            }
+            b"tri-service-group" => {
+                info.service_group = String::from_utf8_lossy(val).into_owned();
+            }
+            _ => {}
+        }
+        if key.starts_with(TRIPLE_HEADER_PREFIX.as_bytes()) {
+            info.is_triple = true;
+            info.is_grpc = true;
+            return;
+        }
+
+        if !key.is_ascii() {
+            return;
+        }
+        let key_str = String::from_utf8_lossy(key);
+        let val_str = String::from_utf8_lossy(val);
+        if config.is_trace_id(&key_str) {
+            if let Some(id) = HttpLog::decode_id(&val_str, &key_str, HttpLog::TRACE_ID) {
+                info.trace_id = id;
+            }
+        }
+        if config.is_span_id(&key_str) {
+            if let Some(id) = HttpLog::decode_id(&val_str, &key_str, HttpLog::SPAN_ID) {
+                info.span_id = id;
+            }
+        }
+        if !config.x_request_id_origin.is_empty() && key_str == config.x_request_id_lower.as_str() {
+            info.x_request_id = val_str.into_owned();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/grpc";
+
+    fn run(name: &str) -> Vec<GrpcInfo> {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(name), Some(1500));
+        let mut packets = capture.as_meta_packets();
+
+        let mut grpc = GrpcLog::new();
+        let mut output = vec![];
+        let first_dst_port = packets[0].lookup_key.dst_port;
+        for packet in packets.iter_mut() {
+            packet.direction = if packet.lookup_key.dst_port == first_dst_port {
+                PacketDirection::ClientToServer
+            } else {
+                PacketDirection::ServerToClient
+            };
+            let payload = match packet.get_l4_payload() {
+                Some(p) => p,
+                None => continue,
+            };
+            let param = ParseParam::from(&*packet);
+            if let Ok(infos) = grpc.parse_payload(payload, &param) {
+                for info in infos {
+                    if let L7ProtocolInfo::GrpcInfo(info) = info {
+                        output.push(info);
+                    }
+                }
+            }
+            grpc.reset();
+        }
+        output
+    }
+
+    #[test]
+    fn check() {
+        let infos = run("grpc-unary.pcap");
+        assert_eq!(infos.len(), 2);
+
+        let req = &infos[0];
+        assert_eq!(req.msg_type, LogMessageType::Request);
+        assert_eq!(req.stream_id, Some(1));
+        assert_eq!(req.path.as_str(), "/hipstershop.CartService/GetCart");
+        assert_eq!(req.authority.as_str(), "cartservice:7070");
+        assert_eq!(req.req_msg_count, 1);
+        assert_eq!(req.req_content_length, Some(43));
+        assert_eq!(
+            req.service_and_method(),
+            Some(("hipstershop.CartService", "GetCart"))
+        );
+
+        let resp = &infos[1];
+        assert_eq!(resp.msg_type, LogMessageType::Response);
+        assert_eq!(resp.stream_id, Some(1));
+        assert_eq!(resp.http_status, Some(200));
+        assert_eq!(resp.grpc_status, Some(GRPC_STATUS_OK));
+        assert_eq!(resp.resp_msg_count, 1);
+        assert_eq!(resp.resp_content_length, Some(21));
+        assert_eq!(resp.status, L7ResponseStatus::Ok);
+    }
+
+    fn http2_frame(frame_type: u8, flags: u8, stream_id: u32, body: &[u8]) -> Vec<u8> {
+        let mut frame = (body.len() as u32).to_be_bytes()[1..].to_vec();
+        frame.extend_from_slice(&[frame_type, flags]);
+        frame.extend_from_slice(&stream_id.to_be_bytes());
+        frame.extend_from_slice(body);
+        frame
+    }
+
+    #[test]
+    fn hpack_dynamic_table() {
+        let message = [0x00, 0x00, 0x00, 0x00, 0x02, 0x0a, 0x00];
+        // :path、:authority和content-type以增量索引方式插入动态表
+        // :path, :authority and content-type inserted into the dynamic table with incremental indexing
+        let mut block = vec![0x83, 0x86, 0x44, 0x20];
+        block.extend_from_slice(b"/hipstershop.CartService/GetCart");
+        block.extend_from_slice(&[0x41, 0x10]);
+        block.extend_from_slice(b"cartservice:7070");
+        block.extend_from_slice(&[0x5f, 0x10]);
+        block.extend_from_slice(b"application/grpc");
+        let mut first_request = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n".to_vec();
+        first_request.extend(http2_frame(HTTPV2_FRAME_SETTINGS_TYPE, 0, 0, &[]));
+        first_request.extend(http2_frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            1,
+            &block,
+        ));
+        first_request.extend(http2_frame(
+            HTTPV2_FRAME_DATA_TYPE,
+            FLAG_HEADERS_END_STREAM,
+            1,
+            &message,
+        ));
+        // 第二个请求通过动态表索引引用:path(64)、:authority(63)和content-type(62)
+        // second request refers to :path(64), :authority(63) and content-type(62) in the dynamic table
+        let mut second_request = http2_frame(
+            HTTPV2_FRAME_HEADERS_TYPE,
+            FLAG_HEADERS_END_HEADERS,
+            3,
+            &[0x83, 0x86, 0xc0, 0xbf, 0xbe],
+        );
+        second_request.extend(http2_frame(
+            HTTPV2_FRAME_DATA_TYPE,
+            FLAG_HEADERS_END_STREAM,
+            3,
+            &message,
+        ));
+
+        let mut grpc = GrpcLog::new();
+        grpc.parse(&first_request, PacketDirection::ClientToServer)
+            .unwrap();
+        assert_eq!(grpc.info.path.as_str(), "/hipstershop.CartService/GetCart");
+        grpc.reset();
+        grpc.parse(&second_request, PacketDirection::ClientToServer)
+            .unwrap();
+        assert!(grpc.info.is_grpc);
+        assert_eq!(grpc.info.stream_id, Some(3));
+        assert_eq!(grpc.info.path.as_str(), "/hipstershop.CartService/GetCart");
+        assert_eq!(grpc.info.authority.as_str(), "cartservice:7070");
+        assert_eq!(grpc.info.req_msg_count, 1);
+
+        // 未见过第一个请求时无法解析动态表引用
+        // dynamic table references can not be resolved without the first request
+        assert!(GrpcLog::new()
+            .parse(&second_request, PacketDirection::ClientToServer)
+            .is_err());
+    }
+
+    #[test]
+    fn status_mapping() {
+        assert_eq!(
+            grpc_status_to_response_status(GRPC_STATUS_OK),
+            L7ResponseStatus::Ok
+        );
+        assert_eq!(
+            grpc_status_to_response_status(GRPC_STATUS_NOT_FOUND),
+            L7ResponseStatus::ClientError
+        );
+        assert_eq!(
+            grpc_status_to_response_status(GRPC_STATUS_UNAVAILABLE),
+            L7ResponseStatus::ServerError
+        );
+        assert_eq!(
+            decode_grpc_message(b"no such user%3A bob"),
+            String::from("no such user: bob")
+        );
+    }
+
+    #[test]
+    fn triple() {
+        let config = L7LogDynamicConfig::default();
+        let mut req = GrpcInfo::default();
+        for (key, val) in [
+            (
+                &b":path"[..],
+                &b"/org.apache.dubbo.demo.GreeterService/sayHello"[..],
+            ),
+            (b"content-type", b"application/grpc+proto"),
+            (b"tri-service-version", b"1.0.0"),
+            (b"tri-service-group", b"demo"),
+            (b"tri-consumer-appname", b"demo-consumer"),
+        ] {
+            GrpcLog::on_header(&config, &mut req, key, val);
+        }
+        req.msg_type = LogMessageType::Request;
+        assert!(req.is_grpc && req.is_triple);
+        assert_eq!(req.service_version.as_str(), "1.0.0");
+        assert_eq!(req.service_group.as_str(), "demo");
+
+        let mut resp = GrpcInfo::default();
+        for (key, val) in [
+            (&b"grpc-status"[..], &b"2"[..]),
+            (
+                b"grpc-message",
+                b"java.lang.IllegalStateException%3A no greeting",
+            ),
+        ] {
+            GrpcLog::on_header(&config, &mut resp, key, val);
+        }
+        resp.msg_type = LogMessageType::Response;
+        resp.set_status();
+        req.merge(resp);
+
+        let log = L7ProtocolSendLog::from(req);
+        assert_eq!(
+            log.ext_info.as_ref().unwrap().rpc_service.as_deref(),
+            Some("org.apache.dubbo.demo.GreeterService")
+        );
+        assert_eq!(log.resp.status, L7ResponseStatus::ServerError);
+        assert_eq!(
+            log.resp.exception.as_str(),
+            "java.lang.IllegalStateException: no greeting"
+        );
+        let attributes = log.ext_info.unwrap().attributes.unwrap();
+        assert!(attributes
+            .iter()
+            .any(|kv| kv.key == "rpc_protocol" && kv.val == "dubbo-triple"));
+        assert!(attributes
+            .iter()
+            .any(|kv| kv.key == "service_version" && kv.val == "1.0.0"));
+    }
+}
@@ -15,5 +15,7 @@
  */
 
 mod dubbo;
+mod grpc;
 
 pub use dubbo::{DubboHeader, DubboInfo, DubboLog};
+pub use grpc::{grpc_status_to_response_status, GrpcHeader, GrpcInfo, GrpcLog};
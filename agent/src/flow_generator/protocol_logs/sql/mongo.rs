@@ -0,0 +1,760 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::str;
+
+use serde::Serialize;
+
+use super::super::{consts::*, value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
+
+use crate::common::enums::IpProtocol;
+use crate::common::flow::L7Protocol;
+use crate::common::flow::PacketDirection;
+use crate::common::l7_protocol_info::L7ProtocolInfo;
+use crate::common::l7_protocol_info::L7ProtocolInfoInterface;
+use crate::common::l7_protocol_log::L7ProtocolParserInterface;
+use crate::common::l7_protocol_log::ParseParam;
+use crate::flow_generator::error::{Error, Result};
+use crate::flow_generator::protocol_logs::pb_adapter::{
+    ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response,
+};
+use crate::log_info_merge;
+use crate::parse_common;
+use crate::utils::bytes::{read_u32_le, read_u64_le};
+
+// BSON element type, 参考：https://bsonspec.org/spec.html
+const BSON_DOUBLE: u8 = 0x01;
+const BSON_STRING: u8 = 0x02;
+const BSON_DOCUMENT: u8 = 0x03;
+const BSON_ARRAY: u8 = 0x04;
+const BSON_BINARY: u8 = 0x05;
+const BSON_UNDEFINED: u8 = 0x06;
+const BSON_OBJECT_ID: u8 = 0x07;
+const BSON_BOOLEAN: u8 = 0x08;
+const BSON_DATETIME: u8 = 0x09;
+const BSON_NULL: u8 = 0x0a;
+const BSON_REGEX: u8 = 0x0b;
+const BSON_DB_POINTER: u8 = 0x0c;
+const BSON_JS_CODE: u8 = 0x0d;
+const BSON_SYMBOL: u8 = 0x0e;
+const BSON_JS_CODE_WITH_SCOPE: u8 = 0x0f;
+const BSON_INT32: u8 = 0x10;
+const BSON_TIMESTAMP: u8 = 0x11;
+const BSON_INT64: u8 = 0x12;
+const BSON_DECIMAL128: u8 = 0x13;
+const BSON_MIN_KEY: u8 = 0xff;
+const BSON_MAX_KEY: u8 = 0x7f;
+
+const BSON_DOCUMENT_MIN_LEN: usize = 5;
+
+// 返回 BSON 元素值的长度，不支持的类型返回 None
+fn bson_value_len(element_type: u8, value: &[u8]) -> Option<usize> {
+    let read_len = |v: &[u8]| {
+        if v.len() < 4 {
+            None
+        } else {
+            Some(read_u32_le(v) as usize)
+        }
+    };
+    match element_type {
+        BSON_DOUBLE | BSON_DATETIME | BSON_TIMESTAMP | BSON_INT64 => Some(8),
+        BSON_STRING | BSON_JS_CODE | BSON_SYMBOL => Some(4 + read_len(value)?),
+        BSON_DOCUMENT | BSON_ARRAY | BSON_JS_CODE_WITH_SCOPE => read_len(value),
+        BSON_BINARY => Some(5 + read_len(value)?),
+        BSON_UNDEFINED | BSON_NULL | BSON_MIN_KEY | BSON_MAX_KEY => Some(0),
+        BSON_OBJECT_ID => Some(12),
+        BSON_BOOLEAN => Some(1),
+        BSON_REGEX => {
+            let pattern_end = value.iter().position(|b| *b == 0)?;
+            let options_end = value[pattern_end + 1..].iter().position(|b| *b == 0)?;
+            Some(pattern_end + options_end + 2)
+        }
+        BSON_DB_POINTER => Some(4 + read_len(value)? + 12),
+        BSON_INT32 => Some(4),
+        BSON_DECIMAL128 => Some(16),
+        _ => None,
+    }
+}
+
+fn bson_str(element_type: u8, value: &[u8]) -> Option<&str> {
+    if element_type != BSON_STRING || value.len() < 5 {
+        return None;
+    }
+    let len = read_u32_le(value) as usize;
+    if len < 1 || 4 + len > value.len() {
+        return None;
+    }
+    str::from_utf8(&value[4..4 + len - 1]).ok()
+}
+
+fn bson_number(element_type: u8, value: &[u8]) -> Option<f64> {
+    match element_type {
+        BSON_DOUBLE if value.len() >= 8 => Some(f64::from_bits(read_u64_le(value))),
+        BSON_INT32 if value.len() >= 4 => Some(read_u32_le(value) as i32 as f64),
+        BSON_INT64 if value.len() >= 8 => Some(read_u64_le(value) as i64 as f64),
+        BSON_BOOLEAN if !value.is_empty() => Some(value[0] as f64),
+        _ => None,
+    }
+}
+
+// 遍历 BSON 文档的元素，返回 (name, type, value)
+// 文档被截断时只返回完整的元素
+struct BsonIter<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> BsonIter<'a> {
+    fn new(doc: &'a [u8]) -> Option<Self> {
+        if doc.len() < BSON_DOCUMENT_MIN_LEN {
+            return None;
+        }
+        let len = read_u32_le(doc) as usize;
+        if len < BSON_DOCUMENT_MIN_LEN {
+            return None;
+        }
+        Some(Self {
+            data: &doc[..len.min(doc.len())],
+            offset: 4,
+        })
+    }
+}
+
+impl<'a> Iterator for BsonIter<'a> {
+    type Item = (&'a str, u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.offset >= self.data.len() {
+            return None;
+        }
+        let element_type = self.data[self.offset];
+        if element_type == 0 {
+            self.offset = self.data.len();
+            return None;
+        }
+        let name_start = self.offset + 1;
+        let name_len = self.data[name_start..].iter().position(|b| *b == 0)?;
+        let name = str::from_utf8(&self.data[name_start..name_start + name_len]).ok()?;
+        let value_start = name_start + name_len + 1;
+        let value_len = bson_value_len(element_type, &self.data[value_start..])?;
+        if value_start + value_len > self.data.len() {
+            self.offset = self.data.len();
+            return None;
+        }
+        self.offset = value_start + value_len;
+        Some((
+            name,
+            element_type,
+            &self.data[value_start..value_start + value_len],
+        ))
+    }
+}
+
+fn read_cstring(payload: &[u8]) -> Option<(&str, usize)> {
+    let len = payload.iter().position(|b| *b == 0)?;
+    let s = str::from_utf8(&payload[..len]).ok()?;
+    Some((s, len + 1))
+}
+
+// 服务端原因导致的错误码，其他错误码均认为是客户端错误
+// 参考：https://github.com/mongodb/mongo/blob/master/src/mongo/base/error_codes.yml
+fn mongo_error_status(code: Option<i32>) -> L7ResponseStatus {
+    match code {
+        Some(1) // InternalError
+        | Some(6) // HostUnreachable
+        | Some(7) // HostNotFound
+        | Some(89) // NetworkTimeout
+        | Some(91) // ShutdownInProgress
+        | Some(189) // PrimarySteppedDown
+        | Some(262) // ExceededTimeLimit
+        | Some(9001) // SocketException
+        | Some(10107) // NotWritablePrimary
+        | Some(11600) // InterruptedAtShutdown
+        | Some(11602) // InterruptedDueToReplStateChange
+        | Some(13435) // NotPrimaryNoSecondaryOk
+        | Some(13436) // NotPrimaryOrSecondary
+        => L7ResponseStatus::ServerError,
+        _ => L7ResponseStatus::ClientError,
+    }
+}
+
+fn op_code_name(op_code: i32) -> &'static str {
+    match op_code {
+        MONGO_OP_REPLY => "reply",
+        MONGO_OP_UPDATE => "update",
+        MONGO_OP_INSERT => "insert",
+        MONGO_OP_QUERY => "query",
+        MONGO_OP_GET_MORE => "getMore",
+        MONGO_OP_DELETE => "delete",
+        MONGO_OP_KILL_CURSORS => "killCursors",
+        MONGO_OP_COMPRESSED => "compressed",
+        MONGO_OP_MSG => "msg",
+        _ => "",
+    }
+}
+
+/*
+ MsgHeader, 所有字段均为小端序
+ +----------------+----------------+----------------+----------------+
+ | messageLength  |   requestID    |   responseTo   |     opCode     |
+ +----------------+----------------+----------------+----------------+
+*/
+#[derive(Debug, Default)]
+struct MongoDBHeader {
+    length: u32,
+    request_id: u32,
+    response_to: u32,
+    op_code: i32,
+}
+
+impl MongoDBHeader {
+    fn decode(&mut self, payload: &[u8]) -> Result<()> {
+        if payload.len() < MONGO_HEADER_LEN {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        self.length = read_u32_le(payload);
+        if (self.length as usize) < MONGO_HEADER_LEN || self.length as usize > MONGO_MESSAGE_MAX_LEN
+        {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        self.request_id = read_u32_le(&payload[4..]);
+        self.response_to = read_u32_le(&payload[8..]);
+        self.op_code = read_u32_le(&payload[12..]) as i32;
+        if op_code_name(self.op_code).is_empty() {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone)]
+pub struct MongoDBInfo {
+    msg_type: LogMessageType,
+    #[serde(skip)]
+    start_time: u64,
+    #[serde(skip)]
+    end_time: u64,
+    #[serde(skip)]
+    is_tls: bool,
+
+    pub request_id: u32,
+    #[serde(skip)]
+    pub response_to: u32,
+    #[serde(skip)]
+    pub op_code: i32,
+
+    // 命令名，例如：find、insert、aggregate
+    #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
+    pub command: String,
+    #[serde(rename = "request_domain", skip_serializing_if = "value_is_default")]
+    pub database: String,
+    #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
+    pub collection: String,
+    #[serde(rename = "request_length", skip_serializing_if = "Option::is_none")]
+    pub req_len: Option<u32>,
+    #[serde(rename = "response_length", skip_serializing_if = "Option::is_none")]
+    pub resp_len: Option<u32>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ok: Option<f64>,
+    #[serde(rename = "response_code", skip_serializing_if = "Option::is_none")]
+    pub error_code: Option<i32>,
+    #[serde(rename = "response_result", skip_serializing_if = "value_is_default")]
+    pub code_name: String,
+    #[serde(
+        rename = "response_exception",
+        skip_serializing_if = "value_is_default"
+    )]
+    pub errmsg: String,
+    #[serde(rename = "response_status")]
+    pub status: L7ResponseStatus,
+}
+
+impl MongoDBInfo {
+    pub fn merge(&mut self, other: Self) {
+        match other.msg_type {
+            LogMessageType::Request => {
+                self.command = other.command;
+                self.database = other.database;
+                self.collection = other.collection;
+                self.req_len = other.req_len;
+            }
+            LogMessageType::Response => {
+                self.resp_len = other.resp_len;
+                self.ok = other.ok;
+                self.error_code = other.error_code;
+                self.code_name = other.code_name;
+                self.errmsg = other.errmsg;
+                self.status = other.status;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl L7ProtocolInfoInterface for MongoDBInfo {
+    fn session_id(&self) -> Option<u32> {
+        match self.msg_type {
+            LogMessageType::Request => Some(self.request_id),
+            LogMessageType::Response => Some(self.response_to),
+            _ => None,
+        }
+    }
+
+    fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
+        log_info_merge!(self, MongoDBInfo, other);
+        Ok(())
+    }
+
+    fn app_proto_head(&self) -> Option<AppProtoHead> {
+        Some(AppProtoHead {
+            proto: L7Protocol::MongoDB,
+            msg_type: self.msg_type,
+            rrt: self.end_time - self.start_time,
+        })
+    }
+
+    fn is_tls(&self) -> bool {
+        self.is_tls
+    }
+
+    fn skip_send(&self) -> bool {
+        false
+    }
+}
+
+impl From<MongoDBInfo> for L7ProtocolSendLog {
+    fn from(f: MongoDBInfo) -> Self {
+        L7ProtocolSendLog {
+            req_len: f.req_len,
+            resp_len: f.resp_len,
+            req: L7Request {
+                req_type: f.command,
+                domain: f.database,
+                resource: f.collection,
+                ..Default::default()
+            },
+            resp: L7Response {
+                status: f.status,
+                code: f.error_code,
+                result: f.code_name,
+                exception: f.errmsg,
+            },
+            ext_info: Some(ExtendedInfo {
+                request_id: Some(f.request_id),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MongoDBLog {
+    info: MongoDBInfo,
+    #[serde(skip)]
+    parsed: bool,
+}
+
+impl L7ProtocolParserInterface for MongoDBLog {
+    fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
+        if param.l4_protocol != IpProtocol::Tcp || !param.ebpf_type.is_raw_protocol() {
+            return false;
+        }
+        parse_common!(self, param);
+        self.parsed = self.parse(payload, param.direction).is_ok();
+        self.parsed
+    }
+
+    fn parse_payload(&mut self, payload: &[u8], param: &ParseParam) -> Result<Vec<L7ProtocolInfo>> {
+        if !self.parsed {
+            parse_common!(self, param);
+            self.parse(payload, param.direction)?;
+        }
+        Ok(vec![L7ProtocolInfo::MongoDBInfo(self.info.clone())])
+    }
+
+    fn protocol(&self) -> L7Protocol {
+        L7Protocol::MongoDB
+    }
+
+    fn parsable_on_udp(&self) -> bool {
+        false
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+impl MongoDBLog {
+    fn parse(&mut self, payload: &[u8], direction: PacketDirection) -> Result<()> {
+        let mut header = MongoDBHeader::default();
+        header.decode(payload)?;
+        // 报文可能被截断，或者一个报文包含多个消息，只解析第一个消息
+        let body = &payload[MONGO_HEADER_LEN..payload.len().min(header.length as usize)];
+
+        self.info.request_id = header.request_id;
+        self.info.response_to = header.response_to;
+        self.info.op_code = header.op_code;
+
+        match direction {
+            PacketDirection::ClientToServer => {
+                if header.response_to != 0 {
+                    return Err(Error::MongoDBLogParseFailed);
+                }
+                self.info.msg_type = LogMessageType::Request;
+                self.info.req_len = Some(header.length);
+                match header.op_code {
+                    MONGO_OP_MSG => self.on_msg_request(body),
+                    MONGO_OP_QUERY => self.on_query(body),
+                    MONGO_OP_UPDATE | MONGO_OP_INSERT | MONGO_OP_GET_MORE | MONGO_OP_DELETE => {
+                        self.on_legacy_request(body, header.op_code)
+                    }
+                    MONGO_OP_KILL_CURSORS | MONGO_OP_COMPRESSED => {
+                        self.info.command = op_code_name(header.op_code).to_owned();
+                        Ok(())
+                    }
+                    _ => Err(Error::MongoDBLogParseFailed),
+                }
+            }
+            PacketDirection::ServerToClient => {
+                if header.response_to == 0 {
+                    return Err(Error::MongoDBLogParseFailed);
+                }
+                self.info.msg_type = LogMessageType::Response;
+                self.info.resp_len = Some(header.length);
+                match header.op_code {
+                    MONGO_OP_MSG => self.on_msg_response(body),
+                    MONGO_OP_REPLY => self.on_reply(body),
+                    // 压缩的响应无法得知结果
+                    MONGO_OP_COMPRESSED => {
+                        self.info.status = L7ResponseStatus::Ok;
+                        Ok(())
+                    }
+                    _ => Err(Error::MongoDBLogParseFailed),
+                }
+            }
+        }
+    }
+
+    /*
+     OP_MSG
+     +----------------+---------------------------+------------------------+
+     | flagBits(u32)  | sections(kind + payload)  | optional checksum(u32) |
+     +----------------+---------------------------+------------------------+
+     kind 0: 一个 BSON 文档，即命令本身
+     kind 1: size(i32) + identifier(cstring) + BSON 文档序列，例如批量 insert 的文档
+    */
+    fn msg_body_document(body: &[u8]) -> Result<&[u8]> {
+        if body.len() < 4 {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        let flags = read_u32_le(body);
+        // 只定义了 checksumPresent(0)、moreToCome(1)、exhaustAllowed(16) 三个标志位
+        if flags & !(1 | 1 << 1 | 1 << 16) != 0 {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        let mut sections = &body[4..];
+        if flags & MONGO_MSG_FLAG_CHECKSUM_PRESENT != 0 && sections.len() >= 4 {
+            sections = &sections[..sections.len() - 4];
+        }
+
+        while sections.len() > BSON_DOCUMENT_MIN_LEN {
+            let kind = sections[0];
+            let section_len = read_u32_le(&sections[1..]) as usize;
+            match kind {
+                MONGO_MSG_SECTION_BODY if section_len >= BSON_DOCUMENT_MIN_LEN => {
+                    return Ok(&sections[1..]);
+                }
+                MONGO_MSG_SECTION_DOCUMENT_SEQUENCE if section_len >= 4 => {
+                    if section_len + 1 >= sections.len() {
+                        break;
+                    }
+                    sections = &sections[section_len + 1..];
+                }
+                _ => break,
+            }
+        }
+        Err(Error::MongoDBLogParseFailed)
+    }
+
+    fn on_msg_request(&mut self, body: &[u8]) -> Result<()> {
+        let doc = Self::msg_body_document(body)?;
+        self.on_command(doc)?;
+        if self.info.database.is_empty() {
+            if let Some(iter) = BsonIter::new(doc) {
+                for (name, element_type, value) in iter {
+                    if name == "$db" {
+                        self.info.database =
+                            bson_str(element_type, value).unwrap_or_default().to_owned();
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 命令文档的第一个元素为命令名，值一般为 collection 名
+    fn on_command(&mut self, doc: &[u8]) -> Result<()> {
+        let mut iter = BsonIter::new(doc).ok_or(Error::MongoDBLogParseFailed)?;
+        let (command, element_type, value) = iter.next().ok_or(Error::MongoDBLogParseFailed)?;
+        self.info.command = command.to_owned();
+        if let Some(collection) = bson_str(element_type, value) {
+            self.info.collection = collection.to_owned();
+        }
+        for (name, element_type, value) in iter {
+            match name {
+                // getMore 的值为 cursor id，collection 在单独的字段中
+                "collection" if self.info.collection.is_empty() => {
+                    self.info.collection =
+                        bson_str(element_type, value).unwrap_or_default().to_owned();
+                }
+                "$db" => {
+                    self.info.database =
+                        bson_str(element_type, value).unwrap_or_default().to_owned();
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn on_msg_response(&mut self, body: &[u8]) -> Result<()> {
+        let doc = Self::msg_body_document(body)?;
+        self.on_result(doc, false)
+    }
+
+    /*
+     OP_QUERY
+     +-------------+-----------------------------+-------------------+---------------------+-------+
+     | flags(i32)  | fullCollectionName(cstring) | numberToSkip(i32) | numberToReturn(i32) | query |
+     +-------------+-----------------------------+-------------------+---------------------+-------+
+    */
+    fn on_query(&mut self, body: &[u8]) -> Result<()> {
+        if body.len() < 4 {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        let (full_collection_name, offset) =
+            read_cstring(&body[4..]).ok_or(Error::MongoDBLogParseFailed)?;
+        let (database, collection) = full_collection_name
+            .split_once('.')
+            .ok_or(Error::MongoDBLogParseFailed)?;
+        self.info.database = database.to_owned();
+
+        let doc_offset = 4 + offset + 8;
+        if doc_offset >= body.len() {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        let doc = &body[doc_offset..];
+        if collection == "$cmd" {
+            self.on_command(doc)?;
+            // 命令中的 $db 优先级低于 fullCollectionName
+            self.info.database = database.to_owned();
+        } else {
+            BsonIter::new(doc).ok_or(Error::MongoDBLogParseFailed)?;
+            self.info.command = String::from("find");
+            self.info.collection = collection.to_owned();
+        }
+        Ok(())
+    }
+
+    // OP_UPDATE、OP_INSERT、OP_GET_MORE、OP_DELETE 均以 i32 + fullCollectionName 开头
+    fn on_legacy_request(&mut self, body: &[u8], op_code: i32) -> Result<()> {
+        if body.len() < 4 {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        let (full_collection_name, _) =
+            read_cstring(&body[4..]).ok_or(Error::MongoDBLogParseFailed)?;
+        let (database, collection) = full_collection_name
+            .split_once('.')
+            .ok_or(Error::MongoDBLogParseFailed)?;
+        self.info.command = op_code_name(op_code).to_owned();
+        self.info.database = database.to_owned();
+        self.info.collection = collection.to_owned();
+        Ok(())
+    }
+
+    /*
+     OP_REPLY
+     +---------------------+---------------+-------------------+---------------------+-----------+
+     | responseFlags(i32)  | cursorID(i64) | startingFrom(i32) | numberReturned(i32) | documents |
+     +---------------------+---------------+-------------------+---------------------+-----------+
+    */
+    fn on_reply(&mut self, body: &[u8]) -> Result<()> {
+        const DOCUMENTS_OFFSET: usize = 20;
+        if body.len() < DOCUMENTS_OFFSET {
+            return Err(Error::MongoDBLogParseFailed);
+        }
+        let flags = read_u32_le(body) as i32;
+        let query_failure = flags & MONGO_REPLY_FLAG_QUERY_FAILURE != 0;
+        if body.len() < DOCUMENTS_OFFSET + BSON_DOCUMENT_MIN_LEN {
+            // 没有返回文档
+            self.info.status = if query_failure {
+                L7ResponseStatus::ClientError
+            } else {
+                L7ResponseStatus::Ok
+            };
+            return Ok(());
+        }
+        self.on_result(&body[DOCUMENTS_OFFSET..], query_failure)
+    }
+
+    // 解析命令结果中的 ok、errmsg、code、codeName 以及 writeErrors
+    fn on_result(&mut self, doc: &[u8], failure: bool) -> Result<()> {
+        let iter = BsonIter::new(doc).ok_or(Error::MongoDBLogParseFailed)?;
+        let mut has_error = failure;
+        for (name, element_type, value) in iter {
+            match name {
+                "ok" => {
+                    self.info.ok = bson_number(element_type, value);
+                    if self.info.ok == Some(0.0) {
+                        has_error = true;
+                    }
+                }
+                "errmsg" | "$err" => {
+                    self.info.errmsg = bson_str(element_type, value).unwrap_or_default().to_owned();
+                }
+                "code" => {
+                    self.info.error_code = bson_number(element_type, value).map(|c| c as i32);
+                }
+                "codeName" => {
+                    self.info.code_name =
+                        bson_str(element_type, value).unwrap_or_default().to_owned();
+                }
+                // ok 为 1 时写操作仍然可能部分失败，取第一个错误
+                "writeErrors" if element_type == BSON_ARRAY => {
+                    let first = BsonIter::new(value)
+                        .and_then(|mut i| i.next())
+                        .filter(|(_, t, _)| *t == BSON_DOCUMENT);
+                    if let Some((_, _, write_error)) = first {
+                        has_error = true;
+                        self.on_write_error(write_error);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.info.status = if has_error {
+            mongo_error_status(self.info.error_code)
+        } else {
+            L7ResponseStatus::Ok
+        };
+        Ok(())
+    }
+
+    fn on_write_error(&mut self, doc: &[u8]) {
+        let iter = match BsonIter::new(doc) {
+            Some(i) => i,
+            None => return,
+        };
+        for (name, element_type, value) in iter {
+            match name {
+                "code" if self.info.error_code.is_none() => {
+                    self.info.error_code = bson_number(element_type, value).map(|c| c as i32);
+                }
+                "errmsg" if self.info.errmsg.is_empty() => {
+                    self.info.errmsg = bson_str(element_type, value).unwrap_or_default().to_owned();
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    use crate::utils::test::Capture;
+
+    const FILE_DIR: &str = "resources/test/flow_generator/mongo";
+
+    fn check_and_parse(file_name: &str) -> MongoDBInfo {
+        let capture = Capture::load_pcap(Path::new(FILE_DIR).join(file_name), Some(1500));
+        let mut p = capture.as_meta_packets();
+        p[0].direction = PacketDirection::ClientToServer;
+        p[1].direction = PacketDirection::ServerToClient;
+
+        let mut parser = MongoDBLog::default();
+        let req_param = &ParseParam::from(&p[0]);
+        let req_payload = p[0].get_l4_payload().unwrap();
+        assert!(parser.check_payload(req_payload, req_param));
+        let mut req = parser
+            .parse_payload(req_payload, req_param)
+            .unwrap()
+            .swap_remove(0);
+        parser.reset();
+
+        let resp_param = &ParseParam::from(&p[1]);
+        let resp_payload = p[1].get_l4_payload().unwrap();
+        assert!(parser.check_payload(resp_payload, resp_param));
+        let resp = parser
+            .parse_payload(resp_payload, resp_param)
+            .unwrap()
+            .swap_remove(0);
+
+        assert_eq!(req.session_id(), resp.session_id());
+        req.merge_log(resp).unwrap();
+        if let L7ProtocolInfo::MongoDBInfo(info) = req {
+            return info;
+        }
+        unreachable!()
+    }
+
+    #[test]
+    fn check() {
+        let info = check_and_parse("mongo_op_msg.pcap");
+        assert_eq!(info.command.as_str(), "find");
+        assert_eq!(info.database.as_str(), "test");
+        assert_eq!(info.collection.as_str(), "users");
+        assert_eq!(info.req_len, Some(115));
+        assert_eq!(info.resp_len, Some(117));
+        assert_eq!(info.ok, Some(1.0));
+        assert_eq!(info.status, L7ResponseStatus::Ok);
+
+        let info = check_and_parse("mongo_error.pcap");
+        assert_eq!(info.command.as_str(), "insert");
+        assert_eq!(info.database.as_str(), "shop");
+        assert_eq!(info.collection.as_str(), "orders");
+        assert_eq!(info.ok, Some(0.0));
+        assert_eq!(info.error_code, Some(13));
+        assert_eq!(info.code_name.as_str(), "Unauthorized");
+        assert_eq!(
+            info.errmsg.as_str(),
+            "command insert requires authentication"
+        );
+        assert_eq!(info.status, L7ResponseStatus::ClientError);
+
+        let info = check_and_parse("mongo_op_query.pcap");
+        assert_eq!(info.command.as_str(), "isMaster");
+        assert_eq!(info.database.as_str(), "admin");
+        assert_eq!(info.collection.as_str(), "");
+        assert_eq!(info.ok, Some(1.0));
+        assert_eq!(info.status, L7ResponseStatus::Ok);
+    }
+
+    #[test]
+    fn check_non_mongo() {
+        let mut parser = MongoDBLog::default();
+        let http = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\n\r\n";
+        assert!(parser.parse(http, PacketDirection::ClientToServer).is_err());
+    }
+}
@@ -14,13 +14,15 @@
  * limitations under the License.
  */
 
+use arc_swap::access::Access;
 use serde::Serialize;
 
 use super::super::{consts::*, value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
 use super::sql_check::is_mysql;
+use super::sql_normalize::{fingerprint_attributes, normalize_statement, SqlDialect};
 
 use crate::common::l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface};
-use crate::common::l7_protocol_log::{L7ProtocolParserInterface, ParseParam};
+use crate::common::l7_protocol_log::{L7ProtocolParserInterface, MessageLength, ParseParam};
 use crate::flow_generator::protocol_logs::pb_adapter::{
     ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response,
 };
@@ -28,6 +30,7 @@ use crate::{
     common::enums::IpProtocol,
     common::flow::L7Protocol,
     common::flow::PacketDirection,
+    config::{handler::LogParserAccess, SqlNormalizationConfig},
     flow_generator::error::{Error, Result},
     utils::bytes,
 };
@@ -55,6 +58,10 @@ pub struct MysqlInfo {
     pub command: u8,
     #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
     pub context: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub sql_fingerprint: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub sql_fingerprint_hash: String,
     // response
     pub response_code: u8,
     #[serde(skip)]
@@ -116,6 +123,8 @@ impl MysqlInfo {
             LogMessageType::Request => {
                 self.command = other.command;
                 self.context = other.context;
+                self.sql_fingerprint = other.sql_fingerprint;
+                self.sql_fingerprint_hash = other.sql_fingerprint_hash;
             }
             LogMessageType::Response => {
                 self.response_code = other.response_code;
@@ -174,6 +183,8 @@ impl MysqlInfo {
 
 impl From<MysqlInfo> for L7ProtocolSendLog {
     fn from(f: MysqlInfo) -> Self {
+        let attributes =
+            fingerprint_attributes(&f.context, f.sql_fingerprint, f.sql_fingerprint_hash);
         let log = L7ProtocolSendLog {
             version: if f.protocol_version == 0 {
                 None
@@ -193,6 +204,7 @@ impl From<MysqlInfo> for L7ProtocolSendLog {
                 ..Default::default()
             },
             ext_info: Some(ExtendedInfo {
+                attributes,
                 ..Default::default()
             }),
             ..Default::default()
@@ -206,9 +218,15 @@ pub struct MysqlLog {
     info: MysqlInfo,
     l7_proto: L7Protocol,
     command: u8,
+    #[serde(skip)]
+    sql_normalization: SqlNormalizationConfig,
 }
 
 impl L7ProtocolParserInterface for MysqlLog {
+    fn set_parse_config(&mut self, log_parser_config: &LogParserAccess) {
+        self.sql_normalization = log_parser_config.load().sql_normalization;
+    }
+
     fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
         if !param.ebpf_type.is_raw_protocol() {
             return false;
@@ -228,6 +246,10 @@ impl L7ProtocolParserInterface for MysqlLog {
         false
     }
 
+    fn message_length(&self, payload: &[u8], param: &ParseParam) -> MessageLength {
+        Self::mysql_message_length(payload, param.direction)
+    }
+
     fn protocol(&self) -> L7Protocol {
         L7Protocol::MySQL
     }
@@ -235,6 +257,7 @@ impl L7ProtocolParserInterface for MysqlLog {
     fn reset(&mut self) {
         *self = Self {
             l7_proto: self.l7_proto,
+            sql_normalization: self.sql_normalization,
             ..Default::default()
         };
     }
@@ -253,6 +276,11 @@ fn mysql_string(payload: &[u8]) -> String {
 impl MysqlLog {
     fn request_string(&mut self, payload: &[u8]) {
         self.info.context = mysql_string(payload);
+        (self.info.sql_fingerprint, self.info.sql_fingerprint_hash) = normalize_statement(
+            &self.sql_normalization,
+            &mut self.info.context,
+            SqlDialect::MySQL,
+        );
     }
 
     fn reset_logs(&mut self) {
@@ -376,6 +404,70 @@ impl MysqlLog {
         Ok(())
     }
 
+    // 读取offset处mysql包的长度(含包头)、序号和第一个字节
+    // reads the length (header included), number and first byte of the mysql packet at offset
+    fn read_packet(payload: &[u8], offset: usize) -> Option<(usize, u8, u8)> {
+        let payload = payload.get(offset..)?;
+        if payload.len() <= HEADER_LEN {
+            return None;
+        }
+        let len = (bytes::read_u32_le(payload) & 0xffffff) as usize;
+        Some((
+            HEADER_LEN + len,
+            payload[NUMBER_OFFSET],
+            payload[HEADER_LEN],
+        ))
+    }
+
+    // 请求和除结果集外的响应都只有一个包，结果集包括列数、列定义和数据行，以EOF、OK或ERR包结束
+    // ==========================================================================================
+    // requests and responses other than result sets are single packets, a result set consists
+    // of column count, column definitions and rows, and ends with an EOF, OK or ERR packet
+    fn mysql_message_length(payload: &[u8], direction: PacketDirection) -> MessageLength {
+        let (len, number, code) = match Self::read_packet(payload, 0) {
+            Some(p) => p,
+            None => return MessageLength::Partial,
+        };
+        if direction == PacketDirection::ClientToServer || number == 0 || payload.len() < len {
+            return MessageLength::Complete(len);
+        }
+        match code {
+            MYSQL_RESPONSE_CODE_OK | MYSQL_RESPONSE_CODE_ERR | MYSQL_RESPONSE_CODE_LOCAL_INFILE => {
+                return MessageLength::Complete(len)
+            }
+            MYSQL_RESPONSE_CODE_EOF if len < HEADER_LEN + EOF_PACKET_MAX_LEN => {
+                return MessageLength::Complete(len)
+            }
+            _ => (),
+        }
+
+        let mut columns = Self::decode_compress_int(&payload[HEADER_LEN..len]);
+        let mut offset = len;
+        let mut after_columns = true;
+        loop {
+            let (len, _, code) = match Self::read_packet(payload, offset) {
+                Some(p) => p,
+                None => return MessageLength::Partial,
+            };
+            if payload.len() < offset + len {
+                return MessageLength::Partial;
+            }
+            offset += len;
+            if columns > 0 {
+                columns -= 1;
+                continue;
+            }
+            let is_eof = code == MYSQL_RESPONSE_CODE_EOF && len < HEADER_LEN + EOF_PACKET_MAX_LEN;
+            // 未开启CLIENT_DEPRECATE_EOF时列定义后有一个EOF包
+            // there is an EOF packet after column definitions without CLIENT_DEPRECATE_EOF
+            let is_column_eof = is_eof && after_columns && len == HEADER_LEN + EOF_PACKET_LEN;
+            if code == MYSQL_RESPONSE_CODE_ERR || (is_eof && !is_column_eof) {
+                return MessageLength::Complete(offset);
+            }
+            after_columns = false;
+        }
+    }
+
     pub fn mysql_check_protocol(payload: &[u8], param: &ParseParam) -> bool {
         if param.l4_protocol != IpProtocol::Tcp {
             return false;
@@ -587,4 +679,96 @@ mod tests {
             }
         }
     }
+
+    fn mysql_packet(number: u8, body: &[u8]) -> Vec<u8> {
+        let mut packet = (body.len() as u32).to_le_bytes().to_vec();
+        packet[NUMBER_OFFSET] = number;
+        packet.extend_from_slice(body);
+        packet
+    }
+
+    #[test]
+    fn message_length() {
+        let s2c = PacketDirection::ServerToClient;
+        let query = mysql_packet(0, b"\x03select 1");
+        assert_eq!(
+            MysqlLog::mysql_message_length(&query[..6], PacketDirection::ClientToServer),
+            MessageLength::Complete(query.len())
+        );
+
+        let ok = mysql_packet(1, &[0, 0, 0, 2, 0, 0, 0]);
+        let mut payload = ok.clone();
+        payload.extend_from_slice(&query);
+        assert_eq!(
+            MysqlLog::mysql_message_length(&payload, s2c),
+            MessageLength::Complete(ok.len())
+        );
+
+        // 结果集：列数、列定义、EOF、数据行、EOF
+        // result set: column count, column definitions, EOF, rows, EOF
+        let eof = [MYSQL_RESPONSE_CODE_EOF, 0, 0, 2, 0];
+        let mut result_set = mysql_packet(1, &[2]);
+        result_set.extend(mysql_packet(2, b"\x03def column a"));
+        result_set.extend(mysql_packet(3, b"\x03def column b"));
+        result_set.extend(mysql_packet(4, &eof));
+        result_set.extend(mysql_packet(5, b"\x011\x012"));
+        assert_eq!(
+            MysqlLog::mysql_message_length(&result_set, s2c),
+            MessageLength::Partial
+        );
+        result_set.extend(mysql_packet(6, &eof));
+        let len = result_set.len();
+        result_set.extend_from_slice(&ok);
+        assert_eq!(
+            MysqlLog::mysql_message_length(&result_set, s2c),
+            MessageLength::Complete(len)
+        );
+
+        // CLIENT_DEPRECATE_EOF时以0xfe开头的OK包结束
+        // ends with an OK packet starting with 0xfe with CLIENT_DEPRECATE_EOF
+        let mut result_set = mysql_packet(1, &[1]);
+        result_set.extend(mysql_packet(2, b"\x03def column a"));
+        result_set.extend(mysql_packet(
+            3,
+            &[MYSQL_RESPONSE_CODE_EOF, 0, 0, 2, 0, 0, 0],
+        ));
+        assert_eq!(
+            MysqlLog::mysql_message_length(&result_set, s2c),
+            MessageLength::Complete(result_set.len())
+        );
+    }
+
+    #[test]
+    fn sql_normalization() {
+        let query = [
+            &[COM_QUERY][..],
+            b"SELECT name FROM user WHERE id IN (1, 2) AND k = 'v'",
+        ]
+        .concat();
+        let fingerprint = "SELECT name FROM user WHERE id IN (?) AND k = ?";
+
+        let mut mysql = MysqlLog::default();
+        mysql.request(&query).unwrap();
+        assert!(mysql.info.sql_fingerprint.is_empty());
+        let log = L7ProtocolSendLog::from(mysql.info.clone());
+        assert!(log.ext_info.unwrap().attributes.is_none());
+
+        mysql.sql_normalization.enabled = true;
+        mysql.request(&query).unwrap();
+        assert_eq!(mysql.info.sql_fingerprint, fingerprint);
+        let log = L7ProtocolSendLog::from(mysql.info.clone());
+        assert_eq!(log.req.resource, String::from_utf8_lossy(&query[1..]));
+        let attributes = log.ext_info.unwrap().attributes.unwrap();
+        assert_eq!(attributes[0].val, fingerprint);
+        assert_eq!(attributes[1].key, "sql_fingerprint_hash");
+
+        mysql.sql_normalization.redact_raw_statement = true;
+        mysql.reset();
+        mysql.request(&query).unwrap();
+        let log = L7ProtocolSendLog::from(mysql.info.clone());
+        assert_eq!(log.req.resource, fingerprint);
+        let attributes = log.ext_info.unwrap().attributes.unwrap();
+        assert_eq!(attributes.len(), 1);
+        assert_eq!(attributes[0].key, "sql_fingerprint_hash");
+    }
 }
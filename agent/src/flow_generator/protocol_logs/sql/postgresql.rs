@@ -14,6 +14,7 @@
  * limitations under the License.
  */
 
+use arc_swap::access::Access;
 use public::{
     bytes::{read_u32_be, read_u64_be},
     l7_protocol::L7Protocol,
@@ -22,13 +23,12 @@ use serde::Serialize;
 
 use crate::{
     common::{
-        flow::{FlowPerfStats, L7PerfStats, PacketDirection},
+        flow::PacketDirection,
         l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
-        l7_protocol_log::{L7ProtocolParserInterface, ParseParam},
-        MetaPacket,
+        l7_protocol_log::{L7ProtocolParserInterface, MessageLength, ParseParam},
     },
+    config::{handler::LogParserAccess, SqlNormalizationConfig},
     flow_generator::{
-        perf::{L7FlowPerf, PerfStats},
         protocol_logs::{
             pb_adapter::{ExtendedInfo, L7ProtocolSendLog, L7Request, L7Response},
             L7ResponseStatus,
@@ -41,9 +41,11 @@ use super::{
     super::value_is_default,
     postgre_convert::{get_code_desc, get_request_str},
     sql_check::is_postgresql,
+    sql_normalize::{fingerprint_attributes, normalize_statement, SqlDialect},
 };
 
 const SSL_REQ: u64 = 34440615471; // 00000008(len) 04d2162f(const 80877103)
+const STARTUP_MESSAGE_MIN_LEN: usize = 8; // len 4B + protocol version 4B
 
 #[derive(Debug, Default, Clone, Serialize)]
 pub struct PostgreInfo {
@@ -76,6 +78,10 @@ pub struct PostgreInfo {
     // request
     #[serde(rename = "request_resource", skip_serializing_if = "value_is_default")]
     pub context: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub sql_fingerprint: String,
+    #[serde(skip_serializing_if = "value_is_default")]
+    pub sql_fingerprint_hash: String,
     #[serde(rename = "request_type", skip_serializing_if = "value_is_default")]
     pub req_type: char,
 
@@ -112,6 +118,8 @@ impl L7ProtocolInfoInterface for PostgreInfo {
                 LogMessageType::Request => {
                     self.req_type = pg.req_type;
                     self.context = pg.context.clone();
+                    self.sql_fingerprint = pg.sql_fingerprint;
+                    self.sql_fingerprint_hash = pg.sql_fingerprint_hash;
                 }
                 LogMessageType::Response => {
                     self.resp_type = pg.resp_type;
@@ -146,6 +154,8 @@ impl L7ProtocolInfoInterface for PostgreInfo {
 
 impl From<PostgreInfo> for L7ProtocolSendLog {
     fn from(p: PostgreInfo) -> L7ProtocolSendLog {
+        let attributes =
+            fingerprint_attributes(&p.context, p.sql_fingerprint, p.sql_fingerprint_hash);
         L7ProtocolSendLog {
             req_len: None,
             resp_len: None,
@@ -162,6 +172,7 @@ impl From<PostgreInfo> for L7ProtocolSendLog {
                 ..Default::default()
             },
             ext_info: Some(ExtendedInfo {
+                attributes,
                 ..Default::default()
             }),
             ..Default::default()
@@ -172,11 +183,16 @@ impl From<PostgreInfo> for L7ProtocolSendLog {
 #[derive(Default, Debug, Clone, Serialize)]
 pub struct PostgresqlLog {
     info: PostgreInfo,
-    perf_stats: Option<PerfStats>,
     parsed: bool,
+    #[serde(skip)]
+    sql_normalization: SqlNormalizationConfig,
 }
 
 impl L7ProtocolParserInterface for PostgresqlLog {
+    fn set_parse_config(&mut self, log_parser_config: &LogParserAccess) {
+        self.sql_normalization = log_parser_config.load().sql_normalization;
+    }
+
     fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
         self.info.start_time = param.time;
         self.info.end_time = param.time;
@@ -225,50 +241,18 @@ impl L7ProtocolParserInterface for PostgresqlLog {
     }
 
     fn reset(&mut self) {
-        *self = Self::new();
+        *self = Self {
+            sql_normalization: self.sql_normalization,
+            ..Self::new()
+        };
     }
 
     fn parsable_on_udp(&self) -> bool {
         false
     }
-}
-
-impl L7FlowPerf for PostgresqlLog {
-    fn parse(&mut self, packet: &MetaPacket, _flow_id: u64) -> Result<()> {
-        if let Some(payload) = packet.get_l4_payload() {
-            self.parse_payload(payload, &ParseParam::from(packet))?;
-        }
-        Ok(())
-    }
-
-    fn data_updated(&self) -> bool {
-        return self.perf_stats.is_some();
-    }
-
-    fn copy_and_reset_data(&mut self, _l7_timeout_count: u32) -> FlowPerfStats {
-        FlowPerfStats {
-            l7_protocol: L7Protocol::PostgreSQL,
-            l7: if let Some(perf) = self.perf_stats.take() {
-                L7PerfStats {
-                    request_count: perf.req_count,
-                    response_count: perf.resp_count,
-                    rrt_count: perf.rrt_count,
-                    rrt_sum: perf.rrt_sum.as_micros() as u64,
-                    rrt_max: perf.rrt_max.as_micros() as u32,
-                    ..Default::default()
-                }
-            } else {
-                L7PerfStats::default()
-            },
-            ..Default::default()
-        }
-    }
 
-    fn app_proto_head(&mut self) -> Option<(AppProtoHead, u16)> {
-        if let Some(h) = L7ProtocolInfoInterface::app_proto_head(&self.info) {
-            return Some((h, 0));
-        }
-        None
+    fn message_length(&self, payload: &[u8], param: &ParseParam) -> MessageLength {
+        message_length(payload, param.direction)
     }
 }
 
@@ -279,17 +263,6 @@ impl PostgresqlLog {
         s
     }
 
-    fn update_perf(&mut self, req_count: u32, resp_count: u32, req_err: u32, resp_err: u32) {
-        if self.perf_stats.is_none() {
-            self.perf_stats = Some(PerfStats::default());
-        }
-        let perf = self.perf_stats.as_mut().unwrap();
-        perf.req_count += req_count;
-        perf.resp_count += resp_count;
-        perf.req_err_count += req_err;
-        perf.resp_err_count += resp_err;
-    }
-
     fn set_msg_type(&mut self, direction: PacketDirection) {
         match direction {
             PacketDirection::ClientToServer => self.info.msg_type = LogMessageType::Request,
@@ -330,6 +303,14 @@ impl PostgresqlLog {
         Err(Error::L7ProtocolUnknown)
     }
 
+    fn normalize_context(&mut self) {
+        (self.info.sql_fingerprint, self.info.sql_fingerprint_hash) = normalize_statement(
+            &self.sql_normalization,
+            &mut self.info.context,
+            SqlDialect::PostgreSQL,
+        );
+    }
+
     fn check_is_ssl_req(&self, payload: &[u8]) -> bool {
         payload.len() == 8
             && self.info.msg_type == LogMessageType::Request
@@ -337,12 +318,12 @@ impl PostgresqlLog {
     }
 
     fn on_req_block(&mut self, tag: char, data: &[u8]) -> Result<()> {
-        self.update_perf(1, 0, 0, 0);
         match tag {
             'Q' => {
                 self.info.req_type = tag;
                 self.info.context = strip_string_end_with_zero(data)?;
                 self.info.ignore = false;
+                self.normalize_context();
                 Ok(())
             }
             'P' => {
@@ -360,6 +341,7 @@ impl PostgresqlLog {
                     if let Some(idx) = data.iter().position(|x| *x == 0x0) {
                         self.info.context = String::from_utf8_lossy(&data[..idx]).to_string();
                         if is_postgresql(&self.info.context) {
+                            self.normalize_context();
                             return Ok(());
                         }
                     }
@@ -373,7 +355,6 @@ impl PostgresqlLog {
 
     fn on_resp_block(&mut self, tag: char, data: &[u8]) -> Result<()> {
         let mut data = data;
-        self.update_perf(0, 1, 0, 0);
         match tag {
             'C' => {
                 self.info.status = L7ResponseStatus::Ok;
@@ -438,11 +419,6 @@ impl PostgresqlLog {
                     let (err_desc, status) = get_code_desc(self.info.result.as_str());
                     self.info.error_message = String::from(err_desc);
                     self.info.status = status;
-                    match self.info.status {
-                        L7ResponseStatus::ClientError => self.update_perf(0, 0, 1, 0),
-                        L7ResponseStatus::ServerError => self.update_perf(0, 0, 0, 1),
-                        _ => {}
-                    }
                     return Ok(());
                 }
 
@@ -474,6 +450,57 @@ fn read_block(payload: &[u8]) -> Option<(char, usize)> {
     Some((tag, len - 4))
 }
 
+// 请求以Query、Sync、Flush等需要等待响应的消息结束，响应以ReadyForQuery等需要等待请求的消息结束
+// =======================================================================================
+// a request ends with a block waiting for response such as Query, Sync or Flush, and a
+// response ends with a block waiting for request such as ReadyForQuery
+fn message_length(payload: &[u8], direction: PacketDirection) -> MessageLength {
+    let terminators: &[u8] = match direction {
+        PacketDirection::ClientToServer => {
+            // 启动消息和SSL请求没有tag，长度的最高字节为0，长度至少包含自身和协议版本共8字节
+            // startup messages and ssl requests have no tag, the highest byte of length is 0,
+            // the length covers at least itself and the protocol version, 8 bytes in total
+            if payload.first() == Some(&0) {
+                if payload.len() < 4 {
+                    return MessageLength::Partial;
+                }
+                let len = read_u32_be(payload) as usize;
+                if len < STARTUP_MESSAGE_MIN_LEN {
+                    return MessageLength::Unknown;
+                }
+                return MessageLength::Complete(len);
+            }
+            b"QSHXcfpF"
+        }
+        PacketDirection::ServerToClient => {
+            // SSL请求的响应只有一个字节
+            // the response to ssl request is a single byte
+            if payload == b"S" || payload == b"N" {
+                return MessageLength::Complete(1);
+            }
+            b"ZGR"
+        }
+    };
+    let mut offset = 0;
+    loop {
+        let block = &payload[offset..];
+        if block.len() < 5 {
+            return MessageLength::Partial;
+        }
+        let len = read_u32_be(&block[1..]) as usize;
+        if !block[0].is_ascii_alphanumeric() || len < 4 {
+            return MessageLength::Unknown;
+        }
+        offset += len + 1;
+        if offset > payload.len() {
+            return MessageLength::Partial;
+        }
+        if terminators.contains(&block[0]) {
+            return MessageLength::Complete(offset);
+        }
+    }
+}
+
 // strip the latest 0x0 in string
 // if not end with 0x0, presume it is not pg protocol
 fn strip_string_end_with_zero(data: &[u8]) -> Result<String> {
@@ -487,12 +514,13 @@ fn strip_string_end_with_zero(data: &[u8]) -> Result<String> {
 mod test {
     use std::path::Path;
 
+    use super::{message_length, SSL_REQ};
     use crate::{
         common::{
             flow::PacketDirection,
             l7_protocol_info::{L7ProtocolInfo, L7ProtocolInfoInterface},
             l7_protocol_log::L7ProtocolParserInterface,
-            l7_protocol_log::ParseParam,
+            l7_protocol_log::{MessageLength, ParseParam},
         },
         flow_generator::protocol_logs::PostgreInfo,
         flow_generator::protocol_logs::PostgresqlLog,
@@ -532,6 +560,43 @@ mod test {
         assert_eq!(info.error_message.as_str(), "syntax_error",);
     }
 
+    #[test]
+    fn test_message_length() {
+        let c2s = PacketDirection::ClientToServer;
+        let s2c = PacketDirection::ServerToClient;
+        let parse = b"P\0\0\0\x0c\0select\0";
+        let sync = b"S\0\0\0\x04";
+        let mut req = parse.to_vec();
+        assert_eq!(message_length(&req, c2s), MessageLength::Partial);
+        req.extend_from_slice(sync);
+        assert_eq!(
+            message_length(&req, c2s),
+            MessageLength::Complete(req.len())
+        );
+
+        let row = b"D\0\0\0\x0b\0\x01\0\0\0\x011";
+        let ready = b"Z\0\0\0\x05I";
+        let mut resp = b"C\0\0\0\x0dSELECT 1\0".to_vec();
+        resp.extend_from_slice(row);
+        resp.extend_from_slice(&ready[..3]);
+        assert_eq!(message_length(&resp, s2c), MessageLength::Partial);
+        resp.extend_from_slice(&ready[3..]);
+        let len = resp.len();
+        resp.extend_from_slice(row);
+        assert_eq!(message_length(&resp, s2c), MessageLength::Complete(len));
+
+        assert_eq!(
+            message_length(&SSL_REQ.to_be_bytes(), c2s),
+            MessageLength::Complete(8)
+        );
+        assert_eq!(message_length(b"\0\0\0\0", c2s), MessageLength::Unknown);
+        assert_eq!(message_length(b"N", s2c), MessageLength::Complete(1));
+        assert_eq!(
+            message_length(b"\x16\x03\x01\x02\x00", s2c),
+            MessageLength::Unknown
+        );
+    }
+
     fn check_and_parse(file_name: &str) -> PostgreInfo {
         let pcap_file = Path::new(FILE_DIR).join(file_name);
         let capture = Capture::load_pcap(pcap_file, None);
@@ -14,9 +14,10 @@
  * limitations under the License.
  */
 
+use arc_swap::access::Access;
 use serde::{Serialize, Serializer};
 
-use std::{fmt, str};
+use std::{fmt, iter, str};
 
 use super::super::{value_is_default, AppProtoHead, L7ResponseStatus, LogMessageType};
 
@@ -26,12 +27,25 @@ use crate::common::flow::PacketDirection;
 use crate::common::l7_protocol_info::L7ProtocolInfo;
 use crate::common::l7_protocol_info::L7ProtocolInfoInterface;
 use crate::common::l7_protocol_log::L7ProtocolParserInterface;
-use crate::common::l7_protocol_log::ParseParam;
+use crate::common::l7_protocol_log::{MessageLength, ParseParam};
+use crate::config::{handler::LogParserAccess, RedisLogConfig};
 use crate::flow_generator::error::{Error, Result};
 use crate::flow_generator::protocol_logs::pb_adapter::{L7ProtocolSendLog, L7Request, L7Response};
 use crate::parse_common;
 
 const SEPARATOR_SIZE: usize = 2;
+// RESP消息的最大嵌套层数，超过时不再分帧
+// max nesting depth of RESP messages, deeper messages are not framed
+const MAX_NESTING_DEPTH: usize = 8;
+const AGGREGATE_TAGS: &[u8] = b"*~>%|";
+// 流内最多等待回应的请求数，超过时丢弃最早的请求
+// max number of requests waiting for responses in a flow, the oldest is dropped if exceeded
+const MAX_PENDING_REQUESTS: u32 = 1024;
+// 开启redact-values时参数全部脱敏的命令
+// commands whose arguments are all redacted when redact-values is on
+const REDACT_ALL_ARGUMENTS_COMMANDS: [&[u8]; 2] = [b"AUTH", b"HELLO"];
+const REDACTED: &[u8] = b"?";
+const TRUNCATED: &[u8] = b"...";
 
 #[derive(Serialize, Debug, Default, Clone)]
 pub struct RedisInfo {
@@ -72,11 +86,15 @@ pub struct RedisInfo {
     pub resp_status: L7ResponseStatus,
 
     cap_seq: Option<u64>,
+    // 请求在流内的序号，回应使用对应请求的序号
+    // sequence number of the request in the flow, responses use that of their requests
+    #[serde(skip)]
+    seq: Option<u32>,
 }
 
 impl L7ProtocolInfoInterface for RedisInfo {
     fn session_id(&self) -> Option<u32> {
-        None
+        self.seq
     }
 
     fn merge_log(&mut self, other: L7ProtocolInfo) -> Result<()> {
@@ -197,9 +215,23 @@ impl From<RedisInfo> for L7ProtocolSendLog {
 #[derive(Clone, Debug, Default, Serialize)]
 pub struct RedisLog {
     info: RedisInfo,
+    #[serde(skip)]
+    config: RedisLogConfig,
+
+    // Redis按请求顺序回应，以流内序号匹配流水线中的请求和回应
+    // redis responds in request order, pipelined requests and responses are matched
+    // by their sequence numbers in the flow
+    #[serde(skip)]
+    req_seq: u32,
+    #[serde(skip)]
+    resp_seq: u32,
 }
 
 impl L7ProtocolParserInterface for RedisLog {
+    fn set_parse_config(&mut self, log_parser_config: &LogParserAccess) {
+        self.config = log_parser_config.load().redis_log;
+    }
+
     fn check_payload(&mut self, payload: &[u8], param: &ParseParam) -> bool {
         if !param.ebpf_type.is_raw_protocol() {
             return false;
@@ -213,8 +245,8 @@ impl L7ProtocolParserInterface for RedisLog {
         parse_common!(self, param);
         self.info.is_tls = param.is_tls();
         self.info.set_packet_seq(param);
-        self.parse(payload, param.l4_protocol, param.direction, None, None)?;
-        Ok(vec![L7ProtocolInfo::RedisInfo(self.info.clone())])
+        let infos = self.parse(payload, param.l4_protocol, param.direction, None, None)?;
+        Ok(infos.into_iter().map(L7ProtocolInfo::RedisInfo).collect())
     }
 
     fn protocol(&self) -> L7Protocol {
@@ -225,41 +257,79 @@ impl L7ProtocolParserInterface for RedisLog {
         false
     }
 
+    fn message_length(&self, payload: &[u8], _param: &ParseParam) -> MessageLength {
+        message_length(payload)
+    }
+
     fn reset(&mut self) {
-        *self = Self::default();
+        RedisLog::reset(self);
     }
 }
 
 impl RedisLog {
     fn reset(&mut self) {
-        *self = RedisLog::default();
+        *self = RedisLog {
+            config: self.config,
+            req_seq: self.req_seq,
+            resp_seq: self.resp_seq,
+            ..Default::default()
+        };
     }
 
-    fn fill_request(&mut self, context: Vec<u8>) {
+    fn fill_request(&mut self, message: &[u8]) -> Result<()> {
+        let context = match decode_command(message) {
+            Some(args) => format_command(&args, &self.config),
+            // 参数不全是批量字符串时，按空格拆分解码结果
+            // splits the decoded context by spaces if not all arguments are bulk strings
+            None => {
+                let (context, _, _) = decode(message, true).ok_or(Error::RedisLogParseFailed)?;
+                let args = context.split(|&x| x == b' ').collect::<Vec<_>>();
+                format_command(&args, &self.config)
+            }
+        };
         self.info.request_type = match (&context).iter().position(|&x| x == b' ') {
             Some(i) if i > 0 => Vec::from(&context[..i]),
             _ => context.clone(),
         };
         self.info.msg_type = LogMessageType::Request;
         self.info.request = context;
+
+        self.info.seq = Some(self.req_seq);
+        self.req_seq = self.req_seq.wrapping_add(1);
+        if self.req_seq.wrapping_sub(self.resp_seq) > MAX_PENDING_REQUESTS {
+            self.resp_seq = self.req_seq.wrapping_sub(MAX_PENDING_REQUESTS);
+        }
+        Ok(())
     }
 
-    fn fill_response(&mut self, context: Vec<u8>, error_response: bool) {
+    fn fill_response(&mut self, message: &[u8]) -> Result<()> {
+        let (mut context, _, error_response) =
+            decode(message, false).ok_or(Error::RedisLogParseFailed)?;
         self.info.msg_type = LogMessageType::Response;
+        // 没有等待回应的请求时不推进序号，回应将无法匹配请求
+        // the sequence number is not advanced if no request is waiting, and the response
+        // will not be matched
+        self.info.seq = Some(self.resp_seq);
+        if self.resp_seq != self.req_seq {
+            self.resp_seq = self.resp_seq.wrapping_add(1);
+        }
         if context.is_empty() {
-            return;
+            return Ok(());
         }
 
+        if self.config.redact_values && is_value_reply(message) {
+            context = REDACTED.to_vec();
+        }
         self.info.resp_status = L7ResponseStatus::Ok;
-        match context[0] {
-            b'+' => self.info.status = context,
-            b'-' if error_response => {
-                self.info.error = context;
-                self.info.resp_status = L7ResponseStatus::ServerError;
-            }
-            b'-' if !error_response => self.info.response = context,
-            _ => self.info.response = context,
+        if error_response {
+            self.info.error = context;
+            self.info.resp_status = L7ResponseStatus::ServerError;
+        } else if context[0] == b'+' {
+            self.info.status = context;
+        } else {
+            self.info.response = context;
         }
+        Ok(())
     }
 
     pub fn redis_check_protocol(payload: &[u8], param: &ParseParam) -> bool {
@@ -280,22 +350,75 @@ impl RedisLog {
         direction: PacketDirection,
         _is_req_end: Option<bool>,
         _is_resp_end: Option<bool>,
-    ) -> Result<()> {
+    ) -> Result<Vec<RedisInfo>> {
         if proto != IpProtocol::Tcp {
             return Err(Error::InvalidIpProtocol);
         }
 
-        let (context, _, error_response) =
-            decode(payload, direction == PacketDirection::ClientToServer)
-                .ok_or(Error::RedisLogParseFailed)?;
-        match direction {
-            PacketDirection::ClientToServer => self.fill_request(context),
-            PacketDirection::ServerToClient => self.fill_response(context, error_response),
-        };
-        Ok(())
+        // 流水线中多个命令或回应可能在同一个报文中，每个生成一条日志
+        // pipelined commands or responses may share a packet, each of them makes a log
+        let template = self.info.clone();
+        let mut infos = vec![];
+        let mut parsed = false;
+        for message in split_messages(payload) {
+            // RESP3推送消息不是对请求的回应，不生成日志
+            // RESP3 pushes are not responses to requests and make no logs
+            if direction == PacketDirection::ServerToClient && message[0] == b'>' {
+                parsed = true;
+                continue;
+            }
+            self.info = template.clone();
+            let ret = match direction {
+                PacketDirection::ClientToServer => self.fill_request(message),
+                PacketDirection::ServerToClient => self.fill_response(message),
+            };
+            if let Err(e) = ret {
+                // 只有第一个消息解析失败时返回错误，后续的可能是被截断的数据
+                // only fails if the first message can not be parsed, the following may be
+                // truncated data
+                if !parsed {
+                    self.info = template;
+                    return Err(e);
+                }
+                break;
+            }
+            parsed = true;
+            infos.push(self.info.clone());
+        }
+        Ok(infos)
     }
 }
 
+// 按配置截断或脱敏参数后以空格连接
+// joins the arguments with spaces after truncating or redacting them as configured
+fn format_command(args: &[&[u8]], config: &RedisLogConfig) -> Vec<u8> {
+    let redact_all = config.redact_values
+        && matches!(args.first(), Some(c) if REDACT_ALL_ARGUMENTS_COMMANDS.iter().any(|r| c.eq_ignore_ascii_case(r)));
+    let mut command = Vec::with_capacity(args.iter().map(|a| a.len() + 1).sum());
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            command.push(b' ');
+        }
+        if config.redact_values && (i > 1 || i == 1 && redact_all) {
+            command.extend_from_slice(REDACTED);
+        } else if config.max_argument_length > 0 && arg.len() > config.max_argument_length {
+            command.extend_from_slice(&arg[..config.max_argument_length]);
+            command.extend_from_slice(TRUNCATED);
+        } else {
+            command.extend_from_slice(arg);
+        }
+    }
+    command
+}
+
+// 批量回复和聚合回复携带存储的值，空值除外
+// bulk and aggregate replies carry stored values, except for null and empty ones
+fn is_value_reply(message: &[u8]) -> bool {
+    matches!(message[0], b'$' | b'*' | b'%' | b'~' | b'=' | b'|')
+        && message.get(1) != Some(&b'-')
+        && !message[1..].starts_with(b"0\r\n")
+}
+
 // 协议解析：http://redisdoc.com/topic/protocol.html#
 fn find_separator(payload: &[u8]) -> Option<usize> {
     let len = payload.len();
@@ -361,7 +484,18 @@ fn decode_dollor(payload: &[u8], strict: bool) -> Option<(&[u8], usize)> {
 }
 
 // 命令为"set mykey myvalue"，实际封装为"*3\r\n$3\r\nSET\r\n$5\r\nmykey\r\n$7\r\nmyvalue\r\n"
-fn decode_asterisk(payload: &[u8], strict: bool) -> Option<(Vec<u8>, usize)> {
+fn decode_asterisk(payload: &[u8], strict: bool, depth: usize) -> Option<(Vec<u8>, usize)> {
+    decode_aggregate(payload, strict, 1, depth)
+}
+
+// 聚合类型，map和attribute每项包含key和value两个元素
+// aggregate types, each entry of maps and attributes has two elements, key and value
+fn decode_aggregate(
+    payload: &[u8],
+    strict: bool,
+    elements_per_entry: isize,
+    depth: usize,
+) -> Option<(Vec<u8>, usize)> {
     let mut offset = 1; // 开头的 *
 
     // 提取请求参数个数/批量回复个数
@@ -375,13 +509,26 @@ fn decode_asterisk(payload: &[u8], strict: bool) -> Option<(Vec<u8>, usize)> {
             offset + sub_offset,
         ));
     }
+    // 同message_length，元素的嵌套深度不能超过MAX_NESTING_DEPTH
+    // nesting depth of elements can not exceed MAX_NESTING_DEPTH, same as message_length
+    if depth >= MAX_NESTING_DEPTH {
+        return None;
+    }
     offset += sub_offset;
 
     let mut ret_vec = Vec::new();
     let len = payload.len();
 
-    for _ in 0..next_data_num {
-        if let Some((sub_vec, sub_offset, _)) = decode(&payload[offset..], strict) {
+    for _ in 0..next_data_num.saturating_mul(elements_per_entry) {
+        let sub_result = decode_nested(&payload[offset..], strict, depth + 1);
+        // 嵌套的聚合类型解析失败时整体失败
+        // fails as a whole if a nested aggregate can not be decoded
+        if sub_result.is_none()
+            && matches!(payload.get(offset), Some(t) if AGGREGATE_TAGS.contains(t))
+        {
+            return None;
+        }
+        if let Some((sub_vec, sub_offset, _)) = sub_result {
             if sub_offset == 0 {
                 if strict {
                     return None;
@@ -395,6 +542,12 @@ fn decode_asterisk(payload: &[u8], strict: bool) -> Option<(Vec<u8>, usize)> {
             ret_vec.extend_from_slice(sub_vec.as_slice());
 
             offset += sub_offset;
+            // 状态、整数等单行类型返回的偏移不包括结尾的分隔符
+            // the offsets of single line types such as status and integer exclude the
+            // trailing separator
+            if payload[offset..].starts_with(b"\r\n") {
+                offset += SEPARATOR_SIZE;
+            }
             if offset >= len {
                 return Some((ret_vec, len));
             }
@@ -420,23 +573,155 @@ fn decode_str(payload: &[u8], limit: usize) -> Option<(&[u8], usize)> {
 
 // 函数在入参为"$-1"或"-1"时都返回"-1", 使用第三个参数区分是否为错误回复
 pub fn decode(payload: &[u8], strict: bool) -> Option<(Vec<u8>, usize, bool)> {
+    decode_nested(payload, strict, 0)
+}
+
+fn decode_nested(payload: &[u8], strict: bool, depth: usize) -> Option<(Vec<u8>, usize, bool)> {
     if payload.len() < SEPARATOR_SIZE {
         return None;
     }
 
     match payload[0] {
-        // 请求或多条批量回复
-        b'*' => decode_asterisk(payload, strict).map(|(v, s)| (v, s, false)),
-        // 状态回复,整数回复
-        b'+' | b':' => decode_str(payload, 32).map(|(v, s)| (v.to_vec(), s, false)),
+        // 请求或多条批量回复，RESP3的集合和推送
+        b'*' | b'~' | b'>' => decode_asterisk(payload, strict, depth).map(|(v, s)| (v, s, false)),
+        // RESP3的map
+        b'%' => decode_aggregate(payload, strict, 2, depth).map(|(v, s)| (v, s, false)),
+        // RESP3的attribute，为其后回复的附加信息，跳过
+        // 同message_length，其后的回复按attribute的元素计算嵌套深度
+        // the following reply is nested as an element of the attribute, same as message_length
+        b'|' => {
+            let (_, offset) = decode_aggregate(payload, strict, 2, depth)?;
+            let (v, s, e) = decode_nested(&payload[offset..], strict, depth + 1)?;
+            Some((v, offset + s, e))
+        }
+        // 状态回复,整数回复，RESP3的浮点数、大整数、布尔值和空值
+        b'+' | b':' | b',' | b'(' | b'#' | b'_' => {
+            decode_str(payload, 32).map(|(v, s)| (v.to_vec(), s, false))
+        }
         // 错误回复
         b'-' => decode_str(payload, 256).map(|(v, s)| (v.to_vec(), s, true)),
         // 批量回复
         b'$' => decode_dollor(payload, strict).map(|(v, s)| (v.to_vec(), s, false)),
+        // RESP3的批量错误回复
+        b'!' => decode_dollor(payload, strict).map(|(v, s)| (v.to_vec(), s, true)),
+        // RESP3的verbatim字符串，内容前为3字节的格式和冒号，例如"txt:"
+        b'=' => decode_dollor(payload, strict).map(|(v, s)| {
+            let v = if v.len() >= 4 && v[3] == b':' {
+                &v[4..]
+            } else {
+                v
+            };
+            (v.to_vec(), s, false)
+        }),
         _ => None,
     }
 }
 
+// 请求为批量字符串数组，返回各参数
+// requests are arrays of bulk strings, returns the arguments
+fn decode_command(payload: &[u8]) -> Option<Vec<&[u8]>> {
+    if payload.first() != Some(&b'*') {
+        return None;
+    }
+    let (n, sub_offset) = decode_integer(&payload[1..])?;
+    if n <= 0 {
+        return None;
+    }
+    let mut offset = 1 + sub_offset;
+    let mut args = vec![];
+    for _ in 0..n {
+        if payload.get(offset) != Some(&b'$') {
+            return None;
+        }
+        let (arg, sub_offset) = decode_dollor(&payload[offset..], true)?;
+        args.push(arg);
+        offset += sub_offset;
+    }
+    Some(args)
+}
+
+// 返回payload开头第一个RESP消息的完整长度
+// returns the full length of the first RESP message at the head of payload
+pub fn message_length(payload: &[u8]) -> MessageLength {
+    resp_length(payload, 0)
+}
+
+fn resp_length(payload: &[u8], depth: usize) -> MessageLength {
+    if depth > MAX_NESTING_DEPTH {
+        return MessageLength::Unknown;
+    }
+    let tag = match payload.first() {
+        Some(t) => *t,
+        None => return MessageLength::Partial,
+    };
+    if !b"+-:,(#_$!=*~>%|".contains(&tag) {
+        return MessageLength::Unknown;
+    }
+    if find_separator(payload).is_none() {
+        return MessageLength::Partial;
+    }
+    if matches!(tag, b'+' | b'-' | b':' | b',' | b'(' | b'#' | b'_') {
+        return MessageLength::Complete(find_separator(payload).unwrap() + SEPARATOR_SIZE);
+    }
+
+    let (n, sub_offset) = match decode_integer(&payload[1..]) {
+        Some(r) => r,
+        None => return MessageLength::Unknown,
+    };
+    let header_len = 1 + sub_offset;
+    if n < 0 {
+        return MessageLength::Complete(header_len);
+    }
+    let elements = match tag {
+        b'$' | b'!' | b'=' => {
+            return MessageLength::Complete(header_len + n as usize + SEPARATOR_SIZE)
+        }
+        b'%' => n.saturating_mul(2),
+        // attribute之后为实际的回复
+        // attributes are followed by the actual reply
+        b'|' => n.saturating_mul(2).saturating_add(1),
+        _ => n,
+    };
+
+    let mut offset = header_len;
+    for i in 0..elements {
+        match resp_length(&payload[offset..], depth + 1) {
+            MessageLength::Complete(len) => {
+                offset += len;
+                if offset > payload.len() {
+                    // 仅最后一个元素不完整时可以确定长度
+                    // the length is known only if the last element is incomplete
+                    if i + 1 == elements {
+                        return MessageLength::Complete(offset);
+                    }
+                    return MessageLength::Partial;
+                }
+            }
+            other => return other,
+        }
+    }
+    MessageLength::Complete(offset)
+}
+
+// 依次返回payload中的RESP消息，无法分帧时剩余数据作为最后一个消息
+// yields RESP messages in payload one by one, the remaining data is yielded as the last
+// message if it can not be framed
+pub fn split_messages(payload: &[u8]) -> impl Iterator<Item = &[u8]> {
+    let mut rest = payload;
+    iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let len = match message_length(rest) {
+            MessageLength::Complete(n) if n <= rest.len() => n,
+            _ => rest.len(),
+        };
+        let (message, next) = rest.split_at(len);
+        rest = next;
+        Some(message)
+    })
+}
+
 pub fn decode_error_code(context: &[u8]) -> Option<&[u8]> {
     for (i, ch) in context.iter().enumerate() {
         if *ch == b' ' || *ch == b'\n' {
@@ -565,4 +850,199 @@ mod tests {
         assert_eq!(n, 2);
         assert_eq!(e, true);
     }
+
+    #[test]
+    fn test_decode_resp3() {
+        let payload = b"%2\r\n+first\r\n:1\r\n+second\r\n:2\r\n";
+        let (context, n, e) = decode(payload, true).unwrap();
+        assert_eq!(context, "+first :1 +second :2".as_bytes());
+        assert_eq!(n, payload.len());
+        assert_eq!(e, false);
+
+        let payload = b"|1\r\n+ttl\r\n:3600\r\n$5\r\nhello\r\n";
+        let (context, n, _) = decode(payload, true).unwrap();
+        assert_eq!(context, "hello".as_bytes());
+        assert_eq!(n, payload.len());
+
+        let payload = b"!21\r\nSYNTAX invalid syntax\r\n";
+        let (context, _, e) = decode(payload, true).unwrap();
+        assert_eq!(context, "SYNTAX invalid syntax".as_bytes());
+        assert_eq!(e, true);
+
+        let payload = b"=15\r\ntxt:Some string\r\n";
+        let (context, _, _) = decode(payload, true).unwrap();
+        assert_eq!(context, "Some string".as_bytes());
+
+        for payload in [
+            &b"_\r\n"[..],
+            b",1.23\r\n",
+            b"(3492890328409238509324850943850943825024385\r\n",
+            b"#t\r\n",
+        ] {
+            assert!(decode(payload, true).is_some());
+            assert_eq!(
+                message_length(payload),
+                MessageLength::Complete(payload.len())
+            );
+        }
+    }
+
+    #[test]
+    fn test_decode_nesting_depth() {
+        let mut payload = b"*1\r\n".repeat(MAX_NESTING_DEPTH);
+        payload.extend_from_slice(b":1\r\n");
+        let (context, n, _) = decode(&payload, true).unwrap();
+        assert_eq!(context, ":1".as_bytes());
+        assert_eq!(n, payload.len());
+        assert_eq!(
+            message_length(&payload),
+            MessageLength::Complete(payload.len())
+        );
+        let payload = [&b"*1\r\n"[..], &payload].concat();
+        assert_eq!(decode(&payload, true), None);
+        assert_eq!(message_length(&payload), MessageLength::Unknown);
+
+        // 过深的嵌套不会导致栈溢出
+        // deep nesting does not overflow the stack
+        for prefix in [&b"*1\r\n"[..], b"|1\r\n:1\r\n:1\r\n"] {
+            let mut payload = prefix.repeat(100000);
+            payload.extend_from_slice(b":1\r\n");
+            assert_eq!(decode(&payload, true), None);
+            assert_eq!(decode(&payload, false), None);
+            assert_eq!(message_length(&payload), MessageLength::Unknown);
+        }
+    }
+
+    #[test]
+    fn test_message_length() {
+        let payload = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n";
+        assert_eq!(
+            message_length(payload),
+            MessageLength::Complete(payload.len())
+        );
+        assert_eq!(message_length(&payload[..10]), MessageLength::Partial);
+        // 最后一个批量字符串不完整时可以确定长度
+        assert_eq!(
+            message_length(&payload[..18]),
+            MessageLength::Complete(payload.len())
+        );
+        assert_eq!(message_length(b"$10\r\n01234"), MessageLength::Complete(17));
+        assert_eq!(message_length(b"*-1\r\n"), MessageLength::Complete(5));
+        assert_eq!(message_length(b">2\r\n+a\r\n"), MessageLength::Partial);
+        assert_eq!(message_length(b"hello"), MessageLength::Unknown);
+
+        let payload = b"+OK\r\n:1\r\n$-1\r\n$10\r\n01234";
+        let messages = split_messages(payload).collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            vec![&b"+OK\r\n"[..], b":1\r\n", b"$-1\r\n", b"$10\r\n01234"]
+        );
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let mut redis = RedisLog::default();
+        let requests = redis
+            .parse(
+                b"*2\r\n$3\r\nGET\r\n$1\r\na\r\n*3\r\n$3\r\nSET\r\n$1\r\nb\r\n$1\r\n1\r\n",
+                IpProtocol::Tcp,
+                PacketDirection::ClientToServer,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].request, b"GET a");
+        assert_eq!(requests[1].request, b"SET b 1");
+        redis.reset();
+
+        let responses = redis
+            .parse(
+                b">3\r\n$7\r\nmessage\r\n$2\r\nch\r\n$1\r\nx\r\n$1\r\n2\r\n+OK\r\n",
+                IpProtocol::Tcp,
+                PacketDirection::ServerToClient,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0].response, b"2");
+        assert_eq!(responses[1].status, b"+OK");
+        for (req, resp) in requests.iter().zip(responses.iter()) {
+            assert_eq!(req.session_id(), resp.session_id());
+        }
+        assert_ne!(requests[0].session_id(), requests[1].session_id());
+
+        // 没有等待回应的请求
+        redis.reset();
+        let responses = redis
+            .parse(
+                b":1\r\n",
+                IpProtocol::Tcp,
+                PacketDirection::ServerToClient,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(responses[0].session_id(), Some(2));
+    }
+
+    #[test]
+    fn test_redact() {
+        let mut redis = RedisLog::default();
+        redis.config.max_argument_length = 4;
+        redis
+            .parse(
+                b"*3\r\n$3\r\nSET\r\n$8\r\nuser:100\r\n$6\r\nsecret\r\n",
+                IpProtocol::Tcp,
+                PacketDirection::ClientToServer,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(redis.info.request, b"SET user... secr...");
+        assert_eq!(redis.info.request_type, b"SET");
+
+        redis.config.redact_values = true;
+        redis.config.max_argument_length = 0;
+        for (request, expected) in [
+            (
+                &b"*3\r\n$3\r\nSET\r\n$8\r\nuser:100\r\n$6\r\nsecret\r\n"[..],
+                &b"SET user:100 ?"[..],
+            ),
+            (b"*2\r\n$4\r\nauth\r\n$6\r\nsecret\r\n", b"auth ?"),
+            (b"*1\r\n$4\r\nPING\r\n", b"PING"),
+        ] {
+            redis.reset();
+            redis
+                .parse(
+                    request,
+                    IpProtocol::Tcp,
+                    PacketDirection::ClientToServer,
+                    None,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(redis.info.request, expected);
+        }
+
+        for (response, expected) in [
+            (&b"$6\r\nsecret\r\n"[..], &b"?"[..]),
+            (b"*2\r\n$1\r\na\r\n$1\r\nb\r\n", b"?"),
+            (b"$-1\r\n", b"-1"),
+            (b":10\r\n", b":10"),
+        ] {
+            redis.reset();
+            redis
+                .parse(
+                    response,
+                    IpProtocol::Tcp,
+                    PacketDirection::ServerToClient,
+                    None,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(redis.info.response, expected);
+        }
+    }
 }
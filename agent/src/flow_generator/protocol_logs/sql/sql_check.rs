@@ -139,7 +139,19 @@ pub(super) fn is_mysql(sql: &String) -> bool {
 
         reference: https://dev.mysql.com/doc/refman/5.6/en/comments.html
 */
-fn trim_head_comment_and_first_upper(mut sql: &str, first_word_max_len: usize) -> Option<String> {
+fn trim_head_comment_and_first_upper(sql: &str, first_word_max_len: usize) -> Option<String> {
+    let sql = trim_head_comment(sql)?;
+    if let Some(idx) = sql.find(|c: char| !c.is_alphabetic()) {
+        if idx <= first_word_max_len && idx != 0 {
+            let (sub_sql, _) = sql.split_at(idx);
+            return Some(sub_sql.to_ascii_uppercase());
+        }
+    }
+    None
+}
+
+// strip the whitespaces and comment blocks from head, return None if the comment is not closed.
+pub(super) fn trim_head_comment(mut sql: &str) -> Option<&str> {
     sql = sql.trim_start();
     // if start with /*, strip all comment block before sql string.
     while sql.starts_with("/*") {
@@ -152,14 +164,7 @@ fn trim_head_comment_and_first_upper(mut sql: &str, first_word_max_len: usize) -
             return None;
         }
     }
-
-    if let Some(idx) = sql.find(|c: char| !c.is_alphabetic()) {
-        if idx <= first_word_max_len && idx != 0 {
-            let (sub_sql, _) = sql.split_at(idx);
-            return Some(sub_sql.to_ascii_uppercase());
-        }
-    }
-    None
+    Some(sql)
 }
 
 #[cfg(test)]
@@ -0,0 +1,433 @@
+/*
+ * Copyright (c) 2023 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use super::sql_check::trim_head_comment;
+
+use crate::{config::SqlNormalizationConfig, flow_generator::protocol_logs::pb_adapter::KeyVal};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum SqlDialect {
+    MySQL,
+    PostgreSQL,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token<'a> {
+    // 关键字、标识符、带引号的标识符以及$1等占位符
+    // keywords, identifiers, quoted identifiers and placeholders like $1
+    Word(&'a [u8]),
+    // 字面量或?占位符
+    // literal or placeholder ?
+    Param,
+    Punct(u8),
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || !b.is_ascii()
+}
+
+// 返回引号结束后的位置，未闭合时返回sql长度
+// returns the position after the closing quote, or length of sql if not closed
+fn skip_quoted(sql: &[u8], start: usize, quote: u8, backslash_escape: bool) -> usize {
+    let mut i = start + 1;
+    while i < sql.len() {
+        match sql[i] {
+            b'\\' if backslash_escape => i += 2,
+            b if b == quote => {
+                // 连续两个引号表示引号本身
+                // doubled quote stands for the quote itself
+                if sql.get(i + 1) == Some(&quote) {
+                    i += 2;
+                } else {
+                    return i + 1;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+    sql.len()
+}
+
+// PostgreSQL的$tag$...$tag$字符串，不是该格式时返回None
+// PostgreSQL $tag$...$tag$ string, returns None if it is not
+fn skip_dollar_quoted(sql: &[u8], start: usize) -> Option<usize> {
+    let tag_len = sql[start + 1..]
+        .iter()
+        .position(|b| !(b.is_ascii_alphanumeric() || *b == b'_'))?;
+    if sql[start + 1 + tag_len] != b'$'
+        || matches!(sql.get(start + 1), Some(c) if c.is_ascii_digit())
+    {
+        return None;
+    }
+    let tag = &sql[start..start + tag_len + 2];
+    let body = start + tag.len();
+    Some(
+        sql[body..]
+            .windows(tag.len())
+            .position(|w| w == tag)
+            .map_or(sql.len(), |p| body + p + tag.len()),
+    )
+}
+
+fn skip_number(sql: &[u8], start: usize) -> usize {
+    let mut i = start;
+    if sql[i..].starts_with(b"0x") || sql[i..].starts_with(b"0X") {
+        i += 2;
+        while i < sql.len() && sql[i].is_ascii_hexdigit() {
+            i += 1;
+        }
+        return i;
+    }
+    while i < sql.len() {
+        match sql[i] {
+            b'0'..=b'9' | b'.' => i += 1,
+            b'e' | b'E' => {
+                i += 1;
+                if i < sql.len() && (sql[i] == b'+' || sql[i] == b'-') {
+                    i += 1;
+                }
+            }
+            _ => break,
+        }
+    }
+    i
+}
+
+// 切分为token，同时返回token前是否有空白或注释
+// splits into tokens, with whether there are whitespaces or comments before each token
+fn tokenize(sql: &[u8], dialect: SqlDialect) -> Vec<(Token<'_>, bool)> {
+    let mut tokens: Vec<(Token, bool)> = vec![];
+    let mut spaced = false;
+    let mut i = 0;
+    while i < sql.len() {
+        let b = sql[i];
+        let next = sql.get(i + 1).copied();
+        if b.is_ascii_whitespace() {
+            spaced = true;
+            i += 1;
+            continue;
+        }
+        if b == b'/' && next == Some(b'*') {
+            i = sql[i + 2..]
+                .windows(2)
+                .position(|w| w == b"*/")
+                .map_or(sql.len(), |p| i + 2 + p + 2);
+            spaced = true;
+            continue;
+        }
+        if (b == b'-' && next == Some(b'-')) || (b == b'#' && dialect == SqlDialect::MySQL) {
+            i = sql[i..]
+                .iter()
+                .position(|c| *c == b'\n')
+                .map_or(sql.len(), |p| i + p + 1);
+            spaced = true;
+            continue;
+        }
+
+        let (token, end) = match b {
+            b'\'' => {
+                // X'0A'、B'01'、N'abc'、E'\n'等字面量的前缀一并去掉
+                // drop the prefix of literals like X'0A', B'01', N'abc' and E'\n'
+                let mut backslash_escape = dialect == SqlDialect::MySQL;
+                let prefix = match tokens.last() {
+                    Some((Token::Word([p]), _)) if !spaced => Some(*p),
+                    _ => None,
+                };
+                if let Some(p) = prefix.filter(|p| b"xXbBnNeE".contains(p)) {
+                    backslash_escape |= p.eq_ignore_ascii_case(&b'e');
+                    spaced = tokens.pop().unwrap().1;
+                }
+                (Token::Param, skip_quoted(sql, i, b, backslash_escape))
+            }
+            b'"' if dialect == SqlDialect::MySQL => (Token::Param, skip_quoted(sql, i, b, true)),
+            b'"' | b'`' => {
+                let end = skip_quoted(sql, i, b, false);
+                (Token::Word(&sql[i..end]), end)
+            }
+            b'$' if dialect == SqlDialect::PostgreSQL => match skip_dollar_quoted(sql, i) {
+                Some(end) => (Token::Param, end),
+                None => {
+                    let end = i
+                        + 1
+                        + sql[i + 1..]
+                            .iter()
+                            .take_while(|c| is_word_byte(**c))
+                            .count();
+                    (Token::Word(&sql[i..end]), end)
+                }
+            },
+            b'?' => (Token::Param, i + 1),
+            b'0'..=b'9' => (Token::Param, skip_number(sql, i)),
+            b'.' if matches!(next, Some(c) if c.is_ascii_digit()) => {
+                (Token::Param, skip_number(sql, i))
+            }
+            _ if is_word_byte(b) => {
+                let end = i + sql[i..].iter().take_while(|c| is_word_byte(**c)).count();
+                (Token::Word(&sql[i..end]), end)
+            }
+            _ => (Token::Punct(b), i + 1),
+        };
+        tokens.push((token, spaced));
+        spaced = false;
+        i = end;
+    }
+    tokens
+}
+
+// 返回从start处的左括号开始到匹配的右括号之后的位置
+// returns the position after the parenthesis matching the one at start
+fn skip_parenthesis(tokens: &[(Token, bool)], start: usize) -> Option<usize> {
+    let mut depth = 0;
+    for (i, (token, _)) in tokens.iter().enumerate().skip(start) {
+        match token {
+            Token::Punct(b'(') => depth += 1,
+            Token::Punct(b')') => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => (),
+        }
+    }
+    None
+}
+
+// IN (?, ?, ?)的列表只由占位符组成时返回右括号的位置
+// returns the position of the closing parenthesis if the IN list consists of placeholders only
+fn param_list_end(tokens: &[(Token, bool)], start: usize) -> Option<usize> {
+    let mut expect_param = true;
+    for (i, (token, _)) in tokens.iter().enumerate().skip(start + 1) {
+        match (token, expect_param) {
+            (Token::Param, true) => expect_param = false,
+            (Token::Punct(b','), false) => expect_param = true,
+            (Token::Punct(b')'), false) => return Some(i),
+            _ => return None,
+        }
+    }
+    None
+}
+
+fn is_keyword(token: &Token, keyword: &str) -> bool {
+    matches!(token, Token::Word(w) if w.eq_ignore_ascii_case(keyword.as_bytes()))
+}
+
+/*
+    normalize the sql statement into fingerprint:
+        comments are stripped and whitespaces are collapsed into one space,
+        string and number literals are replaced with `?`,
+        `IN (?, ?, ?)` is collapsed into `IN (?)`,
+        multiple rows after VALUES are collapsed into the first row.
+
+    for example `select * from t where id in (1, 2, 3) and name = 'a' -- comment`
+    is normalized into `select * from t where id in (?) and name = ?`.
+    returns None if the head comment is not closed.
+*/
+pub(super) fn normalize(sql: &str, dialect: SqlDialect) -> Option<String> {
+    let sql = trim_head_comment(sql)?;
+    let tokens = tokenize(sql.as_bytes(), dialect);
+
+    let mut fingerprint: Vec<u8> = Vec::with_capacity(sql.len());
+    let mut i = 0;
+    while i < tokens.len() {
+        let (token, spaced) = tokens[i];
+        if spaced && !fingerprint.is_empty() {
+            fingerprint.push(b' ');
+        }
+        match token {
+            Token::Word(w) => fingerprint.extend_from_slice(w),
+            Token::Param => fingerprint.push(b'?'),
+            Token::Punct(b) => fingerprint.push(b),
+        }
+        i += 1;
+
+        let next_is_parenthesis = matches!(tokens.get(i), Some((Token::Punct(b'('), _)));
+        if next_is_parenthesis && is_keyword(&token, "IN") {
+            if let Some(end) = param_list_end(&tokens, i) {
+                if tokens[i].1 {
+                    fingerprint.push(b' ');
+                }
+                fingerprint.extend_from_slice(b"(?)");
+                i = end + 1;
+            }
+        } else if next_is_parenthesis
+            && (is_keyword(&token, "VALUES") || is_keyword(&token, "VALUE"))
+        {
+            // 保留第一行，跳过后续的 , (...)
+            // keep the first row and skip the following `, (...)`
+            if let Some(first_row_end) = skip_parenthesis(&tokens, i) {
+                let mut end = first_row_end;
+                while matches!(tokens.get(end), Some((Token::Punct(b','), _)))
+                    && matches!(tokens.get(end + 1), Some((Token::Punct(b'('), _)))
+                {
+                    match skip_parenthesis(&tokens, end + 1) {
+                        Some(e) => end = e,
+                        None => break,
+                    }
+                }
+                if tokens[i].1 {
+                    fingerprint.push(b' ');
+                }
+                render(&tokens[i..first_row_end], &mut fingerprint);
+                i = end;
+            }
+        }
+    }
+    Some(String::from_utf8_lossy(&fingerprint).into_owned())
+}
+
+fn render(tokens: &[(Token, bool)], output: &mut Vec<u8>) {
+    for (i, (token, spaced)) in tokens.iter().enumerate() {
+        if *spaced && i > 0 {
+            output.push(b' ');
+        }
+        match token {
+            Token::Word(w) => output.extend_from_slice(w),
+            Token::Param => output.push(b'?'),
+            Token::Punct(b) => output.push(*b),
+        }
+    }
+}
+
+// 指纹的64位FNV-1a哈希，以16进制字符串表示
+// 64-bit FNV-1a hash of the fingerprint in hex
+pub(super) fn fingerprint_hash(fingerprint: &str) -> String {
+    let hash = fingerprint.bytes().fold(FNV_OFFSET_BASIS, |h, b| {
+        (h ^ b as u64).wrapping_mul(FNV_PRIME)
+    });
+    format!("{:016x}", hash)
+}
+
+// 按配置生成语句的指纹和指纹哈希，开启脱敏时以指纹代替原始语句，指纹无法生成时语句置空
+// =====================================================================================
+// generates the fingerprint and its hash of the statement according to the config, the
+// fingerprint replaces the raw statement if redaction is on, and the statement is cleared
+// if the fingerprint can not be generated
+pub(super) fn normalize_statement(
+    config: &SqlNormalizationConfig,
+    statement: &mut String,
+    dialect: SqlDialect,
+) -> (String, String) {
+    if !config.enabled && !config.redact_raw_statement {
+        return (String::new(), String::new());
+    }
+    let fingerprint = normalize(statement, dialect).unwrap_or_default();
+    let hash = fingerprint_hash(&fingerprint);
+    if config.redact_raw_statement {
+        *statement = fingerprint.clone();
+    }
+    (fingerprint, hash)
+}
+
+// 与上送语句相同的指纹不重复上送
+// the fingerprint is omitted if it is the same as the statement sent
+pub(super) fn fingerprint_attributes(
+    statement: &str,
+    fingerprint: String,
+    hash: String,
+) -> Option<Vec<KeyVal>> {
+    if hash.is_empty() {
+        return None;
+    }
+    let mut attributes = vec![];
+    if fingerprint != statement {
+        attributes.push(KeyVal {
+            key: String::from("sql_fingerprint"),
+            val: fingerprint,
+        });
+    }
+    attributes.push(KeyVal {
+        key: String::from("sql_fingerprint_hash"),
+        val: hash,
+    });
+    Some(attributes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_mysql() {
+        let cases = [
+            (
+                "/* app */ SELECT * FROM t WHERE id IN (1, 2, 3) AND name = 'a''b' -- tail",
+                "SELECT * FROM t WHERE id IN (?) AND name = ?",
+            ),
+            (
+                "select  a,\n\tb from `t` where c=\"x\\\"y\" # comment\n and d > -1.5e3",
+                "select a, b from `t` where c=? and d > -?",
+            ),
+            (
+                "INSERT INTO t (a, b) VALUES (1, 'x'), (2, 'y'),(3, NOW())",
+                "INSERT INTO t (a, b) VALUES (?, ?)",
+            ),
+            (
+                "select x'0A', 0xff, col1 from t2 where id in (select id from t3)",
+                "select ?, ?, col1 from t2 where id in (select id from t3)",
+            ),
+            (
+                "SELECT ? FROM t WHERE a IN(?,?)",
+                "SELECT ? FROM t WHERE a IN(?)",
+            ),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(
+                normalize(sql, SqlDialect::MySQL).as_deref(),
+                Some(expected),
+                "{}",
+                sql
+            );
+        }
+        assert_eq!(normalize("/* not closed SELECT 1", SqlDialect::MySQL), None);
+    }
+
+    #[test]
+    fn normalize_postgresql() {
+        let cases = [
+            (
+                "SELECT \"Name\" FROM users WHERE id = $1 AND note = E'it\\'s' AND tag = 'a'",
+                "SELECT \"Name\" FROM users WHERE id = $1 AND note = ? AND tag = ?",
+            ),
+            (
+                "select $tag$ a 'quoted' text $tag$, $$x$$ from t where a in (1,2)",
+                "select ?, ? from t where a in (?)",
+            ),
+            ("select 1 # 2", "select ? # ?"),
+        ];
+        for (sql, expected) in cases {
+            assert_eq!(
+                normalize(sql, SqlDialect::PostgreSQL).as_deref(),
+                Some(expected),
+                "{}",
+                sql
+            );
+        }
+    }
+
+    #[test]
+    fn check_fingerprint_hash() {
+        assert_eq!(fingerprint_hash(""), "cbf29ce484222325");
+        assert_eq!(fingerprint_hash("a"), "af63dc4c8601ec8c");
+        assert_eq!(
+            fingerprint_hash(&normalize("select 1", SqlDialect::MySQL).unwrap()),
+            fingerprint_hash(&normalize("select  22 -- x", SqlDialect::MySQL).unwrap()),
+        );
+    }
+}
@@ -19,10 +19,10 @@ use std::net::{IpAddr, Ipv6Addr, SocketAddr, TcpStream};
 use std::sync::atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use flate2::{read::GzDecoder, write::ZlibEncoder, Compression};
-use http::header::CONTENT_ENCODING;
+use http::header::{CONTENT_ENCODING, CONTENT_TYPE};
 use http::HeaderMap;
 use hyper::{
     body::{aggregate, Buf},
@@ -32,6 +32,7 @@ use hyper::{
 };
 use log::{debug, error, info, log_enabled, warn, Level};
 use prost::Message;
+use serde_json::Value as JsonValue;
 use tokio::{
     runtime::{Builder, Runtime},
     select,
@@ -43,7 +44,15 @@ use tokio::{
 use crate::exception::ExceptionHandler;
 use crate::proto::integration::opentelemetry::proto::{
     common::v1::any_value::Value,
-    common::v1::{AnyValue, KeyValue},
+    common::v1::{AnyValue, ArrayValue, InstrumentationScope, KeyValue, KeyValueList},
+    logs::v1::{LogRecord, LogsData, ResourceLogs, ScopeLogs},
+    metrics::v1::{
+        exemplar, exponential_histogram_data_point::Buckets, metric::Data, number_data_point,
+        summary_data_point::ValueAtQuantile, Exemplar, ExponentialHistogram,
+        ExponentialHistogramDataPoint, Gauge, Histogram, HistogramDataPoint, Metric, MetricsData,
+        NumberDataPoint, ResourceMetrics, ScopeMetrics, Sum, Summary, SummaryDataPoint,
+    },
+    resource::v1::Resource,
     trace::v1::TracesData,
 };
 use crate::proto::trident::Exception;
@@ -55,6 +64,9 @@ type GenericError = Box<dyn std::error::Error + Send + Sync>;
 
 const NOT_FOUND: &[u8] = b"Not Found";
 const GZIP: &str = "gzip";
+const JSON: &str = "application/json";
+const PROTOBUF: &str = "application/x-protobuf";
+const APP_HOST_IP: &str = "app.host.ip";
 
 // Otel的protobuf数据
 // ingester使用该proto https://github.com/open-telemetry/opentelemetry-proto/blob/main/opentelemetry/proto/trace/v1/trace.proto进行解析
@@ -67,6 +79,10 @@ impl OpenTelemetry {
         buf.append(&mut self.0);
         Ok(length)
     }
+
+    pub fn to_kv_string(&self, dst: &mut String) {
+        payload_to_kv_string("open_telemetry", base64::encode(&self.0), dst);
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -78,6 +94,80 @@ impl OpenTelemetryCompressed {
         buf.append(&mut self.0);
         Ok(length)
     }
+
+    pub fn to_kv_string(&self, dst: &mut String) {
+        payload_to_kv_string("open_telemetry_compressed", base64::encode(&self.0), dst);
+    }
+}
+
+// Otel metrics的protobuf数据，使用 https://github.com/open-telemetry/opentelemetry-proto/blob/main/opentelemetry/proto/metrics/v1/metrics.proto 解析
+#[derive(Debug, PartialEq)]
+pub struct OpenTelemetryMetrics(Vec<u8>);
+
+impl OpenTelemetryMetrics {
+    pub fn encode(mut self, buf: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
+        let length = self.0.len();
+        buf.append(&mut self.0);
+        Ok(length)
+    }
+
+    pub fn to_kv_string(&self, dst: &mut String) {
+        payload_to_kv_string("open_telemetry_metrics", base64::encode(&self.0), dst);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct OpenTelemetryMetricsCompressed(Vec<u8>);
+
+impl OpenTelemetryMetricsCompressed {
+    pub fn encode(mut self, buf: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
+        let length = self.0.len();
+        buf.append(&mut self.0);
+        Ok(length)
+    }
+
+    pub fn to_kv_string(&self, dst: &mut String) {
+        payload_to_kv_string(
+            "open_telemetry_metrics_compressed",
+            base64::encode(&self.0),
+            dst,
+        );
+    }
+}
+
+// Otel logs的protobuf数据，使用 https://github.com/open-telemetry/opentelemetry-proto/blob/main/opentelemetry/proto/logs/v1/logs.proto 解析
+#[derive(Debug, PartialEq)]
+pub struct OpenTelemetryLogs(Vec<u8>);
+
+impl OpenTelemetryLogs {
+    pub fn encode(mut self, buf: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
+        let length = self.0.len();
+        buf.append(&mut self.0);
+        Ok(length)
+    }
+
+    pub fn to_kv_string(&self, dst: &mut String) {
+        payload_to_kv_string("open_telemetry_logs", base64::encode(&self.0), dst);
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct OpenTelemetryLogsCompressed(Vec<u8>);
+
+impl OpenTelemetryLogsCompressed {
+    pub fn encode(mut self, buf: &mut Vec<u8>) -> Result<usize, prost::EncodeError> {
+        let length = self.0.len();
+        buf.append(&mut self.0);
+        Ok(length)
+    }
+
+    pub fn to_kv_string(&self, dst: &mut String) {
+        payload_to_kv_string(
+            "open_telemetry_logs_compressed",
+            base64::encode(&self.0),
+            dst,
+        );
+    }
 }
 
 /// Prometheus metrics, 格式是snappy压缩的pb数据
@@ -91,6 +181,10 @@ impl PrometheusMetric {
         buf.append(&mut self.0);
         Ok(length)
     }
+
+    pub fn to_kv_string(&self, dst: &mut String) {
+        payload_to_kv_string("prometheus", base64::encode(&self.0), dst);
+    }
 }
 
 /// Telegraf metric， 是influxDB标准行协议的UTF8编码的文本数据
@@ -103,6 +197,29 @@ impl TelegrafMetric {
         buf.append(&mut self.0);
         Ok(length)
     }
+
+    pub fn to_kv_string(&self, dst: &mut String) {
+        payload_to_kv_string(
+            "telegraf",
+            String::from_utf8_lossy(&self.0).into_owned(),
+            dst,
+        );
+    }
+}
+
+// 本地文件中二进制数据以base64编码保存，行协议文本原样保存
+// binary payloads are base64 encoded in local files, line protocol text is kept as is
+fn payload_to_kv_string(kind: &str, data: String, dst: &mut String) {
+    let json = serde_json::json!({
+        "type": kind,
+        "timestamp": SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs(),
+        "data": data,
+    });
+    dst.push_str(&json.to_string());
+    dst.push('\n');
 }
 
 fn decode_metric(mut whole_body: impl Buf, headers: &HeaderMap) -> Result<Vec<u8>, GenericError> {
@@ -145,70 +262,580 @@ async fn aggregate_with_catch_exception(
     })
 }
 
-fn decode_otel_trace_data(peer_addr: SocketAddr, data: Vec<u8>) -> Result<Vec<u8>, GenericError> {
-    let mut d = TracesData::decode(data.as_slice())?;
-    // 因为collector传过来traceData的全部resource都有"app.host.ip"的属性，所以只检查第一个resource有没有“app.host.ip”即可，
-    // sdk传过来的traceData因没有该属性则要补上(key: “app.host.ip”, value: 对端IP)属性值
-    // =======================================================================
-    // Because all the resources of the traceData passed by the collector have the attribute "app.host.ip",
-    // only check whether the first resource has "app.host.ip". The traceData passed by the sdk does not have this attribute.
-    //  Fill in the (key: "app.host.ip", value: peer IP) attribute value
-    let mut skip_verify_ip = false;
-    let host_ip = KeyValue {
-        key: "app.host.ip".into(),
+fn host_ip_attribute(peer_addr: SocketAddr) -> KeyValue {
+    let ip_str = match peer_addr.ip() {
+        IpAddr::V4(s) => s.to_string(),
+        IpAddr::V6(s) => match s.to_ipv4() {
+            Some(v4) => v4.to_string(),
+            None => s.to_string(),
+        },
+    };
+    KeyValue {
+        key: APP_HOST_IP.into(),
         value: Some(AnyValue {
-            value: {
-                let ip_str = match peer_addr.ip() {
-                    IpAddr::V4(s) => s.to_string(),
-                    IpAddr::V6(s) => match s.to_ipv4() {
-                        Some(v4) => v4.to_string(),
-                        None => s.to_string(),
-                    },
-                };
-                Some(Value::StringValue(ip_str))
-            },
+            value: Some(Value::StringValue(ip_str)),
         }),
-    };
-    for resource_span in d.resource_spans.iter_mut() {
-        if let Some(resource) = resource_span.resource.as_mut() {
-            if skip_verify_ip {
-                resource.attributes.push(host_ip.clone());
-            } else if resource
+    }
+}
+
+// 因为collector传过来数据的全部resource都有"app.host.ip"的属性，所以只检查第一个resource有没有“app.host.ip”即可，
+// sdk传过来的数据因没有该属性则要补上(key: “app.host.ip”, value: 对端IP)属性值，返回false表示数据无需修改
+// =======================================================================
+// Because all the resources of the data passed by the collector have the attribute "app.host.ip",
+// only check whether the first resource has "app.host.ip". The data passed by the sdk does not have this attribute.
+// Fill in the (key: "app.host.ip", value: peer IP) attribute value, returns false if the data is left untouched
+fn fill_host_ip<'a>(
+    peer_addr: SocketAddr,
+    resources: impl Iterator<Item = &'a mut Resource>,
+) -> bool {
+    let host_ip = host_ip_attribute(peer_addr);
+    let mut filled = false;
+    for resource in resources {
+        if !filled
+            && resource
                 .attributes
                 .iter()
-                .find(|attr| attr.key.as_str() == "app.host.ip")
-                .is_some()
-            {
-                debug!("send otel collector traces_data to sender: {:?}", d);
-                return Ok(data);
-            } else {
-                skip_verify_ip = true;
-                resource.attributes.push(host_ip.clone());
-            }
+                .any(|attr| attr.key.as_str() == APP_HOST_IP)
+        {
+            return false;
         }
+        filled = true;
+        resource.attributes.push(host_ip.clone());
+    }
+    filled
+}
+
+fn decode_otel_trace_data(peer_addr: SocketAddr, data: Vec<u8>) -> Result<Vec<u8>, GenericError> {
+    let mut d = TracesData::decode(data.as_slice())?;
+    if !fill_host_ip(
+        peer_addr,
+        d.resource_spans
+            .iter_mut()
+            .filter_map(|r| r.resource.as_mut()),
+    ) {
+        debug!("send otel collector traces_data to sender: {:?}", d);
+        return Ok(data);
     }
     let sdk_data = d.encode_to_vec();
     debug!("send otel sdk traces_data to sender: {:?}", d);
     return Ok(sdk_data);
 }
 
+fn decode_otel_metrics_data(
+    peer_addr: SocketAddr,
+    data: Vec<u8>,
+    json: bool,
+) -> Result<Vec<u8>, GenericError> {
+    let mut d = if json {
+        otlp_json::metrics_data(&serde_json::from_slice(data.as_slice())?)?
+    } else {
+        MetricsData::decode(data.as_slice())?
+    };
+    let filled = fill_host_ip(
+        peer_addr,
+        d.resource_metrics
+            .iter_mut()
+            .filter_map(|r| r.resource.as_mut()),
+    );
+    debug!("send otel metrics_data to sender: {:?}", d);
+    if !filled && !json {
+        return Ok(data);
+    }
+    Ok(d.encode_to_vec())
+}
+
+fn decode_otel_logs_data(
+    peer_addr: SocketAddr,
+    data: Vec<u8>,
+    json: bool,
+) -> Result<Vec<u8>, GenericError> {
+    let mut d = if json {
+        otlp_json::logs_data(&serde_json::from_slice(data.as_slice())?)?
+    } else {
+        LogsData::decode(data.as_slice())?
+    };
+    let filled = fill_host_ip(
+        peer_addr,
+        d.resource_logs
+            .iter_mut()
+            .filter_map(|r| r.resource.as_mut()),
+    );
+    debug!("send otel logs_data to sender: {:?}", d);
+    if !filled && !json {
+        return Ok(data);
+    }
+    Ok(d.encode_to_vec())
+}
+
+fn is_json_content(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| v.starts_with(JSON))
+        .is_some()
+}
+
+// OTLP/HTTP的JSON格式遵循protobuf的JSON映射，字段名使用lowerCamelCase(也兼容原始字段名)，
+// trace_id和span_id使用16进制字符串，64位整数可以是字符串，枚举值使用整数。
+// 参考 https://github.com/open-telemetry/opentelemetry-proto/blob/main/docs/specification.md#json-protobuf-encoding
+// =======================================================================
+// OTLP/HTTP JSON follows the proto3 JSON mapping: lowerCamelCase field names (original names are also accepted),
+// trace_id and span_id are hex strings, 64-bit integers may be strings and enums are integers.
+mod otlp_json {
+    use super::*;
+
+    type Result<T> = std::result::Result<T, GenericError>;
+
+    fn field<'a>(v: &'a JsonValue, name: &str) -> Option<&'a JsonValue> {
+        let o = v.as_object()?;
+        let f = match o.get(name) {
+            Some(f) => Some(f),
+            None => {
+                let mut snake_name = String::with_capacity(name.len() + 4);
+                for c in name.chars() {
+                    if c.is_ascii_uppercase() {
+                        snake_name.push('_');
+                        snake_name.push(c.to_ascii_lowercase());
+                    } else {
+                        snake_name.push(c);
+                    }
+                }
+                o.get(&snake_name)
+            }
+        };
+        f.filter(|f| !f.is_null())
+    }
+
+    fn to_u64(v: &JsonValue) -> Result<u64> {
+        match v {
+            JsonValue::Number(n) => n.as_u64(),
+            JsonValue::String(s) => s.parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| format!("invalid unsigned integer {}", v).into())
+    }
+
+    fn to_i64(v: &JsonValue) -> Result<i64> {
+        match v {
+            JsonValue::Number(n) => n.as_i64(),
+            JsonValue::String(s) => s.parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| format!("invalid integer {}", v).into())
+    }
+
+    fn to_f64(v: &JsonValue) -> Result<f64> {
+        match v {
+            JsonValue::Number(n) => n.as_f64(),
+            // proto3 JSON 使用字符串表示 NaN 和 Infinity
+            JsonValue::String(s) => s.parse().ok(),
+            _ => None,
+        }
+        .ok_or_else(|| format!("invalid double {}", v).into())
+    }
+
+    fn get_u64(v: &JsonValue, name: &str) -> Result<u64> {
+        field(v, name).map(to_u64).unwrap_or(Ok(0))
+    }
+
+    fn get_u32(v: &JsonValue, name: &str) -> Result<u32> {
+        Ok(u32::try_from(get_u64(v, name)?)?)
+    }
+
+    fn get_i32(v: &JsonValue, name: &str) -> Result<i32> {
+        Ok(field(v, name).map(to_i64).unwrap_or(Ok(0))?.try_into()?)
+    }
+
+    fn get_f64(v: &JsonValue, name: &str) -> Result<Option<f64>> {
+        field(v, name).map(to_f64).transpose()
+    }
+
+    fn get_bool(v: &JsonValue, name: &str) -> Result<bool> {
+        match field(v, name) {
+            None => Ok(false),
+            Some(f) => f
+                .as_bool()
+                .ok_or_else(|| format!("invalid bool {}", f).into()),
+        }
+    }
+
+    fn get_string(v: &JsonValue, name: &str) -> Result<String> {
+        match field(v, name) {
+            None => Ok(String::new()),
+            Some(f) => f
+                .as_str()
+                .map(|s| s.to_owned())
+                .ok_or_else(|| format!("invalid string {}", f).into()),
+        }
+    }
+
+    fn get_hex(v: &JsonValue, name: &str) -> Result<Vec<u8>> {
+        let s = get_string(v, name)?;
+        if s.len() % 2 != 0 {
+            return Err(format!("invalid hex string {}", s).into());
+        }
+        (0..s.len())
+            .step_by(2)
+            .map(|i| {
+                s.get(i..i + 2)
+                    .and_then(|b| u8::from_str_radix(b, 16).ok())
+                    .ok_or_else(|| format!("invalid hex string {}", s).into())
+            })
+            .collect()
+    }
+
+    fn get_list<T>(
+        v: &JsonValue,
+        name: &str,
+        f: impl Fn(&JsonValue) -> Result<T>,
+    ) -> Result<Vec<T>> {
+        match field(v, name) {
+            None => Ok(vec![]),
+            Some(JsonValue::Array(a)) => a.iter().map(f).collect(),
+            Some(o) => Err(format!("invalid array {}", o).into()),
+        }
+    }
+
+    fn get_message<T>(
+        v: &JsonValue,
+        name: &str,
+        f: impl Fn(&JsonValue) -> Result<T>,
+    ) -> Result<Option<T>> {
+        field(v, name).map(f).transpose()
+    }
+
+    fn any_value(v: &JsonValue) -> Result<AnyValue> {
+        let value = if let Some(s) = field(v, "stringValue") {
+            Value::StringValue(
+                s.as_str()
+                    .ok_or_else(|| format!("invalid string {}", s))?
+                    .to_owned(),
+            )
+        } else if field(v, "boolValue").is_some() {
+            Value::BoolValue(get_bool(v, "boolValue")?)
+        } else if let Some(i) = field(v, "intValue") {
+            Value::IntValue(to_i64(i)?)
+        } else if let Some(d) = field(v, "doubleValue") {
+            Value::DoubleValue(to_f64(d)?)
+        } else if let Some(a) = field(v, "arrayValue") {
+            Value::ArrayValue(ArrayValue {
+                values: get_list(a, "values", any_value)?,
+            })
+        } else if let Some(kv) = field(v, "kvlistValue") {
+            Value::KvlistValue(KeyValueList {
+                values: get_list(kv, "values", key_value)?,
+            })
+        } else if field(v, "bytesValue").is_some() {
+            // bytes 在 JSON 中使用 base64 编码，只有 trace_id 和 span_id 例外
+            Value::BytesValue(base64::decode(get_string(v, "bytesValue")?)?)
+        } else {
+            return Ok(AnyValue { value: None });
+        };
+        Ok(AnyValue { value: Some(value) })
+    }
+
+    fn key_value(v: &JsonValue) -> Result<KeyValue> {
+        Ok(KeyValue {
+            key: get_string(v, "key")?,
+            value: get_message(v, "value", any_value)?,
+        })
+    }
+
+    fn resource(v: &JsonValue) -> Result<Resource> {
+        Ok(Resource {
+            attributes: get_list(v, "attributes", key_value)?,
+            dropped_attributes_count: get_u32(v, "droppedAttributesCount")?,
+        })
+    }
+
+    fn scope(v: &JsonValue) -> Result<InstrumentationScope> {
+        Ok(InstrumentationScope {
+            name: get_string(v, "name")?,
+            version: get_string(v, "version")?,
+            attributes: get_list(v, "attributes", key_value)?,
+            dropped_attributes_count: get_u32(v, "droppedAttributesCount")?,
+        })
+    }
+
+    fn exemplar(v: &JsonValue) -> Result<Exemplar> {
+        Ok(Exemplar {
+            filtered_attributes: get_list(v, "filteredAttributes", key_value)?,
+            time_unix_nano: get_u64(v, "timeUnixNano")?,
+            span_id: get_hex(v, "spanId")?,
+            trace_id: get_hex(v, "traceId")?,
+            value: if let Some(d) = field(v, "asDouble") {
+                Some(exemplar::Value::AsDouble(to_f64(d)?))
+            } else if let Some(i) = field(v, "asInt") {
+                Some(exemplar::Value::AsInt(to_i64(i)?))
+            } else {
+                None
+            },
+        })
+    }
+
+    fn number_data_point(v: &JsonValue) -> Result<NumberDataPoint> {
+        Ok(NumberDataPoint {
+            attributes: get_list(v, "attributes", key_value)?,
+            start_time_unix_nano: get_u64(v, "startTimeUnixNano")?,
+            time_unix_nano: get_u64(v, "timeUnixNano")?,
+            exemplars: get_list(v, "exemplars", exemplar)?,
+            flags: get_u32(v, "flags")?,
+            value: if let Some(d) = field(v, "asDouble") {
+                Some(number_data_point::Value::AsDouble(to_f64(d)?))
+            } else if let Some(i) = field(v, "asInt") {
+                Some(number_data_point::Value::AsInt(to_i64(i)?))
+            } else {
+                None
+            },
+        })
+    }
+
+    fn histogram_data_point(v: &JsonValue) -> Result<HistogramDataPoint> {
+        Ok(HistogramDataPoint {
+            attributes: get_list(v, "attributes", key_value)?,
+            start_time_unix_nano: get_u64(v, "startTimeUnixNano")?,
+            time_unix_nano: get_u64(v, "timeUnixNano")?,
+            count: get_u64(v, "count")?,
+            sum: get_f64(v, "sum")?,
+            bucket_counts: get_list(v, "bucketCounts", to_u64)?,
+            explicit_bounds: get_list(v, "explicitBounds", to_f64)?,
+            exemplars: get_list(v, "exemplars", exemplar)?,
+            flags: get_u32(v, "flags")?,
+            min: get_f64(v, "min")?,
+            max: get_f64(v, "max")?,
+            ..Default::default()
+        })
+    }
+
+    fn buckets(v: &JsonValue) -> Result<Buckets> {
+        Ok(Buckets {
+            offset: get_i32(v, "offset")?,
+            bucket_counts: get_list(v, "bucketCounts", to_u64)?,
+        })
+    }
+
+    fn exponential_histogram_data_point(v: &JsonValue) -> Result<ExponentialHistogramDataPoint> {
+        Ok(ExponentialHistogramDataPoint {
+            attributes: get_list(v, "attributes", key_value)?,
+            start_time_unix_nano: get_u64(v, "startTimeUnixNano")?,
+            time_unix_nano: get_u64(v, "timeUnixNano")?,
+            count: get_u64(v, "count")?,
+            sum: get_f64(v, "sum")?,
+            scale: get_i32(v, "scale")?,
+            zero_count: get_u64(v, "zeroCount")?,
+            positive: get_message(v, "positive", buckets)?,
+            negative: get_message(v, "negative", buckets)?,
+            flags: get_u32(v, "flags")?,
+            exemplars: get_list(v, "exemplars", exemplar)?,
+            min: get_f64(v, "min")?,
+            max: get_f64(v, "max")?,
+            ..Default::default()
+        })
+    }
+
+    fn summary_data_point(v: &JsonValue) -> Result<SummaryDataPoint> {
+        Ok(SummaryDataPoint {
+            attributes: get_list(v, "attributes", key_value)?,
+            start_time_unix_nano: get_u64(v, "startTimeUnixNano")?,
+            time_unix_nano: get_u64(v, "timeUnixNano")?,
+            count: get_u64(v, "count")?,
+            sum: get_f64(v, "sum")?.unwrap_or_default(),
+            quantile_values: get_list(v, "quantileValues", |q| {
+                Ok(ValueAtQuantile {
+                    quantile: get_f64(q, "quantile")?.unwrap_or_default(),
+                    value: get_f64(q, "value")?.unwrap_or_default(),
+                })
+            })?,
+            flags: get_u32(v, "flags")?,
+        })
+    }
+
+    fn metric(v: &JsonValue) -> Result<Metric> {
+        let data = if let Some(g) = field(v, "gauge") {
+            Some(Data::Gauge(Gauge {
+                data_points: get_list(g, "dataPoints", number_data_point)?,
+            }))
+        } else if let Some(s) = field(v, "sum") {
+            Some(Data::Sum(Sum {
+                data_points: get_list(s, "dataPoints", number_data_point)?,
+                aggregation_temporality: get_i32(s, "aggregationTemporality")?,
+                is_monotonic: get_bool(s, "isMonotonic")?,
+            }))
+        } else if let Some(h) = field(v, "histogram") {
+            Some(Data::Histogram(Histogram {
+                data_points: get_list(h, "dataPoints", histogram_data_point)?,
+                aggregation_temporality: get_i32(h, "aggregationTemporality")?,
+            }))
+        } else if let Some(h) = field(v, "exponentialHistogram") {
+            Some(Data::ExponentialHistogram(ExponentialHistogram {
+                data_points: get_list(h, "dataPoints", exponential_histogram_data_point)?,
+                aggregation_temporality: get_i32(h, "aggregationTemporality")?,
+            }))
+        } else if let Some(s) = field(v, "summary") {
+            Some(Data::Summary(Summary {
+                data_points: get_list(s, "dataPoints", summary_data_point)?,
+            }))
+        } else {
+            None
+        };
+        Ok(Metric {
+            name: get_string(v, "name")?,
+            description: get_string(v, "description")?,
+            unit: get_string(v, "unit")?,
+            data,
+        })
+    }
+
+    pub(super) fn metrics_data(v: &JsonValue) -> Result<MetricsData> {
+        Ok(MetricsData {
+            resource_metrics: get_list(v, "resourceMetrics", |rm| {
+                Ok(ResourceMetrics {
+                    resource: get_message(rm, "resource", resource)?,
+                    scope_metrics: get_list(rm, "scopeMetrics", |sm| {
+                        Ok(ScopeMetrics {
+                            scope: get_message(sm, "scope", scope)?,
+                            metrics: get_list(sm, "metrics", metric)?,
+                            schema_url: get_string(sm, "schemaUrl")?,
+                        })
+                    })?,
+                    schema_url: get_string(rm, "schemaUrl")?,
+                    ..Default::default()
+                })
+            })?,
+        })
+    }
+
+    fn log_record(v: &JsonValue) -> Result<LogRecord> {
+        Ok(LogRecord {
+            time_unix_nano: get_u64(v, "timeUnixNano")?,
+            observed_time_unix_nano: get_u64(v, "observedTimeUnixNano")?,
+            severity_number: get_i32(v, "severityNumber")?,
+            severity_text: get_string(v, "severityText")?,
+            body: get_message(v, "body", any_value)?,
+            attributes: get_list(v, "attributes", key_value)?,
+            dropped_attributes_count: get_u32(v, "droppedAttributesCount")?,
+            flags: get_u32(v, "flags")?,
+            trace_id: get_hex(v, "traceId")?,
+            span_id: get_hex(v, "spanId")?,
+            ..Default::default()
+        })
+    }
+
+    pub(super) fn logs_data(v: &JsonValue) -> Result<LogsData> {
+        Ok(LogsData {
+            resource_logs: get_list(v, "resourceLogs", |rl| {
+                Ok(ResourceLogs {
+                    resource: get_message(rl, "resource", resource)?,
+                    scope_logs: get_list(rl, "scopeLogs", |sl| {
+                        Ok(ScopeLogs {
+                            scope: get_message(sl, "scope", scope)?,
+                            log_records: get_list(sl, "logRecords", log_record)?,
+                            schema_url: get_string(sl, "schemaUrl")?,
+                        })
+                    })?,
+                    schema_url: get_string(rl, "schemaUrl")?,
+                    ..Default::default()
+                })
+            })?,
+        })
+    }
+}
+
 fn compress_data(input: Vec<u8>) -> std::io::Result<Vec<u8>> {
     let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
     e.write_all(input.as_slice())?;
     e.finish()
 }
 
+fn send_otel_data(
+    data: Vec<u8>,
+    compressed: bool,
+    counter: &CompressedMetric,
+    sender: &DebugSender<SendItem>,
+    compressed_sender: &DebugSender<SendItem>,
+    item: fn(Vec<u8>) -> SendItem,
+    compressed_item: fn(Vec<u8>) -> SendItem,
+) -> std::io::Result<()> {
+    let result = if compressed {
+        counter
+            .uncompressed
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        let compressed_data = compress_data(data)?;
+        counter
+            .compressed
+            .fetch_add(compressed_data.len() as u64, Ordering::Relaxed);
+        compressed_sender.send(compressed_item(compressed_data))
+    } else {
+        sender.send(item(data))
+    };
+    if let Err(Error::Terminated(..)) = result {
+        warn!("sender queue has terminated");
+    }
+    Ok(())
+}
+
+// OTLP/HTTP 的 metrics 和 logs 请求，支持protobuf和JSON格式，响应使用与请求相同的格式
+// =======================================================================
+// OTLP/HTTP metrics and logs requests in protobuf or JSON, the response uses the same format as the request
+async fn handle_otel_signal(
+    peer_addr: SocketAddr,
+    req: Request<Body>,
+    exception_handler: &ExceptionHandler,
+    counter: &OtelSignalMetric,
+    decode: fn(SocketAddr, Vec<u8>, bool) -> Result<Vec<u8>, GenericError>,
+    send: impl FnOnce(Vec<u8>) -> std::io::Result<()>,
+) -> Result<Response<Body>, GenericError> {
+    let (part, body) = req.into_parts();
+    let whole_body = match aggregate_with_catch_exception(body, exception_handler).await {
+        Ok(b) => b,
+        Err(e) => {
+            return Ok(e);
+        }
+    };
+    counter.requests.fetch_add(1, Ordering::Relaxed);
+    let json = is_json_content(&part.headers);
+    let decode_data = match decode_metric(whole_body, &part.headers).and_then(|data| {
+        counter
+            .bytes
+            .fetch_add(data.len() as u64, Ordering::Relaxed);
+        decode(peer_addr, data, json)
+    }) {
+        Ok(d) => d,
+        Err(e) => {
+            debug!("decode otel data error: {}", e);
+            counter.errors.fetch_add(1, Ordering::Relaxed);
+            return Ok(Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(e.to_string().into())
+                .unwrap());
+        }
+    };
+    send(decode_data)?;
+
+    // 空的Export*ServiceResponse表示全部接收成功
+    // An empty Export*ServiceResponse means full success
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, if json { JSON } else { PROTOBUF })
+        .body(if json { "{}".into() } else { Body::empty() })
+        .unwrap())
+}
+
 /// 接收metric server发送的请求，根据路由处理分发
 async fn handler(
     peer_addr: SocketAddr,
     req: Request<Body>,
     otel_sender: DebugSender<SendItem>,
     compressed_otel_sender: DebugSender<SendItem>,
+    otel_metrics_sender: DebugSender<SendItem>,
+    compressed_otel_metrics_sender: DebugSender<SendItem>,
+    otel_logs_sender: DebugSender<SendItem>,
+    compressed_otel_logs_sender: DebugSender<SendItem>,
     prometheus_sender: DebugSender<SendItem>,
     telegraf_sender: DebugSender<SendItem>,
     exception_handler: ExceptionHandler,
     compressed: bool,
-    counter: Arc<CompressedMetric>,
+    otel_metrics_logs_enabled: bool,
+    counter: IntegrationCounter,
 ) -> Result<Response<Body>, GenericError> {
     match (req.method(), req.uri().path()) {
         (&Method::GET, "/") => {
@@ -234,10 +861,12 @@ async fn handler(
             })?;
             if compressed {
                 counter
+                    .metrics
                     .uncompressed
                     .fetch_add(decode_data.len() as u64, Ordering::Relaxed);
                 let compressed_data = compress_data(decode_data)?;
                 counter
+                    .metrics
                     .compressed
                     .fetch_add(compressed_data.len() as u64, Ordering::Relaxed);
                 if let Err(Error::Terminated(..)) = compressed_otel_sender.send(
@@ -255,6 +884,54 @@ async fn handler(
 
             Ok(Response::builder().body(Body::empty()).unwrap())
         }
+        // OpenTelemetry metrics integration
+        (&Method::POST, "/v1/metrics") if otel_metrics_logs_enabled => {
+            handle_otel_signal(
+                peer_addr,
+                req,
+                &exception_handler,
+                &counter.otel_metrics,
+                decode_otel_metrics_data,
+                |data| {
+                    send_otel_data(
+                        data,
+                        compressed,
+                        &counter.otel_metrics_compressed,
+                        &otel_metrics_sender,
+                        &compressed_otel_metrics_sender,
+                        |d| SendItem::ExternalOtelMetrics(OpenTelemetryMetrics(d)),
+                        |d| {
+                            SendItem::ExternalOtelMetricsCompressed(OpenTelemetryMetricsCompressed(
+                                d,
+                            ))
+                        },
+                    )
+                },
+            )
+            .await
+        }
+        // OpenTelemetry logs integration
+        (&Method::POST, "/v1/logs") if otel_metrics_logs_enabled => {
+            handle_otel_signal(
+                peer_addr,
+                req,
+                &exception_handler,
+                &counter.otel_logs,
+                decode_otel_logs_data,
+                |data| {
+                    send_otel_data(
+                        data,
+                        compressed,
+                        &counter.otel_logs_compressed,
+                        &otel_logs_sender,
+                        &compressed_otel_logs_sender,
+                        |d| SendItem::ExternalOtelLogs(OpenTelemetryLogs(d)),
+                        |d| SendItem::ExternalOtelLogsCompressed(OpenTelemetryLogsCompressed(d)),
+                    )
+                },
+            )
+            .await
+        }
         // Prometheus integration
         (&Method::POST, "/api/v1/prometheus") => {
             let mut whole_body =
@@ -310,30 +987,26 @@ struct CompressedMetric {
     uncompressed: AtomicU64, // unit (bytes)
 }
 
-#[derive(Default)]
-pub struct IntegrationCounter {
-    metrics: Arc<CompressedMetric>,
-}
-
-impl OwnedCountable for IntegrationCounter {
-    fn get_counters(&self) -> Vec<Counter> {
+impl CompressedMetric {
+    // names: [compressed, uncompressed, compressed_ratio]
+    fn get_counters(&self, names: [&'static str; 3]) -> Vec<Counter> {
         let (compressed, uncomressed) = (
-            self.metrics.compressed.swap(0, Ordering::Relaxed),
-            self.metrics.uncompressed.swap(0, Ordering::Relaxed),
+            self.compressed.swap(0, Ordering::Relaxed),
+            self.uncompressed.swap(0, Ordering::Relaxed),
         );
         vec![
             (
-                "compressed",
+                names[0],
                 CounterType::Counted,
                 CounterValue::Unsigned(compressed),
             ),
             (
-                "uncompressed",
+                names[1],
                 CounterType::Counted,
                 CounterValue::Unsigned(uncomressed),
             ),
             (
-                "compressed_ratio",
+                names[2],
                 CounterType::Gauged,
                 CounterValue::Float(if compressed == 0 {
                     // avoid divide by zero
@@ -344,37 +1017,115 @@ impl OwnedCountable for IntegrationCounter {
             ),
         ]
     }
+}
+
+#[derive(Default)]
+struct OtelSignalMetric {
+    requests: AtomicU64,
+    bytes: AtomicU64, // unit (bytes)
+    errors: AtomicU64,
+}
+
+impl OtelSignalMetric {
+    // names: [requests, bytes, errors]
+    fn get_counters(&self, names: [&'static str; 3]) -> Vec<Counter> {
+        vec![
+            (
+                names[0],
+                CounterType::Counted,
+                CounterValue::Unsigned(self.requests.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                names[1],
+                CounterType::Counted,
+                CounterValue::Unsigned(self.bytes.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                names[2],
+                CounterType::Counted,
+                CounterValue::Unsigned(self.errors.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct IntegrationCounter {
+    metrics: Arc<CompressedMetric>,
+    otel_metrics: Arc<OtelSignalMetric>,
+    otel_metrics_compressed: Arc<CompressedMetric>,
+    otel_logs: Arc<OtelSignalMetric>,
+    otel_logs_compressed: Arc<CompressedMetric>,
+}
+
+impl OwnedCountable for IntegrationCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        let mut counters =
+            self.metrics
+                .get_counters(["compressed", "uncompressed", "compressed_ratio"]);
+        counters.append(&mut self.otel_metrics.get_counters([
+            "otel_metrics_requests",
+            "otel_metrics_bytes",
+            "otel_metrics_errors",
+        ]));
+        counters.append(&mut self.otel_metrics_compressed.get_counters([
+            "otel_metrics_compressed",
+            "otel_metrics_uncompressed",
+            "otel_metrics_compressed_ratio",
+        ]));
+        counters.append(&mut self.otel_logs.get_counters([
+            "otel_logs_requests",
+            "otel_logs_bytes",
+            "otel_logs_errors",
+        ]));
+        counters.append(&mut self.otel_logs_compressed.get_counters([
+            "otel_logs_compressed",
+            "otel_logs_uncompressed",
+            "otel_logs_compressed_ratio",
+        ]));
+        counters
+    }
 
     fn closed(&self) -> bool {
         false
     }
 }
 
-/// 监听HTTP端口，接收OpenTelemetry的trace pb数据，然后发送到Sender
+/// 监听HTTP端口，接收OpenTelemetry的trace、metrics和logs数据，然后发送到Sender
 pub struct MetricServer {
     running: Arc<AtomicBool>,
     rt: Runtime,
     thread: Arc<Mutex<Option<JoinHandle<()>>>>,
     otel_sender: DebugSender<SendItem>,
     compressed_otel_sender: DebugSender<SendItem>,
+    otel_metrics_sender: DebugSender<SendItem>,
+    compressed_otel_metrics_sender: DebugSender<SendItem>,
+    otel_logs_sender: DebugSender<SendItem>,
+    compressed_otel_logs_sender: DebugSender<SendItem>,
     prometheus_sender: DebugSender<SendItem>,
     telegraf_sender: DebugSender<SendItem>,
     port: Arc<AtomicU16>,
     exception_handler: ExceptionHandler,
     server_shutdown_tx: Mutex<Option<mpsc::Sender<()>>>,
-    counter: Arc<CompressedMetric>,
+    counter: IntegrationCounter,
     compressed: Arc<AtomicBool>,
+    otel_metrics_logs_enabled: Arc<AtomicBool>,
 }
 
 impl MetricServer {
     pub fn new(
         otel_sender: DebugSender<SendItem>,
         compressed_otel_sender: DebugSender<SendItem>,
+        otel_metrics_sender: DebugSender<SendItem>,
+        compressed_otel_metrics_sender: DebugSender<SendItem>,
+        otel_logs_sender: DebugSender<SendItem>,
+        compressed_otel_logs_sender: DebugSender<SendItem>,
         prometheus_sender: DebugSender<SendItem>,
         telegraf_sender: DebugSender<SendItem>,
         port: u16,
         exception_handler: ExceptionHandler,
         compressed: bool,
+        otel_metrics_logs_enabled: bool,
     ) -> (Self, IntegrationCounter) {
         let counter = IntegrationCounter::default();
         (
@@ -388,14 +1139,19 @@ impl MetricServer {
                     .unwrap(),
                 thread: Arc::new(Mutex::new(None)),
                 compressed: Arc::new(AtomicBool::new(compressed)),
+                otel_metrics_logs_enabled: Arc::new(AtomicBool::new(otel_metrics_logs_enabled)),
                 otel_sender,
                 compressed_otel_sender,
+                otel_metrics_sender,
+                compressed_otel_metrics_sender,
+                otel_logs_sender,
+                compressed_otel_logs_sender,
                 prometheus_sender,
                 telegraf_sender,
                 port: Arc::new(AtomicU16::new(port)),
                 exception_handler,
                 server_shutdown_tx: Default::default(),
-                counter: counter.metrics.clone(),
+                counter: counter.clone(),
             },
             counter,
         )
@@ -405,6 +1161,13 @@ impl MetricServer {
         self.compressed.store(enable, Ordering::Relaxed);
     }
 
+    // 关闭时 /v1/metrics 和 /v1/logs 返回 404
+    // /v1/metrics and /v1/logs return 404 when disabled
+    pub fn enable_otel_metrics_logs(&self, enable: bool) {
+        self.otel_metrics_logs_enabled
+            .store(enable, Ordering::Relaxed);
+    }
+
     pub fn set_port(&self, port: u16) {
         if self.port.swap(port, Ordering::Release) != port {
             // port changes, resets server
@@ -422,6 +1185,10 @@ impl MetricServer {
 
         let otel_sender = self.otel_sender.clone();
         let compressed_otel_sender = self.compressed_otel_sender.clone();
+        let otel_metrics_sender = self.otel_metrics_sender.clone();
+        let compressed_otel_metrics_sender = self.compressed_otel_metrics_sender.clone();
+        let otel_logs_sender = self.otel_logs_sender.clone();
+        let compressed_otel_logs_sender = self.compressed_otel_logs_sender.clone();
         let prometheus_sender = self.prometheus_sender.clone();
         let telegraf_sender = self.telegraf_sender.clone();
         let port = self.port.clone();
@@ -431,6 +1198,7 @@ impl MetricServer {
         let running = self.running.clone();
         let counter = self.counter.clone();
         let compressed = self.compressed.clone();
+        let otel_metrics_logs_enabled = self.otel_metrics_logs_enabled.clone();
         let (tx, mut rx) = mpsc::channel(8);
         self.rt
             .spawn(Self::alive_check(monitor_port.clone(), tx.clone(), mon_rx));
@@ -477,20 +1245,30 @@ impl MetricServer {
 
                     let otel_sender = otel_sender.clone();
                     let compressed_otel_sender = compressed_otel_sender.clone();
+                    let otel_metrics_sender = otel_metrics_sender.clone();
+                    let compressed_otel_metrics_sender = compressed_otel_metrics_sender.clone();
+                    let otel_logs_sender = otel_logs_sender.clone();
+                    let compressed_otel_logs_sender = compressed_otel_logs_sender.clone();
                     let prometheus_sender = prometheus_sender.clone();
                     let telegraf_sender = telegraf_sender.clone();
                     let exception_handler_inner = exception_handler.clone();
                     let counter = counter.clone();
                     let compressed = compressed.clone();
+                    let otel_metrics_logs_enabled = otel_metrics_logs_enabled.clone();
                     let service = make_service_fn(move |conn: &AddrStream| {
                         let otel_sender = otel_sender.clone();
                         let compressed_otel_sender = compressed_otel_sender.clone();
+                        let otel_metrics_sender = otel_metrics_sender.clone();
+                        let compressed_otel_metrics_sender = compressed_otel_metrics_sender.clone();
+                        let otel_logs_sender = otel_logs_sender.clone();
+                        let compressed_otel_logs_sender = compressed_otel_logs_sender.clone();
                         let prometheus_sender = prometheus_sender.clone();
                         let telegraf_sender = telegraf_sender.clone();
                         let exception_handler = exception_handler_inner.clone();
                         let peer_addr = conn.remote_addr();
                         let counter = counter.clone();
                         let compressed = compressed.clone();
+                        let otel_metrics_logs_enabled = otel_metrics_logs_enabled.clone();
                         async move {
                             Ok::<_, GenericError>(service_fn(move |req| {
                                 handler(
@@ -498,10 +1276,15 @@ impl MetricServer {
                                     req,
                                     otel_sender.clone(),
                                     compressed_otel_sender.clone(),
+                                    otel_metrics_sender.clone(),
+                                    compressed_otel_metrics_sender.clone(),
+                                    otel_logs_sender.clone(),
+                                    compressed_otel_logs_sender.clone(),
                                     prometheus_sender.clone(),
                                     telegraf_sender.clone(),
                                     exception_handler.clone(),
                                     compressed.load(Ordering::Relaxed),
+                                    otel_metrics_logs_enabled.load(Ordering::Relaxed),
                                     counter.clone(),
                                 )
                             }))
@@ -569,3 +1352,187 @@ impl MetricServer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer_addr() -> SocketAddr {
+        "[::ffff:10.1.2.3]:12345".parse().unwrap()
+    }
+
+    fn host_ip(resource: &Option<Resource>) -> Option<&Value> {
+        resource
+            .as_ref()?
+            .attributes
+            .iter()
+            .find(|attr| attr.key == APP_HOST_IP)?
+            .value
+            .as_ref()?
+            .value
+            .as_ref()
+    }
+
+    #[test]
+    fn decode_json_metrics() {
+        let json = r#"{
+            "resourceMetrics": [{
+                "resource": {"attributes": [{"key": "service.name", "value": {"stringValue": "svc"}}]},
+                "scopeMetrics": [{
+                    "scope": {"name": "meter"},
+                    "metrics": [{
+                        "name": "requests",
+                        "unit": "1",
+                        "sum": {
+                            "aggregationTemporality": 2,
+                            "isMonotonic": true,
+                            "dataPoints": [{"asInt": "42", "timeUnixNano": "1670000000000000000"}]
+                        }
+                    }, {
+                        "name": "latency",
+                        "histogram": {
+                            "aggregationTemporality": 1,
+                            "dataPoints": [{
+                                "count": 3,
+                                "sum": 1.5,
+                                "bucket_counts": ["1", "2"],
+                                "explicitBounds": [0.5]
+                            }]
+                        }
+                    }]
+                }]
+            }]
+        }"#;
+        let data = decode_otel_metrics_data(peer_addr(), json.as_bytes().to_vec(), true).unwrap();
+        let d = MetricsData::decode(data.as_slice()).unwrap();
+        let rm = &d.resource_metrics[0];
+        assert_eq!(
+            host_ip(&rm.resource),
+            Some(&Value::StringValue("10.1.2.3".into()))
+        );
+        let metrics = &rm.scope_metrics[0].metrics;
+        assert_eq!(metrics[0].name, "requests");
+        match metrics[0].data.as_ref() {
+            Some(Data::Sum(s)) => {
+                assert!(s.is_monotonic);
+                assert_eq!(s.aggregation_temporality, 2);
+                assert_eq!(s.data_points[0].time_unix_nano, 1670000000000000000);
+                assert_eq!(
+                    s.data_points[0].value,
+                    Some(number_data_point::Value::AsInt(42))
+                );
+            }
+            d => panic!("unexpected metric data {:?}", d),
+        }
+        match metrics[1].data.as_ref() {
+            Some(Data::Histogram(h)) => {
+                assert_eq!(h.data_points[0].count, 3);
+                assert_eq!(h.data_points[0].sum, Some(1.5));
+                assert_eq!(h.data_points[0].bucket_counts, vec![1, 2]);
+                assert_eq!(h.data_points[0].explicit_bounds, vec![0.5]);
+            }
+            d => panic!("unexpected metric data {:?}", d),
+        }
+    }
+
+    #[test]
+    fn decode_json_logs() {
+        let json = r#"{
+            "resourceLogs": [{
+                "resource": {},
+                "scopeLogs": [{
+                    "logRecords": [{
+                        "timeUnixNano": "1670000000000000000",
+                        "severityNumber": 9,
+                        "severityText": "INFO",
+                        "body": {"stringValue": "hello"},
+                        "attributes": [{"key": "payload", "value": {"bytesValue": "AQID"}}],
+                        "traceId": "5b8efff798038103d269b633813fc60c",
+                        "spanId": "eee19b7ec3c1b174"
+                    }]
+                }]
+            }]
+        }"#;
+        let data = decode_otel_logs_data(peer_addr(), json.as_bytes().to_vec(), true).unwrap();
+        let d = LogsData::decode(data.as_slice()).unwrap();
+        let rl = &d.resource_logs[0];
+        assert_eq!(
+            host_ip(&rl.resource),
+            Some(&Value::StringValue("10.1.2.3".into()))
+        );
+        let record = &rl.scope_logs[0].log_records[0];
+        assert_eq!(record.severity_number, 9);
+        assert_eq!(record.severity_text, "INFO");
+        assert_eq!(
+            record.body.as_ref().and_then(|b| b.value.as_ref()),
+            Some(&Value::StringValue("hello".into()))
+        );
+        assert_eq!(
+            record.attributes[0]
+                .value
+                .as_ref()
+                .and_then(|v| v.value.as_ref()),
+            Some(&Value::BytesValue(vec![1, 2, 3]))
+        );
+        assert_eq!(
+            record.trace_id,
+            vec![
+                0x5b, 0x8e, 0xff, 0xf7, 0x98, 0x03, 0x81, 0x03, 0xd2, 0x69, 0xb6, 0x33, 0x81, 0x3f,
+                0xc6, 0x0c
+            ]
+        );
+        assert_eq!(
+            record.span_id,
+            vec![0xee, 0xe1, 0x9b, 0x7e, 0xc3, 0xc1, 0xb1, 0x74]
+        );
+
+        let invalid =
+            r#"{"resourceLogs": [{"scopeLogs": [{"logRecords": [{"traceId": "5b8"}]}]}]}"#;
+        assert!(decode_otel_logs_data(peer_addr(), invalid.as_bytes().to_vec(), true).is_err());
+    }
+
+    #[test]
+    fn decode_collector_logs() {
+        let d = LogsData {
+            resource_logs: vec![ResourceLogs {
+                resource: Some(Resource {
+                    attributes: vec![host_ip_attribute("192.168.1.1:4318".parse().unwrap())],
+                    dropped_attributes_count: 0,
+                }),
+                ..Default::default()
+            }],
+        };
+        let data = d.encode_to_vec();
+        // 已经带有app.host.ip的数据原样发送
+        assert_eq!(
+            decode_otel_logs_data(peer_addr(), data.clone(), false).unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn compressed_counters() {
+        let counter = IntegrationCounter::default();
+        counter
+            .otel_metrics_compressed
+            .uncompressed
+            .fetch_add(300, Ordering::Relaxed);
+        counter
+            .otel_metrics_compressed
+            .compressed
+            .fetch_add(100, Ordering::Relaxed);
+
+        let counters = counter.get_counters();
+        let value = |name: &str| counters.iter().find(|c| c.0 == name).unwrap().2;
+        // metrics 的压缩不计入 trace 的压缩比
+        assert_eq!(value("compressed_ratio"), CounterValue::Float(0.0));
+        assert_eq!(
+            value("otel_metrics_compressed_ratio"),
+            CounterValue::Float(3.0)
+        );
+        assert_eq!(
+            value("otel_logs_compressed_ratio"),
+            CounterValue::Float(0.0)
+        );
+    }
+}
@@ -36,10 +36,12 @@ mod pcap;
 mod platform;
 mod policy;
 mod proto;
+pub mod replay;
 mod rpc;
 mod sender;
 pub mod trident;
 mod utils;
+mod xflow_collector;
 
 // for benchmarks
 #[doc(hidden)]
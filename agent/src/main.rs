@@ -18,6 +18,7 @@ use std::path::Path;
 
 use anyhow::Result;
 use clap::{ArgAction, Parser};
+use flexi_logger::{colored_opt_format, Logger};
 #[cfg(target_os = "linux")]
 use signal_hook::{consts::TERM_SIGNALS, iterator::Signals};
 
@@ -57,6 +58,18 @@ struct Opts {
     /// grant capabilities including cap_net_admin, cap_net_raw,cap_net_bind_service
     #[clap(long)]
     add_cap: bool,
+
+    /// Replay a pcap/pcapng file through the flow pipeline and exit, used with '--standalone' to load config from '-f'
+    #[clap(long)]
+    replay: Option<String>,
+
+    /// Output directory of flow logs and metrics, used with '--replay'
+    #[clap(long, default_value = ".")]
+    replay_output: String,
+
+    /// Replay speed multiplier, 0 means as fast as possible, used with '--replay'
+    #[clap(long, default_value = "0")]
+    replay_speed: u32,
 }
 
 #[cfg(unix)]
@@ -92,6 +105,22 @@ fn main() -> Result<()> {
         println!("{}", VERSION_INFO);
         return Ok(());
     }
+    if let Some(pcap_file) = opts.replay {
+        let _logger = Logger::try_with_str("info")?
+            .format(colored_opt_format)
+            .start()?;
+        replay::replay(&replay::ReplayOptions {
+            pcap_file: pcap_file.into(),
+            output_dir: opts.replay_output.into(),
+            speed: opts.replay_speed,
+            runtime_config: if opts.standalone {
+                Some(opts.config_file.into())
+            } else {
+                None
+            },
+        })?;
+        return Ok(());
+    }
     let mut t = trident::Trident::start(
         &Path::new(&opts.config_file),
         VERSION_INFO,
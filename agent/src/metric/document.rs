@@ -17,8 +17,9 @@
 use std::net::{IpAddr, Ipv4Addr};
 
 use bitflags::bitflags;
+use log::warn;
 use prost::Message;
-use serde::Serialize;
+use serde::{Serialize, Serializer};
 
 use super::meter::Meter;
 
@@ -27,14 +28,16 @@ use crate::common::{
     flow::L7Protocol,
     tap_port::TapPort,
 };
+use crate::flow_generator::protocol_logs::to_string_format;
 use crate::proto::metric;
 use public::utils::net::MacAddr;
 
-#[derive(Debug)]
+#[derive(Serialize, Debug)]
 pub struct Document {
     pub timestamp: u32,
     pub tagger: Tagger,
     pub meter: Meter,
+    #[serde(serialize_with = "flags_to_bits")]
     pub flags: DocumentFlag,
 }
 
@@ -60,6 +63,24 @@ impl Document {
         let pb_doc: metric::Document = self.into();
         pb_doc.encode(buf).map(|_| pb_doc.encoded_len())
     }
+
+    pub fn to_kv_string(&self, dst: &mut String) {
+        match serde_json::to_string(&self) {
+            Ok(json) => {
+                dst.push_str(&json);
+                dst.push('\n');
+            }
+            Err(e) => warn!("serialize document failed: {}", e),
+        }
+    }
+}
+
+fn flags_to_bits<S: Serializer>(flags: &DocumentFlag, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u32(flags.bits())
+}
+
+fn code_to_bits<S: Serializer>(code: &Code, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(code.bits())
 }
 
 impl From<Document> for metric::Document {
@@ -125,7 +146,7 @@ impl Default for Code {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
 pub enum Direction {
     None,
@@ -221,7 +242,7 @@ impl From<Direction> for TapSide {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Serialize, Debug, Clone, Copy)]
 #[repr(u8)]
 pub enum TagType {
     TunnelIpId = 4,
@@ -233,8 +254,9 @@ impl Default for TagType {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Debug, Clone)]
 pub struct Tagger {
+    #[serde(serialize_with = "code_to_bits")]
     pub code: Code,
 
     pub ip: IpAddr,
@@ -247,7 +269,9 @@ pub struct Tagger {
     pub is_ipv6: bool,
     pub l3_epc_id: i16,
     pub l3_epc_id1: i16,
+    #[serde(serialize_with = "to_string_format")]
     pub mac: MacAddr,
+    #[serde(serialize_with = "to_string_format")]
     pub mac1: MacAddr,
 
     pub direction: Direction,
@@ -256,6 +280,7 @@ pub struct Tagger {
     pub acl_gid: u16,
     pub server_port: u16,
     pub vtap_id: u16,
+    #[serde(serialize_with = "to_string_format")]
     pub tap_port: TapPort,
     pub tap_type: TapType,
     pub l7_protocol: L7Protocol,
@@ -14,24 +14,28 @@
  * limitations under the License.
  */
 
+use std::fmt;
 use std::mem::swap;
 
+use serde::{ser::SerializeSeq, Serialize, Serializer};
+
 use crate::proto::metric;
 
 const FLOW_ID: u32 = 1;
 const USAGE_ID: u32 = 4;
 const APP_ID: u32 = 5;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
 pub enum Meter {
-    Flow(FlowMeter),
+    Flow(Box<FlowMeter>),
     App(AppMeter),
     Usage(UsageMeter),
 }
 
 impl Meter {
     pub fn new_flow() -> Self {
-        Meter::Flow(FlowMeter::default())
+        Meter::Flow(Box::new(FlowMeter::default()))
     }
     pub fn new_app() -> Self {
         Meter::App(AppMeter::default())
@@ -62,7 +66,7 @@ impl From<Meter> for metric::Meter {
         match m {
             Meter::Flow(f) => metric::Meter {
                 meter_id: FLOW_ID,
-                flow: Some(f.into()),
+                flow: Some((*f).into()),
                 app: None,
                 usage: None,
             },
@@ -82,7 +86,7 @@ impl From<Meter> for metric::Meter {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
 pub struct FlowMeter {
     pub traffic: Traffic,
     pub latency: Latency,
@@ -125,7 +129,7 @@ impl From<FlowMeter> for metric::FlowMeter {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
 pub struct Traffic {
     pub packet_tx: u64,
     pub packet_rx: u64,
@@ -192,7 +196,7 @@ impl From<Traffic> for metric::Traffic {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
 pub struct Latency {
     pub rtt_max: u32,
     pub rtt_client_max: u32,
@@ -217,6 +221,12 @@ pub struct Latency {
     pub art_count: u32,
     pub rrt_count: u32,
     pub cit_count: u32,
+
+    pub rtt_histogram: LatencyHistogram,
+    pub srt_histogram: LatencyHistogram,
+    pub art_histogram: LatencyHistogram,
+    pub rrt_histogram: LatencyHistogram,
+    pub cit_histogram: LatencyHistogram,
 }
 
 impl Latency {
@@ -258,6 +268,12 @@ impl Latency {
         self.art_count += other.art_count;
         self.rrt_count += other.rrt_count;
         self.cit_count += other.cit_count;
+
+        self.rtt_histogram.merge(&other.rtt_histogram);
+        self.srt_histogram.merge(&other.srt_histogram);
+        self.art_histogram.merge(&other.art_histogram);
+        self.rrt_histogram.merge(&other.rrt_histogram);
+        self.cit_histogram.merge(&other.cit_histogram);
     }
 }
 
@@ -287,11 +303,169 @@ impl From<Latency> for metric::Latency {
             art_count: m.art_count,
             rrt_count: m.rrt_count,
             cit_count: m.cit_count,
+
+            rtt_histogram: m.rtt_histogram.into(),
+            srt_histogram: m.srt_histogram.into(),
+            art_histogram: m.art_histogram.into(),
+            rrt_histogram: m.rrt_histogram.into(),
+            cit_histogram: m.cit_histogram.into(),
         }
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+// 时延直方图采用对数线性分桶：小于4us的值各占一个桶，之后每个2的幂区间等分为4个桶，
+// 相对误差不超过12.5%，大于等于2^26us(约67s)的值全部落入最后一个桶
+// Latency histogram with log-linear buckets: values below 4us get one bucket each, then
+// every power-of-two range is split into 4 buckets, which bounds the relative error to
+// 12.5%. Values of 2^26us (about 67s) or more all fall into the last bucket.
+const LATENCY_HISTOGRAM_SUB_BUCKET_BITS: u32 = 2;
+const LATENCY_HISTOGRAM_SUB_BUCKETS: usize = 1 << LATENCY_HISTOGRAM_SUB_BUCKET_BITS;
+const LATENCY_HISTOGRAM_MAX_EXPONENT: u32 = 26;
+pub const LATENCY_HISTOGRAM_BUCKETS: usize = LATENCY_HISTOGRAM_SUB_BUCKETS
+    + (LATENCY_HISTOGRAM_MAX_EXPONENT - LATENCY_HISTOGRAM_SUB_BUCKET_BITS) as usize
+        * LATENCY_HISTOGRAM_SUB_BUCKETS;
+
+// 桶的数量固定，相同位置的桶可直接相加，因此可以在任意聚合层级合并
+// The bucket layout is fixed, so histograms merge by adding counts bucket by bucket
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    // 只保存到最后一个非空桶，未记录时不分配内存
+    // Only holds buckets up to the last non-empty one, nothing is allocated until a value is recorded
+    buckets: Vec<u32>,
+}
+
+impl LatencyHistogram {
+    pub fn bucket_index(value: u32) -> usize {
+        if value < LATENCY_HISTOGRAM_SUB_BUCKETS as u32 {
+            return value as usize;
+        }
+        let exponent = u32::BITS - 1 - value.leading_zeros();
+        if exponent >= LATENCY_HISTOGRAM_MAX_EXPONENT {
+            return LATENCY_HISTOGRAM_BUCKETS - 1;
+        }
+        let shift = exponent - LATENCY_HISTOGRAM_SUB_BUCKET_BITS;
+        let sub_bucket = (value >> shift) as usize & (LATENCY_HISTOGRAM_SUB_BUCKETS - 1);
+        LATENCY_HISTOGRAM_SUB_BUCKETS + shift as usize * LATENCY_HISTOGRAM_SUB_BUCKETS + sub_bucket
+    }
+
+    // 返回桶的下界（包含）
+    // Returns the inclusive lower bound of the bucket
+    pub fn bucket_lower_bound(index: usize) -> u32 {
+        if index < LATENCY_HISTOGRAM_SUB_BUCKETS {
+            return index as u32;
+        }
+        let shift = (index - LATENCY_HISTOGRAM_SUB_BUCKETS) / LATENCY_HISTOGRAM_SUB_BUCKETS;
+        let sub_bucket = index % LATENCY_HISTOGRAM_SUB_BUCKETS;
+        ((LATENCY_HISTOGRAM_SUB_BUCKETS + sub_bucket) as u32) << shift
+    }
+
+    pub fn record(&mut self, value: u32) {
+        self.record_n(value, 1);
+    }
+
+    pub fn record_n(&mut self, value: u32, count: u32) {
+        if count == 0 {
+            return;
+        }
+        let index = Self::bucket_index(value);
+        if self.buckets.len() <= index {
+            self.buckets.resize(index + 1, 0);
+        }
+        self.buckets[index] = self.buckets[index].saturating_add(count);
+    }
+
+    // 按(桶内任意值, 样本数)构造，用于测试的期望值
+    // Builds a histogram from (any value in the bucket, sample count) pairs for expected values in tests
+    #[cfg(test)]
+    pub fn from_counts(counts: &[(u32, u32)]) -> Self {
+        let mut histogram = Self::default();
+        for (value, count) in counts {
+            histogram.record_n(*value, *count);
+        }
+        histogram
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        if self.buckets.len() < other.buckets.len() {
+            self.buckets.resize(other.buckets.len(), 0);
+        }
+        for (bucket, count) in self.buckets.iter_mut().zip(other.buckets.iter()) {
+            *bucket = bucket.saturating_add(*count);
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().map(|c| *c as u64).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buckets.is_empty()
+    }
+
+    // 估算分位数(0.0~1.0)，返回所在桶的中点，最后一个桶返回其下界
+    // Estimates a quantile (0.0 to 1.0) as the midpoint of the bucket it falls into,
+    // or the lower bound of the last bucket
+    pub fn quantile(&self, q: f64) -> Option<u32> {
+        let total = self.count();
+        if total == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, count) in self.buckets.iter().enumerate() {
+            seen += *count as u64;
+            if seen < rank {
+                continue;
+            }
+            let lower = Self::bucket_lower_bound(i);
+            if i + 1 == LATENCY_HISTOGRAM_BUCKETS {
+                return Some(lower);
+            }
+            let upper = Self::bucket_lower_bound(i + 1);
+            return Some(lower + (upper - lower - 1) / 2);
+        }
+        None
+    }
+
+    // 末尾为0的桶不保存，编码时也随之省略
+    // Trailing empty buckets are never stored, so they are omitted when encoding as well
+    pub fn as_slice(&self) -> &[u32] {
+        &self.buckets
+    }
+}
+
+impl From<LatencyHistogram> for Vec<u32> {
+    fn from(h: LatencyHistogram) -> Self {
+        h.buckets
+    }
+}
+
+impl fmt::Debug for LatencyHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.buckets
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| **c != 0)
+                    .map(|(i, c)| (Self::bucket_lower_bound(i), c)),
+            )
+            .finish()
+    }
+}
+
+impl Serialize for LatencyHistogram {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let buckets = self.as_slice();
+        let mut seq = serializer.serialize_seq(Some(buckets.len()))?;
+        for count in buckets {
+            seq.serialize_element(count)?;
+        }
+        seq.end()
+    }
+}
+
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
 pub struct Performance {
     pub retrans_tx: u64,
     pub retrans_rx: u64,
@@ -325,7 +499,7 @@ impl From<Performance> for metric::Performance {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
 pub struct Anomaly {
     pub client_rst_flow: u64,
     pub server_rst_flow: u64,
@@ -392,7 +566,7 @@ impl From<Anomaly> for metric::Anomaly {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy, PartialEq)]
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
 pub struct FlowLoad {
     pub load: u64,
     pub flow_count: u64,
@@ -415,7 +589,7 @@ impl From<FlowLoad> for metric::FlowLoad {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Serialize, Debug, Default, Clone)]
 pub struct AppMeter {
     pub traffic: AppTraffic,
     pub latency: AppLatency,
@@ -443,7 +617,7 @@ impl From<AppMeter> for metric::AppMeter {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Serialize, Debug, Default, Clone, Copy)]
 pub struct AppTraffic {
     pub request: u32,
     pub response: u32,
@@ -468,11 +642,12 @@ impl From<AppTraffic> for metric::AppTraffic {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Serialize, Debug, Default, Clone)]
 pub struct AppLatency {
     pub rrt_max: u32,
     pub rrt_sum: u64,
     pub rrt_count: u32,
+    pub rrt_histogram: LatencyHistogram,
 }
 
 impl AppLatency {
@@ -482,6 +657,7 @@ impl AppLatency {
         }
         self.rrt_sum += other.rrt_sum;
         self.rrt_count += other.rrt_count;
+        self.rrt_histogram.merge(&other.rrt_histogram);
     }
 }
 
@@ -491,11 +667,12 @@ impl From<AppLatency> for metric::AppLatency {
             rrt_max: m.rrt_max,
             rrt_sum: m.rrt_sum,
             rrt_count: m.rrt_count,
+            rrt_histogram: m.rrt_histogram.into(),
         }
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Serialize, Debug, Default, Clone, Copy)]
 pub struct AppAnomaly {
     pub client_error: u32,
     pub server_error: u32,
@@ -520,7 +697,7 @@ impl From<AppAnomaly> for metric::AppAnomaly {
     }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Serialize, Debug, Default, Clone, Copy)]
 pub struct UsageMeter {
     pub packet_tx: u64,
     pub packet_rx: u64,
@@ -564,3 +741,79 @@ impl From<UsageMeter> for metric::UsageMeter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_histogram_buckets() {
+        for v in 0..4 {
+            assert_eq!(LatencyHistogram::bucket_index(v), v as usize);
+        }
+        assert_eq!(LatencyHistogram::bucket_index(4), 4);
+        assert_eq!(LatencyHistogram::bucket_index(7), 7);
+        assert_eq!(LatencyHistogram::bucket_index(8), 8);
+        assert_eq!(LatencyHistogram::bucket_index(9), 8);
+        assert_eq!(LatencyHistogram::bucket_index(10), 9);
+        assert_eq!(
+            LatencyHistogram::bucket_index((1 << 26) - 1),
+            LATENCY_HISTOGRAM_BUCKETS - 1
+        );
+        assert_eq!(
+            LatencyHistogram::bucket_index(u32::MAX),
+            LATENCY_HISTOGRAM_BUCKETS - 1
+        );
+
+        for i in 0..LATENCY_HISTOGRAM_BUCKETS {
+            let lower = LatencyHistogram::bucket_lower_bound(i);
+            assert_eq!(LatencyHistogram::bucket_index(lower), i);
+            if i > 0 {
+                assert_eq!(LatencyHistogram::bucket_index(lower - 1), i - 1);
+            }
+        }
+    }
+
+    #[test]
+    fn latency_histogram_quantile() {
+        let mut h = LatencyHistogram::default();
+        assert_eq!(h.quantile(0.99), None);
+        for v in 1..=1000 {
+            h.record(v * 100);
+        }
+        assert_eq!(h.count(), 1000);
+        for (q, expected) in [(0.5, 50000.0), (0.95, 95000.0), (0.99, 99000.0)] {
+            let estimated = h.quantile(q).unwrap() as f64;
+            assert!(
+                (estimated - expected).abs() / expected <= 0.125,
+                "q={} estimated={} expected={}",
+                q,
+                estimated,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn latency_histogram_merge() {
+        let mut a = LatencyHistogram::default();
+        let mut b = LatencyHistogram::default();
+        a.record(10);
+        b.record_n(100, 3);
+        b.record_n(1000, 0);
+        assert_eq!(b.as_slice().len(), LatencyHistogram::bucket_index(100) + 1);
+        a.merge(&b);
+        assert_eq!(a.count(), 4);
+        assert_eq!(a.quantile(0.25), Some(10));
+        assert_eq!(a.quantile(1.0), Some(103));
+        assert_eq!(a.as_slice().len(), LatencyHistogram::bucket_index(100) + 1);
+
+        let mut latency = Latency::default();
+        latency.srt_histogram.record(10);
+        let mut other = Latency::default();
+        other.srt_histogram.record(20);
+        latency.sequential_merge(&other);
+        assert_eq!(latency.srt_histogram.count(), 2);
+        assert!(latency.rtt_histogram.is_empty());
+    }
+}
@@ -29,10 +29,10 @@ use arc_swap::access::Access;
 use log::{debug, error, info, warn};
 
 use super::{
-    format_time, get_temp_filename, worker::Worker, PcapPacket, TapType, GLOBAL_HEADER_LEN,
-    INCL_LEN_OFFSET, RECORD_HEADER_LEN, TS_SEC_OFFSET,
+    format_time, get_temp_filename, reader::Reader, worker::Worker, PcapPacket, TapType,
+    GLOBAL_HEADER_LEN, INCL_LEN_OFFSET, RECORD_HEADER_LEN, TS_SEC_OFFSET,
 };
-use crate::config::handler::PcapAccess;
+use crate::config::{handler::PcapAccess, PcapFileFormat};
 use crate::utils::stats::{Collector, Countable, RefCountable, StatsOption};
 use public::queue;
 
@@ -65,6 +65,7 @@ impl WorkerManager {
                     config_guard.max_file_period,
                     config_guard.file_directory.clone(),
                     config_guard.block_size_kb << 10,
+                    config_guard.file_format,
                     receiver,
                     config_guard.max_file_period,
                     ntp_diff.clone(),
@@ -79,6 +80,7 @@ impl WorkerManager {
             1233,
             Duration::from_secs(10),
             0,
+            PcapFileFormat::Pcap,
         );
 
         Self {
@@ -152,7 +154,7 @@ impl WorkerManager {
             }
             let filename = filename.unwrap();
 
-            filename.ends_with(".pcap.temp")
+            (filename.ends_with(".pcap.temp") || filename.ends_with(".pcapng.temp"))
                 && example_filename.chars().filter(|&c| c == '_').count()
                     == filename.chars().filter(|&c| c == '_').count()
         }
@@ -240,6 +242,10 @@ impl WorkerManager {
     }
 
     fn find_last_record_time(path: &Path) -> Result<Duration> {
+        if path.to_string_lossy().ends_with(".pcapng.temp") {
+            return Self::find_last_pcapng_record_time(path);
+        }
+
         let mut file = File::open(path)?;
 
         if file.metadata()?.len() <= (GLOBAL_HEADER_LEN + RECORD_HEADER_LEN) as u64 {
@@ -292,4 +298,24 @@ impl WorkerManager {
 
         Ok(Duration::from_secs(last_record_time as u64))
     }
+
+    // 未正常关闭的文件末尾可能有不完整的 block，取之前最后一个完整记录的时间
+    // the file may end with an incomplete block if not closed gracefully, the last complete record is used
+    fn find_last_pcapng_record_time(path: &Path) -> Result<Duration> {
+        let mut last_record_time = None;
+        for record in Reader::open(path)? {
+            match record {
+                Ok(r) => {
+                    last_record_time = last_record_time.max(Some(r.timestamp));
+                }
+                Err(_) => break,
+            }
+        }
+        last_record_time.ok_or_else(|| {
+            Error::new(
+                ErrorKind::Other,
+                format!("invalid content in file {}", path.display()),
+            )
+        })
+    }
 }
@@ -21,13 +21,16 @@ use std::{
 
 use chrono::{DateTime, Utc};
 
-use crate::common::enums::TapType;
+use crate::common::{enums::TapType, tap_port::TapPort};
+use crate::config::PcapFileFormat;
 
 mod manager;
+mod reader;
 mod worker;
 mod writer;
 
 pub use manager::WorkerManager;
+pub use reader::{Reader, Record};
 
 const GLOBAL_HEADER_LEN: usize = 24;
 const RECORD_HEADER_LEN: usize = 16;
@@ -49,6 +52,8 @@ pub struct Packet {
     pkt_len: u16,
     vtap_id: u16,
     dispatcher_id: u32,
+    tap_port: TapPort,
+    flow_id: u64,
     raw_pkt: Vec<u8>,
 }
 
@@ -81,6 +86,13 @@ fn format_time(dur: Duration) -> String {
         .to_string()
 }
 
+fn file_extension(format: PcapFileFormat) -> &'static str {
+    match format {
+        PcapFileFormat::Pcap => "pcap",
+        PcapFileFormat::Pcapng => "pcapng",
+    }
+}
+
 fn get_temp_filename(
     base: &Path,
     acl_gid: u16,
@@ -88,13 +100,18 @@ fn get_temp_filename(
     dispatcher_id: u32,
     timestamp: Duration,
     vtap_id: u16,
+    format: PcapFileFormat,
 ) -> PathBuf {
     let formatted_time = format_time(timestamp);
     let mut filename = base.to_path_buf();
     filename.push(format!("{}", acl_gid));
     filename.push(format!(
-        "{}_{:012x}_0_{}_.{}.pcap.temp",
-        tap_type, dispatcher_id, formatted_time, vtap_id
+        "{}_{:012x}_0_{}_.{}.{}.temp",
+        tap_type,
+        dispatcher_id,
+        formatted_time,
+        vtap_id,
+        file_extension(format)
     ));
 
     filename
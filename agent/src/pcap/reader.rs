@@ -0,0 +1,563 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    fs::File,
+    io::{BufReader, Error, ErrorKind, Read, Result},
+    path::Path,
+    time::Duration,
+};
+
+use log::debug;
+
+use crate::common::enums::LinkType;
+
+use super::{GLOBAL_HEADER_LEN, PCAP_MAGIC, RECORD_HEADER_LEN};
+
+const PCAP_NANO_MAGIC: u32 = 0xa1b23c4d;
+
+pub(super) const PCAPNG_SHB_TYPE: u32 = 0x0a0d0d0a;
+pub(super) const PCAPNG_IDB_TYPE: u32 = 0x00000001;
+pub(super) const PCAPNG_SPB_TYPE: u32 = 0x00000003;
+pub(super) const PCAPNG_EPB_TYPE: u32 = 0x00000006;
+pub(super) const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+
+pub(super) const PCAPNG_OPT_ENDOFOPT: u16 = 0;
+pub(super) const PCAPNG_OPT_IF_TSRESOL: u16 = 9;
+
+// block type + block total length + trailing block total length
+const PCAPNG_BLOCK_OVERHEAD: usize = 12;
+// 防止损坏的文件导致分配过大内存
+// refuse absurd block lengths from corrupted files
+const MAX_BLOCK_LEN: usize = 16 << 20;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    pub timestamp: Duration,
+    pub original_length: usize,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    link_type: u16,
+    snap_len: u32,
+    // 时间戳单位, 每秒的单位数
+    // timestamp resolution in units per second
+    units_per_sec: u64,
+}
+
+#[derive(Debug)]
+enum Format {
+    Pcap {
+        big_endian: bool,
+        nano: bool,
+    },
+    PcapNg {
+        big_endian: bool,
+        interfaces: Vec<Interface>,
+    },
+}
+
+// 读取 pcap 和 pcapng 文件，仅输出以太网链路类型的包，其它链路类型的包将被跳过
+// reads packets from pcap and pcapng files, packets of non-ethernet link types are skipped
+#[derive(Debug)]
+pub struct Reader<R> {
+    inner: R,
+    format: Format,
+    skipped: u64,
+}
+
+impl Reader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(mut inner: R) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        inner.read_exact(&mut magic)?;
+
+        if u32::from_le_bytes(magic) == PCAPNG_SHB_TYPE {
+            let mut reader = Self {
+                inner,
+                format: Format::PcapNg {
+                    big_endian: false,
+                    interfaces: vec![],
+                },
+                skipped: 0,
+            };
+            let mut header = [0u8; 8];
+            reader.inner.read_exact(&mut header)?;
+            reader.read_section_body(header)?;
+            return Ok(reader);
+        }
+
+        let (big_endian, nano) = match (u32::from_le_bytes(magic), u32::from_be_bytes(magic)) {
+            (PCAP_MAGIC, _) => (false, false),
+            (PCAP_NANO_MAGIC, _) => (false, true),
+            (_, PCAP_MAGIC) => (true, false),
+            (_, PCAP_NANO_MAGIC) => (true, true),
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("unknown pcap magic {:02x?}", magic),
+                ))
+            }
+        };
+        let mut header = [0u8; GLOBAL_HEADER_LEN - 4];
+        inner.read_exact(&mut header)?;
+        let link_type = read_u32(&header[16..], big_endian);
+        if link_type != u8::from(LinkType::Ethernet) as u32 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("unsupported pcap link type {}", link_type),
+            ));
+        }
+
+        Ok(Self {
+            inner,
+            format: Format::Pcap { big_endian, nano },
+            skipped: 0,
+        })
+    }
+
+    // 非以太网链路类型或无法识别接口而跳过的包数
+    // number of packets skipped for non-ethernet link types or unknown interfaces
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    pub fn next_packet(&mut self) -> Result<Option<Record>> {
+        match self.format {
+            Format::Pcap { big_endian, nano } => self.next_pcap_packet(big_endian, nano),
+            Format::PcapNg { .. } => self.next_pcapng_packet(),
+        }
+    }
+
+    fn next_pcap_packet(&mut self, big_endian: bool, nano: bool) -> Result<Option<Record>> {
+        let mut header = [0u8; RECORD_HEADER_LEN];
+        if !read_exact_or_eof(&mut self.inner, &mut header)? {
+            return Ok(None);
+        }
+        let ts_sec = read_u32(&header[0..], big_endian) as u64;
+        let ts_frac = read_u32(&header[4..], big_endian);
+        let incl_len = read_u32(&header[8..], big_endian) as usize;
+        let orig_len = read_u32(&header[12..], big_endian) as usize;
+        if incl_len > MAX_BLOCK_LEN {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("pcap record length {} too large", incl_len),
+            ));
+        }
+        let mut data = vec![0u8; incl_len];
+        self.inner.read_exact(&mut data)?;
+        let nanos = if nano {
+            ts_frac as u64
+        } else {
+            ts_frac as u64 * 1000
+        };
+        Ok(Some(Record {
+            timestamp: Duration::from_secs(ts_sec) + Duration::from_nanos(nanos),
+            original_length: orig_len.max(incl_len),
+            data,
+        }))
+    }
+
+    fn next_pcapng_packet(&mut self) -> Result<Option<Record>> {
+        loop {
+            let mut header = [0u8; 8];
+            if !read_exact_or_eof(&mut self.inner, &mut header)? {
+                return Ok(None);
+            }
+            let big_endian = match &self.format {
+                Format::PcapNg { big_endian, .. } => *big_endian,
+                _ => unreachable!(),
+            };
+            let block_type = read_u32(&header[0..], big_endian);
+            if block_type == PCAPNG_SHB_TYPE {
+                // 新的 section，字节序和接口列表都需要重置
+                // a new section resets byte order and interfaces
+                let mut shb = [0u8; 8];
+                shb[..4].copy_from_slice(&header[4..]);
+                self.inner.read_exact(&mut shb[4..])?;
+                self.read_section_body(shb)?;
+                continue;
+            }
+
+            let total_len = read_u32(&header[4..], big_endian) as usize;
+            if total_len < PCAPNG_BLOCK_OVERHEAD || total_len % 4 != 0 || total_len > MAX_BLOCK_LEN
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid pcapng block length {}", total_len),
+                ));
+            }
+            let mut body = vec![0u8; total_len - 8];
+            self.inner.read_exact(&mut body)?;
+            body.truncate(body.len() - 4);
+
+            match block_type {
+                PCAPNG_IDB_TYPE => self.parse_interface(&body, big_endian)?,
+                PCAPNG_EPB_TYPE => {
+                    if let Some(record) = self.parse_enhanced_packet(&body, big_endian)? {
+                        return Ok(Some(record));
+                    }
+                }
+                PCAPNG_SPB_TYPE => {
+                    if let Some(record) = self.parse_simple_packet(&body, big_endian)? {
+                        return Ok(Some(record));
+                    }
+                }
+                _ => debug!("skip pcapng block type {:#x}", block_type),
+            }
+        }
+    }
+
+    // header 为 block total length 和 byte-order magic
+    // header contains block total length and byte-order magic
+    fn read_section_body(&mut self, header: [u8; 8]) -> Result<()> {
+        let big_endian = match (
+            u32::from_le_bytes(header[4..8].try_into().unwrap()),
+            u32::from_be_bytes(header[4..8].try_into().unwrap()),
+        ) {
+            (PCAPNG_BYTE_ORDER_MAGIC, _) => false,
+            (_, PCAPNG_BYTE_ORDER_MAGIC) => true,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "invalid pcapng byte-order magic",
+                ))
+            }
+        };
+        let total_len = read_u32(&header[0..], big_endian) as usize;
+        if total_len < PCAPNG_BLOCK_OVERHEAD + 16 || total_len % 4 != 0 || total_len > MAX_BLOCK_LEN
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid pcapng section header length {}", total_len),
+            ));
+        }
+        // 跳过 version、section length、options 和结尾的 block total length
+        // skip version, section length, options and trailing block total length
+        let mut rest = vec![0u8; total_len - 12];
+        self.inner.read_exact(&mut rest)?;
+
+        self.format = Format::PcapNg {
+            big_endian,
+            interfaces: vec![],
+        };
+        Ok(())
+    }
+
+    fn parse_interface(&mut self, body: &[u8], big_endian: bool) -> Result<()> {
+        if body.len() < 8 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "pcapng interface description block too short",
+            ));
+        }
+        let mut interface = Interface {
+            link_type: read_u16(&body[0..], big_endian),
+            snap_len: read_u32(&body[4..], big_endian),
+            units_per_sec: 1_000_000,
+        };
+        let mut options = &body[8..];
+        while options.len() >= 4 {
+            let code = read_u16(&options[0..], big_endian);
+            let len = read_u16(&options[2..], big_endian) as usize;
+            if code == PCAPNG_OPT_ENDOFOPT || options.len() < 4 + len {
+                break;
+            }
+            if code == PCAPNG_OPT_IF_TSRESOL && len >= 1 {
+                let resol = options[4];
+                // 最高位为 0 表示 10 的负幂，为 1 表示 2 的负幂
+                // MSB clear means a negative power of 10, set means a negative power of 2
+                interface.units_per_sec = if resol & 0x80 == 0 {
+                    10u64.checked_pow(resol as u32)
+                } else {
+                    1u64.checked_shl((resol & 0x7f) as u32)
+                }
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidData,
+                        format!("unsupported pcapng if_tsresol {:#x}", resol),
+                    )
+                })?;
+            }
+            options = &options[(4 + len + 3) / 4 * 4..];
+        }
+        if let Format::PcapNg { interfaces, .. } = &mut self.format {
+            interfaces.push(interface);
+        }
+        Ok(())
+    }
+
+    fn interface(&self, id: usize) -> Option<Interface> {
+        match &self.format {
+            Format::PcapNg { interfaces, .. } => interfaces.get(id).copied(),
+            _ => None,
+        }
+    }
+
+    fn parse_enhanced_packet(&mut self, body: &[u8], big_endian: bool) -> Result<Option<Record>> {
+        if body.len() < 20 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "pcapng enhanced packet block too short",
+            ));
+        }
+        let if_id = read_u32(&body[0..], big_endian) as usize;
+        let ts = (read_u32(&body[4..], big_endian) as u64) << 32
+            | read_u32(&body[8..], big_endian) as u64;
+        let cap_len = read_u32(&body[12..], big_endian) as usize;
+        let orig_len = read_u32(&body[16..], big_endian) as usize;
+        if body.len() < 20 + cap_len {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "pcapng enhanced packet data truncated",
+            ));
+        }
+        let interface = match self.interface(if_id) {
+            Some(i) if i.link_type == u8::from(LinkType::Ethernet) as u16 => i,
+            _ => {
+                self.skipped += 1;
+                return Ok(None);
+            }
+        };
+        let timestamp = Duration::new(
+            ts / interface.units_per_sec,
+            ((ts % interface.units_per_sec) as u128 * 1_000_000_000
+                / interface.units_per_sec as u128) as u32,
+        );
+        Ok(Some(Record {
+            timestamp,
+            original_length: orig_len.max(cap_len),
+            data: body[20..20 + cap_len].to_vec(),
+        }))
+    }
+
+    // simple packet block 没有时间戳，使用零值
+    // simple packet blocks carry no timestamp, zero is used
+    fn parse_simple_packet(&mut self, body: &[u8], big_endian: bool) -> Result<Option<Record>> {
+        if body.len() < 4 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "pcapng simple packet block too short",
+            ));
+        }
+        let interface = match self.interface(0) {
+            Some(i) if i.link_type == u8::from(LinkType::Ethernet) as u16 => i,
+            _ => {
+                self.skipped += 1;
+                return Ok(None);
+            }
+        };
+        let orig_len = read_u32(&body[0..], big_endian) as usize;
+        let mut cap_len = orig_len.min(body.len() - 4);
+        if interface.snap_len > 0 {
+            cap_len = cap_len.min(interface.snap_len as usize);
+        }
+        Ok(Some(Record {
+            timestamp: Duration::ZERO,
+            original_length: orig_len,
+            data: body[4..4 + cap_len].to_vec(),
+        }))
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_packet().transpose()
+    }
+}
+
+fn read_u16(bs: &[u8], big_endian: bool) -> u16 {
+    let bs = bs[..2].try_into().unwrap();
+    if big_endian {
+        u16::from_be_bytes(bs)
+    } else {
+        u16::from_le_bytes(bs)
+    }
+}
+
+fn read_u32(bs: &[u8], big_endian: bool) -> u32 {
+    let bs = bs[..4].try_into().unwrap();
+    if big_endian {
+        u32::from_be_bytes(bs)
+    } else {
+        u32::from_le_bytes(bs)
+    }
+}
+
+// 文件在记录边界处结束时返回 false，在记录中间结束时报错
+// returns false if the file ends on a record boundary, errors on a truncated record
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(Error::from(ErrorKind::UnexpectedEof)),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn pcapng_block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let padded = (body.len() + 3) / 4 * 4;
+        let total_len = (padded + PCAPNG_BLOCK_OVERHEAD) as u32;
+        let mut block = vec![];
+        block.extend_from_slice(&block_type.to_le_bytes());
+        block.extend_from_slice(&total_len.to_le_bytes());
+        block.extend_from_slice(body);
+        block.resize(8 + padded, 0);
+        block.extend_from_slice(&total_len.to_le_bytes());
+        block
+    }
+
+    fn section_header() -> Vec<u8> {
+        let mut body = vec![];
+        body.extend_from_slice(&PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes());
+        body.extend_from_slice(&1u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&(-1i64).to_le_bytes());
+        pcapng_block(PCAPNG_SHB_TYPE, &body)
+    }
+
+    fn interface(link_type: u16, tsresol: Option<u8>) -> Vec<u8> {
+        let mut body = vec![];
+        body.extend_from_slice(&link_type.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&65535u32.to_le_bytes());
+        if let Some(r) = tsresol {
+            body.extend_from_slice(&PCAPNG_OPT_IF_TSRESOL.to_le_bytes());
+            body.extend_from_slice(&1u16.to_le_bytes());
+            body.extend_from_slice(&[r, 0, 0, 0]);
+            body.extend_from_slice(&[0u8; 4]);
+        }
+        pcapng_block(PCAPNG_IDB_TYPE, &body)
+    }
+
+    fn enhanced_packet(if_id: u32, ts: u64, data: &[u8]) -> Vec<u8> {
+        let mut body = vec![];
+        body.extend_from_slice(&if_id.to_le_bytes());
+        body.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(ts as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32 + 10).to_le_bytes());
+        body.extend_from_slice(data);
+        pcapng_block(PCAPNG_EPB_TYPE, &body)
+    }
+
+    #[test]
+    fn read_pcap_both_endians() {
+        for big_endian in [false, true] {
+            let mut file = vec![];
+            let put_u32 = |v: u32, f: &mut Vec<u8>| {
+                if big_endian {
+                    f.extend_from_slice(&v.to_be_bytes())
+                } else {
+                    f.extend_from_slice(&v.to_le_bytes())
+                }
+            };
+            put_u32(PCAP_NANO_MAGIC, &mut file);
+            put_u32(0x00040002, &mut file);
+            put_u32(0, &mut file);
+            put_u32(0, &mut file);
+            put_u32(65535, &mut file);
+            put_u32(1, &mut file);
+            put_u32(1600000000, &mut file);
+            put_u32(123456789, &mut file);
+            put_u32(3, &mut file);
+            put_u32(60, &mut file);
+            file.extend_from_slice(&[1, 2, 3]);
+
+            let mut reader = Reader::new(Cursor::new(file)).unwrap();
+            assert_eq!(
+                reader.next_packet().unwrap(),
+                Some(Record {
+                    timestamp: Duration::new(1600000000, 123456789),
+                    original_length: 60,
+                    data: vec![1, 2, 3],
+                })
+            );
+            assert_eq!(reader.next_packet().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn read_pcapng() {
+        let mut file = section_header();
+        file.extend(interface(LinkType::Ethernet as u16, None));
+        file.extend(interface(LinkType::Null as u16, None));
+        file.extend(interface(LinkType::Ethernet as u16, Some(9)));
+        file.extend(enhanced_packet(0, 1_600_000_000_000_001, &[1, 2, 3, 4, 5]));
+        file.extend(enhanced_packet(1, 1, &[6]));
+        file.extend(enhanced_packet(2, 1_600_000_000_000_000_002, &[7, 8]));
+
+        let mut reader = Reader::new(Cursor::new(file)).unwrap();
+        assert_eq!(
+            reader.next_packet().unwrap(),
+            Some(Record {
+                timestamp: Duration::new(1600000000, 1000),
+                original_length: 15,
+                data: vec![1, 2, 3, 4, 5],
+            })
+        );
+        assert_eq!(
+            reader.next_packet().unwrap(),
+            Some(Record {
+                timestamp: Duration::new(1600000000, 2),
+                original_length: 12,
+                data: vec![7, 8],
+            })
+        );
+        assert_eq!(reader.next_packet().unwrap(), None);
+        assert_eq!(reader.skipped(), 1);
+    }
+
+    #[test]
+    fn read_truncated() {
+        let mut file = section_header();
+        file.extend(interface(LinkType::Ethernet as u16, None));
+        let mut packet = enhanced_packet(0, 1, &[1, 2, 3, 4]);
+        packet.truncate(packet.len() - 6);
+        file.extend(packet);
+
+        let mut reader = Reader::new(Cursor::new(file)).unwrap();
+        assert!(reader.next_packet().is_err());
+    }
+
+    #[test]
+    fn read_existing_pcap() {
+        let reader = Reader::open("resources/test/flow_generator/http.pcap").unwrap();
+        let records = reader.collect::<Result<Vec<_>>>().unwrap();
+        assert!(!records.is_empty());
+        assert!(records.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+    }
+}
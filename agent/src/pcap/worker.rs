@@ -29,10 +29,11 @@ use dashmap::DashMap;
 use log::{debug, error, info, warn};
 
 use super::{
-    format_time, get_temp_filename,
+    file_extension, format_time, get_temp_filename,
     writer::{Writer, WriterCounter},
     Packet, PcapPacket, TapType,
 };
+use crate::config::PcapFileFormat;
 use crate::rpc::get_timestamp;
 use crate::utils::stats::{Counter, CounterType, CounterValue, RefCountable};
 use public::queue::{self, Error};
@@ -62,6 +63,7 @@ struct WorkerConfig {
     max_file_period: Duration,
     base_directory: PathBuf,
     writer_buffer_size: u32,
+    file_format: PcapFileFormat,
 }
 
 pub struct Worker {
@@ -84,6 +86,7 @@ impl Worker {
         max_file_period: Duration,
         base_directory: PathBuf,
         writer_buffer_size: u32,
+        file_format: PcapFileFormat,
         packet_receiver: queue::Receiver<PcapPacket>,
         interval: Duration,
         ntp_diff: Arc<AtomicI64>,
@@ -97,6 +100,7 @@ impl Worker {
                 max_file_period,
                 base_directory,
                 writer_buffer_size,
+                file_format,
             },
             counter: Default::default(),
             writers: Arc::new(DashMap::new()),
@@ -191,6 +195,7 @@ impl Worker {
                 first_pkt_time,
                 last_pkt_time,
                 vtap_id,
+                format,
                 ..
             } = writer;
 
@@ -201,8 +206,13 @@ impl Worker {
             new_filename.pop();
 
             new_filename.push(format!(
-                "{}_{:012x}_0_{}_{}.{}.pcap",
-                tap_type, dispatcher_id, first_pkt_time, last_pkt_time, vtap_id
+                "{}_{:012x}_0_{}_{}.{}.{}",
+                tap_type,
+                dispatcher_id,
+                first_pkt_time,
+                last_pkt_time,
+                vtap_id,
+                file_extension(format)
             ));
 
             debug!(
@@ -283,6 +293,7 @@ impl Worker {
                 acl_gid,
                 vtap_id,
                 pkt_timestamp,
+                config.file_format,
             ) {
                 Ok(writer) => {
                     counter.file_creations.fetch_add(1, Ordering::Relaxed);
@@ -297,7 +308,8 @@ impl Worker {
                             tap_type,
                             dispatcher_id,
                             pkt_timestamp,
-                            vtap_id
+                            vtap_id,
+                            config.file_format,
                         )
                         .display(),
                         err
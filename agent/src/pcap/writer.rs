@@ -15,6 +15,7 @@
  */
 
 use std::{
+    collections::BTreeMap,
     fs::{self, File, OpenOptions},
     io::{BufWriter, Result, Write},
     path::{Path, PathBuf},
@@ -23,12 +24,29 @@ use std::{
 
 use log::{debug, error};
 
-use crate::common::enums::{LinkType, TapType};
+use crate::common::{
+    enums::{LinkType, TapType},
+    tap_port::TapPort,
+};
+use crate::config::PcapFileFormat;
 
 use super::{
-    format_time, Packet, PCAP_MAGIC, RECORD_HEADER_LEN, SNAP_LEN, VERSION_MAJOR, VERSION_MINOR,
+    get_temp_filename,
+    reader::{
+        PCAPNG_BYTE_ORDER_MAGIC, PCAPNG_EPB_TYPE, PCAPNG_IDB_TYPE, PCAPNG_OPT_ENDOFOPT,
+        PCAPNG_OPT_IF_TSRESOL, PCAPNG_SHB_TYPE,
+    },
+    Packet, PCAP_MAGIC, RECORD_HEADER_LEN, SNAP_LEN, VERSION_MAJOR, VERSION_MINOR,
 };
 
+const PCAPNG_VERSION_MAJOR: u16 = 1;
+const PCAPNG_VERSION_MINOR: u16 = 0;
+const PCAPNG_OPT_COMMENT: u16 = 1;
+const PCAPNG_OPT_IF_NAME: u16 = 2;
+// 纳秒精度时间戳
+// nanosecond timestamps
+const PCAPNG_TSRESOL_NANO: u8 = 9;
+
 #[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
 pub struct WriterCounter {
     pub written_count: u64,
@@ -46,6 +64,10 @@ pub struct Writer {
     pub vtap_id: u16,
     pub first_pkt_time: Duration,
     pub last_pkt_time: Duration,
+    pub format: PcapFileFormat,
+    // pcapng 中每个 tap port 对应的 interface id
+    // interface id of each tap port in pcapng
+    interfaces: BTreeMap<TapPort, u32>,
 }
 
 impl Writer {
@@ -57,22 +79,23 @@ impl Writer {
         acl_gid: u16,
         vtap_id: u16,
         pkt_timestamp: Duration,
+        format: PcapFileFormat,
     ) -> Result<Self> {
-        let mut filename = base_directory.as_ref().to_path_buf();
-        filename.push(format!("{}", acl_gid));
-
-        if !filename.exists() {
-            fs::create_dir_all(filename.as_path())
-                .unwrap_or_else(|e| error!("failed to create dir {}: {:?}", filename.display(), e));
-        }
-
-        filename.push(format!(
-            "{}_{:012x}_0_{}_.{}.pcap.temp",
+        let filename = get_temp_filename(
+            base_directory.as_ref(),
+            acl_gid,
             tap_type,
             dispatcher_id,
-            format_time(pkt_timestamp),
-            vtap_id
-        ));
+            pkt_timestamp,
+            vtap_id,
+            format,
+        );
+
+        let dir = filename.parent().unwrap();
+        if !dir.exists() {
+            fs::create_dir_all(dir)
+                .unwrap_or_else(|e| error!("failed to create dir {}: {:?}", dir.display(), e));
+        }
 
         debug!("begin to write packets to {}", filename.display());
 
@@ -81,21 +104,19 @@ impl Writer {
             .append(true)
             .create(true)
             .open(filename.as_path())?;
-        let writer = if fp.metadata()?.len() != 0 {
-            if buffer_size == 0 {
-                BufWriter::new(fp)
-            } else {
-                BufWriter::with_capacity(buffer_size, fp)
-            }
+        let is_empty = fp.metadata()?.len() == 0;
+        let mut writer = if buffer_size == 0 {
+            BufWriter::new(fp)
         } else {
-            let mut writer = if buffer_size == 0 {
-                BufWriter::new(fp)
-            } else {
-                BufWriter::with_capacity(buffer_size, fp)
-            };
-            Self::write_global_header(&mut writer, SNAP_LEN)?;
-            writer
+            BufWriter::with_capacity(buffer_size, fp)
         };
+        match format {
+            PcapFileFormat::Pcap if is_empty => Self::write_global_header(&mut writer, SNAP_LEN)?,
+            PcapFileFormat::Pcap => (),
+            // 续写已有文件时开始新的 section，之前的 interface 在新 section 中无效
+            // appending to an existing file starts a new section, previous interfaces are reset
+            PcapFileFormat::Pcapng => Self::write_section_header(&mut writer)?,
+        }
 
         Ok(Self {
             temp_filename: filename,
@@ -107,6 +128,8 @@ impl Writer {
             vtap_id,
             first_pkt_time: pkt_timestamp,
             last_pkt_time: pkt_timestamp,
+            format,
+            interfaces: BTreeMap::new(),
         })
     }
 
@@ -133,23 +156,117 @@ impl Writer {
         Ok(())
     }
 
-    pub fn write(&mut self, pkt: Packet) -> Result<()> {
+    // 写入 pcapng block，body 需要已按 4 字节对齐，返回写入的字节数
+    // writes a pcapng block with a 4-byte aligned body, returns bytes written
+    fn write_block(writer: &mut BufWriter<File>, block_type: u32, body: &[u8]) -> Result<usize> {
+        let total_len = (body.len() + 12) as u32;
+        writer.write_all(block_type.to_le_bytes().as_slice())?;
+        writer.write_all(total_len.to_le_bytes().as_slice())?;
+        writer.write_all(body)?;
+        writer.write_all(total_len.to_le_bytes().as_slice())?;
+        Ok(total_len as usize)
+    }
+
+    fn push_option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+        body.extend_from_slice(code.to_le_bytes().as_slice());
+        body.extend_from_slice((value.len() as u16).to_le_bytes().as_slice());
+        body.extend_from_slice(value);
+        Self::pad(body);
+    }
+
+    fn pad(body: &mut Vec<u8>) {
+        body.resize((body.len() + 3) / 4 * 4, 0);
+    }
+
+    fn write_section_header(writer: &mut BufWriter<File>) -> Result<()> {
+        let mut body = Vec::with_capacity(16);
+        body.extend_from_slice(PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes().as_slice());
+        body.extend_from_slice(PCAPNG_VERSION_MAJOR.to_le_bytes().as_slice());
+        body.extend_from_slice(PCAPNG_VERSION_MINOR.to_le_bytes().as_slice());
+        // section length 未知
+        // section length is not specified
+        body.extend_from_slice((-1i64).to_le_bytes().as_slice());
+        Self::write_block(writer, PCAPNG_SHB_TYPE, &body)?;
+        Ok(())
+    }
+
+    // 每个 tap port 对应一个 interface，名称中包含 dispatcher id 和 tap port
+    // one interface per tap port, named after dispatcher id and tap port
+    fn write_interface(&mut self, tap_port: TapPort) -> Result<u32> {
+        if let Some(id) = self.interfaces.get(&tap_port) {
+            return Ok(*id);
+        }
+        let mut body = Vec::with_capacity(64);
+        body.extend_from_slice(
+            (u8::from(LinkType::Ethernet) as u16)
+                .to_le_bytes()
+                .as_slice(),
+        );
+        body.extend_from_slice(0u16.to_le_bytes().as_slice());
+        body.extend_from_slice(SNAP_LEN.to_le_bytes().as_slice());
+        let name = format!("dispatcher{}@{}", self.dispatcher_id, tap_port);
+        Self::push_option(&mut body, PCAPNG_OPT_IF_NAME, name.as_bytes());
+        Self::push_option(&mut body, PCAPNG_OPT_IF_TSRESOL, &[PCAPNG_TSRESOL_NANO]);
+        Self::push_option(&mut body, PCAPNG_OPT_ENDOFOPT, &[]);
+        let n = Self::write_block(&mut self.writer, PCAPNG_IDB_TYPE, &body)?;
+        self.counter.written_bytes += n as u64;
+
+        let id = self.interfaces.len() as u32;
+        self.interfaces.insert(tap_port, id);
+        Ok(id)
+    }
+
+    fn write_enhanced_packet(&mut self, pkt: &Packet) -> Result<()> {
+        let if_id = self.write_interface(pkt.tap_port)?;
         let pkt_bytes = pkt.bytes();
-        Self::write_record_header(
-            &mut self.writer,
-            pkt.timestamp(),
-            pkt_bytes.len() as u16,
-            pkt.pkt_len(),
-        )?;
-        self.writer.write_all(pkt_bytes)?;
+        let ts = pkt.timestamp().as_nanos() as u64;
+        let mut body = Vec::with_capacity(pkt_bytes.len() + 96);
+        body.extend_from_slice(if_id.to_le_bytes().as_slice());
+        body.extend_from_slice(((ts >> 32) as u32).to_le_bytes().as_slice());
+        body.extend_from_slice((ts as u32).to_le_bytes().as_slice());
+        body.extend_from_slice((pkt_bytes.len() as u32).to_le_bytes().as_slice());
+        body.extend_from_slice((pkt.pkt_len() as u32).to_le_bytes().as_slice());
+        body.extend_from_slice(pkt_bytes);
+        Self::pad(&mut body);
+        // 注释用于在 Wireshark 中按策略过滤，如 frame.comment contains "acl_gid=10"
+        // comments allow filtering by policy in Wireshark, e.g. frame.comment contains "acl_gid=10"
+        let comment = format!(
+            "acl_gid={} tap_type={} flow_id={}",
+            pkt.acl_gid, pkt.tap_type, pkt.flow_id
+        );
+        Self::push_option(&mut body, PCAPNG_OPT_COMMENT, comment.as_bytes());
+        Self::push_option(&mut body, PCAPNG_OPT_ENDOFOPT, &[]);
+        let n = Self::write_block(&mut self.writer, PCAPNG_EPB_TYPE, &body)?;
+        self.counter.written_bytes += n as u64;
+        Ok(())
+    }
+
+    pub fn write(&mut self, pkt: Packet) -> Result<()> {
+        match self.format {
+            PcapFileFormat::Pcap => {
+                let pkt_bytes = pkt.bytes();
+                Self::write_record_header(
+                    &mut self.writer,
+                    pkt.timestamp(),
+                    pkt_bytes.len() as u16,
+                    pkt.pkt_len(),
+                )?;
+                self.writer.write_all(pkt_bytes)?;
+                self.counter.written_bytes += (RECORD_HEADER_LEN + pkt_bytes.len()) as u64;
+            }
+            PcapFileFormat::Pcapng => self.write_enhanced_packet(&pkt)?,
+        }
 
         self.counter.written_count += 1;
-        self.counter.written_bytes += (RECORD_HEADER_LEN + pkt_bytes.len()) as u64;
         self.last_pkt_time = pkt.timestamp;
 
         Ok(())
     }
 
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+
     fn reset_stats(&mut self) {
         self.counter = WriterCounter::default();
     }
@@ -164,3 +281,108 @@ impl Writer {
         c
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::pcap::Reader;
+
+    fn new_packet(tap_port: u64, ts: Duration, flow_id: u64) -> Packet {
+        Packet {
+            timestamp: ts,
+            tap_type: TapType::Cloud,
+            acl_gid: 10,
+            pkt_len: 64,
+            vtap_id: 1,
+            dispatcher_id: 2,
+            tap_port: TapPort(tap_port),
+            flow_id,
+            raw_pkt: vec![0xab; 14],
+        }
+    }
+
+    #[test]
+    fn write_pcapng() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = Duration::new(1600000000, 123456789);
+        let mut writer = Writer::new(
+            dir.path(),
+            0,
+            TapType::Cloud,
+            2,
+            10,
+            1,
+            ts,
+            PcapFileFormat::Pcapng,
+        )
+        .unwrap();
+        writer.write(new_packet(1, ts, 100)).unwrap();
+        writer
+            .write(new_packet(2, ts + Duration::from_nanos(1), 200))
+            .unwrap();
+        writer
+            .write(new_packet(1, ts + Duration::from_secs(1), 100))
+            .unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.interfaces.len(), 2);
+        assert!(writer
+            .temp_filename
+            .to_string_lossy()
+            .ends_with(".pcapng.temp"));
+
+        let records = Reader::open(&writer.temp_filename)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].timestamp, ts);
+        assert_eq!(records[1].timestamp, ts + Duration::from_nanos(1));
+        assert_eq!(records[0].original_length, 64);
+        assert_eq!(records[0].data, vec![0xab; 14]);
+
+        let content = fs::read(&writer.temp_filename).unwrap();
+        let content = String::from_utf8_lossy(&content);
+        assert!(content.contains("acl_gid=10 tap_type=tor flow_id=100"));
+        assert!(content.contains("acl_gid=10 tap_type=tor flow_id=200"));
+        assert_eq!(content.matches("dispatcher2@").count(), 2);
+    }
+
+    #[test]
+    fn append_pcapng() {
+        let dir = tempfile::tempdir().unwrap();
+        let ts = Duration::new(1600000000, 0);
+        for i in 0..2 {
+            let mut writer = Writer::new(
+                dir.path(),
+                0,
+                TapType::Cloud,
+                2,
+                10,
+                1,
+                ts,
+                PcapFileFormat::Pcapng,
+            )
+            .unwrap();
+            writer
+                .write(new_packet(1, ts + Duration::from_secs(i), i))
+                .unwrap();
+        }
+
+        let filename = get_temp_filename(
+            dir.path(),
+            10,
+            TapType::Cloud,
+            2,
+            ts,
+            1,
+            PcapFileFormat::Pcapng,
+        );
+        let records = Reader::open(filename)
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].timestamp, ts + Duration::from_secs(1));
+    }
+}
@@ -10,6 +10,16 @@ pub mod opentelemetry {
                 include!("opentelemetry.proto.trace.v1.rs");
             }
         }
+        pub mod metrics {
+            pub mod v1 {
+                include!("opentelemetry.proto.metrics.v1.rs");
+            }
+        }
+        pub mod logs {
+            pub mod v1 {
+                include!("opentelemetry.proto.logs.v1.rs");
+            }
+        }
         pub mod common {
             pub mod v1 {
                 include!("opentelemetry.proto.common.v1.rs");
@@ -0,0 +1,464 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+// 离线回放: 从 pcap/pcapng 文件读取报文，经过与实时采集相同的 dispatcher（隧道解封装、
+// 策略查询、分片重组）-> flow_map -> quadruple_generator -> collector 和 app protocol logs 流程，
+// 并将 TaggedFlow、应用协议日志和指标 Document 写入本地文件
+//
+// offline replay: packets read from a pcap/pcapng file go through the same dispatcher (tunnel
+// decapsulation, policy lookup and fragment reassembly) -> flow_map -> quadruple_generator ->
+// collector and app protocol logs pipeline as live capture, TaggedFlows, protocol logs and
+// metric Documents are written into local files
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{atomic::AtomicI64, Arc},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use arc_swap::{access::Map, ArcSwap};
+use log::info;
+
+#[cfg(target_os = "linux")]
+use crate::platform::{ActivePoller, GenericPoller};
+use crate::{
+    collector::{quadruple_generator::QuadrupleGeneratorThread, Collector, MetricsType},
+    common::{enums::TapType, tagged_flow::TaggedFlow, tap_types::TapTyper, FeatureFlags},
+    config::{
+        handler::{CollectorConfig, FlowConfig, LogParserConfig, ModuleConfig},
+        Config, RuntimeConfig,
+    },
+    dispatcher::{self, DispatcherBuilder, PcapFile},
+    exception::ExceptionHandler,
+    flow_generator::{protocol_logs::update_wasm_plugins, AppProtoLogsParser},
+    policy::Policy,
+    proto::trident::TapMode,
+    sender::SendItem,
+    trident::COMMON_DELAY,
+    utils::stats,
+};
+
+use public::{
+    debug::QueueDebugger,
+    queue::{self, Receiver},
+    utils::net::MacAddr,
+    LeakyBucket,
+};
+
+pub const L4_FLOW_LOG_FILE: &str = "l4_flow_log";
+pub const L7_FLOW_LOG_FILE: &str = "l7_flow_log";
+pub const METRICS_FILE: &str = "metrics";
+
+const FINISH_CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    pub pcap_file: PathBuf,
+    pub output_dir: PathBuf,
+    // 回放速度倍数，1 为按原始时间间隔回放，0 为不等待尽快回放
+    // replay speed multiplier, 1 keeps original packet intervals, 0 replays as fast as possible
+    pub speed: u32,
+    // 为空时使用 standalone 模式的默认配置
+    // standalone default config is used if not specified
+    pub runtime_config: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayStats {
+    pub packets: u64,
+    pub skipped_packets: u64,
+    pub l4_flow_logs: u64,
+    pub l7_flow_logs: u64,
+    pub metrics: u64,
+}
+
+fn create_output<P: AsRef<Path>>(dir: P, name: &str) -> Result<BufWriter<File>> {
+    let path = dir.as_ref().join(name);
+    let file = File::create(&path)
+        .map_err(|e| anyhow!("create output file {} failed: {}", path.display(), e))?;
+    Ok(BufWriter::new(file))
+}
+
+fn spawn_l4_writer(
+    receiver: Receiver<Arc<TaggedFlow>>,
+    mut writer: BufWriter<File>,
+) -> JoinHandle<Result<u64>> {
+    thread::spawn(move || {
+        let mut count = 0;
+        let mut kv_string = String::with_capacity(2048);
+        while let Ok(flow) = receiver.recv(None) {
+            flow.to_kv_string(&mut kv_string);
+            writer.write_all(kv_string.as_bytes())?;
+            kv_string.truncate(0);
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    })
+}
+
+// 与 standalone 模式的文件输出一致，每种 SendItem 写入以 file_name 命名的文件，返回各文件写入的条数
+// like file output of standalone mode, each kind of SendItem is written into the file named by
+// its file_name, the number of items written into each file is returned
+fn spawn_send_item_writer(
+    receiver: Receiver<SendItem>,
+    output_dir: PathBuf,
+) -> Result<JoinHandle<Result<HashMap<String, u64>>>> {
+    let mut writers = HashMap::new();
+    for name in [L7_FLOW_LOG_FILE, METRICS_FILE] {
+        writers.insert(name.to_owned(), (create_output(&output_dir, name)?, 0));
+    }
+    Ok(thread::spawn(move || {
+        let mut kv_string = String::with_capacity(2048);
+        while let Ok(item) = receiver.recv(None) {
+            kv_string.truncate(0);
+            item.to_kv_string(&mut kv_string);
+            if kv_string.is_empty() {
+                continue;
+            }
+            if !writers.contains_key(item.file_name()) {
+                let writer = create_output(&output_dir, item.file_name())?;
+                writers.insert(item.file_name().to_owned(), (writer, 0));
+            }
+            let (writer, count) = writers.get_mut(item.file_name()).unwrap();
+            writer.write_all(kv_string.as_bytes())?;
+            *count += 1;
+        }
+        let mut counts = HashMap::new();
+        for (name, (mut writer, count)) in writers {
+            writer.flush()?;
+            counts.insert(name, count);
+        }
+        Ok(counts)
+    }))
+}
+
+pub fn replay(options: &ReplayOptions) -> Result<ReplayStats> {
+    let runtime_config = match options.runtime_config.as_ref() {
+        Some(path) => RuntimeConfig::load_from_file(path)?,
+        None => RuntimeConfig::standalone_default(),
+    };
+    let config = ModuleConfig::try_from((
+        Config {
+            controller_ips: vec!["127.0.0.1".into()],
+            ..Default::default()
+        },
+        runtime_config,
+    ))?;
+    update_wasm_plugins(&config.log_parser.wasm_plugin);
+    let current_config = Arc::new(ArcSwap::from_pointee(config));
+    let flow_access = Map::new(current_config.clone(), |config| -> &FlowConfig {
+        &config.flow
+    });
+    let log_parser_access = Map::new(current_config.clone(), |config| -> &LogParserConfig {
+        &config.log_parser
+    });
+    let collector_access = Map::new(current_config.clone(), |config| -> &CollectorConfig {
+        &config.collector
+    });
+
+    let module_config = current_config.load_full();
+    let yaml_config = &module_config.yaml_config;
+    let ntp_diff = Arc::new(AtomicI64::new(0));
+
+    // 文件读完后推动时间直至所有流超时输出
+    // after EOF, time is moved on until all flows are timed out and flushed
+    let flow_config = &module_config.flow;
+    let drain_time = flow_config.flow_timeout.max
+        + flow_config.packet_delay
+        + flow_config.flush_interval
+        + Duration::from_secs(COMMON_DELAY as u64);
+    let pcap_file = PcapFile::open(
+        &options.pcap_file,
+        options.speed,
+        drain_time,
+        ntp_diff.clone(),
+    )
+    .map_err(|e| {
+        anyhow!(
+            "open pcap file {} failed: {}",
+            options.pcap_file.display(),
+            e
+        )
+    })?;
+    let file_status = pcap_file.status();
+    fs::create_dir_all(&options.output_dir)?;
+    let l4_writer = create_output(&options.output_dir, L4_FLOW_LOG_FILE)?;
+
+    let queue_debugger = QueueDebugger::new();
+    let stats_collector = Arc::new(stats::Collector::new(&vec![]));
+
+    let (flow_sender, flow_receiver, _) = queue::bounded_with_debug(
+        yaml_config.flow_queue_size,
+        "1-tagged-flow-to-quadruple-generator",
+        &queue_debugger,
+    );
+    let (log_sender, log_receiver, _) = queue::bounded_with_debug(
+        yaml_config.flow_queue_size,
+        "1-tagged-flow-to-app-protocol-logs",
+        &queue_debugger,
+    );
+    // Enterprise Edition Feature: packet-sequence
+    let (packet_sequence_sender, _, _) = queue::bounded_with_debug(
+        yaml_config.packet_sequence_queue_size,
+        "1-packet-sequence-block-to-uniform-collect-sender",
+        &queue_debugger,
+    );
+    let (second_sender, second_receiver, _) = queue::bounded_with_debug(
+        yaml_config.quadruple_queue_size,
+        "2-flow-with-meter-to-second-collector",
+        &queue_debugger,
+    );
+    let (minute_sender, minute_receiver, _) = queue::bounded_with_debug(
+        yaml_config.quadruple_queue_size,
+        "2-flow-with-meter-to-minute-collector",
+        &queue_debugger,
+    );
+    // 不经过 l4 flow aggr，输出 quadruple generator 收到的每条 TaggedFlow
+    // l4 flow aggr is bypassed, every TaggedFlow received by quadruple generator is written
+    let (l4_log_sender, l4_log_receiver, _) = queue::bounded_with_debug(
+        yaml_config.flow.aggr_queue_size as usize,
+        "2-second-flow-to-replay-writer",
+        &queue_debugger,
+    );
+    let (send_item_sender, send_item_receiver, _) = queue::bounded_with_debug(
+        yaml_config.flow_sender_queue_size,
+        "3-send-item-to-replay-writer",
+        &queue_debugger,
+    );
+
+    let (_, policy_getter) = Policy::new(
+        1,
+        yaml_config.first_path_level as usize,
+        yaml_config.fast_path_map_size,
+        false,
+        FeatureFlags::from(&yaml_config.feature_flags),
+    );
+    // 按 analyzer 模式处理，采集点类型由 VLAN 或 default_tap_type 决定
+    // packets are handled in analyzer mode, tap type is decided by VLAN or default_tap_type
+    let dispatcher_builder = DispatcherBuilder::new()
+        .id(0)
+        .ctrl_mac(MacAddr::ZERO)
+        .leaky_bucket(Arc::new(LeakyBucket::new(None)))
+        .options(Arc::new(dispatcher::Options {
+            #[cfg(target_os = "linux")]
+            af_packet_version: module_config.dispatcher.af_packet_version,
+            tap_mode: TapMode::Analyzer,
+            vxlan_port: yaml_config.vxlan_port,
+            vxlan_flags: yaml_config.vxlan_flags,
+            ip_fragment: yaml_config.ip_fragment.clone(),
+            snap_len: module_config.dispatcher.capture_packet_size as usize,
+            ..Default::default()
+        }))
+        .default_tap_type(
+            (yaml_config.default_tap_type as u16)
+                .try_into()
+                .unwrap_or(TapType::Cloud),
+        )
+        .mirror_traffic_pcp(yaml_config.mirror_traffic_pcp)
+        .tap_typer(Arc::new(TapTyper::new()))
+        .analyzer_dedup_disabled(yaml_config.analyzer_dedup_disabled)
+        .flow_output_queue(flow_sender)
+        .log_output_queue(log_sender)
+        .packet_sequence_output_queue(packet_sequence_sender) // Enterprise Edition Feature: packet-sequence
+        .stats_collector(stats_collector.clone())
+        .flow_map_config(flow_access)
+        .log_parse_config(log_parser_access.clone())
+        .policy_getter(policy_getter)
+        .exception_handler(ExceptionHandler::default())
+        .ntp_diff(ntp_diff.clone())
+        .pcap_file(pcap_file);
+    #[cfg(target_os = "linux")]
+    let dispatcher_builder = dispatcher_builder.platform_poller(Arc::new(GenericPoller::from(
+        ActivePoller::new(Duration::from_secs(60), None),
+    )));
+    let dispatcher = dispatcher_builder.build()?;
+    dispatcher
+        .listener()
+        .on_config_change(&module_config.dispatcher);
+
+    let second_delay = yaml_config.packet_delay.as_secs()
+        + 1
+        + yaml_config.flow.flush_interval.as_secs()
+        + COMMON_DELAY as u64
+        + yaml_config.second_flow_extra_delay.as_secs();
+    let minute_delay = 60
+        + yaml_config.packet_delay.as_secs()
+        + 1
+        + yaml_config.flow.flush_interval.as_secs()
+        + COMMON_DELAY as u64;
+    let mut quadruple_generator = QuadrupleGeneratorThread::new(
+        0,
+        flow_receiver,
+        second_sender,
+        minute_sender,
+        l4_log_sender,
+        (yaml_config.flow.hash_slots << 3) as usize,
+        MetricsType::SECOND | MetricsType::MINUTE,
+        second_delay,
+        minute_delay,
+        1 << 18,
+        collector_access.clone(),
+        ntp_diff.clone(),
+        stats_collector.clone(),
+    );
+    let second_collector = Collector::new(
+        0,
+        second_receiver,
+        send_item_sender.clone(),
+        MetricsType::SECOND,
+        second_delay as u32 + COMMON_DELAY,
+        &stats_collector,
+        collector_access.clone(),
+        ntp_diff.clone(),
+    );
+    let minute_collector = Collector::new(
+        0,
+        minute_receiver,
+        send_item_sender.clone(),
+        MetricsType::MINUTE,
+        minute_delay as u32 + COMMON_DELAY,
+        &stats_collector,
+        collector_access,
+        ntp_diff.clone(),
+    );
+    let (app_proto_logs_parser, _) = AppProtoLogsParser::new(
+        log_receiver,
+        send_item_sender,
+        0,
+        log_parser_access,
+        Arc::new(LeakyBucket::new(Some(
+            module_config.log_parser.l7_log_collect_nps_threshold,
+        ))),
+    );
+
+    let l4_writer = spawn_l4_writer(l4_log_receiver, l4_writer);
+    let send_item_writer = spawn_send_item_writer(send_item_receiver, options.output_dir.clone())?;
+
+    quadruple_generator.start();
+    second_collector.start();
+    minute_collector.start();
+    app_proto_logs_parser.start();
+
+    info!(
+        "replaying {} with speed {}",
+        options.pcap_file.display(),
+        options.speed
+    );
+    dispatcher.start();
+    while !file_status.is_finished() {
+        thread::sleep(FINISH_CHECK_INTERVAL);
+    }
+
+    // 按流水线顺序关闭队列，每个组件输出所有缓存的数据后退出
+    // queues are closed in pipeline order, each component flushes everything it holds before exiting
+    dispatcher.stop();
+    drop(dispatcher);
+    quadruple_generator.join();
+    drop(quadruple_generator);
+    app_proto_logs_parser.join();
+    drop(app_proto_logs_parser);
+    second_collector.join();
+    minute_collector.join();
+    drop(second_collector);
+    drop(minute_collector);
+
+    let mut stats = ReplayStats {
+        packets: file_status.packets(),
+        skipped_packets: file_status.skipped_packets(),
+        ..Default::default()
+    };
+    stats.l4_flow_logs = l4_writer
+        .join()
+        .map_err(|_| anyhow!("l4 flow log writer panicked"))??;
+    let counts = send_item_writer
+        .join()
+        .map_err(|_| anyhow!("send item writer panicked"))??;
+    stats.l7_flow_logs = counts[L7_FLOW_LOG_FILE];
+    stats.metrics = counts[METRICS_FILE];
+    for (name, count) in counts.iter() {
+        if name != L7_FLOW_LOG_FILE && name != METRICS_FILE {
+            info!("replay wrote {} items into {}", count, name);
+        }
+    }
+    if let Some(e) = file_status.error() {
+        return Err(anyhow!(
+            "read {} failed: {}",
+            options.pcap_file.display(),
+            e
+        ));
+    }
+
+    info!(
+        "replay {} finished: {:?}",
+        options.pcap_file.display(),
+        stats
+    );
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    #[test]
+    fn replay_http() {
+        let output_dir = tempfile::tempdir().unwrap();
+        let stats = replay(&ReplayOptions {
+            pcap_file: "resources/test/flow_generator/http/httpv1.pcap".into(),
+            output_dir: output_dir.path().to_path_buf(),
+            speed: 0,
+            runtime_config: None,
+        })
+        .unwrap();
+
+        assert!(stats.packets > 0);
+        assert_eq!(stats.skipped_packets, 0);
+        assert!(stats.l4_flow_logs > 0);
+        assert!(stats.l7_flow_logs > 0);
+        assert!(stats.metrics > 0);
+
+        let l4 = fs::read_to_string(output_dir.path().join(L4_FLOW_LOG_FILE)).unwrap();
+        assert_eq!(l4.lines().count() as u64, stats.l4_flow_logs);
+        let l7 = fs::read_to_string(output_dir.path().join(L7_FLOW_LOG_FILE)).unwrap();
+        assert_eq!(l7.lines().count() as u64, stats.l7_flow_logs);
+        assert!(l7.contains("/query?1590632942"));
+        let metrics = fs::read_to_string(output_dir.path().join(METRICS_FILE)).unwrap();
+        assert_eq!(metrics.lines().count() as u64, stats.metrics);
+        assert!(metrics
+            .lines()
+            .all(|l| serde_json::from_str::<serde_json::Value>(l).is_ok()));
+    }
+
+    #[test]
+    fn replay_missing_file() {
+        let output_dir = tempfile::tempdir().unwrap();
+        assert!(replay(&ReplayOptions {
+            pcap_file: "resources/test/flow_generator/not-exist.pcap".into(),
+            output_dir: output_dir.path().to_path_buf(),
+            speed: 0,
+            runtime_config: None,
+        })
+        .is_err());
+    }
+}
@@ -16,7 +16,10 @@
 
 // NpbBandwidthWatcher NewFragmenterBuilder NewCompressorBuilder NewPCapBuilder NewUniformCollectSender
 pub mod npb_sender;
+mod rotating_file;
+mod spill_queue;
 mod tcp_packet;
+mod tls;
 pub(crate) mod uniform_sender;
 
 use num_enum::IntoPrimitive;
@@ -28,7 +31,8 @@ use std::time::Duration;
 use crate::common::tagged_flow::TaggedFlow;
 use crate::flow_generator::AppProtoLogsData;
 use crate::integration_collector::{
-    OpenTelemetry, OpenTelemetryCompressed, PrometheusMetric, TelegrafMetric,
+    OpenTelemetry, OpenTelemetryCompressed, OpenTelemetryLogs, OpenTelemetryLogsCompressed,
+    OpenTelemetryMetrics, OpenTelemetryMetricsCompressed, PrometheusMetric, TelegrafMetric,
 };
 use crate::metric::document::Document;
 use crate::utils::stats::Batch;
@@ -40,12 +44,12 @@ const FLOW_LOG_VERSION: u32 = 20220128;
 const METRICS_VERSION: u32 = 20220117;
 const OPEN_TELEMETRY: u32 = 20220607;
 const OPEN_TELEMETRY_COMPRESSED: u32 = 20221024;
+const OPEN_TELEMETRY_METRICS: u32 = 20221215;
+const OPEN_TELEMETRY_LOGS: u32 = 20221215;
 const PROMETHEUS: u32 = 20220613;
 const TELEGRAF: u32 = 20220613;
 const PACKET_SEQUENCE_BLOCK: u32 = 20220712; // Enterprise Edition Feature: packet-sequence
 
-const PRE_FILE_SUFFIX: &str = ".pre";
-
 pub enum SendItem {
     L4FlowLog(Box<TaggedFlow>),
     L7FlowLog(Box<AppProtoLogsData>),
@@ -56,6 +60,10 @@ pub enum SendItem {
     PacketSequenceBlock(Box<packet_sequence_block::PacketSequenceBlock>), // Enterprise Edition Feature: packet-sequence
     DeepflowStats(Arc<Batch>),
     ExternalOtelCompressed(OpenTelemetryCompressed),
+    ExternalOtelMetrics(OpenTelemetryMetrics),
+    ExternalOtelMetricsCompressed(OpenTelemetryMetricsCompressed),
+    ExternalOtelLogs(OpenTelemetryLogs),
+    ExternalOtelLogsCompressed(OpenTelemetryLogsCompressed),
 }
 
 impl SendItem {
@@ -70,6 +78,10 @@ impl SendItem {
             Self::PacketSequenceBlock(p) => p.encode(buf), // Enterprise Edition Feature: packet-sequence
             Self::DeepflowStats(b) => b.encode(buf),
             Self::ExternalOtelCompressed(o) => o.encode(buf),
+            Self::ExternalOtelMetrics(o) => o.encode(buf),
+            Self::ExternalOtelMetricsCompressed(o) => o.encode(buf),
+            Self::ExternalOtelLogs(o) => o.encode(buf),
+            Self::ExternalOtelLogsCompressed(o) => o.encode(buf),
         }
     }
 
@@ -77,6 +89,16 @@ impl SendItem {
         match self {
             Self::L4FlowLog(l4) => l4.to_kv_string(kv_string),
             Self::L7FlowLog(l7) => l7.to_kv_string(kv_string),
+            Self::Metrics(m) => m.to_kv_string(kv_string),
+            Self::ExternalOtel(o) => o.to_kv_string(kv_string),
+            Self::ExternalProm(p) => p.to_kv_string(kv_string),
+            Self::ExternalTelegraf(p) => p.to_kv_string(kv_string),
+            Self::DeepflowStats(b) => b.to_kv_string(kv_string),
+            Self::ExternalOtelCompressed(o) => o.to_kv_string(kv_string),
+            Self::ExternalOtelMetrics(o) => o.to_kv_string(kv_string),
+            Self::ExternalOtelMetricsCompressed(o) => o.to_kv_string(kv_string),
+            Self::ExternalOtelLogs(o) => o.to_kv_string(kv_string),
+            Self::ExternalOtelLogsCompressed(o) => o.to_kv_string(kv_string),
             _ => return,
         }
     }
@@ -85,7 +107,17 @@ impl SendItem {
         match self {
             Self::L4FlowLog(_) => "l4_flow_log",
             Self::L7FlowLog(_) => "l7_flow_log",
-            _ => "other",
+            Self::Metrics(_) => "metrics",
+            Self::ExternalOtel(_) => "open_telemetry",
+            Self::ExternalProm(_) => "prometheus",
+            Self::ExternalTelegraf(_) => "telegraf",
+            Self::PacketSequenceBlock(_) => "packet_sequence_block", // Enterprise Edition Feature: packet-sequence
+            Self::DeepflowStats(_) => "deepflow_stats",
+            Self::ExternalOtelCompressed(_) => "open_telemetry_compressed",
+            Self::ExternalOtelMetrics(_) => "open_telemetry_metrics",
+            Self::ExternalOtelMetricsCompressed(_) => "open_telemetry_metrics_compressed",
+            Self::ExternalOtelLogs(_) => "open_telemetry_logs",
+            Self::ExternalOtelLogsCompressed(_) => "open_telemetry_logs_compressed",
         }
     }
 
@@ -100,6 +132,12 @@ impl SendItem {
             Self::PacketSequenceBlock(_) => SendMessageType::PacketSequenceBlock, // Enterprise Edition Feature: packet-sequence
             Self::DeepflowStats(_) => SendMessageType::DeepflowStats,
             Self::ExternalOtelCompressed(_) => SendMessageType::OpenTelemetryCompressed,
+            Self::ExternalOtelMetrics(_) => SendMessageType::OpenTelemetryMetrics,
+            Self::ExternalOtelMetricsCompressed(_) => {
+                SendMessageType::OpenTelemetryMetricsCompressed
+            }
+            Self::ExternalOtelLogs(_) => SendMessageType::OpenTelemetryLogs,
+            Self::ExternalOtelLogsCompressed(_) => SendMessageType::OpenTelemetryLogsCompressed,
         }
     }
 
@@ -113,6 +151,10 @@ impl SendItem {
             Self::ExternalTelegraf(_) => TELEGRAF,
             Self::PacketSequenceBlock(_) => PACKET_SEQUENCE_BLOCK, // Enterprise Edition Feature: packet-sequence
             Self::ExternalOtelCompressed(_) => OPEN_TELEMETRY_COMPRESSED,
+            Self::ExternalOtelMetrics(_) | Self::ExternalOtelMetricsCompressed(_) => {
+                OPEN_TELEMETRY_METRICS
+            }
+            Self::ExternalOtelLogs(_) | Self::ExternalOtelLogsCompressed(_) => OPEN_TELEMETRY_LOGS,
             _ => 0,
         }
     }
@@ -130,6 +172,14 @@ impl fmt::Display for SendItem {
             Self::PacketSequenceBlock(p) => write!(f, "packet_sequence_block: {:?}", p), // Enterprise Edition Feature: packet-sequence
             Self::DeepflowStats(s) => write!(f, "deepflow_stats: {:?}", s),
             Self::ExternalOtelCompressed(o) => write!(f, "open_telemetry compressed: {:?}", o),
+            Self::ExternalOtelMetrics(o) => write!(f, "open_telemetry metrics: {:?}", o),
+            Self::ExternalOtelMetricsCompressed(o) => {
+                write!(f, "open_telemetry metrics compressed: {:?}", o)
+            }
+            Self::ExternalOtelLogs(o) => write!(f, "open_telemetry logs: {:?}", o),
+            Self::ExternalOtelLogsCompressed(o) => {
+                write!(f, "open_telemetry logs compressed: {:?}", o)
+            }
         }
     }
 }
@@ -146,6 +196,14 @@ impl fmt::Debug for SendItem {
             Self::PacketSequenceBlock(p) => write!(f, "packet_sequence_block: {:?}", p), // Enterprise Edition Feature: packet-sequence
             Self::DeepflowStats(s) => write!(f, "deepflow_stats: {:?}", s),
             Self::ExternalOtelCompressed(o) => write!(f, "open_telemetry compressed: {:?}", o),
+            Self::ExternalOtelMetrics(o) => write!(f, "open_telemetry metrics: {:?}", o),
+            Self::ExternalOtelMetricsCompressed(o) => {
+                write!(f, "open_telemetry metrics compressed: {:?}", o)
+            }
+            Self::ExternalOtelLogs(o) => write!(f, "open_telemetry logs: {:?}", o),
+            Self::ExternalOtelLogsCompressed(o) => {
+                write!(f, "open_telemetry logs compressed: {:?}", o)
+            }
         }
     }
 }
@@ -165,6 +223,10 @@ pub enum SendMessageType {
     PacketSequenceBlock = 9, // Enterprise Edition Feature: packet-sequence
     DeepflowStats = 10,
     OpenTelemetryCompressed = 11,
+    OpenTelemetryMetrics = 12,
+    OpenTelemetryMetricsCompressed = 13,
+    OpenTelemetryLogs = 14,
+    OpenTelemetryLogsCompressed = 15,
 }
 
 impl fmt::Display for SendMessageType {
@@ -182,6 +244,10 @@ impl fmt::Display for SendMessageType {
             Self::PacketSequenceBlock => write!(f, "packet_sequence_block"), // Enterprise Edition Feature: packet-sequence
             Self::DeepflowStats => write!(f, "deepflow_stats"),
             Self::OpenTelemetryCompressed => write!(f, "open_telemetry compressed"),
+            Self::OpenTelemetryMetrics => write!(f, "open_telemetry metrics"),
+            Self::OpenTelemetryMetricsCompressed => write!(f, "open_telemetry metrics compressed"),
+            Self::OpenTelemetryLogs => write!(f, "open_telemetry logs"),
+            Self::OpenTelemetryLogsCompressed => write!(f, "open_telemetry logs compressed"),
         }
     }
 }
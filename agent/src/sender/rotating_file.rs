@@ -0,0 +1,514 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::{DateTime, Local};
+use flate2::{write::GzEncoder, Compression};
+use log::{debug, info, warn};
+
+const ARCHIVE_SUFFIX: &str = ".gz";
+const TEMP_EXTENSION: &str = "tmp";
+const TIME_FORMAT: &str = "%Y%m%d%H%M%S";
+const TIME_FORMAT_LEN: usize = 14;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RotateOptions {
+    // 单个文件的最大字节数
+    // the maximum size of a single file in bytes
+    pub max_file_size: u64,
+    // 按时间分桶轮转的周期，为零时不按时间轮转
+    // period of time-bucketed rotation, zero disables it
+    pub interval: Duration,
+    // 每种数据保留的压缩文件数量
+    // number of compressed files retained for each data type
+    pub max_files: usize,
+    // 目录中所有压缩文件的总字节数上限，为零时不限制
+    // total bytes of all compressed files in the directory, zero means unlimited
+    pub quota: u64,
+}
+
+// 待压缩的文件，压缩完成后按保留策略清理
+// a file waiting to be compressed, files are cleaned up by the retention options afterwards
+struct ArchiveTask {
+    source: PathBuf,
+    target: PathBuf,
+    options: RotateOptions,
+}
+
+struct Archiver {
+    sender: Sender<ArchiveTask>,
+    thread: JoinHandle<()>,
+}
+
+// 当前文件写满或跨越时间桶后被gzip压缩为 {name}.{桶起始时间}[.{序号}].gz
+// 文件先被改名为不带.gz的名称，再由后台线程压缩，避免大文件的压缩阻塞发送线程
+// the active file is gzip compressed as {name}.{bucket start}[.{index}].gz
+// when it is full or a new time bucket begins
+// it is renamed without the .gz suffix first and compressed by a background thread,
+// so that compressing large files never blocks the sender thread
+pub struct RotatingFile {
+    dir: PathBuf,
+    name: String,
+    writer: Option<BufWriter<File>>,
+    written_size: u64,
+    // 当前文件所属时间桶的起始时间
+    // start time of the bucket the active file belongs to
+    bucket_start: SystemTime,
+    archiver: Option<Archiver>,
+}
+
+impl RotatingFile {
+    pub fn new<P: AsRef<Path>>(dir: P, name: &str) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            name: name.to_owned(),
+            writer: None,
+            written_size: 0,
+            bucket_start: UNIX_EPOCH,
+            archiver: None,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(&self.name)
+    }
+
+    pub fn write(
+        &mut self,
+        data: &[u8],
+        now: SystemTime,
+        options: &RotateOptions,
+    ) -> io::Result<()> {
+        let bucket_start = bucket_start_of(now, options.interval);
+        if self.writer.is_some() && !options.interval.is_zero() && bucket_start != self.bucket_start
+        {
+            self.rotate(options)?;
+        }
+        if self.writer.is_none() {
+            self.open(bucket_start, options)?;
+        }
+
+        self.writer.as_mut().unwrap().write_all(data)?;
+        self.written_size += data.len() as u64;
+
+        if self.written_size >= options.max_file_size {
+            self.rotate(options)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match self.writer.as_mut() {
+            Some(w) => w.flush(),
+            None => Ok(()),
+        }
+    }
+
+    // 丢弃当前的writer，下次写入时重新打开文件
+    // drop the current writer, the file is reopened on next write
+    pub fn reset(&mut self) {
+        self.writer.take();
+    }
+
+    fn open(&mut self, bucket_start: SystemTime, options: &RotateOptions) -> io::Result<()> {
+        create_dir_all(&self.dir)?;
+        let path = self.path();
+        // 上次运行遗留的文件先归档，避免与新数据混在一起
+        // archive the file left by the last run first, so that it is not mixed with new data
+        if let Ok(meta) = fs::metadata(&path) {
+            if meta.len() > 0 {
+                let modified = meta.modified().unwrap_or(bucket_start);
+                self.archive(bucket_start_of(modified, options.interval), options)?;
+            }
+        }
+        let f = OpenOptions::new().create(true).append(true).open(&path)?;
+        self.written_size = f.metadata().map(|m| m.len()).unwrap_or_default();
+        self.writer = Some(BufWriter::new(f));
+        self.bucket_start = bucket_start;
+        Ok(())
+    }
+
+    fn rotate(&mut self, options: &RotateOptions) -> io::Result<()> {
+        if let Some(mut w) = self.writer.take() {
+            w.flush()?;
+        }
+        self.written_size = 0;
+        self.archive(self.bucket_start, options)
+    }
+
+    fn archive(&mut self, bucket_start: SystemTime, options: &RotateOptions) -> io::Result<()> {
+        let path = self.path();
+        let prefix = format!(
+            "{}.{}",
+            self.name,
+            DateTime::<Local>::from(bucket_start).format(TIME_FORMAT)
+        );
+        let mut target = self.dir.join(format!("{}{}", prefix, ARCHIVE_SUFFIX));
+        let mut index = 1;
+        while target.exists() || target.with_extension("").exists() {
+            target = self
+                .dir
+                .join(format!("{}.{}{}", prefix, index, ARCHIVE_SUFFIX));
+            index += 1;
+        }
+        let source = target.with_extension("");
+        fs::rename(&path, &source)?;
+
+        let task = ArchiveTask {
+            source,
+            target,
+            options: options.clone(),
+        };
+        // 后台线程退出时重新创建
+        // the background thread is recreated if it has exited
+        let task = match self.archiver.as_ref() {
+            Some(a) => match a.sender.send(task) {
+                Ok(_) => return Ok(()),
+                Err(mpsc::SendError(task)) => task,
+            },
+            None => task,
+        };
+        self.stop_archiver();
+        let (sender, receiver) = mpsc::channel::<ArchiveTask>();
+        let dir = self.dir.clone();
+        let name = self.name.clone();
+        let thread = thread::spawn(move || {
+            while let Ok(task) = receiver.recv() {
+                match compress(&task.source, &task.target) {
+                    Ok(_) => debug!(
+                        "archived {} to {}",
+                        task.source.display(),
+                        task.target.display()
+                    ),
+                    Err(e) => warn!("archive {} failed: {}", task.source.display(), e),
+                }
+                cleanup(&dir, &name, &task.options);
+            }
+        });
+        let _ = sender.send(task);
+        self.archiver = Some(Archiver { sender, thread });
+        Ok(())
+    }
+
+    // 等待已提交的文件压缩完成，drop时不等待，后台线程处理完已提交的文件后退出
+    // waits for submitted files to be compressed, which is not done on drop,
+    // the background thread exits after the submitted files are processed
+    fn stop_archiver(&mut self) {
+        if let Some(a) = self.archiver.take() {
+            drop(a.sender);
+            let _ = a.thread.join();
+        }
+    }
+}
+
+fn compress(source: &Path, target: &Path) -> io::Result<()> {
+    // 先写入临时文件，避免清理时把未完成的文件计入
+    // write to a temporary file first, so that unfinished files are never counted in cleanup
+    let temp = target.with_extension(TEMP_EXTENSION);
+    let result = (|| {
+        let mut encoder = GzEncoder::new(File::create(&temp)?, Compression::default());
+        io::copy(&mut File::open(source)?, &mut encoder)?;
+        encoder.finish()?.sync_all()?;
+        fs::rename(&temp, target)
+    })();
+    if let Err(e) = result {
+        let _ = fs::remove_file(&temp);
+        return Err(e);
+    }
+    fs::remove_file(source)
+}
+
+// 先按数量清理本类型的文件，再按总配额清理目录中所有类型的文件
+// remove files of this type by count first, then files of all types in the directory by quota
+fn cleanup(dir: &Path, name: &str, options: &RotateOptions) {
+    let mut archives = match list_archives(dir) {
+        Ok(a) => a,
+        Err(e) => {
+            warn!("list data files in {} failed: {}", dir.display(), e);
+            return;
+        }
+    };
+
+    let own = archives.iter().filter(|a| a.name == name).count();
+    let mut excess = own.saturating_sub(options.max_files);
+    archives.retain(|a| {
+        if excess > 0 && a.name == name {
+            excess -= 1;
+            remove_archive(&a.path);
+            false
+        } else {
+            true
+        }
+    });
+
+    if options.quota == 0 {
+        return;
+    }
+    let mut total: u64 = archives.iter().map(|a| a.size).sum();
+    for a in archives.iter() {
+        if total <= options.quota {
+            break;
+        }
+        remove_archive(&a.path);
+        total -= a.size;
+    }
+}
+
+fn remove_archive(path: &Path) {
+    match fs::remove_file(path) {
+        Ok(_) => info!("removed data file {}", path.display()),
+        // 其他发送线程可能已经删除
+        // it may have been removed by other sender threads
+        Err(e) if e.kind() == ErrorKind::NotFound => (),
+        Err(e) => warn!("remove data file {} failed: {}", path.display(), e),
+    }
+}
+
+// 时间桶以本地时间对齐，使按小时、按天轮转的文件名落在整点，不按时间轮转时取整到秒
+// buckets are aligned in local time so that hourly or daily files start on the hour,
+// truncated to the second if time-based rotation is disabled
+fn bucket_start_of(t: SystemTime, interval: Duration) -> SystemTime {
+    let secs = t.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64;
+    if interval.is_zero() {
+        return UNIX_EPOCH + Duration::from_secs(secs as u64);
+    }
+    let offset = DateTime::<Local>::from(t).offset().local_minus_utc() as i64;
+    let interval = interval.as_secs().max(1) as i64;
+    let start = (secs + offset).div_euclid(interval) * interval - offset;
+    UNIX_EPOCH + Duration::from_secs(start.max(0) as u64)
+}
+
+struct Archive {
+    name: String,
+    time: String,
+    index: u32,
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+// 归档文件名形如 {name}.{%Y%m%d%H%M%S}[.{序号}].gz，其他文件被忽略
+// archives are named as {name}.{%Y%m%d%H%M%S}[.{index}].gz, other files are ignored
+fn parse_archive_name(file_name: &str) -> Option<(&str, &str, u32)> {
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    let stem = file_name.strip_suffix(ARCHIVE_SUFFIX)?;
+    let (rest, last) = stem.rsplit_once('.')?;
+    let (name, time, index) = if last.len() == TIME_FORMAT_LEN && is_digits(last) {
+        (rest, last, 0)
+    } else if is_digits(last) {
+        let (name, time) = rest.rsplit_once('.')?;
+        (name, time, last.parse().ok()?)
+    } else {
+        return None;
+    };
+    if name.is_empty() || time.len() != TIME_FORMAT_LEN || !is_digits(time) {
+        return None;
+    }
+    Some((name, time, index))
+}
+
+// 按修改时间从旧到新排序
+// sorted from the oldest to the newest by modified time
+fn list_archives(dir: &Path) -> io::Result<Vec<Archive>> {
+    let mut archives = vec![];
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let (name, time, index) = match file_name.to_str().and_then(parse_archive_name) {
+            Some((n, t, i)) => (n.to_owned(), t.to_owned(), i),
+            None => continue,
+        };
+        let meta = match entry.metadata() {
+            Ok(m) if m.is_file() => m,
+            _ => continue,
+        };
+        archives.push(Archive {
+            name,
+            time,
+            index,
+            path: entry.path(),
+            size: meta.len(),
+            modified: meta.modified().unwrap_or(UNIX_EPOCH),
+        });
+    }
+    archives.sort_by(|a, b| {
+        a.modified
+            .cmp(&b.modified)
+            .then_with(|| a.time.cmp(&b.time))
+            .then(a.index.cmp(&b.index))
+    });
+    Ok(archives)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+
+    fn options() -> RotateOptions {
+        RotateOptions {
+            max_file_size: 1 << 20,
+            interval: Duration::from_secs(3600),
+            max_files: 10,
+            quota: 0,
+        }
+    }
+
+    fn archives_of(dir: &Path, name: &str) -> Vec<Archive> {
+        let mut a = list_archives(dir).unwrap();
+        a.retain(|a| a.name == name);
+        a
+    }
+
+    fn read_archive(path: &Path) -> String {
+        let mut s = String::new();
+        GzDecoder::new(File::open(path).unwrap())
+            .read_to_string(&mut s)
+            .unwrap();
+        s
+    }
+
+    #[test]
+    fn archive_name() {
+        assert_eq!(
+            parse_archive_name("l4_flow_log.20221018100000.gz"),
+            Some(("l4_flow_log", "20221018100000", 0))
+        );
+        assert_eq!(
+            parse_archive_name("metrics.20221018100000.3.gz"),
+            Some(("metrics", "20221018100000", 3))
+        );
+        assert_eq!(parse_archive_name("l4_flow_log"), None);
+        assert_eq!(parse_archive_name("l4_flow_log.20221018100000.tmp"), None);
+        assert_eq!(parse_archive_name("deepflow-agent.log.gz"), None);
+        assert_eq!(parse_archive_name(".20221018100000.gz"), None);
+    }
+
+    #[test]
+    fn rotate_by_time_and_size() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut f = RotatingFile::new(dir.path(), "l4_flow_log");
+        let mut opts = options();
+        let t0 = bucket_start_of(SystemTime::now(), opts.interval);
+
+        f.write(b"a\n", t0, &opts).unwrap();
+        f.write(b"b\n", t0 + Duration::from_secs(10), &opts)
+            .unwrap();
+        assert!(archives_of(dir.path(), "l4_flow_log").is_empty());
+
+        // 跨越时间桶
+        // a new time bucket begins
+        f.write(b"c\n", t0 + Duration::from_secs(3600), &opts)
+            .unwrap();
+        f.stop_archiver();
+        let archives = archives_of(dir.path(), "l4_flow_log");
+        assert_eq!(archives.len(), 1);
+        assert_eq!(read_archive(&archives[0].path), "a\nb\n");
+        f.flush().unwrap();
+        assert_eq!(fs::read_to_string(f.path()).unwrap(), "c\n");
+
+        // 写满后轮转，同一时间桶内的文件名带序号
+        // rotated when full, files in the same bucket are named with an index
+        opts.max_file_size = 4;
+        f.write(b"d\n", t0 + Duration::from_secs(3601), &opts)
+            .unwrap();
+        assert!(!f.path().exists());
+        f.write(b"e\n", t0 + Duration::from_secs(3602), &opts)
+            .unwrap();
+        f.write(b"f\n", t0 + Duration::from_secs(3603), &opts)
+            .unwrap();
+        f.write(b"g\n", t0 + Duration::from_secs(3604), &opts)
+            .unwrap();
+        f.stop_archiver();
+        let archives = archives_of(dir.path(), "l4_flow_log");
+        assert_eq!(archives.len(), 3);
+        let mut names: Vec<String> = archives
+            .iter()
+            .map(|a| a.path.file_name().unwrap().to_str().unwrap().to_owned())
+            .collect();
+        names.sort();
+        let prefix = format!(
+            "l4_flow_log.{}",
+            DateTime::<Local>::from(t0 + Duration::from_secs(3600)).format(TIME_FORMAT)
+        );
+        assert_eq!(
+            names[1..],
+            [format!("{}.1.gz", prefix), format!("{}.gz", prefix)]
+        );
+    }
+
+    #[test]
+    fn retention_and_quota() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut opts = options();
+        opts.max_file_size = 1;
+        opts.max_files = 3;
+        let t0 = bucket_start_of(SystemTime::now(), opts.interval);
+
+        let mut flows = RotatingFile::new(dir.path(), "l4_flow_log");
+        for i in 0..5 {
+            flows
+                .write(format!("{}\n", i).as_bytes(), t0, &opts)
+                .unwrap();
+        }
+        flows.stop_archiver();
+        let archives = archives_of(dir.path(), "l4_flow_log");
+        assert_eq!(archives.len(), 3);
+        assert_eq!(read_archive(&archives[0].path), "2\n");
+
+        // 配额按修改时间删除所有类型中最旧的文件
+        // the quota removes the oldest files of all types by modified time
+        let size = archives[0].size;
+        opts.quota = size * 4;
+        let mut metrics = RotatingFile::new(dir.path(), "metrics");
+        for i in 0..2 {
+            metrics
+                .write(format!("{}\n", i).as_bytes(), t0, &opts)
+                .unwrap();
+        }
+        metrics.stop_archiver();
+        let all = list_archives(dir.path()).unwrap();
+        assert!(all.iter().map(|a| a.size).sum::<u64>() <= opts.quota);
+        assert_eq!(archives_of(dir.path(), "metrics").len(), 2);
+    }
+
+    #[test]
+    fn archive_leftover() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("metrics"), "old\n").unwrap();
+        let mut f = RotatingFile::new(dir.path(), "metrics");
+        f.write(b"new\n", SystemTime::now(), &options()).unwrap();
+        f.flush().unwrap();
+        f.stop_archiver();
+        let archives = archives_of(dir.path(), "metrics");
+        assert_eq!(archives.len(), 1);
+        assert_eq!(read_archive(&archives[0].path), "old\n");
+        assert_eq!(fs::read_to_string(f.path()).unwrap(), "new\n");
+    }
+}
@@ -0,0 +1,450 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{self, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::Crc;
+use log::{info, warn};
+
+const MAGIC: u32 = 0x44465351; // "DFSQ"
+const VERSION: u32 = 1;
+// 文件头：magic, version, capacity, head, tail, used, crc，剩余部分保留
+// file header: magic, version, capacity, head, tail, used, crc, the rest is reserved
+const HEADER_LEN: u64 = 64;
+const HEADER_CRC_OFFSET: usize = 40;
+// 记录头：负载长度和负载的crc32
+// record header: payload length and crc32 of the payload
+const RECORD_HEADER_LEN: u64 = 8;
+// 环尾部放不下记录时写入此标记，读取时跳到环首
+// written when a record does not fit at the end of the ring, readers skip to the start
+const WRAP_MARKER: u32 = u32::MAX;
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc::new();
+    crc.update(data);
+    crc.sum()
+}
+
+// 有界的磁盘环形队列，空间不足时淘汰最旧的记录
+// bounded on-disk ring queue, the oldest records are evicted when it is full
+pub struct SpillQueue {
+    path: PathBuf,
+    file: File,
+    capacity: u64,
+
+    head: u64,
+    tail: u64,
+    // 已占用的字节数，包含记录头和环尾部的空洞
+    // bytes in use, including record headers and the gap at the end of the ring
+    used: u64,
+
+    // 被淘汰或因校验失败丢弃的负载字节数
+    // payload bytes evicted or discarded due to checksum failures
+    dropped_bytes: u64,
+}
+
+impl SpillQueue {
+    // 文件头有效且容量一致时恢复上次未回放的数据，否则重建文件
+    // records not replayed last time are restored if the header is valid and the
+    // capacity is unchanged, otherwise the file is recreated
+    pub fn open<P: AsRef<Path>>(path: P, capacity: u64) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(dir) = path.parent() {
+            create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        let mut queue = Self {
+            path,
+            file,
+            capacity,
+            head: 0,
+            tail: 0,
+            used: 0,
+            dropped_bytes: 0,
+        };
+        match queue.read_header() {
+            Ok(true) => {
+                if queue.used > 0 {
+                    info!(
+                        "restored {} bytes from spill queue {}",
+                        queue.used,
+                        queue.path.display()
+                    );
+                }
+            }
+            result => {
+                if let Err(e) = result {
+                    warn!(
+                        "read spill queue {} header failed: {}",
+                        queue.path.display(),
+                        e
+                    );
+                }
+                queue.file.set_len(0)?;
+                queue.file.set_len(HEADER_LEN + capacity)?;
+                queue.write_header()?;
+            }
+        }
+        Ok(queue)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.used == 0
+    }
+
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
+    pub fn take_dropped_bytes(&mut self) -> u64 {
+        std::mem::take(&mut self.dropped_bytes)
+    }
+
+    pub fn push(&mut self, data: &[u8]) -> io::Result<()> {
+        let size = RECORD_HEADER_LEN + data.len() as u64;
+        if size > self.capacity {
+            self.dropped_bytes += data.len() as u64;
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "record of {} bytes exceeds spill queue capacity {}",
+                    data.len(),
+                    self.capacity
+                ),
+            ));
+        }
+
+        let wrap = loop {
+            if let Some(wrap) = self.reserve(size) {
+                break wrap;
+            }
+            match self.front()? {
+                Some((_, len, _)) => {
+                    self.advance(len);
+                    self.dropped_bytes += len as u64;
+                }
+                None => unreachable!("empty spill queue is always able to hold the record"),
+            }
+        };
+
+        if wrap {
+            let gap = self.capacity - self.tail;
+            if gap >= RECORD_HEADER_LEN {
+                self.write_at(self.tail, WRAP_MARKER.to_le_bytes().as_slice())?;
+            }
+            self.used += gap;
+            self.tail = 0;
+        }
+
+        let mut record = Vec::with_capacity(size as usize);
+        record.extend_from_slice((data.len() as u32).to_le_bytes().as_slice());
+        record.extend_from_slice(crc32(data).to_le_bytes().as_slice());
+        record.extend_from_slice(data);
+        self.write_at(self.tail, &record)?;
+        self.tail += size;
+        if self.tail == self.capacity {
+            self.tail = 0;
+        }
+        self.used += size;
+        self.write_header()
+    }
+
+    // 返回队首记录但不出队，校验失败时清空队列
+    // returns the first record without removing it, the queue is cleared on checksum failures
+    pub fn peek(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let (offset, len, crc) = match self.front()? {
+            Some(r) => r,
+            None => return Ok(None),
+        };
+        let mut data = vec![0u8; len as usize];
+        self.read_at(offset + RECORD_HEADER_LEN, &mut data)?;
+        if crc32(&data) != crc {
+            return Err(self.corrupted());
+        }
+        Ok(Some(data))
+    }
+
+    pub fn pop(&mut self) -> io::Result<()> {
+        if let Some((_, len, _)) = self.front()? {
+            self.advance(len);
+            self.write_header()?;
+        }
+        Ok(())
+    }
+
+    // 返回记录的写入位置是否需要回绕到环首，空间不足时返回None
+    // returns whether the record is written after wrapping to the start of the ring,
+    // or None if there is not enough space
+    fn reserve(&mut self, size: u64) -> Option<bool> {
+        if self.used == 0 {
+            self.head = 0;
+            self.tail = 0;
+        }
+        if self.used == 0 || self.tail > self.head {
+            if self.capacity - self.tail >= size {
+                Some(false)
+            } else if self.head >= size {
+                Some(true)
+            } else {
+                None
+            }
+        } else if self.tail < self.head && self.head - self.tail >= size {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    // 跳过环尾部的空洞，返回队首记录的偏移、长度和crc
+    // skips the gap at the end of the ring, returns offset, length and crc of the first record
+    fn front(&mut self) -> io::Result<Option<(u64, u32, u32)>> {
+        loop {
+            if self.used == 0 {
+                return Ok(None);
+            }
+            if self.capacity - self.head >= RECORD_HEADER_LEN {
+                let mut header = [0u8; RECORD_HEADER_LEN as usize];
+                self.read_at(self.head, &mut header)?;
+                let len = u32::from_le_bytes(header[..4].try_into().unwrap());
+                if len != WRAP_MARKER {
+                    let size = RECORD_HEADER_LEN + len as u64;
+                    if size > self.capacity - self.head || size > self.used {
+                        return Err(self.corrupted());
+                    }
+                    let crc = u32::from_le_bytes(header[4..].try_into().unwrap());
+                    return Ok(Some((self.head, len, crc)));
+                }
+            }
+            let gap = self.capacity - self.head;
+            if gap >= self.used || self.head == 0 {
+                return Err(self.corrupted());
+            }
+            self.used -= gap;
+            self.head = 0;
+        }
+    }
+
+    fn advance(&mut self, len: u32) {
+        let size = RECORD_HEADER_LEN + len as u64;
+        self.head += size;
+        if self.head == self.capacity {
+            self.head = 0;
+        }
+        self.used -= size;
+        if self.used == 0 {
+            self.head = 0;
+            self.tail = 0;
+        }
+    }
+
+    fn corrupted(&mut self) -> io::Error {
+        warn!(
+            "spill queue {} corrupted, drop {} bytes",
+            self.path.display(),
+            self.used
+        );
+        self.dropped_bytes += self.used;
+        self.head = 0;
+        self.tail = 0;
+        self.used = 0;
+        if let Err(e) = self.write_header() {
+            warn!(
+                "write spill queue {} header failed: {}",
+                self.path.display(),
+                e
+            );
+        }
+        io::Error::new(ErrorKind::InvalidData, "spill queue corrupted")
+    }
+
+    fn read_header(&mut self) -> io::Result<bool> {
+        if self.file.metadata()?.len() != HEADER_LEN + self.capacity {
+            return Ok(false);
+        }
+        let mut header = [0u8; HEADER_LEN as usize];
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_exact(&mut header)?;
+        let u32_at = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+        let u64_at = |i: usize| u64::from_le_bytes(header[i..i + 8].try_into().unwrap());
+        if u32_at(0) != MAGIC
+            || u32_at(4) != VERSION
+            || u32_at(HEADER_CRC_OFFSET) != crc32(&header[..HEADER_CRC_OFFSET])
+            || u64_at(8) != self.capacity
+        {
+            return Ok(false);
+        }
+        let (head, tail, used) = (u64_at(16), u64_at(24), u64_at(32));
+        if head >= self.capacity || tail >= self.capacity || used > self.capacity {
+            return Ok(false);
+        }
+        self.head = head;
+        self.tail = tail;
+        self.used = used;
+        Ok(true)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let mut header = Vec::with_capacity(HEADER_LEN as usize);
+        header.extend_from_slice(MAGIC.to_le_bytes().as_slice());
+        header.extend_from_slice(VERSION.to_le_bytes().as_slice());
+        header.extend_from_slice(self.capacity.to_le_bytes().as_slice());
+        header.extend_from_slice(self.head.to_le_bytes().as_slice());
+        header.extend_from_slice(self.tail.to_le_bytes().as_slice());
+        header.extend_from_slice(self.used.to_le_bytes().as_slice());
+        let crc = crc32(&header);
+        header.extend_from_slice(crc.to_le_bytes().as_slice());
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header)
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(HEADER_LEN + offset))?;
+        self.file.read_exact(buf)
+    }
+
+    fn write_at(&mut self, offset: u64, buf: &[u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(HEADER_LEN + offset))?;
+        self.file.write_all(buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop_all(queue: &mut SpillQueue) -> Vec<Vec<u8>> {
+        let mut records = vec![];
+        while let Some(r) = queue.peek().unwrap() {
+            records.push(r);
+            queue.pop().unwrap();
+        }
+        records
+    }
+
+    #[test]
+    fn push_and_pop() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut queue = SpillQueue::open(dir.path().join("metrics-1.spill"), 1024).unwrap();
+        assert!(queue.is_empty());
+        assert_eq!(queue.peek().unwrap(), None);
+
+        queue.push(b"hello").unwrap();
+        queue.push(b"world").unwrap();
+        assert_eq!(queue.used(), 2 * (RECORD_HEADER_LEN + 5));
+        assert_eq!(queue.peek().unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(queue.peek().unwrap(), Some(b"hello".to_vec()));
+        queue.pop().unwrap();
+        assert_eq!(pop_all(&mut queue), vec![b"world".to_vec()]);
+        assert!(queue.is_empty());
+        assert_eq!(queue.take_dropped_bytes(), 0);
+
+        assert!(queue.push(&[0u8; 1024]).is_err());
+        assert_eq!(queue.take_dropped_bytes(), 1024);
+    }
+
+    #[test]
+    fn wrap_and_evict() {
+        let dir = tempfile::tempdir().unwrap();
+        // 每条记录占用18字节，容量可容纳3条记录
+        // each record takes 18 bytes, the ring holds 3 of them
+        let mut queue = SpillQueue::open(dir.path().join("l4_log-0.spill"), 60).unwrap();
+        for i in 0..3u8 {
+            queue.push(&[i; 10]).unwrap();
+        }
+        assert_eq!(queue.take_dropped_bytes(), 0);
+
+        // 尾部剩余6字节，回绕时淘汰最旧的记录
+        // 6 bytes left at the end, the oldest record is evicted when wrapping
+        queue.push(&[3; 10]).unwrap();
+        assert_eq!(queue.take_dropped_bytes(), 10);
+        queue.push(&[4; 10]).unwrap();
+        assert_eq!(queue.take_dropped_bytes(), 10);
+        assert_eq!(
+            pop_all(&mut queue),
+            vec![vec![2; 10], vec![3; 10], vec![4; 10]]
+        );
+
+        // 大记录淘汰多条旧记录
+        // a large record evicts several old ones
+        for i in 0..3u8 {
+            queue.push(&[i; 10]).unwrap();
+        }
+        queue.push(&[9; 30]).unwrap();
+        assert_eq!(queue.take_dropped_bytes(), 30);
+        queue.push(&[8; 4]).unwrap();
+        assert_eq!(queue.take_dropped_bytes(), 0);
+        assert_eq!(pop_all(&mut queue), vec![vec![9; 30], vec![8; 4]]);
+    }
+
+    #[test]
+    fn restore_after_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("l7_log-2.spill");
+        {
+            let mut queue = SpillQueue::open(&path, 60).unwrap();
+            for i in 0..4u8 {
+                queue.push(&[i; 10]).unwrap();
+            }
+            queue.pop().unwrap();
+        }
+        let mut queue = SpillQueue::open(&path, 60).unwrap();
+        assert_eq!(pop_all(&mut queue), vec![vec![2; 10], vec![3; 10]]);
+
+        // 容量变化时重建文件
+        // the file is recreated if the capacity changes
+        queue.push(b"old").unwrap();
+        drop(queue);
+        let mut queue = SpillQueue::open(&path, 120).unwrap();
+        assert!(queue.is_empty());
+        assert_eq!(queue.peek().unwrap(), None);
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("metrics-3.spill");
+        {
+            let mut queue = SpillQueue::open(&path, 1024).unwrap();
+            queue.push(b"hello").unwrap();
+            queue.push(b"world").unwrap();
+        }
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(HEADER_LEN + RECORD_HEADER_LEN))
+            .unwrap();
+        file.write_all(b"J").unwrap();
+        drop(file);
+
+        let mut queue = SpillQueue::open(&path, 1024).unwrap();
+        assert_eq!(queue.peek().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(queue.is_empty());
+        assert_eq!(queue.take_dropped_bytes(), 2 * (RECORD_HEADER_LEN + 5));
+    }
+}
@@ -16,7 +16,7 @@
 
 use std::{
     io::Write,
-    net::{IpAddr, TcpStream},
+    net::{IpAddr, Shutdown},
     sync::{
         atomic::{AtomicBool, AtomicU16, AtomicU64, Ordering},
         Arc, Mutex,
@@ -27,10 +27,15 @@ use std::{
     time::SystemTime,
 };
 
-use log::{info, warn};
+use arc_swap::access::Access;
+use log::{error, info, warn};
 
-use super::{ERR_INTERVAL, RCV_TIMEOUT, SEQUENCE_OFFSET};
+use super::{
+    tls::{DataStream, TlsClient, TlsConnector},
+    ERR_INTERVAL, RCV_TIMEOUT, SEQUENCE_OFFSET,
+};
 
+use crate::config::handler::SenderAccess;
 use crate::utils::{
     bytes::write_u64_be,
     stats::{Counter, CounterType, CounterValue, RefCountable},
@@ -68,6 +73,7 @@ pub struct TcpPacketSender {
     dst_port: Arc<AtomicU16>,
     reconnect: Arc<AtomicBool>,
     running: Arc<AtomicBool>,
+    config: SenderAccess,
 
     receiver: Arc<Receiver<Vec<u8>>>,
     counter: Arc<TcpPacketCounter>,
@@ -75,10 +81,13 @@ pub struct TcpPacketSender {
 }
 
 impl TcpPacketSender {
+    const TCP_WRITE_TIMEOUT: Duration = Duration::from_secs(3);
+
     pub fn new(
         id: u32,
         dst_ip: IpAddr,
         dst_port: u16,
+        config: SenderAccess,
         receiver: Receiver<Vec<u8>>,
     ) -> (Self, Arc<TcpPacketCounter>) {
         let running = Arc::new(AtomicBool::new(false));
@@ -93,6 +102,7 @@ impl TcpPacketSender {
                 dst_port: Arc::new(AtomicU16::new(dst_port)),
                 reconnect: Arc::new(AtomicBool::new(true)),
                 running,
+                config,
                 receiver: Arc::new(receiver),
                 counter: counter.clone(),
                 thread: Mutex::new(None),
@@ -111,21 +121,27 @@ impl TcpPacketSender {
         let counter = self.counter.clone();
         let dst_ip = self.dst_ip.clone();
         let dst_port = self.dst_port.clone();
+        let config = self.config.clone();
         let receiver = self.receiver.clone();
 
         let thread = thread::spawn(move || {
             let mut sequence = 0;
             let mut last_err_time = Duration::ZERO;
             let mut socket = None;
+            let mut tls = TlsClient::default();
             while running.load(Ordering::Relaxed) {
                 match receiver.recv(Some(RCV_TIMEOUT)) {
                     Ok(mut pkt) => {
+                        if !Self::update_tls(&mut tls, &config, &reconnect) {
+                            continue;
+                        }
                         if (socket.is_none() || reconnect.load(Ordering::Relaxed))
                             && !Self::connect(
                                 &reconnect,
                                 &mut socket,
                                 *dst_ip.lock().unwrap(),
                                 dst_port.load(Ordering::Relaxed),
+                                tls.connector(),
                             )
                         {
                             continue;
@@ -154,6 +170,9 @@ impl TcpPacketSender {
                     Err(Error::Timeout) => continue,
                 }
             }
+            if let Some(mut s) = socket.take() {
+                let _ = s.shutdown(Shutdown::Both);
+            }
         });
 
         self.thread.lock().unwrap().replace(thread);
@@ -177,13 +196,39 @@ impl TcpPacketSender {
         self.reconnect.store(true, Ordering::Relaxed);
     }
 
+    // 按发送配置更新TLS客户端，TLS配置无效时返回false，不会退化为明文发送
+    // updates the TLS client from the sender config, returns false if the TLS config
+    // is invalid, in which case packets are never sent in plaintext
+    fn update_tls(tls: &mut TlsClient, config: &SenderAccess, reconnect: &AtomicBool) -> bool {
+        match tls.update(&config.load().tls) {
+            Ok(true) => {
+                info!(
+                    "tcp packet sender tls {}",
+                    if tls.connector().is_some() {
+                        "enabled"
+                    } else {
+                        "disabled"
+                    }
+                );
+                reconnect.store(true, Ordering::Relaxed);
+            }
+            Ok(false) => (),
+            Err(e) => {
+                error!("tcp packet sender invalid tls config: {}", e);
+                reconnect.store(true, Ordering::Relaxed);
+            }
+        }
+        tls.is_valid()
+    }
+
     fn connect(
         reconnect: &AtomicBool,
-        socket: &mut Option<TcpStream>,
+        socket: &mut Option<DataStream>,
         dst_ip: IpAddr,
         dst_port: u16,
+        tls: Option<&TlsConnector>,
     ) -> bool {
-        match TcpStream::connect((dst_ip, dst_port)) {
+        match DataStream::connect(dst_ip, dst_port, tls, Self::TCP_WRITE_TIMEOUT) {
             Ok(s) => {
                 socket.replace(s);
                 reconnect.swap(false, Ordering::Relaxed);
@@ -196,3 +241,50 @@ impl TcpPacketSender {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    use arc_swap::{access::Map, ArcSwap};
+
+    use crate::config::handler::{ModuleConfig, SenderConfig};
+    use crate::sender::tls::tests::{client_config, serve};
+    use public::queue::bounded;
+
+    #[test]
+    fn tls_from_sender_config() {
+        let mut config = ModuleConfig::default();
+        config.sender.tls = client_config(false);
+        let current_config = Arc::new(ArcSwap::from_pointee(config));
+
+        let (port, server) = serve(false);
+        let (queue_sender, queue_receiver, _) = bounded(16);
+        let (sender, counter) = TcpPacketSender::new(
+            0,
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port,
+            Map::new(current_config.clone(), |config| -> &SenderConfig {
+                &config.sender
+            }),
+            queue_receiver,
+        );
+        sender.start();
+
+        let mut packet = vec![0u8; SEQUENCE_OFFSET + 8];
+        packet.extend_from_slice(b"hello analyzer");
+        queue_sender.send(packet.clone()).unwrap();
+        for _ in 0..500 {
+            if counter.tx.load(Ordering::Relaxed) > 0 {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        sender.stop();
+
+        write_u64_be(&mut packet[SEQUENCE_OFFSET..SEQUENCE_OFFSET + 8], 1);
+        assert_eq!(server.join().unwrap().unwrap(), packet);
+    }
+}
@@ -0,0 +1,430 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Write},
+    net::{IpAddr, Shutdown, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+
+use rustls::{
+    Certificate, ClientConfig, ClientConnection, PrivateKey, RootCertStore, ServerName, StreamOwned,
+};
+use rustls_pemfile::Item;
+use thiserror::Error;
+
+use crate::config::SenderTlsConfig;
+
+#[derive(Debug, Error)]
+pub enum TlsError {
+    #[error("read {0} failed: {1}")]
+    ReadFile(String, io::Error),
+    #[error("no valid certificate found in {0}")]
+    NoCertificate(String),
+    #[error("no private key found in {0}")]
+    NoPrivateKey(String),
+    #[error("cert-file and key-file must be set together")]
+    IncompleteClientCert,
+    #[error("ca-file is required")]
+    NoCaFile,
+    #[error("server-name is required to verify the server certificate")]
+    NoServerName,
+    #[error("invalid server-name {0}")]
+    InvalidServerName(String),
+    #[error(transparent)]
+    Rustls(#[from] rustls::Error),
+}
+
+fn read_pem(path: &str) -> Result<Vec<Item>, TlsError> {
+    let f = File::open(path).map_err(|e| TlsError::ReadFile(path.to_owned(), e))?;
+    rustls_pemfile::read_all(&mut BufReader::new(f))
+        .map_err(|e| TlsError::ReadFile(path.to_owned(), e))
+}
+
+fn read_certs(path: &str) -> Result<Vec<Certificate>, TlsError> {
+    let certs: Vec<Certificate> = read_pem(path)?
+        .into_iter()
+        .filter_map(|item| match item {
+            Item::X509Certificate(c) => Some(Certificate(c)),
+            _ => None,
+        })
+        .collect();
+    if certs.is_empty() {
+        return Err(TlsError::NoCertificate(path.to_owned()));
+    }
+    Ok(certs)
+}
+
+fn read_private_key(path: &str) -> Result<PrivateKey, TlsError> {
+    read_pem(path)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(k) | Item::RSAKey(k) | Item::ECKey(k) => Some(PrivateKey(k)),
+            _ => None,
+        })
+        .ok_or_else(|| TlsError::NoPrivateKey(path.to_owned()))
+}
+
+// 由配置构造的TLS客户端，配置不变时可在重连间复用
+// TLS client built from config, reusable across reconnections as long as the config is unchanged
+#[derive(Clone)]
+pub struct TlsConnector {
+    config: SenderTlsConfig,
+    client_config: Arc<ClientConfig>,
+    server_name: ServerName,
+}
+
+impl TlsConnector {
+    pub fn new(config: &SenderTlsConfig) -> Result<Self, TlsError> {
+        if config.ca_file.is_empty() {
+            return Err(TlsError::NoCaFile);
+        }
+        // 证书校验只支持域名，数据节点通常以IP访问，因此必须配置server-name
+        // certificates are verified by DNS names only, while analyzers are usually
+        // connected by IP, so server-name is required
+        if config.server_name.is_empty() {
+            return Err(TlsError::NoServerName);
+        }
+        let server_name = match ServerName::try_from(config.server_name.as_str()) {
+            Ok(name @ ServerName::DnsName(_)) => name,
+            _ => return Err(TlsError::InvalidServerName(config.server_name.clone())),
+        };
+
+        let mut roots = RootCertStore::empty();
+        let ca_certs = read_certs(&config.ca_file)?;
+        let ca_certs: Vec<Vec<u8>> = ca_certs.into_iter().map(|c| c.0).collect();
+        let (added, _) = roots.add_parsable_certificates(&ca_certs);
+        if added == 0 {
+            return Err(TlsError::NoCertificate(config.ca_file.clone()));
+        }
+
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots);
+        let client_config = match (config.cert_file.is_empty(), config.key_file.is_empty()) {
+            (true, true) => builder.with_no_client_auth(),
+            (false, false) => builder.with_single_cert(
+                read_certs(&config.cert_file)?,
+                read_private_key(&config.key_file)?,
+            )?,
+            _ => return Err(TlsError::IncompleteClientCert),
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            client_config: Arc::new(client_config),
+            server_name,
+        })
+    }
+
+    pub fn config(&self) -> &SenderTlsConfig {
+        &self.config
+    }
+}
+
+// 跟随发送配置更新的TLS客户端，无效的配置会被记住，配置变化前不再重复读取证书文件
+// TLS client following the sender config, an invalid config is remembered so that
+// certificate files are not read again until the config changes
+#[derive(Default)]
+pub struct TlsClient {
+    connector: Option<TlsConnector>,
+    invalid_config: Option<SenderTlsConfig>,
+}
+
+impl TlsClient {
+    // 返回TLS客户端是否发生变化，变化时需要重新连接；新配置无效时返回错误
+    // returns whether the TLS client changed, in which case the connection has to be
+    // reestablished; returns an error if the new config is invalid
+    pub fn update(&mut self, config: &SenderTlsConfig) -> Result<bool, TlsError> {
+        if !config.enabled {
+            self.invalid_config = None;
+            return Ok(self.connector.take().is_some());
+        }
+        if self.invalid_config.as_ref() == Some(config) {
+            return Ok(false);
+        }
+        if let Some(connector) = self.connector.as_ref() {
+            if connector.config() == config {
+                return Ok(false);
+            }
+        }
+        self.connector = None;
+        match TlsConnector::new(config) {
+            Ok(connector) => {
+                self.invalid_config = None;
+                self.connector = Some(connector);
+                Ok(true)
+            }
+            Err(e) => {
+                self.invalid_config = Some(config.clone());
+                Err(e)
+            }
+        }
+    }
+
+    // TLS配置无效时为false，此时不能退化为明文发送
+    // false if the TLS config is invalid, data must not be sent in plaintext then
+    pub fn is_valid(&self) -> bool {
+        self.invalid_config.is_none()
+    }
+
+    pub fn connector(&self) -> Option<&TlsConnector> {
+        self.connector.as_ref()
+    }
+}
+
+// 发送到数据节点的连接，明文TCP或TLS
+// connection to analyzers, either plain TCP or TLS
+pub enum DataStream {
+    Tcp(TcpStream),
+    Tls(Box<StreamOwned<ClientConnection, TcpStream>>),
+}
+
+impl DataStream {
+    // 连接并完成TLS握手，timeout同时作为握手时的读写超时
+    // connects and completes the TLS handshake, timeout is also used as the
+    // read and write timeout during the handshake
+    pub fn connect(
+        dst_ip: IpAddr,
+        dst_port: u16,
+        tls: Option<&TlsConnector>,
+        timeout: Duration,
+    ) -> io::Result<Self> {
+        let mut tcp = TcpStream::connect((dst_ip, dst_port))?;
+        tcp.set_write_timeout(Some(timeout))?;
+        let tls = match tls {
+            Some(t) => t,
+            None => return Ok(Self::Tcp(tcp)),
+        };
+
+        tcp.set_read_timeout(Some(timeout))?;
+        let mut conn = ClientConnection::new(tls.client_config.clone(), tls.server_name.clone())
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut tcp)?;
+        }
+        Ok(Self::Tls(Box::new(StreamOwned::new(conn, tcp))))
+    }
+
+    pub fn shutdown(&mut self, how: Shutdown) -> io::Result<()> {
+        match self {
+            Self::Tcp(s) => s.shutdown(how),
+            Self::Tls(s) => {
+                s.conn.send_close_notify();
+                let _ = s.flush();
+                s.sock.shutdown(how)
+            }
+        }
+    }
+}
+
+impl Write for DataStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Tcp(s) => s.write(buf),
+            Self::Tls(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Tcp(s) => s.flush(),
+            Self::Tls(s) => s.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    use std::io::Read;
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::thread;
+
+    use rustls::{server::AllowAnyAuthenticatedClient, ServerConfig, ServerConnection};
+
+    const CERT_PATH_PREFIX: &str = "./resources/test/sender/tls";
+
+    pub(crate) fn path(name: &str) -> String {
+        format!("{}/{}", CERT_PATH_PREFIX, name)
+    }
+
+    pub(crate) fn client_config(mutual: bool) -> SenderTlsConfig {
+        SenderTlsConfig {
+            enabled: true,
+            ca_file: path("ca.pem"),
+            cert_file: if mutual {
+                path("client.pem")
+            } else {
+                "".into()
+            },
+            key_file: if mutual {
+                path("client.key")
+            } else {
+                "".into()
+            },
+            server_name: "server.deepflow.test".into(),
+        }
+    }
+
+    // 启动只接受一个连接的TLS服务端，返回端口和收到的数据
+    // starts a TLS server accepting a single connection, returns the port and received data
+    pub(crate) fn serve(mutual: bool) -> (u16, thread::JoinHandle<io::Result<Vec<u8>>>) {
+        let builder = ServerConfig::builder().with_safe_defaults();
+        let builder = if mutual {
+            let mut roots = RootCertStore::empty();
+            for c in read_certs(&path("ca.pem")).unwrap() {
+                roots.add(&c).unwrap();
+            }
+            builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots))
+        } else {
+            builder.with_no_client_auth()
+        };
+        let config = Arc::new(
+            builder
+                .with_single_cert(
+                    read_certs(&path("server.pem")).unwrap(),
+                    read_private_key(&path("server.key")).unwrap(),
+                )
+                .unwrap(),
+        );
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (tcp, _) = listener.accept()?;
+            tcp.set_read_timeout(Some(Duration::from_secs(5)))?;
+            let conn = ServerConnection::new(config)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            let mut stream = StreamOwned::new(conn, tcp);
+            let mut data = vec![];
+            stream.read_to_end(&mut data)?;
+            Ok(data)
+        });
+        (port, handle)
+    }
+
+    fn send(port: u16, connector: &TlsConnector, data: &[u8]) -> io::Result<()> {
+        let mut stream = DataStream::connect(
+            IpAddr::V4(Ipv4Addr::LOCALHOST),
+            port,
+            Some(connector),
+            Duration::from_secs(5),
+        )?;
+        assert!(matches!(stream, DataStream::Tls(_)));
+        stream.write_all(data)?;
+        stream.shutdown(Shutdown::Both)
+    }
+
+    #[test]
+    fn tls() {
+        let connector = TlsConnector::new(&client_config(false)).unwrap();
+        let (port, server) = serve(false);
+        send(port, &connector, b"hello analyzer").unwrap();
+        assert_eq!(server.join().unwrap().unwrap(), b"hello analyzer");
+    }
+
+    #[test]
+    fn mutual_tls() {
+        let connector = TlsConnector::new(&client_config(true)).unwrap();
+        let (port, server) = serve(true);
+        send(port, &connector, b"hello analyzer").unwrap();
+        assert_eq!(server.join().unwrap().unwrap(), b"hello analyzer");
+
+        // 服务端要求客户端证书时，不带证书的连接被拒绝
+        // connections without a client certificate are rejected if the server requires one
+        let connector = TlsConnector::new(&client_config(false)).unwrap();
+        let (port, server) = serve(true);
+        let _ = send(port, &connector, b"hello analyzer");
+        assert!(server.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn server_name_mismatch() {
+        let mut config = client_config(false);
+        config.server_name = "other.deepflow.test".into();
+        let connector = TlsConnector::new(&config).unwrap();
+        let (port, server) = serve(false);
+        assert!(send(port, &connector, b"hello analyzer").is_err());
+        assert!(server.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn invalid_config() {
+        let mut config = client_config(false);
+        config.server_name = "".into();
+        assert!(matches!(
+            TlsConnector::new(&config),
+            Err(TlsError::NoServerName)
+        ));
+        config.server_name = "10.1.2.3".into();
+        assert!(matches!(
+            TlsConnector::new(&config),
+            Err(TlsError::InvalidServerName(_))
+        ));
+
+        let mut config = client_config(true);
+        config.key_file = "".into();
+        assert!(matches!(
+            TlsConnector::new(&config),
+            Err(TlsError::IncompleteClientCert)
+        ));
+        config.key_file = path("client.pem");
+        assert!(matches!(
+            TlsConnector::new(&config),
+            Err(TlsError::NoPrivateKey(_))
+        ));
+
+        let mut config = client_config(false);
+        config.ca_file = path("missing.pem");
+        assert!(matches!(
+            TlsConnector::new(&config),
+            Err(TlsError::ReadFile(..))
+        ));
+    }
+
+    #[test]
+    fn tls_client_update() {
+        let mut client = TlsClient::default();
+        assert!(!client.update(&SenderTlsConfig::default()).unwrap());
+        assert!(client.connector().is_none());
+
+        // 无效配置只报告一次错误，配置不变时不再重建
+        // an invalid config is reported once and not rebuilt while unchanged
+        let mut invalid = client_config(false);
+        invalid.ca_file = path("missing.pem");
+        assert!(matches!(
+            client.update(&invalid),
+            Err(TlsError::ReadFile(..))
+        ));
+        assert!(!client.update(&invalid).unwrap());
+        assert!(!client.is_valid());
+        assert!(client.connector().is_none());
+
+        let config = client_config(false);
+        assert!(client.update(&config).unwrap());
+        assert!(client.is_valid());
+        assert!(client.connector().is_some());
+        assert!(!client.update(&config).unwrap());
+
+        assert!(client.update(&SenderTlsConfig::default()).unwrap());
+        assert!(client.is_valid());
+        assert!(client.connector().is_none());
+    }
+}
@@ -14,22 +14,26 @@
  * limitations under the License.
  */
 
-use std::fs::{create_dir_all, rename, File, OpenOptions};
-use std::io::{BufWriter, ErrorKind, Write};
-use std::net::{IpAddr, Shutdown, TcpStream};
+use std::io::{ErrorKind, Write};
+use std::net::{IpAddr, Shutdown};
 use std::path::Path;
 use std::sync::{
     atomic::{AtomicBool, AtomicU64, Ordering},
     Arc, Weak,
 };
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use arc_swap::access::Access;
 use log::{debug, error, info, warn};
 use thread::JoinHandle;
 
-use super::{SendItem, SendMessageType, PRE_FILE_SUFFIX};
+use super::{
+    rotating_file::{RotateOptions, RotatingFile},
+    spill_queue::SpillQueue,
+    tls::{DataStream, TlsClient},
+    SendItem, SendMessageType,
+};
 use crate::config::handler::SenderAccess;
 use crate::exception::ExceptionHandler;
 use crate::proto::trident::{Exception, SocketType};
@@ -44,6 +48,9 @@ pub struct SenderCounter {
     pub tx: AtomicU64,
     pub tx_bytes: AtomicU64,
     pub dropped: AtomicU64,
+    pub spilled_bytes: AtomicU64,
+    pub replayed_bytes: AtomicU64,
+    pub spill_dropped_bytes: AtomicU64,
 }
 
 impl RefCountable for SenderCounter {
@@ -69,6 +76,21 @@ impl RefCountable for SenderCounter {
                 CounterType::Counted,
                 CounterValue::Unsigned(self.dropped.swap(0, Ordering::Relaxed)),
             ),
+            (
+                "spilled-bytes",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.spilled_bytes.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "replayed-bytes",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.replayed_bytes.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "spill-dropped-bytes",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.spill_dropped_bytes.swap(0, Ordering::Relaxed)),
+            ),
         ]
     }
 }
@@ -241,7 +263,8 @@ pub struct UniformSender {
     input: Arc<Receiver<SendItem>>,
     counter: Arc<SenderCounter>,
 
-    tcp_stream: Option<TcpStream>,
+    tcp_stream: Option<DataStream>,
+    tls: TlsClient,
     encoder: Encoder,
     last_flush: Duration,
 
@@ -254,10 +277,14 @@ pub struct UniformSender {
     stats: Arc<Collector>,
     stats_registered: bool,
     exception_handler: ExceptionHandler,
-    buf_writer: Option<BufWriter<File>>,
-    file_path: String,
-    pre_file_path: String,
-    written_size: u64,
+    file: Option<RotatingFile>,
+    // 服务端不可达时暂存发送失败的数据，重连后按顺序回放
+    // frames failed to send are spilled to disk while the server is unreachable,
+    // and replayed in order after reconnecting
+    spill: Option<SpillQueue>,
+    // 上次检查暂存队列时的大小和目录，变化时才重新打开
+    // spill size and dir when the spill queue was last checked, it is reopened only if they change
+    spill_config: Option<(u32, String)>,
 }
 
 impl UniformSender {
@@ -284,15 +311,15 @@ impl UniformSender {
             dst_port: config.load().dest_port,
             config,
             tcp_stream: None,
+            tls: TlsClient::default(),
             reconnect: false,
             running,
             stats,
             stats_registered: false,
             exception_handler,
-            buf_writer: None,
-            file_path: String::new(),
-            pre_file_path: String::new(),
-            written_size: 0,
+            file: None,
+            spill: None,
+            spill_config: None,
         }
     }
 
@@ -329,38 +356,83 @@ impl UniformSender {
     }
 
     fn send_buffer(&mut self, buffer: &[u8]) {
-        if self.reconnect || self.tcp_stream.is_none() {
-            if let Some(t) = self.tcp_stream.take() {
-                if let Err(e) = t.shutdown(Shutdown::Both) {
-                    debug!("{} sender tcp stream shutdown failed {}", self.name, e);
+        self.update_spill_queue();
+        if self.connect() && self.replay_spilled() && self.write_buffer(buffer) {
+            return;
+        }
+        self.spill_buffer(buffer);
+    }
+
+    // 首次失败时才打印日志，避免服务端不可达时刷屏
+    // only the first failure in a stats interval is logged
+    fn is_first_failure(&self) -> bool {
+        self.counter.dropped.load(Ordering::Relaxed) == 0
+            && self.counter.spilled_bytes.load(Ordering::Relaxed) == 0
+    }
+
+    // 按配置更新TLS客户端，TLS配置无效时返回false，不会退化为明文发送
+    // updates the TLS client as configured, returns false if the TLS config is invalid,
+    // in which case data is never sent in plaintext
+    fn update_tls(&mut self) -> bool {
+        match self.tls.update(&self.config.load().tls) {
+            Ok(true) => {
+                if self.tls.connector().is_some() {
+                    info!("{} sender tls enabled", self.name);
+                } else {
+                    info!("{} sender tls disabled", self.name);
                 }
+                self.reconnect = true;
             }
-            self.tcp_stream = TcpStream::connect((self.dst_ip, self.dst_port)).ok();
-            if let Some(tcp_stream) = self.tcp_stream.as_mut() {
-                if let Err(e) =
-                    tcp_stream.set_write_timeout(Some(Duration::from_secs(Self::TCP_WRITE_TIMEOUT)))
-                {
-                    debug!(
-                        "{} sender tcp stream set write timeout failed {}",
-                        self.name, e
-                    );
-                    self.tcp_stream.take();
-                    return;
-                }
+            Ok(false) => (),
+            Err(e) => {
+                error!("{} sender invalid tls config: {}", self.name, e);
+                self.reconnect = true;
+            }
+        }
+        if !self.tls.is_valid() {
+            self.exception_handler.set(Exception::AnalyzerSocketError);
+            return false;
+        }
+        true
+    }
+
+    fn connect(&mut self) -> bool {
+        if !self.update_tls() {
+            return false;
+        }
+        if !self.reconnect && self.tcp_stream.is_some() {
+            return true;
+        }
+        if let Some(mut t) = self.tcp_stream.take() {
+            if let Err(e) = t.shutdown(Shutdown::Both) {
+                debug!("{} sender tcp stream shutdown failed {}", self.name, e);
+            }
+        }
+        match DataStream::connect(
+            self.dst_ip,
+            self.dst_port,
+            self.tls.connector(),
+            Duration::from_secs(Self::TCP_WRITE_TIMEOUT),
+        ) {
+            Ok(stream) => {
+                self.tcp_stream = Some(stream);
                 self.reconnect = false;
-            } else {
-                if self.counter.dropped.load(Ordering::Relaxed) == 0 {
+                true
+            }
+            Err(e) => {
+                if self.is_first_failure() {
                     self.exception_handler.set(Exception::AnalyzerSocketError);
                     error!(
-                        "{} sender tcp connection to {}:{} failed",
-                        self.name, self.dst_ip, self.dst_port,
+                        "{} sender tcp connection to {}:{} failed: {}",
+                        self.name, self.dst_ip, self.dst_port, e
                     );
                 }
-                self.counter.dropped.fetch_add(1, Ordering::Relaxed);
-                return;
+                false
             }
         }
+    }
 
+    fn write_buffer(&mut self, buffer: &[u8]) -> bool {
         let tcp_stream = self.tcp_stream.as_mut().unwrap();
 
         let mut write_offset = 0usize;
@@ -374,7 +446,7 @@ impl UniformSender {
                         self.counter
                             .tx_bytes
                             .fetch_add(buffer.len() as u64, Ordering::Relaxed);
-                        break;
+                        return true;
                     }
                 }
                 Err(e) if e.kind() == ErrorKind::WouldBlock => {
@@ -382,21 +454,137 @@ impl UniformSender {
                     continue;
                 }
                 Err(e) => {
-                    if self.counter.dropped.load(Ordering::Relaxed) == 0 {
+                    if self.is_first_failure() {
                         self.exception_handler.set(Exception::AnalyzerSocketError);
                         error!(
                             "{} sender tcp stream write data to {}:{} failed: {}",
                             self.name, self.dst_ip, self.dst_port, e
                         );
                     }
-                    self.counter.dropped.fetch_add(1, Ordering::Relaxed);
                     self.tcp_stream.take();
-                    break;
+                    return false;
                 }
             };
         }
     }
 
+    // 按配置打开或关闭暂存队列，文件按消息类型和发送器id区分
+    // opens or closes the spill queue as configured, files are named by message type and sender id
+    fn update_spill_queue(&mut self) {
+        let config = self.config.load();
+        if let Some((size, dir)) = self.spill_config.as_ref() {
+            if *size == config.collector_sender_spill_size
+                && dir == &config.collector_sender_spill_dir
+            {
+                return;
+            }
+        }
+        self.spill_config = Some((
+            config.collector_sender_spill_size,
+            config.collector_sender_spill_dir.clone(),
+        ));
+
+        let capacity = (config.collector_sender_spill_size as u64) << 20;
+        if capacity == 0 {
+            self.spill.take();
+            return;
+        }
+        let path = Path::new(&config.collector_sender_spill_dir).join(format!(
+            "{}-{}.spill",
+            self.encoder.header.msg_type.to_string().replace(' ', "_"),
+            self.id
+        ));
+        if let Some(spill) = self.spill.as_ref() {
+            if spill.capacity() == capacity && spill.path() == path {
+                return;
+            }
+        }
+        self.spill.take();
+        match SpillQueue::open(&path, capacity) {
+            Ok(spill) => self.spill = Some(spill),
+            Err(e) => warn!(
+                "{} sender open spill queue {} failed: {}",
+                self.name,
+                path.display(),
+                e
+            ),
+        }
+    }
+
+    fn spill_buffer(&mut self, buffer: &[u8]) {
+        let spill = match self.spill.as_mut() {
+            Some(s) => s,
+            None => {
+                self.counter.dropped.fetch_add(1, Ordering::Relaxed);
+                return;
+            }
+        };
+        match spill.push(buffer) {
+            Ok(_) => {
+                self.counter
+                    .spilled_bytes
+                    .fetch_add(buffer.len() as u64, Ordering::Relaxed);
+            }
+            Err(e) => {
+                debug!("{} sender spill frame failed: {}", self.name, e);
+                self.counter.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.counter
+            .spill_dropped_bytes
+            .fetch_add(spill.take_dropped_bytes(), Ordering::Relaxed);
+    }
+
+    // 连接可用时按顺序回放暂存的数据，全部发送成功后才返回true
+    // replays spilled frames in order over the connection, returns true only if all are sent
+    fn replay_spilled(&mut self) -> bool {
+        let mut spill = match self.spill.take() {
+            Some(s) if !s.is_empty() => s,
+            s => {
+                self.spill = s;
+                return true;
+            }
+        };
+        let mut replayed = 0;
+        let succeeded = loop {
+            let frame = match spill.peek() {
+                Ok(Some(f)) => f,
+                Ok(None) => break true,
+                Err(e) => {
+                    warn!("{} sender read spill queue failed: {}", self.name, e);
+                    if e.kind() == ErrorKind::InvalidData {
+                        continue;
+                    }
+                    break true;
+                }
+            };
+            if !self.write_buffer(&frame) {
+                break false;
+            }
+            replayed += frame.len() as u64;
+            if let Err(e) = spill.pop() {
+                warn!("{} sender pop spill queue failed: {}", self.name, e);
+                break true;
+            }
+        };
+        if replayed > 0 {
+            info!(
+                "{} sender replayed {} bytes spilled to {}",
+                self.name,
+                replayed,
+                spill.path().display()
+            );
+        }
+        self.counter
+            .replayed_bytes
+            .fetch_add(replayed, Ordering::Relaxed);
+        self.counter
+            .spill_dropped_bytes
+            .fetch_add(spill.take_dropped_bytes(), Ordering::Relaxed);
+        self.spill = Some(spill);
+        succeeded
+    }
+
     fn check_or_register_counterable(&mut self, message_type: SendMessageType) {
         if self.stats_registered {
             return;
@@ -434,8 +622,10 @@ impl UniformSender {
                                 "{} sender send item {} failed {}",
                                 self.name, message_type, e
                             );
-                            // reopen write file and overwritten
-                            let _ = self.buf_writer.take();
+                            // reopen write file on next write
+                            if let Some(file) = self.file.as_mut() {
+                                file.reset();
+                            }
                         }
                         self.counter.dropped.fetch_add(1, Ordering::Relaxed);
                     }
@@ -445,6 +635,7 @@ impl UniformSender {
                     _ => {
                         self.update_dst_ip_and_port();
                         self.flush_encoder();
+                        self.retry_spilled();
                     }
                 },
                 Err(Error::Terminated(_, _)) => {
@@ -459,8 +650,8 @@ impl UniformSender {
     }
 
     pub fn flush_writer(&mut self) {
-        if let Some(buf_writer) = self.buf_writer.as_mut() {
-            _ = buf_writer.flush();
+        if let Some(file) = self.file.as_mut() {
+            _ = file.flush();
         }
     }
 
@@ -469,44 +660,39 @@ impl UniformSender {
         send_item: SendItem,
         kv_string: &mut String,
     ) -> std::io::Result<()> {
+        kv_string.truncate(0);
         send_item.to_kv_string(kv_string);
         if kv_string.is_empty() {
             return Ok(());
         }
-        if self.file_path.is_empty() {
-            create_dir_all(&self.config.load().standalone_data_file_dir)?;
-            self.file_path = Path::new(&self.config.load().standalone_data_file_dir)
-                .join(send_item.file_name())
-                .to_str()
-                .unwrap()
-                .into();
-            self.pre_file_path = format!("{}{}", &self.file_path, PRE_FILE_SUFFIX);
-        }
 
-        if self.buf_writer.is_none() {
+        let config = self.config.load();
+        let dir = Path::new(&config.standalone_data_file_dir);
+        if self.file.as_ref().filter(|f| f.dir() == dir).is_none() {
             self.check_or_register_counterable(send_item.message_type());
-            let f = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .open(&self.file_path)?;
-            self.buf_writer = Some(BufWriter::new(f));
+            self.file = Some(RotatingFile::new(dir, send_item.file_name()));
         }
-
-        self.buf_writer
+        let options = RotateOptions {
+            max_file_size: (config.standalone_data_file_size as u64) << 20,
+            interval: config.standalone_data_file_rotate_interval,
+            max_files: config.standalone_data_file_retention_count,
+            quota: (config.standalone_data_file_quota as u64) << 20,
+        };
+        self.file
             .as_mut()
             .unwrap()
-            .write_all(kv_string.as_bytes())?;
-        self.written_size += kv_string.len() as u64;
-        kv_string.truncate(0);
+            .write(kv_string.as_bytes(), SystemTime::now(), &options)
+    }
 
-        if self.written_size > (self.config.load().standalone_data_file_size as u64) << 20 {
-            self.buf_writer.as_mut().unwrap().flush()?;
-            self.buf_writer.take();
-            rename(&self.file_path, &self.pre_file_path)?;
-            self.written_size = 0;
+    // 没有新数据时也尝试回放暂存的数据
+    // try to replay spilled frames even if there is no new data
+    fn retry_spilled(&mut self) {
+        if self.spill.as_ref().map(|s| s.is_empty()).unwrap_or(true) {
+            return;
+        }
+        if self.connect() {
+            self.replay_spilled();
         }
-
-        Ok(())
     }
 
     pub fn handle_target_server(&mut self, send_item: SendItem) -> std::io::Result<()> {
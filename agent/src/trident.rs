@@ -48,6 +48,7 @@ use crate::pcap::WorkerManager;
 use crate::platform::ApiWatcher;
 #[cfg(target_os = "linux")]
 use crate::utils::cgroups::Cgroups;
+use crate::xflow_collector::XflowCollector;
 use crate::{
     collector::Collector,
     collector::{
@@ -96,7 +97,7 @@ use public::{
 };
 
 const MINUTE: Duration = Duration::from_secs(60);
-const COMMON_DELAY: u32 = 5;
+pub(crate) const COMMON_DELAY: u32 = 5;
 
 #[derive(Default)]
 pub struct ChangedConfig {
@@ -741,6 +742,7 @@ pub struct Components {
     pub pcap_manager: WorkerManager,
     #[cfg(target_os = "linux")]
     pub ebpf_collector: Option<Box<EbpfCollector>>,
+    pub xflow_collector: Option<XflowCollector>,
     pub running: AtomicBool,
     pub stats_collector: Arc<stats::Collector>,
     #[cfg(target_os = "linux")]
@@ -756,6 +758,10 @@ pub struct Components {
     pub npb_bps_limit: Arc<LeakyBucket>,
     pub handler_builders: Vec<Arc<Mutex<Vec<PacketHandlerBuilder>>>>,
     pub compressed_otel_uniform_sender: UniformSenderThread,
+    pub otel_metrics_uniform_sender: UniformSenderThread,
+    pub compressed_otel_metrics_uniform_sender: UniformSenderThread,
+    pub otel_logs_uniform_sender: UniformSenderThread,
+    pub compressed_otel_logs_uniform_sender: UniformSenderThread,
     max_memory: u64,
     tap_mode: TapMode,
     agent_mode: RunningMode,
@@ -818,9 +824,16 @@ impl Components {
         if let Some(ebpf_collector) = self.ebpf_collector.as_mut() {
             ebpf_collector.start();
         }
+        if let Some(xflow_collector) = self.xflow_collector.as_ref() {
+            xflow_collector.start();
+        }
         if matches!(self.agent_mode, RunningMode::Managed) {
             self.otel_uniform_sender.start();
             self.compressed_otel_uniform_sender.start();
+            self.otel_metrics_uniform_sender.start();
+            self.compressed_otel_metrics_uniform_sender.start();
+            self.otel_logs_uniform_sender.start();
+            self.compressed_otel_logs_uniform_sender.start();
             self.prometheus_uniform_sender.start();
             self.telegraf_uniform_sender.start();
             if self.config.metric_server.enabled {
@@ -893,8 +906,11 @@ impl Components {
         // applicable to fastpath, so the number of queues is 1
         // =================================================================================
         // 目前仅支持local-mode + ebpf-collector，ebpf-collector不适用fastpath, 所以队列数为1
+        // 每个sFlow/NetFlow监听端口额外占用一个队列
+        // each sFlow/NetFlow listening port takes an extra queue
+        let xflow_ports = XflowCollector::listen_ports(&yaml_config.xflow_collector);
         let (policy_setter, policy_getter) = Policy::new(
-            1.max(yaml_config.src_interfaces.len()),
+            1.max(yaml_config.src_interfaces.len()) + xflow_ports.len(),
             yaml_config.first_path_level as usize,
             yaml_config.fast_path_map_size,
             false,
@@ -1292,6 +1308,7 @@ impl Components {
                     vxlan_flags: yaml_config.vxlan_flags,
                     controller_port: static_config.controller_port,
                     controller_tls_port: static_config.controller_tls_port,
+                    ip_fragment: yaml_config.ip_fragment.clone(),
                     snap_len: config_handler
                         .candidate_config
                         .dispatcher
@@ -1360,6 +1377,55 @@ impl Components {
             collectors.push(collector);
         }
 
+        // sFlow/NetFlow/IPFIX记录转换为TaggedFlow后使用独立的collector
+        // flows converted from sFlow/NetFlow/IPFIX records use a dedicated collector
+        let xflow_collector = if !xflow_ports.is_empty() {
+            let index = collectors.len();
+            let (flow_sender, flow_receiver, counter) = queue::bounded_with_debug(
+                yaml_config.flow_queue_size,
+                "1-tagged-flow-to-quadruple-generator",
+                &queue_debugger,
+            );
+            stats_collector.register_countable(
+                "queue",
+                Countable::Owned(Box::new(counter)),
+                vec![
+                    StatsOption::Tag("module", "1-tagged-flow-to-quadruple-generator".to_string()),
+                    StatsOption::Tag("index", index.to_string()),
+                ],
+            );
+            let xflow_collector = XflowCollector::new(
+                xflow_ports,
+                1.max(yaml_config.src_interfaces.len()),
+                flow_sender,
+                tap_typer.clone(),
+                (yaml_config.default_tap_type as u16)
+                    .try_into()
+                    .unwrap_or(TapType::Cloud),
+                policy_getter,
+                config_handler.flow(),
+                synchronizer.ntp_diff(),
+                yaml_config.flow_queue_size,
+                &queue_debugger,
+                &stats_collector,
+            );
+            let collector = Self::new_collector(
+                index,
+                stats_collector.clone(),
+                flow_receiver,
+                l4_flow_aggr_sender.clone(),
+                metrics_sender.clone(),
+                MetricsType::SECOND | MetricsType::MINUTE,
+                config_handler,
+                &queue_debugger,
+                &synchronizer,
+            );
+            collectors.push(collector);
+            Some(xflow_collector)
+        } else {
+            None
+        };
+
         #[cfg(target_os = "linux")]
         let ebpf_collector = EbpfCollector::new(
             synchronizer.ntp_diff(),
@@ -1382,110 +1448,94 @@ impl Components {
         #[cfg(target_os = "linux")]
         let cgroups_controller: Arc<Cgroups> = Arc::new(Cgroups { cgroup: None });
 
-        let sender_id = 3;
-        let otel_queue_name = "otel-to-sender";
-        let (otel_sender, otel_receiver, counter) = queue::bounded_with_debug(
+        let (otel_sender, otel_uniform_sender) = Self::new_uniform_sender(
+            3,
+            "otel-to-sender",
             yaml_config.external_metrics_sender_queue_size,
-            otel_queue_name,
+            config_handler,
             &queue_debugger,
+            &stats_collector,
+            &exception_handler,
         );
-        stats_collector.register_countable(
-            "queue",
-            Countable::Owned(Box::new(counter)),
-            vec![
-                StatsOption::Tag("module", otel_queue_name.to_string()),
-                StatsOption::Tag("index", sender_id.to_string()),
-            ],
-        );
-        let otel_uniform_sender = UniformSenderThread::new(
-            sender_id,
-            otel_queue_name,
-            Arc::new(otel_receiver),
-            config_handler.sender(),
-            stats_collector.clone(),
-            exception_handler.clone(),
-        );
-
-        let sender_id = 4;
-        let prometheus_queue_name = "prometheus-to-sender";
-        let (prometheus_sender, prometheus_receiver, counter) = queue::bounded_with_debug(
+        let (prometheus_sender, prometheus_uniform_sender) = Self::new_uniform_sender(
+            4,
+            "prometheus-to-sender",
             yaml_config.external_metrics_sender_queue_size,
-            prometheus_queue_name,
+            config_handler,
             &queue_debugger,
+            &stats_collector,
+            &exception_handler,
         );
-        stats_collector.register_countable(
-            "queue",
-            Countable::Owned(Box::new(counter)),
-            vec![
-                StatsOption::Tag("module", prometheus_queue_name.to_string()),
-                StatsOption::Tag("index", sender_id.to_string()),
-            ],
-        );
-        let prometheus_uniform_sender = UniformSenderThread::new(
-            sender_id,
-            prometheus_queue_name,
-            Arc::new(prometheus_receiver),
-            config_handler.sender(),
-            stats_collector.clone(),
-            exception_handler.clone(),
-        );
-
-        let sender_id = 5;
-        let telegraf_queue_name = "telegraf-to-sender";
-        let (telegraf_sender, telegraf_receiver, counter) = queue::bounded_with_debug(
+        let (telegraf_sender, telegraf_uniform_sender) = Self::new_uniform_sender(
+            5,
+            "telegraf-to-sender",
             yaml_config.external_metrics_sender_queue_size,
-            telegraf_queue_name,
+            config_handler,
             &queue_debugger,
+            &stats_collector,
+            &exception_handler,
         );
-        stats_collector.register_countable(
-            "queue",
-            Countable::Owned(Box::new(counter)),
-            vec![
-                StatsOption::Tag("module", telegraf_queue_name.to_string()),
-                StatsOption::Tag("index", sender_id.to_string()),
-            ],
-        );
-        let telegraf_uniform_sender = UniformSenderThread::new(
-            sender_id,
-            telegraf_queue_name,
-            Arc::new(telegraf_receiver),
-            config_handler.sender(),
-            stats_collector.clone(),
-            exception_handler.clone(),
-        );
-
-        let sender_id = 6;
-        let compressed_otel_queue_name = "compressed-otel-to-sender";
-        let (compressed_otel_sender, compressed_otel_receiver, counter) = queue::bounded_with_debug(
+        let (compressed_otel_sender, compressed_otel_uniform_sender) = Self::new_uniform_sender(
+            6,
+            "compressed-otel-to-sender",
             yaml_config.external_metrics_sender_queue_size,
-            compressed_otel_queue_name,
+            config_handler,
             &queue_debugger,
+            &stats_collector,
+            &exception_handler,
         );
-        stats_collector.register_countable(
-            "queue",
-            Countable::Owned(Box::new(counter)),
-            vec![
-                StatsOption::Tag("module", compressed_otel_queue_name.to_string()),
-                StatsOption::Tag("index", sender_id.to_string()),
-            ],
+        let (otel_metrics_sender, otel_metrics_uniform_sender) = Self::new_uniform_sender(
+            7,
+            "otel-metrics-to-sender",
+            yaml_config.external_otel_metrics_sender_queue_size,
+            config_handler,
+            &queue_debugger,
+            &stats_collector,
+            &exception_handler,
         );
-        let compressed_otel_uniform_sender = UniformSenderThread::new(
-            sender_id,
-            compressed_otel_queue_name,
-            Arc::new(compressed_otel_receiver),
-            config_handler.sender(),
-            stats_collector.clone(),
-            exception_handler.clone(),
+        let (compressed_otel_metrics_sender, compressed_otel_metrics_uniform_sender) =
+            Self::new_uniform_sender(
+                8,
+                "compressed-otel-metrics-to-sender",
+                yaml_config.external_otel_metrics_sender_queue_size,
+                config_handler,
+                &queue_debugger,
+                &stats_collector,
+                &exception_handler,
+            );
+        let (otel_logs_sender, otel_logs_uniform_sender) = Self::new_uniform_sender(
+            9,
+            "otel-logs-to-sender",
+            yaml_config.external_otel_logs_sender_queue_size,
+            config_handler,
+            &queue_debugger,
+            &stats_collector,
+            &exception_handler,
         );
+        let (compressed_otel_logs_sender, compressed_otel_logs_uniform_sender) =
+            Self::new_uniform_sender(
+                10,
+                "compressed-otel-logs-to-sender",
+                yaml_config.external_otel_logs_sender_queue_size,
+                config_handler,
+                &queue_debugger,
+                &stats_collector,
+                &exception_handler,
+            );
 
         let (external_metrics_server, external_metrics_counter) = MetricServer::new(
             otel_sender,
             compressed_otel_sender,
+            otel_metrics_sender,
+            compressed_otel_metrics_sender,
+            otel_logs_sender,
+            compressed_otel_logs_sender,
             prometheus_sender,
             telegraf_sender,
             candidate_config.metric_server.port,
             exception_handler.clone(),
             candidate_config.metric_server.compressed,
+            candidate_config.metric_server.otel_metrics_logs_enabled,
         );
 
         stats_collector.register_countable(
@@ -1529,6 +1579,7 @@ impl Components {
             log_parsers,
             #[cfg(target_os = "linux")]
             ebpf_collector,
+            xflow_collector,
             stats_collector,
             running: AtomicBool::new(false),
             #[cfg(target_os = "linux")]
@@ -1546,10 +1597,44 @@ impl Components {
             npb_bps_limit,
             handler_builders,
             compressed_otel_uniform_sender,
+            otel_metrics_uniform_sender,
+            compressed_otel_metrics_uniform_sender,
+            otel_logs_uniform_sender,
+            compressed_otel_logs_uniform_sender,
             agent_mode,
         })
     }
 
+    fn new_uniform_sender(
+        id: usize,
+        queue_name: &'static str,
+        queue_size: usize,
+        config_handler: &ConfigHandler,
+        queue_debugger: &QueueDebugger,
+        stats_collector: &Arc<stats::Collector>,
+        exception_handler: &ExceptionHandler,
+    ) -> (queue::DebugSender<SendItem>, UniformSenderThread) {
+        let (sender, receiver, counter) =
+            queue::bounded_with_debug(queue_size, queue_name, queue_debugger);
+        stats_collector.register_countable(
+            "queue",
+            Countable::Owned(Box::new(counter)),
+            vec![
+                StatsOption::Tag("module", queue_name.to_string()),
+                StatsOption::Tag("index", id.to_string()),
+            ],
+        );
+        let uniform_sender = UniformSenderThread::new(
+            id,
+            queue_name,
+            Arc::new(receiver),
+            config_handler.sender(),
+            stats_collector.clone(),
+            exception_handler.clone(),
+        );
+        (sender, uniform_sender)
+    }
+
     fn new_collector(
         id: usize,
         stats_collector: Arc<stats::Collector>,
@@ -1697,6 +1782,9 @@ impl Components {
         for d in self.dispatchers.iter_mut() {
             d.stop();
         }
+        if let Some(xflow_collector) = self.xflow_collector.as_ref() {
+            xflow_collector.stop();
+        }
         self.platform_synchronizer.stop();
 
         #[cfg(target_os = "linux")]
@@ -1733,6 +1821,10 @@ impl Components {
         self.external_metrics_server.stop();
         self.otel_uniform_sender.stop();
         self.compressed_otel_uniform_sender.stop();
+        self.otel_metrics_uniform_sender.stop();
+        self.compressed_otel_metrics_uniform_sender.stop();
+        self.otel_logs_uniform_sender.stop();
+        self.compressed_otel_logs_uniform_sender.stop();
         self.prometheus_uniform_sender.stop();
         self.telegraf_uniform_sender.stop();
         self.packet_sequence_uniform_sender.stop(); // Enterprise Edition Feature: packet-sequence
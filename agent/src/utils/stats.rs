@@ -83,6 +83,32 @@ impl Batch {
         pb_stats.encode(buf).map(|_| pb_stats.encoded_len())
     }
 
+    pub fn to_kv_string(&self, dst: &mut String) {
+        let s = self.to_stats();
+        let tags: serde_json::Map<String, serde_json::Value> = s
+            .tag_names
+            .into_iter()
+            .zip(s.tag_values.into_iter().map(serde_json::Value::from))
+            .collect();
+        let metrics: serde_json::Map<String, serde_json::Value> = s
+            .metrics_float_names
+            .into_iter()
+            .zip(
+                s.metrics_float_values
+                    .into_iter()
+                    .map(serde_json::Value::from),
+            )
+            .collect();
+        let json = serde_json::json!({
+            "name": s.name,
+            "timestamp": s.timestamp,
+            "tags": tags,
+            "metrics": metrics,
+        });
+        dst.push_str(&json.to_string());
+        dst.push('\n');
+    }
+
     fn to_stats(&self) -> stats::Stats {
         let mut tag_names = vec![];
         let mut tag_values = vec![];
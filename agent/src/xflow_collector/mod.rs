@@ -0,0 +1,152 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+pub mod netflow;
+pub mod sflow;
+mod xflow_collector;
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use public::enums::{EthernetType, IpProtocol, TcpFlags};
+use public::utils::net::MacAddr;
+
+pub use xflow_collector::XflowCollector;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Error {
+    #[error("datagram truncated")]
+    Truncated,
+    #[error("unsupported version {0}")]
+    UnsupportedVersion(u32),
+    #[error("invalid datagram: {0}")]
+    InvalidDatagram(&'static str),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+// sFlow采样或NetFlow/IPFIX流记录解析后的统一格式，由XflowCollector转换为TaggedFlow
+// unified format of sFlow samples and NetFlow/IPFIX records, converted to TaggedFlow by XflowCollector
+#[derive(Debug, Clone, PartialEq)]
+pub struct XflowRecord {
+    // 设备地址，sFlow为agent address，NetFlow为报文的源地址
+    // sFlow agent address, or source address of the NetFlow datagram
+    pub exporter: IpAddr,
+    pub input_if: u32,
+
+    pub mac_src: MacAddr,
+    pub mac_dst: MacAddr,
+    pub vlan: u16,
+    pub eth_type: EthernetType,
+    pub ip_src: IpAddr,
+    pub ip_dst: IpAddr,
+    pub port_src: u16,
+    pub port_dst: u16,
+    pub proto: IpProtocol,
+    pub tcp_flags: TcpFlags,
+
+    // 已按采样率还原的包数和字节数
+    // packet and byte counts scaled by the sampling rate
+    pub packets: u64,
+    pub bytes: u64,
+    // 为零表示设备未携带时间，使用接收时间
+    // zero means the exporter does not carry timestamps, receive time is used instead
+    pub start_time: Duration,
+    pub end_time: Duration,
+}
+
+impl Default for XflowRecord {
+    fn default() -> Self {
+        Self {
+            exporter: Ipv4Addr::UNSPECIFIED.into(),
+            input_if: 0,
+            mac_src: MacAddr::ZERO,
+            mac_dst: MacAddr::ZERO,
+            vlan: 0,
+            eth_type: EthernetType::default(),
+            ip_src: Ipv4Addr::UNSPECIFIED.into(),
+            ip_dst: Ipv4Addr::UNSPECIFIED.into(),
+            port_src: 0,
+            port_dst: 0,
+            proto: IpProtocol::default(),
+            tcp_flags: TcpFlags::empty(),
+            packets: 0,
+            bytes: 0,
+            start_time: Duration::ZERO,
+            end_time: Duration::ZERO,
+        }
+    }
+}
+
+// sFlow(XDR)和NetFlow/IPFIX均使用网络字节序
+// both sFlow (XDR) and NetFlow/IPFIX are in network byte order
+struct Cursor<'a> {
+    buf: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, offset: 0 }
+    }
+
+    fn remaining(&self) -> usize {
+        self.buf.len() - self.offset
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        if self.remaining() < n {
+            return Err(Error::Truncated);
+        }
+        let b = &self.buf[self.offset..self.offset + n];
+        self.offset += n;
+        Ok(b)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let b = &self.buf[self.offset..];
+        self.offset = self.buf.len();
+        b
+    }
+
+    fn skip(&mut self, n: usize) -> Result<()> {
+        self.bytes(n).map(|_| ())
+    }
+
+    fn sub(&mut self, n: usize) -> Result<Cursor<'a>> {
+        self.bytes(n).map(Cursor::new)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        self.bytes(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        self.bytes(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        self.bytes(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn mac(&mut self) -> Result<MacAddr> {
+        let b = self.bytes(6)?;
+        Ok(MacAddr::from([b[0], b[1], b[2], b[3], b[4], b[5]]))
+    }
+}
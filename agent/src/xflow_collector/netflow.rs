@@ -0,0 +1,793 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use super::{Cursor, Error, Result, XflowRecord};
+
+use public::enums::{EthernetType, IpProtocol, TcpFlags};
+use public::utils::net::MacAddr;
+
+const NETFLOW_V5: u16 = 5;
+const NETFLOW_V9: u16 = 9;
+const IPFIX: u16 = 10;
+
+const V5_HEADER_LEN: usize = 24;
+const V5_RECORD_LEN: usize = 48;
+const V9_HEADER_LEN: usize = 20;
+const IPFIX_HEADER_LEN: usize = 16;
+
+const V9_TEMPLATE_FLOWSET: u16 = 0;
+const V9_OPTIONS_TEMPLATE_FLOWSET: u16 = 1;
+const IPFIX_TEMPLATE_SET: u16 = 2;
+const IPFIX_OPTIONS_TEMPLATE_SET: u16 = 3;
+const MIN_DATA_SET_ID: u16 = 256;
+
+const IPFIX_ENTERPRISE_BIT: u16 = 0x8000;
+const IPFIX_VARIABLE_LENGTH: u16 = 65535;
+
+// 模板数量超过上限时清空缓存，避免异常设备耗尽内存
+// the template cache is cleared when exceeding the limit, in case of misbehaving exporters
+const MAX_TEMPLATES: usize = 4096;
+
+// 参考 https://www.iana.org/assignments/ipfix/ipfix.xhtml
+const IN_BYTES: u16 = 1;
+const IN_PKTS: u16 = 2;
+const PROTOCOL: u16 = 4;
+const TCP_FLAGS: u16 = 6;
+const L4_SRC_PORT: u16 = 7;
+const IPV4_SRC_ADDR: u16 = 8;
+const INPUT_SNMP: u16 = 10;
+const L4_DST_PORT: u16 = 11;
+const IPV4_DST_ADDR: u16 = 12;
+const LAST_SWITCHED: u16 = 21;
+const FIRST_SWITCHED: u16 = 22;
+const IPV6_SRC_ADDR: u16 = 27;
+const IPV6_DST_ADDR: u16 = 28;
+const SAMPLING_INTERVAL: u16 = 34;
+const SRC_MAC: u16 = 56;
+const SRC_VLAN: u16 = 58;
+const DST_MAC: u16 = 80;
+const OCTET_DELTA_COUNT: u16 = 85;
+const PACKET_DELTA_COUNT: u16 = 86;
+const FLOW_START_SECONDS: u16 = 150;
+const FLOW_END_SECONDS: u16 = 151;
+const FLOW_START_MILLISECONDS: u16 = 152;
+const FLOW_END_MILLISECONDS: u16 = 153;
+const SYSTEM_INIT_TIME_MILLISECONDS: u16 = 160;
+const DOT1Q_VLAN_ID: u16 = 243;
+const SAMPLING_PACKET_INTERVAL: u16 = 305;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Field {
+    id: u16,
+    // IPFIX变长字段为IPFIX_VARIABLE_LENGTH
+    // IPFIX_VARIABLE_LENGTH for IPFIX variable-length fields
+    length: u16,
+    enterprise: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Template {
+    Data(Vec<Field>),
+    // 选项模板只记录长度用于跳过其数据
+    // only the length of options templates is kept to skip their data
+    Options(Vec<Field>),
+}
+
+impl Template {
+    fn fields(&self) -> &[Field] {
+        match self {
+            Template::Data(f) | Template::Options(f) => f,
+        }
+    }
+
+    // 变长字段最少占用1字节
+    // a variable-length field takes at least 1 byte
+    fn min_record_len(&self) -> usize {
+        self.fields()
+            .iter()
+            .map(|f| {
+                if f.length == IPFIX_VARIABLE_LENGTH {
+                    1
+                } else {
+                    f.length as usize
+                }
+            })
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct TemplateKey {
+    exporter: IpAddr,
+    version: u16,
+    // NetFlow v9为source id，IPFIX为observation domain id
+    // source id for NetFlow v9, observation domain id for IPFIX
+    domain: u32,
+    id: u16,
+}
+
+struct Header {
+    version: u16,
+    domain: u32,
+    // 设备启动时刻，用于换算NetFlow v9的相对时间
+    // the boot time of the exporter, used to convert NetFlow v9 uptime-relative timestamps
+    boot_time: Duration,
+}
+
+// NetFlow v9和IPFIX的模板按设备缓存，因此每个端口使用独立的NetflowDecoder
+// NetFlow v9 and IPFIX templates are cached per exporter, each port uses its own NetflowDecoder
+#[derive(Default)]
+pub struct NetflowDecoder {
+    templates: HashMap<TemplateKey, Template>,
+}
+
+impl NetflowDecoder {
+    pub fn decode(
+        &mut self,
+        exporter: IpAddr,
+        data: &[u8],
+        records: &mut Vec<XflowRecord>,
+    ) -> Result<()> {
+        if data.len() < 2 {
+            return Err(Error::Truncated);
+        }
+        match u16::from_be_bytes([data[0], data[1]]) {
+            NETFLOW_V5 => decode_v5(exporter, data, records),
+            NETFLOW_V9 => self.decode_v9(exporter, data, records),
+            IPFIX => self.decode_ipfix(exporter, data, records),
+            v => Err(Error::UnsupportedVersion(v as u32)),
+        }
+    }
+
+    fn decode_v9(
+        &mut self,
+        exporter: IpAddr,
+        data: &[u8],
+        records: &mut Vec<XflowRecord>,
+    ) -> Result<()> {
+        let mut c = Cursor::new(data);
+        let mut h = c.sub(V9_HEADER_LEN)?;
+        // version, count
+        h.skip(4)?;
+        let sys_uptime = h.u32()?;
+        let unix_secs = h.u32()?;
+        // sequence
+        h.skip(4)?;
+        let header = Header {
+            version: NETFLOW_V9,
+            domain: h.u32()?,
+            boot_time: Duration::from_secs(unix_secs as u64)
+                .saturating_sub(Duration::from_millis(sys_uptime as u64)),
+        };
+        while c.remaining() >= 4 {
+            let id = c.u16()?;
+            let length = c.u16()? as usize;
+            if length < 4 {
+                return Err(Error::InvalidDatagram("flowset length"));
+            }
+            let set = c.sub(length - 4)?;
+            match id {
+                V9_TEMPLATE_FLOWSET => self.parse_templates(exporter, &header, set, false)?,
+                V9_OPTIONS_TEMPLATE_FLOWSET => {
+                    self.parse_v9_options_templates(exporter, &header, set)?
+                }
+                id if id >= MIN_DATA_SET_ID => {
+                    self.parse_data(exporter, &header, id, set, records)?
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn decode_ipfix(
+        &mut self,
+        exporter: IpAddr,
+        data: &[u8],
+        records: &mut Vec<XflowRecord>,
+    ) -> Result<()> {
+        let mut c = Cursor::new(data);
+        let mut h = c.sub(IPFIX_HEADER_LEN)?;
+        // version
+        h.skip(2)?;
+        let length = h.u16()? as usize;
+        // export_time, sequence
+        h.skip(8)?;
+        let header = Header {
+            version: IPFIX,
+            domain: h.u32()?,
+            boot_time: Duration::ZERO,
+        };
+        if length < IPFIX_HEADER_LEN || length > data.len() {
+            return Err(Error::InvalidDatagram("message length"));
+        }
+        let mut c = Cursor::new(&data[IPFIX_HEADER_LEN..length]);
+        while c.remaining() >= 4 {
+            let id = c.u16()?;
+            let length = c.u16()? as usize;
+            if length < 4 {
+                return Err(Error::InvalidDatagram("set length"));
+            }
+            let set = c.sub(length - 4)?;
+            match id {
+                IPFIX_TEMPLATE_SET => self.parse_templates(exporter, &header, set, false)?,
+                IPFIX_OPTIONS_TEMPLATE_SET => self.parse_templates(exporter, &header, set, true)?,
+                id if id >= MIN_DATA_SET_ID => {
+                    self.parse_data(exporter, &header, id, set, records)?
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_template(&mut self, key: TemplateKey, template: Template) {
+        if self.templates.len() >= MAX_TEMPLATES && !self.templates.contains_key(&key) {
+            self.templates.clear();
+        }
+        self.templates.insert(key, template);
+    }
+
+    fn parse_fields(c: &mut Cursor, count: usize, ipfix: bool) -> Result<Vec<Field>> {
+        let mut fields = Vec::with_capacity(count);
+        for _ in 0..count {
+            let id = c.u16()?;
+            let length = c.u16()?;
+            let enterprise = ipfix && id & IPFIX_ENTERPRISE_BIT != 0;
+            if enterprise {
+                // enterprise number
+                c.skip(4)?;
+            }
+            if !ipfix && length == IPFIX_VARIABLE_LENGTH {
+                return Err(Error::InvalidDatagram(
+                    "variable length field in netflow v9",
+                ));
+            }
+            fields.push(Field {
+                id: id & !IPFIX_ENTERPRISE_BIT,
+                length,
+                enterprise,
+            });
+        }
+        Ok(fields)
+    }
+
+    // NetFlow v9模板和IPFIX(选项)模板
+    // NetFlow v9 templates and IPFIX (options) templates
+    fn parse_templates(
+        &mut self,
+        exporter: IpAddr,
+        header: &Header,
+        mut set: Cursor,
+        options: bool,
+    ) -> Result<()> {
+        let ipfix = header.version == IPFIX;
+        // 剩余不足一个模板头时为填充
+        // remaining bytes less than a template header are padding
+        while set.remaining() >= 4 {
+            let id = set.u16()?;
+            let field_count = set.u16()? as usize;
+            if id < MIN_DATA_SET_ID {
+                return Err(Error::InvalidDatagram("template id"));
+            }
+            let key = TemplateKey {
+                exporter,
+                version: header.version,
+                domain: header.domain,
+                id,
+            };
+            if field_count == 0 {
+                // IPFIX模板撤销
+                // IPFIX template withdrawal
+                self.templates.remove(&key);
+                continue;
+            }
+            let template = if options {
+                // scope field count
+                set.skip(2)?;
+                Template::Options(Self::parse_fields(&mut set, field_count, ipfix)?)
+            } else {
+                Template::Data(Self::parse_fields(&mut set, field_count, ipfix)?)
+            };
+            self.insert_template(key, template);
+        }
+        Ok(())
+    }
+
+    fn parse_v9_options_templates(
+        &mut self,
+        exporter: IpAddr,
+        header: &Header,
+        mut set: Cursor,
+    ) -> Result<()> {
+        while set.remaining() >= 6 {
+            let id = set.u16()?;
+            // v9选项模板中给出的是字节长度
+            // lengths in v9 options templates are in bytes
+            let scope_length = set.u16()? as usize;
+            let option_length = set.u16()? as usize;
+            if id < MIN_DATA_SET_ID {
+                return Err(Error::InvalidDatagram("template id"));
+            }
+            let fields = Self::parse_fields(&mut set, (scope_length + option_length) / 4, false)?;
+            self.insert_template(
+                TemplateKey {
+                    exporter,
+                    version: header.version,
+                    domain: header.domain,
+                    id,
+                },
+                Template::Options(fields),
+            );
+        }
+        Ok(())
+    }
+
+    fn parse_data(
+        &self,
+        exporter: IpAddr,
+        header: &Header,
+        id: u16,
+        mut set: Cursor,
+        records: &mut Vec<XflowRecord>,
+    ) -> Result<()> {
+        let key = TemplateKey {
+            exporter,
+            version: header.version,
+            domain: header.domain,
+            id,
+        };
+        // 未收到模板的数据无法解析，直接丢弃
+        // data without a known template cannot be decoded and is dropped
+        let template = match self.templates.get(&key) {
+            Some(t @ Template::Data(_)) => t,
+            _ => return Ok(()),
+        };
+        let min_len = template.min_record_len();
+        if min_len == 0 {
+            return Ok(());
+        }
+        while set.remaining() >= min_len {
+            let mut record = XflowRecord {
+                exporter,
+                ..Default::default()
+            };
+            let mut times = RecordTimes::default();
+            for field in template.fields() {
+                let length = if field.length == IPFIX_VARIABLE_LENGTH {
+                    match set.u8()? {
+                        255 => set.u16()? as usize,
+                        l => l as usize,
+                    }
+                } else {
+                    field.length as usize
+                };
+                let value = set.bytes(length)?;
+                if !field.enterprise {
+                    apply_field(field.id, value, &mut record, &mut times);
+                }
+            }
+            times.apply(header.boot_time, &mut record);
+            if record.packets > 0 || record.bytes > 0 {
+                records.push(record);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct RecordTimes {
+    first_switched: Option<u64>,
+    last_switched: Option<u64>,
+    system_init_time: Option<u64>,
+    sampling_interval: u64,
+}
+
+impl RecordTimes {
+    fn apply(&self, boot_time: Duration, record: &mut XflowRecord) {
+        let boot_time = self
+            .system_init_time
+            .map(Duration::from_millis)
+            .unwrap_or(boot_time);
+        if !boot_time.is_zero() {
+            if let Some(t) = self.first_switched {
+                record.start_time = boot_time + Duration::from_millis(t);
+            }
+            if let Some(t) = self.last_switched {
+                record.end_time = boot_time + Duration::from_millis(t);
+            }
+        }
+        // 计数和采样间隔都来自报文，饱和相乘避免溢出
+        // both counters and sampling interval come from the packet, saturate to avoid overflow
+        if self.sampling_interval > 1 {
+            record.packets = record.packets.saturating_mul(self.sampling_interval);
+            record.bytes = record.bytes.saturating_mul(self.sampling_interval);
+        }
+    }
+}
+
+// 按大端序读取不超过8字节的整数，设备可能使用精简编码
+// read a big-endian integer of up to 8 bytes, exporters may use reduced-size encoding
+fn read_uint(value: &[u8]) -> u64 {
+    value
+        .iter()
+        .take(8)
+        .fold(0, |acc, b| (acc << 8) | *b as u64)
+}
+
+fn apply_field(id: u16, value: &[u8], record: &mut XflowRecord, times: &mut RecordTimes) {
+    match id {
+        IN_BYTES | OCTET_DELTA_COUNT => record.bytes = read_uint(value),
+        IN_PKTS | PACKET_DELTA_COUNT => record.packets = read_uint(value),
+        PROTOCOL => {
+            record.proto =
+                IpProtocol::try_from(read_uint(value) as u8).unwrap_or(IpProtocol::Unknown)
+        }
+        TCP_FLAGS => record.tcp_flags = TcpFlags::from_bits_truncate(read_uint(value) as u8),
+        L4_SRC_PORT => record.port_src = read_uint(value) as u16,
+        L4_DST_PORT => record.port_dst = read_uint(value) as u16,
+        IPV4_SRC_ADDR | IPV4_DST_ADDR if value.len() == 4 => {
+            let ip = Ipv4Addr::new(value[0], value[1], value[2], value[3]).into();
+            if id == IPV4_SRC_ADDR {
+                record.ip_src = ip;
+            } else {
+                record.ip_dst = ip;
+            }
+            record.eth_type = EthernetType::Ipv4;
+        }
+        IPV6_SRC_ADDR | IPV6_DST_ADDR if value.len() == 16 => {
+            let b: [u8; 16] = value.try_into().unwrap();
+            let ip = Ipv6Addr::from(b).into();
+            if id == IPV6_SRC_ADDR {
+                record.ip_src = ip;
+            } else {
+                record.ip_dst = ip;
+            }
+            record.eth_type = EthernetType::Ipv6;
+        }
+        INPUT_SNMP => record.input_if = read_uint(value) as u32,
+        SRC_MAC | DST_MAC if value.len() == 6 => {
+            let mac = MacAddr::from([value[0], value[1], value[2], value[3], value[4], value[5]]);
+            if id == SRC_MAC {
+                record.mac_src = mac;
+            } else {
+                record.mac_dst = mac;
+            }
+        }
+        SRC_VLAN | DOT1Q_VLAN_ID => record.vlan = read_uint(value) as u16 & 0xfff,
+        FIRST_SWITCHED => times.first_switched = Some(read_uint(value)),
+        LAST_SWITCHED => times.last_switched = Some(read_uint(value)),
+        FLOW_START_SECONDS => record.start_time = Duration::from_secs(read_uint(value)),
+        FLOW_END_SECONDS => record.end_time = Duration::from_secs(read_uint(value)),
+        FLOW_START_MILLISECONDS => record.start_time = Duration::from_millis(read_uint(value)),
+        FLOW_END_MILLISECONDS => record.end_time = Duration::from_millis(read_uint(value)),
+        SYSTEM_INIT_TIME_MILLISECONDS => times.system_init_time = Some(read_uint(value)),
+        SAMPLING_INTERVAL | SAMPLING_PACKET_INTERVAL => times.sampling_interval = read_uint(value),
+        _ => (),
+    }
+}
+
+fn decode_v5(exporter: IpAddr, data: &[u8], records: &mut Vec<XflowRecord>) -> Result<()> {
+    let mut c = Cursor::new(data);
+    let mut h = c.sub(V5_HEADER_LEN)?;
+    // version
+    h.skip(2)?;
+    let count = h.u16()? as usize;
+    let sys_uptime = h.u32()? as u64;
+    let unix_secs = h.u32()? as u64;
+    let unix_nsecs = h.u32()? as u64;
+    // flow_sequence, engine_type, engine_id
+    h.skip(6)?;
+    // 高2位为采样模式，低14位为采样间隔
+    // the highest 2 bits are the sampling mode, the lower 14 bits are the interval
+    let sampling_interval = (h.u16()? & 0x3fff).max(1) as u64;
+    if c.remaining() < count * V5_RECORD_LEN {
+        return Err(Error::Truncated);
+    }
+    let boot_time = (Duration::from_secs(unix_secs) + Duration::from_nanos(unix_nsecs))
+        .saturating_sub(Duration::from_millis(sys_uptime));
+    for _ in 0..count {
+        let mut r = c.sub(V5_RECORD_LEN)?;
+        let ip_src = Ipv4Addr::from(r.u32()?);
+        let ip_dst = Ipv4Addr::from(r.u32()?);
+        // nexthop
+        r.skip(4)?;
+        let input_if = r.u16()? as u32;
+        // output
+        r.skip(2)?;
+        let packets = r.u32()? as u64;
+        let bytes = r.u32()? as u64;
+        let first = r.u32()? as u64;
+        let last = r.u32()? as u64;
+        let port_src = r.u16()?;
+        let port_dst = r.u16()?;
+        // pad1
+        r.skip(1)?;
+        let tcp_flags = TcpFlags::from_bits_truncate(r.u8()?);
+        let proto = IpProtocol::try_from(r.u8()?).unwrap_or(IpProtocol::Unknown);
+        records.push(XflowRecord {
+            exporter,
+            input_if,
+            eth_type: EthernetType::Ipv4,
+            ip_src: ip_src.into(),
+            ip_dst: ip_dst.into(),
+            port_src,
+            port_dst,
+            proto,
+            tcp_flags,
+            packets: packets * sampling_interval,
+            bytes: bytes * sampling_interval,
+            start_time: boot_time + Duration::from_millis(first),
+            end_time: boot_time + Duration::from_millis(last),
+            ..Default::default()
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXPORTER: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1));
+
+    fn put_u16(buf: &mut Vec<u8>, v: u16) {
+        buf.extend_from_slice(&v.to_be_bytes());
+    }
+
+    fn put_u32(buf: &mut Vec<u8>, v: u32) {
+        buf.extend_from_slice(&v.to_be_bytes());
+    }
+
+    #[test]
+    fn decode_netflow_v5() {
+        let mut d = vec![];
+        put_u16(&mut d, NETFLOW_V5);
+        put_u16(&mut d, 1);
+        put_u32(&mut d, 10_000); // sys_uptime
+        put_u32(&mut d, 1_600_000_000); // unix_secs
+        put_u32(&mut d, 0); // unix_nsecs
+        put_u32(&mut d, 1); // flow_sequence
+        d.extend_from_slice(&[0, 0]); // engine_type, engine_id
+        put_u16(&mut d, 0x4000 | 10); // sampling
+        d.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2, 0, 0, 0, 0]);
+        put_u16(&mut d, 3); // input
+        put_u16(&mut d, 4); // output
+        put_u32(&mut d, 2); // packets
+        put_u32(&mut d, 120); // bytes
+        put_u32(&mut d, 8_000); // first
+        put_u32(&mut d, 9_000); // last
+        put_u16(&mut d, 12345);
+        put_u16(&mut d, 53);
+        d.extend_from_slice(&[0, 0, 17, 0]);
+        d.extend_from_slice(&[0; 8]);
+
+        let mut records = vec![];
+        NetflowDecoder::default()
+            .decode(EXPORTER, &d, &mut records)
+            .unwrap();
+        assert_eq!(records.len(), 1);
+        let r = &records[0];
+        assert_eq!(r.ip_src, IpAddr::from(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(r.ip_dst, IpAddr::from(Ipv4Addr::new(10, 0, 0, 2)));
+        assert_eq!((r.port_src, r.port_dst), (12345, 53));
+        assert_eq!(r.proto, IpProtocol::Udp);
+        assert_eq!(r.input_if, 3);
+        assert_eq!((r.packets, r.bytes), (20, 1200));
+        assert_eq!(r.start_time, Duration::from_millis(1_599_999_998_000));
+        assert_eq!(r.end_time, Duration::from_millis(1_599_999_999_000));
+
+        assert_eq!(
+            NetflowDecoder::default().decode(EXPORTER, &d[..d.len() - 1], &mut records),
+            Err(Error::Truncated)
+        );
+    }
+
+    fn v9_template_fields() -> Vec<(u16, u16)> {
+        vec![
+            (IPV4_SRC_ADDR, 4),
+            (IPV4_DST_ADDR, 4),
+            (L4_SRC_PORT, 2),
+            (L4_DST_PORT, 2),
+            (PROTOCOL, 1),
+            (TCP_FLAGS, 1),
+            (IN_PKTS, 4),
+            (IN_BYTES, 8),
+            (FIRST_SWITCHED, 4),
+            (LAST_SWITCHED, 4),
+        ]
+    }
+
+    fn v9_data_record(d: &mut Vec<u8>) {
+        d.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        put_u16(d, 54321);
+        put_u16(d, 80);
+        d.extend_from_slice(&[6, 0x12]);
+        put_u32(d, 5);
+        d.extend_from_slice(&1000u64.to_be_bytes());
+        put_u32(d, 1_000);
+        put_u32(d, 2_000);
+    }
+
+    fn v9_header(d: &mut Vec<u8>, count: u16) {
+        put_u16(d, NETFLOW_V9);
+        put_u16(d, count);
+        put_u32(d, 10_000); // sys_uptime
+        put_u32(d, 1_600_000_000); // unix_secs
+        put_u32(d, 1); // sequence
+        put_u32(d, 7); // source id
+    }
+
+    #[test]
+    fn decode_netflow_v9() {
+        let fields = v9_template_fields();
+        let mut template = vec![];
+        put_u16(&mut template, V9_TEMPLATE_FLOWSET);
+        put_u16(&mut template, (8 + fields.len() * 4) as u16);
+        put_u16(&mut template, 300);
+        put_u16(&mut template, fields.len() as u16);
+        for (id, len) in fields.iter() {
+            put_u16(&mut template, *id);
+            put_u16(&mut template, *len);
+        }
+
+        let mut data = vec![];
+        v9_data_record(&mut data);
+        v9_data_record(&mut data);
+        // padding
+        data.extend_from_slice(&[0, 0]);
+        let mut data_set = vec![];
+        put_u16(&mut data_set, 300);
+        put_u16(&mut data_set, (data.len() + 4) as u16);
+        data_set.extend_from_slice(&data);
+
+        let mut decoder = NetflowDecoder::default();
+        let mut records = vec![];
+        // 模板到达之前的数据被丢弃
+        // data arriving before its template is dropped
+        let mut d = vec![];
+        v9_header(&mut d, 2);
+        d.extend_from_slice(&data_set);
+        decoder.decode(EXPORTER, &d, &mut records).unwrap();
+        assert!(records.is_empty());
+
+        let mut d = vec![];
+        v9_header(&mut d, 3);
+        d.extend_from_slice(&template);
+        d.extend_from_slice(&data_set);
+        decoder.decode(EXPORTER, &d, &mut records).unwrap();
+        assert_eq!(decoder.templates.len(), 1);
+        assert_eq!(records.len(), 2);
+        let r = &records[0];
+        assert_eq!(r.ip_src, IpAddr::from(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!((r.port_src, r.port_dst), (54321, 80));
+        assert_eq!(r.proto, IpProtocol::Tcp);
+        assert_eq!(r.tcp_flags, TcpFlags::SYN_ACK);
+        assert_eq!((r.packets, r.bytes), (5, 1000));
+        assert_eq!(r.start_time, Duration::from_millis(1_599_999_991_000));
+        assert_eq!(r.end_time, Duration::from_millis(1_599_999_992_000));
+
+        // 其他设备的模板互不影响
+        // templates of other exporters are isolated
+        records.clear();
+        let mut d = vec![];
+        v9_header(&mut d, 2);
+        d.extend_from_slice(&data_set);
+        decoder
+            .decode(Ipv4Addr::new(192, 168, 0, 2).into(), &d, &mut records)
+            .unwrap();
+        assert!(records.is_empty());
+    }
+
+    #[test]
+    fn sampling_interval_saturates() {
+        let times = RecordTimes {
+            sampling_interval: u64::MAX / 2,
+            ..Default::default()
+        };
+        let mut record = XflowRecord {
+            packets: 3,
+            bytes: 1,
+            ..Default::default()
+        };
+        times.apply(Duration::ZERO, &mut record);
+        assert_eq!((record.packets, record.bytes), (u64::MAX, u64::MAX / 2));
+    }
+
+    #[test]
+    fn decode_ipfix() {
+        let mut template = vec![];
+        put_u16(&mut template, IPFIX_TEMPLATE_SET);
+        put_u16(&mut template, 4 + 4 + 7 * 4 + 4);
+        put_u16(&mut template, 256);
+        put_u16(&mut template, 7);
+        for (id, len) in [
+            (IPV6_SRC_ADDR, 16),
+            (IPV6_DST_ADDR, 16),
+            (PROTOCOL, 1),
+            (PACKET_DELTA_COUNT, 8),
+            (OCTET_DELTA_COUNT, 8),
+            (FLOW_START_MILLISECONDS, 8),
+        ] {
+            put_u16(&mut template, id);
+            put_u16(&mut template, len);
+        }
+        // 企业私有变长字段
+        // enterprise variable-length field
+        put_u16(&mut template, IPFIX_ENTERPRISE_BIT | 1);
+        put_u16(&mut template, IPFIX_VARIABLE_LENGTH);
+        put_u32(&mut template, 29305);
+
+        let mut data = vec![];
+        put_u16(&mut data, 256);
+        put_u16(&mut data, 0);
+        let src = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 1);
+        let dst = Ipv6Addr::new(0xfd00, 0, 0, 0, 0, 0, 0, 2);
+        data.extend_from_slice(&src.octets());
+        data.extend_from_slice(&dst.octets());
+        data.push(17);
+        data.extend_from_slice(&3u64.to_be_bytes());
+        data.extend_from_slice(&300u64.to_be_bytes());
+        data.extend_from_slice(&1_600_000_000_123u64.to_be_bytes());
+        data.extend_from_slice(&[3, b'a', b'b', b'c']);
+        let data_len = data.len() as u16;
+        data[2..4].copy_from_slice(&data_len.to_be_bytes());
+
+        let mut d = vec![];
+        put_u16(&mut d, IPFIX);
+        put_u16(&mut d, 0);
+        put_u32(&mut d, 1_600_000_001); // export time
+        put_u32(&mut d, 1); // sequence
+        put_u32(&mut d, 1); // observation domain
+        d.extend_from_slice(&template);
+        d.extend_from_slice(&data);
+        let len = d.len() as u16;
+        d[2..4].copy_from_slice(&len.to_be_bytes());
+
+        let mut decoder = NetflowDecoder::default();
+        let mut records = vec![];
+        decoder.decode(EXPORTER, &d, &mut records).unwrap();
+        assert_eq!(records.len(), 1);
+        let r = &records[0];
+        assert_eq!(r.eth_type, EthernetType::Ipv6);
+        assert_eq!(r.ip_src, IpAddr::from(src));
+        assert_eq!(r.ip_dst, IpAddr::from(dst));
+        assert_eq!(r.proto, IpProtocol::Udp);
+        assert_eq!((r.packets, r.bytes), (3, 300));
+        assert_eq!(r.start_time, Duration::from_millis(1_600_000_000_123));
+        assert_eq!(r.end_time, Duration::ZERO);
+
+        // 撤销模板
+        // withdraw the template
+        let mut d = vec![];
+        put_u16(&mut d, IPFIX);
+        put_u16(&mut d, 24);
+        put_u32(&mut d, 1_600_000_002);
+        put_u32(&mut d, 2);
+        put_u32(&mut d, 1);
+        put_u16(&mut d, IPFIX_TEMPLATE_SET);
+        put_u16(&mut d, 8);
+        put_u16(&mut d, 256);
+        put_u16(&mut d, 0);
+        decoder.decode(EXPORTER, &d, &mut records).unwrap();
+        assert_eq!(decoder.templates.len(), 0);
+    }
+}
@@ -0,0 +1,378 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use super::{Cursor, Error, Result, XflowRecord};
+
+use public::enums::{EthernetType, IpProtocol, TcpFlags};
+
+// 参考 https://sflow.org/sflow_version_5.txt
+const SFLOW_VERSION_5: u32 = 5;
+
+const ADDRESS_TYPE_IPV4: u32 = 1;
+const ADDRESS_TYPE_IPV6: u32 = 2;
+
+const SAMPLE_FLOW: u32 = 1;
+const SAMPLE_FLOW_EXPANDED: u32 = 3;
+
+const RECORD_RAW_PACKET_HEADER: u32 = 1;
+const RECORD_ETHERNET_FRAME: u32 = 2;
+const RECORD_IPV4: u32 = 3;
+const RECORD_IPV6: u32 = 4;
+
+const HEADER_PROTOCOL_ETHERNET: u32 = 1;
+const HEADER_PROTOCOL_IPV4: u32 = 11;
+const HEADER_PROTOCOL_IPV6: u32 = 12;
+
+// 接口编号的最高两位表示格式，0表示单个接口
+// the highest two bits of the interface indicate the format, 0 means a single ifIndex
+const INTERFACE_FORMAT_SHIFT: u32 = 30;
+const INTERFACE_VALUE_MASK: u32 = 0x3fffffff;
+
+// 解析sFlow v5报文，每个flow sample生成一条记录，counter sample被忽略
+// decode an sFlow v5 datagram, one record for each flow sample, counter samples are ignored
+pub fn decode(data: &[u8], records: &mut Vec<XflowRecord>) -> Result<()> {
+    let mut c = Cursor::new(data);
+    let version = c.u32()?;
+    if version != SFLOW_VERSION_5 {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    let exporter: IpAddr = match c.u32()? {
+        ADDRESS_TYPE_IPV4 => {
+            let b: [u8; 4] = c.bytes(4)?.try_into().unwrap();
+            Ipv4Addr::from(b).into()
+        }
+        ADDRESS_TYPE_IPV6 => {
+            let b: [u8; 16] = c.bytes(16)?.try_into().unwrap();
+            Ipv6Addr::from(b).into()
+        }
+        _ => return Err(Error::InvalidDatagram("unknown agent address type")),
+    };
+    // sub_agent_id, sequence_number, uptime
+    c.skip(12)?;
+    let sample_count = c.u32()?;
+    for _ in 0..sample_count {
+        let format = c.u32()?;
+        let length = c.u32()? as usize;
+        let mut sample = c.sub(length)?;
+        // enterprise为0的标准格式
+        // standard formats with enterprise 0
+        if format >> 12 != 0 {
+            continue;
+        }
+        let (sampling_rate, input_if) = match format & 0xfff {
+            SAMPLE_FLOW => {
+                // sequence_number, source_id
+                sample.skip(8)?;
+                let sampling_rate = sample.u32()?;
+                // sample_pool, drops
+                sample.skip(8)?;
+                let input = sample.u32()?;
+                // output
+                sample.skip(4)?;
+                let input_if = if input >> INTERFACE_FORMAT_SHIFT == 0 {
+                    input & INTERFACE_VALUE_MASK
+                } else {
+                    0
+                };
+                (sampling_rate, input_if)
+            }
+            SAMPLE_FLOW_EXPANDED => {
+                // sequence_number, source_id_type, source_id_index
+                sample.skip(12)?;
+                let sampling_rate = sample.u32()?;
+                // sample_pool, drops
+                sample.skip(8)?;
+                let input_format = sample.u32()?;
+                let input_value = sample.u32()?;
+                // output_format, output_value
+                sample.skip(8)?;
+                (
+                    sampling_rate,
+                    if input_format == 0 { input_value } else { 0 },
+                )
+            }
+            _ => continue,
+        };
+        let mut record = XflowRecord {
+            exporter,
+            input_if,
+            ..Default::default()
+        };
+        if decode_flow_records(&mut sample, &mut record)? {
+            let sampling_rate = sampling_rate.max(1) as u64;
+            record.packets = sampling_rate;
+            record.bytes *= sampling_rate;
+            records.push(record);
+        }
+    }
+    Ok(())
+}
+
+// 返回false表示sample中没有可用的IP信息
+// returns false if there is no usable IP information in the sample
+fn decode_flow_records(sample: &mut Cursor, record: &mut XflowRecord) -> Result<bool> {
+    let record_count = sample.u32()?;
+    let mut has_header = false;
+    let mut has_ip = false;
+    for _ in 0..record_count {
+        let format = sample.u32()?;
+        let length = sample.u32()? as usize;
+        let mut r = sample.sub(length)?;
+        if format >> 12 != 0 {
+            continue;
+        }
+        match format & 0xfff {
+            RECORD_RAW_PACKET_HEADER => {
+                let protocol = r.u32()?;
+                let frame_length = r.u32()?;
+                // stripped
+                r.skip(4)?;
+                let header_length = r.u32()? as usize;
+                let header = r.bytes(header_length)?;
+                let parsed = match protocol {
+                    HEADER_PROTOCOL_ETHERNET => parse_ethernet(header, record),
+                    HEADER_PROTOCOL_IPV4 => parse_ip(EthernetType::Ipv4, header, record),
+                    HEADER_PROTOCOL_IPV6 => parse_ip(EthernetType::Ipv6, header, record),
+                    _ => false,
+                };
+                if parsed {
+                    record.bytes = frame_length as u64;
+                    has_header = true;
+                    has_ip = true;
+                }
+            }
+            RECORD_ETHERNET_FRAME if !has_header => {
+                // length
+                r.skip(4)?;
+                // mac按XDR规则填充到8字节
+                // mac is padded to 8 bytes by XDR
+                record.mac_src = r.mac()?;
+                r.skip(2)?;
+                record.mac_dst = r.mac()?;
+            }
+            RECORD_IPV4 if !has_header => {
+                record.bytes = r.u32()? as u64;
+                record.proto = IpProtocol::try_from(r.u32()? as u8).unwrap_or(IpProtocol::Unknown);
+                let src: [u8; 4] = r.bytes(4)?.try_into().unwrap();
+                let dst: [u8; 4] = r.bytes(4)?.try_into().unwrap();
+                record.ip_src = Ipv4Addr::from(src).into();
+                record.ip_dst = Ipv4Addr::from(dst).into();
+                record.port_src = r.u32()? as u16;
+                record.port_dst = r.u32()? as u16;
+                record.tcp_flags = TcpFlags::from_bits_truncate(r.u32()? as u8);
+                record.eth_type = EthernetType::Ipv4;
+                has_ip = true;
+            }
+            RECORD_IPV6 if !has_header => {
+                record.bytes = r.u32()? as u64;
+                record.proto = IpProtocol::try_from(r.u32()? as u8).unwrap_or(IpProtocol::Unknown);
+                let src: [u8; 16] = r.bytes(16)?.try_into().unwrap();
+                let dst: [u8; 16] = r.bytes(16)?.try_into().unwrap();
+                record.ip_src = Ipv6Addr::from(src).into();
+                record.ip_dst = Ipv6Addr::from(dst).into();
+                record.port_src = r.u32()? as u16;
+                record.port_dst = r.u32()? as u16;
+                record.tcp_flags = TcpFlags::from_bits_truncate(r.u32()? as u8);
+                record.eth_type = EthernetType::Ipv6;
+                has_ip = true;
+            }
+            _ => (),
+        }
+    }
+    Ok(has_ip)
+}
+
+// 解析采样的报文头，报文头可能被截断，能解析出IP即可
+// parse the sampled packet header, which may be truncated, IP addresses are enough
+fn parse_ethernet(header: &[u8], record: &mut XflowRecord) -> bool {
+    let mut c = Cursor::new(header);
+    match parse_ethernet_header(&mut c, record) {
+        Ok(t @ EthernetType::Ipv4) | Ok(t @ EthernetType::Ipv6) => parse_ip(t, c.rest(), record),
+        _ => false,
+    }
+}
+
+fn parse_ethernet_header(c: &mut Cursor, record: &mut XflowRecord) -> Result<EthernetType> {
+    record.mac_dst = c.mac()?;
+    record.mac_src = c.mac()?;
+    let mut eth_type = c.u16()?;
+    while eth_type == u16::from(EthernetType::Dot1Q) || eth_type == u16::from(EthernetType::QinQ) {
+        let tci = c.u16()?;
+        if record.vlan == 0 {
+            record.vlan = tci & 0xfff;
+        }
+        eth_type = c.u16()?;
+    }
+    // EthernetType带有默认值，转换不会失败
+    // EthernetType has a default variant, the conversion never fails
+    Ok(EthernetType::try_from(eth_type).unwrap_or(EthernetType::Unknown))
+}
+
+fn parse_ip(eth_type: EthernetType, header: &[u8], record: &mut XflowRecord) -> bool {
+    let (proto, l4) = match eth_type {
+        EthernetType::Ipv4 => {
+            if header.len() < 20 || header[0] >> 4 != 4 {
+                return false;
+            }
+            let ihl = ((header[0] & 0xf) as usize) << 2;
+            let src: [u8; 4] = header[12..16].try_into().unwrap();
+            let dst: [u8; 4] = header[16..20].try_into().unwrap();
+            record.ip_src = Ipv4Addr::from(src).into();
+            record.ip_dst = Ipv4Addr::from(dst).into();
+            // 非首个分片没有四层头
+            // non-first fragments carry no L4 header
+            let fragment_offset = u16::from_be_bytes([header[6], header[7]]) & 0x1fff;
+            let l4 = if fragment_offset == 0 && header.len() > ihl {
+                &header[ihl..]
+            } else {
+                &[][..]
+            };
+            (header[9], l4)
+        }
+        EthernetType::Ipv6 => {
+            if header.len() < 40 || header[0] >> 4 != 6 {
+                return false;
+            }
+            let src: [u8; 16] = header[8..24].try_into().unwrap();
+            let dst: [u8; 16] = header[24..40].try_into().unwrap();
+            record.ip_src = Ipv6Addr::from(src).into();
+            record.ip_dst = Ipv6Addr::from(dst).into();
+            (header[6], &header[40..])
+        }
+        _ => return false,
+    };
+    record.eth_type = eth_type;
+    record.proto = IpProtocol::try_from(proto).unwrap_or(IpProtocol::Unknown);
+    match record.proto {
+        IpProtocol::Tcp if l4.len() >= 14 => {
+            record.port_src = u16::from_be_bytes([l4[0], l4[1]]);
+            record.port_dst = u16::from_be_bytes([l4[2], l4[3]]);
+            record.tcp_flags = TcpFlags::from_bits_truncate(l4[13] & TcpFlags::MASK.bits());
+        }
+        IpProtocol::Udp if l4.len() >= 4 => {
+            record.port_src = u16::from_be_bytes([l4[0], l4[1]]);
+            record.port_dst = u16::from_be_bytes([l4[2], l4[3]]);
+        }
+        _ => (),
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use public::utils::net::MacAddr;
+
+    fn tcp_packet() -> Vec<u8> {
+        let mut p = vec![];
+        // ethernet with 802.1Q
+        p.extend_from_slice(&[0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        p.extend_from_slice(&[0x00, 0x66, 0x77, 0x88, 0x99, 0xaa]);
+        p.extend_from_slice(&[0x81, 0x00, 0x00, 0x64, 0x08, 0x00]);
+        // ipv4
+        p.extend_from_slice(&[0x45, 0, 0, 40, 0, 0, 0x40, 0, 64, 6, 0, 0]);
+        p.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        // tcp
+        p.extend_from_slice(&[0x30, 0x39, 0x00, 0x50, 0, 0, 0, 1, 0, 0, 0, 0, 0x50, 0x02]);
+        p.extend_from_slice(&[0xff, 0xff, 0, 0, 0, 0]);
+        p
+    }
+
+    fn put_u32(buf: &mut Vec<u8>, v: u32) {
+        buf.extend_from_slice(&v.to_be_bytes());
+    }
+
+    fn datagram(packet: &[u8]) -> Vec<u8> {
+        let mut header_record = vec![];
+        put_u32(&mut header_record, HEADER_PROTOCOL_ETHERNET);
+        put_u32(&mut header_record, 1500);
+        put_u32(&mut header_record, 4);
+        put_u32(&mut header_record, packet.len() as u32);
+        header_record.extend_from_slice(packet);
+        while header_record.len() % 4 != 0 {
+            header_record.push(0);
+        }
+
+        let mut sample = vec![];
+        put_u32(&mut sample, 1); // sequence_number
+        put_u32(&mut sample, 3); // source_id
+        put_u32(&mut sample, 1000); // sampling_rate
+        put_u32(&mut sample, 100000); // sample_pool
+        put_u32(&mut sample, 0); // drops
+        put_u32(&mut sample, 7); // input
+        put_u32(&mut sample, 8); // output
+        put_u32(&mut sample, 1); // record count
+        put_u32(&mut sample, RECORD_RAW_PACKET_HEADER);
+        put_u32(&mut sample, header_record.len() as u32);
+        sample.extend_from_slice(&header_record);
+
+        let mut d = vec![];
+        put_u32(&mut d, SFLOW_VERSION_5);
+        put_u32(&mut d, ADDRESS_TYPE_IPV4);
+        d.extend_from_slice(&[192, 168, 0, 1]);
+        put_u32(&mut d, 0); // sub_agent_id
+        put_u32(&mut d, 1); // sequence_number
+        put_u32(&mut d, 1000); // uptime
+        put_u32(&mut d, 2); // sample count
+                            // counter sample, ignored
+        put_u32(&mut d, 2);
+        put_u32(&mut d, 4);
+        put_u32(&mut d, 0);
+        put_u32(&mut d, SAMPLE_FLOW);
+        put_u32(&mut d, sample.len() as u32);
+        d.extend_from_slice(&sample);
+        d
+    }
+
+    #[test]
+    fn decode_flow_sample() {
+        let mut records = vec![];
+        decode(&datagram(&tcp_packet()), &mut records).unwrap();
+        assert_eq!(records.len(), 1);
+        let r = &records[0];
+        assert_eq!(r.exporter, IpAddr::from(Ipv4Addr::new(192, 168, 0, 1)));
+        assert_eq!(r.input_if, 7);
+        assert_eq!(
+            r.mac_src,
+            MacAddr::from([0x00, 0x66, 0x77, 0x88, 0x99, 0xaa])
+        );
+        assert_eq!(r.vlan, 100);
+        assert_eq!(r.eth_type, EthernetType::Ipv4);
+        assert_eq!(r.ip_src, IpAddr::from(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(r.ip_dst, IpAddr::from(Ipv4Addr::new(10, 0, 0, 2)));
+        assert_eq!((r.port_src, r.port_dst), (12345, 80));
+        assert_eq!(r.proto, IpProtocol::Tcp);
+        assert_eq!(r.tcp_flags, TcpFlags::SYN);
+        assert_eq!(r.packets, 1000);
+        assert_eq!(r.bytes, 1500 * 1000);
+    }
+
+    #[test]
+    fn decode_invalid() {
+        let mut records = vec![];
+        let mut d = datagram(&tcp_packet());
+        assert_eq!(
+            decode(&d[..d.len() - 8], &mut records),
+            Err(Error::Truncated)
+        );
+        d[3] = 4;
+        assert_eq!(decode(&d, &mut records), Err(Error::UnsupportedVersion(4)));
+        assert!(records.is_empty());
+    }
+}
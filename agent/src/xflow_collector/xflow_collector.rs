@@ -0,0 +1,456 @@
+/*
+ * Copyright (c) 2022 Yunshan Networks
+ *
+ * Licensed under the Apache License, Version 2.0 (the "License");
+ * you may not use this file except in compliance with the License.
+ * You may obtain a copy of the License at
+ *
+ *     http://www.apache.org/licenses/LICENSE-2.0
+ *
+ * Unless required by applicable law or agreed to in writing, software
+ * distributed under the License is distributed on an "AS IS" BASIS,
+ * WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+ * See the License for the specific language governing permissions and
+ * limitations under the License.
+ */
+
+use std::{
+    fmt,
+    io::ErrorKind,
+    net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+    thread,
+    thread::JoinHandle,
+    time::Duration,
+};
+
+use arc_swap::access::Access;
+use log::{debug, info, warn};
+
+use super::{netflow::NetflowDecoder, sflow, XflowRecord};
+
+use crate::{
+    common::{
+        flow::{CloseType, FlowSource},
+        lookup_key::LookupKey,
+        TaggedFlow, TapPort, TapTyper, XflowKey,
+    },
+    config::{handler::FlowAccess, XflowGeneratorConfig},
+    flow_generator::flow_map,
+    metric::document::TapSide,
+    policy::PolicyGetter,
+    rpc::get_timestamp,
+    utils::stats::{
+        self, Countable, Counter, CounterType, CounterValue, RefCountable, StatsOption,
+    },
+};
+use npb_pcap_policy::PolicyData;
+use public::{
+    debug::QueueDebugger,
+    enums::TapType,
+    queue::{self, DebugSender, Error, Receiver},
+};
+
+const QUEUE_BATCH_SIZE: usize = 1024;
+const RCV_TIMEOUT: Duration = Duration::from_secs(1);
+// UDP报文最大长度
+// the maximum length of an UDP datagram
+const MAX_DATAGRAM_SIZE: usize = 65535;
+
+struct XflowPacket {
+    peer: SocketAddr,
+    data: Vec<u8>,
+}
+
+impl fmt::Debug for XflowPacket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "peer: {} length: {}", self.peer, self.data.len())
+    }
+}
+
+#[derive(Default)]
+pub struct XflowCounter {
+    rx_packets: AtomicU64,
+    rx_bytes: AtomicU64,
+    invalid_packets: AtomicU64,
+    records: AtomicU64,
+}
+
+impl RefCountable for XflowCounter {
+    fn get_counters(&self) -> Vec<Counter> {
+        vec![
+            (
+                "rx-packets",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.rx_packets.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "rx-bytes",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.rx_bytes.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "invalid-packets",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.invalid_packets.swap(0, Ordering::Relaxed)),
+            ),
+            (
+                "records",
+                CounterType::Counted,
+                CounterValue::Unsigned(self.records.swap(0, Ordering::Relaxed)),
+            ),
+        ]
+    }
+}
+
+struct Context {
+    tap_typer: Arc<TapTyper>,
+    default_tap_type: TapType,
+    flow_config: FlowAccess,
+    ntp_diff: Arc<AtomicI64>,
+}
+
+// 每个监听端口对应一个接收线程和一个解析线程
+// each listening port has a receiving thread and a decoding thread
+struct Listener {
+    source: FlowSource,
+    port: u16,
+    // 策略fast path的队列索引
+    // queue index of the policy fast path
+    fast_index: usize,
+    packet_sender: DebugSender<XflowPacket>,
+    packet_receiver: Arc<Receiver<XflowPacket>>,
+    counter: Arc<XflowCounter>,
+}
+
+pub struct XflowCollector {
+    listeners: Vec<Listener>,
+    output_queue: DebugSender<Box<TaggedFlow>>,
+    policy_getter: PolicyGetter,
+    context: Arc<Context>,
+    running: Arc<AtomicBool>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl XflowCollector {
+    // 解析配置中的端口，非法端口被忽略
+    // parse ports in the config, invalid ones are ignored
+    pub fn listen_ports(config: &XflowGeneratorConfig) -> Vec<(FlowSource, u16)> {
+        let mut ports = vec![];
+        for (source, list) in [
+            (FlowSource::Sflow, &config.sflow_ports),
+            (FlowSource::NetFlow, &config.netflow_ports),
+        ] {
+            for p in list.iter() {
+                match p.trim().parse::<u16>() {
+                    Ok(port) if port != 0 => ports.push((source, port)),
+                    _ => warn!("invalid {:?} port {} in xflow-collector", source, p),
+                }
+            }
+        }
+        ports
+    }
+
+    pub fn new(
+        ports: Vec<(FlowSource, u16)>,
+        fast_index_base: usize,
+        output_queue: DebugSender<Box<TaggedFlow>>,
+        tap_typer: Arc<TapTyper>,
+        default_tap_type: TapType,
+        policy_getter: PolicyGetter,
+        flow_config: FlowAccess,
+        ntp_diff: Arc<AtomicI64>,
+        queue_size: usize,
+        queue_debugger: &QueueDebugger,
+        stats_collector: &stats::Collector,
+    ) -> Self {
+        let mut listeners = vec![];
+        for (i, (source, port)) in ports.into_iter().enumerate() {
+            let name = match source {
+                FlowSource::Sflow => "0-sflow-packet-to-tagged-flow",
+                _ => "0-netflow-packet-to-tagged-flow",
+            };
+            let (packet_sender, packet_receiver, counter) =
+                queue::bounded_with_debug(queue_size, name, queue_debugger);
+            stats_collector.register_countable(
+                "queue",
+                Countable::Owned(Box::new(counter)),
+                vec![
+                    StatsOption::Tag("module", name.to_string()),
+                    StatsOption::Tag("index", port.to_string()),
+                ],
+            );
+            let counter: Arc<XflowCounter> = Default::default();
+            stats_collector.register_countable(
+                "xflow-collector",
+                Countable::Ref(Arc::downgrade(&counter) as Weak<dyn RefCountable>),
+                vec![
+                    StatsOption::Tag("type", format!("{:?}", source).to_lowercase()),
+                    StatsOption::Tag("port", port.to_string()),
+                ],
+            );
+            listeners.push(Listener {
+                source,
+                port,
+                fast_index: fast_index_base + i,
+                packet_sender,
+                packet_receiver: Arc::new(packet_receiver),
+                counter,
+            });
+        }
+        Self {
+            listeners,
+            output_queue,
+            policy_getter,
+            context: Arc::new(Context {
+                tap_typer,
+                default_tap_type,
+                flow_config,
+                ntp_diff,
+            }),
+            running: Default::default(),
+            threads: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn start(&self) {
+        if self.running.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let mut threads = self.threads.lock().unwrap();
+        for l in self.listeners.iter() {
+            let socket = match UdpSocket::bind((Ipv4Addr::UNSPECIFIED, l.port)) {
+                Ok(s) => s,
+                Err(e) => {
+                    warn!(
+                        "xflow collector bind {:?} port {} failed: {}",
+                        l.source, l.port, e
+                    );
+                    continue;
+                }
+            };
+            // 超时用于检查退出标志
+            // the timeout is used to check the running flag
+            if let Err(e) = socket.set_read_timeout(Some(RCV_TIMEOUT)) {
+                warn!("xflow collector set read timeout failed: {}", e);
+                continue;
+            }
+
+            let running = self.running.clone();
+            let sender = l.packet_sender.clone();
+            let counter = l.counter.clone();
+            threads.push(thread::spawn(move || {
+                let mut buf = vec![0u8; MAX_DATAGRAM_SIZE];
+                while running.load(Ordering::Relaxed) {
+                    let (n, peer) = match socket.recv_from(&mut buf) {
+                        Ok(r) => r,
+                        Err(e)
+                            if e.kind() == ErrorKind::WouldBlock
+                                || e.kind() == ErrorKind::TimedOut =>
+                        {
+                            continue
+                        }
+                        Err(e) => {
+                            warn!("xflow collector recv failed: {}", e);
+                            continue;
+                        }
+                    };
+                    counter.rx_packets.fetch_add(1, Ordering::Relaxed);
+                    counter.rx_bytes.fetch_add(n as u64, Ordering::Relaxed);
+                    let packet = XflowPacket {
+                        peer,
+                        data: buf[..n].to_vec(),
+                    };
+                    if let Err(e) = sender.send(packet) {
+                        debug!("xflow collector send packet failed: {:?}", e);
+                    }
+                }
+            }));
+
+            let running = self.running.clone();
+            let receiver = l.packet_receiver.clone();
+            let counter = l.counter.clone();
+            let output_queue = self.output_queue.clone();
+            let mut converter = Converter {
+                source: l.source,
+                fast_index: l.fast_index,
+                policy_getter: self.policy_getter,
+                context: self.context.clone(),
+                total_flow: 0,
+            };
+            threads.push(thread::spawn(move || {
+                let mut netflow_decoder = NetflowDecoder::default();
+                let mut records = vec![];
+                let mut flows = vec![];
+                while running.load(Ordering::Relaxed) {
+                    let packets = match receiver.recv_n(QUEUE_BATCH_SIZE, Some(RCV_TIMEOUT)) {
+                        Ok(p) => p,
+                        Err(Error::Timeout) => continue,
+                        Err(Error::Terminated(..)) => break,
+                    };
+                    for packet in packets {
+                        let result = match converter.source {
+                            FlowSource::Sflow => sflow::decode(&packet.data, &mut records),
+                            _ => {
+                                netflow_decoder.decode(packet.peer.ip(), &packet.data, &mut records)
+                            }
+                        };
+                        if let Err(e) = result {
+                            counter.invalid_packets.fetch_add(1, Ordering::Relaxed);
+                            debug!(
+                                "invalid {:?} packet from {}: {}",
+                                converter.source, packet.peer, e
+                            );
+                        }
+                    }
+                    if records.is_empty() {
+                        continue;
+                    }
+                    counter
+                        .records
+                        .fetch_add(records.len() as u64, Ordering::Relaxed);
+                    let now = get_timestamp(converter.context.ntp_diff.load(Ordering::Relaxed));
+                    for record in records.drain(..) {
+                        flows.push(converter.convert(record, now));
+                    }
+                    if let Err(e) = output_queue.send_all(flows.drain(..).collect()) {
+                        debug!("xflow collector send flows failed: {:?}", e);
+                    }
+                }
+            }));
+            info!(
+                "xflow collector listening {:?} on port {}",
+                l.source, l.port
+            );
+        }
+    }
+
+    pub fn stop(&self) {
+        if !self.running.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        for t in self.threads.lock().unwrap().drain(..) {
+            let _ = t.join();
+        }
+        info!("xflow collector stopped");
+    }
+}
+
+struct Converter {
+    source: FlowSource,
+    fast_index: usize,
+    policy_getter: PolicyGetter,
+    context: Arc<Context>,
+    total_flow: u64,
+}
+
+impl Converter {
+    // 同flow_map的规则生成flowID，每条记录都是独立的流
+    // flow id is generated by the same rule as flow_map, each record is an individual flow
+    fn generate_flow_id(&mut self, timestamp: Duration) -> u64 {
+        self.total_flow += 1;
+        flow_map::generate_flow_id(timestamp, self.fast_index as u32, self.total_flow)
+    }
+
+    fn convert(&mut self, record: XflowRecord, now: Duration) -> Box<TaggedFlow> {
+        // XflowKey和TapPort只能使用IPv4地址，IPv6设备取最后4字节
+        // XflowKey and TapPort take IPv4 only, the last 4 bytes are used for IPv6 exporters
+        let exporter = match record.exporter {
+            IpAddr::V4(ip) => ip,
+            IpAddr::V6(ip) => {
+                let o = ip.octets();
+                Ipv4Addr::new(o[12], o[13], o[14], o[15])
+            }
+        };
+        let tap_type = self
+            .context
+            .tap_typer
+            .get_tap_type_by_xflow_key(&XflowKey::new(exporter, record.input_if))
+            .unwrap_or(self.context.default_tap_type);
+        let tap_port = match self.source {
+            FlowSource::Sflow => TapPort::from_sflow(u32::from(exporter)),
+            _ => TapPort::from_netflow(u32::from(exporter)),
+        };
+        let start_time = if record.start_time.is_zero() {
+            now
+        } else {
+            record.start_time
+        };
+        let end_time = if record.end_time.is_zero() {
+            now.max(start_time)
+        } else {
+            record.end_time
+        };
+
+        let mut tagged_flow = Box::new(TaggedFlow::default());
+        let flow = &mut tagged_flow.flow;
+        flow.flow_key.vtap_id = self.context.flow_config.load().vtap_id;
+        flow.flow_key.tap_type = tap_type;
+        flow.flow_key.tap_port = tap_port;
+        flow.flow_key.mac_src = record.mac_src;
+        flow.flow_key.mac_dst = record.mac_dst;
+        flow.flow_key.ip_src = record.ip_src;
+        flow.flow_key.ip_dst = record.ip_dst;
+        flow.flow_key.port_src = record.port_src;
+        flow.flow_key.port_dst = record.port_dst;
+        flow.flow_key.proto = record.proto;
+        flow.flow_id = self.generate_flow_id(now);
+        flow.start_time = start_time;
+        flow.end_time = end_time;
+        flow.duration = end_time.saturating_sub(start_time);
+        // 设备上报的是历史统计，统计时间使用接收时间以进入quadruple_generator的窗口
+        // exporters report historical data, use receive time to fit the quadruple_generator window
+        flow.flow_stat_time = Duration::from_secs(now.as_secs());
+        flow.vlan = record.vlan;
+        flow.eth_type = record.eth_type;
+        flow.close_type = CloseType::ForcedReport;
+        flow.flow_source = self.source;
+        flow.tap_side = TapSide::Rest;
+
+        let peer = &mut flow.flow_metrics_peers[0];
+        peer.byte_count = record.bytes;
+        peer.packet_count = record.packets;
+        peer.total_byte_count = record.bytes;
+        peer.total_packet_count = record.packets;
+        peer.first = start_time;
+        peer.last = end_time;
+        peer.tcp_flags = record.tcp_flags;
+
+        let mut key = LookupKey {
+            timestamp: now,
+            src_mac: record.mac_src,
+            dst_mac: record.mac_dst,
+            src_ip: record.ip_src,
+            dst_ip: record.ip_dst,
+            src_port: record.port_src,
+            dst_port: record.port_dst,
+            eth_type: record.eth_type,
+            proto: record.proto,
+            tap_type,
+            fast_index: self.fast_index,
+            ..Default::default()
+        };
+        if let Some((policy, endpoints)) = self.policy_getter.lookup_all_by_key(&mut key) {
+            for (peer, info) in flow
+                .flow_metrics_peers
+                .iter_mut()
+                .zip([&endpoints.src_info, &endpoints.dst_info])
+            {
+                peer.is_device = info.is_device;
+                peer.is_vip_interface = info.is_vip_interface;
+                peer.is_l2_end = info.l2_end;
+                peer.is_l3_end = info.l3_end;
+                peer.l3_epc_id = info.l3_epc_id;
+                peer.is_vip = info.is_vip;
+                peer.is_local_mac = info.is_local_mac;
+                peer.is_local_ip = info.is_local_ip;
+            }
+            tagged_flow.tag.policy_data[0] = PolicyData::clone(&policy);
+        }
+        tagged_flow
+    }
+}
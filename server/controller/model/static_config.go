@@ -47,8 +47,12 @@ type StaticConfig struct {
 	CollectorSenderQueueCount        *int                               `yaml:"collector-sender-queue-count,omitempty"`
 	FlowSenderQueueSize              *int                               `yaml:"flow-sender-queue-size,omitempty"`
 	FlowSenderQueueCount             *int                               `yaml:"flow-sender-queue-count,omitempty"`
+	CollectorSenderSpillSize         *uint64                            `yaml:"collector-sender-spill-size,omitempty"` // 单位：M
+	CollectorSenderSpillDir          *string                            `yaml:"collector-sender-spill-dir,omitempty"`
+	SenderTls                        *SenderTlsConfig                   `yaml:"sender-tls,omitempty"`
 	SecondFlowExtraDelaySecond       *int                               `yaml:"second-flow-extra-delay-second,omitempty"`
 	PacketDelay                      *int                               `yaml:"packet-delay,omitempty"`
+	IpFragment                       *IpFragmentConfig                  `yaml:"ip-fragment,omitempty"`
 	Triple                           *TripleMapConfig                   `yaml:"triple,omitempty"`
 	KubernetesPollerType             *string                            `yaml:"kubernetes-poller-type,omitempty"`
 	DecapErspan                      *bool                              `yaml:"decap-erspan,omitempty"`
@@ -72,10 +76,26 @@ type StaticConfig struct {
 	EbpfUprobeProcessNameRegexs      *EbpfUprobeProcessNameRegexsConfig `yaml:"ebpf-uprobe-process-name-regexs,omitempty"`
 	StandaloneDataFileSize           *uint64                            `yaml:"standalone-data-file-size,omitempty"`
 	StandaloneDataFileDir            *string                            `yaml:"standalone-data-file-dir,omitempty"`
+	StandaloneDataFileRotateInterval *string                            `yaml:"standalone-data-file-rotate-interval,omitempty"`
+	StandaloneDataFileRetentionCount *int                               `yaml:"standalone-data-file-retention-count,omitempty"`
+	StandaloneDataFileQuota          *uint64                            `yaml:"standalone-data-file-quota,omitempty"` // 单位：M
 	LogFile                          *string                            `yaml:"log-file,omitempty"`
 	ExternalAgentHttpProxyCompressed *bool                              `yaml:"external-agent-http-proxy-compressed,omitempty"`
 	FeatureFlags                     []string                           `yaml:"feature-flags,omitempty"`
 	L7ProtocolPorts                  map[string]string                  `yaml:"l7-protocol-ports,omitempty"`
+	TcpReassembly                    *TcpReassemblyConfig               `yaml:"tcp-reassembly,omitempty"`
+	SqlNormalization                 *SqlNormalizationConfig            `yaml:"sql-normalization,omitempty"`
+	RedisLog                         *RedisLogConfig                    `yaml:"redis-log,omitempty"`
+	L7LogBaggageKeys                 []string                           `yaml:"l7-log-baggage-keys,omitempty"`
+	HttpLogCapture                   *HttpLogCaptureConfig              `yaml:"http-log-capture,omitempty"`
+}
+
+type SenderTlsConfig struct {
+	Enabled    *bool   `yaml:"enabled,omitempty"`
+	CaFile     *string `yaml:"ca-file,omitempty"`
+	CertFile   *string `yaml:"cert-file,omitempty"`
+	KeyFile    *string `yaml:"key-file,omitempty"`
+	ServerName *string `yaml:"server-name,omitempty"`
 }
 
 type XflowCollectorConfig struct {
@@ -98,6 +118,35 @@ type PCapConfig struct {
 	ServerPort            *int    `yaml:"server-port,omitempty"`
 }
 
+type IpFragmentConfig struct {
+	Enabled   *bool   `yaml:"enabled,omitempty"`
+	Timeout   *string `yaml:"timeout,omitempty"`
+	MaxMemory *uint32 `yaml:"max-memory,omitempty"` // 单位：M
+}
+
+type TcpReassemblyConfig struct {
+	Protocols     []string `yaml:"protocols,omitempty"`
+	MaxBufferSize *int     `yaml:"max-buffer-size,omitempty"` // 单位：字节
+}
+
+type SqlNormalizationConfig struct {
+	Enabled            *bool `yaml:"enabled,omitempty"`
+	RedactRawStatement *bool `yaml:"redact-raw-statement,omitempty"`
+}
+
+type RedisLogConfig struct {
+	MaxArgumentLength *int  `yaml:"max-argument-length,omitempty"` // 单位：字节
+	RedactValues      *bool `yaml:"redact-values,omitempty"`
+}
+
+type HttpLogCaptureConfig struct {
+	RequestHeaders  []string `yaml:"request-headers,omitempty"`
+	ResponseHeaders []string `yaml:"response-headers,omitempty"`
+	QueryParameters []string `yaml:"query-parameters,omitempty"`
+	RedactNames     []string `yaml:"redact-names,omitempty"`
+	RedactPatterns  []string `yaml:"redact-patterns,omitempty"`
+}
+
 type TripleMapConfig struct {
 	HashSlots *int `yaml:"hash-slots-size,omitempty"`
 	Capacity  *int `yaml:"capacity,omitempty"`
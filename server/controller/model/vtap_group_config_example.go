@@ -130,7 +130,8 @@ vtap_group_id: g-xxxxxx
 #tap_mode: 0
 
 ## Decapsulation Tunnel Protocols
-## Default: [1, 3], means VXLAN and IPIP. Options: 1 (VXLAN), 2 (GRE), 3 (IPIP)
+## Default: [1, 3], means VXLAN and IPIP. Options: 1 (VXLAN), 2 (GRE), 3 (IPIP),
+##   5 (Geneve), 6 (GTP-U), 7 (MPLS)
 #decap_type:
 #- 1
 #- 3
@@ -220,7 +221,9 @@ vtap_group_id: g-xxxxxx
 ## Data Socket Type
 ## Default: TCP. Options: TCP, UDP, FILE
 ## Note: It can only be set to FILE in standalone mode, in which case
-##   l4_flow_log and l7_flow_log will be written to local files.
+##   all collected data (flow logs, metrics, integration data and agent
+##   stats) will be written to local files as JSON lines, one file per
+##   data type, see standalone-data-file-* for rotation and retention.
 #collector_socket_type: TCP
 
 ## PCAP Socket Type
@@ -450,7 +453,7 @@ vtap_group_id: g-xxxxxx
   ## the size of queue linking flow generator and quadruple generator, minimum 65536:
   ##    - 1-tagged-flow-to-quadruple-generator
   ##    - 1-tagged-flow-to-app-protocol-logs
-  ##    - 0-{flow_type}-{port}-packet-to-tagged-flow   ## flow_type: sflow, netflow
+  ##    - 0-{flow_type}-packet-to-tagged-flow   ## flow_type: sflow, netflow
   #flow-queue-size: 65536
   ## the size of queue linking quadruple generator and collector, minimum 262144:
   ##    - 2-flow-with-meter-to-second-collector
@@ -467,6 +470,23 @@ vtap_group_id: g-xxxxxx
   #flow-sender-queue-size: 65536
   ## the number of encoders for raw flow sender
   #flow-sender-queue-count: 1
+  ## 服务端不可达时，每个发送器暂存发送失败数据的磁盘队列大小，单位MB，配置为 0 时不暂存直接丢弃
+  ## 队列写满后淘汰最旧的数据，重连成功后按顺序回放
+  #collector-sender-spill-size: 0
+  ## 暂存队列文件所在的目录，文件以 {消息类型}-{发送器id}.spill 命名
+  #collector-sender-spill-dir: /var/log/deepflow-agent/spill
+  ## 发送流日志、指标和PCAP数据到数据节点时使用TLS加密
+  #sender-tls:
+    ## 是否开启TLS，TLS配置无效时数据不会以明文发送
+    #enabled: false
+    ## 用于校验数据节点证书的CA证书文件（PEM格式），开启TLS时必须配置
+    #ca-file: ""
+    ## 客户端证书和私钥文件（PEM格式），同时配置时开启双向认证
+    #cert-file: ""
+    #key-file: ""
+    ## 数据节点证书中的域名，用于SNI和证书校验，开启TLS时必须配置
+    ## 注意：不支持以IP校验证书
+    #server-name: ""
   ## 该队列在ANALYZER模式下使用:
   ##    - 0.1-bytes-to-parse
   ##    - 0.2-packet-to-flowgenerator
@@ -499,6 +519,11 @@ vtap_group_id: g-xxxxxx
     #file-directory: /var/lib/pcap
     ## pcap服务器端口
     #server-port: 20205
+    ## pcap文件格式，pcap 或 pcapng，默认pcap
+    ## pcapng格式为每个tap port写入Interface Description Block，并在每个包的注释中记录acl_gid、tap_type和flow_id
+    ## file format of pcap files, pcap or pcapng, default pcap
+    ## pcapng writes an Interface Description Block for each tap port, and records acl_gid, tap_type and flow_id in packet comments
+    #file-format: pcap
   #flow:
     ## flow hash solts大小
     ## 由于Flow是计算的第一步，这个值也广泛用于遥测数据统计的字典哈希桶大小
@@ -529,6 +554,7 @@ vtap_group_id: g-xxxxxx
   #dpdk-pmd-core-id: 0
   #dpdk-ring-port: "dpdkr0"
   ## sflow, netflow server ports
+  ## sFlow v5, NetFlow v5/v9 and IPFIX are supported, an empty port list disables the collector
   #xflow-collector:
     #sflow-ports:
       #- 6343
@@ -541,6 +567,14 @@ vtap_group_id: g-xxxxxx
   ## 网包时间与当前时间相比的最大delay，单位为秒，可配置[1, 10]，默认为1
   ## 大流量下该delay可能高达近10秒
   #packet-delay: 1s
+  ## IP分片重组，重组后的报文用于应用协议解析，L4统计仍按原始分片计算
+  #ip-fragment:
+    ## 是否开启分片重组
+    #enabled: true
+    ## 等待其余分片的最长时间，可配置[1s, 60s]，默认5s
+    #timeout: 5s
+    ## 每个采集线程缓存分片的内存上限，单位为M，超出后丢弃最早的报文，默认4M
+    #max-memory: 4
   ## 二元表配置
   #triple:
     #hash-slots-size: 65536
@@ -592,14 +626,18 @@ vtap_group_id: g-xxxxxx
   ## eBPF、AF_PACKET、WINPCAP 开启的应用协议解析列表，默认包括支持的所有应用协议。
   #l7-protocol-enabled:
     #- HTTP ## for both HTTP and HTTP_TLS
-    #- HTTP2 ## for HTTP2, HTTP2_TLS and gRPC
+    #- HTTP2 ## for HTTP2 and HTTP2_TLS, also enables Grpc
     #- Dubbo
     #- MySQL
     #- PostgreSQL
     #- Redis
+    #- MongoDB
     #- Kafka
     #- MQTT
+    #- AMQP
     #- DNS
+    #- Grpc
+    #- Custom ## for all wasm plugins
   ## eBPF uprobe 各项子功能生效的进程名，以正则表达式的方式配置
   #ebpf-uprobe-process-name-regexs:
     ## eBPF uprobe 开启 Golang 符号表解析的进程，默认为空表示不对任何进程开启。
@@ -612,10 +650,16 @@ vtap_group_id: g-xxxxxx
     #golang: ".*"
     ## eBPF uprobe 开启应用协议数据采集的使用 openssl 库的进程，默认为 .* 表示对所有使用了 openssl 库的进程开启。
     #openssl: ".*"
-  ## 写入单个数据文件的最大大小，单位MB
+  ## 写入单个数据文件的最大大小，单位MB，写满后压缩归档为 {类型}.{时间}[.{序号}].gz
   #standalone-data-file-size: 200
   ## 写入数据文件的路径
   #standalone-data-file-dir: /var/log/deepflow-agent/
+  ## 数据文件按时间轮转的周期，时间桶以本地时间对齐，配置为 0 时只按大小轮转
+  #standalone-data-file-rotate-interval: 1h
+  ## 每种数据保留的压缩文件数量
+  #standalone-data-file-retention-count: 168
+  ## 数据目录中所有压缩文件的总大小上限，单位MB，超出时删除最旧的文件，配置为 0 时不限制
+  #standalone-data-file-quota: 10240
   ## 日志文件路径
   #log-file: /var/log/deepflow-agent/deepflow-agent.log
   ## 开发过程中的功能控制开关，支持多个
@@ -624,12 +668,56 @@ vtap_group_id: g-xxxxxx
   #l7-protocol-ports:
     ##协议名称: 端口范围，端口范围可以是数字或范围
     #"HTTP": "80,8080,10000-15000" # for both HTTP and HTTP_TLS
-    #"HTTP2": "1-65535" # for HTTP2, HTTP2_TLS and gRPC
+    #"HTTP2": "1-65535" # for HTTP2 and HTTP2_TLS
     #"Dubbo": "1-65535"
     #"MySQL": "1-65535"
     #"PostgreSQL": "1-65535"
     #"Redis": "1-65535"
+    #"MongoDB": "1-65535"
     #"Kafka": "1-65535"
     #"MQTT": "1-65535"
+    #"AMQP": "1-65535"
     #"DNS": "53"
+    #"Grpc": "1-65535"
+    #"Custom": "1-65535"
+  ## WASM 协议解析插件目录，目录下的每个 *.wasm 文件为一个插件，文件名作为协议名称，默认为空表示不加载插件
+  #wasm-plugin-dir: ""
+  ## WASM 插件单次调用 check_payload/parse_payload 可消耗的 fuel 上限，超出后调用失败
+  #wasm-plugin-fuel-limit: 1000000
+  ## WASM 插件单个实例可使用的内存上限，单位字节
+  #wasm-plugin-memory-limit: 16777216
+  ## TCP流重组，将跨多个报文的应用协议消息重组后再解析，仅对报文采集的流量生效
+  #tcp-reassembly:
+    ## 开启重组的协议，可选HTTP、MySQL、PostgreSQL、Kafka，默认为空表示不重组
+    #protocols: []
+    ## 每条流每个方向的重组缓存上限，单位字节，可配置[1024, 16777216]，默认65536
+    #max-buffer-size: 65536
+  ## SQL语句规范化，为MySQL和PostgreSQL日志生成语句指纹（字面量替换为?、IN列表合并、去除注释和多余空白）及其哈希
+  #sql-normalization:
+    ## 开启后日志的attributes中携带sql_fingerprint和sql_fingerprint_hash，默认关闭
+    #enabled: false
+    ## 开启后以语句指纹代替原始语句上送，原始语句不离开采集器，默认关闭
+    #redact-raw-statement: false
+  ## Redis日志的请求参数处理
+  #redis-log:
+    ## 超过该长度的参数截断并以...结尾，单位字节，默认0表示不截断
+    #max-argument-length: 0
+    ## 开启后仅保留命令名和第一个参数（通常为key），其余参数以?代替，AUTH和HELLO的参数全部以?代替，
+    ## 批量回复和聚合回复的内容也以?代替，默认关闭
+    #redact-values: false
+  ## 从HTTP头和Dubbo attachment的W3C baggage中提取的key，提取结果以baggage.<key>携带在调用日志的attributes中，
+  ## 默认为空表示不提取
+  #l7-log-baggage-keys: []
+  ## HTTP日志额外采集的字段及脱敏规则，采集结果携带在调用日志的attributes中，
+  ## 名字分别为http.request.header.<name>、http.response.header.<name>和http.request.query.<name>
+  #http-log-capture:
+    ## 采集的请求头和响应头，不区分大小写，默认为空
+    #request-headers: []
+    #response-headers: []
+    ## 采集的URL query参数，不区分大小写，默认为空
+    #query-parameters: []
+    ## 值被整体替换为***的header和query参数，对URL中的query参数同样生效
+    #redact-names: [authorization, proxy-authorization, cookie, set-cookie]
+    ## 正则表达式，匹配的部分替换为***，作用于采集的值和URL，例如脱敏URL中的卡号：'\b\d{13,19}\b'
+    #redact-patterns: []
 `)
@@ -86,6 +86,9 @@ var DecapTypeData = map[int]string{
 	1: "VXLAN",
 	2: "IPIP",
 	3: "GRE",
+	5: "Geneve",
+	6: "GTP-U",
+	7: "MPLS",
 }
 
 func getTypeInfo(tapTypeValue int, idToTapTypeName map[int]string) *model.TypeInfo {
@@ -462,6 +462,21 @@ var ColumnMod615 = []*ColumnMod{
 	},
 }
 
+var ColumnAdd616 = []*ColumnAdds{
+	&ColumnAdds{
+		Dbs:         []string{"flow_log"},
+		Tables:      []string{"l4_flow_log", "l4_flow_log_local"},
+		ColumnNames: []string{"tunnel_types"},
+		ColumnType:  ckdb.ArrayUInt16,
+	},
+	&ColumnAdds{
+		Dbs:         []string{"flow_log"},
+		Tables:      []string{"l4_flow_log", "l4_flow_log_local"},
+		ColumnNames: []string{"tunnel_tx_ids", "tunnel_rx_ids"},
+		ColumnType:  ckdb.ArrayUInt32,
+	},
+}
+
 func getTables(connect *sql.DB, db, tableName string) ([]string, error) {
 	sql := fmt.Sprintf("SHOW TABLES IN %s", db)
 	rows, err := connect.Query(sql)
@@ -687,7 +702,7 @@ func NewCKIssu(cfg *config.Config) (*Issu, error) {
 		// columnRenames: ColumnRename572,
 	}
 
-	allVersionAdds := [][]*ColumnAdds{ColumnAdd612, ColumnAdd613, ColumnAdd615}
+	allVersionAdds := [][]*ColumnAdds{ColumnAdd612, ColumnAdd613, ColumnAdd615, ColumnAdd616}
 	i.columnAdds = []*ColumnAdd{}
 	for _, versionAdd := range allVersionAdds {
 		for _, adds := range versionAdd {
@@ -109,6 +109,10 @@ type NetworkLayer struct {
 	TunnelTxMac1 uint32 `json:"tunnel_tx_mac_1,omitempty"`
 	TunnelRxMac0 uint32 `json:"tunnel_rx_mac_0,omitempty"`
 	TunnelRxMac1 uint32 `json:"tunnel_rx_mac_1,omitempty"`
+	// 由外向内的各层隧道
+	TunnelTypes []uint16 `json:"tunnel_types,omitempty"`
+	TunnelTxIDs []uint32 `json:"tunnel_tx_ids,omitempty"`
+	TunnelRxIDs []uint32 `json:"tunnel_rx_ids,omitempty"`
 }
 
 var NetworkLayerColumns = []*ckdb.Column{
@@ -135,6 +139,9 @@ var NetworkLayerColumns = []*ckdb.Column{
 	ckdb.NewColumn("tunnel_tx_mac_1", ckdb.UInt32),
 	ckdb.NewColumn("tunnel_rx_mac_0", ckdb.UInt32),
 	ckdb.NewColumn("tunnel_rx_mac_1", ckdb.UInt32),
+	ckdb.NewColumn("tunnel_types", ckdb.ArrayUInt16),
+	ckdb.NewColumn("tunnel_tx_ids", ckdb.ArrayUInt32),
+	ckdb.NewColumn("tunnel_rx_ids", ckdb.ArrayUInt32),
 }
 
 func (n *NetworkLayer) WriteBlock(block *ckdb.Block) error {
@@ -227,6 +234,15 @@ func (n *NetworkLayer) WriteBlock(block *ckdb.Block) error {
 	if err := block.WriteUInt32(n.TunnelRxMac1); err != nil {
 		return err
 	}
+	if err := block.WriteArrayUint16(n.TunnelTypes); err != nil {
+		return err
+	}
+	if err := block.WriteArrayUInt32(n.TunnelTxIDs); err != nil {
+		return err
+	}
+	if err := block.WriteArrayUInt32(n.TunnelRxIDs); err != nil {
+		return err
+	}
 
 	return nil
 }
@@ -946,6 +962,20 @@ func (n *NetworkLayer) Fill(f *pb.Flow, isIPV6 bool) {
 		n.TunnelTxMac1 = f.Tunnel.TxMac1
 		n.TunnelRxMac0 = f.Tunnel.RxMac0
 		n.TunnelRxMac1 = f.Tunnel.RxMac1
+
+		layers := f.Tunnel.TxLayers
+		if len(layers) == 0 {
+			layers = f.Tunnel.RxLayers
+		}
+		for _, layer := range layers {
+			n.TunnelTypes = append(n.TunnelTypes, uint16(layer.TunnelType))
+		}
+		for _, layer := range f.Tunnel.TxLayers {
+			n.TunnelTxIDs = append(n.TunnelTxIDs, layer.Id)
+		}
+		for _, layer := range f.Tunnel.RxLayers {
+			n.TunnelRxIDs = append(n.TunnelRxIDs, layer.Id)
+		}
 	}
 }
 
@@ -504,6 +504,10 @@ func (h *L7Logger) fillL7Log(l *pb.AppProtoLogsData) {
 			h.RequestId = &h.requestId
 		}
 		h.ServiceName = l.ExtInfo.ServiceName
+		// wasm 插件解析的协议使用插件提供的协议名称
+		if l.ExtInfo.ProtocolStr != "" {
+			h.L7ProtocolStr = l.ExtInfo.ProtocolStr
+		}
 		h.XRequestId = l.ExtInfo.XRequestId
 		h.HttpProxyClient = l.ExtInfo.ClientIp
 		if l.ExtInfo.HttpUserAgent != "" {
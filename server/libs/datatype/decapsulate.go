@@ -35,6 +35,9 @@ const (
 	TUNNEL_TYPE_IPIP          = TunnelType(pb.DecapType_DECAP_TYPE_IPIP)
 	TUNNEL_TYPE_TENCENT_GRE   = TunnelType(pb.DecapType_DECAP_TYPE_TENCENT) // GRE.ver=0/1 GRE.protoType=IPv4/IPv6
 	TUNNEL_TYPE_ERSPAN_OR_TEB = TUNNEL_TYPE_TENCENT_GRE + 1
+	TUNNEL_TYPE_GENEVE        = TunnelType(pb.DecapType_DECAP_TYPE_GENEVE)
+	TUNNEL_TYPE_GTPU          = TunnelType(pb.DecapType_DECAP_TYPE_GTPU)
+	TUNNEL_TYPE_MPLS          = TunnelType(pb.DecapType_DECAP_TYPE_MPLS)
 
 	LE_IPV4_PROTO_TYPE_I      = 0x0008 // 0x0008's LittleEndian
 	LE_IPV6_PROTO_TYPE_I      = 0xDD86 // 0x86dd's LittleEndian
@@ -56,6 +59,9 @@ var (
 		TUNNEL_TYPE_IPIP:          "IPIP",
 		TUNNEL_TYPE_TENCENT_GRE:   "GRE",
 		TUNNEL_TYPE_ERSPAN_OR_TEB: "ERSPAN_TEB",
+		TUNNEL_TYPE_GENEVE:        "GENEVE",
+		TUNNEL_TYPE_GTPU:          "GTPU",
+		TUNNEL_TYPE_MPLS:          "MPLS",
 	}
 )
 
@@ -87,7 +93,7 @@ func (b TunnelTypeBitmap) IsEmpty() bool {
 
 func (b TunnelTypeBitmap) String() string {
 	context := ""
-	for i := TunnelType(0); i <= TUNNEL_TYPE_ERSPAN_OR_TEB; i++ {
+	for i := TunnelType(0); i <= TUNNEL_TYPE_MPLS; i++ {
 		if b.Has(i) {
 			context += tunnelTypeTips[i]
 		}
@@ -46,6 +46,10 @@ const (
 
 	MESSAGE_TYPE_DFSTATS
 	MESSAGE_TYPE_OPENTELEMETRY_COMPRESSED
+	MESSAGE_TYPE_OPENTELEMETRY_METRICS
+	MESSAGE_TYPE_OPENTELEMETRY_METRICS_COMPRESSED
+	MESSAGE_TYPE_OPENTELEMETRY_LOGS
+	MESSAGE_TYPE_OPENTELEMETRY_LOGS_COMPRESSED
 	MESSAGE_TYPE_MAX
 )
 
@@ -62,8 +66,12 @@ var MessageTypeString = [MESSAGE_TYPE_MAX]string{
 	MESSAGE_TYPE_TELEGRAF:       "telegraf",
 	MESSAGE_TYPE_PACKETSEQUENCE: "l4_packet",
 
-	MESSAGE_TYPE_DFSTATS:                  "deepflow_stats",
-	MESSAGE_TYPE_OPENTELEMETRY_COMPRESSED: "open_telemetry_compressed",
+	MESSAGE_TYPE_DFSTATS:                          "deepflow_stats",
+	MESSAGE_TYPE_OPENTELEMETRY_COMPRESSED:         "open_telemetry_compressed",
+	MESSAGE_TYPE_OPENTELEMETRY_METRICS:            "open_telemetry_metrics",
+	MESSAGE_TYPE_OPENTELEMETRY_METRICS_COMPRESSED: "open_telemetry_metrics_compressed",
+	MESSAGE_TYPE_OPENTELEMETRY_LOGS:               "open_telemetry_logs",
+	MESSAGE_TYPE_OPENTELEMETRY_LOGS_COMPRESSED:    "open_telemetry_logs_compressed",
 }
 
 func (m MessageType) String() string {
@@ -98,8 +106,12 @@ var MessageHeaderTypes = [MESSAGE_TYPE_MAX]MessageHeaderType{
 	MESSAGE_TYPE_TELEGRAF:       HEADER_TYPE_LT_VTAP,
 	MESSAGE_TYPE_PACKETSEQUENCE: HEADER_TYPE_LT_VTAP,
 
-	MESSAGE_TYPE_DFSTATS:                  HEADER_TYPE_LT_VTAP,
-	MESSAGE_TYPE_OPENTELEMETRY_COMPRESSED: HEADER_TYPE_LT_VTAP,
+	MESSAGE_TYPE_DFSTATS:                          HEADER_TYPE_LT_VTAP,
+	MESSAGE_TYPE_OPENTELEMETRY_COMPRESSED:         HEADER_TYPE_LT_VTAP,
+	MESSAGE_TYPE_OPENTELEMETRY_METRICS:            HEADER_TYPE_LT_VTAP,
+	MESSAGE_TYPE_OPENTELEMETRY_METRICS_COMPRESSED: HEADER_TYPE_LT_VTAP,
+	MESSAGE_TYPE_OPENTELEMETRY_LOGS:               HEADER_TYPE_LT_VTAP,
+	MESSAGE_TYPE_OPENTELEMETRY_LOGS_COMPRESSED:    HEADER_TYPE_LT_VTAP,
 }
 
 func (m MessageType) HeaderType() MessageHeaderType {
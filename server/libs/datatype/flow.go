@@ -155,9 +155,12 @@ const (
 	L7_PROTOCOL_MYSQL      L7Protocol = 60
 	L7_PROTOCOL_POSTGRE    L7Protocol = 61
 	L7_PROTOCOL_REDIS      L7Protocol = 80
+	L7_PROTOCOL_MONGODB    L7Protocol = 81
 	L7_PROTOCOL_KAFKA      L7Protocol = 100
 	L7_PROTOCOL_MQTT       L7Protocol = 101
+	L7_PROTOCOL_AMQP       L7Protocol = 102
 	L7_PROTOCOL_DNS        L7Protocol = 120
+	L7_PROTOCOL_CUSTOM     L7Protocol = 127
 )
 
 // size = 9 * 4B = 36B
@@ -604,6 +607,8 @@ func (p L7Protocol) String() string {
 		formatted = "postgresql"
 	case L7_PROTOCOL_REDIS:
 		formatted = "redis"
+	case L7_PROTOCOL_MONGODB:
+		formatted = "mongodb"
 	case L7_PROTOCOL_DUBBO:
 		formatted = "dubbo"
 	case L7_PROTOCOL_GRPC:
@@ -612,6 +617,10 @@ func (p L7Protocol) String() string {
 		formatted = "kafka"
 	case L7_PROTOCOL_MQTT:
 		formatted = "mqtt"
+	case L7_PROTOCOL_AMQP:
+		formatted = "amqp"
+	case L7_PROTOCOL_CUSTOM:
+		formatted = "custom"
 	case L7_PROTOCOL_OTHER:
 		formatted = "other"
 	default:
@@ -628,10 +637,13 @@ var L7ProtocolStringMap = map[string]L7Protocol{
 	L7_PROTOCOL_DNS.String():        L7_PROTOCOL_DNS,
 	L7_PROTOCOL_MYSQL.String():      L7_PROTOCOL_MYSQL,
 	L7_PROTOCOL_REDIS.String():      L7_PROTOCOL_REDIS,
+	L7_PROTOCOL_MONGODB.String():    L7_PROTOCOL_MONGODB,
 	L7_PROTOCOL_DUBBO.String():      L7_PROTOCOL_DUBBO,
 	L7_PROTOCOL_GRPC.String():       L7_PROTOCOL_GRPC,
 	L7_PROTOCOL_KAFKA.String():      L7_PROTOCOL_KAFKA,
 	L7_PROTOCOL_MQTT.String():       L7_PROTOCOL_MQTT,
+	L7_PROTOCOL_AMQP.String():       L7_PROTOCOL_AMQP,
+	L7_PROTOCOL_CUSTOM.String():     L7_PROTOCOL_CUSTOM,
 	L7_PROTOCOL_OTHER.String():      L7_PROTOCOL_OTHER,
 	L7_PROTOCOL_UNKNOWN.String():    L7_PROTOCOL_UNKNOWN,
 }
@@ -65,8 +65,8 @@ func FromGatewayMAC(tunnelType TunnelType, mac uint32) TapPort {
 	return TapPort(mac) | TapPort(tunnelType)<<_TUNNEL_TYPE_OFFSET | TAPPORT_FROM_GATEWAY_MAC<<_FROM_OFFSET
 }
 
-func FromTunnelIP(ip uint32, isIPv6 bool) TapPort {
-	tapPort := TapPort(ip)
+func FromTunnelIP(tunnelType TunnelType, ip uint32, isIPv6 bool) TapPort {
+	tapPort := TapPort(ip) | TapPort(tunnelType)<<_TUNNEL_TYPE_OFFSET
 	if !isIPv6 {
 		tapPort |= TAPPORT_FROM_TUNNEL_IPV4 << _FROM_OFFSET
 	} else {
@@ -99,9 +99,9 @@ func (p TapPort) String() string {
 		return fmt.Sprintf("GMAC@%s@%02x:%02x:%02x:%02x",
 			tunnelType, uint8(tapPort>>24), uint8(tapPort>>16), uint8(tapPort>>8), uint8(tapPort))
 	case TAPPORT_FROM_TUNNEL_IPV4:
-		return fmt.Sprintf("IPv4@%s", utils.IpFromUint32(tapPort))
+		return fmt.Sprintf("IPv4@%s@%s", tunnelType, utils.IpFromUint32(tapPort))
 	case TAPPORT_FROM_TUNNEL_IPV6:
-		return fmt.Sprintf("IPv6@0x%08x", tapPort)
+		return fmt.Sprintf("IPv6@%s@0x%08x", tunnelType, tapPort)
 	case TAPPORT_FROM_ID:
 		return fmt.Sprintf("ID@%s@%d", tunnelType, tapPort)
 	case TAPPORT_FROM_NETFLOW: